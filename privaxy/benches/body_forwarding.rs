@@ -0,0 +1,116 @@
+//! Compares the previous body forwarding strategy, where a dedicated task sent every chunk
+//! through a channel, with wrapping the upstream stream with `Body::wrap_stream`. Chunks are
+//! reference counted either way, the difference is the task and the channel.
+//! Rewritten documents are gzip compressed the same two ways.
+//!
+//! Run with `cargo bench --bench body_forwarding`.
+use async_compression::tokio::bufread::GzipEncoder;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use hyper::body::{Bytes, HttpBody};
+use hyper::Body;
+use std::convert::Infallible;
+use std::io;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+const CHUNK_SIZE: usize = 16 * 1024;
+
+fn upstream_chunks(size: usize) -> Vec<Bytes> {
+    let chunk = Bytes::from(vec![0u8; CHUNK_SIZE]);
+
+    (0..size / CHUNK_SIZE).map(|_| chunk.clone()).collect()
+}
+
+async fn drain(mut body: Body) -> usize {
+    let mut received = 0;
+
+    while let Some(Ok(chunk)) = body.data().await {
+        received += chunk.len();
+    }
+
+    received
+}
+
+async fn forward_through_channel(chunks: Vec<Bytes>) -> usize {
+    let (mut sender, body) = Body::channel();
+
+    tokio::spawn(async move {
+        for chunk in chunks {
+            if sender.send_data(chunk).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    drain(body).await
+}
+
+async fn forward_wrapped_stream(chunks: Vec<Bytes>) -> usize {
+    let body = Body::wrap_stream(stream::iter(chunks).map(Ok::<_, Infallible>));
+
+    drain(body).await
+}
+
+fn gzip_reader(body: Body) -> ReaderStream<GzipEncoder<impl tokio::io::AsyncBufRead>> {
+    let reader = StreamReader::new(TryStreamExt::map_err(body, io::Error::other));
+
+    ReaderStream::new(GzipEncoder::new(reader))
+}
+
+async fn gzip_through_channel(chunks: Vec<Bytes>) -> usize {
+    let body = Body::wrap_stream(stream::iter(chunks).map(Ok::<_, Infallible>));
+    let (mut sender, compressed) = Body::channel();
+
+    tokio::spawn(async move {
+        let mut chunks = gzip_reader(body);
+        while let Some(Ok(chunk)) = chunks.next().await {
+            if sender.send_data(chunk).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    drain(compressed).await
+}
+
+async fn gzip_wrapped_stream(chunks: Vec<Bytes>) -> usize {
+    let body = Body::wrap_stream(stream::iter(chunks).map(Ok::<_, Infallible>));
+
+    drain(Body::wrap_stream(gzip_reader(body))).await
+}
+
+fn body_forwarding(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("body_forwarding");
+
+    for size in [1024 * 1024, 64 * 1024 * 1024] {
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("channel", size), &size, |b, &size| {
+            b.to_async(&runtime)
+                .iter(|| forward_through_channel(upstream_chunks(size)))
+        });
+        group.bench_with_input(BenchmarkId::new("wrap_stream", size), &size, |b, &size| {
+            b.to_async(&runtime)
+                .iter(|| forward_wrapped_stream(upstream_chunks(size)))
+        });
+    }
+
+    group.finish();
+
+    let mut group = c.benchmark_group("gzip_compression");
+    let size = 1024 * 1024;
+    group.throughput(Throughput::Bytes(size as u64));
+    group.bench_function("channel", |b| {
+        b.to_async(&runtime)
+            .iter(|| gzip_through_channel(upstream_chunks(size)))
+    });
+    group.bench_function("wrap_stream", |b| {
+        b.to_async(&runtime)
+            .iter(|| gzip_wrapped_stream(upstream_chunks(size)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, body_forwarding);
+criterion_main!(benches);
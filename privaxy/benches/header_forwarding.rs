@@ -0,0 +1,165 @@
+//! Compares the previous handling of request headers with the current one: `Accept-Encoding`
+//! values formatted for every request against values shared by all requests, and requests
+//! rebuilt from a copy of their headers for every attempt against requests built once and
+//! copied.
+//!
+//! Run with `cargo bench --bench header_forwarding`.
+use criterion::{criterion_group, criterion_main, Criterion};
+use hyper::body::Bytes;
+use hyper::http::{self, HeaderMap, HeaderValue, Method};
+use once_cell::sync::Lazy;
+
+const ENCODINGS: [&str; 4] = ["gzip", "deflate", "br", "zstd"];
+
+static ACCEPT_ENCODING_VALUES: Lazy<Vec<Option<HeaderValue>>> = Lazy::new(|| {
+    (0..1 << ENCODINGS.len())
+        .map(|mask: usize| {
+            let encodings = ENCODINGS
+                .iter()
+                .enumerate()
+                .filter(|(index, _encoding)| mask & (1 << index) != 0)
+                .map(|(_index, encoding)| *encoding)
+                .collect::<Vec<_>>();
+
+            (!encodings.is_empty()).then(|| HeaderValue::from_str(&encodings.join(", ")).unwrap())
+        })
+        .collect()
+});
+
+/// Headers of a typical browser navigation.
+fn browser_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in [
+        (
+            "user-agent",
+            "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0",
+        ),
+        (
+            "accept",
+            "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+        ),
+        ("accept-language", "en-US,en;q=0.5"),
+        ("accept-encoding", "gzip, deflate, br, zstd"),
+        ("referer", "https://www.example.com/"),
+        (
+            "cookie",
+            "session=0123456789abcdef0123456789abcdef; theme=dark; consent=1",
+        ),
+        ("upgrade-insecure-requests", "1"),
+        ("sec-fetch-dest", "document"),
+        ("sec-fetch-mode", "navigate"),
+        ("sec-fetch-site", "same-origin"),
+    ] {
+        headers.insert(
+            http::HeaderName::from_static(name),
+            HeaderValue::from_static(value),
+        );
+    }
+
+    headers
+}
+
+fn accepted_encodings(headers: &HeaderMap) -> impl Iterator<Item = usize> + '_ {
+    headers
+        .get_all(http::header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|token| {
+            ENCODINGS
+                .iter()
+                .position(|encoding| token.trim().eq_ignore_ascii_case(encoding))
+        })
+}
+
+fn negotiate_formatted(headers: &mut HeaderMap) {
+    let mut encodings: Vec<&'static str> = accepted_encodings(headers)
+        .map(|index| ENCODINGS[index])
+        .collect();
+    encodings.dedup();
+
+    headers.remove(http::header::ACCEPT_ENCODING);
+    if !encodings.is_empty() {
+        headers.insert(
+            http::header::ACCEPT_ENCODING,
+            HeaderValue::from_str(&encodings.join(", ")).unwrap(),
+        );
+    }
+}
+
+fn negotiate_shared(headers: &mut HeaderMap) {
+    let mask = accepted_encodings(headers).fold(0, |mask, index| mask | 1 << index);
+
+    headers.remove(http::header::ACCEPT_ENCODING);
+    if let Some(accept_encoding) = &ACCEPT_ENCODING_VALUES[mask] {
+        headers.insert(http::header::ACCEPT_ENCODING, accept_encoding.clone());
+    }
+}
+
+const URL: &str = "http://www.example.com/articles/1234?utm_source=newsletter";
+const ATTEMPTS: usize = 2;
+
+fn rebuild_per_attempt(client: &reqwest::Client, headers: HeaderMap, body: Bytes) {
+    for _attempt in 0..ATTEMPTS {
+        let request = client
+            .request(Method::GET, URL.to_string())
+            .headers(headers.clone())
+            .body(body.clone())
+            .build()
+            .unwrap();
+        criterion::black_box(request);
+    }
+}
+
+fn build_once(client: &reqwest::Client, headers: HeaderMap, body: Bytes) {
+    let request = client
+        .request(Method::GET, URL.to_string())
+        .headers(headers)
+        .body(body)
+        .build()
+        .unwrap();
+
+    for _attempt in 0..ATTEMPTS {
+        criterion::black_box(request.try_clone().unwrap());
+    }
+}
+
+fn header_forwarding(c: &mut Criterion) {
+    let mut group = c.benchmark_group("accept_encoding");
+    group.bench_function("formatted", |b| {
+        b.iter_batched(
+            browser_headers,
+            |mut headers| negotiate_formatted(&mut headers),
+            criterion::BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("shared", |b| {
+        b.iter_batched(
+            browser_headers,
+            |mut headers| negotiate_shared(&mut headers),
+            criterion::BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+
+    let client = reqwest::Client::new();
+    let mut group = c.benchmark_group("replayable_request");
+    group.bench_function("rebuilt_per_attempt", |b| {
+        b.iter_batched(
+            browser_headers,
+            |headers| rebuild_per_attempt(&client, headers, Bytes::new()),
+            criterion::BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("built_once", |b| {
+        b.iter_batched(
+            browser_headers,
+            |headers| build_once(&client, headers, Bytes::new()),
+            criterion::BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, header_forwarding);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+fn main() {
+    // Requires protoc, see the README.
+    #[cfg(feature = "grpc")]
+    tonic_build::configure()
+        .build_client(false)
+        .compile(&["proto/admin.proto"], &["proto"])
+        .expect("Unable to compile the gRPC API definition");
+}
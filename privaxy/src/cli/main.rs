@@ -0,0 +1,466 @@
+//! Administration of a running Privaxy instance through its REST API, for headless servers.
+use clap::{Parser, Subcommand, ValueEnum};
+use futures_util::StreamExt;
+use reqwest::{Method, StatusCode};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tokio_tungstenite::tungstenite::Message;
+use url::Url;
+
+#[derive(Parser)]
+#[command(
+    name = "privaxy-cli",
+    version,
+    about = "Administer a running Privaxy instance"
+)]
+struct Cli {
+    /// Address of the Privaxy web interface.
+    #[arg(long, env = "PRIVAXY_URL", default_value = "http://127.0.0.1:8200")]
+    url: Url,
+    /// Accept invalid TLS certificates, e.g. self-signed ones. Only applies to HTTP requests,
+    /// not to the WebSocket ones made by `stats` and `tail`.
+    #[arg(long)]
+    insecure: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show request counters and the top blocked domains and paths.
+    Stats {
+        /// Only count requests over this period, top lists are not available then.
+        #[arg(long)]
+        window: Option<Window>,
+    },
+    /// Print requests as they are handled.
+    Tail {
+        /// Print allowed requests as well as blocked ones.
+        #[arg(long)]
+        all: bool,
+    },
+    /// Show, add or remove exclusions, hosts that are neither filtered nor intercepted.
+    Exclusions {
+        #[command(subcommand)]
+        command: Option<ExclusionsCommand>,
+    },
+    /// Download enabled filter lists again and apply them.
+    UpdateFilters,
+    /// Turn blocking on or off, or show whether it is on.
+    Blocking { state: Option<BlockingState> },
+    /// Switch to another blocking profile, or list profiles and show the active one.
+    Profile { name: Option<String> },
+    /// Write the CA certificate to install in browsers.
+    ExportCa {
+        /// File to write to, instead of the standard output.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Reload the configuration from disk.
+    Reload,
+}
+
+#[derive(Subcommand)]
+enum ExclusionsCommand {
+    Add { hosts: Vec<String> },
+    Remove { hosts: Vec<String> },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Window {
+    #[value(name = "1h")]
+    Hour,
+    #[value(name = "24h")]
+    Day,
+    #[value(name = "7d")]
+    Week,
+}
+
+impl Window {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Window::Hour => "1h",
+            Window::Day => "24h",
+            Window::Week => "7d",
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BlockingState {
+    On,
+    Off,
+}
+
+struct Client {
+    base_url: Url,
+    http_client: reqwest::Client,
+}
+
+impl Client {
+    fn new(base_url: Url, insecure: bool) -> Result<Self, String> {
+        let http_client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(insecure)
+            .build()
+            .map_err(|err| err.to_string())?;
+
+        Ok(Self {
+            base_url,
+            http_client,
+        })
+    }
+
+    fn url(&self, path: &str) -> Result<Url, String> {
+        self.base_url
+            .join(path)
+            .map_err(|err| format!("Invalid URL: {}", err))
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<reqwest::Response, String> {
+        self.request_if_match(method, path, body, None).await
+    }
+
+    /// Sends `revision`, as returned in the `ETag` of a previous response, in `If-Match`.
+    async fn request_if_match(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+        revision: Option<&str>,
+    ) -> Result<reqwest::Response, String> {
+        let mut request = self.http_client.request(method, self.url(path)?);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        if let Some(revision) = revision {
+            request = request.header(reqwest::header::IF_MATCH, revision);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|err| format!("Unable to reach Privaxy: {}", err))?;
+
+        let status = response.status();
+        if status.is_client_error() || status.is_server_error() {
+            let body = response.text().await.unwrap_or_default();
+            let error = serde_json::from_str::<Value>(&body)
+                .ok()
+                .and_then(|body| body.get("error")?.as_str().map(str::to_string))
+                .unwrap_or(body);
+
+            return Err(format!("{}: {}", status, error));
+        }
+
+        Ok(response)
+    }
+
+    async fn get_json(&self, path: &str) -> Result<Value, String> {
+        self.request(Method::GET, path, None)
+            .await?
+            .json()
+            .await
+            .map_err(|err| format!("Invalid response: {}", err))
+    }
+
+    async fn websocket(
+        &self,
+        path: &str,
+    ) -> Result<
+        tokio_tungstenite::WebSocketStream<
+            tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+        >,
+        String,
+    > {
+        let mut url = self.url(path)?;
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        url.set_scheme(scheme)
+            .map_err(|_err| "Invalid URL scheme".to_string())?;
+
+        let (stream, _response) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .map_err(|err| format!("Unable to reach Privaxy: {}", err))?;
+
+        Ok(stream)
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match Client::new(cli.url, cli.insecure) {
+        Ok(client) => run(&client, cli.command).await,
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(client: &Client, command: Command) -> Result<(), String> {
+    match command {
+        Command::Stats { window } => stats(client, window).await,
+        Command::Tail { all } => tail(client, all).await,
+        Command::Exclusions { command } => exclusions(client, command).await,
+        Command::UpdateFilters => {
+            client
+                .request(Method::POST, "/api/filters/update", None)
+                .await?;
+            println!("Filters updated");
+            Ok(())
+        }
+        Command::Blocking { state } => blocking(client, state).await,
+        Command::Profile { name } => profile(client, name).await,
+        Command::ExportCa { output } => export_ca(client, output).await,
+        Command::Reload => {
+            client.request(Method::POST, "/api/reload", None).await?;
+            println!("Configuration reloaded");
+            Ok(())
+        }
+    }
+}
+
+fn print_counters(statistics: &Value) {
+    for (name, key) in [
+        ("Proxied requests", "proxied_requests"),
+        ("Blocked requests", "blocked_requests"),
+        ("Modified responses", "modified_responses"),
+        ("Threats blocked", "threats_blocked"),
+    ] {
+        println!(
+            "{:<20}{}",
+            name,
+            statistics[key].as_u64().unwrap_or_default()
+        );
+    }
+}
+
+/// Top lists are serialized as objects of counts, which lose their order.
+fn print_top(title: &str, entries: &Value) {
+    let mut entries = entries
+        .as_object()
+        .map(|entries| {
+            entries
+                .iter()
+                .map(|(name, count)| (name.as_str(), count.as_u64().unwrap_or_default()))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if entries.is_empty() {
+        return;
+    }
+    entries.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    println!("\n{}", title);
+    for (name, count) in entries.into_iter().take(10) {
+        println!("{:>10}  {}", count, name);
+    }
+}
+
+async fn stats(client: &Client, window: Option<Window>) -> Result<(), String> {
+    if let Some(window) = window {
+        let statistics = client
+            .get_json(&format!("/api/statistics?window={}", window.as_str()))
+            .await?;
+
+        println!(
+            "Over the last {} seconds\n",
+            statistics["covered_seconds"].as_u64().unwrap_or_default()
+        );
+        print_counters(&statistics);
+
+        return Ok(());
+    }
+
+    // Statistics are pushed over a WebSocket, the first message is sent right away.
+    let mut stream = client.websocket("/api/statistics").await?;
+    let statistics = loop {
+        match stream.next().await {
+            Some(Ok(Message::Text(text))) => {
+                break serde_json::from_str::<Value>(&text)
+                    .map_err(|err| format!("Invalid response: {}", err))?
+            }
+            Some(Ok(_message)) => continue,
+            Some(Err(err)) => return Err(format!("Connection lost: {}", err)),
+            None => return Err("Connection closed before receiving statistics".to_string()),
+        }
+    };
+    let _result = stream.close(None).await;
+
+    print_counters(&statistics);
+    print_top("Top blocked domains", &statistics["top_blocked_domains"]);
+    print_top("Top blocked paths", &statistics["top_blocked_paths"]);
+    print_top("Top clients", &statistics["top_clients"]);
+
+    Ok(())
+}
+
+async fn tail(client: &Client, all: bool) -> Result<(), String> {
+    let mut stream = client.websocket("/api/events").await?;
+
+    while let Some(message) = stream.next().await {
+        let text = match message {
+            Ok(Message::Text(text)) => text,
+            Ok(_message) => continue,
+            Err(err) => return Err(format!("Connection lost: {}", err)),
+        };
+        let event = match serde_json::from_str::<Value>(&text) {
+            Ok(event) => event,
+            Err(_err) => continue,
+        };
+
+        let is_blocked = event["is_request_blocked"].as_bool().unwrap_or_default();
+        if !is_blocked && !all {
+            continue;
+        }
+
+        println!(
+            "{} {:<7} {:<7} {}",
+            event["now"].as_str().unwrap_or_default(),
+            if is_blocked { "BLOCKED" } else { "ALLOWED" },
+            event["method"].as_str().unwrap_or_default(),
+            event["url"].as_str().unwrap_or_default(),
+        );
+    }
+
+    Ok(())
+}
+
+async fn exclusions(client: &Client, command: Option<ExclusionsCommand>) -> Result<(), String> {
+    let response = client.request(Method::GET, "/api/exclusions", None).await?;
+    let revision = response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|revision| revision.to_str().ok())
+        .map(str::to_string);
+    let current = response
+        .json::<Value>()
+        .await
+        .map_err(|err| format!("Invalid response: {}", err))?;
+    let mut exclusions = current
+        .as_str()
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    match command {
+        None => {
+            for exclusion in exclusions {
+                println!("{}", exclusion);
+            }
+            return Ok(());
+        }
+        Some(ExclusionsCommand::Add { hosts }) => {
+            for host in hosts {
+                if !exclusions.contains(&host) {
+                    exclusions.push(host);
+                }
+            }
+        }
+        Some(ExclusionsCommand::Remove { hosts }) => {
+            exclusions.retain(|exclusion| !hosts.contains(exclusion));
+        }
+    }
+
+    client
+        .request_if_match(
+            Method::PUT,
+            "/api/exclusions",
+            Some(&Value::String(exclusions.join("\n"))),
+            revision.as_deref(),
+        )
+        .await?;
+    println!("Exclusions saved");
+
+    Ok(())
+}
+
+async fn blocking(client: &Client, state: Option<BlockingState>) -> Result<(), String> {
+    if let Some(state) = state {
+        client
+            .request(
+                Method::PUT,
+                "/api/blocking-enabled",
+                Some(&Value::Bool(matches!(state, BlockingState::On))),
+            )
+            .await?;
+    }
+
+    let enabled = client
+        .get_json("/api/blocking-enabled")
+        .await?
+        .as_bool()
+        .unwrap_or_default();
+
+    println!("Blocking is {}", if enabled { "on" } else { "off" });
+
+    Ok(())
+}
+
+async fn profile(client: &Client, name: Option<String>) -> Result<(), String> {
+    if let Some(name) = name {
+        client
+            .request(
+                Method::PUT,
+                "/api/profiles/active",
+                Some(&Value::String(name)),
+            )
+            .await?;
+    }
+
+    let profiles = client.get_json("/api/profiles").await?;
+    let active = profiles["active"].as_str().unwrap_or_default();
+    let names = std::iter::once("default").chain(
+        profiles["profiles"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|profile| profile["name"].as_str()),
+    );
+
+    for name in names {
+        let marker = if name == active { "*" } else { " " };
+        println!("{} {}", marker, name);
+    }
+
+    Ok(())
+}
+
+async fn export_ca(client: &Client, output: Option<PathBuf>) -> Result<(), String> {
+    let response = client
+        .request(Method::GET, "/api/settings/ca-certificate", None)
+        .await?;
+    if response.status() != StatusCode::OK {
+        return Err(format!("Unexpected response: {}", response.status()));
+    }
+    let certificate = response
+        .text()
+        .await
+        .map_err(|err| format!("Invalid response: {}", err))?;
+
+    match output {
+        Some(path) => {
+            tokio::fs::write(&path, certificate)
+                .await
+                .map_err(|err| format!("Unable to write {}: {}", path.display(), err))?;
+            println!("CA certificate written to {}", path.display());
+        }
+        None => print!("{}", certificate),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,389 @@
+//! Webhook notifications for events which need attention, such as failing filter updates
+//! or a CA certificate about to expire.
+use crate::configuration::{AlertsConfig, Webhook, WebhookFormat};
+use crate::statistics::{AnomalySeries, Statistics};
+use openssl::asn1::Asn1Time;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+const CA_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60 * 6);
+const BLOCKED_SPIKE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Minutes observed before spikes are reported, so that the average means something.
+const BLOCKED_SPIKE_WARMUP_MINUTES: u32 = 10;
+/// Weight of the last minute in the average of blocked requests per minute.
+const BLOCKED_SPIKE_SMOOTHING: f64 = 0.1;
+
+#[derive(Debug, Clone)]
+pub(crate) enum Alert {
+    FilterUpdatesFailing {
+        failures: u32,
+        error: String,
+    },
+    CaCertificateExpiring {
+        days_left: i32,
+    },
+    BlockedRequestsSpike {
+        blocked: u64,
+        average: u64,
+    },
+    ClientSpike {
+        client: String,
+        series: AnomalySeries,
+        count: u64,
+        average: u64,
+    },
+    ProxyError(String),
+}
+
+impl Alert {
+    /// Alerts of the same kind share a cooldown.
+    fn kind(&self) -> &'static str {
+        match self {
+            Alert::FilterUpdatesFailing { .. } => "filter_updates_failing",
+            Alert::CaCertificateExpiring { .. } => "ca_certificate_expiring",
+            Alert::BlockedRequestsSpike { .. } => "blocked_requests_spike",
+            Alert::ClientSpike { .. } => "client_spike",
+            Alert::ProxyError(_) => "proxy_error",
+        }
+    }
+
+    /// Spikes of different clients are cooled down separately.
+    fn cooldown_key(&self) -> String {
+        match self {
+            Alert::ClientSpike { client, series, .. } => {
+                format!("{}:{}:{}", self.kind(), client, series.as_str())
+            }
+            _ => self.kind().to_string(),
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Alert::FilterUpdatesFailing { .. } => "Filter updates are failing",
+            Alert::CaCertificateExpiring { .. } => "CA certificate is about to expire",
+            Alert::BlockedRequestsSpike { .. } => "Spike of blocked requests",
+            Alert::ClientSpike { .. } => "Unusual activity of a client",
+            Alert::ProxyError(_) => "Proxy error",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Alert::FilterUpdatesFailing { failures, error } => {
+                format!("The last {} filter updates failed: {}", failures, error)
+            }
+            Alert::CaCertificateExpiring { days_left } if *days_left <= 0 => {
+                "The CA certificate has expired, browsers will reject proxied HTTPS sites."
+                    .to_string()
+            }
+            Alert::CaCertificateExpiring { days_left } => format!(
+                "The CA certificate expires in {} days, replace it from the settings.",
+                days_left
+            ),
+            Alert::BlockedRequestsSpike { blocked, average } => format!(
+                "{} requests were blocked in the last minute, against {} on average.",
+                blocked, average
+            ),
+            Alert::ClientSpike {
+                client,
+                series,
+                count,
+                average,
+            } => format!(
+                "{} made {} {} in the last minute, against {} on average. It may be infected or misconfigured.",
+                client,
+                count,
+                series.as_str(),
+                average
+            ),
+            Alert::ProxyError(error) => error.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Alerter {
+    http_client: reqwest::Client,
+    configuration: Arc<RwLock<AlertsConfig>>,
+    last_sent: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl Alerter {
+    pub(crate) fn new(http_client: reqwest::Client, configuration: AlertsConfig) -> Self {
+        Self {
+            http_client,
+            configuration: Arc::new(RwLock::new(configuration)),
+            last_sent: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub(crate) fn set_configuration(&self, configuration: AlertsConfig) {
+        *self.configuration.write().unwrap() = configuration;
+    }
+
+    pub(crate) fn configuration(&self) -> AlertsConfig {
+        self.configuration.read().unwrap().clone()
+    }
+
+    /// Sends `alert` to every webhook in the background, unless an alert of the same kind
+    /// was sent during the cooldown.
+    pub(crate) fn send(&self, alert: Alert) {
+        let configuration = self.configuration();
+        if configuration.webhooks.is_empty() {
+            return;
+        }
+
+        {
+            let mut last_sent = self.last_sent.lock().unwrap();
+            let now = Instant::now();
+            if let Some(sent_at) = last_sent.get(&alert.cooldown_key()) {
+                if now.duration_since(*sent_at) < configuration.cooldown() {
+                    log::debug!("Alert {} not sent, cooling down", alert.kind());
+                    return;
+                }
+            }
+            last_sent.insert(alert.cooldown_key(), now);
+        }
+
+        log::info!("Sending alert: {}", alert.title());
+
+        for webhook in configuration.webhooks {
+            let http_client = self.http_client.clone();
+            let alert = alert.clone();
+
+            tokio::spawn(async move {
+                let result = send_webhook(
+                    &http_client,
+                    &webhook,
+                    alert.kind(),
+                    alert.title(),
+                    &alert.message(),
+                )
+                .await;
+                if let Err(err) = result {
+                    log::warn!("Unable to send alert to {}: {}", webhook.url, err);
+                }
+            });
+        }
+    }
+
+    /// Periodically checks the CA certificate expiry date from the configuration file.
+    pub(crate) fn spawn_ca_expiry_monitor(
+        &self,
+        configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    ) {
+        let alerter = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let ca_expiry_days = alerter.configuration().ca_expiry_days;
+
+                if ca_expiry_days > 0 {
+                    let lock = configuration_save_lock.lock().await;
+                    let configuration = crate::configuration::Configuration::read_from_home().await;
+                    drop(lock);
+
+                    let days_left = match configuration {
+                        Ok(configuration) => match configuration.ca.get_ca_certificate().await {
+                            Ok(ca_certificate) => Asn1Time::days_from_now(0)
+                                .and_then(|now| now.diff(ca_certificate.not_after()))
+                                .map(|diff| diff.days)
+                                .ok(),
+                            Err(err) => {
+                                log::warn!("Unable to check the CA certificate expiry: {}", err);
+                                None
+                            }
+                        },
+                        Err(err) => {
+                            log::warn!("Unable to check the CA certificate expiry: {}", err);
+                            None
+                        }
+                    };
+
+                    if let Some(days_left) = days_left {
+                        if days_left <= ca_expiry_days as i32 {
+                            alerter.send(Alert::CaCertificateExpiring { days_left });
+                        }
+                    }
+                }
+
+                tokio::time::sleep(CA_EXPIRY_CHECK_INTERVAL).await;
+            }
+        });
+    }
+
+    /// Compares blocked requests of every minute, overall and by client, as well as requests
+    /// by client, with their moving average. Spikes are shown on the dashboard whether or not
+    /// webhooks are configured.
+    pub(crate) fn spawn_blocked_spike_monitor(&self, statistics: Statistics) {
+        let alerter = self.clone();
+
+        tokio::spawn(async move {
+            let mut blocked_average =
+                MovingAverage::new(*statistics.blocked_requests.lock().unwrap());
+            let mut client_averages: HashMap<(String, AnomalySeries), MovingAverage> =
+                HashMap::new();
+
+            loop {
+                tokio::time::sleep(BLOCKED_SPIKE_CHECK_INTERVAL).await;
+
+                let configuration = alerter.configuration();
+
+                let (blocked, average) =
+                    blocked_average.update(*statistics.blocked_requests.lock().unwrap());
+                if let Some(average) = average {
+                    if is_spike(
+                        blocked,
+                        average,
+                        configuration.blocked_spike_multiplier,
+                        configuration.blocked_spike_minimum,
+                    ) {
+                        let average = average.round() as u64;
+                        statistics.record_anomaly(
+                            None,
+                            AnomalySeries::BlockedRequests,
+                            blocked,
+                            average,
+                        );
+                        alerter.send(Alert::BlockedRequestsSpike { blocked, average });
+                    }
+                }
+
+                let totals = [
+                    (
+                        AnomalySeries::Requests,
+                        statistics.top_clients.lock().unwrap().clone(),
+                    ),
+                    (
+                        AnomalySeries::BlockedRequests,
+                        statistics.blocked_clients.lock().unwrap().clone(),
+                    ),
+                ];
+                // Counters of clients are cumulative, they only disappear on reset.
+                for moving_average in client_averages.values_mut() {
+                    moving_average.seen = false;
+                }
+                for (series, totals) in totals {
+                    for (client, total) in totals {
+                        let moving_average = client_averages
+                            .entry((client.clone(), series))
+                            .or_insert_with(|| MovingAverage::new(0));
+                        moving_average.seen = true;
+
+                        let (count, average) = moving_average.update(total);
+                        let average = match average {
+                            Some(average) => average,
+                            None => continue,
+                        };
+
+                        if is_spike(
+                            count,
+                            average,
+                            configuration.client_spike_multiplier,
+                            configuration.client_spike_minimum,
+                        ) {
+                            let average = average.round() as u64;
+                            statistics.record_anomaly(Some(client.clone()), series, count, average);
+                            alerter.send(Alert::ClientSpike {
+                                client,
+                                series,
+                                count,
+                                average,
+                            });
+                        }
+                    }
+                }
+                client_averages.retain(|_client, moving_average| moving_average.seen);
+            }
+        });
+    }
+}
+
+/// Exponential moving average of the per-minute increase of a counter.
+struct MovingAverage {
+    previous_total: u64,
+    average: f64,
+    minutes: u32,
+    /// Whether the counter still exists, it doesn't after statistics were reset.
+    seen: bool,
+}
+
+impl MovingAverage {
+    fn new(total: u64) -> Self {
+        Self {
+            previous_total: total,
+            average: 0.0,
+            minutes: 0,
+            seen: true,
+        }
+    }
+
+    /// Takes the new value of the counter, returns its increase over the last minute along with
+    /// the average before it, once enough minutes were observed for it to mean something.
+    fn update(&mut self, total: u64) -> (u64, Option<f64>) {
+        // The counter went down when statistics were reset.
+        let count = if total < self.previous_total {
+            total
+        } else {
+            total - self.previous_total
+        };
+        self.previous_total = total;
+
+        let average = (self.minutes >= BLOCKED_SPIKE_WARMUP_MINUTES).then_some(self.average);
+
+        self.average = if self.minutes == 0 {
+            count as f64
+        } else {
+            self.average + (count as f64 - self.average) * BLOCKED_SPIKE_SMOOTHING
+        };
+        self.minutes = self.minutes.saturating_add(1);
+
+        (count, average)
+    }
+}
+
+/// Whether `count` is at least `minimum` and more than `multiplier` times `average`, 0 disabling
+/// the check.
+fn is_spike(count: u64, average: f64, multiplier: u32, minimum: u64) -> bool {
+    multiplier > 0 && count >= minimum && count as f64 > average * multiplier as f64
+}
+
+/// Posts a notification to `webhook`, in its format. Alerts and reports are sent this way.
+pub(crate) async fn send_webhook(
+    http_client: &reqwest::Client,
+    webhook: &Webhook,
+    kind: &str,
+    title: &str,
+    message: &str,
+) -> Result<(), reqwest::Error> {
+    let request = http_client
+        .post(webhook.url.as_str())
+        .timeout(WEBHOOK_TIMEOUT);
+
+    let request = match webhook.format {
+        WebhookFormat::Json => request.json(&json!({
+            "kind": kind,
+            "title": title,
+            "message": message,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        })),
+        WebhookFormat::Telegram => request.json(&json!({
+            "chat_id": webhook.chat_id,
+            "text": format!("Privaxy: {}\n{}", title, message),
+        })),
+        WebhookFormat::Ntfy => request
+            .header("Title", format!("Privaxy: {}", title))
+            .header("Tags", "warning")
+            .body(message.to_string()),
+        WebhookFormat::Discord => request.json(&json!({
+            "content": format!("**Privaxy: {}**\n{}", title, message),
+        })),
+    };
+
+    request.send().await?.error_for_status()?;
+
+    Ok(())
+}
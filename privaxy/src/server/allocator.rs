@@ -0,0 +1,80 @@
+//! Statistics and purging of the global allocator, only available when built with the
+//! `jemalloc` feature.
+use serde::Serialize;
+use utoipa::ToSchema;
+
+pub(crate) const UNAVAILABLE: &str = "Privaxy was built without the jemalloc feature";
+
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+/// Memory held by the allocator, in bytes
+pub struct AllocatorStatistics {
+    /// Allocated by Privaxy.
+    pub allocated: u64,
+    /// In pages holding allocations, at least `allocated`.
+    pub active: u64,
+    /// Used by the allocator for its own bookkeeping.
+    pub metadata: u64,
+    /// In physically resident pages, the share of the RSS due to the heap.
+    pub resident: u64,
+    /// In extents mapped by the allocator.
+    pub mapped: u64,
+    /// Kept mapped to be reused instead of being returned to the operating system.
+    pub retained: u64,
+}
+
+#[cfg(feature = "jemalloc")]
+pub(crate) fn statistics() -> Result<AllocatorStatistics, String> {
+    use tikv_jemalloc_ctl::{epoch, stats};
+
+    // jemalloc only refreshes the statistics it reports when the epoch is advanced.
+    epoch::advance().map_err(|err| err.to_string())?;
+
+    let read = |value: tikv_jemalloc_ctl::Result<usize>| {
+        value
+            .map(|value| value as u64)
+            .map_err(|err| err.to_string())
+    };
+
+    Ok(AllocatorStatistics {
+        allocated: read(stats::allocated::read())?,
+        active: read(stats::active::read())?,
+        metadata: read(stats::metadata::read())?,
+        resident: read(stats::resident::read())?,
+        mapped: read(stats::mapped::read())?,
+        retained: read(stats::retained::read())?,
+    })
+}
+
+#[cfg(not(feature = "jemalloc"))]
+pub(crate) fn statistics() -> Result<AllocatorStatistics, String> {
+    Err(UNAVAILABLE.to_string())
+}
+
+/// Returns the unused dirty pages of every arena to the operating system.
+#[cfg(feature = "jemalloc")]
+pub(crate) fn purge() -> Result<(), String> {
+    // 4096 is `MALLCTL_ARENAS_ALL`, which addresses every arena at once.
+    const PURGE_ALL_ARENAS: &[u8] = b"arena.4096.purge\0";
+
+    // `arena.<i>.purge` takes no value, so it can't go through `tikv_jemalloc_ctl::raw` which
+    // always passes one.
+    let result = unsafe {
+        tikv_jemalloc_sys::mallctl(
+            PURGE_ALL_ARENAS.as_ptr() as *const _,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+
+    match result {
+        0 => Ok(()),
+        errno => Err(std::io::Error::from_raw_os_error(errno).to_string()),
+    }
+}
+
+#[cfg(not(feature = "jemalloc"))]
+pub(crate) fn purge() -> Result<(), String> {
+    Err(UNAVAILABLE.to_string())
+}
@@ -0,0 +1,270 @@
+use crate::blocker::{build_engine, RuleCounts};
+use crate::configuration::{self, Configuration, DEFAULT_PROFILE};
+use crate::proxy::dns::DnsResolver;
+use crate::proxy::egress::EgressRouter;
+use adblock::request::Request;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const BUNDLED_CORPUS: &str = include_str!("../resources/bench/requests.txt");
+
+/// What `privaxy bench` replays, and against which filters.
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// Requests to replay instead of the bundled corpus, one `url source_url type` per line.
+    pub corpus: Option<PathBuf>,
+    /// Filter list files to load instead of the configured ones.
+    pub filter_lists: Vec<PathBuf>,
+    /// Profile whose filters are loaded when no filter list files are given.
+    pub profile: String,
+    /// Number of times the corpus is replayed.
+    pub iterations: usize,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            corpus: None,
+            filter_lists: Vec::new(),
+            profile: DEFAULT_PROFILE.to_string(),
+            iterations: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub filter_lists: usize,
+    pub filter_lines: usize,
+    pub network_rules: u64,
+    pub cosmetic_rules: u64,
+    pub engine_build: Duration,
+    /// Resident memory added by building the engine, when the platform reports it.
+    pub engine_memory: Option<u64>,
+    pub resident_memory: Option<u64>,
+    pub corpus_requests: usize,
+    pub skipped_lines: usize,
+    pub checks: usize,
+    pub matched: usize,
+    pub exceptions: usize,
+    pub elapsed: Duration,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl BenchReport {
+    pub fn checks_per_second(&self) -> f64 {
+        self.checks as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Filters:     {} lists, {} lines, engine built in {:.2?}",
+            self.filter_lists, self.filter_lines, self.engine_build
+        )?;
+        writeln!(
+            f,
+            "Rules:       {} network, {} cosmetic",
+            self.network_rules, self.cosmetic_rules
+        )?;
+        writeln!(
+            f,
+            "Corpus:      {} requests ({} lines skipped)",
+            self.corpus_requests, self.skipped_lines
+        )?;
+        writeln!(
+            f,
+            "Matches:     {} checks, {} blocked, {} exceptions",
+            self.checks, self.matched, self.exceptions
+        )?;
+        writeln!(f, "Throughput:  {:.0} checks/s", self.checks_per_second())?;
+        writeln!(
+            f,
+            "Latency:     p50 {:.2?}, p99 {:.2?}, max {:.2?}",
+            self.p50, self.p99, self.max
+        )?;
+        write!(
+            f,
+            "Memory:      engine {}, resident {}",
+            format_memory(self.engine_memory),
+            format_memory(self.resident_memory)
+        )
+    }
+}
+
+fn format_memory(bytes: Option<u64>) -> String {
+    match bytes {
+        Some(bytes) => format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+        None => "unavailable".to_string(),
+    }
+}
+
+/// Loads the filters, builds an engine the way the proxy does and replays the corpus through
+/// it, timing every check.
+pub async fn run(options: &BenchOptions) -> Result<BenchReport, String> {
+    let corpus = match &options.corpus {
+        Some(path) => tokio::fs::read_to_string(path)
+            .await
+            .map_err(|err| format!("unable to read {}: {}", path.display(), err))?,
+        None => BUNDLED_CORPUS.to_string(),
+    };
+    let (requests, skipped_lines) = parse_corpus(&corpus);
+    if requests.is_empty() {
+        return Err("the corpus has no valid request".to_string());
+    }
+
+    let filters = load_filters(options).await?;
+    let filter_lines = filters.iter().map(|filter| filter.lines().count()).sum();
+    let rules = RuleCounts::of(&filters);
+
+    let memory_before = resident_memory();
+    let build_start = Instant::now();
+    let engine = build_engine(&filters);
+    let engine_build = build_start.elapsed();
+    let filter_lists = filters.len();
+    drop(filters);
+    let resident_memory = resident_memory();
+    let engine_memory = memory_before
+        .zip(resident_memory)
+        .map(|(before, after)| after.saturating_sub(before));
+
+    let iterations = options.iterations.max(1);
+    let mut latencies = Vec::with_capacity(requests.len() * iterations);
+    let mut matched = 0;
+    let mut exceptions = 0;
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        for request in &requests {
+            let check_start = Instant::now();
+            let result = engine.check_network_request(request);
+            latencies.push(check_start.elapsed());
+
+            if result.matched {
+                matched += 1;
+            }
+            if result.exception.is_some() {
+                exceptions += 1;
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+
+    latencies.sort_unstable();
+
+    Ok(BenchReport {
+        filter_lists,
+        filter_lines,
+        network_rules: rules.network,
+        cosmetic_rules: rules.cosmetic,
+        engine_build,
+        engine_memory,
+        resident_memory,
+        corpus_requests: requests.len(),
+        skipped_lines,
+        checks: latencies.len(),
+        matched,
+        exceptions,
+        elapsed,
+        p50: percentile(&latencies, 50),
+        p99: percentile(&latencies, 99),
+        max: latencies.last().copied().unwrap_or_default(),
+    })
+}
+
+async fn load_filters(options: &BenchOptions) -> Result<Vec<String>, String> {
+    if !options.filter_lists.is_empty() {
+        let mut filters = Vec::with_capacity(options.filter_lists.len());
+        for path in &options.filter_lists {
+            let filter = tokio::fs::read_to_string(path)
+                .await
+                .map_err(|err| format!("unable to read {}: {}", path.display(), err))?;
+            filters.push(filter);
+        }
+        return Ok(filters);
+    }
+
+    if configuration::get_base_directory().is_err() {
+        return Err("configuration directory not found, set PRIVAXY_BASE_PATH".to_string());
+    }
+
+    let mut configuration = Configuration::read_from_home()
+        .await
+        .map_err(|err| format!("unable to read the configuration: {}", err))?;
+
+    // Filters missing on disk are downloaded the same way the proxy would.
+    let dns_resolver = DnsResolver::new(&configuration.dns);
+    let egress_router = EgressRouter::new(&configuration.egress);
+    let http_client = crate::create_proxy_client(
+        dns_resolver,
+        Some(egress_router.reqwest_proxy()),
+        &configuration.proxy.timeouts,
+        &configuration.proxy.pool,
+    );
+
+    configuration::get_profile_filters_content(&mut configuration, &options.profile, &http_client)
+        .await
+        .map(|filters| filters.into_all())
+        .ok_or_else(|| format!("no profile named {}", options.profile))
+}
+
+/// Requests of the corpus and the number of lines which couldn't be parsed.
+fn parse_corpus(corpus: &str) -> (Vec<Request>, usize) {
+    let mut requests = Vec::new();
+    let mut skipped = 0;
+
+    for line in corpus.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let request = match (fields.next(), fields.next()) {
+            (Some(url), Some(source_url)) => {
+                Request::new(url, source_url, fields.next().unwrap_or("other")).ok()
+            }
+            _ => None,
+        };
+
+        match request {
+            Some(request) => requests.push(request),
+            None => skipped += 1,
+        }
+    }
+
+    (requests, skipped)
+}
+
+fn percentile(sorted: &[Duration], percentile: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+
+    let index = (sorted.len() * percentile).div_ceil(100).saturating_sub(1);
+    sorted[index.min(sorted.len() - 1)]
+}
+
+/// Resident set size of the process, only reported on Linux.
+fn resident_memory() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let kilobytes = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))?
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse::<u64>()
+            .ok()?;
+        Some(kilobytes * 1024)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    None
+}
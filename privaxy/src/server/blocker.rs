@@ -1,17 +1,24 @@
 use crate::blocker_utils::{
-    build_resource_from_file_contents, read_redirectable_resource_mapping, read_template_resources,
+    build_resource_from_file_contents, parse_template_resources,
+    read_redirectable_resource_mapping, read_template_resources,
 };
+use crate::configuration::{ProfileFilters, DEFAULT_PROFILE};
+use crate::statistics::{EngineStatistics, Statistics};
 use adblock::blocker::BlockerResult as AdblockerBlockerResult;
-use adblock::lists::FilterSet;
+use adblock::lists::{FilterSet, ParseOptions, ParsedFilter};
 use adblock::request::Request;
 use adblock::resources::Resource;
 use adblock::Engine;
 use crossbeam_channel::{Receiver, Sender};
 use include_dir::{include_dir, Dir};
 use lazy_static::lazy_static;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::sync::{Arc, RwLock};
 use tokio::sync::oneshot;
+use utoipa::ToSchema;
 
 pub type AdblockRequestChannel = Sender<BlockerRequest>;
 
@@ -39,38 +46,442 @@ pub struct CosmeticRequest {
 pub struct NetworkUrl {
     url: String,
     referer: String,
+    request_type: &'static str,
 }
 
-#[derive(Debug)]
 pub enum RequestKind {
     Url(NetworkUrl),
     Cosmetic(CosmeticRequest),
-    ReplaceEngine(Vec<String>),
+    /// Filters of each profile, by profile name.
+    ReplaceEngines(HashMap<String, ProfileFilters>),
+    /// Custom rules of each profile, by profile name. Engines of the filter lists are kept.
+    ReplaceCustomEngines(HashMap<String, Vec<String>>),
+    /// Scriptlets added by users, available to every engine along with the bundled resources.
+    ReplaceScriptlets(Vec<Resource>),
 }
 
 #[derive(Debug)]
 pub enum BlockerResult {
-    Network(adblock::blocker::BlockerResult),
+    /// Along with what blocked the request, when it is.
+    Network(adblock::blocker::BlockerResult, Option<BlockSource>),
     Cosmetic(CosmeticBlockerResult),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+/// Kind of content a blocked request was blocked as
+pub enum BlockCategory {
+    Ads,
+    Trackers,
+    Malware,
+    /// Custom rules and settings.
+    Custom,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+/// What blocked a request
+pub struct BlockSource {
+    /// Title of the filter list, or what else blocked the request.
+    pub list: String,
+    pub category: BlockCategory,
+}
+
+impl BlockSource {
+    pub fn new(list: impl Into<String>, category: BlockCategory) -> Self {
+        Self {
+            list: list.into(),
+            category,
+        }
+    }
+
+    fn custom_filters() -> Self {
+        Self::new("Custom filters", BlockCategory::Custom)
+    }
+
+    /// Lists of bundles aren't known, nor those of rules the engine reports differently than
+    /// they are written. Their rules are counted as ads.
+    pub(crate) fn unknown_list() -> Self {
+        Self::new("Filter lists", BlockCategory::Ads)
+    }
+}
+
 #[derive(Debug)]
 pub struct CosmeticBlockerResult {
     pub hidden_selectors: Vec<String>,
     pub style_selectors: HashMap<String, Vec<String>>,
     pub injected_script: Option<String>,
+    pub exceptions: CosmeticExceptions,
+}
+
+/// Exceptions which disabled cosmetic filters on a page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CosmeticExceptions {
+    /// `$generichide`: filters which apply to every site are disabled.
+    pub generichide: bool,
+    /// `$specifichide`: filters targeting the site are disabled.
+    pub specifichide: bool,
+    /// `$elemhide`: elements are no longer hidden at all.
+    pub elemhide: bool,
+}
+
+impl CosmeticExceptions {
+    pub fn any(&self) -> bool {
+        self.generichide || self.specifichide || self.elemhide
+    }
 }
 
 pub struct BlockerRequest {
     pub(crate) kind: RequestKind,
+    /// Profile whose engine answers the request.
+    pub(crate) profile: Arc<str>,
     pub(crate) respond_to: oneshot::Sender<BlockerResult>,
 }
 
+/// Page no cosmetic filter targets, to look up the generic ones.
+const GENERIC_PAGE_URL: &str = "https://generic.invalid/";
+
+/// Pages `$specifichide` and `$elemhide` exceptions apply to. The engine ignores these options,
+/// so their rules are matched against documents by engines of their own, as blocking rules.
+struct HideExceptions {
+    specifichide: Engine,
+    elemhide: Engine,
+}
+
+impl HideExceptions {
+    fn new(filters: &[String]) -> Self {
+        let mut specifichide = Vec::new();
+        let mut elemhide = Vec::new();
+
+        for line in filters.iter().flat_map(|filter| filter.lines()) {
+            let (pattern, options) = match line
+                .trim()
+                .strip_prefix("@@")
+                .and_then(|rule| rule.rsplit_once('$'))
+            {
+                Some(rule) => rule,
+                None => continue,
+            };
+
+            let mut is_specifichide = false;
+            let mut is_elemhide = false;
+            let mut other_options = Vec::new();
+
+            for option in options.split(',') {
+                match option.trim() {
+                    "specifichide" | "shide" => is_specifichide = true,
+                    "elemhide" | "ehide" => is_elemhide = true,
+                    // Handled by the engine itself, or implied.
+                    "generichide" | "ghide" | "document" | "doc" => {}
+                    option => other_options.push(option),
+                }
+            }
+            other_options.push("document");
+
+            let rule = format!("{}${}", pattern, other_options.join(","));
+            if is_specifichide {
+                specifichide.push(rule.clone());
+            }
+            if is_elemhide {
+                elemhide.push(rule);
+            }
+        }
+
+        Self {
+            specifichide: build_engine_with_resources(&[specifichide.join("\n")], &[]),
+            elemhide: build_engine_with_resources(&[elemhide.join("\n")], &[]),
+        }
+    }
+
+    fn check(&self, url: &str) -> CosmeticExceptions {
+        let request = match Request::new(url, url, "document") {
+            Ok(request) => request,
+            Err(_err) => return CosmeticExceptions::default(),
+        };
+
+        CosmeticExceptions {
+            generichide: false,
+            specifichide: self.specifichide.check_network_request(&request).matched,
+            elemhide: self.elemhide.check_network_request(&request).matched,
+        }
+    }
+}
+
+/// Filter lists the network rules of an engine come from, to tell which one blocked a request.
+/// Rules are kept as hashes, the lists themselves are already in memory in the engine.
+struct RuleSources {
+    sources: Vec<BlockSource>,
+    /// Index in `sources` of the first list each rule is in.
+    rules: HashMap<u64, usize>,
+}
+
+impl RuleSources {
+    fn new(lists: &[String], sources: &[BlockSource]) -> Self {
+        let mut rules = HashMap::new();
+
+        for (index, list) in lists.iter().enumerate().take(sources.len()) {
+            for line in list.lines() {
+                rules.entry(hash_rule(line)).or_insert(index);
+            }
+        }
+
+        Self {
+            sources: sources.to_vec(),
+            rules,
+        }
+    }
+
+    fn empty() -> Self {
+        Self {
+            sources: Vec::new(),
+            rules: HashMap::new(),
+        }
+    }
+
+    /// List `filter`, as reported by the engine, was loaded from.
+    fn find(&self, filter: &str) -> Option<&BlockSource> {
+        self.rules
+            .get(&hash_rule(filter))
+            .map(|index| &self.sources[*index])
+    }
+}
+
+fn hash_rule(rule: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    rule.trim().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Engines of a profile. Custom rules have an engine of their own, small enough to be rebuilt
+/// in milliseconds when they are edited, which is consulted after the one of the lists.
+struct ProfileEngines {
+    lists: Engine,
+    lists_rules: RuleCounts,
+    lists_memory: u64,
+    /// Unknown when the lists were loaded from a bundle.
+    lists_hide_exceptions: HideExceptions,
+    /// Empty when the lists were loaded from a bundle.
+    lists_sources: RuleSources,
+    custom: Engine,
+    custom_rules: RuleCounts,
+    custom_memory: u64,
+    custom_hide_exceptions: HideExceptions,
+}
+
+impl ProfileEngines {
+    fn new(filters: &ProfileFilters, resources: &[Resource]) -> Self {
+        let bundle =
+            filters.bundle.as_deref().and_then(|bundle| {
+                match load_bundle_with_resources(bundle, resources) {
+                    Ok(engine) => Some((engine, bundle.len() as u64)),
+                    Err(err) => {
+                        log::error!("Unable to load the filter bundle: {err}");
+                        None
+                    }
+                }
+            });
+        // Rules aren't known when loaded from a bundle.
+        let (lists, lists_rules, lists_memory, lists_hide_exceptions, lists_sources) = match bundle
+        {
+            Some((engine, memory)) => (
+                engine,
+                RuleCounts::default(),
+                memory,
+                HideExceptions::new(&[]),
+                RuleSources::empty(),
+            ),
+            None => {
+                let engine = build_engine_with_resources(&filters.lists, resources);
+                let memory = engine_memory(&engine);
+                (
+                    engine,
+                    RuleCounts::of(&filters.lists),
+                    memory,
+                    HideExceptions::new(&filters.lists),
+                    RuleSources::new(&filters.lists, &filters.sources),
+                )
+            }
+        };
+        let custom = build_engine_with_resources(&filters.custom, resources);
+
+        Self {
+            lists_rules,
+            lists_memory,
+            lists_hide_exceptions,
+            lists_sources,
+            lists,
+            custom_rules: RuleCounts::of(&filters.custom),
+            custom_memory: engine_memory(&custom),
+            custom_hide_exceptions: HideExceptions::new(&filters.custom),
+            custom,
+        }
+    }
+
+    fn empty() -> Self {
+        Self {
+            lists: Engine::new(true),
+            lists_rules: RuleCounts::default(),
+            lists_memory: 0,
+            lists_hide_exceptions: HideExceptions::new(&[]),
+            lists_sources: RuleSources::empty(),
+            custom: Engine::new(true),
+            custom_rules: RuleCounts::default(),
+            custom_memory: 0,
+            custom_hide_exceptions: HideExceptions::new(&[]),
+        }
+    }
+
+    fn replace_custom(&mut self, custom_filters: &[String], resources: &[Resource]) {
+        self.custom = build_engine_with_resources(custom_filters, resources);
+        self.custom_rules = RuleCounts::of(custom_filters);
+        self.custom_memory = engine_memory(&self.custom);
+        self.custom_hide_exceptions = HideExceptions::new(custom_filters);
+    }
+
+    fn use_resources(&mut self, resources: &[Resource]) {
+        self.lists.use_resources(resources.to_vec());
+        self.custom.use_resources(resources.to_vec());
+    }
+
+    /// Exceptions among the custom rules unblock requests blocked by the lists, unless by an
+    /// `$important` rule, and their blocking rules apply to requests the lists let through.
+    ///
+    /// Surrogates of `$redirect-rule` filters only apply to requests blocked by another rule,
+    /// which may come from the other engine, or by Privaxy itself. Those of custom rules take
+    /// precedence.
+    ///
+    /// Blocked requests come along with the list of the rule which blocked them.
+    fn check_network_request(
+        &self,
+        request: &Request,
+    ) -> (AdblockerBlockerResult, Option<BlockSource>) {
+        let lists = self.lists.check_network_request(request);
+        let custom = self.custom.check_network_request(request);
+        let redirect = custom.redirect.clone().or_else(|| lists.redirect.clone());
+
+        let (mut result, is_custom) = match (lists.matched, custom.matched) {
+            (true, _) if custom.exception.is_some() && !lists.important => (custom, true),
+            (true, _) => (lists, false),
+            (false, true) if lists.exception.is_some() && !custom.important => (lists, false),
+            (false, true) => (custom, true),
+            (false, false) if lists.exception.is_some() || lists.rewritten_url.is_some() => {
+                (lists, false)
+            }
+            (false, false) => (custom, true),
+        };
+
+        // Kept for requests Privaxy blocks for other reasons, unless they are excepted.
+        if result.exception.is_none() {
+            result.redirect = redirect;
+        }
+
+        let source = match (result.matched, is_custom) {
+            (false, _) => None,
+            (true, true) => Some(BlockSource::custom_filters()),
+            (true, false) => Some(
+                result
+                    .filter
+                    .as_deref()
+                    .and_then(|filter| self.lists_sources.find(filter))
+                    .cloned()
+                    .unwrap_or_else(BlockSource::unknown_list),
+            ),
+        };
+
+        (result, source)
+    }
+
+    fn cosmetic_resources(&self, request: &CosmeticRequest) -> CosmeticBlockerResult {
+        let mut lists = self.lists.url_cosmetic_resources(request.url.as_str());
+        let mut custom = self.custom.url_cosmetic_resources(request.url.as_str());
+
+        let lists_hide_exceptions = self.lists_hide_exceptions.check(&request.url);
+        let custom_hide_exceptions = self.custom_hide_exceptions.check(&request.url);
+        let exceptions = CosmeticExceptions {
+            generichide: lists.generichide || custom.generichide,
+            specifichide: lists_hide_exceptions.specifichide || custom_hide_exceptions.specifichide,
+            elemhide: lists_hide_exceptions.elemhide || custom_hide_exceptions.elemhide,
+        };
+        let hides_nothing =
+            exceptions.elemhide || (exceptions.specifichide && exceptions.generichide);
+
+        if hides_nothing {
+            for resources in [&mut lists, &mut custom] {
+                resources.hide_selectors.clear();
+                resources.style_selectors.clear();
+            }
+        } else if exceptions.specifichide {
+            // Filters returned for a page none of them targets are the generic ones.
+            for (resources, engine) in [(&mut lists, &self.lists), (&mut custom, &self.custom)] {
+                let generic = engine.url_cosmetic_resources(GENERIC_PAGE_URL);
+                resources.hide_selectors = generic.hide_selectors;
+                resources.style_selectors = generic.style_selectors;
+            }
+        }
+
+        // Exceptions of either engine apply to the selectors of both.
+        let mut selector_exceptions = lists.exceptions;
+        selector_exceptions.extend(custom.exceptions);
+
+        let mut hidden_selectors = Vec::new();
+
+        if !exceptions.generichide && !hides_nothing {
+            for engine in [&self.lists, &self.custom] {
+                hidden_selectors.extend(engine.hidden_class_id_selectors(
+                    &request.classes,
+                    &request.ids,
+                    &selector_exceptions,
+                ));
+            }
+        }
+
+        hidden_selectors.extend(
+            lists
+                .hide_selectors
+                .into_iter()
+                .chain(custom.hide_selectors)
+                .filter(|selector| !selector_exceptions.contains(selector)),
+        );
+
+        let mut style_selectors = lists.style_selectors;
+        for (selector, styles) in custom.style_selectors {
+            style_selectors.entry(selector).or_default().extend(styles);
+        }
+
+        let injected_script = [lists.injected_script, custom.injected_script]
+            .into_iter()
+            .filter(|script| !script.is_empty())
+            .collect::<Vec<_>>();
+
+        CosmeticBlockerResult {
+            hidden_selectors,
+            style_selectors,
+            injected_script: if injected_script.is_empty() {
+                None
+            } else {
+                Some(injected_script.join("\n"))
+            },
+            exceptions,
+        }
+    }
+
+    fn statistics(&self, profile: &str) -> EngineStatistics {
+        EngineStatistics {
+            profile: profile.to_string(),
+            network_rules: self.lists_rules.network + self.custom_rules.network,
+            cosmetic_rules: self.lists_rules.cosmetic + self.custom_rules.cosmetic,
+            memory_bytes: self.lists_memory + self.custom_memory,
+        }
+    }
+}
+
 pub struct Blocker {
     pub sender: Sender<BlockerRequest>,
     receiver: Receiver<BlockerRequest>,
-    engine: Engine,
+    engines: HashMap<String, ProfileEngines>,
+    /// Bundled resources and custom scriptlets.
+    resources: Vec<Resource>,
     blocking_disabled: BlockingDisabledStore,
+    statistics: Statistics,
 }
 
 lazy_static! {
@@ -103,15 +514,36 @@ impl Blocker {
         sender: Sender<BlockerRequest>,
         receiver: Receiver<BlockerRequest>,
         blocking_disabled: BlockingDisabledStore,
+        statistics: Statistics,
     ) -> Self {
         Self {
             sender,
             receiver,
-            engine: Engine::new(true),
+            engines: HashMap::from([(DEFAULT_PROFILE.to_string(), ProfileEngines::empty())]),
+            resources: ADBLOCKING_RESOURCES.clone(),
             blocking_disabled,
+            statistics,
         }
     }
 
+    /// Engines of profiles which were removed since the request was made fall back on the
+    /// default one.
+    fn engine(&self, profile: &str) -> &ProfileEngines {
+        self.engines
+            .get(profile)
+            .or_else(|| self.engines.get(DEFAULT_PROFILE))
+            .unwrap()
+    }
+
+    fn publish_statistics(&self) {
+        self.statistics.set_engines(
+            self.engines
+                .iter()
+                .map(|(profile, engines)| engines.statistics(profile))
+                .collect(),
+        );
+    }
+
     pub fn handle_requests(mut self) {
         while let Ok(request) = self.receiver.recv() {
             match request.kind {
@@ -122,42 +554,29 @@ impl Blocker {
                                 hidden_selectors: Vec::new(),
                                 style_selectors: HashMap::new(),
                                 injected_script: None,
+                                exceptions: CosmeticExceptions::default(),
                             },
                         ));
                         continue;
                     }
 
-                    let mut hidden_selectors = Vec::new();
-                    let url_specific_resources = self
-                        .engine
-                        .url_cosmetic_resources(cosmetic_request.url.as_str());
-
-                    if !url_specific_resources.generichide {
-                        let generic_selectors = self.engine.hidden_class_id_selectors(
-                            &cosmetic_request.classes,
-                            &cosmetic_request.ids,
-                            &url_specific_resources.exceptions,
-                        );
-
-                        hidden_selectors.extend(generic_selectors);
+                    let blocker_result = self
+                        .engine(&request.profile)
+                        .cosmetic_resources(&cosmetic_request);
+
+                    if blocker_result.exceptions.any() {
+                        if let Some(host) = url::Url::parse(&cosmetic_request.url)
+                            .ok()
+                            .and_then(|url| url.host_str().map(str::to_string))
+                        {
+                            self.statistics
+                                .record_cosmetic_exceptions(host, blocker_result.exceptions);
+                        }
                     }
 
-                    hidden_selectors.extend(url_specific_resources.hide_selectors);
-
-                    let injected_script = if !url_specific_resources.injected_script.is_empty() {
-                        Some(url_specific_resources.injected_script)
-                    } else {
-                        None
-                    };
-
-                    let _ =
-                        request
-                            .respond_to
-                            .send(BlockerResult::Cosmetic(CosmeticBlockerResult {
-                                hidden_selectors,
-                                style_selectors: url_specific_resources.style_selectors,
-                                injected_script,
-                            }));
+                    let _ = request
+                        .respond_to
+                        .send(BlockerResult::Cosmetic(blocker_result));
                 }
                 RequestKind::Url(network_url) => {
                     if !self.blocking_disabled.is_enabled() {
@@ -170,6 +589,7 @@ impl Blocker {
                                 filter: None,
                                 rewritten_url: None,
                             },
+                            None,
                         ));
                         continue;
                     }
@@ -177,54 +597,250 @@ impl Blocker {
                     let req = Request::new(
                         network_url.url.as_str(),
                         network_url.referer.as_str(),
-                        "other",
+                        network_url.request_type,
                     )
                     .unwrap();
-                    let blocker_result = self.engine.check_network_request(&req);
+                    let (blocker_result, source) =
+                        self.engine(&request.profile).check_network_request(&req);
 
                     let _ = request
                         .respond_to
-                        .send(BlockerResult::Network(blocker_result));
+                        .send(BlockerResult::Network(blocker_result, source));
                 }
-                RequestKind::ReplaceEngine(filters) => {
-                    log::debug!("Configuring blocking engine.");
+                RequestKind::ReplaceEngines(profiles_filters) => {
+                    log::debug!("Configuring blocking engines.");
 
-                    let mut filter_set = FilterSet::new(true);
+                    let mut engines = HashMap::with_capacity(profiles_filters.len() + 1);
 
-                    for filter in filters {
-                        filter_set
-                            .add_filter_list(&filter, adblock::lists::ParseOptions::default());
+                    for (profile, filters) in profiles_filters {
+                        engines.insert(profile, ProfileEngines::new(&filters, &self.resources));
                     }
 
-                    let mut adblock_engine = Engine::from_filter_set(filter_set, true);
-                    adblock_engine.use_resources(ADBLOCKING_RESOURCES.clone());
+                    engines
+                        .entry(DEFAULT_PROFILE.to_string())
+                        .or_insert_with(ProfileEngines::empty);
+
+                    self.engines = engines;
+                    self.publish_statistics();
+                }
+                RequestKind::ReplaceCustomEngines(profiles_custom_filters) => {
+                    log::debug!("Configuring custom rules engines.");
+
+                    for (profile, custom_filters) in profiles_custom_filters {
+                        // Profiles are only added along with their filter lists.
+                        if let Some(engines) = self.engines.get_mut(&profile) {
+                            engines.replace_custom(&custom_filters, &self.resources);
+                        }
+                    }
 
-                    self.engine = adblock_engine;
+                    self.publish_statistics();
                 }
+                RequestKind::ReplaceScriptlets(scriptlets) => {
+                    log::debug!("Configuring {} custom scriptlets.", scriptlets.len());
+
+                    self.resources = ADBLOCKING_RESOURCES
+                        .iter()
+                        .cloned()
+                        .chain(scriptlets)
+                        .collect();
+
+                    for engines in self.engines.values_mut() {
+                        engines.use_resources(&self.resources);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Engine matching against the given filter lists, with the bundled redirect resources and
+/// scriptlets.
+pub(crate) fn build_engine(filters: &[String]) -> Engine {
+    build_engine_with_resources(filters, &ADBLOCKING_RESOURCES)
+}
+
+fn build_engine_with_resources(filters: &[String], resources: &[Resource]) -> Engine {
+    let mut filter_set = FilterSet::new(true);
+
+    for filter in filters {
+        filter_set.add_filter_list(filter, ParseOptions::default());
+    }
+
+    let mut adblock_engine = Engine::from_filter_set(filter_set, true);
+    adblock_engine.use_resources(resources.to_vec());
+
+    adblock_engine
+}
+
+/// Engine serialized by `serialize_raw`, on this instance or another one running the same
+/// version, with the bundled redirect resources and scriptlets.
+pub(crate) fn load_bundle(bundle: &[u8]) -> Result<Engine, String> {
+    load_bundle_with_resources(bundle, &ADBLOCKING_RESOURCES)
+}
+
+fn load_bundle_with_resources(bundle: &[u8], resources: &[Resource]) -> Result<Engine, String> {
+    let mut adblock_engine = Engine::new(true);
+    adblock_engine
+        .deserialize(bundle)
+        .map_err(|err| format!("Invalid filter bundle: {:?}", err))?;
+    adblock_engine.use_resources(resources.to_vec());
+
+    Ok(adblock_engine)
+}
+
+/// Scriptlets of `custom_scriptlets`, in the format of uBlock Origin's `scriptlets.js`. Their
+/// names must end with `.js`, as `+js()` rules refer to them, and not be taken by a bundled
+/// resource nor by another scriptlet.
+pub(crate) fn parse_custom_scriptlets(custom_scriptlets: &str) -> Result<Vec<Resource>, String> {
+    let scriptlets = parse_template_resources(custom_scriptlets)?;
+
+    let mut names = ADBLOCKING_RESOURCES
+        .iter()
+        .flat_map(|resource| std::iter::once(&resource.name).chain(&resource.aliases))
+        .map(String::as_str)
+        .collect::<HashSet<_>>();
+
+    for scriptlet in &scriptlets {
+        if !scriptlet.name.ends_with(".js") {
+            return Err(format!(
+                "{}: names of scriptlets end with .js",
+                scriptlet.name
+            ));
+        }
+        for name in std::iter::once(&scriptlet.name).chain(&scriptlet.aliases) {
+            if !names.insert(name) {
+                return Err(format!("{}: the name is already taken", name));
             }
         }
     }
+
+    Ok(scriptlets)
+}
+
+/// Network exception rules among `rules` which apply to the request, each checked on its own.
+/// Cosmetic exceptions, and options which only make sense in exceptions, never match.
+pub(crate) fn matching_exceptions(
+    rules: &[String],
+    url: &str,
+    source_url: &str,
+    request_type: &str,
+) -> Result<Vec<String>, adblock::request::RequestError> {
+    let request = Request::new(url, source_url, request_type)?;
+
+    Ok(rules
+        .iter()
+        .filter(|rule| {
+            // The rule blocking what the exception unblocks matches the same requests.
+            let blocking_rule = match rule.strip_prefix("@@") {
+                Some(blocking_rule) => blocking_rule,
+                None => return false,
+            };
+
+            let mut filter_set = FilterSet::new(false);
+            filter_set.add_filter_list(blocking_rule, ParseOptions::default());
+
+            Engine::from_filter_set(filter_set, true)
+                .check_network_request(&request)
+                .matched
+        })
+        .cloned()
+        .collect())
+}
+
+/// Rules which parse successfully, by kind. Comments and invalid lines are left out.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct RuleCounts {
+    pub(crate) network: u64,
+    pub(crate) cosmetic: u64,
+}
+
+impl RuleCounts {
+    pub(crate) fn of(filters: &[String]) -> Self {
+        let mut counts = Self::default();
+
+        for line in filters.iter().flat_map(|filter| filter.lines()) {
+            match adblock::lists::parse_filter(line, false, ParseOptions::default()) {
+                Ok(ParsedFilter::Network(_)) => counts.network += 1,
+                Ok(ParsedFilter::Cosmetic(_)) => counts.cosmetic += 1,
+                Err(_) => {}
+            }
+        }
+
+        counts
+    }
+}
+
+/// Estimate of the heap used by `engine`, from the size of its serialized form which holds
+/// the same rules and lookup tables.
+pub(crate) fn engine_memory(engine: &Engine) -> u64 {
+    engine
+        .serialize_raw()
+        .map(|serialized| serialized.len() as u64)
+        .unwrap_or_default()
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct AdblockRequester {
     adblock_request_channel: AdblockRequestChannel,
+    profile: Arc<str>,
 }
 
 impl AdblockRequester {
     pub(crate) fn new(adblock_request_channel: AdblockRequestChannel) -> Self {
         Self {
             adblock_request_channel,
+            profile: Arc::from(DEFAULT_PROFILE),
         }
     }
 
-    pub(crate) async fn replace_engine(&self, filters: Vec<String>) {
+    /// Requests waiting to be handled by the blocker thread.
+    pub(crate) fn backlog(&self) -> usize {
+        self.adblock_request_channel.len()
+    }
+
+    /// A requester checking against the engine of `profile`.
+    pub(crate) fn for_profile(&self, profile: &str) -> Self {
+        Self {
+            adblock_request_channel: self.adblock_request_channel.clone(),
+            profile: Arc::from(profile),
+        }
+    }
+
+    pub(crate) async fn replace_engines(&self, profiles_filters: HashMap<String, ProfileFilters>) {
+        let (sender, _receiver) = oneshot::channel();
+
+        self.adblock_request_channel
+            .send(BlockerRequest {
+                respond_to: sender,
+                profile: self.profile.clone(),
+                kind: RequestKind::ReplaceEngines(profiles_filters),
+            })
+            .unwrap();
+    }
+
+    pub(crate) async fn replace_custom_engines(
+        &self,
+        profiles_custom_filters: HashMap<String, Vec<String>>,
+    ) {
         let (sender, _receiver) = oneshot::channel();
 
         self.adblock_request_channel
             .send(BlockerRequest {
                 respond_to: sender,
-                kind: RequestKind::ReplaceEngine(filters),
+                profile: self.profile.clone(),
+                kind: RequestKind::ReplaceCustomEngines(profiles_custom_filters),
+            })
+            .unwrap();
+    }
+
+    pub(crate) async fn replace_scriptlets(&self, scriptlets: Vec<Resource>) {
+        let (sender, _receiver) = oneshot::channel();
+
+        self.adblock_request_channel
+            .send(BlockerRequest {
+                respond_to: sender,
+                profile: self.profile.clone(),
+                kind: RequestKind::ReplaceScriptlets(scriptlets),
             })
             .unwrap();
     }
@@ -240,6 +856,7 @@ impl AdblockRequester {
         self.adblock_request_channel
             .send(BlockerRequest {
                 respond_to: sender,
+                profile: self.profile.clone(),
                 kind: RequestKind::Cosmetic(CosmeticRequest { url, ids, classes }),
             })
             .unwrap();
@@ -247,32 +864,38 @@ impl AdblockRequester {
         match receiver.await {
             Ok(blocker_result) => match blocker_result {
                 BlockerResult::Cosmetic(blocker_result) => blocker_result,
-                BlockerResult::Network(_) => unreachable!(),
+                BlockerResult::Network(..) => unreachable!(),
             },
             Err(_err) => unreachable!(),
         }
     }
 
+    /// Whether the request is blocked, along with the list which blocked it then.
     pub(crate) async fn is_network_url_blocked(
         &self,
         network_url: String,
         referer: String,
-    ) -> (bool, adblock::blocker::BlockerResult) {
+        request_type: &'static str,
+    ) -> (bool, adblock::blocker::BlockerResult, Option<BlockSource>) {
         let (sender, receiver) = oneshot::channel();
 
         self.adblock_request_channel
             .send(BlockerRequest {
                 respond_to: sender,
+                profile: self.profile.clone(),
                 kind: RequestKind::Url(NetworkUrl {
                     url: network_url,
                     referer,
+                    request_type,
                 }),
             })
             .unwrap();
 
         match receiver.await {
             Ok(blocker_result) => match blocker_result {
-                BlockerResult::Network(blocker_result) => (blocker_result.matched, blocker_result),
+                BlockerResult::Network(blocker_result, source) => {
+                    (blocker_result.matched, blocker_result, source)
+                }
                 BlockerResult::Cosmetic(_) => unreachable!(),
             },
             Err(_err) => unreachable!(),
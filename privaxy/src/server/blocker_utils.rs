@@ -12,15 +12,16 @@ static NON_EMPTY_LINE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"\S"#).unwrap(
 /// Represents a single entry of the `Map` from uBlock Origin's `redirect-resources.js`.
 ///
 /// - `name` is the name of a resource, corresponding to its path in the `web_accessible_resources`
-/// directory
+///   directory
 ///
 /// - `alias` is a list of optional additional names that can be used to reference the resource
 ///
 /// - `data` is either `"text"` or `"blob"`, but is currently unused in `adblock-rust`. Within
-/// uBlock Origin, it's used to prevent text files from being encoded in base64 in a data URL.
+///   uBlock Origin, it's used to prevent text files from being encoded in base64 in a data URL.
 pub struct ResourceProperties {
     pub name: String,
     pub alias: Vec<String>,
+    #[allow(dead_code)]
     pub data: Option<String>,
 }
 use base64::{engine::general_purpose, Engine};
@@ -126,6 +127,13 @@ pub fn read_redirectable_resource_mapping(mapfile_data: &str) -> Vec<ResourcePro
 /// Reads data from a file in the form of uBlock Origin's `scriptlets.js` file and produces
 /// templatable scriptlets for use in cosmetic filtering.
 pub fn read_template_resources(scriptlets_data: &str) -> Vec<Resource> {
+    parse_template_resources(scriptlets_data).expect("Bundled scriptlets are well formed")
+}
+
+/// Same as `read_template_resources`, for scriptlets written by users: malformed ones are
+/// reported rather than panicking, and the last one may end with the text rather than with an
+/// empty line.
+pub fn parse_template_resources(scriptlets_data: &str) -> Result<Vec<Resource>, String> {
     let mut resources = Vec::new();
 
     let uncommented = TOP_COMMENT_RE.replace_all(scriptlets_data, "");
@@ -133,22 +141,32 @@ pub fn read_template_resources(scriptlets_data: &str) -> Vec<Resource> {
     let mut details = std::collections::HashMap::<_, Vec<_>>::new();
     let mut script = String::new();
 
-    for line in uncommented.lines() {
+    for line in uncommented.lines().chain(std::iter::once("")) {
         if line.starts_with('#') || line.starts_with("// ") || line == "//" {
             continue;
         }
 
-        if name.is_none() {
-            if let Some(stripped) = line.strip_prefix("/// ") {
-                name = Some(stripped.trim());
+        let current_name = match name {
+            Some(current_name) => current_name,
+            None => {
+                if let Some(stripped) = line.strip_prefix("/// ") {
+                    name = Some(stripped.trim());
+                }
+                continue;
             }
-            continue;
-        }
+        };
 
         if let Some(stripped) = line.strip_prefix("/// ") {
             let mut line_parts = stripped.split_whitespace();
-            let prop = line_parts.next().expect("Detail line has property name");
-            let value = line_parts.next().expect("Detail line has property value");
+            let (prop, value) = match (line_parts.next(), line_parts.next()) {
+                (Some(prop), Some(value)) => (prop, value),
+                _ => {
+                    return Err(format!(
+                        "{}: detail lines need a property name and a value, such as `/// alias name.js`",
+                        current_name
+                    ))
+                }
+            };
             details.entry(prop).or_default().push(value);
             continue;
         }
@@ -159,6 +177,10 @@ pub fn read_template_resources(scriptlets_data: &str) -> Vec<Resource> {
             continue;
         }
 
+        if script.is_empty() {
+            return Err(format!("{}: the scriptlet has no script", current_name));
+        }
+
         let kind = if script.contains("{{1}}") {
             ResourceType::Template
         } else {
@@ -166,7 +188,7 @@ pub fn read_template_resources(scriptlets_data: &str) -> Vec<Resource> {
         };
 
         resources.push(Resource {
-            name: name.expect("Resource name must be specified").to_owned(),
+            name: current_name.to_owned(),
             aliases: details
                 .remove("alias")
                 .unwrap_or_default()
@@ -184,7 +206,7 @@ pub fn read_template_resources(scriptlets_data: &str) -> Vec<Resource> {
         script.clear();
     }
 
-    resources
+    Ok(resources)
 }
 
 /// Reads byte data from an arbitrary resource file, and assembles a `Resource` from it with the
@@ -1,3 +1,6 @@
+use crate::configuration::{self, InterceptionTlsConfig};
+use crate::key_log;
+use crate::statistics::Statistics;
 use http::uri::Authority;
 use openssl::{
     asn1::Asn1Time,
@@ -13,18 +16,29 @@ use openssl::{
         X509NameBuilder, X509Ref, X509Req, X509ReqBuilder, X509,
     },
 };
-use rustls::{Certificate, PrivateKey, ServerConfig};
-use std::time::{SystemTime, UNIX_EPOCH};
+use rustls::{Certificate, KeyLog, PrivateKey, ServerConfig};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{str::FromStr, sync::Arc};
-use tokio::sync::Mutex;
+use tokio::fs;
+use tokio::sync::{watch, Mutex};
 use uluru::LRUCache;
+use utoipa::ToSchema;
 
-const MAX_CACHED_CERTIFICATES: usize = 1_000;
+pub(crate) const MAX_CACHED_CERTIFICATES: usize = 1_000;
+/// Authorities intercepted the most, one per line, most frequent first.
+const POPULAR_AUTHORITIES_FILE_NAME: &str = "popular_authorities.txt";
+const POPULAR_AUTHORITIES_SAVE_INTERVAL: Duration = Duration::from_secs(60 * 5);
 
 #[derive(Clone)]
 pub struct SignedWithCaCert {
     authority: Authority,
     pub server_configuration: ServerConfig,
+    signed_at: Instant,
 }
 
 impl SignedWithCaCert {
@@ -33,6 +47,8 @@ impl SignedWithCaCert {
         private_key: PKey<Private>,
         ca_certificate: X509,
         ca_private_key: PKey<Private>,
+        tls: &InterceptionTlsConfig,
+        key_log: Option<Arc<dyn KeyLog>>,
     ) -> Self {
         let x509 =
             Self::build_ca_signed_cert(&ca_certificate, &ca_private_key, &authority, &private_key);
@@ -42,18 +58,30 @@ impl SignedWithCaCert {
             Certificate(ca_certificate.to_der().unwrap()),
         ];
 
-        let server_configuration = ServerConfig::builder()
-            .with_safe_default_cipher_suites()
-            .with_safe_default_kx_groups()
-            .with_safe_default_protocol_versions()
-            .unwrap()
+        // Settings are validated on startup, but not when reloaded.
+        let server_configuration_builder = match tls.server_config_builder() {
+            Ok(builder) => builder,
+            Err(err) => {
+                log::error!("Invalid TLS settings, using defaults: {err}");
+                ServerConfig::builder().with_safe_defaults()
+            }
+        };
+
+        let mut server_configuration = server_configuration_builder
             .with_no_client_auth()
             .with_single_cert(certs, PrivateKey(private_key.private_key_to_der().unwrap()))
             .unwrap();
+        if let Some(key_log) = key_log {
+            server_configuration.key_log = key_log;
+        }
+        if tls.http2 {
+            server_configuration.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        }
 
         Self {
             authority,
             server_configuration,
+            signed_at: Instant::now(),
         }
     }
 
@@ -180,59 +208,388 @@ impl SignedWithCaCert {
     }
 }
 
-#[derive(Clone)]
-pub struct CertCache {
-    cache: Arc<Mutex<LRUCache<SignedWithCaCert, MAX_CACHED_CERTIFICATES>>>,
+/// What certificates are signed with, replaced when the CA or the TLS settings change.
+struct Signer {
     // We use a single RSA key for all certificates.
     private_key: PKey<Private>,
     ca_certificate: X509,
     ca_private_key: PKey<Private>,
+    tls: InterceptionTlsConfig,
+    key_log: Option<Arc<dyn KeyLog>>,
 }
 
-impl CertCache {
-    pub fn new(ca_certificate: X509, ca_private_key: PKey<Private>) -> Self {
+impl Signer {
+    fn new(
+        ca_certificate: X509,
+        ca_private_key: PKey<Private>,
+        tls: InterceptionTlsConfig,
+    ) -> Self {
         Self {
-            cache: Arc::new(Mutex::new(LRUCache::default())),
             private_key: {
                 let rsa: Rsa<Private> = Rsa::generate(2048).unwrap();
                 PKey::from_rsa(rsa).unwrap()
             },
             ca_certificate,
             ca_private_key,
+            key_log: key_log::open(&tls),
+            tls,
+        }
+    }
+
+    fn sign(&self, authority: Authority) -> SignedWithCaCert {
+        SignedWithCaCert::new(
+            authority,
+            self.private_key.clone(),
+            self.ca_certificate.clone(),
+            self.ca_private_key.clone(),
+            &self.tls,
+            self.key_log.clone(),
+        )
+    }
+}
+
+/// Lookups of intercepted connections, certificates pre-generated aren't counted.
+#[derive(Debug, Default)]
+struct CacheMetrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+/// Certificates signed for intercepted hosts
+pub struct CertificateCacheStatus {
+    pub entries: usize,
+    pub capacity: usize,
+    /// Connections whose certificate was cached, since the start.
+    pub hits: u64,
+    /// Connections whose certificate had to be signed, since the start.
+    pub misses: u64,
+    /// Share of connections whose certificate was cached, `None` before the first one.
+    pub hit_rate: Option<f64>,
+    pub ages: CertificateAges,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+/// Cached certificates by time since they were signed
+pub struct CertificateAges {
+    pub last_hour: usize,
+    pub last_day: usize,
+    pub last_week: usize,
+    pub older: usize,
+}
+
+#[derive(Clone)]
+pub struct CertCache {
+    cache: Arc<Mutex<LRUCache<SignedWithCaCert, MAX_CACHED_CERTIFICATES>>>,
+    /// Certificates being generated, concurrent connections to the same authority wait for
+    /// the first one to be done instead of generating their own.
+    pending: Arc<std::sync::Mutex<HashMap<Authority, PendingCertificate>>>,
+    signer: Arc<RwLock<Arc<Signer>>>,
+    metrics: Arc<CacheMetrics>,
+    statistics: Statistics,
+}
+
+impl CertCache {
+    pub fn new(
+        ca_certificate: X509,
+        ca_private_key: PKey<Private>,
+        tls: InterceptionTlsConfig,
+        statistics: Statistics,
+    ) -> Self {
+        Self {
+            cache: Arc::new(Mutex::new(LRUCache::default())),
+            pending: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            signer: Arc::new(RwLock::new(Arc::new(Signer::new(
+                ca_certificate,
+                ca_private_key,
+                tls,
+            )))),
+            metrics: Arc::new(CacheMetrics::default()),
+            statistics,
         }
     }
 
+    pub(crate) fn tls(&self) -> InterceptionTlsConfig {
+        self.signer.read().unwrap().tls.clone()
+    }
+
+    /// Signs certificates with the given CA and TLS settings from now on, discarding those
+    /// cached when they differ from the current ones. Returns whether they did.
+    pub(crate) async fn reconfigure(
+        &self,
+        ca_certificate: X509,
+        ca_private_key: PKey<Private>,
+        tls: InterceptionTlsConfig,
+    ) -> bool {
+        {
+            let signer = self.signer.read().unwrap();
+
+            if !ca_private_key.public_eq(&signer.ca_private_key)
+                || ca_certificate.to_der().unwrap() != signer.ca_certificate.to_der().unwrap()
+            {
+                log::info!("CA certificate changed, discarding cached certificates");
+            } else if tls != signer.tls {
+                log::info!("TLS settings changed, discarding cached certificates");
+            } else {
+                return false;
+            }
+        }
+
+        // Generating the RSA key takes a while on small devices.
+        let signer =
+            tokio::task::spawn_blocking(move || Signer::new(ca_certificate, ca_private_key, tls))
+                .await
+                .unwrap();
+        *self.signer.write().unwrap() = Arc::new(signer);
+        self.flush().await;
+
+        true
+    }
+
     async fn insert(&self, certificate: SignedWithCaCert) {
         let mut cache = self.cache.lock().await;
         cache.insert(certificate);
     }
 
-    pub async fn get(&self, authority: Authority) -> SignedWithCaCert {
+    /// Discards every cached certificate, returns how many there were.
+    pub(crate) async fn flush(&self) -> usize {
         let mut cache = self.cache.lock().await;
+        let count = cache.len();
+        cache.clear();
 
-        match cache.find(|cert| cert.authority == authority) {
-            Some(certificate) => certificate.clone(),
-            None => {
-                // We release the previously acquired lock early as `insert`, which we will call just
-                // afterwards also waits to acquire a lock.
-                std::mem::drop(cache);
+        count
+    }
 
-                let private_key = self.private_key.clone();
+    /// Discards the certificate of `authority`, returns whether it was cached.
+    pub(crate) async fn evict(&self, authority: &Authority) -> bool {
+        let mut cache = self.cache.lock().await;
 
-                let ca_certificate = self.ca_certificate.clone();
-                let ca_private_key = self.ca_private_key.clone();
+        // The cache can't remove a single entry, it is refilled with the others from the least
+        // recently used one so that their order is kept.
+        let mut certificates = Vec::with_capacity(cache.len());
+        let mut evicted = false;
+        for certificate in cache.iter() {
+            if certificate.authority == *authority {
+                evicted = true;
+            } else {
+                certificates.push(certificate.clone());
+            }
+        }
 
-                // This operation is somewhat CPU intensive and on some lower powered machines,
-                // not running it inside of a thread pool may cause it to block the executor for too long.
-                let certificate = tokio::task::spawn_blocking(move || {
-                    SignedWithCaCert::new(authority, private_key, ca_certificate, ca_private_key)
-                })
-                .await
-                .unwrap();
+        if evicted {
+            cache.clear();
+            for certificate in certificates.into_iter().rev() {
+                cache.insert(certificate);
+            }
+        }
+
+        evicted
+    }
+
+    pub(crate) async fn status(&self) -> CertificateCacheStatus {
+        let cache = self.cache.lock().await;
+
+        let mut ages = CertificateAges::default();
+        for certificate in cache.iter() {
+            match certificate.signed_at.elapsed().as_secs() {
+                age if age < 60 * 60 => ages.last_hour += 1,
+                age if age < 60 * 60 * 24 => ages.last_day += 1,
+                age if age < 60 * 60 * 24 * 7 => ages.last_week += 1,
+                _ => ages.older += 1,
+            }
+        }
+
+        let hits = self.metrics.hits.load(Ordering::Relaxed);
+        let misses = self.metrics.misses.load(Ordering::Relaxed);
+
+        CertificateCacheStatus {
+            entries: cache.len(),
+            capacity: MAX_CACHED_CERTIFICATES,
+            hits,
+            misses,
+            hit_rate: match hits + misses {
+                0 => None,
+                lookups => Some(hits as f64 / lookups as f64),
+            },
+            ages,
+        }
+    }
+
+    pub async fn get(&self, authority: Authority) -> SignedWithCaCert {
+        self.statistics
+            .increment_intercepted_authorities(authority.to_string());
+
+        self.get_or_generate(authority, Some(&self.metrics)).await
+    }
+
+    async fn get_or_generate(
+        &self,
+        authority: Authority,
+        metrics: Option<&CacheMetrics>,
+    ) -> SignedWithCaCert {
+        let mut cache = self.cache.lock().await;
+
+        if let Some(certificate) = cache.find(|cert| cert.authority == authority) {
+            if let Some(metrics) = metrics {
+                metrics.hits.fetch_add(1, Ordering::Relaxed);
+            }
+            return certificate.clone();
+        }
+        if let Some(metrics) = metrics {
+            metrics.misses.fetch_add(1, Ordering::Relaxed);
+        }
+
+        // Registered while holding the cache lock: a generation is either still pending or its
+        // certificate was found in the cache above.
+        let sender = {
+            let mut pending = self.pending.lock().unwrap();
+            match pending.get(&authority) {
+                Some(receiver) => Err(receiver.clone()),
+                None => {
+                    let (sender, receiver) = watch::channel(None);
+                    pending.insert(authority.clone(), receiver);
+                    Ok(sender)
+                }
+            }
+        };
+        std::mem::drop(cache);
+
+        match sender {
+            Ok(sender) => {
+                let _pending = PendingGuard {
+                    pending: self.pending.clone(),
+                    authority: authority.clone(),
+                };
+                let certificate = self.generate(authority).await;
+                let _ = sender.send(Some(certificate.clone()));
 
-                self.insert(certificate.clone()).await;
                 certificate
             }
+            Err(mut receiver) => {
+                let certificate = receiver
+                    .wait_for(Option::is_some)
+                    .await
+                    .ok()
+                    .and_then(|certificate| certificate.clone());
+
+                match certificate {
+                    Some(certificate) => certificate,
+                    // The connection generating it went away before it was done.
+                    None => self.generate(authority).await,
+                }
+            }
         }
     }
+
+    /// Signs a certificate for `authority` and caches it, unless the CA or the TLS settings
+    /// changed meanwhile.
+    async fn generate(&self, authority: Authority) -> SignedWithCaCert {
+        let signer = self.signer.read().unwrap().clone();
+        let signer_ref = signer.clone();
+        let _generation = self.statistics.certificate_generation();
+
+        // This operation is somewhat CPU intensive and on some lower powered machines,
+        // not running it inside of a thread pool may cause it to block the executor for too long.
+        let certificate = tokio::task::spawn_blocking(move || signer_ref.sign(authority))
+            .await
+            .unwrap();
+
+        let is_current = Arc::ptr_eq(&signer, &self.signer.read().unwrap());
+        if is_current {
+            self.insert(certificate.clone()).await;
+        }
+        certificate
+    }
+
+    /// Signs certificates for the authorities intercepted the most, one at a time so that
+    /// connections being handled meanwhile aren't starved of blocking threads.
+    pub(crate) async fn pregenerate(&self) {
+        let authorities = self
+            .statistics
+            .intercepted_authorities()
+            .into_iter()
+            .take(
+                self.tls()
+                    .pregenerated_certificates
+                    .min(MAX_CACHED_CERTIFICATES),
+            )
+            .filter_map(|authority| Authority::from_str(&authority).ok())
+            .collect::<Vec<_>>();
+
+        if authorities.is_empty() {
+            return;
+        }
+
+        let count = authorities.len();
+        for authority in authorities {
+            self.get_or_generate(authority, None).await;
+        }
+
+        log::info!("Pre-generated certificates of {} hosts", count);
+    }
+}
+
+type PendingCertificate = watch::Receiver<Option<SignedWithCaCert>>;
+
+/// Removes a pending generation once done, or when the connection generating it went away.
+struct PendingGuard {
+    pending: Arc<std::sync::Mutex<HashMap<Authority, PendingCertificate>>>,
+    authority: Authority,
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.authority);
+    }
+}
+
+fn popular_authorities_path() -> Option<PathBuf> {
+    configuration::get_base_directory()
+        .ok()
+        .map(|directory| directory.join(POPULAR_AUTHORITIES_FILE_NAME))
+}
+
+/// Restores the authorities saved by the previous run into `statistics`.
+pub(crate) async fn restore_popular_authorities(statistics: &Statistics) {
+    let path = match popular_authorities_path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    match fs::read_to_string(&path).await {
+        Ok(contents) => statistics.restore_intercepted_authorities(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+        ),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => log::warn!("Unable to read {}: {}", path.display(), err),
+    }
+}
+
+/// Periodically saves the authorities intercepted the most, for `restore_popular_authorities`.
+pub(crate) fn spawn_popular_authorities_saver(statistics: Statistics) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(POPULAR_AUTHORITIES_SAVE_INTERVAL).await;
+
+            let path = match popular_authorities_path() {
+                Some(path) => path,
+                None => continue,
+            };
+            let authorities = statistics.intercepted_authorities();
+            if authorities.is_empty() {
+                continue;
+            }
+
+            let mut contents = authorities.join("\n");
+            contents.push('\n');
+            if let Err(err) = fs::write(&path, contents).await {
+                log::warn!("Unable to save {}: {}", path.display(), err);
+            }
+        }
+    });
 }
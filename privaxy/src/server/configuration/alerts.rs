@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use std::time::Duration;
+use url::Url;
+
+const DEFAULT_FILTER_UPDATE_FAILURES: u32 = 3;
+const DEFAULT_CA_EXPIRY_DAYS: u32 = 30;
+const DEFAULT_BLOCKED_SPIKE_MULTIPLIER: u32 = 5;
+const DEFAULT_BLOCKED_SPIKE_MINIMUM: u64 = 100;
+const DEFAULT_CLIENT_SPIKE_MULTIPLIER: u32 = 5;
+const DEFAULT_CLIENT_SPIKE_MINIMUM: u64 = 300;
+const DEFAULT_COOLDOWN_MINUTES: u64 = 60;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// Payload sent to a webhook
+pub enum WebhookFormat {
+    /// `{"kind": ..., "title": ..., "message": ..., "timestamp": ...}`
+    #[default]
+    Json,
+    /// Telegram Bot API `sendMessage` URL, requires `chat_id`.
+    Telegram,
+    /// ntfy topic URL.
+    Ntfy,
+    /// Discord webhook URL.
+    Discord,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Webhook {
+    #[serde_as(as = "DisplayFromStr")]
+    pub url: Url,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// Chat messages are sent to, Telegram only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chat_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Notifications sent to webhooks when something needs attention
+pub struct AlertsConfig {
+    pub webhooks: Vec<Webhook>,
+    /// Consecutive failed filter updates after which an alert is sent, 0 disables the alert.
+    pub filter_update_failures: u32,
+    /// Days before the CA certificate expires from which an alert is sent, 0 disables the alert.
+    pub ca_expiry_days: u32,
+    /// Blocked requests in a minute, relative to the average, considered abnormal.
+    /// 0 disables the alert.
+    pub blocked_spike_multiplier: u32,
+    /// Blocked requests in a minute under which no spike is reported, however high the ratio.
+    pub blocked_spike_minimum: u64,
+    /// Requests, or blocked requests, of a client in a minute, relative to its average,
+    /// considered abnormal. A device suddenly talking much more than usual may be infected or
+    /// misconfigured. 0 disables the alert.
+    pub client_spike_multiplier: u32,
+    /// Requests of a client in a minute under which no spike is reported.
+    pub client_spike_minimum: u64,
+    /// Minimum time between two alerts of the same kind.
+    pub cooldown_minutes: u64,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            webhooks: Vec::new(),
+            filter_update_failures: DEFAULT_FILTER_UPDATE_FAILURES,
+            ca_expiry_days: DEFAULT_CA_EXPIRY_DAYS,
+            blocked_spike_multiplier: DEFAULT_BLOCKED_SPIKE_MULTIPLIER,
+            blocked_spike_minimum: DEFAULT_BLOCKED_SPIKE_MINIMUM,
+            client_spike_multiplier: DEFAULT_CLIENT_SPIKE_MULTIPLIER,
+            client_spike_minimum: DEFAULT_CLIENT_SPIKE_MINIMUM,
+            cooldown_minutes: DEFAULT_COOLDOWN_MINUTES,
+        }
+    }
+}
+
+impl AlertsConfig {
+    pub(crate) fn cooldown(&self) -> Duration {
+        Duration::from_secs(self.cooldown_minutes * 60)
+    }
+}
@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use std::path::PathBuf;
+use tokio::fs;
+use url::Url;
+
+/// File the imported bundle is kept in, in the filters directory.
+const BUNDLE_FILE_NAME: &str = "bundle.dat";
+
+#[serde_as]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Engine compiled by another Privaxy instance, so that devices too slow to compile large filter
+/// lists only have to load it
+pub struct FilterBundleConfig {
+    /// Load the imported bundle for the default profile instead of compiling its filter lists.
+    /// Custom rules and the lists of other profiles are still compiled locally.
+    pub enabled: bool,
+    /// `/api/filters/bundle` of the instance compiling the lists, downloaded on every filter
+    /// update. Bundles can be imported through the API instead.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<Url>,
+}
+
+fn get_bundle_path() -> PathBuf {
+    super::filter::get_filter_directory().join(BUNDLE_FILE_NAME)
+}
+
+pub(crate) async fn read_bundle() -> super::ConfigurationResult<Vec<u8>> {
+    Ok(fs::read(get_bundle_path()).await?)
+}
+
+/// Checks that `bundle` can be loaded by this version of Privaxy before saving it.
+pub(crate) async fn save_bundle(bundle: Vec<u8>) -> super::ConfigurationResult<()> {
+    let bundle = tokio::task::spawn_blocking(move || {
+        crate::blocker::load_bundle(&bundle).map(|_engine| bundle)
+    })
+    .await
+    .map_err(|err| super::ConfigurationError::FilterError(err.to_string()))?
+    .map_err(super::ConfigurationError::FilterError)?;
+
+    fs::create_dir_all(super::filter::get_filter_directory()).await?;
+    fs::write(get_bundle_path(), bundle).await?;
+
+    Ok(())
+}
+
+impl FilterBundleConfig {
+    /// Downloads the bundle from `url`, if any.
+    pub(super) async fn update(
+        &self,
+        http_client: &reqwest::Client,
+    ) -> super::ConfigurationResult<()> {
+        let url = match &self.url {
+            Some(url) if self.enabled => url,
+            _ => return Ok(()),
+        };
+
+        log::debug!("Updating filter bundle from {}", url);
+
+        let response = http_client.get(url.as_str()).send().await?;
+        if !response.status().is_success() {
+            return Err(super::ConfigurationError::FilterError(format!(
+                "Failed to fetch filter bundle: {}",
+                response.status()
+            )));
+        }
+
+        save_bundle(response.bytes().await?.to_vec()).await
+    }
+}
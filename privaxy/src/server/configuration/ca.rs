@@ -1,15 +1,19 @@
+use super::{resolve_secret, secret_environment_variable, secret_file, SecretError};
 use openssl::pkey::PKey;
 use openssl::pkey::Private;
 use openssl::x509::X509;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 use tokio::fs;
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
 pub struct Ca {
+    /// PEM certificate, or a `file://` or `env://` reference to it.
     #[serde(default)]
     pub(super) ca_certificate: Option<String>,
+    /// PEM private key, or a `file://` or `env://` reference to it.
     #[serde(default)]
     pub(super) ca_private_key: Option<String>,
     #[serde(default)]
@@ -78,6 +82,7 @@ impl Ca {
                 Err(err) => Err(super::ConfigurationError::FileSystemError(err)),
             }
         } else if let Some(ref ca_certificate) = self.ca_certificate {
+            let ca_certificate = resolve_secret(ca_certificate)?;
             let ca_cert = X509::from_pem(ca_certificate.as_bytes())
                 .map_err(|_| super::ConfigurationError::DirectoryNotFound)?;
             Ok(ca_cert)
@@ -90,12 +95,23 @@ impl Ca {
         &mut self,
         ca_certificate: &str,
     ) -> super::ConfigurationResult<()> {
-        if let Some(ref ca_certificate_path) = &self.ca_certificate_path {
-            let ca_path = PathBuf::from(ca_certificate_path);
-            match fs::write(&ca_path, ca_certificate.as_bytes()).await {
+        let referenced_path = self.ca_certificate.as_deref().and_then(secret_file);
+        if let Some(ca_path) = self
+            .ca_certificate_path
+            .as_deref()
+            .map(Path::new)
+            .or(referenced_path)
+        {
+            match fs::write(ca_path, ca_certificate.as_bytes()).await {
                 Ok(()) => Ok(()),
                 Err(err) => Err(super::ConfigurationError::FileSystemError(err)),
             }
+        } else if let Some(name) = self
+            .ca_certificate
+            .as_deref()
+            .and_then(secret_environment_variable)
+        {
+            Err(SecretError::ReadOnly(name.to_string()).into())
         } else {
             self.ca_certificate = Some(ca_certificate.to_string());
             Ok(())
@@ -114,6 +130,7 @@ impl Ca {
                 Err(err) => Err(super::ConfigurationError::FileSystemError(err)),
             }
         } else if let Some(ref ca_private_key) = self.ca_private_key {
+            let ca_private_key = resolve_secret(ca_private_key)?;
             let pkey = PKey::private_key_from_pem(ca_private_key.as_bytes())
                 .map_err(|_| super::ConfigurationError::DirectoryNotFound)?;
             Ok(pkey)
@@ -126,12 +143,23 @@ impl Ca {
         &mut self,
         ca_private_key: &str,
     ) -> super::ConfigurationResult<()> {
-        if let Some(ref ca_private_key_path) = &self.ca_private_key_path {
-            let ca_path = PathBuf::from(ca_private_key_path);
-            match fs::write(&ca_path, ca_private_key.as_bytes()).await {
+        let referenced_path = self.ca_private_key.as_deref().and_then(secret_file);
+        if let Some(ca_path) = self
+            .ca_private_key_path
+            .as_deref()
+            .map(Path::new)
+            .or(referenced_path)
+        {
+            match fs::write(ca_path, ca_private_key.as_bytes()).await {
                 Ok(()) => Ok(()),
                 Err(err) => Err(super::ConfigurationError::FileSystemError(err)),
             }
+        } else if let Some(name) = self
+            .ca_private_key
+            .as_deref()
+            .and_then(secret_environment_variable)
+        {
+            Err(SecretError::ReadOnly(name.to_string()).into())
         } else {
             self.ca_private_key = Some(ca_private_key.to_string());
             Ok(())
@@ -0,0 +1,218 @@
+use crate::blocker::BlockCategory;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::net::IpAddr;
+use tokio::fs;
+use url::Url;
+use utoipa::ToSchema;
+
+#[derive(
+    Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Adult,
+    Gambling,
+    Social,
+    Ads,
+}
+
+impl Category {
+    pub const ALL: [Category; 4] = [
+        Category::Adult,
+        Category::Gambling,
+        Category::Social,
+        Category::Ads,
+    ];
+
+    fn default_list_url(&self) -> &'static str {
+        match self {
+            Category::Adult => {
+                "https://raw.githubusercontent.com/StevenBlack/hosts/master/alternates/porn-only/hosts"
+            }
+            Category::Gambling => {
+                "https://raw.githubusercontent.com/StevenBlack/hosts/master/alternates/gambling-only/hosts"
+            }
+            Category::Social => {
+                "https://raw.githubusercontent.com/StevenBlack/hosts/master/alternates/social-only/hosts"
+            }
+            Category::Ads => "https://raw.githubusercontent.com/StevenBlack/hosts/master/hosts",
+        }
+    }
+
+    /// Category requests blocked as part of this one are counted in.
+    pub(crate) fn block_category(&self) -> BlockCategory {
+        match self {
+            Category::Ads => BlockCategory::Ads,
+            Category::Social => BlockCategory::Trackers,
+            Category::Adult | Category::Gambling => BlockCategory::Custom,
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Category::Adult => "adult",
+            Category::Gambling => "gambling",
+            Category::Social => "social",
+            Category::Ads => "ads",
+        })
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+/// Domain list backing a category, in hosts file format or one domain per line
+pub struct CategoryList {
+    pub category: Category,
+    #[serde_as(as = "DisplayFromStr")]
+    pub url: Url,
+}
+
+impl CategoryList {
+    fn file_name(&self) -> String {
+        super::filter::calc_filter_filename(self.url.as_str())
+    }
+
+    pub(super) async fn update(
+        &self,
+        http_client: &reqwest::Client,
+    ) -> super::ConfigurationResult<String> {
+        log::debug!("Updating {} category list: {}", self.category, self.url);
+
+        let response = http_client.get(self.url.as_str()).send().await?;
+        if !response.status().is_success() {
+            return Err(super::ConfigurationError::FilterError(format!(
+                "Failed to fetch category list: {}",
+                response.status()
+            )));
+        }
+        let content = response.text().await?;
+
+        let filters_directory = super::filter::get_filter_directory();
+        fs::create_dir_all(&filters_directory).await?;
+        fs::write(filters_directory.join(self.file_name()), &content).await?;
+
+        Ok(content)
+    }
+
+    pub(super) async fn get_contents(
+        &self,
+        http_client: &reqwest::Client,
+    ) -> super::ConfigurationResult<String> {
+        let list_path = super::filter::get_filter_directory().join(self.file_name());
+        match fs::read(&list_path).await {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                self.update(http_client).await
+            }
+            Err(err) => Err(super::ConfigurationError::FileSystemError(err)),
+            Ok(list) => Ok(std::str::from_utf8(&list)?.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+/// Categories blocked for a single client
+pub struct ClientCategories {
+    /// IP address or username of the client.
+    pub client: String,
+    pub blocked: BTreeSet<Category>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Category blocking, on top of filter lists
+pub struct CategoriesConfig {
+    pub lists: Vec<CategoryList>,
+    /// Categories blocked for clients without their own entry in `clients`.
+    pub blocked: BTreeSet<Category>,
+    pub clients: Vec<ClientCategories>,
+}
+
+impl Default for CategoriesConfig {
+    fn default() -> Self {
+        Self {
+            lists: Category::ALL
+                .into_iter()
+                .map(|category| CategoryList {
+                    category,
+                    url: Url::parse(category.default_list_url()).unwrap(),
+                })
+                .collect(),
+            blocked: BTreeSet::new(),
+            clients: Vec::new(),
+        }
+    }
+}
+
+impl CategoriesConfig {
+    /// Categories blocked for a client. Entries matching the username take precedence over
+    /// entries matching the IP address.
+    pub(crate) fn blocked_for(
+        &self,
+        client_ip_address: IpAddr,
+        client_username: Option<&str>,
+    ) -> &BTreeSet<Category> {
+        let by_username = client_username
+            .and_then(|username| self.clients.iter().find(|client| client.client == username));
+        let by_ip_address = || {
+            self.clients
+                .iter()
+                .find(|client| client.client.parse::<IpAddr>().ok() == Some(client_ip_address))
+        };
+
+        match by_username.or_else(by_ip_address) {
+            Some(client) => &client.blocked,
+            None => &self.blocked,
+        }
+    }
+
+    /// Categories which are blocked for at least one client, other lists don't need to be loaded.
+    pub(super) fn used_categories(&self) -> BTreeSet<Category> {
+        self.clients
+            .iter()
+            .flat_map(|client| client.blocked.iter())
+            .chain(self.blocked.iter())
+            .copied()
+            .collect()
+    }
+}
+
+/// Loads the lists of the categories in use into `category_store`. Lists that can't be
+/// retrieved are skipped, their category is then never matched.
+pub(crate) async fn load_categories(
+    configuration: &super::Configuration,
+    http_client: &reqwest::Client,
+    category_store: &crate::proxy::categories::CategoryStore,
+    update: bool,
+) {
+    let categories = &configuration.proxy.categories;
+    let used_categories = categories.used_categories();
+
+    let futures = categories
+        .lists
+        .iter()
+        .filter(|list| used_categories.contains(&list.category))
+        .map(|list| async move {
+            let contents = if update {
+                list.update(http_client).await
+            } else {
+                list.get_contents(http_client).await
+            };
+
+            (list.category, contents)
+        });
+
+    let mut lists = Vec::new();
+    for (category, contents) in futures::future::join_all(futures).await {
+        match contents {
+            Ok(contents) => lists.push((category, contents)),
+            Err(err) => log::error!("Unable to retrieve {} category list: {:?}", category, err),
+        }
+    }
+
+    category_store.replace_lists(lists);
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+/// Protocol used to reach DNS servers
+pub enum DnsProtocol {
+    /// Plain DNS, over TCP for truncated answers.
+    #[default]
+    Udp,
+    /// DNS over TLS.
+    Tls,
+    /// DNS over HTTPS.
+    Https,
+}
+
+impl DnsProtocol {
+    pub(crate) fn default_port(&self) -> u16 {
+        match self {
+            DnsProtocol::Udp => 53,
+            DnsProtocol::Tls => 853,
+            DnsProtocol::Https => 443,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+/// Address family tried first when connecting to dual-stack hosts
+pub enum IpPreference {
+    /// In the order of the DNS answer.
+    #[default]
+    System,
+    Ipv4,
+    Ipv6,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(default)]
+/// DNS servers queried instead of the system ones
+pub struct DnsServers {
+    pub protocol: DnsProtocol,
+    #[schema(value_type = Vec<String>)]
+    pub addresses: Vec<IpAddr>,
+    /// Defaults to the standard port of the protocol.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Name the certificate of the servers is checked against, required over TLS and HTTPS.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+/// DNS servers resolving a domain and its subdomains
+pub struct DomainDnsServers {
+    /// `corp.example` or `*.corp.example`, both match the domain and its subdomains.
+    pub domain: String,
+    #[serde(flatten)]
+    pub servers: DnsServers,
+}
+
+impl DomainDnsServers {
+    pub(crate) fn suffix(&self) -> String {
+        self.domain
+            .trim_start_matches("*.")
+            .trim_matches('.')
+            .to_lowercase()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(default)]
+/// Resolution of upstream servers and connection to them, shared by proxied requests and tunnels
+pub struct DnsConfig {
+    pub ip_preference: IpPreference,
+    /// Delay, in milliseconds, before connecting to the next address of a host while
+    /// connecting to the previous one, on networks where one address family is broken.
+    pub connection_attempt_delay_ms: u64,
+    /// Number of answers kept in the cache.
+    pub cache_size: usize,
+    /// Answers are cached for at least this many seconds, whatever their TTL.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_ttl: Option<u64>,
+    /// Answers are cached for at most this many seconds, whatever their TTL.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_ttl: Option<u64>,
+    /// Addresses of hosts, used instead of resolving them. Useful for names only known on
+    /// the local network.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[schema(value_type = BTreeMap<String, Vec<String>>)]
+    pub hosts: BTreeMap<String, Vec<IpAddr>>,
+    /// Servers resolving domains which aren't listed in `domains`, the system ones when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<DnsServers>,
+    /// Servers resolving specific domains, the most specific domain applies.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub domains: Vec<DomainDnsServers>,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            ip_preference: IpPreference::System,
+            connection_attempt_delay_ms: 250,
+            cache_size: 4096,
+            min_ttl: None,
+            max_ttl: None,
+            hosts: BTreeMap::new(),
+            upstream: None,
+            domains: Vec::new(),
+        }
+    }
+}
+
+impl DnsConfig {
+    pub(crate) fn connection_attempt_delay(&self) -> Duration {
+        Duration::from_millis(self.connection_attempt_delay_ms)
+    }
+
+    /// Addresses `host` is overridden with.
+    pub(crate) fn host_override(&self, host: &str) -> Option<&[IpAddr]> {
+        let host = host.trim_end_matches('.');
+
+        self.hosts
+            .iter()
+            .find(|(name, _addresses)| name.eq_ignore_ascii_case(host))
+            .map(|(_name, addresses)| addresses.as_slice())
+    }
+}
@@ -0,0 +1,208 @@
+use super::resolve_optional_secret;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_TOR_ADDRESS: &str = "127.0.0.1:9050";
+
+fn default_tor_address() -> String {
+    DEFAULT_TOR_ADDRESS.to_string()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum UpstreamKind {
+    /// Connect to servers directly.
+    Direct,
+    /// Connect through a SOCKS5 proxy, which resolves host names.
+    Socks5 {
+        /// `host:port` of the proxy.
+        address: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        /// May be a `file://` or `env://` reference.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        password: Option<String>,
+    },
+    /// Connect through the SOCKS port of a Tor client, which is required for `.onion` sites.
+    Tor {
+        #[serde(default = "default_tor_address")]
+        address: String,
+    },
+    /// Connect through an HTTP proxy, such as a corporate one, with `CONNECT` tunnels.
+    Http {
+        /// `host:port` of the proxy.
+        address: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        authentication: Option<ProxyAuthentication>,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(tag = "scheme", rename_all = "lowercase")]
+/// Authentication to an HTTP proxy
+pub enum ProxyAuthentication {
+    Basic {
+        username: String,
+        /// May be a `file://` or `env://` reference.
+        password: String,
+    },
+    /// NTLMv2, as required by Windows proxies.
+    Ntlm {
+        /// May be prefixed by the domain, as `DOMAIN\user`.
+        username: String,
+        /// May be a `file://` or `env://` reference.
+        password: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        domain: Option<String>,
+    },
+    /// Kerberos through SPNEGO, with the ticket of the user Privaxy runs as. Requires the
+    /// `kerberos` feature.
+    Negotiate {
+        /// `HTTP/<host of the proxy>` when unset.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        service_principal: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+/// A way out to the internet
+pub struct Upstream {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: UpstreamKind,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+/// Domains reached through an upstream
+pub struct EgressRoute {
+    /// `example.com`, `*.example.com` or `.example.com`, all match the domain and its
+    /// subdomains. `.onion` matches every onion site.
+    pub domains: Vec<String>,
+    /// Name of the upstream.
+    pub upstream: String,
+}
+
+/// A SOCKS5 proxy, as used to connect to a server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SocksProxy {
+    pub(crate) address: String,
+    pub(crate) credentials: Option<(String, String)>,
+}
+
+/// An HTTP proxy, as used to connect to a server. Passwords are resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct HttpProxy {
+    pub(crate) address: String,
+    pub(crate) authentication: Option<ProxyAuthentication>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Routing of connections to servers, domains which aren't routed are reached directly
+pub struct EgressConfig {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub upstreams: Vec<Upstream>,
+    /// The first route matching a domain applies.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<EgressRoute>,
+}
+
+impl EgressConfig {
+    pub(crate) fn upstream(&self, name: &str) -> Option<&Upstream> {
+        self.upstreams.iter().find(|upstream| upstream.name == name)
+    }
+
+    /// Upstream connections to `host` go through, `None` when they are direct.
+    pub(crate) fn upstream_for(&self, host: &str) -> Option<&Upstream> {
+        let route = self.routes.iter().find(|route| {
+            route
+                .domains
+                .iter()
+                .any(|domain| is_within_domain(host, domain))
+        })?;
+
+        self.upstream(&route.upstream)
+    }
+}
+
+impl UpstreamKind {
+    /// SOCKS proxy connections go through, `None` when they are direct or go through an HTTP
+    /// proxy.
+    pub(crate) fn socks_proxy(&self) -> Option<SocksProxy> {
+        match self {
+            UpstreamKind::Direct | UpstreamKind::Http { .. } => None,
+            UpstreamKind::Socks5 {
+                address,
+                username,
+                password,
+            } => Some(SocksProxy {
+                address: address.clone(),
+                credentials: username.clone().map(|username| {
+                    let password = resolve_optional_secret(password.as_deref());
+                    (username, password.unwrap_or_default().into_owned())
+                }),
+            }),
+            UpstreamKind::Tor { address } => Some(SocksProxy {
+                address: address.clone(),
+                credentials: None,
+            }),
+        }
+    }
+
+    /// HTTP proxy connections go through, `None` when they don't.
+    pub(crate) fn http_proxy(&self) -> Option<HttpProxy> {
+        let (address, authentication) = match self {
+            UpstreamKind::Http {
+                address,
+                authentication,
+            } => (address, authentication),
+            _ => return None,
+        };
+
+        let resolve = |password: &str| {
+            resolve_optional_secret(Some(password))
+                .unwrap_or_default()
+                .into_owned()
+        };
+        let authentication = authentication
+            .as_ref()
+            .map(|authentication| match authentication {
+                ProxyAuthentication::Basic { username, password } => ProxyAuthentication::Basic {
+                    username: username.clone(),
+                    password: resolve(password),
+                },
+                ProxyAuthentication::Ntlm {
+                    username,
+                    password,
+                    domain,
+                } => ProxyAuthentication::Ntlm {
+                    username: username.clone(),
+                    password: resolve(password),
+                    domain: domain.clone(),
+                },
+                ProxyAuthentication::Negotiate { service_principal } => {
+                    ProxyAuthentication::Negotiate {
+                        service_principal: service_principal.clone(),
+                    }
+                }
+            });
+
+        Some(HttpProxy {
+            address: address.clone(),
+            authentication,
+        })
+    }
+}
+
+/// Whether `host` is `domain` or one of its subdomains. `domain` may be prefixed by `*.` or `.`.
+pub(crate) fn is_within_domain(host: &str, domain: &str) -> bool {
+    let host = host.trim_end_matches('.').to_lowercase();
+    let domain = domain
+        .trim_start_matches('*')
+        .trim_matches('.')
+        .to_lowercase();
+
+    host == domain
+        || host
+            .strip_suffix(domain.as_str())
+            .is_some_and(|subdomain| subdomain.ends_with('.'))
+}
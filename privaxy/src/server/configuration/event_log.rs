@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_MAX_EVENTS: usize = 5_000;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Events of the requests feed kept on disk, so that they survive restarts and are shown to
+/// clients of the feed which weren't connected when they happened
+pub struct EventLogConfig {
+    pub enabled: bool,
+    /// Latest events replayed to new clients of the feed. Up to twice as many are kept on disk.
+    pub max_events: usize,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_events: DEFAULT_MAX_EVENTS,
+        }
+    }
+}
@@ -1,14 +1,18 @@
+use crate::blocker::{BlockCategory, BlockSource};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::env;
+use std::fmt;
 use std::path::PathBuf;
 use tokio::fs;
 use url::Url;
 
 use serde_with::{serde_as, DisplayFromStr};
+use utoipa::ToSchema;
 pub(crate) const FILTERS_DIRECTORY_NAME: &str = "filters";
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
 pub enum FilterGroup {
     Default,
     Regional,
@@ -18,17 +22,29 @@ pub enum FilterGroup {
     Social,
 }
 
-impl ToString for FilterGroup {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for FilterGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let group = match self {
             FilterGroup::Default => "default",
             FilterGroup::Regional => "regional",
             FilterGroup::Ads => "ads",
             FilterGroup::Privacy => "privacy",
             FilterGroup::Malware => "malware",
             FilterGroup::Social => "social",
+        };
+
+        f.write_str(group)
+    }
+}
+
+impl FilterGroup {
+    /// Category requests blocked by lists of the group are counted in.
+    pub(crate) fn block_category(&self) -> BlockCategory {
+        match self {
+            FilterGroup::Default | FilterGroup::Regional | FilterGroup::Ads => BlockCategory::Ads,
+            FilterGroup::Privacy | FilterGroup::Social => BlockCategory::Trackers,
+            FilterGroup::Malware => BlockCategory::Malware,
         }
-        .to_string()
     }
 }
 
@@ -44,7 +60,7 @@ pub struct DefaultFilter {
 }
 
 #[serde_as]
-#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
 pub struct Filter {
     /// If the filter is enabled
     pub enabled: bool,
@@ -55,6 +71,7 @@ pub struct Filter {
     /// Local file name of the filter
     pub file_name: String,
     #[serde_as(as = "DisplayFromStr")]
+    #[schema(value_type = String)]
     /// Remote URL of the filter
     pub url: Url,
 }
@@ -62,6 +79,12 @@ pub struct Filter {
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 pub struct DefaultFilters(Vec<DefaultFilter>);
 
+impl Default for DefaultFilters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DefaultFilters {
     pub fn new() -> Self {
         let mut filters = Vec::new();
@@ -319,40 +342,262 @@ pub(crate) async fn get_filter(
     }
 }
 
-fn get_filter_directory() -> PathBuf {
+pub(super) fn get_filter_directory() -> PathBuf {
     let filter_dir: PathBuf = match env::var("PRIVAXY_FILTER_PATH") {
         Ok(val) => PathBuf::from(&val),
         // Assume home directory
         Err(_) => PathBuf::from(FILTERS_DIRECTORY_NAME),
     };
-    return super::get_base_directory().unwrap().join(filter_dir);
+    super::get_base_directory().unwrap().join(filter_dir)
+}
+
+/// Contents of the filter lists of a profile, and its custom rules which are loaded in an
+/// engine of their own so that editing them doesn't rebuild the one of the lists.
+#[derive(Debug, Clone, Default)]
+pub struct ProfileFilters {
+    pub(crate) lists: Vec<String>,
+    /// Where each of `lists` comes from, in the same order.
+    pub(crate) sources: Vec<BlockSource>,
+    pub(crate) custom: Vec<String>,
+    /// Engine compiled by another instance, loaded in place of `lists`.
+    pub(crate) bundle: Option<Vec<u8>>,
+}
+
+impl ProfileFilters {
+    fn new(
+        lists: Vec<(BlockSource, String)>,
+        custom_filters: &[String],
+        exception_filters: &[String],
+    ) -> Self {
+        let mut custom = [custom_filters, exception_filters].concat();
+        custom.sort_unstable();
+        custom.dedup();
+
+        let (sources, lists) = lists.into_iter().unzip();
+
+        Self {
+            lists,
+            sources,
+            custom,
+            bundle: None,
+        }
+    }
+
+    /// Lists and custom rules together, as a single engine would load them.
+    pub(crate) fn into_all(mut self) -> Vec<String> {
+        self.lists.append(&mut self.custom);
+        self.lists
+    }
 }
 
 pub(crate) async fn get_filters_content(
     configuration: &mut super::Configuration,
     http_client: &reqwest::Client,
-) -> Vec<String> {
+) -> ProfileFilters {
+    let lists = collect_filters_content(
+        configuration
+            .filters
+            .iter_mut()
+            .filter(|filter| filter.enabled),
+        http_client,
+    )
+    .await;
+
+    ProfileFilters::new(
+        lists,
+        &configuration.custom_filters,
+        &configuration.exception_filters,
+    )
+}
+
+/// Filters of the default profile with the imported bundle in place of its lists. `None` when no
+/// bundle is used or it can't be read, the lists are compiled then.
+async fn get_bundle_filters(configuration: &super::Configuration) -> Option<ProfileFilters> {
+    if !configuration.filter_bundle.enabled {
+        return None;
+    }
+
+    match super::read_bundle().await {
+        Ok(bundle) => {
+            let mut filters = ProfileFilters::new(
+                Vec::new(),
+                &configuration.custom_filters,
+                &configuration.exception_filters,
+            );
+            filters.bundle = Some(bundle);
+            Some(filters)
+        }
+        Err(err) => {
+            log::error!(
+                "Unable to read the filter bundle, compiling the filter lists instead: {err}"
+            );
+            None
+        }
+    }
+}
+
+/// Filters of the default profile and of every profile in use, by profile name.
+pub(crate) async fn get_profiles_filters_content(
+    configuration: &mut super::Configuration,
+    http_client: &reqwest::Client,
+) -> HashMap<String, ProfileFilters> {
+    let default_filters = match get_bundle_filters(configuration).await {
+        Some(default_filters) => default_filters,
+        None => get_filters_content(configuration, http_client).await,
+    };
+
+    let mut profiles_filters = HashMap::new();
+    profiles_filters.insert(super::DEFAULT_PROFILE.to_string(), default_filters);
+
+    let profiles = configuration
+        .proxy
+        .profiles
+        .used_profiles()
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for profile in profiles {
+        let lists = collect_filters_content(
+            configuration
+                .filters
+                .iter_mut()
+                .filter(|filter| profile.filters.contains(&filter.file_name)),
+            http_client,
+        )
+        .await;
+        profiles_filters.insert(
+            profile.name,
+            ProfileFilters::new(
+                lists,
+                &profile.custom_filters,
+                &configuration.exception_filters,
+            ),
+        );
+    }
+
+    profiles_filters
+}
+
+/// Custom rules of the default profile and of every profile in use, by profile name.
+pub(crate) fn get_profiles_custom_filters(
+    configuration: &super::Configuration,
+) -> HashMap<String, Vec<String>> {
+    std::iter::once((
+        super::DEFAULT_PROFILE.to_string(),
+        &configuration.custom_filters,
+    ))
+    .chain(
+        configuration
+            .proxy
+            .profiles
+            .used_profiles()
+            .into_iter()
+            .map(|profile| (profile.name.clone(), &profile.custom_filters)),
+    )
+    .map(|(name, custom_filters)| {
+        let filters =
+            ProfileFilters::new(Vec::new(), custom_filters, &configuration.exception_filters);
+        (name, filters.custom)
+    })
+    .collect()
+}
+
+/// Why `rule` can't be saved as an exception rule, `None` when it can. Comments are accepted.
+pub(crate) fn exception_filter_error(rule: &str) -> Option<&'static str> {
+    if rule.starts_with('!') {
+        return None;
+    }
+
+    match adblock::lists::parse_filter(rule, false, adblock::lists::ParseOptions::default()) {
+        Ok(adblock::lists::ParsedFilter::Network(_)) if !rule.starts_with("@@") => {
+            Some("Network exceptions start with @@, this rule would block requests")
+        }
+        Ok(adblock::lists::ParsedFilter::Cosmetic(_)) if !rule.contains("#@") => {
+            Some("Cosmetic exceptions use #@#, this rule would hide elements")
+        }
+        Ok(_) => None,
+        Err(_) => Some("Not a valid filter rule"),
+    }
+}
+
+/// Whether both configurations load the same filter lists in the same profiles, in which case
+/// only the engines of the custom rules need to be rebuilt when going from one to the other.
+/// Bundles may be imported without the configuration changing, loading them is cheap anyway.
+pub(crate) fn same_filter_lists(a: &super::Configuration, b: &super::Configuration) -> bool {
+    if a.filter_bundle.enabled || b.filter_bundle.enabled {
+        return false;
+    }
+
+    let profiles_lists = |configuration: &super::Configuration| {
+        configuration
+            .proxy
+            .profiles
+            .used_profiles()
+            .into_iter()
+            .map(|profile| (profile.name.clone(), profile.filters.clone()))
+            .collect::<Vec<_>>()
+    };
+
+    a.filters == b.filters && profiles_lists(a) == profiles_lists(b)
+}
+
+/// Filters of the profile named `profile_name`, whether it is in use or not. `None` when no
+/// such profile is configured.
+pub(crate) async fn get_profile_filters_content(
+    configuration: &mut super::Configuration,
+    profile_name: &str,
+    http_client: &reqwest::Client,
+) -> Option<ProfileFilters> {
+    if profile_name == super::DEFAULT_PROFILE {
+        return Some(get_filters_content(configuration, http_client).await);
+    }
+
+    let profile = configuration.proxy.profiles.get(profile_name)?.clone();
+    let lists = collect_filters_content(
+        configuration
+            .filters
+            .iter_mut()
+            .filter(|filter| profile.filters.contains(&filter.file_name)),
+        http_client,
+    )
+    .await;
+
+    Some(ProfileFilters::new(
+        lists,
+        &profile.custom_filters,
+        &configuration.exception_filters,
+    ))
+}
+
+async fn collect_filters_content(
+    enabled_filters: impl Iterator<Item = &mut Filter>,
+    http_client: &reqwest::Client,
+) -> Vec<(BlockSource, String)> {
     let mut filters = Vec::new();
     let mut futures = vec![];
 
-    for filter in configuration.get_enabled_filters() {
+    for filter in enabled_filters {
+        let source = BlockSource {
+            list: filter.title.clone(),
+            category: filter.group.block_category(),
+        };
         let future = filter.get_contents(http_client);
-        futures.push(future);
+        futures.push(async move { (source, future.await) });
     }
 
     let results = futures::future::join_all(futures).await;
-    for result in results {
+    for (source, result) in results {
         match result {
-            Ok(filter_content) => filters.push(filter_content),
+            Ok(filter_content) => filters.push((source, filter_content)),
             Err(err) => {
                 log::error!("Unable to retrieve filter: {:?}, skipping.", err)
             }
         }
     }
 
-    filters.append(&mut configuration.custom_filters);
-    filters.sort_unstable();
+    filters.sort_unstable_by(|(_, a), (_, b)| a.cmp(b));
     // Filter out duplicate lines, if present
-    filters.dedup();
+    filters.dedup_by(|(_, a), (_, b)| a == b);
     filters
 }
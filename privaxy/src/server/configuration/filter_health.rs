@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Periodic checks of the filter lists in use, reported on the filters page and at `/readyz`
+pub struct FilterHealthConfig {
+    pub enabled: bool,
+    /// Minutes between two checks of every list.
+    pub interval_minutes: u64,
+    /// Seconds a list has to be downloaded in before the check counts as failed.
+    pub timeout_seconds: u64,
+    /// Consecutive failed downloads, such as 404 responses or timeouts, after which a list is
+    /// reported as unreachable.
+    pub failure_threshold: u32,
+    /// Size of a list, in kilobytes, above which it is reported as too large. `0` disables the
+    /// budget.
+    pub max_size_kb: u64,
+}
+
+impl Default for FilterHealthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_minutes: 60 * 6,
+            timeout_seconds: 30,
+            failure_threshold: 3,
+            max_size_kb: 20 * 1024,
+        }
+    }
+}
+
+impl FilterHealthConfig {
+    pub(crate) fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_minutes.max(1) * 60)
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_seconds.max(1))
+    }
+
+    pub(crate) fn max_size_bytes(&self) -> Option<u64> {
+        (self.max_size_kb > 0).then(|| self.max_size_kb * 1024)
+    }
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Country and ASN lookups of upstream servers, from MaxMind DB files such as GeoLite2
+/// or IP2Location LITE MMDB
+pub struct GeoIpConfig {
+    /// Country database, `GeoLite2-Country.mmdb` for instance.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country_database_path: Option<PathBuf>,
+    /// ASN database, `GeoLite2-ASN.mmdb` for instance.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn_database_path: Option<PathBuf>,
+    /// ISO 3166-1 country codes of servers which are blocked.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub blocked_countries: Vec<String>,
+    /// When not empty, servers located in other countries are blocked. Servers whose country
+    /// is unknown are allowed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_countries: Vec<String>,
+    /// Locate servers of proxied requests for statistics, events and access logs too, rather
+    /// than only to apply country rules.
+    pub statistics: bool,
+}
+
+impl Default for GeoIpConfig {
+    fn default() -> Self {
+        Self {
+            country_database_path: None,
+            asn_database_path: None,
+            blocked_countries: Vec::new(),
+            allowed_countries: Vec::new(),
+            statistics: true,
+        }
+    }
+}
+
+impl GeoIpConfig {
+    pub(crate) fn has_country_rules(&self) -> bool {
+        !self.blocked_countries.is_empty() || !self.allowed_countries.is_empty()
+    }
+
+    pub(crate) fn is_country_blocked(&self, country: &str) -> bool {
+        let is_listed =
+            |countries: &[String]| countries.iter().any(|c| c.eq_ignore_ascii_case(country));
+
+        is_listed(&self.blocked_countries)
+            || (!self.allowed_countries.is_empty() && !is_listed(&self.allowed_countries))
+    }
+}
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, SocketAddr};
+
+const DEFAULT_PORT: u16 = 8300;
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Admin operations served over gRPC, when Privaxy is built with the `grpc` feature
+pub struct GrpcConfig {
+    pub enabled: bool,
+    pub address: SocketAddr,
+    /// Bearer token clients must send in the `authorization` metadata. May be a `file://` or
+    /// `env://` reference. Unset lets every client in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: SocketAddr::from((Ipv4Addr::LOCALHOST, DEFAULT_PORT)),
+            token: None,
+        }
+    }
+}
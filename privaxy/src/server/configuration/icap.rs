@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use std::time::Duration;
+use url::Url;
+use wildmatch::WildMatch;
+
+const DEFAULT_MAX_SIZE_KB: u64 = 10 * 1024;
+const DEFAULT_TIMEOUT_SECONDS: u64 = 10;
+
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// External content scanning over ICAP (RFC 3507), with ClamAV through c-icap or a DLP appliance
+/// for instance
+pub struct IcapConfig {
+    /// REQMOD service requests are sent to before being forwarded, such as
+    /// `icap://127.0.0.1:1344/reqmod`. Requests aren't scanned when not set.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reqmod_url: Option<Url>,
+    /// RESPMOD service responses are sent to before being forwarded, such as
+    /// `icap://127.0.0.1:1344/respmod`. Responses aren't scanned when not set.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub respmod_url: Option<Url>,
+    /// Content types whose bodies are scanned, wildcards are allowed, e.g. `application/*`.
+    /// Every content type is scanned when empty.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mime_types: Vec<String>,
+    /// Largest body scanned, in kilobytes. Bodies whose size isn't known beforehand are read
+    /// up to it. Larger bodies are handled as scans which failed, according to `fail_open`.
+    pub max_size_kb: u64,
+    /// Forward requests and responses when the scanner can't be reached, or their body is too
+    /// large to be scanned, instead of failing them.
+    pub fail_open: bool,
+    /// Seconds a scan may take. `0` disables the timeout.
+    pub timeout_seconds: u64,
+}
+
+impl Default for IcapConfig {
+    fn default() -> Self {
+        Self {
+            reqmod_url: None,
+            respmod_url: None,
+            mime_types: Vec::new(),
+            max_size_kb: DEFAULT_MAX_SIZE_KB,
+            fail_open: true,
+            timeout_seconds: DEFAULT_TIMEOUT_SECONDS,
+        }
+    }
+}
+
+impl IcapConfig {
+    /// Whether a body of `content_type` is scanned.
+    pub(crate) fn scans(&self, content_type: Option<&str>) -> bool {
+        if self.mime_types.is_empty() {
+            return true;
+        }
+
+        let mime_type = content_type
+            .and_then(|content_type| content_type.split(';').next())
+            .map(|mime_type| mime_type.trim().to_lowercase())
+            .unwrap_or_default();
+
+        self.mime_types
+            .iter()
+            .any(|pattern| WildMatch::new(&pattern.to_lowercase()).matches(&mime_type))
+    }
+
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        (self.timeout_seconds > 0).then(|| Duration::from_secs(self.timeout_seconds))
+    }
+}
@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// hyper refuses smaller read buffers.
+const MIN_HEADER_KB: usize = 8;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Limits of the connections a server accepts
+pub struct ListenerLimits {
+    /// Connections open at the same time, further ones are closed right away. `0` disables
+    /// the limit.
+    pub max_connections: usize,
+    /// Connections a single client address may open per minute, further ones are closed
+    /// right away. `0` disables the limit.
+    pub max_connections_per_minute_per_ip: u32,
+    /// Size of the headers of requests, in kilobytes. Larger requests are rejected.
+    pub max_header_kb: usize,
+}
+
+/// Limits of the proxy server, those of the web server are tighter.
+impl Default for ListenerLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: 4096,
+            max_connections_per_minute_per_ip: 1200,
+            max_header_kb: 64,
+        }
+    }
+}
+
+impl ListenerLimits {
+    pub(crate) fn max_header_bytes(&self) -> usize {
+        self.max_header_kb.max(MIN_HEADER_KB) * 1024
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Protections against clients exhausting the resources of Privaxy, such as slowloris attacks
+pub struct LimitsConfig {
+    pub proxy: ListenerLimits,
+    pub web: ListenerLimits,
+    /// Seconds clients of the web server have to perform the TLS handshake and to send the
+    /// headers of a request. The proxy server uses `proxy.timeouts` instead. `0` disables
+    /// the timeout.
+    pub web_header_read_seconds: u64,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            proxy: ListenerLimits::default(),
+            web: ListenerLimits {
+                max_connections: 256,
+                max_connections_per_minute_per_ip: 600,
+                max_header_kb: 16,
+            },
+            web_header_read_seconds: 10,
+        }
+    }
+}
+
+impl LimitsConfig {
+    pub(crate) fn web_header_read_timeout(&self) -> Option<Duration> {
+        (self.web_header_read_seconds > 0)
+            .then(|| Duration::from_secs(self.web_header_read_seconds))
+    }
+}
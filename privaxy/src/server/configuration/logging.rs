@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_LOG_FILE_PATH: &str = "/var/log/privaxy/privaxy.log";
+const DEFAULT_MAX_SIZE_MB: u64 = 10;
+const DEFAULT_MAX_FILES: usize = 5;
+
+fn default_file_path() -> String {
+    DEFAULT_LOG_FILE_PATH.to_string()
+}
+
+fn default_max_size_mb() -> u64 {
+    DEFAULT_MAX_SIZE_MB
+}
+
+fn default_max_files() -> usize {
+    DEFAULT_MAX_FILES
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// Where logs are written to
+pub enum LogOutput {
+    #[default]
+    Stderr,
+    /// A file, rotated once it grows too large.
+    File,
+    /// The local syslog socket, which journald also listens on.
+    Syslog,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+/// Logging configuration for Privaxy
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub output: LogOutput,
+    /// Path of the log file, when logging to a file. The directory of the default one is
+    /// created by the systemd unit, other ones must be writable by the user Privaxy runs as.
+    #[serde(default = "default_file_path")]
+    pub file_path: String,
+    /// Size in megabytes after which the log file is rotated, 0 disables rotation.
+    #[serde(default = "default_max_size_mb")]
+    pub max_size_mb: u64,
+    /// Number of rotated log files to keep.
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            output: LogOutput::default(),
+            file_path: default_file_path(),
+            max_size_mb: DEFAULT_MAX_SIZE_MB,
+            max_files: DEFAULT_MAX_FILES,
+        }
+    }
+}
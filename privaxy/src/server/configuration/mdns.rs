@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_NAME: &str = "privaxy";
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Web interface and proxy advertised over mDNS, so that devices on the LAN find Privaxy at
+/// `<name>.local`
+pub struct MdnsConfig {
+    pub enabled: bool,
+    /// Host name, without `.local`, and name of the advertised services.
+    pub name: String,
+}
+
+impl Default for MdnsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            name: DEFAULT_NAME.to_string(),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use std::collections::BTreeMap;
+use url::Url;
+
+const DEFAULT_INTERVAL_SECONDS: u64 = 10;
+const DEFAULT_PREFIX: &str = "privaxy";
+
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "format", rename_all = "lowercase")]
+/// Time series database metrics are written to
+pub enum MetricsPushTarget {
+    /// InfluxDB write endpoint, such as
+    /// `http://localhost:8086/api/v2/write?org=home&bucket=privaxy&precision=s`.
+    /// Timestamps are sent in seconds, the endpoint must expect them so.
+    InfluxDb {
+        #[serde_as(as = "DisplayFromStr")]
+        url: Url,
+        /// Sent as `Authorization: Token <token>`. May be a `file://` or `env://` reference.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+    },
+    /// Carbon plaintext receiver, `localhost:2003` for instance.
+    Graphite { address: String },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Periodic export of statistics to InfluxDB or Graphite
+pub struct MetricsPushConfig {
+    pub targets: Vec<MetricsPushTarget>,
+    pub interval_seconds: u64,
+    /// Measurement name for InfluxDB, first path component for Graphite.
+    pub prefix: String,
+    /// Added to every InfluxDB point, such as `host = "router"`.
+    pub tags: BTreeMap<String, String>,
+}
+
+impl Default for MetricsPushConfig {
+    fn default() -> Self {
+        Self {
+            targets: Vec::new(),
+            interval_seconds: DEFAULT_INTERVAL_SECONDS,
+            prefix: DEFAULT_PREFIX.to_string(),
+            tags: BTreeMap::new(),
+        }
+    }
+}
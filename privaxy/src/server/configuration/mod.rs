@@ -1,23 +1,91 @@
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeSet, time::Duration};
+use std::{
+    collections::{BTreeSet, HashSet},
+    time::Duration,
+};
 use thiserror::Error;
 use tokio::fs;
+mod alerts;
+mod bundle;
 mod ca;
+mod category;
+mod dns;
+mod egress;
+mod event_log;
 mod filter;
+mod filter_health;
+mod geoip;
+mod grpc;
+mod icap;
+mod limits;
+mod logging;
+mod mdns;
+mod metrics_push;
+mod mqtt;
 mod network;
+mod pool;
+mod privacy;
+mod profile;
+mod proxy;
+mod reports;
+mod resources;
+mod retries;
+mod scripting;
+mod secret;
+mod store;
+mod system_proxy;
+mod threat;
+mod timeouts;
+mod tls;
 mod updater;
+mod validation;
+mod webrtc;
+mod wpad;
+pub use alerts::*;
+pub use bundle::*;
 pub use ca::*;
+pub use category::*;
+pub use dns::*;
+pub use egress::*;
+pub use event_log::*;
 pub use filter::*;
+pub use filter_health::*;
 use futures::future::try_join_all;
+pub use geoip::*;
+pub use grpc::*;
+pub use icap::*;
+pub use limits::*;
+pub use logging::*;
+pub use mdns::*;
+pub use metrics_push::*;
+pub use mqtt::*;
 pub use network::*;
+pub use pool::*;
+pub use privacy::*;
+pub use profile::*;
+pub use proxy::*;
+pub use reports::*;
+pub use resources::*;
+pub use retries::*;
+pub use scripting::*;
+pub use secret::*;
 use std::env;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+pub use store::*;
+pub use system_proxy::*;
+pub use threat::*;
+pub use timeouts::*;
+pub use tls::*;
 pub use updater::*;
+pub use validation::*;
+pub use webrtc::*;
+pub use wpad::*;
 pub(crate) type ConfigurationResult<T> = Result<T, ConfigurationError>;
 pub(crate) const FILTERS_UPDATE_AFTER: Duration = Duration::from_secs(60 * 10);
 
 /// Filename of the configuration file.
-pub(crate) const CONFIGURATION_FILE_NAME: &str = "config";
+pub const CONFIGURATION_FILE_NAME: &str = "config";
 
 /// Default configuration directory name.
 const CONFIGURATION_DIRECTORY_NAME: &str = "/etc/privaxy";
@@ -42,15 +110,64 @@ pub enum ConfigurationError {
     UnableToDecodePem(#[from] openssl::error::ErrorStack),
     #[error("filter error: {0}")]
     FilterError(String),
+    #[error("configuration store error: {0}")]
+    StoreError(String),
+    #[error("secret error: {0}")]
+    SecretError(#[from] SecretError),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct Configuration {
+    /// Incremented on every save, editors send back the revision they started from so that
+    /// concurrent changes are not silently overwritten.
+    #[serde(default)]
+    pub revision: u64,
     pub exclusions: BTreeSet<String>,
     pub custom_filters: Vec<String>,
+    /// `@@` and `#@#` rules unblocking what the filter lists block by mistake, in every profile.
+    #[serde(default)]
+    pub exception_filters: Vec<String>,
+    /// Scriptlets `+js()` rules may inject besides the bundled ones, in the format of uBlock
+    /// Origin's `scriptlets.js`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub custom_scriptlets: String,
     pub ca: Ca,
     pub network: NetworkConfig,
     pub filters: Vec<Filter>,
+    #[serde(default)]
+    pub filter_bundle: FilterBundleConfig,
+    #[serde(default)]
+    pub filter_health: FilterHealthConfig,
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    #[serde(default)]
+    pub mqtt: MqttConfig,
+    #[serde(default)]
+    pub mdns: MdnsConfig,
+    #[serde(default)]
+    pub wpad: WpadConfig,
+    #[serde(default)]
+    pub metrics_push: MetricsPushConfig,
+    #[serde(default)]
+    pub event_log: EventLogConfig,
+    #[serde(default)]
+    pub grpc: GrpcConfig,
+    #[serde(default)]
+    pub reports: ReportsConfig,
+    #[serde(default)]
+    pub privacy: PrivacyConfig,
+    #[serde(default)]
+    pub system_proxy: SystemProxyConfig,
+    #[serde(default)]
+    pub dns: DnsConfig,
+    #[serde(default)]
+    pub egress: EgressConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
 }
 
 #[derive(Error, Debug)]
@@ -62,7 +179,6 @@ pub enum PrivaxyError {
 impl Configuration {
     pub async fn read_from_home() -> ConfigurationResult<Self> {
         let configuration_directory = get_base_directory().unwrap();
-        let configuration_file_path = get_config_file();
 
         if let Err(err) = fs::metadata(&configuration_directory).await {
             if err.kind() == std::io::ErrorKind::NotFound {
@@ -70,7 +186,7 @@ impl Configuration {
 
                 fs::create_dir(&configuration_directory).await?;
 
-                let configuration = Self::new_default().await?;
+                let mut configuration = Self::new_default().await?;
                 configuration.save().await?;
 
                 return Ok(configuration);
@@ -79,31 +195,25 @@ impl Configuration {
             }
         };
 
-        match fs::read(&configuration_file_path).await {
-            Ok(bytes) => Ok(toml::from_str(std::str::from_utf8(&bytes)?)?),
-            Err(err) => {
-                log::debug!("Configuration file not found, creating one");
+        match store().load().await? {
+            Some(contents) => Ok(toml::from_str(&contents)?),
+            None => {
+                log::debug!("Configuration not found in {}, creating one", store());
 
-                if err.kind() == std::io::ErrorKind::NotFound {
-                    let configuration = Self::new_default().await?;
-                    configuration.save().await?;
+                let mut configuration = Self::new_default().await?;
+                configuration.save().await?;
 
-                    Ok(configuration)
-                } else {
-                    Err(ConfigurationError::FileSystemError(err))
-                }
+                Ok(configuration)
             }
         }
     }
 
-    pub async fn save(&self) -> ConfigurationResult<()> {
-        let configuration_file_path = get_config_file();
+    pub async fn save(&mut self) -> ConfigurationResult<()> {
+        self.revision += 1;
 
         let configuration_serialized = toml::to_string_pretty(&self).unwrap();
 
-        fs::write(configuration_file_path, configuration_serialized).await?;
-
-        Ok(())
+        store().save(&configuration_serialized).await
     }
 
     pub async fn set_custom_filters(&mut self, custom_filters: &str) -> ConfigurationResult<()> {
@@ -114,6 +224,28 @@ impl Configuration {
         Ok(())
     }
 
+    pub async fn set_custom_scriptlets(
+        &mut self,
+        custom_scriptlets: &str,
+    ) -> ConfigurationResult<()> {
+        self.custom_scriptlets = custom_scriptlets.trim_end().to_string();
+
+        self.save().await?;
+
+        Ok(())
+    }
+
+    pub async fn set_exception_filters(
+        &mut self,
+        exception_filters: Vec<String>,
+    ) -> ConfigurationResult<()> {
+        self.exception_filters = exception_filters;
+
+        self.save().await?;
+
+        Ok(())
+    }
+
     fn deserialize_lines<T>(lines: &str) -> T
     where
         T: FromIterator<String>,
@@ -142,12 +274,45 @@ impl Configuration {
 
         self.save().await?;
 
-        local_exclusion_store
-            .replace_exclusions(Vec::from_iter(self.exclusions.clone().into_iter()));
+        local_exclusion_store.replace_exclusions(Vec::from_iter(self.exclusions.clone()));
 
         Ok(())
     }
 
+    /// Adds `host` to the exclusions, unless it is already excluded.
+    pub async fn add_exclusion(
+        &mut self,
+        host: &str,
+        mut local_exclusion_store: crate::exclusions::LocalExclusionStore,
+    ) -> ConfigurationResult<()> {
+        if !self.exclusions.insert(host.to_lowercase()) {
+            return Ok(());
+        }
+
+        self.save().await?;
+
+        local_exclusion_store.replace_exclusions(Vec::from_iter(self.exclusions.clone()));
+
+        Ok(())
+    }
+
+    /// Removes `host` from the exclusions. Returns whether it was excluded.
+    pub async fn remove_exclusion(
+        &mut self,
+        host: &str,
+        mut local_exclusion_store: crate::exclusions::LocalExclusionStore,
+    ) -> ConfigurationResult<bool> {
+        if !self.exclusions.remove(&host.to_lowercase()) {
+            return Ok(false);
+        }
+
+        self.save().await?;
+
+        local_exclusion_store.replace_exclusions(Vec::from_iter(self.exclusions.clone()));
+
+        Ok(true)
+    }
+
     pub async fn set_filter_enabled_status(
         &mut self,
         filter_file_name: &str,
@@ -170,21 +335,61 @@ impl Configuration {
         self.filters.iter_mut().filter(|f| f.enabled)
     }
 
+    /// File names of the filter lists of the profiles in use, which are needed even when
+    /// disabled.
+    fn used_profiles_filters(&self) -> HashSet<String> {
+        self.proxy
+            .profiles
+            .used_profiles()
+            .into_iter()
+            .flat_map(|profile| profile.filters.iter().cloned())
+            .collect()
+    }
+
+    /// Whether `filter` is loaded by the default profile, unless it loads a bundle, or by a
+    /// profile in use.
+    fn is_filter_in_use(&self, filter: &Filter, profile_filters: &HashSet<String>) -> bool {
+        (filter.enabled && !self.filter_bundle.enabled)
+            || profile_filters.contains(&filter.file_name)
+    }
+
+    /// Filter lists loaded by the default profile or by a profile in use.
+    pub(crate) fn filters_in_use(&self) -> Vec<&Filter> {
+        let profile_filters = self.used_profiles_filters();
+
+        self.filters
+            .iter()
+            .filter(|filter| self.is_filter_in_use(filter, &profile_filters))
+            .collect()
+    }
+
     pub async fn update_filters(
         &mut self,
         http_client: reqwest::Client,
     ) -> ConfigurationResult<()> {
         log::debug!("Updating filters");
 
-        let futures = self.filters.iter_mut().filter_map(|filter| {
-            if filter.enabled {
-                Some(filter.update(&http_client))
-            } else {
-                None
-            }
-        });
+        let profile_filters = self.used_profiles_filters();
+        let in_use = self
+            .filters
+            .iter()
+            .map(|filter| self.is_filter_in_use(filter, &profile_filters))
+            .collect::<Vec<_>>();
+
+        let futures = self
+            .filters
+            .iter_mut()
+            .zip(in_use)
+            .filter_map(|(filter, in_use)| {
+                if in_use {
+                    Some(filter.update(&http_client))
+                } else {
+                    None
+                }
+            });
 
         try_join_all(futures).await?;
+        self.filter_bundle.update(&http_client).await?;
 
         Ok(())
     }
@@ -243,6 +448,7 @@ impl Configuration {
 
         let default_filters = DefaultFilters::new();
         Ok(Configuration {
+            revision: 0,
             filters: default_filters
                 .list()
                 .into_iter()
@@ -262,21 +468,82 @@ impl Configuration {
                 tls_cert_path: None,
                 tls_key_path: None,
                 listen_url: None,
+                proxy_protocol: false,
+                proxy_protocol_trusted_networks: Vec::new(),
+                secure_proxy_port: None,
+                proxy_listeners: Vec::new(),
+                web_listeners: Vec::new(),
+                web_allowed_networks: Vec::new(),
+                web_host_names: Vec::new(),
             },
             exclusions: BTreeSet::new(),
             custom_filters: Vec::new(),
+            exception_filters: Vec::new(),
+            custom_scriptlets: String::new(),
+            proxy: ProxyConfig::default(),
+            logging: LoggingConfig::default(),
+            alerts: AlertsConfig::default(),
+            mqtt: MqttConfig::default(),
+            mdns: MdnsConfig::default(),
+            wpad: WpadConfig::default(),
+            metrics_push: MetricsPushConfig::default(),
+            event_log: EventLogConfig::default(),
+            grpc: GrpcConfig::default(),
+            reports: ReportsConfig::default(),
+            filter_bundle: FilterBundleConfig::default(),
+            filter_health: FilterHealthConfig::default(),
+            privacy: PrivacyConfig::default(),
+            system_proxy: SystemProxyConfig::default(),
+            dns: DnsConfig::default(),
+            egress: EgressConfig::default(),
+            limits: LimitsConfig::default(),
         })
     }
 }
 
-pub(crate) fn get_config_file() -> PathBuf {
-    get_base_directory()
-        .unwrap()
-        .join(CONFIGURATION_FILE_NAME)
+/// Re-reads the configuration file and applies it: exclusions are replaced in place, filters are
+/// handed over to the configuration updater and the proxy and web servers are restarted so that
+/// listener and CA changes are picked up. Connections in flight are left to complete.
+pub(crate) async fn reload_configuration(
+    configuration_updater_sender: &tokio::sync::mpsc::Sender<Configuration>,
+    configuration_save_lock: &Arc<tokio::sync::Mutex<()>>,
+    local_exclusion_store: &crate::exclusions::LocalExclusionStore,
+    notify_reload: &tokio::sync::Notify,
+) -> ConfigurationResult<()> {
+    let guard = configuration_save_lock.lock().await;
+    let configuration = Configuration::read_from_home().await?;
+    drop(guard);
+
+    // The proxy unwraps these once restarted, make sure they are usable beforehand.
+    configuration.network.validate().await?;
+    configuration.ca.get_ca_certificate().await?;
+    configuration.ca.get_ca_private_key().await?;
+
+    crate::systemd::notify("RELOADING=1");
+
+    crate::logging::configure(&configuration.logging);
+
+    local_exclusion_store
+        .clone()
+        .replace_exclusions(Vec::from_iter(configuration.exclusions.clone()));
+
+    configuration_updater_sender
+        .send(configuration)
+        .await
+        .unwrap();
+
+    notify_reload.notify_waiters();
+
+    log::info!("Reloaded configuration");
+
+    Ok(())
 }
 
+pub(crate) fn get_config_file() -> PathBuf {
+    get_base_directory().unwrap().join(CONFIGURATION_FILE_NAME)
+}
 
-fn get_base_directory() -> ConfigurationResult<PathBuf> {
+pub fn get_base_directory() -> ConfigurationResult<PathBuf> {
     let base_directory: PathBuf = match env::var("PRIVAXY_BASE_PATH") {
         Ok(val) => PathBuf::from(&val),
         // Assume home directory
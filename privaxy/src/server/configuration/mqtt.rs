@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_PORT: u16 = 1883;
+const DEFAULT_CLIENT_ID: &str = "privaxy";
+const DEFAULT_TOPIC_PREFIX: &str = "privaxy";
+const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+const DEFAULT_PUBLISH_INTERVAL_SECONDS: u64 = 30;
+const DEFAULT_PAUSE_MINUTES: u64 = 30;
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Metrics published to an MQTT broker, with Home Assistant discovery
+pub struct MqttConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// May be a `file://` or `env://` reference.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    pub client_id: String,
+    /// Prefix of the topics metrics are published to and commands are received from.
+    pub topic_prefix: String,
+    /// Prefix Home Assistant listens to for discovery messages.
+    pub discovery_prefix: String,
+    pub publish_interval_seconds: u64,
+    /// For how long blocking is paused when the pause command has no duration.
+    pub pause_minutes: u64,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: DEFAULT_PORT,
+            username: None,
+            password: None,
+            client_id: DEFAULT_CLIENT_ID.to_string(),
+            topic_prefix: DEFAULT_TOPIC_PREFIX.to_string(),
+            discovery_prefix: DEFAULT_DISCOVERY_PREFIX.to_string(),
+            publish_interval_seconds: DEFAULT_PUBLISH_INTERVAL_SECONDS,
+            pause_minutes: DEFAULT_PAUSE_MINUTES,
+        }
+    }
+}
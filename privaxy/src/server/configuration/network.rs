@@ -1,12 +1,11 @@
-use regex::Regex;
 use serde::{Deserialize, Serialize};
-use serde_with::serde_as;
+use serde_with::{serde_as, DisplayFromStr};
 use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use tokio::fs;
 
-use super::ConfigurationResult;
+use super::{ConfigurationResult, IpNetwork};
 use openssl::{
     asn1::Asn1Time,
     bn::{BigNum, MsbOption},
@@ -20,10 +19,11 @@ use openssl::{
         X509NameBuilder, X509Ref, X509Req, X509ReqBuilder, X509,
     },
 };
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
 /// Network configuration for Privaxy
 pub struct NetworkConfig {
     /// Bind address for the proxy server.
@@ -47,6 +47,56 @@ pub struct NetworkConfig {
     /// URL to listen on. Only used when TLS is enabled.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub listen_url: Option<String>,
+    /// Expect a PROXY protocol header on connections to the proxy server, as sent by
+    /// load balancers, and use the client address it conveys.
+    #[serde(default)]
+    pub proxy_protocol: bool,
+    /// Addresses and networks of the load balancers allowed to send a PROXY protocol header.
+    /// Connections from other addresses are served with their own address.
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(value_type = Vec<String>)]
+    pub proxy_protocol_trusted_networks: Vec<IpNetwork>,
+    /// Port of the secure proxy, on the bind address, which clients reach over TLS with the
+    /// certificate of the web server. Browsers refer to it as an HTTPS proxy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secure_proxy_port: Option<u16>,
+    /// Additional addresses for the proxy server.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub proxy_listeners: Vec<ListenerConfig>,
+    /// Additional addresses for the web server.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub web_listeners: Vec<ListenerConfig>,
+    /// Addresses and networks allowed to connect to the web server. Connections from other
+    /// addresses are closed before anything is read from them. Empty allows every address.
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(value_type = Vec<String>)]
+    pub web_allowed_networks: Vec<IpNetwork>,
+    /// Host names the web server is reached under, besides its addresses, `localhost` and the
+    /// host of `listen_url`. Requests sent to other names are refused, so that other sites
+    /// can't reach it by having their own name resolve to its address.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub web_host_names: Vec<String>,
+}
+
+fn default_ipv6_only() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+/// An additional address to listen on
+pub struct ListenerConfig {
+    /// Address and port, e.g. `0.0.0.0:8100` or `[::]:8100`.
+    #[schema(value_type = String)]
+    pub address: SocketAddr,
+    /// Only accept IPv6 connections on an IPv6 address, so that the same port can
+    /// also be bound on IPv4.
+    #[serde(default = "default_ipv6_only")]
+    pub ipv6_only: bool,
+    /// Expect a PROXY protocol header on connections. Only supported by the proxy server.
+    #[serde(default)]
+    pub proxy_protocol: bool,
 }
 
 #[derive(Error, Debug)]
@@ -66,6 +116,21 @@ pub enum NetworkConfigError {
 }
 
 impl NetworkConfig {
+    /// Host names the web server is reached under, including the host of `listen_url`.
+    pub(crate) fn host_names(&self) -> Vec<String> {
+        let listen_host = self
+            .listen_url
+            .as_deref()
+            .and_then(|listen_url| url::Url::parse(listen_url).ok())
+            .and_then(|listen_url| listen_url.host_str().map(String::from));
+
+        self.web_host_names
+            .iter()
+            .cloned()
+            .chain(listen_host)
+            .collect()
+    }
+
     pub(crate) async fn validate(&self) -> super::ConfigurationResult<()> {
         if self.proxy_port == 0 {
             return Err(
@@ -89,13 +154,56 @@ impl NetworkConfig {
             )
             .into());
         };
-        let addr_regex = Regex::new(r"^((25[0-5]|(2[0-4]|1\d|[1-9]|)\d)\.?\b){4}$");
-        if !addr_regex.unwrap().is_match(&self.bind_addr) {
+        if IpAddr::from_str(&self.bind_addr).is_err() {
             return Err(NetworkConfigError::BindAddressError(
                 format!("Invalid bind address: {}", self.bind_addr).to_string(),
             )
             .into());
         };
+        if let Some(secure_proxy_port) = self.secure_proxy_port {
+            if secure_proxy_port == 0 {
+                return Err(NetworkConfigError::ProxyPortError(
+                    "Secure proxy port cannot be 0".to_string(),
+                )
+                .into());
+            }
+            if secure_proxy_port == self.proxy_port || secure_proxy_port == self.web_port {
+                return Err(NetworkConfigError::PortCollisionError(
+                    "Secure proxy port cannot be the proxy or web port".to_string(),
+                )
+                .into());
+            }
+        };
+        let listeners = self.proxy_listeners.iter().chain(self.web_listeners.iter());
+        for listener in listeners {
+            if listener.address.port() == 0 {
+                return Err(NetworkConfigError::ProxyPortError(format!(
+                    "Listener port cannot be 0: {}",
+                    listener.address
+                ))
+                .into());
+            }
+        }
+        let mut proxy_addresses = self
+            .proxy_listeners
+            .iter()
+            .map(|listener| listener.address)
+            .chain(std::iter::once(SocketAddr::new(
+                self.parsed_ip_address(),
+                self.proxy_port,
+            )));
+        if let Some(address) = proxy_addresses.find(|address| {
+            self.web_listeners
+                .iter()
+                .any(|listener| listener.address == *address)
+                || *address == SocketAddr::new(self.parsed_ip_address(), self.web_port)
+        }) {
+            return Err(NetworkConfigError::PortCollisionError(format!(
+                "Proxy and web servers cannot both listen on {}",
+                address
+            ))
+            .into());
+        };
         Ok(())
     }
 
@@ -188,7 +296,7 @@ impl NetworkConfig {
             Ok(cert) => Ok(cert),
             Err(err) => {
                 log::error!("Failed to read TLS certificate: {err}");
-                return Err(err);
+                Err(err)
             }
         }
     }
@@ -323,7 +431,7 @@ fn build_ca_signed_cert(
         )
         .unwrap();
     let subject_alternative_name = SubjectAlternativeName::new()
-        .ip(bind_addr.as_str().into())
+        .ip(bind_addr.as_str())
         .build(&cert_builder.x509v3_context(Some(ca_cert), None))
         .unwrap();
 
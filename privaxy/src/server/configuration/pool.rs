@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Connections to upstream servers kept open for reuse. How long unused connections are kept
+/// is set by `timeouts.idle_seconds`.
+pub struct PoolConfig {
+    /// Unused connections kept open per host. `0` doesn't limit them.
+    pub max_idle_per_host: usize,
+    /// Interval of TCP keepalive probes on connections to servers, which keep connections
+    /// through NAT gateways and detect dead peers. `0` disables keepalives.
+    pub tcp_keepalive_seconds: u64,
+    /// Send small writes right away rather than coalescing them, lowering latency at the
+    /// cost of more packets.
+    pub tcp_nodelay: bool,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: 0,
+            tcp_keepalive_seconds: 60,
+            tcp_nodelay: true,
+        }
+    }
+}
+
+impl PoolConfig {
+    pub(crate) fn max_idle_per_host(&self) -> usize {
+        match self.max_idle_per_host {
+            0 => usize::MAX,
+            max_idle_per_host => max_idle_per_host,
+        }
+    }
+
+    pub(crate) fn tcp_keepalive(&self) -> Option<Duration> {
+        (self.tcp_keepalive_seconds > 0).then(|| Duration::from_secs(self.tcp_keepalive_seconds))
+    }
+}
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+const SECONDS_PER_DAY: u64 = 60 * 60 * 24;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// What is kept about clients and for how long
+pub struct PrivacyConfig {
+    /// Clients are recorded by network rather than by address: the last octet of IPv4
+    /// addresses and all but the first 48 bits of IPv6 addresses are zeroed, in statistics
+    /// and in the access log.
+    pub anonymize_client_ips: bool,
+    /// Count requests per client and per user. When disabled, client tables stay empty and
+    /// spikes are only detected across all clients.
+    pub track_clients: bool,
+    /// Rotated log files are removed once their last line is older than this many days, and
+    /// events of the event log once they are. `0` keeps them until `logging.max_files` or
+    /// `event_log.max_events` is reached.
+    pub log_retention_days: u64,
+    /// History and tables of the statistics are pruned once older than this many days.
+    /// `0` keeps them until statistics are reset.
+    pub statistics_retention_days: u64,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            anonymize_client_ips: false,
+            track_clients: true,
+            log_retention_days: 0,
+            statistics_retention_days: 0,
+        }
+    }
+}
+
+fn days(days: u64) -> Option<Duration> {
+    (days > 0).then(|| Duration::from_secs(days * SECONDS_PER_DAY))
+}
+
+impl PrivacyConfig {
+    pub(crate) fn log_retention(&self) -> Option<Duration> {
+        days(self.log_retention_days)
+    }
+
+    pub(crate) fn statistics_retention(&self) -> Option<Duration> {
+        days(self.statistics_retention_days)
+    }
+
+    /// Address of a client as it may be recorded.
+    pub(crate) fn client_address(&self, client_ip_address: IpAddr) -> IpAddr {
+        if !self.anonymize_client_ips {
+            return client_ip_address;
+        }
+
+        match client_ip_address {
+            IpAddr::V4(address) => {
+                let [a, b, c, _d] = address.octets();
+                IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+            }
+            IpAddr::V6(address) => {
+                let segments = address.segments();
+                IpAddr::V6(Ipv6Addr::new(
+                    segments[0],
+                    segments[1],
+                    segments[2],
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                ))
+            }
+        }
+    }
+
+    /// Key requests of a client are counted under in statistics, `None` when clients aren't
+    /// tracked.
+    pub(crate) fn client_key(
+        &self,
+        client_ip_address: IpAddr,
+        client_username: Option<&str>,
+    ) -> Option<String> {
+        if !self.track_clients {
+            return None;
+        }
+
+        Some(match client_username {
+            Some(client_username) => client_username.to_string(),
+            None => self.client_address(client_ip_address).to_string(),
+        })
+    }
+}
@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use utoipa::ToSchema;
+use wildmatch::WildMatch;
+
+/// Name of the profile made of the top-level filters, custom filters and exclusions.
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+/// Filter lists, custom filters and exclusions applied together, such as "strict" or "kids"
+pub struct Profile {
+    pub name: String,
+    /// File names of the filter lists used, among the configured ones. They are used by the
+    /// profile whether they are enabled or not.
+    #[serde(default)]
+    pub filters: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_filters: Vec<String>,
+    /// Hosts tunneled without interception, instead of the top-level exclusions.
+    /// Wildcards are allowed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclusions: Vec<String>,
+}
+
+impl Profile {
+    pub(crate) fn is_excluded(&self, host: &str) -> bool {
+        let host = host.to_lowercase();
+
+        self.exclusions
+            .iter()
+            .any(|pattern| WildMatch::new(&pattern.to_lowercase()).matches(&host))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+/// Profile of a single client
+pub struct ClientProfile {
+    /// IP address or username of the client.
+    pub client: String,
+    pub profile: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(default)]
+/// Named blocking profiles, each with an engine of its own
+pub struct ProfilesConfig {
+    pub profiles: Vec<Profile>,
+    /// Profile of clients without their own entry in `clients`.
+    pub active: String,
+    pub clients: Vec<ClientProfile>,
+}
+
+impl Default for ProfilesConfig {
+    fn default() -> Self {
+        Self {
+            profiles: Vec::new(),
+            active: DEFAULT_PROFILE.to_string(),
+            clients: Vec::new(),
+        }
+    }
+}
+
+impl ProfilesConfig {
+    pub(crate) fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Name of the profile of a client, unknown profiles fall back on the default one.
+    pub(crate) fn profile_name_for(
+        &self,
+        client_ip_address: IpAddr,
+        client_username: Option<&str>,
+    ) -> &str {
+        let by_username = client_username
+            .and_then(|username| self.clients.iter().find(|client| client.client == username));
+        let by_ip_address = || {
+            self.clients
+                .iter()
+                .find(|client| client.client.parse::<IpAddr>().ok() == Some(client_ip_address))
+        };
+
+        let name = match by_username.or_else(by_ip_address) {
+            Some(client) => &client.profile,
+            None => &self.active,
+        };
+
+        match self.get(name) {
+            Some(profile) => &profile.name,
+            None => DEFAULT_PROFILE,
+        }
+    }
+
+    /// `None` for the default profile.
+    pub(crate) fn profile_for(
+        &self,
+        client_ip_address: IpAddr,
+        client_username: Option<&str>,
+    ) -> Option<&Profile> {
+        self.get(self.profile_name_for(client_ip_address, client_username))
+    }
+
+    /// Profiles in use by at least one client, other ones don't need an engine.
+    pub(super) fn used_profiles(&self) -> Vec<&Profile> {
+        let mut names = self
+            .clients
+            .iter()
+            .map(|client| client.profile.as_str())
+            .chain(std::iter::once(self.active.as_str()))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+
+        names
+            .into_iter()
+            .filter_map(|name| self.get(name))
+            .collect()
+    }
+}
@@ -0,0 +1,339 @@
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use wildmatch::WildMatch;
+
+const DEFAULT_TEMPORARY_ALLOW_MINUTES: u64 = 15;
+const DEFAULT_MAX_REWRITE_SIZE_KB: u64 = 8 * 1024;
+const DEFAULT_MAX_REWRITE_SECONDS: u64 = 30;
+const DEFAULT_CACHE_MAX_SIZE_MB: u64 = 256;
+const DEFAULT_CACHE_MAX_OBJECT_SIZE_KB: u64 = 8 * 1024;
+
+fn default_temporary_allow_minutes() -> u64 {
+    DEFAULT_TEMPORARY_ALLOW_MINUTES
+}
+
+fn default_blocked_frame_allow_link() -> bool {
+    true
+}
+
+fn default_max_rewrite_size_kb() -> u64 {
+    DEFAULT_MAX_REWRITE_SIZE_KB
+}
+
+fn default_max_rewrite_seconds() -> u64 {
+    DEFAULT_MAX_REWRITE_SECONDS
+}
+
+fn default_cache_max_size_mb() -> u64 {
+    DEFAULT_CACHE_MAX_SIZE_MB
+}
+
+fn default_cache_max_object_size_kb() -> u64 {
+    DEFAULT_CACHE_MAX_OBJECT_SIZE_KB
+}
+
+fn default_coalesce_requests() -> bool {
+    true
+}
+
+fn default_upstream_encodings() -> Vec<ContentEncoding> {
+    vec![
+        ContentEncoding::Gzip,
+        ContentEncoding::Deflate,
+        ContentEncoding::Brotli,
+        ContentEncoding::Zstd,
+    ]
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// Compression of response bodies which Privaxy decodes to rewrite documents
+pub enum ContentEncoding {
+    Gzip,
+    Deflate,
+    #[serde(rename = "br")]
+    Brotli,
+    Zstd,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+/// In memory cache of upstream responses, shared by all clients
+pub struct CacheConfig {
+    /// Store cacheable responses and serve them to subsequent requests.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Maximum size of the cache, in megabytes.
+    #[serde(default = "default_cache_max_size_mb")]
+    pub max_size_mb: u64,
+    /// Responses larger than this, in kilobytes, are not stored.
+    #[serde(default = "default_cache_max_object_size_kb")]
+    pub max_object_size_kb: u64,
+    /// Concurrent requests for the same resource wait for a single upstream fetch and are
+    /// served its response from the cache, should it be stored.
+    #[serde(default = "default_coalesce_requests")]
+    pub coalesce_requests: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_size_mb: DEFAULT_CACHE_MAX_SIZE_MB,
+            max_object_size_kb: DEFAULT_CACHE_MAX_OBJECT_SIZE_KB,
+            coalesce_requests: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+/// Credentials and bandwidth of a proxy user. Filters and exclusions are those of the profile
+/// assigned to the username in `profiles.clients`.
+pub struct ProxyUser {
+    pub username: String,
+    /// Plain text password, or its SHA-256 hex digest prefixed with `sha256:`. May be read from a
+    /// `file://` or `env://` reference.
+    pub password: String,
+    /// Download bandwidth, in kilobytes per second, shared by all the connections of the user.
+    /// `0` means unlimited.
+    #[serde(default)]
+    pub bandwidth_limit_kbps: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+/// Basic authentication of proxy clients
+pub struct ProxyAuthenticationConfig {
+    /// Reject clients that don't send valid credentials with `407 Proxy Authentication Required`.
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<ProxyUser>,
+}
+
+impl ProxyAuthenticationConfig {
+    pub(crate) fn user(&self, username: &str) -> Option<&ProxyUser> {
+        self.users.iter().find(|user| user.username == username)
+    }
+}
+
+/// IP address or CIDR network, such as `203.0.113.7` or `198.51.100.0/24`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork(IpNet);
+
+impl IpNetwork {
+    pub(crate) fn contains(&self, ip_address: &IpAddr) -> bool {
+        self.0.contains(ip_address)
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = ipnet::AddrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<IpAddr>() {
+            Ok(ip_address) => Ok(Self(IpNet::from(ip_address))),
+            Err(_) => s.parse::<IpNet>().map(|network| Self(network.trunc())),
+        }
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.prefix_len() == self.0.max_prefix_len() {
+            write!(f, "{}", self.0.addr())
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+/// Proxy behavior configuration for Privaxy
+pub struct ProxyConfig {
+    /// Retry plain HTTP `GET` and `HEAD` requests over HTTPS first. Hosts for which the
+    /// upgrade fails are remembered and served over HTTP afterwards.
+    #[serde(default)]
+    pub https_upgrade: bool,
+    /// Answer every blocked request with an empty `403` response instead of the block page.
+    #[serde(default)]
+    pub silent_blocking: bool,
+    /// Hosts for which blocked requests are answered silently, regardless of `silent_blocking`.
+    /// Wildcards are allowed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub silent_blocking_hosts: Vec<String>,
+    /// For how long a site stays allowed after clicking "Temporarily allow this site"
+    /// on the block page.
+    #[serde(default = "default_temporary_allow_minutes")]
+    pub temporary_allow_minutes: u64,
+    /// Offer to temporarily allow the site in the placeholder shown in place of blocked frames.
+    #[serde(default = "default_blocked_frame_allow_link")]
+    pub blocked_frame_allow_link: bool,
+    /// Sites on which scripts don't run: `<script>` tags are stripped from their documents and
+    /// the scripts their pages load are blocked. Wildcards are allowed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub no_script_hosts: Vec<String>,
+    /// Sites whose documents are passed through without being rewritten, for web apps which
+    /// break when they are. Their requests are still filtered, but cosmetic filters and
+    /// `no_script_hosts` no longer apply to their pages. Wildcards are allowed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub no_rewrite_hosts: Vec<String>,
+    #[serde(default)]
+    pub resources: super::ResourceBlockingConfig,
+    #[serde(default)]
+    pub webrtc: super::WebRtcProtectionConfig,
+    /// Gzip compress rewritten HTML documents for clients that accept it.
+    /// Documents are always decompressed before being rewritten.
+    #[serde(default)]
+    pub compress_rewritten_html: bool,
+    /// HTML documents announcing a larger size, in kilobytes, are not rewritten.
+    /// `0` disables the limit.
+    #[serde(default = "default_max_rewrite_size_kb")]
+    pub max_rewrite_size_kb: u64,
+    /// Documents still being received after this many seconds, such as streamed ones, have the
+    /// rest of their body passed through as is, along with the cosmetic filters of the part
+    /// rewritten so far. Documents whose scripts are stripped are always rewritten entirely.
+    /// `0` disables the limit.
+    #[serde(default = "default_max_rewrite_seconds")]
+    pub max_rewrite_seconds: u64,
+    /// Encodings asked for upstream, among those the client accepts. Documents received in
+    /// another encoding are passed through without being rewritten. Empty asks for
+    /// uncompressed responses.
+    #[serde(default = "default_upstream_encodings")]
+    pub upstream_encodings: Vec<ContentEncoding>,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub authentication: ProxyAuthenticationConfig,
+    #[serde(default)]
+    pub categories: super::CategoriesConfig,
+    #[serde(default)]
+    pub threat_protection: super::ThreatProtectionConfig,
+    #[serde(default)]
+    pub profiles: super::ProfilesConfig,
+    #[serde(default)]
+    pub tls: super::InterceptionTlsConfig,
+    #[serde(default)]
+    pub timeouts: super::TimeoutsConfig,
+    #[serde(default)]
+    pub retries: super::RetriesConfig,
+    #[serde(default)]
+    pub pool: super::PoolConfig,
+    /// Resolve the canonical name of hosts which are not blocked and check it against filters,
+    /// catching trackers hidden behind first party CNAMEs. Adds a DNS lookup to requests.
+    #[serde(default)]
+    pub cname_uncloaking: bool,
+    /// Destinations blocked once resolved, for tracking and telemetry endpoints which
+    /// can't be matched by host name. Upstream connections are never opened to them, even for
+    /// allowed requests or hosts whose DNS answer changed since their request was checked.
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocked_networks: Vec<IpNetwork>,
+    #[serde(default)]
+    pub geoip: super::GeoIpConfig,
+    #[serde(default)]
+    pub scripting: super::ScriptingConfig,
+    #[serde(default)]
+    pub icap: super::IcapConfig,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            https_upgrade: false,
+            silent_blocking: false,
+            silent_blocking_hosts: Vec::new(),
+            temporary_allow_minutes: DEFAULT_TEMPORARY_ALLOW_MINUTES,
+            blocked_frame_allow_link: true,
+            no_script_hosts: Vec::new(),
+            no_rewrite_hosts: Vec::new(),
+            resources: super::ResourceBlockingConfig::default(),
+            webrtc: super::WebRtcProtectionConfig::default(),
+            compress_rewritten_html: false,
+            max_rewrite_size_kb: DEFAULT_MAX_REWRITE_SIZE_KB,
+            max_rewrite_seconds: DEFAULT_MAX_REWRITE_SECONDS,
+            upstream_encodings: default_upstream_encodings(),
+            cache: CacheConfig::default(),
+            authentication: ProxyAuthenticationConfig::default(),
+            categories: super::CategoriesConfig::default(),
+            threat_protection: super::ThreatProtectionConfig::default(),
+            profiles: super::ProfilesConfig::default(),
+            tls: super::InterceptionTlsConfig::default(),
+            timeouts: super::TimeoutsConfig::default(),
+            retries: super::RetriesConfig::default(),
+            pool: super::PoolConfig::default(),
+            cname_uncloaking: false,
+            blocked_networks: Vec::new(),
+            geoip: super::GeoIpConfig::default(),
+            scripting: super::ScriptingConfig::default(),
+            icap: super::IcapConfig::default(),
+        }
+    }
+}
+
+impl ProxyConfig {
+    pub(crate) fn is_silently_blocked(&self, host: &str) -> bool {
+        if self.silent_blocking {
+            return true;
+        }
+
+        let host = host.to_lowercase();
+
+        self.silent_blocking_hosts
+            .iter()
+            .any(|pattern| WildMatch::new(&pattern.to_lowercase()).matches(&host))
+    }
+
+    pub(crate) fn is_script_blocked(&self, host: &str) -> bool {
+        let host = host.to_lowercase();
+
+        self.no_script_hosts
+            .iter()
+            .any(|pattern| WildMatch::new(&pattern.to_lowercase()).matches(&host))
+    }
+
+    pub(crate) fn is_rewrite_disabled(&self, host: &str) -> bool {
+        let host = host.to_lowercase();
+
+        self.no_rewrite_hosts
+            .iter()
+            .any(|pattern| WildMatch::new(&pattern.to_lowercase()).matches(&host))
+    }
+
+    pub(crate) fn exceeds_rewrite_size(&self, content_length: Option<u64>) -> bool {
+        match content_length {
+            Some(content_length) => {
+                self.max_rewrite_size_kb != 0 && content_length > self.max_rewrite_size_kb * 1024
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn max_rewrite_duration(&self) -> Option<Duration> {
+        match self.max_rewrite_seconds {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        }
+    }
+
+    /// Returns the first of `ip_addresses` which is blocked, along with the network it belongs to.
+    pub(crate) fn find_blocked_ip_address(
+        &self,
+        ip_addresses: &[IpAddr],
+    ) -> Option<(IpAddr, IpNetwork)> {
+        ip_addresses.iter().find_map(|ip_address| {
+            self.blocked_networks
+                .iter()
+                .find(|network| network.contains(ip_address))
+                .map(|network| (*ip_address, *network))
+        })
+    }
+
+    pub(crate) fn temporary_allow_duration(&self) -> Duration {
+        Duration::from_secs(self.temporary_allow_minutes * 60)
+    }
+}
@@ -0,0 +1,77 @@
+use super::Webhook;
+use chrono::Weekday;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_HOUR: u32 = 8;
+const DEFAULT_SMTP_PORT: u16 = 587;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFrequency {
+    #[default]
+    Never,
+    /// Every day, covering the last 24 hours.
+    Daily,
+    /// Every week on `weekday`, covering the last 7 days.
+    Weekly,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Upgraded with `STARTTLS`, usually on port 587.
+    #[default]
+    StartTls,
+    /// TLS from the start, usually on port 465.
+    Tls,
+    /// Unencrypted, for relays on the local network only.
+    None,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+/// Mail server reports are sent through
+pub struct SmtpConfig {
+    pub host: String,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// May be a `file://` or `env://` reference.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Sender address, such as `Privaxy <privaxy@example.com>`.
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+fn default_smtp_port() -> u16 {
+    DEFAULT_SMTP_PORT
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Summaries of the statistics sent by email or to webhooks
+pub struct ReportsConfig {
+    pub frequency: ReportFrequency,
+    /// Local hour reports are sent at, from 0 to 23.
+    pub hour: u32,
+    /// Day weekly reports are sent on.
+    pub weekday: Weekday,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smtp: Option<SmtpConfig>,
+    pub webhooks: Vec<Webhook>,
+}
+
+impl Default for ReportsConfig {
+    fn default() -> Self {
+        Self {
+            frequency: ReportFrequency::Never,
+            hour: DEFAULT_HOUR,
+            weekday: Weekday::Mon,
+            smtp: None,
+            webhooks: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use wildmatch::WildMatch;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(default)]
+/// Kinds of resources which are blocked
+pub struct BlockedResources {
+    /// Web fonts, pages fall back on the fonts of the system.
+    pub fonts: bool,
+    /// Audio and video.
+    pub media: bool,
+}
+
+impl BlockedResources {
+    /// Whether responses of type `content_type` are blocked.
+    pub(crate) fn blocks_content_type(&self, content_type: &str) -> bool {
+        let content_type = content_type.trim_start().to_lowercase();
+        let is_font = content_type.starts_with("font/")
+            || content_type.starts_with("application/font-")
+            || content_type.starts_with("application/x-font-")
+            || content_type.starts_with("application/vnd.ms-fontobject");
+        let is_media = content_type.starts_with("audio/")
+            || content_type.starts_with("video/")
+            || content_type.starts_with("application/vnd.apple.mpegurl")
+            || content_type.starts_with("application/x-mpegurl")
+            || content_type.starts_with("application/dash+xml");
+
+        (self.fonts && is_font) || (self.media && is_media)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+/// Resources blocked on a site instead of the global ones
+pub struct SiteBlockedResources {
+    /// Host of the pages, wildcards are allowed.
+    pub site: String,
+    #[serde(flatten)]
+    pub blocked: BlockedResources,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(default)]
+/// Blocking of remote fonts and media, for saving bandwidth or avoiding distractions
+pub struct ResourceBlockingConfig {
+    /// Resources blocked on sites without their own entry.
+    #[serde(flatten)]
+    pub blocked: BlockedResources,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sites: Vec<SiteBlockedResources>,
+}
+
+impl ResourceBlockingConfig {
+    /// Resources blocked on pages of `site`, the first matching entry applies.
+    pub(crate) fn blocked_for(&self, site: Option<&str>) -> BlockedResources {
+        let site = match site {
+            Some(site) => site.to_lowercase(),
+            None => return self.blocked,
+        };
+
+        self.sites
+            .iter()
+            .find(|entry| WildMatch::new(&entry.site.to_lowercase()).matches(&site))
+            .map_or(self.blocked, |entry| entry.blocked)
+    }
+}
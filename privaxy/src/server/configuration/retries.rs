@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Retries of requests failing because a server couldn't be reached
+pub struct RetriesConfig {
+    /// Retries of idempotent requests on refused or reset connections and failed DNS
+    /// lookups. `0` disables retries.
+    pub max_retries: u32,
+    /// Retry requests carrying a body too, which are then buffered before being sent. Only
+    /// requests without one are retried otherwise.
+    pub retry_request_bodies: bool,
+    /// Delay before the first retry, doubled for each following retry.
+    pub backoff_ms: u64,
+    /// Requests to a host failing this many times in a row, once retried, make Privaxy
+    /// consider it down. `0` disables the circuit breaker.
+    pub breaker_failures: u32,
+    /// Requests to a host considered down fail right away for this long, then a request
+    /// is let through to check whether it is back.
+    pub breaker_open_seconds: u64,
+}
+
+impl Default for RetriesConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            retry_request_bodies: false,
+            backoff_ms: 100,
+            breaker_failures: 5,
+            breaker_open_seconds: 30,
+        }
+    }
+}
+
+impl RetriesConfig {
+    /// Delay before retry number `retry`, starting at `0`.
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        Duration::from_millis(self.backoff_ms.saturating_mul(1 << retry.min(16)))
+    }
+
+    pub(crate) fn breaker_open_for(&self) -> Duration {
+        Duration::from_secs(self.breaker_open_seconds)
+    }
+}
@@ -0,0 +1,64 @@
+use rhai::{Engine, AST};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const DEFAULT_MAX_OPERATIONS: u64 = 100_000;
+/// Function of the script called for every request.
+pub(crate) const DECIDE_FUNCTION: &str = "decide";
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Rules written as a [Rhai](https://rhai.rs) script, deciding what happens to intercepted requests.
+///
+/// The script defines `fn decide(request)`, where `request` has the `method`, `scheme`, `host`,
+/// `port`, `path`, `query`, `client`, `user` and `headers` of the request, header names being
+/// lowercase. It returns `()` to leave the request to filters, `"allow"`, `"block"`, or one of
+/// `#{ action: "block", reason: "..." }`, `#{ action: "rewrite", url: "https://..." }` and
+/// `#{ action: "upstream", upstream: "tor" }`, the latter naming an egress upstream.
+pub struct ScriptingConfig {
+    /// Script file, scripting is disabled when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Operations a script may perform for a request before it is aborted.
+    pub max_operations: u64,
+}
+
+impl Default for ScriptingConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_operations: DEFAULT_MAX_OPERATIONS,
+        }
+    }
+}
+
+impl ScriptingConfig {
+    pub(crate) fn engine(&self) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_max_operations(self.max_operations);
+        engine.set_max_call_levels(32);
+        engine.set_max_expr_depths(64, 32);
+        engine.set_max_string_size(64 * 1024);
+        engine.set_max_array_size(10_000);
+        engine.set_max_map_size(10_000);
+
+        engine
+    }
+}
+
+/// Compiles `source`, making sure that it defines the function called for requests.
+pub(crate) fn compile_script(engine: &Engine, source: &str) -> Result<AST, String> {
+    let ast = engine.compile(source).map_err(|err| err.to_string())?;
+
+    if ast
+        .iter_functions()
+        .any(|function| function.name == DECIDE_FUNCTION && function.params.len() == 1)
+    {
+        Ok(ast)
+    } else {
+        Err(format!(
+            "The script must define fn {}(request)",
+            DECIDE_FUNCTION
+        ))
+    }
+}
@@ -0,0 +1,61 @@
+//! Secret settings, the CA certificate and private key, passwords and API tokens, may reference a
+//! file, `file:///run/secrets/privaxy_ca_key`, or an environment variable,
+//! `env://PRIVAXY_CA_KEY`, instead of holding the secret itself.
+//!
+//! References are resolved every time the secret is used and saved as they are, so that secrets
+//! never end up in the configuration or its backups.
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const FILE_PREFIX: &str = "file://";
+const ENV_PREFIX: &str = "env://";
+
+#[derive(Error, Debug)]
+pub enum SecretError {
+    #[error("unable to read secret file {0}: {1}")]
+    File(PathBuf, std::io::Error),
+    #[error("environment variable {0} is not set")]
+    Environment(String),
+    #[error("read from environment variable {0}, it can't be changed")]
+    ReadOnly(String),
+}
+
+/// The secret `value` holds or references. Trailing line breaks of files are removed.
+pub(crate) fn resolve_secret(value: &str) -> Result<Cow<'_, str>, SecretError> {
+    if let Some(path) = secret_file(value) {
+        return std::fs::read_to_string(path)
+            .map(|contents| Cow::Owned(contents.trim_end_matches(['\r', '\n']).to_string()))
+            .map_err(|err| SecretError::File(path.to_path_buf(), err));
+    }
+
+    if let Some(name) = secret_environment_variable(value) {
+        return std::env::var(name)
+            .map(Cow::Owned)
+            .map_err(|_err| SecretError::Environment(name.to_string()));
+    }
+
+    Ok(Cow::Borrowed(value))
+}
+
+/// Like [`resolve_secret`], for optional settings. Unresolvable references are logged and
+/// treated as unset.
+pub(crate) fn resolve_optional_secret(value: Option<&str>) -> Option<Cow<'_, str>> {
+    match resolve_secret(value?) {
+        Ok(secret) => Some(secret),
+        Err(err) => {
+            log::error!("Unable to resolve secret: {}", err);
+            None
+        }
+    }
+}
+
+/// Path of the file `value` references, if it does.
+pub(crate) fn secret_file(value: &str) -> Option<&Path> {
+    value.strip_prefix(FILE_PREFIX).map(Path::new)
+}
+
+/// Name of the environment variable `value` references, if it does.
+pub(crate) fn secret_environment_variable(value: &str) -> Option<&str> {
+    value.strip_prefix(ENV_PREFIX)
+}
@@ -0,0 +1,383 @@
+//! Where the configuration is kept: the `config` file by default, an SQLite database or a key of
+//! an etcd or Consul cluster so that several instances share it.
+//!
+//! Only the configuration itself is stored there, downloaded filter lists and other files are
+//! still written to the base directory.
+use super::{get_config_file, ConfigurationError, ConfigurationResult};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures::future::BoxFuture;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::fs;
+use url::Url;
+
+/// Key the configuration is stored under in etcd and Consul when the URL has no path.
+const DEFAULT_KEY: &str = "privaxy/config";
+
+static STORE: OnceCell<Box<dyn ConfigurationStore>> = OnceCell::new();
+
+/// Persists the serialized configuration.
+pub trait ConfigurationStore: fmt::Display + Send + Sync {
+    /// The saved configuration, `None` when none was saved yet.
+    fn load(&self) -> BoxFuture<'_, ConfigurationResult<Option<String>>>;
+
+    fn save<'a>(&'a self, contents: &'a str) -> BoxFuture<'a, ConfigurationResult<()>>;
+}
+
+/// Sets the store used from now on. Must be called before the configuration is first read,
+/// returns `false` when a store was already in use.
+pub fn set_store(store: Box<dyn ConfigurationStore>) -> bool {
+    STORE.set(store).is_ok()
+}
+
+/// The store selected at startup, the configuration file when none was.
+pub fn store() -> &'static dyn ConfigurationStore {
+    STORE
+        .get_or_init(|| Box::new(FileStore::new(get_config_file())))
+        .as_ref()
+}
+
+/// Store given on the command line, as `file`, `file:<path>`, `sqlite:<path>`,
+/// `etcd://<host>:<port>/<key>` or `consul://<host>:<port>/<key>`. `etcd+https` and
+/// `consul+https` reach the cluster over TLS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreLocation {
+    File(Option<PathBuf>),
+    Sqlite(PathBuf),
+    Etcd { endpoint: Url, key: String },
+    Consul { endpoint: Url, key: String },
+}
+
+impl StoreLocation {
+    pub fn open(self) -> Box<dyn ConfigurationStore> {
+        match self {
+            Self::File(path) => Box::new(FileStore::new(path.unwrap_or_else(get_config_file))),
+            Self::Sqlite(path) => Box::new(SqliteStore { path }),
+            Self::Etcd { endpoint, key } => Box::new(EtcdStore {
+                endpoint,
+                key,
+                http_client: reqwest::Client::new(),
+            }),
+            Self::Consul { endpoint, key } => Box::new(ConsulStore {
+                endpoint,
+                key,
+                http_client: reqwest::Client::new(),
+            }),
+        }
+    }
+}
+
+impl FromStr for StoreLocation {
+    type Err = String;
+
+    fn from_str(location: &str) -> Result<Self, Self::Err> {
+        if location == "file" {
+            return Ok(Self::File(None));
+        }
+        if let Some(path) = location.strip_prefix("file:") {
+            return Ok(Self::File(Some(PathBuf::from(path))));
+        }
+        if let Some(path) = location.strip_prefix("sqlite:") {
+            if path.is_empty() {
+                return Err("missing SQLite database path".to_string());
+            }
+            return Ok(Self::Sqlite(PathBuf::from(path)));
+        }
+
+        let url = Url::parse(location).map_err(|err| format!("invalid store: {}", err))?;
+        let (kind, scheme) = match url.scheme().split_once('+') {
+            Some((kind, "https")) => (kind, "https"),
+            Some((_kind, scheme)) => return Err(format!("unsupported scheme: {}", scheme)),
+            None => (url.scheme(), "http"),
+        };
+        let host = url.host_str().ok_or_else(|| "missing host".to_string())?;
+        let endpoint = match url.port() {
+            Some(port) => format!("{}://{}:{}/", scheme, host, port),
+            None => format!("{}://{}/", scheme, host),
+        };
+        let endpoint = Url::parse(&endpoint).map_err(|err| format!("invalid store: {}", err))?;
+        let key = match url.path().trim_matches('/') {
+            "" => DEFAULT_KEY.to_string(),
+            key => key.to_string(),
+        };
+
+        match kind {
+            "etcd" => Ok(Self::Etcd { endpoint, key }),
+            "consul" => Ok(Self::Consul { endpoint, key }),
+            kind => Err(format!(
+                "unknown store {}, expected file, sqlite, etcd or consul",
+                kind
+            )),
+        }
+    }
+}
+
+fn store_error(err: impl fmt::Display) -> ConfigurationError {
+    ConfigurationError::StoreError(err.to_string())
+}
+
+/// The TOML configuration file.
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl fmt::Display for FileStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path.display())
+    }
+}
+
+impl ConfigurationStore for FileStore {
+    fn load(&self) -> BoxFuture<'_, ConfigurationResult<Option<String>>> {
+        Box::pin(async move {
+            match fs::read(&self.path).await {
+                Ok(bytes) => Ok(Some(std::str::from_utf8(&bytes)?.to_string())),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(ConfigurationError::FileSystemError(err)),
+            }
+        })
+    }
+
+    fn save<'a>(&'a self, contents: &'a str) -> BoxFuture<'a, ConfigurationResult<()>> {
+        Box::pin(async move {
+            fs::write(&self.path, contents).await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// A single row table of an SQLite database, created when missing.
+pub struct SqliteStore {
+    path: PathBuf,
+}
+
+impl SqliteStore {
+    fn open(path: &Path) -> rusqlite::Result<rusqlite::Connection> {
+        let connection = rusqlite::Connection::open(path)?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS configuration (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                contents TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )",
+            (),
+        )?;
+
+        Ok(connection)
+    }
+}
+
+impl fmt::Display for SqliteStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "sqlite:{}", self.path.display())
+    }
+}
+
+impl ConfigurationStore for SqliteStore {
+    fn load(&self) -> BoxFuture<'_, ConfigurationResult<Option<String>>> {
+        let path = self.path.clone();
+
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let connection = Self::open(&path)?;
+                let mut statement =
+                    connection.prepare("SELECT contents FROM configuration WHERE id = 1")?;
+                let mut rows = statement.query(())?;
+
+                match rows.next()? {
+                    Some(row) => row.get::<_, String>(0).map(Some),
+                    None => Ok(None),
+                }
+            })
+            .await
+            .map_err(store_error)?
+            .map_err(store_error)
+        })
+    }
+
+    fn save<'a>(&'a self, contents: &'a str) -> BoxFuture<'a, ConfigurationResult<()>> {
+        let path = self.path.clone();
+        let contents = contents.to_string();
+
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let connection = Self::open(&path)?;
+                connection.execute(
+                    "INSERT INTO configuration (id, contents, updated_at) VALUES (1, ?1, ?2)
+                     ON CONFLICT (id) DO UPDATE SET
+                        contents = excluded.contents,
+                        updated_at = excluded.updated_at",
+                    (&contents, chrono::Utc::now().to_rfc3339()),
+                )?;
+
+                Ok::<_, rusqlite::Error>(())
+            })
+            .await
+            .map_err(store_error)?
+            .map_err(store_error)
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct EtcdRangeRequest {
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct EtcdRangeResponse {
+    #[serde(default)]
+    kvs: Vec<EtcdKeyValue>,
+}
+
+#[derive(Deserialize)]
+struct EtcdKeyValue {
+    value: String,
+}
+
+#[derive(Serialize)]
+struct EtcdPutRequest {
+    key: String,
+    value: String,
+}
+
+/// A key of an etcd cluster, through its JSON gateway. Keys and values are base64 encoded.
+pub struct EtcdStore {
+    endpoint: Url,
+    key: String,
+    http_client: reqwest::Client,
+}
+
+impl EtcdStore {
+    async fn post<T: Serialize>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> ConfigurationResult<reqwest::Response> {
+        let url = self.endpoint.join(path).map_err(store_error)?;
+
+        self.http_client
+            .post(url)
+            .json(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(store_error)
+    }
+}
+
+impl fmt::Display for EtcdStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "etcd key {} at {}", self.key, self.endpoint)
+    }
+}
+
+impl ConfigurationStore for EtcdStore {
+    fn load(&self) -> BoxFuture<'_, ConfigurationResult<Option<String>>> {
+        Box::pin(async move {
+            let request = EtcdRangeRequest {
+                key: BASE64.encode(&self.key),
+            };
+            let response = self
+                .post("v3/kv/range", &request)
+                .await?
+                .json::<EtcdRangeResponse>()
+                .await
+                .map_err(store_error)?;
+
+            match response.kvs.into_iter().next() {
+                Some(key_value) => {
+                    let value = BASE64.decode(key_value.value).map_err(store_error)?;
+                    Ok(Some(String::from_utf8(value).map_err(store_error)?))
+                }
+                None => Ok(None),
+            }
+        })
+    }
+
+    fn save<'a>(&'a self, contents: &'a str) -> BoxFuture<'a, ConfigurationResult<()>> {
+        Box::pin(async move {
+            let request = EtcdPutRequest {
+                key: BASE64.encode(&self.key),
+                value: BASE64.encode(contents),
+            };
+            self.post("v3/kv/put", &request).await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// A key of the Consul key/value store.
+pub struct ConsulStore {
+    endpoint: Url,
+    key: String,
+    http_client: reqwest::Client,
+}
+
+impl ConsulStore {
+    fn url(&self) -> ConfigurationResult<Url> {
+        self.endpoint
+            .join(&format!("v1/kv/{}", self.key))
+            .map_err(store_error)
+    }
+}
+
+impl fmt::Display for ConsulStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Consul key {} at {}", self.key, self.endpoint)
+    }
+}
+
+impl ConfigurationStore for ConsulStore {
+    fn load(&self) -> BoxFuture<'_, ConfigurationResult<Option<String>>> {
+        Box::pin(async move {
+            let mut url = self.url()?;
+            url.set_query(Some("raw"));
+
+            let response = self
+                .http_client
+                .get(url)
+                .send()
+                .await
+                .map_err(store_error)?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+
+            let contents = response
+                .error_for_status()
+                .map_err(store_error)?
+                .text()
+                .await
+                .map_err(store_error)?;
+
+            Ok(Some(contents))
+        })
+    }
+
+    fn save<'a>(&'a self, contents: &'a str) -> BoxFuture<'a, ConfigurationResult<()>> {
+        Box::pin(async move {
+            self.http_client
+                .put(self.url()?)
+                .body(contents.to_string())
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(store_error)?;
+
+            Ok(())
+        })
+    }
+}
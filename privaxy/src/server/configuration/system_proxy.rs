@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Points the system proxy to Privaxy while it runs
+pub struct SystemProxyConfig {
+    /// Set the system proxy on start and restore the previous one on stop. Supported on
+    /// Windows, macOS, and the GNOME and KDE desktops on Linux.
+    pub enabled: bool,
+    /// Hosts reached directly, in the WinINET `ProxyOverride` syntax. `<local>` matches
+    /// host names without a dot, and is ignored on macOS and Linux.
+    pub bypass: Vec<String>,
+}
+
+impl Default for SystemProxyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bypass: vec!["<local>".to_string()],
+        }
+    }
+}
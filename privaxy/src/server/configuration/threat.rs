@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use std::time::Duration;
+use tokio::fs;
+use url::Url;
+
+const DEFAULT_SAFE_BROWSING_CACHE_MINUTES: u64 = 30;
+
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+/// Threat intelligence feed listing one malicious URL per line
+pub struct ThreatFeed {
+    /// Shown on the block page.
+    pub name: String,
+    #[serde_as(as = "DisplayFromStr")]
+    pub url: Url,
+}
+
+impl ThreatFeed {
+    fn file_name(&self) -> String {
+        super::filter::calc_filter_filename(self.url.as_str())
+    }
+
+    pub(super) async fn update(
+        &self,
+        http_client: &reqwest::Client,
+    ) -> super::ConfigurationResult<String> {
+        log::debug!("Updating threat feed: {}", self.name);
+
+        let response = http_client.get(self.url.as_str()).send().await?;
+        if !response.status().is_success() {
+            return Err(super::ConfigurationError::FilterError(format!(
+                "Failed to fetch threat feed: {}",
+                response.status()
+            )));
+        }
+        let content = response.text().await?;
+
+        let filters_directory = super::filter::get_filter_directory();
+        fs::create_dir_all(&filters_directory).await?;
+        fs::write(filters_directory.join(self.file_name()), &content).await?;
+
+        Ok(content)
+    }
+
+    pub(super) async fn get_contents(
+        &self,
+        http_client: &reqwest::Client,
+    ) -> super::ConfigurationResult<String> {
+        let feed_path = super::filter::get_filter_directory().join(self.file_name());
+        match fs::read(&feed_path).await {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                self.update(http_client).await
+            }
+            Err(err) => Err(super::ConfigurationError::FileSystemError(err)),
+            Ok(feed) => Ok(std::str::from_utf8(&feed)?.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Malware and phishing protection, checked before filter lists
+pub struct ThreatProtectionConfig {
+    pub enabled: bool,
+    pub feeds: Vec<ThreatFeed>,
+    /// Google Safe Browsing API key, or a `file://` or `env://` reference to it. Documents not
+    /// found in feeds are looked up when set. Only their host is sent, so threats limited to
+    /// some paths of a host aren't found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safe_browsing_api_key: Option<String>,
+    /// For how long Safe Browsing verdicts are cached when the API doesn't say otherwise.
+    pub safe_browsing_cache_minutes: u64,
+}
+
+impl Default for ThreatProtectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            feeds: vec![
+                ThreatFeed {
+                    name: "URLhaus".to_string(),
+                    url: Url::parse("https://urlhaus.abuse.ch/downloads/text_online/").unwrap(),
+                },
+                ThreatFeed {
+                    name: "OpenPhish".to_string(),
+                    url: Url::parse("https://openphish.com/feed.txt").unwrap(),
+                },
+            ],
+            safe_browsing_api_key: None,
+            safe_browsing_cache_minutes: DEFAULT_SAFE_BROWSING_CACHE_MINUTES,
+        }
+    }
+}
+
+impl ThreatProtectionConfig {
+    pub(crate) fn safe_browsing_cache_duration(&self) -> Duration {
+        Duration::from_secs(self.safe_browsing_cache_minutes * 60)
+    }
+}
+
+/// Loads threat feeds into `threat_store`. Feeds that can't be retrieved are skipped.
+pub(crate) async fn load_threat_feeds(
+    configuration: &super::Configuration,
+    http_client: &reqwest::Client,
+    threat_store: &crate::proxy::threats::ThreatStore,
+    update: bool,
+) {
+    let threat_protection = &configuration.proxy.threat_protection;
+    if !threat_protection.enabled {
+        threat_store.replace_feeds(Vec::new());
+        return;
+    }
+
+    let futures = threat_protection.feeds.iter().map(|feed| async move {
+        let contents = if update {
+            feed.update(http_client).await
+        } else {
+            feed.get_contents(http_client).await
+        };
+
+        (feed.name.clone(), contents)
+    });
+
+    let mut feeds = Vec::new();
+    for (name, contents) in futures::future::join_all(futures).await {
+        match contents {
+            Ok(contents) => feeds.push((name, contents)),
+            Err(err) => log::error!("Unable to retrieve threat feed {}: {:?}", name, err),
+        }
+    }
+
+    threat_store.replace_feeds(feeds);
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Timeouts of the stages of proxied connections, in seconds. `0` disables a timeout.
+pub struct TimeoutsConfig {
+    /// Connecting to servers, including through an egress proxy.
+    pub connect_seconds: u64,
+    /// TLS handshake with clients of intercepted connections.
+    pub tls_handshake_seconds: u64,
+    /// Clients sending the headers of a request.
+    pub request_seconds: u64,
+    /// Servers answering with the headers of a response, once the request is sent.
+    pub response_header_seconds: u64,
+    /// Unused connections to servers are kept open for reuse this long.
+    pub idle_seconds: u64,
+    /// Tunnels are closed when no data went through them for this long.
+    pub tunnel_idle_seconds: u64,
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        Self {
+            connect_seconds: 10,
+            tls_handshake_seconds: 5,
+            request_seconds: 30,
+            response_header_seconds: 60,
+            idle_seconds: 90,
+            tunnel_idle_seconds: 60 * 10,
+        }
+    }
+}
+
+fn seconds(seconds: u64) -> Option<Duration> {
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+impl TimeoutsConfig {
+    pub(crate) fn connect(&self) -> Option<Duration> {
+        seconds(self.connect_seconds)
+    }
+
+    pub(crate) fn tls_handshake(&self) -> Option<Duration> {
+        seconds(self.tls_handshake_seconds)
+    }
+
+    pub(crate) fn request(&self) -> Option<Duration> {
+        seconds(self.request_seconds)
+    }
+
+    pub(crate) fn response_header(&self) -> Option<Duration> {
+        seconds(self.response_header_seconds)
+    }
+
+    pub(crate) fn idle(&self) -> Option<Duration> {
+        seconds(self.idle_seconds)
+    }
+
+    pub(crate) fn tunnel_idle(&self) -> Option<Duration> {
+        seconds(self.tunnel_idle_seconds)
+    }
+}
@@ -0,0 +1,106 @@
+use rustls::{
+    ConfigBuilder, ServerConfig, SupportedCipherSuite, SupportedProtocolVersion, WantsVerifier,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+impl TlsVersion {
+    fn protocol_version(self) -> &'static SupportedProtocolVersion {
+        match self {
+            Self::Tls12 => &rustls::version::TLS12,
+            Self::Tls13 => &rustls::version::TLS13,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// TLS served to clients on intercepted connections, rustls safe defaults when unset
+pub struct InterceptionTlsConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_version: Option<TlsVersion>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_version: Option<TlsVersion>,
+    /// Names of the cipher suites offered, such as `TLS13_AES_256_GCM_SHA384` or
+    /// `TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256`, in order of preference.
+    /// Certificates use RSA keys, ECDSA suites are never negotiated.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cipher_suites: Vec<String>,
+    /// For debugging only: TLS secrets of intercepted connections are appended to this file
+    /// in the NSS key log format, so that packet captures can be decrypted with Wireshark.
+    /// The `SSLKEYLOGFILE` environment variable is used when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_log_file: Option<PathBuf>,
+    /// Certificates signed at startup and after CA changes for the hosts intercepted the
+    /// most, so that the first connection to them doesn't wait for one. 0 disables it.
+    pub pregenerated_certificates: usize,
+    /// Offer HTTP/2 to clients, which is required for trailers, used by gRPC, to reach them.
+    /// Off by default, so that clients keep the HTTP/1.1 connections they always had.
+    pub http2: bool,
+}
+
+impl Default for InterceptionTlsConfig {
+    fn default() -> Self {
+        Self {
+            min_version: None,
+            max_version: None,
+            cipher_suites: Vec::new(),
+            key_log_file: None,
+            pregenerated_certificates: 100,
+            http2: false,
+        }
+    }
+}
+
+impl InterceptionTlsConfig {
+    fn protocol_versions(&self) -> Vec<&'static SupportedProtocolVersion> {
+        [TlsVersion::Tls12, TlsVersion::Tls13]
+            .into_iter()
+            .filter(|version| self.min_version.is_none_or(|min| *version >= min))
+            .filter(|version| self.max_version.is_none_or(|max| *version <= max))
+            .map(TlsVersion::protocol_version)
+            .collect()
+    }
+
+    fn cipher_suites(&self) -> Result<Vec<SupportedCipherSuite>, String> {
+        if self.cipher_suites.is_empty() {
+            return Ok(rustls::DEFAULT_CIPHER_SUITES.to_vec());
+        }
+
+        self.cipher_suites
+            .iter()
+            .map(|name| {
+                rustls::ALL_CIPHER_SUITES
+                    .iter()
+                    .find(|suite| format!("{:?}", suite.suite()) == *name)
+                    .copied()
+                    .ok_or_else(|| format!("Unknown cipher suite {}", name))
+            })
+            .collect()
+    }
+
+    /// A server configuration builder with these versions and cipher suites, which fails
+    /// when no suite can be used with the allowed versions.
+    pub(crate) fn server_config_builder(
+        &self,
+    ) -> Result<ConfigBuilder<ServerConfig, WantsVerifier>, String> {
+        let protocol_versions = self.protocol_versions();
+        if protocol_versions.is_empty() {
+            return Err("min_version is greater than max_version".to_string());
+        }
+
+        ServerConfig::builder()
+            .with_cipher_suites(&self.cipher_suites()?)
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&protocol_versions)
+            .map_err(|err| err.to_string())
+    }
+}
@@ -1,4 +1,7 @@
-use crate::blocker::AdblockRequester;
+use crate::alerts::{Alert, Alerter};
+use crate::blocker::{parse_custom_scriptlets, AdblockRequester};
+use crate::proxy::categories::CategoryStore;
+use crate::proxy::threats::ThreatStore;
 use futures::future::{AbortHandle, Abortable};
 
 use tokio::sync::mpsc::Receiver;
@@ -10,6 +13,9 @@ pub struct ConfigurationUpdater {
     pub tx: Sender<super::Configuration>,
     http_client: reqwest::Client,
     adblock_requester: AdblockRequester,
+    category_store: CategoryStore,
+    threat_store: ThreatStore,
+    alerter: Alerter,
 }
 
 impl ConfigurationUpdater {
@@ -17,6 +23,9 @@ impl ConfigurationUpdater {
         configuration: super::Configuration,
         http_client: reqwest::Client,
         adblock_requester: AdblockRequester,
+        category_store: CategoryStore,
+        threat_store: ThreatStore,
+        alerter: Alerter,
         tx_rx: Option<(
             sync::mpsc::Sender<super::Configuration>,
             sync::mpsc::Receiver<super::Configuration>,
@@ -31,12 +40,18 @@ impl ConfigurationUpdater {
 
         let http_client_clone = http_client.clone();
         let adblock_requester_clone = adblock_requester.clone();
+        let category_store_clone = category_store.clone();
+        let threat_store_clone = threat_store.clone();
+        let alerter_clone = alerter.clone();
 
         let filters_updater = Abortable::new(
             async move {
                 Self::filters_updater(
                     configuration,
                     adblock_requester_clone,
+                    category_store_clone,
+                    threat_store_clone,
+                    alerter_clone,
                     http_client_clone.clone(),
                 )
                 .await
@@ -52,26 +67,80 @@ impl ConfigurationUpdater {
             tx,
             http_client,
             adblock_requester,
+            category_store,
+            threat_store,
+            alerter,
         }
     }
 
-    pub(crate) fn start(mut self: Self) {
+    pub(crate) fn start(mut self) {
         tokio::spawn(async move {
+            let mut applied_configuration: Option<super::Configuration> = None;
+
             loop {
                 let mut configuration = self.rx.recv().await.unwrap();
                 self.filters_updater_abort_handle.abort();
-
-                let filters =
-                    super::filter::get_filters_content(&mut configuration, &self.http_client).await;
-                self.adblock_requester.replace_engine(filters).await;
+                self.alerter.set_configuration(configuration.alerts.clone());
+
+                // Sent first so that engines rebuilt below are given the scriptlets.
+                let scriptlets = match parse_custom_scriptlets(&configuration.custom_scriptlets) {
+                    Ok(scriptlets) => scriptlets,
+                    Err(err) => {
+                        log::error!("Ignoring custom scriptlets: {err}");
+                        Vec::new()
+                    }
+                };
+                self.adblock_requester.replace_scriptlets(scriptlets).await;
+
+                // Editing custom rules is much more frequent than changing filter lists, and
+                // rebuilding their engines alone is nearly instant.
+                let same_filter_lists = applied_configuration.as_ref().is_some_and(|applied| {
+                    super::filter::same_filter_lists(applied, &configuration)
+                });
+                if same_filter_lists {
+                    self.adblock_requester
+                        .replace_custom_engines(super::filter::get_profiles_custom_filters(
+                            &configuration,
+                        ))
+                        .await;
+                } else {
+                    let filters = super::filter::get_profiles_filters_content(
+                        &mut configuration,
+                        &self.http_client,
+                    )
+                    .await;
+                    self.adblock_requester.replace_engines(filters).await;
+                }
+                applied_configuration = Some(configuration.clone());
+
+                super::load_categories(
+                    &configuration,
+                    &self.http_client,
+                    &self.category_store,
+                    false,
+                )
+                .await;
+                super::load_threat_feeds(
+                    &configuration,
+                    &self.http_client,
+                    &self.threat_store,
+                    false,
+                )
+                .await;
 
                 let adblock_requester_clone = self.adblock_requester.clone();
+                let category_store_clone = self.category_store.clone();
+                let threat_store_clone = self.threat_store.clone();
+                let alerter_clone = self.alerter.clone();
                 let http_client_clone = self.http_client.clone();
 
                 tokio::spawn(async move {
                     Self::filters_updater(
                         configuration,
                         adblock_requester_clone,
+                        category_store_clone,
+                        threat_store_clone,
+                        alerter_clone,
                         http_client_clone,
                     )
                     .await;
@@ -85,20 +154,40 @@ impl ConfigurationUpdater {
     async fn filters_updater(
         mut configuration: super::Configuration,
         adblock_requester: AdblockRequester,
+        category_store: CategoryStore,
+        threat_store: ThreatStore,
+        alerter: Alerter,
         http_client: reqwest::Client,
     ) {
+        let mut consecutive_failures = 0;
+
         loop {
             tokio::time::sleep(super::FILTERS_UPDATE_AFTER).await;
 
-            if let Err(err) = configuration.update_filters(http_client.clone()).await {
-                log::error!("An error occured while trying to update filters: {:?}", err);
+            match configuration.update_filters(http_client.clone()).await {
+                Ok(()) => consecutive_failures = 0,
+                Err(err) => {
+                    log::error!("An error occured while trying to update filters: {:?}", err);
+
+                    consecutive_failures += 1;
+                    let threshold = configuration.alerts.filter_update_failures;
+                    if threshold > 0 && consecutive_failures >= threshold {
+                        alerter.send(Alert::FilterUpdatesFailing {
+                            failures: consecutive_failures,
+                            error: err.to_string(),
+                        });
+                    }
+                }
             }
 
             // We don't bother diffing the filters as replacing the engine is very cheap and
             // filters are not updated often enough that the cost would matter.
             let filters =
-                super::filter::get_filters_content(&mut configuration, &http_client).await;
-            adblock_requester.replace_engine(filters).await;
+                super::filter::get_profiles_filters_content(&mut configuration, &http_client).await;
+            adblock_requester.replace_engines(filters).await;
+
+            super::load_categories(&configuration, &http_client, &category_store, true).await;
+            super::load_threat_feeds(&configuration, &http_client, &threat_store, true).await;
 
             log::info!("Updated filters");
         }
@@ -0,0 +1,827 @@
+use super::{
+    compile_script, resolve_secret, Ca, Configuration, DnsProtocol, DnsServers, LogOutput,
+    MetricsPushTarget, NetworkConfigError, ProxyAuthentication, ReportFrequency, UpstreamKind,
+    DEFAULT_PROFILE,
+};
+use openssl::asn1::Asn1Time;
+use openssl::pkey::PKey;
+use openssl::x509::X509;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use tokio::fs;
+use url::Url;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Privaxy refuses to start with this configuration.
+    Error,
+    /// Likely a mistake, such as a misspelled key, but Privaxy starts anyway.
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+/// A problem found in the configuration
+pub struct ValidationIssue {
+    pub severity: Severity,
+    /// Dotted path of the offending key, such as `network.web_port` or `filters.2.url`.
+    /// Empty when the problem is with the file as a whole.
+    pub key: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+
+        if self.key.is_empty() {
+            write!(f, "{}: {}", severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", severity, self.key, self.message)
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+/// Every problem found in a configuration, rather than only the first one
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Error)
+    }
+
+    /// Errors with keys in `section`, so that saving a section isn't refused because of
+    /// problems elsewhere in the file.
+    pub fn section_errors<'a>(
+        &'a self,
+        section: &'a str,
+    ) -> impl Iterator<Item = &'a ValidationIssue> {
+        self.errors().filter(move |issue| {
+            issue.key == section
+                || issue
+                    .key
+                    .strip_prefix(section)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+    }
+
+    fn error(&mut self, key: impl Into<String>, message: impl Into<String>) {
+        self.issues.push(ValidationIssue {
+            severity: Severity::Error,
+            key: key.into(),
+            message: message.into(),
+        });
+    }
+
+    fn warning(&mut self, key: impl Into<String>, message: impl Into<String>) {
+        self.issues.push(ValidationIssue {
+            severity: Severity::Warning,
+            key: key.into(),
+            message: message.into(),
+        });
+    }
+}
+
+/// Reads and validates the configuration file at `path`.
+pub async fn validate_file(path: &Path) -> ValidationReport {
+    match fs::read_to_string(path).await {
+        Ok(contents) => validate_str(&contents).await,
+        Err(err) => {
+            let mut report = ValidationReport::default();
+            report.error("", format!("Unable to read {}: {}", path.display(), err));
+            report
+        }
+    }
+}
+
+/// Validates the configuration kept in the store selected at startup. A missing configuration is
+/// valid, it is created with defaults.
+pub async fn validate_stored() -> ValidationReport {
+    match super::store().load().await {
+        Ok(Some(contents)) => validate_str(&contents).await,
+        Ok(None) => ValidationReport::default(),
+        Err(err) => {
+            let mut report = ValidationReport::default();
+            report.error("", format!("Unable to read {}: {}", super::store(), err));
+            report
+        }
+    }
+}
+
+/// Parses `contents` as a configuration file and validates it. Keys Privaxy doesn't know about
+/// are reported as warnings as they are otherwise silently ignored.
+pub async fn validate_str(contents: &str) -> ValidationReport {
+    let mut unknown_keys = Vec::new();
+    let parsed: Result<Configuration, _> =
+        serde_ignored::deserialize(toml::Deserializer::new(contents), |path| {
+            unknown_keys.push(path.to_string())
+        });
+
+    let mut report = match parsed {
+        Ok(configuration) => configuration.validate().await,
+        Err(err) => {
+            let mut report = ValidationReport::default();
+            let message = match err.span() {
+                Some(span) => {
+                    let (line, column) = line_and_column(contents, span.start);
+                    format!("line {}, column {}: {}", line, column, err.message())
+                }
+                None => err.message().to_string(),
+            };
+            report.error("", message);
+            report
+        }
+    };
+
+    for key in unknown_keys {
+        report.warning(key, "Unknown key, ignored");
+    }
+
+    report
+}
+
+/// Reports references to files that can't be read and to unset environment variables.
+fn validate_secret(report: &mut ValidationReport, key: String, value: Option<&str>) {
+    if let Some(Err(err)) = value.map(resolve_secret) {
+        report.error(key, err.to_string());
+    }
+}
+
+fn line_and_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |index| index + 1) + 1;
+
+    (line, column)
+}
+
+impl Configuration {
+    /// Checks what deserializing doesn't: ports, certificates and keys, URLs and paths.
+    pub async fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        self.validate_network(&mut report).await;
+        validate_ca(&self.ca, &mut report).await;
+
+        for (index, filter) in self.filters.iter().enumerate() {
+            validate_http_url(&mut report, format!("filters.{}.url", index), &filter.url);
+        }
+        for (index, list) in self.proxy.categories.lists.iter().enumerate() {
+            validate_http_url(
+                &mut report,
+                format!("proxy.categories.lists.{}.url", index),
+                &list.url,
+            );
+        }
+        for (index, feed) in self.proxy.threat_protection.feeds.iter().enumerate() {
+            validate_http_url(
+                &mut report,
+                format!("proxy.threat_protection.feeds.{}.url", index),
+                &feed.url,
+            );
+        }
+        for (index, webhook) in self.alerts.webhooks.iter().enumerate() {
+            validate_http_url(
+                &mut report,
+                format!("alerts.webhooks.{}.url", index),
+                &webhook.url,
+            );
+        }
+        for (index, webhook) in self.reports.webhooks.iter().enumerate() {
+            validate_http_url(
+                &mut report,
+                format!("reports.webhooks.{}.url", index),
+                &webhook.url,
+            );
+        }
+        if let Some(url) = &self.filter_bundle.url {
+            validate_http_url(&mut report, "filter_bundle.url".to_string(), url);
+        }
+
+        let mut usernames = HashSet::new();
+        for (index, user) in self.proxy.authentication.users.iter().enumerate() {
+            let key = format!("proxy.authentication.users.{}.username", index);
+            if user.username.is_empty() {
+                report.error(key, "Username cannot be empty");
+            } else if !usernames.insert(user.username.as_str()) {
+                report.error(key, format!("Duplicate username {}", user.username));
+            }
+        }
+
+        self.validate_secrets(&mut report);
+        self.validate_profiles(&mut report);
+
+        for (index, site) in self.proxy.resources.sites.iter().enumerate() {
+            if site.site.trim().is_empty() {
+                report.error(
+                    format!("proxy.resources.sites.{}.site", index),
+                    "Site cannot be empty",
+                );
+            }
+        }
+
+        if let Err(err) = self.proxy.tls.server_config_builder() {
+            report.error("proxy.tls", err);
+        }
+        if self.proxy.tls.pregenerated_certificates > crate::cert::MAX_CACHED_CERTIFICATES {
+            report.warning(
+                "proxy.tls.pregenerated_certificates",
+                format!(
+                    "At most {} certificates are kept, only as many are pre-generated",
+                    crate::cert::MAX_CACHED_CERTIFICATES
+                ),
+            );
+        }
+        if let Some(key_log_file) = &self.proxy.tls.key_log_file {
+            report.warning(
+                "proxy.tls.key_log_file",
+                format!(
+                    "TLS secrets of intercepted connections are written to {}, unset it once done debugging",
+                    key_log_file.display()
+                ),
+            );
+        }
+
+        let geoip = &self.proxy.geoip;
+        for (key, path) in [
+            (
+                "proxy.geoip.country_database_path",
+                &geoip.country_database_path,
+            ),
+            ("proxy.geoip.asn_database_path", &geoip.asn_database_path),
+        ] {
+            if let Some(path) = path {
+                if let Err(err) = fs::metadata(path).await {
+                    report.error(key, format!("Unable to read {}: {}", path.display(), err));
+                }
+            }
+        }
+        for (key, countries) in [
+            ("proxy.geoip.blocked_countries", &geoip.blocked_countries),
+            ("proxy.geoip.allowed_countries", &geoip.allowed_countries),
+        ] {
+            for country in countries {
+                if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+                    report.error(
+                        key,
+                        format!("{} is not an ISO 3166-1 alpha-2 country code", country),
+                    );
+                }
+            }
+        }
+
+        for (key, url) in [
+            ("proxy.icap.reqmod_url", &self.proxy.icap.reqmod_url),
+            ("proxy.icap.respmod_url", &self.proxy.icap.respmod_url),
+        ] {
+            if let Some(url) = url {
+                if url.scheme() != "icap" || url.host_str().is_none() {
+                    report.error(key, format!("{} is not an icap:// URL", url));
+                }
+            }
+        }
+
+        let scripting = &self.proxy.scripting;
+        if let Some(path) = &scripting.path {
+            match fs::read_to_string(path).await {
+                Ok(source) => {
+                    if let Err(err) = compile_script(&scripting.engine(), &source) {
+                        report.error("proxy.scripting.path", err);
+                    }
+                }
+                Err(err) => {
+                    report.error(
+                        "proxy.scripting.path",
+                        format!("Unable to read {}: {}", path.display(), err),
+                    );
+                }
+            }
+        }
+
+        if let (Some(min_ttl), Some(max_ttl)) = (self.dns.min_ttl, self.dns.max_ttl) {
+            if min_ttl > max_ttl {
+                report.error("dns.min_ttl", "Minimum TTL cannot exceed the maximum TTL");
+            }
+        }
+        for (host, addresses) in &self.dns.hosts {
+            if addresses.is_empty() {
+                report.error(
+                    format!("dns.hosts.{}", host),
+                    "At least one address is required",
+                );
+            }
+        }
+        if let Some(servers) = &self.dns.upstream {
+            validate_dns_servers(&mut report, "dns.upstream", servers);
+        }
+        for (index, domain) in self.dns.domains.iter().enumerate() {
+            let key = format!("dns.domains.{}", index);
+            if domain.suffix().is_empty() {
+                report.error(format!("{}.domain", key), "Domain cannot be empty");
+            }
+            validate_dns_servers(&mut report, &key, &domain.servers);
+        }
+
+        let mut upstream_names = HashSet::new();
+        for (index, upstream) in self.egress.upstreams.iter().enumerate() {
+            let key = format!("egress.upstreams.{}", index);
+            if upstream.name.is_empty() {
+                report.error(format!("{}.name", key), "Upstream name cannot be empty");
+            } else if !upstream_names.insert(upstream.name.as_str()) {
+                report.error(
+                    format!("{}.name", key),
+                    format!("Duplicate upstream {}", upstream.name),
+                );
+            }
+            if let UpstreamKind::Socks5 { address, .. }
+            | UpstreamKind::Tor { address }
+            | UpstreamKind::Http { address, .. } = &upstream.kind
+            {
+                if !is_host_port(address) {
+                    report.error(
+                        format!("{}.address", key),
+                        format!("{} is not a host:port address", address),
+                    );
+                }
+            }
+            #[cfg(not(feature = "kerberos"))]
+            if let UpstreamKind::Http {
+                authentication: Some(ProxyAuthentication::Negotiate { .. }),
+                ..
+            } = &upstream.kind
+            {
+                report.error(
+                    format!("{}.authentication.scheme", key),
+                    "Negotiate authentication requires Privaxy to be built with the kerberos feature",
+                );
+            }
+        }
+        for (index, route) in self.egress.routes.iter().enumerate() {
+            if self.egress.upstream(&route.upstream).is_none() {
+                report.error(
+                    format!("egress.routes.{}.upstream", index),
+                    format!("Unknown upstream {}", route.upstream),
+                );
+            }
+        }
+
+        if self.mqtt.enabled {
+            if self.mqtt.host.is_empty() {
+                report.error("mqtt.host", "Broker host cannot be empty");
+            }
+            if self.mqtt.port == 0 {
+                report.error("mqtt.port", "Broker port cannot be 0");
+            }
+        }
+
+        if self.reports.hour > 23 {
+            report.error("reports.hour", "Hour must be between 0 and 23");
+        }
+        if let Some(smtp) = &self.reports.smtp {
+            if smtp.host.is_empty() {
+                report.error("reports.smtp.host", "Mail server host cannot be empty");
+            }
+            if smtp.port == 0 {
+                report.error("reports.smtp.port", "Mail server port cannot be 0");
+            }
+            if smtp.from.parse::<lettre::message::Mailbox>().is_err() {
+                report.error(
+                    "reports.smtp.from",
+                    format!("Invalid sender address {}", smtp.from),
+                );
+            }
+            if smtp.to.is_empty() {
+                report.error("reports.smtp.to", "At least one recipient is required");
+            }
+            for (index, to) in smtp.to.iter().enumerate() {
+                if to.parse::<lettre::message::Mailbox>().is_err() {
+                    report.error(
+                        format!("reports.smtp.to.{}", index),
+                        format!("Invalid recipient address {}", to),
+                    );
+                }
+            }
+        }
+        if self.reports.frequency != ReportFrequency::Never
+            && self.reports.smtp.is_none()
+            && self.reports.webhooks.is_empty()
+        {
+            report.warning(
+                "reports.frequency",
+                "Reports are scheduled but have neither a mail server nor a webhook to be sent to",
+            );
+        }
+
+        if let Err(err) = crate::blocker::parse_custom_scriptlets(&self.custom_scriptlets) {
+            report.error("custom_scriptlets", err);
+        }
+
+        if !self.filter_bundle.enabled && self.filter_bundle.url.is_some() {
+            report.warning(
+                "filter_bundle.url",
+                "The bundle is only downloaded when filter_bundle.enabled is set",
+            );
+        }
+
+        if self.filter_health.enabled && self.filter_health.failure_threshold == 0 {
+            report.warning(
+                "filter_health.failure_threshold",
+                "Lists are reported as unreachable as soon as a single download fails",
+            );
+        }
+
+        if self.privacy.log_retention_days > 0 {
+            if self.logging.output != LogOutput::File && !self.event_log.enabled {
+                report.warning(
+                    "privacy.log_retention_days",
+                    "Log retention only applies when logging to a file or keeping the event log",
+                );
+            } else if self.privacy.log_retention_days > self.logging.max_files as u64 {
+                report.warning(
+                    "privacy.log_retention_days",
+                    format!(
+                        "The log file is rotated daily, only {} days of logs are kept with logging.max_files",
+                        self.logging.max_files
+                    ),
+                );
+            }
+        }
+
+        if self.mdns.enabled {
+            let name = &self.mdns.name;
+            if name.is_empty()
+                || name.len() > 63
+                || name.starts_with('-')
+                || name.ends_with('-')
+                || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                report.error(
+                    "mdns.name",
+                    "Name must be up to 63 letters, digits or hyphens, not starting or ending with a hyphen",
+                );
+            }
+        }
+
+        if self.wpad.enabled {
+            if self.wpad.port == 0 {
+                report.error("wpad.port", "Port cannot be 0");
+            } else if self.wpad.port == self.network.web_port
+                || self.wpad.port == self.network.proxy_port
+            {
+                report.error(
+                    "wpad.port",
+                    "Port is already used by the proxy or the web server",
+                );
+            }
+        }
+
+        for (index, target) in self.metrics_push.targets.iter().enumerate() {
+            match target {
+                MetricsPushTarget::InfluxDb { url, .. } => validate_http_url(
+                    &mut report,
+                    format!("metrics_push.targets.{}.url", index),
+                    url,
+                ),
+                MetricsPushTarget::Graphite { address } => {
+                    if !is_host_port(address) {
+                        report.error(
+                            format!("metrics_push.targets.{}.address", index),
+                            format!("{} is not a host:port address", address),
+                        );
+                    }
+                }
+            }
+        }
+
+        if self.event_log.enabled && self.event_log.max_events == 0 {
+            report.error("event_log.max_events", "At least one event must be kept");
+        }
+
+        #[cfg(not(feature = "grpc"))]
+        if self.grpc.enabled {
+            report.error(
+                "grpc.enabled",
+                "The gRPC API requires Privaxy to be built with the grpc feature",
+            );
+        }
+
+        if self.logging.output == LogOutput::File {
+            let directory = Path::new(&self.logging.file_path).parent();
+            if let Some(directory) = directory.filter(|directory| !directory.as_os_str().is_empty())
+            {
+                if fs::metadata(directory).await.is_err() {
+                    report.warning(
+                        "logging.file_path",
+                        format!("Directory {} does not exist", directory.display()),
+                    );
+                }
+            }
+        }
+
+        report
+    }
+
+    fn validate_secrets(&self, report: &mut ValidationReport) {
+        for (index, user) in self.proxy.authentication.users.iter().enumerate() {
+            validate_secret(
+                report,
+                format!("proxy.authentication.users.{}.password", index),
+                Some(&user.password),
+            );
+        }
+        for (index, upstream) in self.egress.upstreams.iter().enumerate() {
+            if let UpstreamKind::Socks5 { password, .. } = &upstream.kind {
+                validate_secret(
+                    report,
+                    format!("egress.upstreams.{}.password", index),
+                    password.as_deref(),
+                );
+            }
+            if let UpstreamKind::Http {
+                authentication:
+                    Some(
+                        ProxyAuthentication::Basic { password, .. }
+                        | ProxyAuthentication::Ntlm { password, .. },
+                    ),
+                ..
+            } = &upstream.kind
+            {
+                validate_secret(
+                    report,
+                    format!("egress.upstreams.{}.authentication.password", index),
+                    Some(password.as_str()),
+                );
+            }
+        }
+        for (index, target) in self.metrics_push.targets.iter().enumerate() {
+            if let MetricsPushTarget::InfluxDb { token, .. } = target {
+                validate_secret(
+                    report,
+                    format!("metrics_push.targets.{}.token", index),
+                    token.as_deref(),
+                );
+            }
+        }
+        validate_secret(
+            report,
+            "mqtt.password".to_string(),
+            self.mqtt.password.as_deref(),
+        );
+        validate_secret(report, "grpc.token".to_string(), self.grpc.token.as_deref());
+        if let Some(smtp) = &self.reports.smtp {
+            validate_secret(
+                report,
+                "reports.smtp.password".to_string(),
+                smtp.password.as_deref(),
+            );
+        }
+        validate_secret(
+            report,
+            "proxy.threat_protection.safe_browsing_api_key".to_string(),
+            self.proxy
+                .threat_protection
+                .safe_browsing_api_key
+                .as_deref(),
+        );
+    }
+
+    fn validate_profiles(&self, report: &mut ValidationReport) {
+        let profiles = &self.proxy.profiles;
+        let is_known = |name: &str| name == DEFAULT_PROFILE || profiles.get(name).is_some();
+
+        let mut names = HashSet::new();
+        for (index, profile) in profiles.profiles.iter().enumerate() {
+            let key = format!("proxy.profiles.profiles.{}", index);
+            if profile.name.is_empty() {
+                report.error(format!("{}.name", key), "Profile name cannot be empty");
+            } else if profile.name == DEFAULT_PROFILE {
+                report.error(
+                    format!("{}.name", key),
+                    format!("{} is the name of the top-level filters", DEFAULT_PROFILE),
+                );
+            } else if !names.insert(profile.name.as_str()) {
+                report.error(
+                    format!("{}.name", key),
+                    format!("Duplicate profile {}", profile.name),
+                );
+            }
+
+            for file_name in &profile.filters {
+                if !self
+                    .filters
+                    .iter()
+                    .any(|filter| &filter.file_name == file_name)
+                {
+                    report.error(
+                        format!("{}.filters", key),
+                        format!("No configured filter has the file name {}", file_name),
+                    );
+                }
+            }
+        }
+
+        if !is_known(&profiles.active) {
+            report.error(
+                "proxy.profiles.active",
+                format!("Unknown profile {}", profiles.active),
+            );
+        }
+        for (index, client) in profiles.clients.iter().enumerate() {
+            if !is_known(&client.profile) {
+                report.error(
+                    format!("proxy.profiles.clients.{}.profile", index),
+                    format!("Unknown profile {}", client.profile),
+                );
+            }
+        }
+    }
+
+    async fn validate_network(&self, report: &mut ValidationReport) {
+        let network = &self.network;
+
+        if let Err(super::ConfigurationError::NetworkConfigError(err)) = network.validate().await {
+            let key = match err {
+                NetworkConfigError::BindAddressError(_) => "network.bind_addr",
+                NetworkConfigError::ProxyPortError(_) => "network.proxy_port",
+                NetworkConfigError::WebPortError(_) => "network.web_port",
+                _ => "network",
+            };
+            report.error(key, err.to_string());
+        }
+
+        if let Some(listen_url) = &network.listen_url {
+            if let Err(err) = Url::parse(listen_url) {
+                report.error("network.listen_url", format!("Invalid URL: {}", err));
+            }
+        }
+
+        if !network.tls {
+            return;
+        }
+
+        // Missing files are generated from the CA on startup, existing ones must be usable.
+        if let Some(path) = &network.tls_cert_path {
+            if let Ok(pem) = fs::read(path).await {
+                if let Err(err) = X509::from_pem(&pem) {
+                    report.error(
+                        "network.tls_cert_path",
+                        format!("Invalid PEM certificate in {}: {}", path, err),
+                    );
+                }
+            }
+        }
+        if let Some(path) = &network.tls_key_path {
+            if let Ok(pem) = fs::read(path).await {
+                if let Err(err) = PKey::private_key_from_pem(&pem) {
+                    report.error(
+                        "network.tls_key_path",
+                        format!("Invalid PEM private key in {}: {}", path, err),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Reads PEM data from a file when `path` is set, otherwise from `inline`.
+async fn read_pem(
+    report: &mut ValidationReport,
+    path: &Option<String>,
+    path_key: &str,
+    inline: &Option<String>,
+    inline_key: &str,
+) -> Option<(Vec<u8>, String)> {
+    match (path, inline) {
+        (Some(path), _) => match fs::read(path).await {
+            Ok(pem) => Some((pem, format!("ca.{}", path_key))),
+            Err(err) => {
+                report.error(
+                    format!("ca.{}", path_key),
+                    format!("Unable to read {}: {}", path, err),
+                );
+                None
+            }
+        },
+        (None, Some(pem)) => match resolve_secret(pem) {
+            Ok(pem) => Some((pem.as_bytes().to_vec(), format!("ca.{}", inline_key))),
+            Err(err) => {
+                report.error(format!("ca.{}", inline_key), err.to_string());
+                None
+            }
+        },
+        (None, None) => {
+            report.error(
+                "ca",
+                format!("Either {} or {} must be set", inline_key, path_key),
+            );
+            None
+        }
+    }
+}
+
+async fn validate_ca(ca: &Ca, report: &mut ValidationReport) {
+    let certificate = match read_pem(
+        report,
+        &ca.ca_certificate_path,
+        "ca_certificate_path",
+        &ca.ca_certificate,
+        "ca_certificate",
+    )
+    .await
+    {
+        Some((pem, key)) => match X509::from_pem(&pem) {
+            Ok(certificate) => Some(certificate),
+            Err(err) => {
+                report.error(key, format!("Invalid PEM certificate: {}", err));
+                None
+            }
+        },
+        None => None,
+    };
+
+    let private_key = match read_pem(
+        report,
+        &ca.ca_private_key_path,
+        "ca_private_key_path",
+        &ca.ca_private_key,
+        "ca_private_key",
+    )
+    .await
+    {
+        Some((pem, key)) => match PKey::private_key_from_pem(&pem) {
+            Ok(private_key) => Some(private_key),
+            Err(err) => {
+                report.error(key, format!("Invalid PEM private key: {}", err));
+                None
+            }
+        },
+        None => None,
+    };
+
+    if let Some(certificate) = &certificate {
+        if let Ok(now) = Asn1Time::days_from_now(0) {
+            if certificate.not_after() < now {
+                report.error(
+                    "ca",
+                    format!("Certificate expired on {}", certificate.not_after()),
+                );
+            }
+        }
+    }
+
+    if let (Some(certificate), Some(private_key)) = (certificate, private_key) {
+        let matches = certificate
+            .public_key()
+            .is_ok_and(|public_key| private_key.public_eq(&public_key));
+        if !matches {
+            report.error("ca", "Private key does not match the certificate");
+        }
+    }
+}
+
+fn is_host_port(address: &str) -> bool {
+    address
+        .rsplit_once(':')
+        .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+}
+
+fn validate_dns_servers(report: &mut ValidationReport, key: &str, servers: &DnsServers) {
+    if servers.addresses.is_empty() {
+        report.error(
+            format!("{}.addresses", key),
+            "At least one address is required",
+        );
+    }
+    if servers.protocol != DnsProtocol::Udp
+        && servers.tls_name.as_deref().unwrap_or_default().is_empty()
+    {
+        report.error(
+            format!("{}.tls_name", key),
+            "A TLS name is required over TLS and HTTPS",
+        );
+    }
+}
+
+fn validate_http_url(report: &mut ValidationReport, key: String, url: &Url) {
+    if !matches!(url.scheme(), "http" | "https") {
+        report.error(
+            key,
+            format!("Only http and https URLs are supported, not {}", url),
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use wildmatch::WildMatch;
+
+/// Removes the WebRTC constructors, pages can't open peer connections at all.
+const DISABLE_SCRIPT: &str = r#"(function () {
+    ["RTCPeerConnection", "webkitRTCPeerConnection", "mozRTCPeerConnection", "RTCDataChannel"].forEach(function (name) {
+        try {
+            Object.defineProperty(window, name, { value: undefined, writable: false, configurable: false });
+        } catch (e) {}
+    });
+})();"#;
+
+/// Only lets peer connections go through TURN relays, which keeps local and public addresses
+/// out of ICE candidates.
+const RELAY_ONLY_SCRIPT: &str = r#"(function () {
+    var NativePeerConnection = window.RTCPeerConnection || window.webkitRTCPeerConnection;
+    if (!NativePeerConnection) {
+        return;
+    }
+    var relayOnly = function (configuration) {
+        return Object.assign({}, configuration, { iceTransportPolicy: "relay" });
+    };
+    var PeerConnection = function (configuration, constraints) {
+        return new NativePeerConnection(relayOnly(configuration), constraints);
+    };
+    PeerConnection.prototype = NativePeerConnection.prototype;
+    if (NativePeerConnection.generateCertificate) {
+        PeerConnection.generateCertificate = NativePeerConnection.generateCertificate.bind(NativePeerConnection);
+    }
+    var setConfiguration = NativePeerConnection.prototype.setConfiguration;
+    if (setConfiguration) {
+        NativePeerConnection.prototype.setConfiguration = function (configuration) {
+            return setConfiguration.call(this, relayOnly(configuration));
+        };
+    }
+    window.RTCPeerConnection = PeerConnection;
+    if (window.webkitRTCPeerConnection) {
+        window.webkitRTCPeerConnection = PeerConnection;
+    }
+})();"#;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebRtcProtectionMode {
+    /// Pages can't use WebRTC.
+    #[default]
+    Disable,
+    /// Peer connections only go through TURN relays, calls keep working where the site
+    /// provides relays.
+    RelayOnly,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// WebRTC connections don't go through the proxy and expose the addresses of the client.
+/// A script restricting them is injected into proxied pages, pages whose Content Security
+/// Policy forbids inline scripts are left unprotected.
+pub struct WebRtcProtectionConfig {
+    pub enabled: bool,
+    pub mode: WebRtcProtectionMode,
+    /// Sites on which WebRTC is left alone, such as video conferencing services.
+    /// Wildcards are allowed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exceptions: Vec<String>,
+}
+
+impl WebRtcProtectionConfig {
+    /// Script injected into pages of `host`, if any.
+    pub(crate) fn script_for(&self, host: &str) -> Option<&'static str> {
+        let host = host.to_lowercase();
+        let is_exception = self
+            .exceptions
+            .iter()
+            .any(|pattern| WildMatch::new(&pattern.to_lowercase()).matches(&host));
+
+        if !self.enabled || is_exception {
+            return None;
+        }
+
+        Some(match self.mode {
+            WebRtcProtectionMode::Disable => DISABLE_SCRIPT,
+            WebRtcProtectionMode::RelayOnly => RELAY_ONLY_SCRIPT,
+        })
+    }
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_PORT: u16 = 80;
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+/// Proxy auto-config, served at `/proxy.pac` and `/wpad.dat` by the web server and optionally on
+/// the port devices detecting proxies automatically look for it
+pub struct WpadConfig {
+    /// Serve `wpad.dat` on `port`, for devices resolving `wpad.<domain>` to Privaxy. Requires a
+    /// DNS or DHCP server pointing them to it.
+    pub enabled: bool,
+    pub port: u16,
+    /// Host name or address devices reach the proxy at, the one they requested the file from when
+    /// unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_host: Option<String>,
+}
+
+impl Default for WpadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_PORT,
+            proxy_host: None,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Limits on accepted connections, so that a misbehaving client on the network can't
+//! exhaust the file descriptors or the memory of Privaxy.
+use crate::configuration::{IpNetwork, ListenerLimits};
+use crate::proxy::proxy_protocol::ClientStream;
+use crate::statistics::{ConnectionRejection, Statistics};
+use futures_util::future::ready;
+use futures_util::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// Addresses tracked for rate limiting before those outside of their window are forgotten.
+const MAX_TRACKED_ADDRESSES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Listener {
+    Proxy,
+    Web,
+}
+
+#[derive(Clone)]
+pub(crate) struct ConnectionLimiter {
+    limits: ListenerLimits,
+    listener: Listener,
+    statistics: Statistics,
+    /// Addresses allowed to connect, every address when empty.
+    allowed_networks: Arc<Vec<IpNetwork>>,
+    active_connections: Arc<AtomicUsize>,
+    /// Start of the current window and connections opened since, by client address.
+    recent_connections: Arc<Mutex<HashMap<IpAddr, (Instant, u32)>>>,
+}
+
+impl ConnectionLimiter {
+    pub(crate) fn new(limits: ListenerLimits, listener: Listener, statistics: Statistics) -> Self {
+        Self {
+            limits,
+            listener,
+            statistics,
+            allowed_networks: Arc::new(Vec::new()),
+            active_connections: Arc::new(AtomicUsize::new(0)),
+            recent_connections: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Refuses connections from addresses outside of `allowed_networks`, unless it is empty.
+    pub(crate) fn with_allowed_networks(mut self, allowed_networks: Vec<IpNetwork>) -> Self {
+        self.allowed_networks = Arc::new(allowed_networks);
+        self
+    }
+
+    /// A permit to keep the connection of `client_address` open, `None` when it is refused.
+    fn admit(&self, client_address: IpAddr) -> Option<ConnectionPermit> {
+        if !self.is_allowed(client_address) {
+            log::debug!(
+                "Refused connection from {}: address not allowed",
+                client_address
+            );
+            return None;
+        }
+
+        if !self.is_within_rate(client_address) {
+            log::debug!(
+                "Refused connection from {}: too many connections per minute",
+                client_address
+            );
+            self.statistics
+                .increment_rejected_connections(self.listener, ConnectionRejection::RateLimit);
+            return None;
+        }
+
+        let active_connections = self.active_connections.fetch_add(1, Ordering::SeqCst);
+        let permit = ConnectionPermit(self.active_connections.clone());
+        if self.limits.max_connections > 0 && active_connections >= self.limits.max_connections {
+            log::debug!(
+                "Refused connection from {}: {} connections are open",
+                client_address,
+                active_connections
+            );
+            self.statistics.increment_rejected_connections(
+                self.listener,
+                ConnectionRejection::ConnectionLimit,
+            );
+            return None;
+        }
+
+        Some(permit)
+    }
+
+    fn is_allowed(&self, client_address: IpAddr) -> bool {
+        // Clients connecting over IPv6 to a dual stack socket have mapped IPv4 addresses.
+        let client_address = match client_address {
+            IpAddr::V6(address) => address.to_ipv4_mapped().map_or(client_address, IpAddr::V4),
+            IpAddr::V4(_) => client_address,
+        };
+
+        self.allowed_networks.is_empty()
+            || self
+                .allowed_networks
+                .iter()
+                .any(|network| network.contains(&client_address))
+    }
+
+    fn is_within_rate(&self, client_address: IpAddr) -> bool {
+        let max_connections = self.limits.max_connections_per_minute_per_ip;
+        if max_connections == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut recent_connections = self.recent_connections.lock().unwrap();
+        if recent_connections.len() >= MAX_TRACKED_ADDRESSES {
+            recent_connections
+                .retain(|_address, (window_start, _count)| now - *window_start < RATE_WINDOW);
+        }
+
+        let (window_start, count) = recent_connections.entry(client_address).or_insert((now, 0));
+        if now - *window_start >= RATE_WINDOW {
+            *window_start = now;
+            *count = 0;
+        }
+        *count = count.saturating_add(1);
+
+        *count <= max_connections
+    }
+}
+
+/// Counts a connection as open until dropped, along with the connection.
+pub(crate) struct ConnectionPermit(Arc<AtomicUsize>);
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Drops the connections refused by `limiter`.
+pub(crate) fn limit<S>(
+    connections: S,
+    limiter: ConnectionLimiter,
+) -> impl Stream<Item = io::Result<ClientStream>> + Send
+where
+    S: Stream<Item = io::Result<ClientStream>> + Send,
+{
+    connections.filter_map(move |connection| {
+        let connection = match connection {
+            Ok(connection) => limiter
+                .admit(connection.client_address().ip())
+                .map(|permit| Ok(connection.with_permit(permit))),
+            Err(err) => Some(Err(err)),
+        };
+
+        ready(connection)
+    })
+}
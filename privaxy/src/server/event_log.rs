@@ -0,0 +1,268 @@
+//! Keeps the latest events of the requests feed on disk, so that they survive restarts and are
+//! replayed to clients connecting to the feed after they happened.
+//!
+//! Events are appended to a file, one JSON document per line, which is rotated once it holds
+//! `max_events` of them. Only the current and the previous file are kept. Events older than
+//! `privacy.log_retention_days` are dropped, from memory and from both files.
+use crate::configuration::{self, EventLogConfig};
+use crate::retention::PRUNE_INTERVAL;
+use crate::web_gui::events::Event;
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::Notify;
+
+const EVENTS_FILE_NAME: &str = "events.jsonl";
+const ROTATED_EVENTS_FILE_NAME: &str = "events.1.jsonl";
+
+/// Latest events, oldest first. Empty when the log is disabled.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EventLog(Arc<Mutex<VecDeque<Event>>>);
+
+impl EventLog {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events to replay to a new client of the feed, oldest first.
+    pub(crate) fn recent(&self) -> Vec<Event> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn replace(&self, events: VecDeque<Event>) {
+        *self.0.lock().unwrap() = events;
+    }
+
+    fn push(&self, event: Event, max_events: usize) {
+        let mut events = self.0.lock().unwrap();
+
+        while events.len() >= max_events {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// Drops events which happened before `cutoff`.
+    fn prune(&self, cutoff: DateTime<Utc>) {
+        self.0.lock().unwrap().retain(|event| event.now >= cutoff);
+    }
+}
+
+/// File events are appended to, rotated every `max_events`.
+struct EventWriter {
+    directory: PathBuf,
+    file: BufWriter<File>,
+    /// Events in the current file.
+    written: usize,
+    max_events: usize,
+}
+
+impl EventWriter {
+    async fn open(directory: &Path, written: usize, max_events: usize) -> std::io::Result<Self> {
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            file: BufWriter::new(open_append(&directory.join(EVENTS_FILE_NAME)).await?),
+            written,
+            max_events,
+        })
+    }
+
+    async fn append(&mut self, event: &Event) -> std::io::Result<()> {
+        if self.written >= self.max_events {
+            self.file.flush().await?;
+            fs::rename(
+                self.directory.join(EVENTS_FILE_NAME),
+                self.directory.join(ROTATED_EVENTS_FILE_NAME),
+            )
+            .await?;
+            self.file = BufWriter::new(open_append(&self.directory.join(EVENTS_FILE_NAME)).await?);
+            self.written = 0;
+        }
+
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+        self.file.write_all(&line).await?;
+        self.written += 1;
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush().await
+    }
+
+    /// Drops the events of both files which happened before `cutoff`.
+    async fn prune(&mut self, cutoff: DateTime<Utc>) -> std::io::Result<()> {
+        self.file.flush().await?;
+
+        prune_events_file(&self.directory.join(ROTATED_EVENTS_FILE_NAME), cutoff).await?;
+        self.written = prune_events_file(&self.directory.join(EVENTS_FILE_NAME), cutoff).await?;
+        // The file may have been replaced.
+        self.file = BufWriter::new(open_append(&self.directory.join(EVENTS_FILE_NAME)).await?);
+
+        Ok(())
+    }
+}
+
+async fn open_append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+}
+
+/// Events of a file, skipping lines which can't be read such as one cut short by a crash.
+async fn read_events_file(path: &Path) -> Vec<Event> {
+    match fs::read_to_string(path).await {
+        Ok(contents) => contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => {
+            log::warn!("Unable to read {}: {}", path.display(), err);
+            Vec::new()
+        }
+    }
+}
+
+/// Rewrites a file without the events which happened before `cutoff`, removing it when none is
+/// left. Returns how many events it holds.
+async fn prune_events_file(path: &Path, cutoff: DateTime<Utc>) -> std::io::Result<usize> {
+    let events = read_events_file(path).await;
+    let kept = events
+        .iter()
+        .filter(|event| event.now >= cutoff)
+        .collect::<Vec<_>>();
+
+    if kept.len() == events.len() {
+        return Ok(kept.len());
+    }
+
+    if kept.is_empty() {
+        fs::remove_file(path).await?;
+        return Ok(0);
+    }
+
+    let mut contents = Vec::new();
+    for event in &kept {
+        contents.extend(serde_json::to_vec(event)?);
+        contents.push(b'\n');
+    }
+    // Replaced at once, so that a crash doesn't lose the events kept.
+    let pruned_path = path.with_extension("jsonl.tmp");
+    fs::write(&pruned_path, contents).await?;
+    fs::rename(&pruned_path, path).await?;
+
+    Ok(kept.len())
+}
+
+/// Oldest time events may have happened at to be kept, `None` when they are kept regardless.
+fn cutoff(retention: Option<Duration>) -> Option<DateTime<Utc>> {
+    let retention = chrono::Duration::from_std(retention?).ok()?;
+
+    Utc::now().checked_sub_signed(retention)
+}
+
+/// Records events into `event_log` and on disk until a configuration reload is notified. The
+/// events of the previous run are restored first.
+pub(crate) async fn run(
+    configuration: EventLogConfig,
+    retention: Option<Duration>,
+    event_log: EventLog,
+    events_sender: broadcast::Sender<Event>,
+    notify_reload: Arc<Notify>,
+) {
+    let directory = match configuration::get_base_directory() {
+        Ok(directory) if configuration.enabled && configuration.max_events > 0 => directory,
+        result => {
+            if let (true, Err(err)) = (configuration.enabled, result) {
+                log::warn!("Unable to keep events on disk: {}", err);
+            }
+
+            event_log.replace(VecDeque::new());
+            notify_reload.notified().await;
+            return;
+        }
+    };
+    let max_events = configuration.max_events;
+
+    // Subscribed before reading the files, so that no event goes missing meanwhile.
+    let mut events_receiver = events_sender.subscribe();
+
+    let current = read_events_file(&directory.join(EVENTS_FILE_NAME)).await;
+    let written = current.len();
+    let mut events = read_events_file(&directory.join(ROTATED_EVENTS_FILE_NAME))
+        .await
+        .into_iter()
+        .chain(current)
+        .collect::<VecDeque<_>>();
+    while events.len() > max_events {
+        events.pop_front();
+    }
+    if let Some(cutoff) = cutoff(retention) {
+        events.retain(|event| event.now >= cutoff);
+    }
+    log::debug!("Restored {} events", events.len());
+    event_log.replace(events);
+
+    let record = async {
+        let mut writer = match EventWriter::open(&directory, written, max_events).await {
+            Ok(writer) => writer,
+            Err(err) => {
+                log::warn!("Unable to open the event log: {}", err);
+                // Tried again on the next reload.
+                return std::future::pending().await;
+            }
+        };
+
+        // Ticks right away, so that files restored from disk are pruned as well.
+        let mut prune_interval = tokio::time::interval(PRUNE_INTERVAL);
+
+        loop {
+            let received = tokio::select! {
+                received = events_receiver.recv() => received,
+                _ = prune_interval.tick(), if retention.is_some() => {
+                    if let Some(cutoff) = cutoff(retention) {
+                        event_log.prune(cutoff);
+                        if let Err(err) = writer.prune(cutoff).await {
+                            log::warn!("Unable to prune the event log: {}", err);
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            match received {
+                Ok(event) => {
+                    if let Err(err) = writer.append(&event).await {
+                        log::warn!("Unable to write to the event log: {}", err);
+                    }
+                    event_log.push(event, max_events);
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    log::debug!("{} events were not written to the event log", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+
+            // Written in batches while events keep coming.
+            if events_receiver.is_empty() {
+                if let Err(err) = writer.flush().await {
+                    log::warn!("Unable to write to the event log: {}", err);
+                }
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = record => {}
+        _ = notify_reload.notified() => {}
+    }
+}
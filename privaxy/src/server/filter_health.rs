@@ -0,0 +1,248 @@
+//! Checks the filter lists in use on a schedule, so that lists which stopped being served, no
+//! longer contain any rule Privaxy understands or grew out of proportion are noticed rather
+//! than silently blocking less.
+use crate::configuration::{Configuration, Filter, FilterHealthConfig};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+use utoipa::ToSchema;
+
+/// Lists are downloaded when Privaxy starts, there is no point in checking them right away.
+const FIRST_CHECK_DELAY: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+/// Outcome of the checks of a filter list
+pub struct FilterHealth {
+    pub file_name: String,
+    pub title: String,
+    /// Downloads which failed since the last successful one.
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    /// Rules parsed from the last successful download.
+    pub rules: Option<usize>,
+    /// Size of the last successful download.
+    pub size_bytes: Option<u64>,
+    /// Unix timestamp of the last check.
+    pub checked_at: u64,
+    /// Problems found, the list is healthy when empty.
+    pub warnings: Vec<String>,
+}
+
+impl FilterHealth {
+    fn update_warnings(&mut self, configuration: &FilterHealthConfig) {
+        self.warnings.clear();
+
+        if self.consecutive_failures >= configuration.failure_threshold.max(1) {
+            self.warnings.push(format!(
+                "Download failed {} times in a row: {}",
+                self.consecutive_failures,
+                self.last_error.as_deref().unwrap_or_default()
+            ));
+        }
+        if self.rules == Some(0) {
+            self.warnings
+                .push("No rules could be parsed from the list".to_string());
+        }
+        if let (Some(size_bytes), Some(max_size_bytes)) =
+            (self.size_bytes, configuration.max_size_bytes())
+        {
+            if size_bytes > max_size_bytes {
+                self.warnings.push(format!(
+                    "The list is {} KB, over the budget of {} KB",
+                    size_bytes / 1024,
+                    configuration.max_size_kb
+                ));
+            }
+        }
+    }
+}
+
+/// Latest outcome of the checks of every list in use, by file name.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FilterHealthStore {
+    lists: Arc<RwLock<HashMap<String, FilterHealth>>>,
+    /// Kept across reloads so that the schedule isn't reset by every change of the settings.
+    last_check: Arc<Mutex<Option<Instant>>>,
+}
+
+impl FilterHealthStore {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lists in use, sorted by title.
+    pub(crate) fn list(&self) -> Vec<FilterHealth> {
+        let mut lists = self
+            .lists
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        lists.sort_unstable_by(|a, b| a.title.cmp(&b.title));
+        lists
+    }
+
+    /// Lists with at least one warning, sorted by title.
+    pub(crate) fn unhealthy(&self) -> Vec<FilterHealth> {
+        let mut lists = self.list();
+        lists.retain(|list| !list.warnings.is_empty());
+        lists
+    }
+
+    fn clear(&self) {
+        self.lists.write().unwrap().clear();
+    }
+
+    fn next_check(&self, interval: Duration) -> Instant {
+        match *self.last_check.lock().unwrap() {
+            Some(last_check) => last_check + interval,
+            None => Instant::now() + FIRST_CHECK_DELAY,
+        }
+    }
+
+    /// Replaces the outcomes, lists which are no longer in use are forgotten.
+    fn replace(&self, lists: HashMap<String, FilterHealth>) {
+        *self.lists.write().unwrap() = lists;
+        self.postpone();
+    }
+
+    /// Schedules the next check an interval from now.
+    fn postpone(&self) {
+        *self.last_check.lock().unwrap() = Some(Instant::now());
+    }
+
+    fn previous(&self, file_name: &str) -> Option<FilterHealth> {
+        self.lists.read().unwrap().get(file_name).cloned()
+    }
+}
+
+/// Rules of a list the adblock engine is able to parse, comments excluded.
+fn count_rules(contents: &str) -> usize {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('!') && !line.starts_with('['))
+        .filter(|line| {
+            adblock::lists::parse_filter(line, false, adblock::lists::ParseOptions::default())
+                .is_ok()
+        })
+        .count()
+}
+
+async fn download(
+    filter: &Filter,
+    http_client: &reqwest::Client,
+    timeout: Duration,
+) -> Result<String, reqwest::Error> {
+    http_client
+        .get(filter.url.as_str())
+        .timeout(timeout)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await
+}
+
+async fn check(
+    filter: &Filter,
+    previous: Option<FilterHealth>,
+    configuration: &FilterHealthConfig,
+    http_client: &reqwest::Client,
+) -> FilterHealth {
+    let mut health = previous.unwrap_or_default();
+    health.file_name = filter.file_name.clone();
+    health.title = filter.title.clone();
+    health.checked_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    match download(filter, http_client, configuration.timeout()).await {
+        Ok(contents) => {
+            health.consecutive_failures = 0;
+            health.last_error = None;
+            health.size_bytes = Some(contents.len() as u64);
+            health.rules = tokio::task::spawn_blocking(move || count_rules(&contents))
+                .await
+                .ok();
+        }
+        Err(err) => {
+            log::debug!("Health check of filter {} failed: {}", filter.title, err);
+
+            health.consecutive_failures += 1;
+            health.last_error = Some(err.to_string());
+        }
+    }
+
+    health.update_warnings(configuration);
+    for warning in &health.warnings {
+        log::warn!("Filter {}: {}", filter.title, warning);
+    }
+
+    health
+}
+
+/// Checks the lists in use every `filter_health.interval_minutes` until a configuration reload
+/// is notified. Lists are enabled and disabled without a reload, so the configuration is read
+/// again before every check.
+pub(crate) async fn run(
+    configuration: FilterHealthConfig,
+    http_client: reqwest::Client,
+    store: FilterHealthStore,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    notify_reload: Arc<Notify>,
+) {
+    if !configuration.enabled {
+        store.clear();
+        notify_reload.notified().await;
+        return;
+    }
+
+    let checks = async {
+        loop {
+            let next_check = store.next_check(configuration.interval());
+            tokio::time::sleep_until(next_check.into()).await;
+
+            let guard = configuration_save_lock.lock().await;
+            let current_configuration = Configuration::read_from_home().await;
+            drop(guard);
+            let current_configuration = match current_configuration {
+                Ok(current_configuration) => current_configuration,
+                Err(err) => {
+                    log::warn!("Unable to read the configuration to check filters: {err}");
+                    store.postpone();
+                    continue;
+                }
+            };
+
+            let checks = current_configuration
+                .filters_in_use()
+                .into_iter()
+                .map(|filter| {
+                    check(
+                        filter,
+                        store.previous(&filter.file_name),
+                        &configuration,
+                        &http_client,
+                    )
+                });
+            let lists = futures::future::join_all(checks)
+                .await
+                .into_iter()
+                .map(|health| (health.file_name.clone(), health))
+                .collect();
+            store.replace(lists);
+
+            log::debug!("Checked filter lists health");
+        }
+    };
+
+    tokio::select! {
+        _ = checks => {},
+        _ = notify_reload.notified() => {},
+    }
+}
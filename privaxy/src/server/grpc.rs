@@ -0,0 +1,313 @@
+//! Admin operations served over gRPC, for integrations which would rather use typed clients and
+//! streams than the REST API and its WebSockets. The service is defined in `proto/admin.proto`.
+use crate::blocker::BlockingDisabledStore;
+use crate::configuration::{resolve_secret, Configuration, GrpcConfig};
+use crate::proxy::authentication::constant_time_eq;
+use crate::proxy::exclusions::LocalExclusionStore;
+use crate::statistics::{SerializableStatistics, Statistics};
+use crate::web_gui::exclusions::is_valid_host;
+use futures::Stream;
+use std::borrow::Cow;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::Notify;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+mod proto {
+    tonic::include_proto!("privaxy.admin.v1");
+}
+
+use proto::admin_server::{Admin, AdminServer};
+
+const DEFAULT_STATISTICS_INTERVAL: Duration = Duration::from_millis(500);
+
+type StatisticsStream = Pin<Box<dyn Stream<Item = Result<proto::Statistics, Status>> + Send>>;
+
+#[derive(Clone)]
+pub(crate) struct AdminService {
+    pub(crate) statistics: Statistics,
+    pub(crate) blocking_disabled_store: BlockingDisabledStore,
+    pub(crate) configuration_updater_sender: Sender<Configuration>,
+    pub(crate) configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    pub(crate) local_exclusions_store: LocalExclusionStore,
+    pub(crate) http_client: reqwest::Client,
+}
+
+fn internal_error(err: impl std::fmt::Display) -> Status {
+    log::error!("gRPC request failed: {}", err);
+    Status::internal(err.to_string())
+}
+
+fn counts(counts: Vec<(String, u64)>) -> Vec<proto::Count> {
+    counts
+        .into_iter()
+        .map(|(name, count)| proto::Count { name, count })
+        .collect()
+}
+
+impl From<SerializableStatistics> for proto::Statistics {
+    fn from(statistics: SerializableStatistics) -> Self {
+        let blocked_by_category = statistics.blocked_by_category;
+        let upstream_errors = statistics.upstream_errors;
+
+        Self {
+            proxied_requests: statistics.proxied_requests,
+            blocked_requests: statistics.blocked_requests,
+            modified_responses: statistics.modified_responses,
+            threats_blocked: statistics.threats_blocked,
+            blocked_by_category: Some(proto::BlockedByCategory {
+                ads: blocked_by_category.ads,
+                trackers: blocked_by_category.trackers,
+                malware: blocked_by_category.malware,
+                custom: blocked_by_category.custom,
+            }),
+            blocked_by_list: counts(statistics.blocked_by_list),
+            upstream_errors: Some(proto::UpstreamErrors {
+                dns: upstream_errors.dns,
+                tls: upstream_errors.tls,
+                timeout: upstream_errors.timeout,
+                connection_refused: upstream_errors.connection_refused,
+                other: upstream_errors.other,
+            }),
+            proxied_bytes: statistics.proxied_bytes,
+            tunneled_connections: statistics.tunneled_connections,
+            tunneled_bytes: statistics.tunneled_bytes,
+            top_blocked_domains: counts(statistics.top_blocked_domains),
+            top_blocked_paths: counts(statistics.top_blocked_paths),
+            top_clients: counts(statistics.top_clients),
+        }
+    }
+}
+
+/// Counters, then every change noticed when checking them each `interval`.
+fn statistics_stream(statistics: Statistics, interval: Duration) -> StatisticsStream {
+    Box::pin(futures::stream::unfold(
+        None,
+        move |last_message: Option<proto::Statistics>| {
+            let statistics = statistics.clone();
+
+            async move {
+                loop {
+                    if last_message.is_some() {
+                        tokio::time::sleep(interval).await;
+                    }
+
+                    let message = proto::Statistics::from(statistics.get_serialized());
+                    if last_message.as_ref() != Some(&message) {
+                        return Some((Ok(message.clone()), Some(message)));
+                    }
+                }
+            }
+        },
+    ))
+}
+
+#[tonic::async_trait]
+impl Admin for AdminService {
+    type StreamStatisticsStream = StatisticsStream;
+
+    async fn stream_statistics(
+        &self,
+        request: Request<proto::StreamStatisticsRequest>,
+    ) -> Result<Response<Self::StreamStatisticsStream>, Status> {
+        let interval = match request.into_inner().interval_ms {
+            0 => DEFAULT_STATISTICS_INTERVAL,
+            interval_ms => Duration::from_millis(interval_ms.into()),
+        };
+
+        Ok(Response::new(statistics_stream(
+            self.statistics.clone(),
+            interval,
+        )))
+    }
+
+    async fn update_filters(
+        &self,
+        _request: Request<proto::UpdateFiltersRequest>,
+    ) -> Result<Response<proto::UpdateFiltersResponse>, Status> {
+        // Held until the configuration is sent, so that changes saved during the download
+        // aren't overwritten by the configuration read before it.
+        let _guard = self.configuration_save_lock.lock().await;
+
+        let mut configuration = Configuration::read_from_home()
+            .await
+            .map_err(internal_error)?;
+
+        configuration
+            .update_filters(self.http_client.clone())
+            .await
+            .map_err(internal_error)?;
+
+        // The updater reads the downloaded filters back when applying the configuration.
+        self.configuration_updater_sender
+            .send(configuration)
+            .await
+            .map_err(internal_error)?;
+
+        log::info!("Updated filters on gRPC request");
+
+        Ok(Response::new(proto::UpdateFiltersResponse {}))
+    }
+
+    async fn list_exclusions(
+        &self,
+        _request: Request<proto::ListExclusionsRequest>,
+    ) -> Result<Response<proto::ListExclusionsResponse>, Status> {
+        let configuration = Configuration::read_from_home()
+            .await
+            .map_err(internal_error)?;
+
+        Ok(Response::new(proto::ListExclusionsResponse {
+            hosts: configuration.exclusions.into_iter().collect(),
+        }))
+    }
+
+    async fn add_exclusion(
+        &self,
+        request: Request<proto::AddExclusionRequest>,
+    ) -> Result<Response<proto::AddExclusionResponse>, Status> {
+        let request = request.into_inner();
+        let host = request.host.trim();
+        if !is_valid_host(host) {
+            return Err(Status::invalid_argument("Invalid host"));
+        }
+
+        let _guard = self.configuration_save_lock.lock().await;
+
+        let mut configuration = Configuration::read_from_home()
+            .await
+            .map_err(internal_error)?;
+
+        configuration
+            .add_exclusion(host, self.local_exclusions_store.clone())
+            .await
+            .map_err(internal_error)?;
+
+        self.configuration_updater_sender
+            .send(configuration)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(Response::new(proto::AddExclusionResponse {}))
+    }
+
+    async fn remove_exclusion(
+        &self,
+        request: Request<proto::RemoveExclusionRequest>,
+    ) -> Result<Response<proto::RemoveExclusionResponse>, Status> {
+        let request = request.into_inner();
+
+        let _guard = self.configuration_save_lock.lock().await;
+
+        let mut configuration = Configuration::read_from_home()
+            .await
+            .map_err(internal_error)?;
+
+        let removed = configuration
+            .remove_exclusion(request.host.trim(), self.local_exclusions_store.clone())
+            .await
+            .map_err(internal_error)?;
+        if !removed {
+            return Err(Status::not_found("This host isn't excluded"));
+        }
+
+        self.configuration_updater_sender
+            .send(configuration)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(Response::new(proto::RemoveExclusionResponse {}))
+    }
+
+    async fn get_blocking_enabled(
+        &self,
+        _request: Request<proto::GetBlockingEnabledRequest>,
+    ) -> Result<Response<proto::BlockingEnabled>, Status> {
+        Ok(Response::new(proto::BlockingEnabled {
+            enabled: self.blocking_disabled_store.is_enabled(),
+        }))
+    }
+
+    async fn set_blocking_enabled(
+        &self,
+        request: Request<proto::BlockingEnabled>,
+    ) -> Result<Response<proto::BlockingEnabled>, Status> {
+        self.blocking_disabled_store
+            .set(request.into_inner().enabled);
+
+        Ok(Response::new(proto::BlockingEnabled {
+            enabled: self.blocking_disabled_store.is_enabled(),
+        }))
+    }
+}
+
+/// Lets requests through when no token is configured, or when they carry it as a bearer token.
+fn authenticate(request: Request<()>, token: Option<&str>) -> Result<Request<()>, Status> {
+    let token = match token {
+        Some(token) => token,
+        None => return Ok(request),
+    };
+
+    let presented_token = request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match presented_token {
+        Some(presented_token) if constant_time_eq(token.as_bytes(), presented_token.as_bytes()) => {
+            Ok(request)
+        }
+        _ => Err(Status::unauthenticated("Missing or invalid token")),
+    }
+}
+
+/// Serves the admin service until a configuration reload is notified.
+pub(crate) async fn run(
+    configuration: GrpcConfig,
+    service: AdminService,
+    notify_reload: Arc<Notify>,
+) {
+    if !configuration.enabled {
+        notify_reload.notified().await;
+        return;
+    }
+
+    // Resolved once, secrets changed in the meantime are picked up on the next reload.
+    let token = match configuration
+        .token
+        .as_deref()
+        .map(|token| resolve_secret(token).map(Cow::into_owned))
+        .transpose()
+    {
+        Ok(token) => token,
+        Err(err) => {
+            log::error!(
+                "Unable to resolve the gRPC token, not serving the gRPC API: {}",
+                err
+            );
+            notify_reload.notified().await;
+            return;
+        }
+    };
+
+    log::info!("gRPC API available at {}", configuration.address);
+
+    let service = AdminServer::with_interceptor(service, move |request| {
+        authenticate(request, token.as_deref())
+    });
+
+    let result = Server::builder()
+        .add_service(service)
+        .serve_with_shutdown(configuration.address, notify_reload.notified())
+        .await;
+
+    if let Err(err) = result {
+        log::error!("Unable to serve the gRPC API: {}", err);
+        // Tried again on the next reload.
+        notify_reload.notified().await;
+    }
+}
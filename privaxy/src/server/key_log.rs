@@ -0,0 +1,72 @@
+//! TLS secrets of intercepted connections, written in the NSS key log format so that packet
+//! captures can be decrypted with Wireshark.
+use crate::configuration::InterceptionTlsConfig;
+use rustls::KeyLog;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const KEY_LOG_FILE_ENV: &str = "SSLKEYLOGFILE";
+
+#[derive(Debug)]
+struct KeyLogFile {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl KeyLog for KeyLogFile {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let line = format!(
+            "{} {} {}\n",
+            label,
+            hex::encode(client_random),
+            hex::encode(secret)
+        );
+
+        if let Err(err) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            log::error!("Unable to write TLS secrets to {:?}: {}", self.path, err);
+        }
+    }
+}
+
+/// The key log of `tls`, or of the `SSLKEYLOGFILE` environment variable. `None` when secrets
+/// are not logged, which is the default.
+pub(crate) fn open(tls: &InterceptionTlsConfig) -> Option<Arc<dyn KeyLog>> {
+    let path = tls
+        .key_log_file
+        .clone()
+        .or_else(|| env::var_os(KEY_LOG_FILE_ENV).map(PathBuf::from))
+        .filter(|path| !path.as_os_str().is_empty())?;
+
+    match open_file(&path) {
+        Ok(file) => {
+            log::warn!(
+                "TLS secrets of intercepted connections are written to {:?}, anyone reading it can decrypt them",
+                path
+            );
+
+            Some(Arc::new(KeyLogFile {
+                path,
+                file: Mutex::new(file),
+            }))
+        }
+        Err(err) => {
+            log::error!("Unable to open TLS key log {:?}: {}", path, err);
+            None
+        }
+    }
+}
+
+fn open_file(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)
+}
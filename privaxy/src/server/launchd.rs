@@ -0,0 +1,93 @@
+//! Running Privaxy as a launchd daemon on macOS.
+//!
+//! The daemon runs as root, which `networksetup` requires to change the system proxy, and
+//! starts at boot. Its working directory, where the configuration directory is looked up, is
+//! the one `--install-service` is run from.
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const SERVICE_NAME: &str = "com.privaxy.server";
+const LAUNCH_DAEMONS_DIRECTORY: &str = "/Library/LaunchDaemons";
+
+fn plist_path() -> PathBuf {
+    Path::new(LAUNCH_DAEMONS_DIRECTORY).join(format!("{}.plist", SERVICE_NAME))
+}
+
+/// Escapes text for use in a property list.
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn plist(executable_path: &Path, working_directory: &Path) -> String {
+    let working_directory = escape(&working_directory.to_string_lossy());
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{executable_path}</string>
+    </array>
+    <key>WorkingDirectory</key>
+    <string>{working_directory}</string>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>StandardErrorPath</key>
+    <string>{working_directory}/privaxy.log</string>
+</dict>
+</plist>
+"#,
+        label = SERVICE_NAME,
+        executable_path = escape(&executable_path.to_string_lossy()),
+        working_directory = working_directory,
+    )
+}
+
+fn launchctl(args: &[&str]) -> io::Result<()> {
+    let output = Command::new("launchctl").args(args).output()?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "launchctl {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ))
+    }
+}
+
+/// Writes the daemon property list and loads it, which starts Privaxy.
+pub fn install(working_directory: &Path) -> io::Result<()> {
+    let executable_path = std::env::current_exe()?;
+    let plist_path = plist_path();
+
+    std::fs::write(&plist_path, plist(&executable_path, working_directory))?;
+
+    launchctl(&["bootstrap", "system", &plist_path.to_string_lossy()])
+}
+
+/// Stops the daemon and removes its property list.
+pub fn uninstall() -> io::Result<()> {
+    let service_target = format!("system/{}", SERVICE_NAME);
+
+    // Unloading sends SIGTERM, which restores the system proxy.
+    if launchctl(&["print", &service_target]).is_ok() {
+        launchctl(&["bootout", &service_target])?;
+    }
+
+    std::fs::remove_file(plist_path())
+}
@@ -1,34 +1,89 @@
+use crate::alerts::{Alert, Alerter};
 use crate::blocker::AdblockRequester;
-use crate::configuration::NetworkConfig;
-use crate::proxy::exclusions::LocalExclusionStore;
+use crate::configuration::{NetworkConfig, PoolConfig, TimeoutsConfig};
+use crate::connection_limits::{ConnectionLimiter, Listener};
+use crate::event_log::EventLog;
+use crate::filter_health::FilterHealthStore;
+use crate::proxy::bandwidth::BandwidthLimiter;
+use crate::proxy::cache::ResponseCache;
+use crate::proxy::capture::CaptureStore;
+use crate::proxy::categories::CategoryStore;
+use crate::proxy::circuit_breaker::CircuitBreaker;
+use crate::proxy::client_certificates::ClientCertificateStore;
+use crate::proxy::cname::CnameResolver;
+use crate::proxy::context::ProxyContext;
+use crate::proxy::dns::DnsResolver;
+use crate::proxy::egress::{self, EgressConnector, EgressRouter, UpstreamClients};
+use crate::proxy::exclusions::{LocalExclusionStore, TemporaryExclusionStore};
+use crate::proxy::frame_context::FrameContextStore;
+use crate::proxy::geoip::GeoIp;
+use crate::proxy::https_upgrade::HttpsUpgradeStore;
+use crate::proxy::pool::CountingResolver;
+use crate::proxy::proxy_protocol::{self, ClientStream};
+use crate::proxy::scripting::RulesScript;
+use crate::proxy::templates::TemplateStore;
+use crate::proxy::threats::ThreatStore;
 use crate::web_gui::events::Event;
-use hyper::server::conn::AddrStream;
+use crate::web_stream::WebStream;
+use futures::{Stream, TryStreamExt};
+use hyper::client::HttpConnector;
+use hyper::server::accept::{self, Accept};
+use hyper::server::conn::AddrIncoming;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Client, Server};
+#[cfg(feature = "gui")]
 use include_dir::{include_dir, Dir};
 use proxy::exclusions;
 use reqwest::redirect::Policy;
+use socket2::{Domain, Protocol, Socket, Type};
 use std::convert::Infallible;
 use std::env;
 use std::net::IpAddr;
 use std::net::SocketAddr;
+use std::pin::Pin;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
+#[cfg(unix)]
 use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::broadcast;
 use tokio::sync::Notify;
+use tokio_rustls::TlsAcceptor;
 
+mod alerts;
+mod allocator;
+pub mod bench;
 pub mod blocker;
 mod blocker_utils;
 mod ca;
 mod cert;
 pub mod configuration;
+mod connection_limits;
+mod event_log;
+mod filter_health;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod key_log;
+#[cfg(target_os = "macos")]
+pub mod launchd;
+pub mod logging;
+mod mdns;
+mod metrics_push;
+mod mqtt;
 mod proxy;
+mod reports;
+mod retention;
 pub mod statistics;
+mod system_proxy;
+mod systemd;
 mod web_gui;
+mod web_stream;
+#[cfg(windows)]
+pub mod winservice;
+mod wpad;
 
+#[cfg(feature = "gui")]
 pub const WEBAPP_FRONTEND_DIR: Dir<'_> = include_dir!("web_frontend/dist");
 
 #[derive(Debug)]
@@ -43,52 +98,109 @@ pub struct PrivaxyServer {
     pub requests_broadcast_sender: broadcast::Sender<Event>,
 }
 
+type ClientStreams = Pin<Box<dyn Stream<Item = std::io::Result<ClientStream>> + Send>>;
+type WebStreams = Pin<Box<dyn Stream<Item = std::io::Result<WebStream>> + Send>>;
+
 pub(crate) fn parse_ip_address(ip_str: &str) -> IpAddr {
     IpAddr::from_str(ip_str).unwrap()
 }
 
-async fn handle_signals() -> (Arc<Notify>, Arc<Notify>) {
+async fn handle_signals(
+    configuration_updater_sender: tokio::sync::mpsc::Sender<configuration::Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    local_exclusion_store: LocalExclusionStore,
+) -> (Arc<Notify>, Arc<Notify>) {
     let notify_shutdown = Arc::new(Notify::new());
     let notify_reload = Arc::new(Notify::new());
     let notify_shutdown_clone = notify_shutdown.clone();
     let notify_reload_clone = notify_reload.clone();
 
+    #[cfg(unix)]
     tokio::spawn(async move {
         let mut hup_signal =
             signal(SignalKind::hangup()).expect("failed to set up SIGHUP signal handler");
         let mut term_signal =
             signal(SignalKind::terminate()).expect("failed to set up SIGTERM signal handler");
+        // Handled so that the system proxy is restored when stopped from a terminal.
+        let mut int_signal =
+            signal(SignalKind::interrupt()).expect("failed to set up SIGINT signal handler");
 
         loop {
             tokio::select! {
                 _ = hup_signal.recv() => {
-                    log::info!("Received SIGHUP signal, restarting child processes...");
-                    notify_reload_clone.notify_waiters();
+                    log::info!("Received SIGHUP signal, reloading configuration...");
+                    if let Err(err) = configuration::reload_configuration(
+                        &configuration_updater_sender,
+                        &configuration_save_lock,
+                        &local_exclusion_store,
+                        &notify_reload_clone,
+                    )
+                    .await
+                    {
+                        log::error!("Unable to reload configuration, keeping the current one: {err}");
+                    }
                 }
                 _ = term_signal.recv() => {
                     log::info!("Received SIGTERM signal, shutting down gracefully...");
-                    notify_shutdown_clone.notify_waiters();
-                    std::process::exit(0);
+                    shutdown(&notify_shutdown_clone);
+                }
+                _ = int_signal.recv() => {
+                    log::info!("Received SIGINT signal, shutting down gracefully...");
+                    shutdown(&notify_shutdown_clone);
                 }
             }
         }
     });
 
+    // There is no SIGHUP equivalent, the configuration is reloaded through the API.
+    #[cfg(windows)]
+    {
+        let _ = (
+            configuration_updater_sender,
+            configuration_save_lock,
+            local_exclusion_store,
+            notify_reload_clone,
+        );
+
+        tokio::spawn(async move {
+            match tokio::signal::ctrl_c().await {
+                Ok(()) => {
+                    log::info!("Received Ctrl-C, shutting down gracefully...");
+                    shutdown(&notify_shutdown_clone);
+                }
+                Err(err) => log::warn!("Unable to listen for Ctrl-C: {err}"),
+            }
+        });
+    }
+
     (notify_shutdown, notify_reload)
 }
 
+/// Undoes changes made outside of the process, such as to the system proxy, then exits.
+fn shutdown(notify_shutdown: &Notify) -> ! {
+    systemd::notify("STOPPING=1");
+    system_proxy::restore();
+    notify_shutdown.notify_waiters();
+    std::process::exit(0);
+}
+
 pub async fn start_privaxy() -> PrivaxyServer {
-    // We use reqwest instead of hyper's client to perform most of the proxying as it's more convenient
-    // to handle compression as well as offers a more convenient interface.
-    let client = reqwest::Client::builder()
-        .use_rustls_tls()
-        .redirect(Policy::none())
-        .no_proxy()
-        .gzip(true)
-        .brotli(true)
-        .deflate(true)
-        .build()
-        .unwrap();
+    // Must be done before anything else has a chance to open file descriptors.
+    let activated_listeners = Arc::new(systemd::ActivatedListeners::from_env());
+
+    // A missing configuration is created with defaults below, an existing one is checked
+    // thoroughly so that every problem is reported at once rather than failing on the first one.
+    let report = configuration::validate_stored().await;
+    for issue in &report.issues {
+        match issue.severity {
+            configuration::Severity::Error => log::error!("{}", issue),
+            configuration::Severity::Warning => log::warn!("{}", issue),
+        }
+    }
+    if !report.is_valid() {
+        log::error!("Invalid configuration in {}", configuration::store());
+        std::process::exit(1)
+    }
 
     let configuration = match configuration::Configuration::read_from_home().await {
         Ok(configuration) => configuration,
@@ -101,10 +213,34 @@ pub async fn start_privaxy() -> PrivaxyServer {
         }
     };
 
+    logging::configure(&configuration.logging);
+    system_proxy::apply(&configuration.system_proxy, &configuration.network);
+
+    let dns_resolver = DnsResolver::new(&configuration.dns);
+    let egress_router = EgressRouter::new(&configuration.egress);
+
+    let client = create_proxy_client(
+        dns_resolver.clone(),
+        Some(egress_router.reqwest_proxy()),
+        &configuration.proxy.timeouts,
+        &configuration.proxy.pool,
+    );
+
     let local_exclusion_store =
-        LocalExclusionStore::new(Vec::from_iter(configuration.exclusions.clone().into_iter()));
+        LocalExclusionStore::new(Vec::from_iter(configuration.exclusions.clone()));
     let local_exclusion_store_clone = local_exclusion_store.clone();
 
+    let https_upgrade_store = HttpsUpgradeStore::new();
+    let circuit_breaker = CircuitBreaker::new();
+    let client_certificate_store = ClientCertificateStore::new();
+    let template_store = TemplateStore::new();
+    let temporary_exclusion_store = TemporaryExclusionStore::new();
+    let response_cache = ResponseCache::new();
+    let bandwidth_limiter = BandwidthLimiter::new();
+    let capture_store = CaptureStore::new();
+    let frame_context_store = FrameContextStore::new();
+    let filter_health_store = FilterHealthStore::new();
+
     let ca_certificate = match configuration.ca.get_ca_certificate().await {
         Ok(ca_certificate) => ca_certificate,
         Err(err) => {
@@ -125,25 +261,39 @@ pub async fn start_privaxy() -> PrivaxyServer {
         }
     };
 
+    let interception_tls = configuration.proxy.tls.clone();
+
     let statistics = statistics::Statistics::new();
+    statistics.spawn_snapshots();
+    cert::restore_popular_authorities(&statistics).await;
+    cert::spawn_popular_authorities_saver(statistics.clone());
     let statistics_clone = statistics.clone();
 
     let (broadcast_tx, _broadcast_rx) = broadcast::channel(32);
     let broadcast_tx_clone = broadcast_tx.clone();
+    let event_log = EventLog::new();
 
     let blocking_disabled_store =
         blocker::BlockingDisabledStore(Arc::new(std::sync::RwLock::new(false)));
     let blocking_disabled_store_clone = blocking_disabled_store.clone();
+    let category_store = CategoryStore::new(blocking_disabled_store.clone());
+    let threat_store = ThreatStore::new(blocking_disabled_store.clone());
+    let cname_resolver = CnameResolver::new(dns_resolver.clone(), egress_router.clone());
+    let alerter = Alerter::new(client.clone(), configuration.alerts.clone());
 
     let (crossbeam_sender, crossbeam_receiver) = crossbeam_channel::unbounded();
     let blocker_sender = crossbeam_sender.clone();
 
     let blocker_requester = AdblockRequester::new(blocker_sender);
+    statistics.spawn_runtime_sampler(blocker_requester.clone());
 
     let configuration_updater = configuration::ConfigurationUpdater::new(
         configuration.clone(),
         client.clone(),
         blocker_requester.clone(),
+        category_store.clone(),
+        threat_store.clone(),
+        alerter.clone(),
         None,
     )
     .await;
@@ -155,15 +305,40 @@ pub async fn start_privaxy() -> PrivaxyServer {
 
     let configuration_save_lock = Arc::new(tokio::sync::Mutex::new(()));
 
-    let (_notify_shutdown, notify_reload) = handle_signals().await;
+    let (_notify_shutdown, notify_reload) = handle_signals(
+        configuration_updater_tx.clone(),
+        configuration_save_lock.clone(),
+        local_exclusion_store.clone(),
+    )
+    .await;
+
+    alerter.spawn_ca_expiry_monitor(configuration_save_lock.clone());
+    alerter.spawn_blocked_spike_monitor(statistics.clone());
+
+    let cert_cache = cert::CertCache::new(
+        ca_certificate,
+        ca_private_key,
+        interception_tls,
+        statistics.clone(),
+    );
+    let cert_cache_pregenerate = cert_cache.clone();
+    tokio::spawn(async move { cert_cache_pregenerate.pregenerate().await });
 
     let block_disable_ref = blocking_disabled_store.clone();
     let local_exclusion_store_ref = local_exclusion_store.clone();
+    let temporary_exclusion_store_ref = temporary_exclusion_store.clone();
+    let cert_cache_ref = cert_cache.clone();
+    let client_certificate_store_ref = client_certificate_store.clone();
+    let dns_resolver_ref = dns_resolver.clone();
+    let capture_store_ref = capture_store.clone();
+    let filter_health_store_ref = filter_health_store.clone();
     let stats_clone = statistics.clone();
     let configuration_updater_tx_ref = configuration_updater_tx.clone();
     let configuration_save_lock_ref = configuration_save_lock.clone();
     let broadcast_tx_ref = broadcast_tx.clone();
+    let event_log_ref = event_log.clone();
     let notify_reload_clone = notify_reload.clone();
+    let activated_listeners_frontend = activated_listeners.clone();
 
     tokio::spawn(async move {
         let notify_reload_frontend = notify_reload_clone.clone();
@@ -172,12 +347,20 @@ pub async fn start_privaxy() -> PrivaxyServer {
             log::info!("Starting Privaxy frontend");
             privaxy_frontend(
                 broadcast_tx_ref.clone(),
+                event_log_ref.clone(),
                 local_exclusion_store_ref.clone(),
+                temporary_exclusion_store_ref.clone(),
+                client_certificate_store_ref.clone(),
+                dns_resolver_ref.clone(),
+                capture_store_ref.clone(),
+                filter_health_store_ref.clone(),
+                cert_cache_ref.clone(),
                 stats_clone.clone(),
                 block_disable_ref.clone(),
                 configuration_updater_tx_ref.clone(),
                 cfg_lock_frontend.clone(),
                 notify_reload_frontend.clone(),
+                activated_listeners_frontend.clone(),
             )
             .await;
             notify_reload_frontend.notified().await;
@@ -185,10 +368,175 @@ pub async fn start_privaxy() -> PrivaxyServer {
         }
     });
 
+    let statistics_mqtt = statistics.clone();
+    let blocking_disabled_store_mqtt = blocking_disabled_store.clone();
+    let configuration_save_lock_mqtt = configuration_save_lock.clone();
+    let notify_reload_mqtt = notify_reload.clone();
+
+    tokio::spawn(async move {
+        loop {
+            let configuration = read_configuration(&configuration_save_lock_mqtt).await;
+            mqtt::run(
+                configuration.mqtt,
+                statistics_mqtt.clone(),
+                blocking_disabled_store_mqtt.clone(),
+                notify_reload_mqtt.clone(),
+            )
+            .await;
+        }
+    });
+
+    #[cfg(feature = "grpc")]
+    {
+        let admin_service = grpc::AdminService {
+            statistics: statistics.clone(),
+            blocking_disabled_store: blocking_disabled_store.clone(),
+            configuration_updater_sender: configuration_updater_tx.clone(),
+            configuration_save_lock: configuration_save_lock.clone(),
+            local_exclusions_store: local_exclusion_store.clone(),
+            http_client: reqwest::Client::new(),
+        };
+        let configuration_save_lock_grpc = configuration_save_lock.clone();
+        let notify_reload_grpc = notify_reload.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let configuration = read_configuration(&configuration_save_lock_grpc).await;
+                grpc::run(
+                    configuration.grpc,
+                    admin_service.clone(),
+                    notify_reload_grpc.clone(),
+                )
+                .await;
+            }
+        });
+    }
+
+    let configuration_save_lock_mdns = configuration_save_lock.clone();
+    let notify_reload_mdns = notify_reload.clone();
+
+    tokio::spawn(async move {
+        loop {
+            let configuration = read_configuration(&configuration_save_lock_mdns).await;
+            let ip = env_or_config_ip(&configuration.network).await;
+            mdns::run(
+                configuration.mdns,
+                configuration.network,
+                ip,
+                notify_reload_mdns.clone(),
+            )
+            .await;
+        }
+    });
+
+    let configuration_save_lock_wpad = configuration_save_lock.clone();
+    let notify_reload_wpad = notify_reload.clone();
+
+    tokio::spawn(async move {
+        loop {
+            let configuration = read_configuration(&configuration_save_lock_wpad).await;
+            let ip = env_or_config_ip(&configuration.network).await;
+            wpad::run(configuration.wpad, ip, notify_reload_wpad.clone()).await;
+        }
+    });
+
+    let client_metrics_push = client.clone();
+    let statistics_metrics_push = statistics.clone();
+    let configuration_save_lock_metrics_push = configuration_save_lock.clone();
+    let notify_reload_metrics_push = notify_reload.clone();
+
+    tokio::spawn(async move {
+        loop {
+            let configuration = read_configuration(&configuration_save_lock_metrics_push).await;
+            metrics_push::run(
+                configuration.metrics_push,
+                client_metrics_push.clone(),
+                statistics_metrics_push.clone(),
+                notify_reload_metrics_push.clone(),
+            )
+            .await;
+        }
+    });
+
+    let client_reports = client.clone();
+    let statistics_reports = statistics.clone();
+    let configuration_save_lock_reports = configuration_save_lock.clone();
+    let notify_reload_reports = notify_reload.clone();
+
+    tokio::spawn(async move {
+        loop {
+            let configuration = read_configuration(&configuration_save_lock_reports).await;
+            reports::run(
+                configuration.reports,
+                client_reports.clone(),
+                statistics_reports.clone(),
+                notify_reload_reports.clone(),
+            )
+            .await;
+        }
+    });
+
+    let broadcast_tx_event_log = broadcast_tx.clone();
+    let configuration_save_lock_event_log = configuration_save_lock.clone();
+    let notify_reload_event_log = notify_reload.clone();
+
+    tokio::spawn(async move {
+        loop {
+            let configuration = read_configuration(&configuration_save_lock_event_log).await;
+            event_log::run(
+                configuration.event_log,
+                configuration.privacy.log_retention(),
+                event_log.clone(),
+                broadcast_tx_event_log.clone(),
+                notify_reload_event_log.clone(),
+            )
+            .await;
+        }
+    });
+
+    let statistics_retention = statistics.clone();
+    let configuration_save_lock_retention = configuration_save_lock.clone();
+    let notify_reload_retention = notify_reload.clone();
+
+    tokio::spawn(async move {
+        loop {
+            let configuration = read_configuration(&configuration_save_lock_retention).await;
+            retention::run(
+                configuration.privacy,
+                statistics_retention.clone(),
+                notify_reload_retention.clone(),
+            )
+            .await;
+        }
+    });
+
+    let client_filter_health = client.clone();
+    let configuration_save_lock_filter_health = configuration_save_lock.clone();
+    let notify_reload_filter_health = notify_reload.clone();
+
+    tokio::spawn(async move {
+        loop {
+            let configuration = read_configuration(&configuration_save_lock_filter_health).await;
+            filter_health::run(
+                configuration.filter_health,
+                client_filter_health.clone(),
+                filter_health_store.clone(),
+                configuration_save_lock_filter_health.clone(),
+                notify_reload_filter_health.clone(),
+            )
+            .await;
+        }
+    });
+
     let disabled_store_ref = blocking_disabled_store_clone.clone();
+    let statistics_blocker = statistics.clone();
     thread::spawn(move || {
-        let blocker =
-            blocker::Blocker::new(crossbeam_sender, crossbeam_receiver, disabled_store_ref);
+        let blocker = blocker::Blocker::new(
+            crossbeam_sender,
+            crossbeam_receiver,
+            disabled_store_ref,
+            statistics_blocker,
+        );
 
         blocker.handle_requests()
     });
@@ -199,31 +547,47 @@ pub async fn start_privaxy() -> PrivaxyServer {
     tokio::spawn(async move {
         let notify_reload_backend = notify_reload_clone.clone();
         let cfg_lock_backend = configuration_save_lock_ref.clone();
-        let mut rt_cert_cache =
-            cert::CertCache::new(ca_certificate.clone(), ca_private_key.clone());
-        let mut rt_ca_certificate = ca_certificate;
         loop {
             log::info!("Starting Privaxy proxy");
             privaxy_backend(
-                client.clone(),
-                rt_cert_cache.clone(),
+                cert_cache.clone(),
                 blocker_requester.clone(),
                 broadcast_tx.clone(),
                 statistics.clone(),
                 local_exclusion_store.clone(),
+                https_upgrade_store.clone(),
+                circuit_breaker.clone(),
+                client_certificate_store.clone(),
+                template_store.clone(),
+                temporary_exclusion_store.clone(),
+                response_cache.clone(),
+                bandwidth_limiter.clone(),
+                category_store.clone(),
+                threat_store.clone(),
+                cname_resolver.clone(),
+                dns_resolver.clone(),
+                capture_store.clone(),
+                frame_context_store.clone(),
+                alerter.clone(),
                 cfg_lock_backend.clone(),
                 notify_reload_backend.clone(),
+                activated_listeners.clone(),
             )
             .await;
             let cfg = read_configuration(&cfg_lock_backend).await;
+            dns_resolver.configure(&cfg.dns);
+            egress_router.configure(&cfg.egress);
             let ca_cert = cfg.ca.get_ca_certificate().await.unwrap();
             let ca_key = cfg.ca.get_ca_private_key().await.unwrap();
-            if !ca_key.public_eq(&rt_ca_certificate.public_key().unwrap()) {
-                rt_ca_certificate = ca_cert.clone();
-                rt_cert_cache = cert::CertCache::new(ca_cert, ca_key);
+            if cert_cache.reconfigure(ca_cert, ca_key, cfg.proxy.tls).await {
+                let cert_cache_pregenerate = cert_cache.clone();
+                tokio::spawn(async move { cert_cache_pregenerate.pregenerate().await });
             }
         }
     });
+
+    systemd::spawn_watchdog();
+
     PrivaxyServer {
         ca_certificate_pem,
         configuration_updater_sender: configuration_updater_tx,
@@ -235,73 +599,236 @@ pub async fn start_privaxy() -> PrivaxyServer {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn privaxy_frontend(
     broadcast_tx: tokio::sync::broadcast::Sender<Event>,
+    event_log: EventLog,
     local_exclusion_store: LocalExclusionStore,
+    temporary_exclusion_store: TemporaryExclusionStore,
+    client_certificate_store: ClientCertificateStore,
+    dns_resolver: DnsResolver,
+    capture_store: CaptureStore,
+    filter_health_store: FilterHealthStore,
+    cert_cache: cert::CertCache,
     statistics: statistics::Statistics,
     block_disable_ref: blocker::BlockingDisabledStore,
     configuration_updater_tx: tokio::sync::mpsc::Sender<configuration::Configuration>,
     configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
     notify_reload: Arc<tokio::sync::Notify>,
+    activated_listeners: Arc<systemd::ActivatedListeners>,
 ) {
+    let config = read_configuration(&configuration_save_lock).await;
     let frontend = web_gui::get_frontend(
         broadcast_tx.clone(),
+        event_log,
         statistics.clone(),
         &block_disable_ref,
         &configuration_updater_tx,
         &configuration_save_lock,
         &local_exclusion_store,
+        &temporary_exclusion_store,
+        &client_certificate_store,
+        &dns_resolver,
+        &capture_store,
+        &filter_health_store,
+        &cert_cache,
         notify_reload.clone(),
+        config.network.host_names(),
     );
-    let frontend_server = warp::serve(frontend);
-    let config = read_configuration(&configuration_save_lock).await;
     let ip = env_or_config_ip(&config.network).await;
     let web_api_server_addr = SocketAddr::from((ip, config.network.web_port));
-    if config.network.tls {
-        let lock = configuration_save_lock.lock().await;
-        let ca_certificate = config.ca.get_ca_certificate().await.unwrap();
-        let ca_private_key = config.ca.get_ca_private_key().await.unwrap();
-        drop(lock);
-        let tls_cert = match config
-            .network
-            .read_or_create_tls_cert(ca_certificate.clone(), ca_private_key.clone())
-            .await
-        {
-            Ok(cert) => cert,
-            Err(err) => {
-                panic!("Failed to read or create TLS certificate: {err}");
-            }
-        };
-        let tls_key = match config.network.get_tls_key().await {
-            Ok(key) => key,
-            Err(err) => {
-                panic!("Failed to read or create TLS key: {err}");
-            }
-        };
-        tokio::spawn(async move {
-            let (_, task) = frontend_server
-                .tls()
-                .cert(tls_cert.to_pem().unwrap())
-                .key(tls_key.private_key_to_pem_pkcs8().unwrap())
-                .bind_with_graceful_shutdown(web_api_server_addr, async move {
-                    notify_reload.clone().notified().await;
-                });
-            log::info!("Web server available at https://{web_api_server_addr}/");
-            log::info!("API server available at https://{web_api_server_addr}/api");
-
-            task.await;
-        });
+    let activated_listener = activated_listeners.web();
+    let limits = config.limits;
+
+    let tls_acceptor = if config.network.tls {
+        match tls_server_configuration(&config, &configuration_save_lock).await {
+            Ok(server_configuration) => Some(TlsAcceptor::from(Arc::new(server_configuration))),
+            Err(err) => panic!("{err}"),
+        }
     } else {
-        tokio::spawn(async move {
-            let (_, task) =
-                frontend_server.bind_with_graceful_shutdown(web_api_server_addr, async move {
-                    let _ = notify_reload.clone().notified().await;
-                });
-            log::info!("Web server available at http://{web_api_server_addr}/");
-            log::info!("API server available at http://{web_api_server_addr}/api");
-            task.await
+        None
+    };
+    let scheme = if tls_acceptor.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+
+    let listeners = bind_listeners(
+        activated_listener,
+        web_api_server_addr,
+        false,
+        &config.network.web_listeners,
+    );
+
+    let connection_limiter = ConnectionLimiter::new(limits.web, Listener::Web, statistics)
+        .with_allowed_networks(config.network.web_allowed_networks.clone());
+    let incoming =
+        futures::stream::select_all(listeners.into_iter().filter_map(|(listener, _)| {
+            let web_api_server_addr = listener.local_addr().ok()?;
+            let listener = tokio::net::TcpListener::from_std(listener).ok()?;
+            let mut incoming = AddrIncoming::from_listener(listener).ok()?;
+
+            log::info!("Web server available at {scheme}://{web_api_server_addr}/");
+            log::info!("API server available at {scheme}://{web_api_server_addr}/api");
+
+            let connections =
+                futures::stream::poll_fn(move |cx| Pin::new(&mut incoming).poll_accept(cx))
+                    .map_ok(ClientStream::new);
+            let connections: ClientStreams = Box::pin(connection_limits::limit(
+                connections,
+                connection_limiter.clone(),
+            ));
+
+            Some(connections)
+        }));
+
+    let header_read_timeout = limits.web_header_read_timeout();
+    let concurrent_handshakes = match limits.web.max_connections {
+        0 => 1024,
+        max_connections => max_connections,
+    };
+    let incoming = web_stream::accept_tls(
+        incoming,
+        tls_acceptor,
+        header_read_timeout,
+        concurrent_handshakes,
+    );
+
+    let service = warp::service(frontend);
+    let make_service = make_service_fn(move |_conn: &WebStream| {
+        let service = service.clone();
+
+        async move { Ok::<_, Infallible>(service) }
+    });
+
+    let mut builder = Server::builder(accept::from_stream(incoming));
+    if let Some(header_read_timeout) = header_read_timeout {
+        builder = builder.http1_header_read_timeout(header_read_timeout);
+    }
+    let server = builder
+        .http1_max_buf_size(limits.web.max_header_bytes())
+        .serve(make_service)
+        .with_graceful_shutdown(async move {
+            let _ = notify_reload.clone().notified().await;
         });
+
+    tokio::spawn(server);
+}
+
+/// TLS configuration of the web server, also used by the secure proxy listener. Its certificate
+/// is signed by the CA unless one was provided.
+async fn tls_server_configuration(
+    config: &configuration::Configuration,
+    configuration_save_lock: &Arc<tokio::sync::Mutex<()>>,
+) -> Result<rustls::ServerConfig, String> {
+    let lock = configuration_save_lock.lock().await;
+    let ca_certificate = config.ca.get_ca_certificate().await.unwrap();
+    let ca_private_key = config.ca.get_ca_private_key().await.unwrap();
+    drop(lock);
+    let tls_cert = config
+        .network
+        .read_or_create_tls_cert(ca_certificate, ca_private_key)
+        .await
+        .map_err(|err| format!("Failed to read or create TLS certificate: {err}"))?;
+    let tls_key = config
+        .network
+        .get_tls_key()
+        .await
+        .map_err(|err| format!("Failed to read or create TLS key: {err}"))?;
+    let mut server_configuration = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![rustls::Certificate(tls_cert.to_der().unwrap())],
+            rustls::PrivateKey(tls_key.private_key_to_pkcs8().unwrap()),
+        )
+        .map_err(|err| format!("Failed to use TLS certificate: {err}"))?;
+    server_configuration.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(server_configuration)
+}
+
+/// Connections to the secure proxy listener at `address`, over TLS. `None` when it can't be
+/// started, the proxy then keeps serving its other listeners.
+async fn secure_proxy_connections(
+    config: &configuration::Configuration,
+    configuration_save_lock: &Arc<tokio::sync::Mutex<()>>,
+    address: SocketAddr,
+    connection_limiter: ConnectionLimiter,
+    handshake_timeout: Option<Duration>,
+    concurrent_handshakes: usize,
+) -> Option<WebStreams> {
+    let server_configuration = tls_server_configuration(config, configuration_save_lock)
+        .await
+        .map_err(|err| log::error!("Unable to start the secure proxy: {err}"))
+        .ok()?;
+    let listener = bind_listener(address, true)
+        .map_err(|err| log::error!("Unable to listen on {address}: {err}"))
+        .ok()?;
+    let listener = tokio::net::TcpListener::from_std(listener).ok()?;
+    let mut incoming = AddrIncoming::from_listener(listener).ok()?;
+    incoming.set_keepalive(Some(Duration::from_secs(600)));
+
+    log::info!("Secure proxy available at https://{}", address);
+
+    let connections = futures::stream::poll_fn(move |cx| Pin::new(&mut incoming).poll_accept(cx))
+        .map_ok(ClientStream::new);
+
+    Some(Box::pin(web_stream::accept_tls(
+        connection_limits::limit(connections, connection_limiter),
+        Some(TlsAcceptor::from(Arc::new(server_configuration))),
+        handshake_timeout,
+        concurrent_handshakes,
+    )))
+}
+
+/// Binds the main address of a server, unless systemd passed a socket for it, along with
+/// the additional addresses from the configuration. Addresses that can't be bound are skipped.
+///
+/// Listeners are returned along with whether they expect a PROXY protocol header.
+fn bind_listeners(
+    activated_listener: Option<std::net::TcpListener>,
+    address: SocketAddr,
+    proxy_protocol: bool,
+    listener_configs: &[configuration::ListenerConfig],
+) -> Vec<(std::net::TcpListener, bool)> {
+    let main_listener = match activated_listener {
+        Some(listener) => Some(listener),
+        None => bind_listener(address, true)
+            .map_err(|err| log::error!("Unable to listen on {address}: {err}"))
+            .ok(),
+    };
+
+    main_listener
+        .map(|listener| (listener, proxy_protocol))
+        .into_iter()
+        .chain(listener_configs.iter().filter_map(|listener_config| {
+            bind_listener(listener_config.address, listener_config.ipv6_only)
+                .map(|listener| (listener, listener_config.proxy_protocol))
+                .map_err(|err| {
+                    log::error!("Unable to listen on {}: {err}", listener_config.address)
+                })
+                .ok()
+        }))
+        .collect()
+}
+
+fn bind_listener(address: SocketAddr, ipv6_only: bool) -> std::io::Result<std::net::TcpListener> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    if address.is_ipv6() {
+        socket.set_only_v6(ipv6_only)?;
     }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(1024)?;
+
+    Ok(socket.into())
 }
 
 async fn read_configuration(
@@ -321,51 +848,194 @@ async fn env_or_config_ip(network_config: &NetworkConfig) -> IpAddr {
     }
 }
 
+/// Client proxying requests through `proxy`, which is usually the egress routes. Requests
+/// are sent directly without one.
+fn create_proxy_client(
+    resolver: impl reqwest::dns::Resolve + 'static,
+    proxy: Option<reqwest::Proxy>,
+    timeouts: &TimeoutsConfig,
+    pool: &PoolConfig,
+) -> reqwest::Client {
+    let mut builder = reqwest::Client::builder()
+        .use_rustls_tls()
+        .redirect(Policy::none())
+        .no_proxy();
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(proxy);
+    }
+    builder = builder
+        .dns_resolver(Arc::new(resolver))
+        .pool_idle_timeout(timeouts.idle())
+        .pool_max_idle_per_host(pool.max_idle_per_host())
+        .tcp_keepalive(pool.tcp_keepalive())
+        .tcp_nodelay(pool.tcp_nodelay)
+        // Bodies are decoded by the proxy, only when they are rewritten, and otherwise
+        // forwarded with their encoding.
+        .gzip(false)
+        .brotli(false)
+        .deflate(false);
+    if let Some(connect_timeout) = timeouts.connect() {
+        builder = builder.connect_timeout(connect_timeout);
+    }
+
+    builder.build().unwrap()
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn privaxy_backend(
-    client: reqwest::Client,
     cert_cache: cert::CertCache,
     blocker_requester: AdblockRequester,
     broadcast_tx: broadcast::Sender<Event>,
     statistics: statistics::Statistics,
     local_exclusion_store: LocalExclusionStore,
+    https_upgrade_store: HttpsUpgradeStore,
+    circuit_breaker: CircuitBreaker,
+    client_certificate_store: ClientCertificateStore,
+    template_store: TemplateStore,
+    temporary_exclusion_store: TemporaryExclusionStore,
+    response_cache: ResponseCache,
+    bandwidth_limiter: BandwidthLimiter,
+    category_store: CategoryStore,
+    threat_store: ThreatStore,
+    cname_resolver: CnameResolver,
+    dns_resolver: DnsResolver,
+    capture_store: CaptureStore,
+    frame_context_store: FrameContextStore,
+    alerter: Alerter,
     configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
     notify_reload: Arc<tokio::sync::Notify>,
+    activated_listeners: Arc<systemd::ActivatedListeners>,
 ) {
+    let config = read_configuration(&configuration_save_lock).await;
+    let network_config = &config.network;
+    let proxy_config = config.proxy.clone();
+    let privacy_config = config.privacy;
+    let geoip = GeoIp::open(&proxy_config.geoip);
+    let timeouts = proxy_config.timeouts;
+    let pool = proxy_config.pool;
+    let limits = config.limits.proxy;
+    // Connections opened by the upstream clients are counted, to report how well pools are
+    // reused.
+    let counting_resolver = CountingResolver::new(dns_resolver.clone(), statistics.clone())
+        .with_blocked_networks(proxy_config.blocked_networks.clone());
+
+    // We use reqwest instead of hyper's client to perform most of the proxying as it offers a more
+    // convenient interface.
+    // Built for each run of the proxy, so that timeouts apply on reload.
+    let client = create_proxy_client(
+        counting_resolver.clone(),
+        Some(cname_resolver.egress_router().reqwest_proxy()),
+        &timeouts,
+        &pool,
+    );
+    // Upstreams chosen by the script, regardless of the egress routes.
+    let upstream_clients: UpstreamClients = Arc::new(
+        config
+            .egress
+            .upstreams
+            .iter()
+            .map(|upstream| {
+                (
+                    upstream.name.clone(),
+                    create_proxy_client(
+                        counting_resolver.clone(),
+                        egress::upstream_proxy(upstream),
+                        &timeouts,
+                        &pool,
+                    ),
+                )
+            })
+            .collect(),
+    );
+    let rules_script = RulesScript::load(&proxy_config.scripting).await;
+
+    let mut http_connector = HttpConnector::new_with_resolver(counting_resolver);
+    http_connector.enforce_http(false);
+    http_connector.set_keepalive(pool.tcp_keepalive());
+    http_connector.set_nodelay(pool.tcp_nodelay);
+    http_connector.set_happy_eyeballs_timeout(Some(dns_resolver.connection_attempt_delay()));
+    http_connector.set_connect_timeout(timeouts.connect());
     let https_connector = hyper_rustls::HttpsConnectorBuilder::new()
         .with_native_roots()
         .https_or_http()
         .enable_http1()
-        .build();
-    let config = read_configuration(&configuration_save_lock).await;
-    let network_config = &config.network;
+        .wrap_connector(
+            EgressConnector::new(
+                http_connector.clone(),
+                cname_resolver.egress_router().clone(),
+                timeouts.connect(),
+            )
+            .with_blocked_networks(proxy_config.blocked_networks.clone()),
+        );
+    // Upgrades need HTTP/1.1, requests expecting trailers get a client negotiating HTTP/2.
+    let trailers_https_connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .https_or_http()
+        .enable_http1()
+        .enable_http2()
+        .wrap_connector(
+            EgressConnector::new(
+                http_connector,
+                cname_resolver.egress_router().clone(),
+                timeouts.connect(),
+            )
+            .with_blocked_networks(proxy_config.blocked_networks.clone()),
+        );
 
-    // The hyper client is only used to perform upgrades. We don't need to
-    // handle compression.
+    // The hyper clients are only used to perform upgrades and to forward requests whose
+    // responses carry trailers, which reqwest drops. We don't need to handle compression.
     // Hyper's client don't follow redirects, which is what we want, nothing to
     // disable here.
-    let hyper_client = Client::builder().build(https_connector);
+    let hyper_client = Client::builder()
+        .pool_idle_timeout(timeouts.idle())
+        .pool_max_idle_per_host(pool.max_idle_per_host())
+        .build(https_connector);
+    let trailers_client = Client::builder()
+        .pool_idle_timeout(timeouts.idle())
+        .pool_max_idle_per_host(pool.max_idle_per_host())
+        .build(trailers_https_connector);
 
-    let make_service = make_service_fn(move |conn: &AddrStream| {
-        let client_ip_address = conn.remote_addr().ip();
+    let statistics_clone = statistics.clone();
+
+    let context = Arc::new(ProxyContext {
+        adblock_requester: blocker_requester,
+        hyper_client,
+        trailers_client,
+        client,
+        upstream_clients,
+        broadcast_sender: broadcast_tx,
+        statistics,
+        proxy_config,
+        privacy_config,
+        https_upgrade_store,
+        circuit_breaker,
+        client_certificate_store,
+        template_store,
+        temporary_exclusion_store,
+        response_cache,
+        bandwidth_limiter,
+        category_store,
+        threat_store,
+        cname_resolver,
+        geoip,
+        rules_script,
+        capture_store,
+        frame_context_store,
+    });
+
+    let make_service = make_service_fn(move |conn: &WebStream| {
+        let client_ip_address = conn.client_address().ip();
 
-        let client = client.clone();
-        let hyper_client = hyper_client.clone();
+        let context = context.clone();
         let cert_cache = cert_cache.clone();
-        let blocker_requester = blocker_requester.clone();
-        let broadcast_tx = broadcast_tx.clone();
-        let statistics = statistics.clone();
         let local_exclusion_store = local_exclusion_store.clone();
 
         async move {
             Ok::<_, Infallible>(service_fn(move |req| {
                 proxy::serve_mitm_session(
-                    blocker_requester.clone(),
-                    hyper_client.clone(),
-                    client.clone(),
+                    context.clone(),
                     req,
                     cert_cache.clone(),
-                    broadcast_tx.clone(),
-                    statistics.clone(),
                     client_ip_address,
                     local_exclusion_store.clone(),
                 )
@@ -373,19 +1043,101 @@ async fn privaxy_backend(
         }
     });
 
-    let ip = env_or_config_ip(&network_config).await;
-    let proxy_server_addr = SocketAddr::from((ip, network_config.proxy_port));
+    let ip = env_or_config_ip(network_config).await;
+    let listeners = bind_listeners(
+        activated_listeners.proxy(),
+        SocketAddr::from((ip, network_config.proxy_port)),
+        network_config.proxy_protocol,
+        &network_config.proxy_listeners,
+    );
+
+    if listeners.is_empty() {
+        log::error!("The proxy has no address to listen on, waiting for a configuration reload");
+        alerter.send(Alert::ProxyError(
+            "The proxy has no address to listen on, check the network settings.".to_string(),
+        ));
+        notify_reload.notified().await;
+        return;
+    }
+
+    let concurrent_handshakes = match limits.max_connections {
+        0 => 1024,
+        max_connections => max_connections,
+    };
+    // Connections from every listener are served by the same server, and share its limits.
+    let connection_limiter = ConnectionLimiter::new(limits, Listener::Proxy, statistics_clone);
+    let trusted_senders = Arc::new(network_config.proxy_protocol_trusted_networks.clone());
+    let incoming = futures::stream::select_all(listeners.into_iter().filter_map(
+        |(listener, proxy_protocol)| {
+            let proxy_server_addr = listener.local_addr().ok()?;
+            let listener = tokio::net::TcpListener::from_std(listener).ok()?;
+            let mut incoming = AddrIncoming::from_listener(listener).ok()?;
+            incoming.set_keepalive(Some(Duration::from_secs(600)));
+
+            log::info!("Proxy available at http://{}", proxy_server_addr);
+
+            let connections =
+                futures::stream::poll_fn(move |cx| Pin::new(&mut incoming).poll_accept(cx));
+            // Limits apply to the client address from the PROXY protocol header.
+            let connections: ClientStreams = if proxy_protocol {
+                Box::pin(connection_limits::limit(
+                    proxy_protocol::accept(connections, trusted_senders.clone()),
+                    connection_limiter.clone(),
+                ))
+            } else {
+                Box::pin(connection_limits::limit(
+                    connections.map_ok(ClientStream::new),
+                    connection_limiter.clone(),
+                ))
+            };
+
+            Some(connections)
+        },
+    ));
 
-    let server = Server::bind(&proxy_server_addr)
+    let incoming: WebStreams = Box::pin(incoming.map_ok(WebStream::Plain));
+
+    let secure_incoming = match network_config.secure_proxy_port {
+        Some(secure_proxy_port) => {
+            secure_proxy_connections(
+                &config,
+                &configuration_save_lock,
+                SocketAddr::from((ip, secure_proxy_port)),
+                connection_limiter,
+                timeouts.request(),
+                concurrent_handshakes,
+            )
+            .await
+        }
+        None => None,
+    };
+    let incoming: WebStreams = match secure_incoming {
+        Some(secure_incoming) => Box::pin(futures::stream::select(incoming, secure_incoming)),
+        None => incoming,
+    };
+
+    let (stopped_tx, stopped_rx) = tokio::sync::oneshot::channel();
+
+    let mut builder = Server::builder(accept::from_stream(incoming));
+    if let Some(request_timeout) = timeouts.request() {
+        builder = builder.http1_header_read_timeout(request_timeout);
+    }
+    let server = builder
+        .http1_max_buf_size(limits.max_header_bytes())
         .http1_preserve_header_case(true)
         .http1_title_case_headers(true)
-        .tcp_keepalive(Some(Duration::from_secs(600)))
         .serve(make_service)
         .with_graceful_shutdown(async move {
-            log::info!("Proxy available at http://{}", proxy_server_addr);
             let _ = notify_reload.clone().notified().await;
             log::info!("Stopping Privaxy proxy");
+            let _ = stopped_tx.send(());
         });
 
-    let _ = server.await;
+    // The listener is closed as soon as the shutdown starts, active connections are drained
+    // in the background so that a new listener can be started without waiting for them.
+    tokio::spawn(server);
+
+    systemd::notify("READY=1");
+
+    let _ = stopped_rx.await;
 }
@@ -0,0 +1,317 @@
+//! Backend for the `log` facade.
+//!
+//! Records are written either as text or, when `PRIVAXY_LOG_FORMAT` is set to `json`, as one
+//! JSON object per line carrying the key-values attached to the record. Levels follow the
+//! `RUST_LOG` syntax (`privaxy=info,hyper=warn`) and can be changed at runtime.
+//!
+//! Logs go to stderr until the configuration is read, which may send them to a rotated file or
+//! to syslog instead.
+use crate::configuration::LoggingConfig;
+use log::kv::{self, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use once_cell::sync::OnceCell;
+use serde_json::{Map, Value};
+use sink::Sink;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+
+mod sink;
+
+const LOG_FORMAT_ENV_KEY: &str = "PRIVAXY_LOG_FORMAT";
+const LOG_LEVEL_ENV_KEY: &str = "RUST_LOG";
+
+static LOGGER: OnceCell<Logger> = OnceCell::new();
+
+#[derive(Error, Debug)]
+pub enum LogDirectivesError {
+    #[error("invalid log level: {0}")]
+    InvalidLevel(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Directive {
+    module: Option<String>,
+    level: LevelFilter,
+}
+
+/// Log levels, per module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogDirectives(Vec<Directive>);
+
+impl LogDirectives {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        if self.0.is_empty() {
+            return LevelFilter::Error;
+        }
+
+        // The most specific directive wins.
+        self.0
+            .iter()
+            .filter(|directive| match &directive.module {
+                Some(module) => {
+                    target == module
+                        || (target.starts_with(module.as_str())
+                            && target[module.len()..].starts_with("::"))
+                }
+                None => true,
+            })
+            .max_by_key(|directive| directive.module.as_ref().map_or(0, |module| module.len()))
+            .map_or(LevelFilter::Off, |directive| directive.level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.0
+            .iter()
+            .map(|directive| directive.level)
+            .max()
+            .unwrap_or(LevelFilter::Error)
+    }
+}
+
+impl FromStr for LogDirectives {
+    type Err = LogDirectivesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut directives = Vec::new();
+
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let directive = match directive.split_once('=') {
+                Some((module, level)) => Directive {
+                    module: Some(module.trim().to_string()),
+                    level: LevelFilter::from_str(level.trim())
+                        .map_err(|_| LogDirectivesError::InvalidLevel(level.to_string()))?,
+                },
+                // A lone level applies to everything, a lone module enables all its logs.
+                None => match LevelFilter::from_str(directive) {
+                    Ok(level) => Directive {
+                        module: None,
+                        level,
+                    },
+                    Err(_) => Directive {
+                        module: Some(directive.to_string()),
+                        level: LevelFilter::Trace,
+                    },
+                },
+            };
+
+            directives.push(directive);
+        }
+
+        Ok(Self(directives))
+    }
+}
+
+impl fmt::Display for LogDirectives {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let directives = self
+            .0
+            .iter()
+            .map(|directive| match &directive.module {
+                Some(module) => format!("{}={}", module, directive.level.as_str().to_lowercase()),
+                None => directive.level.as_str().to_lowercase(),
+            })
+            .collect::<Vec<_>>();
+
+        write!(f, "{}", directives.join(","))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+struct Logger {
+    format: LogFormat,
+    directives: RwLock<LogDirectives>,
+    sink: Mutex<Sink>,
+}
+
+/// Collects the key-values of a record.
+struct KeyValues(Vec<(String, Value)>);
+
+impl<'kvs> VisitSource<'kvs> for KeyValues {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(value) = value.to_u64() {
+            Value::from(value)
+        } else if let Some(value) = value.to_i64() {
+            Value::from(value)
+        } else if let Some(value) = value.to_f64() {
+            Value::from(value)
+        } else if let Some(value) = value.to_bool() {
+            Value::from(value)
+        } else {
+            Value::from(value.to_string())
+        };
+
+        self.0.push((key.to_string(), value));
+
+        Ok(())
+    }
+}
+
+impl Logger {
+    fn format_text(record: &Record, key_values: KeyValues, with_timestamp: bool) -> String {
+        let mut line = if with_timestamp {
+            format!(
+                "[{} {:<5} {}] {}",
+                chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        } else {
+            format!(
+                "[{:<5} {}] {}",
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        };
+
+        for (key, value) in key_values.0 {
+            match value {
+                Value::String(value) => line.push_str(&format!(" {}={:?}", key, value)),
+                value => line.push_str(&format!(" {}={}", key, value)),
+            }
+        }
+
+        line
+    }
+
+    fn format_json(record: &Record, key_values: KeyValues) -> String {
+        let mut object = Map::new();
+        object.insert(
+            "timestamp".to_string(),
+            Value::from(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)),
+        );
+        object.insert(
+            "level".to_string(),
+            Value::from(record.level().as_str().to_lowercase()),
+        );
+        object.insert("target".to_string(), Value::from(record.target()));
+        object.insert(
+            "message".to_string(),
+            Value::from(record.args().to_string()),
+        );
+
+        for (key, value) in key_values.0 {
+            object.insert(key, value);
+        }
+
+        Value::Object(object).to_string()
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.directives.read().unwrap().level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut key_values = KeyValues(Vec::new());
+        let _ = record.key_values().visit(&mut key_values);
+
+        let mut sink = self.sink.lock().unwrap();
+
+        let line = match self.format {
+            LogFormat::Text => Self::format_text(record, key_values, sink.needs_timestamp()),
+            LogFormat::Json => Self::format_json(record, key_values),
+        };
+
+        if let Err(err) = sink.write(record.level(), &line) {
+            eprintln!("Unable to write log record: {err}\n{line}");
+        }
+    }
+
+    fn flush(&self) {
+        let _ = self.sink.lock().unwrap().flush();
+    }
+}
+
+/// Installs the logger, levels are read from `RUST_LOG` and the format from `PRIVAXY_LOG_FORMAT`.
+pub fn init() {
+    let format = match std::env::var(LOG_FORMAT_ENV_KEY).as_deref() {
+        Ok("json") => LogFormat::Json,
+        _ => LogFormat::Text,
+    };
+
+    let (directives, error) = match std::env::var(LOG_LEVEL_ENV_KEY)
+        .unwrap_or_default()
+        .parse::<LogDirectives>()
+    {
+        Ok(directives) => (directives, None),
+        Err(err) => (LogDirectives(Vec::new()), Some(err)),
+    };
+
+    let max_level = directives.max_level();
+
+    let logger = LOGGER.get_or_init(|| Logger {
+        format,
+        directives: RwLock::new(directives),
+        sink: Mutex::new(Sink::Stderr),
+    });
+
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(max_level);
+    }
+
+    if let Some(err) = error {
+        log::error!("Ignoring {}: {}", LOG_LEVEL_ENV_KEY, err);
+    }
+}
+
+/// Current log levels.
+pub(crate) fn directives() -> Option<LogDirectives> {
+    LOGGER
+        .get()
+        .map(|logger| logger.directives.read().unwrap().clone())
+}
+
+/// Replaces log levels of the running process.
+pub(crate) fn set_directives(directives: LogDirectives) {
+    if let Some(logger) = LOGGER.get() {
+        log::set_max_level(directives.max_level());
+        *logger.directives.write().unwrap() = directives;
+    }
+}
+
+/// Sends logs to the output selected in the configuration, the current output is kept when the
+/// new one cannot be opened.
+pub(crate) fn configure(logging_config: &LoggingConfig) {
+    let logger = match LOGGER.get() {
+        Some(logger) => logger,
+        None => return,
+    };
+
+    match Sink::from_config(logging_config) {
+        Ok(sink) => *logger.sink.lock().unwrap() = sink,
+        Err(err) => log::error!(
+            "Unable to log to {:?}, keeping the current output: {}",
+            logging_config.output,
+            err
+        ),
+    }
+}
+
+/// Removes logs older than `retention`, when logging to a file.
+pub(crate) fn prune(retention: Duration) {
+    let logger = match LOGGER.get() {
+        Some(logger) => logger,
+        None => return,
+    };
+
+    // The sink must be released before logging the error.
+    let result = logger.sink.lock().unwrap().prune(retention);
+    if let Err(err) = result {
+        log::warn!("Unable to remove expired log files: {}", err);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::configuration::{LogOutput, LoggingConfig};
+use log::Level;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[cfg(unix)]
+const SYSLOG_SOCKET_PATH: &str = "/dev/log";
+const SYSLOG_TAG: &str = "privaxy";
+/// `LOG_DAEMON`, see syslog(3).
+const SYSLOG_FACILITY: u8 = 3;
+/// With a retention, the log file is rotated at least this often so that rotated files can be
+/// removed as they expire.
+const RETENTION_ROTATION_INTERVAL: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// Where formatted records end up.
+pub(super) enum Sink {
+    Stderr,
+    File(RotatingFile),
+    #[cfg(unix)]
+    Syslog(UnixDatagram),
+}
+
+impl Sink {
+    pub(super) fn from_config(config: &LoggingConfig) -> io::Result<Self> {
+        match config.output {
+            LogOutput::Stderr => Ok(Self::Stderr),
+            LogOutput::File => Ok(Self::File(RotatingFile::open(
+                PathBuf::from(&config.file_path),
+                config.max_size_mb * 1024 * 1024,
+                config.max_files,
+            )?)),
+            #[cfg(unix)]
+            LogOutput::Syslog => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(SYSLOG_SOCKET_PATH)?;
+
+                Ok(Self::Syslog(socket))
+            }
+            #[cfg(not(unix))]
+            LogOutput::Syslog => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "syslog is only available on Unix",
+            )),
+        }
+    }
+
+    /// Whether lines should carry their own timestamp, syslog adds one on its own.
+    pub(super) fn needs_timestamp(&self) -> bool {
+        #[cfg(unix)]
+        return !matches!(self, Self::Syslog(_));
+        #[cfg(not(unix))]
+        return true;
+    }
+
+    pub(super) fn write(&mut self, level: Level, line: &str) -> io::Result<()> {
+        match self {
+            Self::Stderr => writeln!(io::stderr().lock(), "{}", line),
+            Self::File(file) => file.write_line(line),
+            #[cfg(unix)]
+            Self::Syslog(socket) => {
+                let severity = match level {
+                    Level::Error => 3,
+                    Level::Warn => 4,
+                    Level::Info => 6,
+                    Level::Debug | Level::Trace => 7,
+                };
+                let message = format!(
+                    "<{}>{}[{}]: {}",
+                    SYSLOG_FACILITY * 8 + severity,
+                    SYSLOG_TAG,
+                    std::process::id(),
+                    line
+                );
+
+                socket.send(message.as_bytes()).map(|_| ())
+            }
+        }
+    }
+
+    /// Removes logs older than `retention`, only log files can be pruned.
+    pub(super) fn prune(&mut self, retention: Duration) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.prune(retention),
+            _ => Ok(()),
+        }
+    }
+
+    pub(super) fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Stderr => io::stderr().flush(),
+            Self::File(file) => file.file.flush(),
+            #[cfg(unix)]
+            Self::Syslog(_) => Ok(()),
+        }
+    }
+}
+
+/// A log file, renamed to `<path>.1`, `<path>.2`... once it exceeds `max_size`.
+pub(super) struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    /// 0 disables rotation.
+    max_size: u64,
+    max_files: usize,
+    /// When the first line of the current file was written, roughly.
+    started_at: SystemTime,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+        let size = metadata.len();
+        let started_at = match size {
+            0 => SystemTime::now(),
+            _ => metadata.created().unwrap_or_else(|_| SystemTime::now()),
+        };
+
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size,
+            max_files,
+            started_at,
+        })
+    }
+
+    fn rotated_path(path: &Path, index: usize) -> PathBuf {
+        let mut rotated_path = path.as_os_str().to_owned();
+        rotated_path.push(format!(".{}", index));
+        PathBuf::from(rotated_path)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let from = Self::rotated_path(&self.path, index);
+                if from.exists() {
+                    fs::rename(&from, Self::rotated_path(&self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, Self::rotated_path(&self.path, 1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.started_at = SystemTime::now();
+
+        Ok(())
+    }
+
+    /// Rotates the file daily and removes rotated files whose last line is older than
+    /// `retention`, lines are thus kept up to a day longer.
+    fn prune(&mut self, retention: Duration) -> io::Result<()> {
+        let now = SystemTime::now();
+        let age = |time: SystemTime| now.duration_since(time).unwrap_or_default();
+
+        if self.size > 0 && age(self.started_at) >= RETENTION_ROTATION_INTERVAL {
+            self.rotate()?;
+        }
+
+        for index in 1..=self.max_files {
+            let rotated_path = Self::rotated_path(&self.path, index);
+            let modified = match fs::metadata(&rotated_path).and_then(|m| m.modified()) {
+                Ok(modified) => modified,
+                Err(_) => continue,
+            };
+
+            if age(modified) >= retention {
+                fs::remove_file(&rotated_path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let length = line.len() as u64 + 1;
+
+        if self.max_size > 0 && self.size > 0 && self.size + length > self.max_size {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{}", line)?;
+        self.size += length;
+
+        Ok(())
+    }
+}
@@ -1,15 +1,103 @@
+use clap::{Parser, Subcommand};
+use privaxy::bench::{self, BenchOptions};
+use privaxy::configuration;
 use privaxy::start_privaxy;
+use std::path::PathBuf;
 use std::time::Duration;
 
 const RUST_LOG_ENV_KEY: &str = "RUST_LOG";
 
-#[tokio::main]
-async fn main() {
-    if std::env::var(RUST_LOG_ENV_KEY).is_err() {
-        std::env::set_var(RUST_LOG_ENV_KEY, "privaxy=info");
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Validate the configuration file, or the given one, report every problem found and exit.
+    #[arg(long, value_name = "FILE")]
+    check_config: Option<Option<PathBuf>>,
+    /// Where the configuration is kept: `file`, `file:<path>`, `sqlite:<path>`,
+    /// `etcd://<host>:<port>/<key>` or `consul://<host>:<port>/<key>`. Use `etcd+https` or
+    /// `consul+https` to reach the cluster over TLS.
+    #[arg(long, value_name = "STORE", env = "PRIVAXY_CONFIG_STORE")]
+    config_store: Option<configuration::StoreLocation>,
+    /// Register Privaxy as a service started at boot from the current directory, a Windows
+    /// service or a launchd daemon on macOS.
+    #[cfg(any(windows, target_os = "macos"))]
+    #[arg(long, conflicts_with = "uninstall_service")]
+    install_service: bool,
+    /// Stop and remove the service.
+    #[cfg(any(windows, target_os = "macos"))]
+    #[arg(long)]
+    uninstall_service: bool,
+    /// Run as a Windows service from the given directory, used by the service control manager.
+    #[cfg(windows)]
+    #[arg(long, value_name = "DIRECTORY", hide = true)]
+    service: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Replay a request corpus through the blocking engine built from the configured filter
+    /// lists and report match throughput, latency and memory.
+    Bench {
+        /// Requests to replay instead of the bundled corpus, one `url source_url type` per line.
+        #[arg(long, value_name = "FILE")]
+        corpus: Option<PathBuf>,
+        /// Filter list to load instead of the configured ones, may be repeated.
+        #[arg(long = "filter-list", value_name = "FILE")]
+        filter_lists: Vec<PathBuf>,
+        /// Profile whose filter lists are loaded.
+        #[arg(long, default_value = configuration::DEFAULT_PROFILE)]
+        profile: String,
+        /// Number of times the corpus is replayed.
+        #[arg(long, default_value_t = 10)]
+        iterations: usize,
+    },
+}
+
+fn main() {
+    let args = Args::parse();
+
+    #[cfg(any(windows, target_os = "macos"))]
+    if let Some(code) = service_command(&args) {
+        std::process::exit(code);
     }
 
-    env_logger::init();
+    tokio::runtime::Runtime::new()
+        .expect("failed to start the tokio runtime")
+        .block_on(run(args));
+}
+
+async fn run(args: Args) {
+    if let Some(location) = args.config_store {
+        configuration::set_store(location.open());
+    }
+
+    if let Some(path) = args.check_config {
+        std::process::exit(check_config(path).await);
+    }
+
+    init_logging();
+
+    if let Some(Command::Bench {
+        corpus,
+        filter_lists,
+        profile,
+        iterations,
+    }) = args.command
+    {
+        let options = BenchOptions {
+            corpus,
+            filter_lists,
+            profile,
+            iterations,
+        };
+        std::process::exit(run_bench(&options).await);
+    }
 
     start_privaxy().await;
 
@@ -17,3 +105,120 @@ async fn main() {
         tokio::time::sleep(Duration::from_secs(3600 * 24 * 30 * 365)).await
     }
 }
+
+fn init_logging() {
+    if std::env::var(RUST_LOG_ENV_KEY).is_err() {
+        std::env::set_var(RUST_LOG_ENV_KEY, "privaxy=info,privaxy::access=warn");
+    }
+
+    privaxy::logging::init();
+}
+
+/// Handles the service related arguments, returns the exit code when one was given.
+#[cfg(any(windows, target_os = "macos"))]
+fn service_command(args: &Args) -> Option<i32> {
+    #[cfg(target_os = "macos")]
+    use privaxy::launchd as service;
+    #[cfg(windows)]
+    use privaxy::winservice as service;
+
+    if args.install_service {
+        let working_directory = match std::env::current_dir() {
+            Ok(working_directory) => working_directory,
+            Err(err) => {
+                eprintln!("error: unable to get the current directory: {}", err);
+                return Some(1);
+            }
+        };
+
+        return Some(match service::install(&working_directory) {
+            Ok(()) => {
+                println!(
+                    "Installed the {} service, running from {}",
+                    service::SERVICE_NAME,
+                    working_directory.display()
+                );
+                0
+            }
+            Err(err) => {
+                eprintln!("error: unable to install the service: {}", err);
+                1
+            }
+        });
+    }
+
+    if args.uninstall_service {
+        return Some(match service::uninstall() {
+            Ok(()) => {
+                println!("Removed the {} service", service::SERVICE_NAME);
+                0
+            }
+            Err(err) => {
+                eprintln!("error: unable to remove the service: {}", err);
+                1
+            }
+        });
+    }
+
+    #[cfg(windows)]
+    if let Some(working_directory) = &args.service {
+        init_logging();
+
+        return Some(match service::run(working_directory) {
+            Ok(()) => 0,
+            Err(err) => {
+                log::error!("Unable to run as a service: {}", err);
+                1
+            }
+        });
+    }
+
+    None
+}
+
+/// Returns the exit code, non-zero when the benchmark couldn't run.
+async fn run_bench(options: &BenchOptions) -> i32 {
+    match bench::run(options).await {
+        Ok(report) => {
+            println!("{}", report);
+            0
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            1
+        }
+    }
+}
+
+/// Returns the exit code, non-zero when errors were found.
+async fn check_config(path: Option<PathBuf>) -> i32 {
+    let (report, location) = match path {
+        Some(path) => (
+            configuration::validate_file(&path).await,
+            path.display().to_string(),
+        ),
+        None => {
+            if configuration::get_base_directory().is_err() {
+                eprintln!("error: configuration directory not found, set PRIVAXY_BASE_PATH");
+                return 1;
+            }
+
+            (
+                configuration::validate_stored().await,
+                configuration::store().to_string(),
+            )
+        }
+    };
+
+    for issue in &report.issues {
+        eprintln!("{}", issue);
+    }
+
+    if report.is_valid() {
+        println!("{}: configuration is valid", location);
+        0
+    } else {
+        eprintln!("{}: configuration is invalid", location);
+        1
+    }
+}
@@ -0,0 +1,113 @@
+//! Advertises the web interface as `_http._tcp` (`_https._tcp` with TLS) and the proxy as
+//! `_privaxy-proxy._tcp` over mDNS, with `<name>.local` as host name.
+use crate::configuration::{MdnsConfig, NetworkConfig};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+const PROXY_SERVICE_TYPE: &str = "_privaxy-proxy._tcp.local.";
+
+fn service(
+    configuration: &MdnsConfig,
+    ip: IpAddr,
+    service_type: &str,
+    port: u16,
+    properties: &[(&str, &str)],
+) -> Result<ServiceInfo, mdns_sd::Error> {
+    let host_name = format!("{}.local.", configuration.name);
+
+    // Services bound to every interface are advertised with the addresses of each of them.
+    if ip.is_unspecified() {
+        ServiceInfo::new(
+            service_type,
+            &configuration.name,
+            &host_name,
+            "",
+            port,
+            properties,
+        )
+        .map(ServiceInfo::enable_addr_auto)
+    } else {
+        ServiceInfo::new(
+            service_type,
+            &configuration.name,
+            &host_name,
+            ip,
+            port,
+            properties,
+        )
+    }
+}
+
+/// Advertises the services until a configuration reload is notified.
+pub(crate) async fn run(
+    configuration: MdnsConfig,
+    network: NetworkConfig,
+    ip: IpAddr,
+    notify_reload: Arc<Notify>,
+) {
+    if !configuration.enabled {
+        notify_reload.notified().await;
+        return;
+    }
+
+    let daemon = match ServiceDaemon::new() {
+        Ok(daemon) => daemon,
+        Err(err) => {
+            log::error!("Unable to start mDNS responder: {}", err);
+            notify_reload.notified().await;
+            return;
+        }
+    };
+
+    let web_service_type = if network.tls {
+        "_https._tcp.local."
+    } else {
+        "_http._tcp.local."
+    };
+    let services = [
+        service(
+            &configuration,
+            ip,
+            web_service_type,
+            network.web_port,
+            &[("path", "/")],
+        ),
+        service(
+            &configuration,
+            ip,
+            PROXY_SERVICE_TYPE,
+            network.proxy_port,
+            &[],
+        ),
+    ];
+
+    let mut registered = Vec::new();
+    for service in services {
+        let result = service.and_then(|service| {
+            let fullname = service.get_fullname().to_string();
+            daemon.register(service).map(|()| fullname)
+        });
+
+        match result {
+            Ok(fullname) => {
+                log::info!("Advertising {} over mDNS", fullname);
+                registered.push(fullname);
+            }
+            Err(err) => log::error!("Unable to advertise service over mDNS: {}", err),
+        }
+    }
+
+    notify_reload.notified().await;
+
+    // Goodbye packets are sent so that clients forget the services before their TTL expires.
+    for fullname in registered {
+        if let Err(err) = daemon.unregister(&fullname) {
+            log::warn!("Unable to withdraw {} from mDNS: {}", fullname, err);
+        }
+    }
+    if let Err(err) = daemon.shutdown() {
+        log::warn!("Unable to stop mDNS responder: {}", err);
+    }
+}
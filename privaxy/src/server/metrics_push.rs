@@ -0,0 +1,358 @@
+//! Periodically writes statistics to InfluxDB, in line protocol, or to Graphite, in the
+//! Carbon plaintext protocol.
+use crate::configuration::{resolve_optional_secret, MetricsPushConfig, MetricsPushTarget};
+use crate::statistics::Statistics;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::Notify;
+
+const PUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Values of a single point in time.
+struct Snapshot {
+    timestamp: u64,
+    counters: Vec<(&'static str, u64)>,
+    /// Counters of authenticated users, keyed by username.
+    users: Vec<(String, Vec<(&'static str, u64)>)>,
+    /// Rules and memory of the blocking engines, keyed by profile name.
+    engines: Vec<(String, Vec<(&'static str, u64)>)>,
+    /// Requests blocked by each filter list, keyed by its title.
+    lists: Vec<(String, Vec<(&'static str, u64)>)>,
+}
+
+impl Snapshot {
+    fn take(statistics: &Statistics) -> Self {
+        let serialized = statistics.get_serialized();
+
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            counters: vec![
+                ("proxied_requests", serialized.proxied_requests),
+                ("blocked_requests", serialized.blocked_requests),
+                ("ads_blocked", serialized.blocked_by_category.ads),
+                ("trackers_blocked", serialized.blocked_by_category.trackers),
+                ("malware_blocked", serialized.blocked_by_category.malware),
+                ("custom_blocked", serialized.blocked_by_category.custom),
+                ("modified_responses", serialized.modified_responses),
+                ("threats_blocked", serialized.threats_blocked),
+                ("coalesced_requests", serialized.coalesced_requests),
+                ("tunneled_connections", serialized.tunneled_connections),
+                (
+                    "rejected_connections",
+                    serialized.rejected_connections.proxy_connection_limit
+                        + serialized.rejected_connections.proxy_rate_limit
+                        + serialized.rejected_connections.web_connection_limit
+                        + serialized.rejected_connections.web_rate_limit,
+                ),
+                ("proxied_bytes", serialized.proxied_bytes),
+                ("tunneled_bytes", serialized.tunneled_bytes),
+                ("upstream_connections", serialized.pool.connections_opened),
+                ("connect_timeouts", serialized.timeouts.connect),
+                ("tls_handshake_timeouts", serialized.timeouts.tls_handshake),
+                (
+                    "response_header_timeouts",
+                    serialized.timeouts.response_header,
+                ),
+                ("tunnel_idle_timeouts", serialized.timeouts.tunnel_idle),
+                ("dns_errors", serialized.upstream_errors.dns),
+                ("tls_errors", serialized.upstream_errors.tls),
+                ("timeout_errors", serialized.upstream_errors.timeout),
+                (
+                    "connection_refused_errors",
+                    serialized.upstream_errors.connection_refused,
+                ),
+                ("other_upstream_errors", serialized.upstream_errors.other),
+                (
+                    "responses_1xx",
+                    serialized.responses.status_classes.informational,
+                ),
+                ("responses_2xx", serialized.responses.status_classes.success),
+                (
+                    "responses_3xx",
+                    serialized.responses.status_classes.redirection,
+                ),
+                (
+                    "responses_4xx",
+                    serialized.responses.status_classes.client_error,
+                ),
+                (
+                    "responses_5xx",
+                    serialized.responses.status_classes.server_error,
+                ),
+                (
+                    "added_latency_us",
+                    serialized.overhead.average_added_latency_us as u64,
+                ),
+                (
+                    "p95_added_latency_us",
+                    serialized.overhead.p95_added_latency_us,
+                ),
+            ],
+            users: serialized
+                .users
+                .into_iter()
+                .map(|user| {
+                    (
+                        user.username,
+                        vec![
+                            ("proxied_requests", user.proxied_requests),
+                            ("blocked_requests", user.blocked_requests),
+                        ],
+                    )
+                })
+                .collect(),
+            engines: serialized
+                .engines
+                .into_iter()
+                .map(|engine| {
+                    (
+                        engine.profile,
+                        vec![
+                            ("network_rules", engine.network_rules),
+                            ("cosmetic_rules", engine.cosmetic_rules),
+                            ("memory_bytes", engine.memory_bytes),
+                        ],
+                    )
+                })
+                .collect(),
+            lists: serialized
+                .blocked_by_list
+                .into_iter()
+                .map(|(list, blocked_requests)| {
+                    (list, vec![("blocked_requests", blocked_requests)])
+                })
+                .collect(),
+        }
+    }
+
+    fn to_influxdb_lines(&self, configuration: &MetricsPushConfig) -> String {
+        let measurement = escape_influxdb(&configuration.prefix, ", ");
+        let tags = configuration
+            .tags
+            .iter()
+            .map(|(key, value)| {
+                format!(
+                    ",{}={}",
+                    escape_influxdb(key, ",= "),
+                    escape_influxdb(value, ",= ")
+                )
+            })
+            .collect::<String>();
+        let fields = |counters: &[(&str, u64)]| {
+            counters
+                .iter()
+                .map(|(name, value)| format!("{}={}i", name, value))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        let mut lines = format!(
+            "{}{} {} {}\n",
+            measurement,
+            tags,
+            fields(&self.counters),
+            self.timestamp
+        );
+        for (username, counters) in &self.users {
+            lines.push_str(&format!(
+                "{}_user{},user={} {} {}\n",
+                measurement,
+                tags,
+                escape_influxdb(username, ",= "),
+                fields(counters),
+                self.timestamp
+            ));
+        }
+        for (profile, values) in &self.engines {
+            lines.push_str(&format!(
+                "{}_engine{},profile={} {} {}\n",
+                measurement,
+                tags,
+                escape_influxdb(profile, ",= "),
+                fields(values),
+                self.timestamp
+            ));
+        }
+        for (list, values) in &self.lists {
+            lines.push_str(&format!(
+                "{}_list{},list={} {} {}\n",
+                measurement,
+                tags,
+                escape_influxdb(list, ",= "),
+                fields(values),
+                self.timestamp
+            ));
+        }
+
+        lines
+    }
+
+    fn to_graphite_lines(&self, configuration: &MetricsPushConfig) -> String {
+        let prefix = sanitize_graphite(&configuration.prefix);
+
+        let mut lines = String::new();
+        for (name, value) in &self.counters {
+            lines.push_str(&format!(
+                "{}.{} {} {}\n",
+                prefix, name, value, self.timestamp
+            ));
+        }
+        for (username, counters) in &self.users {
+            for (name, value) in counters {
+                lines.push_str(&format!(
+                    "{}.users.{}.{} {} {}\n",
+                    prefix,
+                    sanitize_graphite(username),
+                    name,
+                    value,
+                    self.timestamp
+                ));
+            }
+        }
+        for (profile, values) in &self.engines {
+            for (name, value) in values {
+                lines.push_str(&format!(
+                    "{}.engines.{}.{} {} {}\n",
+                    prefix,
+                    sanitize_graphite(profile),
+                    name,
+                    value,
+                    self.timestamp
+                ));
+            }
+        }
+        for (list, values) in &self.lists {
+            for (name, value) in values {
+                lines.push_str(&format!(
+                    "{}.lists.{}.{} {} {}\n",
+                    prefix,
+                    sanitize_graphite(list),
+                    name,
+                    value,
+                    self.timestamp
+                ));
+            }
+        }
+
+        lines
+    }
+}
+
+/// Backslash escapes `characters` as required by the line protocol.
+fn escape_influxdb(value: &str, characters: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for character in value.chars() {
+        if characters.contains(character) {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+
+    escaped
+}
+
+/// Graphite paths are separated by dots and end at the first space.
+fn sanitize_graphite(value: &str) -> String {
+    value
+        .chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() || character == '-' || character == '_' {
+                character
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Pushes statistics to the configured targets until a configuration reload is notified.
+pub(crate) async fn run(
+    configuration: MetricsPushConfig,
+    http_client: reqwest::Client,
+    statistics: Statistics,
+    notify_reload: Arc<Notify>,
+) {
+    if configuration.targets.is_empty() {
+        notify_reload.notified().await;
+        return;
+    }
+
+    let push = async {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(configuration.interval_seconds.max(1)));
+
+        loop {
+            interval.tick().await;
+
+            let snapshot = Snapshot::take(&statistics);
+            for target in &configuration.targets {
+                let result = match target {
+                    MetricsPushTarget::InfluxDb { url, token } => {
+                        push_influxdb(
+                            &http_client,
+                            url,
+                            resolve_optional_secret(token.as_deref()).as_deref(),
+                            snapshot.to_influxdb_lines(&configuration),
+                        )
+                        .await
+                    }
+                    MetricsPushTarget::Graphite { address } => {
+                        push_graphite(address, snapshot.to_graphite_lines(&configuration)).await
+                    }
+                };
+
+                if let Err(err) = result {
+                    log::warn!("Unable to push metrics: {}", err);
+                }
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = push => {}
+        _ = notify_reload.notified() => {}
+    }
+}
+
+async fn push_influxdb(
+    http_client: &reqwest::Client,
+    url: &url::Url,
+    token: Option<&str>,
+    lines: String,
+) -> Result<(), String> {
+    let mut request = http_client
+        .post(url.as_str())
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .timeout(PUSH_TIMEOUT)
+        .body(lines);
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Token {}", token));
+    }
+
+    request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| format!("InfluxDB at {}: {}", url, err))?;
+
+    Ok(())
+}
+
+async fn push_graphite(address: &str, lines: String) -> Result<(), String> {
+    let send = async {
+        let mut stream = TcpStream::connect(address).await?;
+        stream.write_all(lines.as_bytes()).await?;
+        stream.shutdown().await
+    };
+
+    match tokio::time::timeout(PUSH_TIMEOUT, send).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => Err(format!("Graphite at {}: {}", address, err)),
+        Err(_elapsed) => Err(format!("Graphite at {}: timed out", address)),
+    }
+}
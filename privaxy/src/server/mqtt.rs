@@ -0,0 +1,354 @@
+//! Publishes metrics to an MQTT broker and accepts commands from it, Home Assistant discovers
+//! Privaxy as a device with sensors for metrics and a switch for blocking.
+use crate::blocker::BlockingDisabledStore;
+use crate::configuration::{resolve_optional_secret, MqttConfig};
+use crate::statistics::Statistics;
+use chrono::{Local, NaiveDate};
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const RECONNECT_AFTER: Duration = Duration::from_secs(10);
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+
+/// Counters sampled at each publication, rates are computed against the previous sample.
+struct Sample {
+    at: Instant,
+    proxied_requests: u64,
+    day: NaiveDate,
+    /// Blocked requests at the start of `day`.
+    blocked_at_day_start: u64,
+}
+
+#[derive(Clone)]
+struct Topics {
+    availability: String,
+    blocked_today: String,
+    requests_per_second: String,
+    blocking_state: String,
+    blocking_set: String,
+    blocking_pause: String,
+}
+
+impl Topics {
+    fn new(prefix: &str) -> Self {
+        Self {
+            availability: format!("{}/status", prefix),
+            blocked_today: format!("{}/blocked_today", prefix),
+            requests_per_second: format!("{}/requests_per_second", prefix),
+            blocking_state: format!("{}/blocking/state", prefix),
+            blocking_set: format!("{}/blocking/set", prefix),
+            blocking_pause: format!("{}/blocking/pause", prefix),
+        }
+    }
+}
+
+/// Runs the MQTT session until a configuration reload is notified.
+pub(crate) async fn run(
+    configuration: MqttConfig,
+    statistics: Statistics,
+    blocking_disabled_store: BlockingDisabledStore,
+    notify_reload: Arc<Notify>,
+) {
+    if !configuration.enabled {
+        notify_reload.notified().await;
+        return;
+    }
+
+    log::info!(
+        "Publishing to MQTT broker {}:{}",
+        configuration.host,
+        configuration.port
+    );
+
+    let topics = Topics::new(&configuration.topic_prefix);
+
+    let mut options = MqttOptions::new(
+        &configuration.client_id,
+        &configuration.host,
+        configuration.port,
+    );
+    options.set_keep_alive(KEEP_ALIVE);
+    options.set_last_will(LastWill::new(
+        &topics.availability,
+        OFFLINE,
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = &configuration.username {
+        let password = resolve_optional_secret(configuration.password.as_deref());
+        options.set_credentials(username, password.unwrap_or_default());
+    }
+
+    let (client, event_loop) = AsyncClient::new(options, 16);
+
+    let session = session(
+        &configuration,
+        &topics,
+        &client,
+        event_loop,
+        &statistics,
+        &blocking_disabled_store,
+    );
+
+    tokio::select! {
+        _ = session => {}
+        _ = notify_reload.notified() => {}
+    }
+
+    let _result = client
+        .publish(&topics.availability, QoS::AtLeastOnce, true, OFFLINE)
+        .await;
+    let _result = client.disconnect().await;
+}
+
+async fn session(
+    configuration: &MqttConfig,
+    topics: &Topics,
+    client: &AsyncClient,
+    mut event_loop: EventLoop,
+    statistics: &Statistics,
+    blocking_disabled_store: &BlockingDisabledStore,
+) {
+    let mut publish_interval = tokio::time::interval(Duration::from_secs(
+        configuration.publish_interval_seconds.max(1),
+    ));
+    let mut sample = Sample {
+        at: Instant::now(),
+        proxied_requests: *statistics.proxied_requests.lock().unwrap(),
+        day: Local::now().date_naive(),
+        blocked_at_day_start: *statistics.blocked_requests.lock().unwrap(),
+    };
+    // Pauses are identified so that an expired pause doesn't undo a later one.
+    let pause_generation = Arc::new(AtomicU64::new(0));
+
+    loop {
+        tokio::select! {
+            event = event_loop.poll() => match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    log::debug!("Connected to MQTT broker");
+                    // Announced from another task, as its messages are only sent once the event
+                    // loop is polled again and would never be with a full request channel.
+                    let announcement = (configuration.clone(), topics.clone(), client.clone());
+                    tokio::spawn(async move {
+                        let (configuration, topics, client) = announcement;
+                        if let Err(err) = announce(&configuration, &topics, &client).await {
+                            log::warn!("Unable to announce Privaxy to the MQTT broker: {}", err);
+                        }
+                    });
+                    publish_blocking_state(topics, client, blocking_disabled_store);
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let payload = String::from_utf8_lossy(&publish.payload).trim().to_string();
+
+                    handle_command(
+                        configuration,
+                        topics,
+                        &publish.topic,
+                        &payload,
+                        blocking_disabled_store,
+                        &pause_generation,
+                    );
+                    publish_blocking_state(topics, client, blocking_disabled_store);
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    log::warn!("MQTT connection error: {}", err);
+                    tokio::time::sleep(RECONNECT_AFTER).await;
+                }
+            },
+            _ = publish_interval.tick() => {
+                publish_metrics(topics, client, statistics, &mut sample);
+                publish_blocking_state(topics, client, blocking_disabled_store);
+            }
+        }
+    }
+}
+
+fn handle_command(
+    configuration: &MqttConfig,
+    topics: &Topics,
+    topic: &str,
+    payload: &str,
+    blocking_disabled_store: &BlockingDisabledStore,
+    pause_generation: &Arc<AtomicU64>,
+) {
+    if topic == topics.blocking_set {
+        match payload {
+            "ON" => blocking_disabled_store.set(true),
+            "OFF" => blocking_disabled_store.set(false),
+            _ => {
+                log::warn!("Ignoring MQTT blocking command: {}", payload);
+                return;
+            }
+        }
+        // Explicit commands cancel pending pauses.
+        pause_generation.fetch_add(1, Ordering::Relaxed);
+        log::info!("Blocking turned {} over MQTT", payload);
+    } else if topic == topics.blocking_pause {
+        let minutes = payload.parse().unwrap_or(configuration.pause_minutes);
+        let generation = pause_generation.fetch_add(1, Ordering::Relaxed) + 1;
+        blocking_disabled_store.set(false);
+        log::info!("Blocking paused for {} minutes over MQTT", minutes);
+
+        let blocking_disabled_store = blocking_disabled_store.clone();
+        let pause_generation = pause_generation.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(minutes * 60)).await;
+            if pause_generation.load(Ordering::Relaxed) == generation {
+                blocking_disabled_store.set(true);
+                log::info!("Blocking resumed after pause");
+            }
+        });
+    }
+}
+
+/// Publishes Home Assistant discovery messages and subscribes to commands.
+async fn announce(
+    configuration: &MqttConfig,
+    topics: &Topics,
+    client: &AsyncClient,
+) -> Result<(), rumqttc::ClientError> {
+    let node_id = &configuration.client_id;
+    let device = json!({
+        "identifiers": [node_id],
+        "name": "Privaxy",
+        "manufacturer": "Privaxy",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+
+    let entities = [
+        (
+            "sensor",
+            "blocked_today",
+            json!({
+                "name": "Blocked today",
+                "state_topic": topics.blocked_today,
+                "state_class": "total_increasing",
+                "icon": "mdi:shield-check",
+            }),
+        ),
+        (
+            "sensor",
+            "requests_per_second",
+            json!({
+                "name": "Requests per second",
+                "state_topic": topics.requests_per_second,
+                "unit_of_measurement": "req/s",
+                "state_class": "measurement",
+                "icon": "mdi:swap-vertical",
+            }),
+        ),
+        (
+            "switch",
+            "blocking",
+            json!({
+                "name": "Blocking",
+                "state_topic": topics.blocking_state,
+                "command_topic": topics.blocking_set,
+                "icon": "mdi:shield",
+            }),
+        ),
+        (
+            "button",
+            "pause_blocking",
+            json!({
+                "name": format!("Pause blocking for {} minutes", configuration.pause_minutes),
+                "command_topic": topics.blocking_pause,
+                "payload_press": configuration.pause_minutes.to_string(),
+                "icon": "mdi:shield-off",
+            }),
+        ),
+    ];
+
+    for (component, object_id, mut entity) in entities {
+        entity["unique_id"] = json!(format!("{}_{}", node_id, object_id));
+        entity["availability_topic"] = json!(topics.availability);
+        entity["device"] = device.clone();
+
+        client
+            .publish(
+                format!(
+                    "{}/{}/{}/{}/config",
+                    configuration.discovery_prefix, component, node_id, object_id
+                ),
+                QoS::AtLeastOnce,
+                true,
+                entity.to_string(),
+            )
+            .await?;
+    }
+
+    client
+        .subscribe(&topics.blocking_set, QoS::AtLeastOnce)
+        .await?;
+    client
+        .subscribe(&topics.blocking_pause, QoS::AtLeastOnce)
+        .await?;
+    client
+        .publish(&topics.availability, QoS::AtLeastOnce, true, ONLINE)
+        .await?;
+
+    Ok(())
+}
+
+fn publish_metrics(
+    topics: &Topics,
+    client: &AsyncClient,
+    statistics: &Statistics,
+    sample: &mut Sample,
+) {
+    let now = Instant::now();
+    let today = Local::now().date_naive();
+    let proxied_requests = *statistics.proxied_requests.lock().unwrap();
+    let blocked_requests = *statistics.blocked_requests.lock().unwrap();
+
+    if today != sample.day {
+        sample.day = today;
+        sample.blocked_at_day_start = blocked_requests;
+    }
+
+    let elapsed = now.duration_since(sample.at).as_secs_f64();
+    let requests_per_second = if elapsed > 0.0 {
+        proxied_requests.saturating_sub(sample.proxied_requests) as f64 / elapsed
+    } else {
+        0.0
+    };
+    sample.at = now;
+    sample.proxied_requests = proxied_requests;
+
+    let blocked_today = blocked_requests.saturating_sub(sample.blocked_at_day_start);
+
+    for (topic, payload) in [
+        (&topics.blocked_today, blocked_today.to_string()),
+        (
+            &topics.requests_per_second,
+            format!("{:.2}", requests_per_second),
+        ),
+    ] {
+        if let Err(err) = client.try_publish(topic, QoS::AtMostOnce, false, payload) {
+            log::debug!("Unable to publish to {}: {}", topic, err);
+        }
+    }
+}
+
+fn publish_blocking_state(
+    topics: &Topics,
+    client: &AsyncClient,
+    blocking_disabled_store: &BlockingDisabledStore,
+) {
+    let state = if blocking_disabled_store.is_enabled() {
+        "ON"
+    } else {
+        "OFF"
+    };
+
+    if let Err(err) = client.try_publish(&topics.blocking_state, QoS::AtMostOnce, true, state) {
+        log::debug!("Unable to publish to {}: {}", topics.blocking_state, err);
+    }
+}
@@ -0,0 +1,83 @@
+//! Basic authentication of proxy clients, with the `Proxy-Authorization` header (RFC 7235).
+use crate::configuration::{resolve_secret, ProxyAuthenticationConfig};
+use base64::Engine;
+use hyper::{http, Body, Response};
+use sha2::{Digest, Sha256};
+
+const REALM: &str = "Privaxy";
+const HASHED_PASSWORD_PREFIX: &str = "sha256:";
+
+/// Returns the name of the authenticated user, `None` when authentication is disabled.
+/// Requests without valid credentials are rejected.
+pub(crate) fn authenticate(
+    authentication_config: &ProxyAuthenticationConfig,
+    headers: &http::HeaderMap,
+) -> Result<Option<String>, ()> {
+    if !authentication_config.enabled {
+        return Ok(None);
+    }
+
+    let credentials = headers
+        .get(http::header::PROXY_AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|credentials| {
+            base64::engine::general_purpose::STANDARD
+                .decode(credentials.trim())
+                .ok()
+        })
+        .and_then(|credentials| String::from_utf8(credentials).ok())
+        .ok_or(())?;
+
+    let (username, password) = credentials.split_once(':').ok_or(())?;
+
+    let user = authentication_config
+        .users
+        .iter()
+        .find(|user| user.username == username);
+
+    let expected_password = match user.map(|user| resolve_secret(&user.password)) {
+        Some(Ok(expected_password)) => Some(expected_password),
+        Some(Err(err)) => {
+            log::error!("Unable to get the password of user {}: {}", username, err);
+            None
+        }
+        None => None,
+    };
+
+    match expected_password {
+        Some(expected_password) if password_matches(&expected_password, password) => {
+            Ok(Some(username.to_string()))
+        }
+        _ => {
+            log::debug!("Rejected proxy credentials for user {}", username);
+            Err(())
+        }
+    }
+}
+
+fn password_matches(expected: &str, password: &str) -> bool {
+    match expected.strip_prefix(HASHED_PASSWORD_PREFIX) {
+        Some(expected_digest) => {
+            let digest = hex::encode(Sha256::digest(password.as_bytes()));
+
+            constant_time_eq(expected_digest.to_lowercase().as_bytes(), digest.as_bytes())
+        }
+        None => constant_time_eq(expected.as_bytes(), password.as_bytes()),
+    }
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && openssl::memcmp::eq(a, b)
+}
+
+pub(crate) fn get_proxy_authentication_required_response() -> Response<Body> {
+    Response::builder()
+        .status(http::StatusCode::PROXY_AUTHENTICATION_REQUIRED)
+        .header(
+            http::header::PROXY_AUTHENTICATE,
+            format!("Basic realm=\"{}\"", REALM),
+        )
+        .body(Body::empty())
+        .unwrap()
+}
@@ -0,0 +1,200 @@
+//! Download bandwidth limits, shared by all the connections of a user.
+use super::trailers;
+use hyper::Body;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+/// Schedules the chunks sent to a user so that they don't exceed its rate.
+#[derive(Debug)]
+struct Bucket {
+    bytes_per_second: u64,
+    /// When the next chunk may be sent.
+    next_send: Instant,
+}
+
+impl Bucket {
+    /// When `bytes` may be sent, the time it takes to send them at the rate of the bucket is
+    /// reserved.
+    fn reserve(&mut self, bytes: usize) -> Instant {
+        let send_at = self.next_send.max(Instant::now());
+        self.next_send =
+            send_at + Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+
+        send_at
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BandwidthLimiter(Arc<Mutex<HashMap<String, Arc<Mutex<Bucket>>>>>);
+
+impl BandwidthLimiter {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    fn bucket(&self, username: &str, bytes_per_second: u64) -> Arc<Mutex<Bucket>> {
+        let mut buckets = self.0.lock().unwrap();
+
+        // Buckets no transfer holds anymore, and whose reservations are over, are forgotten.
+        let now = Instant::now();
+        buckets.retain(|_username, bucket| {
+            Arc::strong_count(bucket) > 1 || bucket.lock().unwrap().next_send > now
+        });
+
+        let bucket = buckets
+            .entry(username.to_string())
+            .or_insert_with(|| {
+                Arc::new(Mutex::new(Bucket {
+                    bytes_per_second,
+                    next_send: now,
+                }))
+            })
+            .clone();
+
+        // The limit may have changed since the configuration was reloaded.
+        bucket.lock().unwrap().bytes_per_second = bytes_per_second;
+
+        bucket
+    }
+
+    /// Delays the chunks of `body` so that, along with the other bodies sent to the same user,
+    /// no more than `bytes_per_second` are sent.
+    pub(crate) fn throttle(&self, username: &str, bytes_per_second: u64, body: Body) -> Body {
+        let bucket = self.bucket(username, bytes_per_second);
+
+        trailers::map_chunks(body, move |chunk| {
+            let send_at = bucket.lock().unwrap().reserve(chunk.len());
+
+            async move {
+                tokio::time::sleep_until(send_at).await;
+                chunk
+            }
+        })
+    }
+
+    /// Delays reads from `stream`, the server end of a tunnel, so that along with the other
+    /// transfers of the same user no more than `bytes_per_second` are received.
+    pub(crate) fn throttle_stream<S>(
+        &self,
+        username: &str,
+        bytes_per_second: u64,
+        stream: S,
+    ) -> ThrottledStream<S> {
+        ThrottledStream {
+            stream,
+            bucket: Some(self.bucket(username, bytes_per_second)),
+            delay: None,
+        }
+    }
+}
+
+/// Stream whose reads wait for the time reserved by the previous ones to be over.
+pub(crate) struct ThrottledStream<S> {
+    stream: S,
+    /// Not throttled when `None`.
+    bucket: Option<Arc<Mutex<Bucket>>>,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> ThrottledStream<S> {
+    pub(crate) fn unlimited(stream: S) -> Self {
+        Self {
+            stream,
+            bucket: None,
+            delay: None,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ThrottledStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let bucket = match self.bucket.clone() {
+            Some(bucket) => bucket,
+            None => return Pin::new(&mut self.stream).poll_read(cx, buf),
+        };
+
+        loop {
+            if let Some(delay) = self.delay.as_mut() {
+                ready!(delay.as_mut().poll(cx));
+                self.delay = None;
+            }
+
+            let next_send = bucket.lock().unwrap().next_send;
+            if next_send <= Instant::now() {
+                break;
+            }
+            self.delay = Some(Box::pin(tokio::time::sleep_until(next_send)));
+        }
+
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.stream).poll_read(cx, buf))?;
+        let read = buf.filled().len() - filled;
+        if read > 0 {
+            bucket.lock().unwrap().reserve(read);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ThrottledStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_buckets_are_evicted() {
+        let limiter = BandwidthLimiter::new();
+
+        let held = limiter.bucket("held", 1024);
+        drop(limiter.bucket("idle", 1024));
+        limiter.bucket("other", 1024);
+
+        let buckets = limiter.0.lock().unwrap();
+        assert!(buckets.contains_key("held"));
+        assert!(!buckets.contains_key("idle"));
+        drop(held);
+    }
+
+    #[test]
+    fn buckets_with_pending_reservations_are_kept() {
+        let limiter = BandwidthLimiter::new();
+
+        limiter
+            .bucket("busy", 1024)
+            .lock()
+            .unwrap()
+            .reserve(1024 * 60);
+        limiter.bucket("other", 1024);
+
+        assert!(limiter.0.lock().unwrap().contains_key("busy"));
+    }
+}
@@ -0,0 +1,738 @@
+//! A shared HTTP cache, as described by RFC 7234.
+//!
+//! Only complete `GET` responses are stored, in memory. Stale entries carrying validators
+//! are revalidated upstream with conditional requests. Concurrent misses of the same resource
+//! can be coalesced, a single request is sent upstream and the others are served its response
+//! once stored.
+use crate::configuration::CacheConfig;
+use futures_util::Stream;
+use hyper::body::Bytes;
+use hyper::{http, Body, Response, StatusCode, Uri};
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+
+/// Status codes that are cacheable by default (RFC 7231, section 6.1).
+const CACHEABLE_STATUS_CODES: [u16; 8] = [200, 203, 204, 300, 301, 404, 405, 410];
+/// How long a coalesced request waits for the fetch in progress before sending its own.
+pub(crate) const COALESCING_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub(crate) struct CachedResponse {
+    status: StatusCode,
+    headers: http::HeaderMap,
+    body: Bytes,
+    /// Values of the request headers listed in `Vary`, when the response was stored.
+    vary: Vec<(http::HeaderName, Option<http::HeaderValue>)>,
+    stored_at: SystemTime,
+    freshness_lifetime: Duration,
+}
+
+impl CachedResponse {
+    fn size(&self) -> usize {
+        self.body.len()
+    }
+
+    fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.stored_at)
+            .unwrap_or_default()
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.age() < self.freshness_lifetime
+    }
+
+    fn matches(&self, request_headers: &http::HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| request_headers.get(name) == value.as_ref())
+    }
+
+    fn has_validators(&self) -> bool {
+        self.headers.contains_key(http::header::ETAG)
+            || self.headers.contains_key(http::header::LAST_MODIFIED)
+    }
+
+    /// Adds the validators of this response to a request, turning it into a conditional request.
+    pub(crate) fn add_validators(&self, request_headers: &mut http::HeaderMap) {
+        if let Some(etag) = self.headers.get(http::header::ETAG) {
+            request_headers.insert(http::header::IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = self.headers.get(http::header::LAST_MODIFIED) {
+            request_headers.insert(http::header::IF_MODIFIED_SINCE, last_modified.clone());
+        }
+    }
+
+    pub(crate) fn to_response(&self) -> Response<Body> {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response.headers_mut().insert(
+            http::header::AGE,
+            http::HeaderValue::from(self.age().as_secs()),
+        );
+
+        response
+    }
+}
+
+pub(crate) enum CacheLookup {
+    /// The response can be served as is.
+    Fresh(CachedResponse),
+    /// The response has to be revalidated before being served.
+    Stale(CachedResponse),
+}
+
+#[derive(Debug, Default)]
+struct CacheEntries {
+    entries: HashMap<String, Vec<CachedResponse>>,
+    /// Keys from the least to the most recently stored, each appearing once, used for eviction.
+    insertion_order: VecDeque<String>,
+    size: usize,
+    /// Upstream fetches of missed resources, by key. Their receivers are notified once the
+    /// fetch is over, by the sender being dropped.
+    in_flight: HashMap<String, watch::Receiver<()>>,
+}
+
+pub(crate) enum Coalescing {
+    /// No fetch of the resource is in progress, this request makes it for the others.
+    Leader(InFlightFetch),
+    /// Another request is fetching the resource, the cache should be looked up again once
+    /// it is done.
+    Follower(watch::Receiver<()>),
+}
+
+/// Fetch of a missed resource, waiting requests are released when it is dropped.
+pub(crate) struct InFlightFetch {
+    cache: ResponseCache,
+    key: String,
+    _done: watch::Sender<()>,
+}
+
+impl Drop for InFlightFetch {
+    fn drop(&mut self) {
+        // Removed before the sender is dropped, so that released requests don't find it.
+        self.cache.0.lock().unwrap().in_flight.remove(&self.key);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ResponseCache(Arc<Mutex<CacheEntries>>);
+
+impl ResponseCache {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    fn key(method: &http::Method, uri: &Uri) -> String {
+        format!("{} {}", method, uri)
+    }
+
+    pub(crate) fn lookup(
+        &self,
+        method: &http::Method,
+        uri: &Uri,
+        request_headers: &http::HeaderMap,
+    ) -> Option<CacheLookup> {
+        // Only complete responses are stored, range requests are forwarded so that seeking
+        // doesn't get the whole resource.
+        if method != http::Method::GET || request_headers.contains_key(http::header::RANGE) {
+            return None;
+        }
+
+        let request_directives = CacheControl::from_headers(request_headers);
+        if request_directives.no_store {
+            return None;
+        }
+
+        let entries = self.0.lock().unwrap();
+        let cached_response = entries
+            .entries
+            .get(&Self::key(method, uri))?
+            .iter()
+            .find(|cached_response| cached_response.matches(request_headers))?
+            .clone();
+
+        if cached_response.is_fresh() && !request_directives.no_cache {
+            Some(CacheLookup::Fresh(cached_response))
+        } else if cached_response.has_validators() {
+            Some(CacheLookup::Stale(cached_response))
+        } else {
+            None
+        }
+    }
+
+    /// Registers the fetch of a missed resource, or returns the one already in progress.
+    /// Requests which can't be served from the cache aren't coalesced.
+    pub(crate) fn coalesce(
+        &self,
+        method: &http::Method,
+        uri: &Uri,
+        request_headers: &http::HeaderMap,
+    ) -> Option<Coalescing> {
+        let request_directives = CacheControl::from_headers(request_headers);
+        if method != http::Method::GET
+            || request_directives.no_store
+            || request_directives.no_cache
+            || request_headers.contains_key(http::header::AUTHORIZATION)
+            || request_headers.contains_key(http::header::RANGE)
+        {
+            return None;
+        }
+
+        let key = Self::key(method, uri);
+        let mut entries = self.0.lock().unwrap();
+
+        if let Some(fetch) = entries.in_flight.get(&key) {
+            return Some(Coalescing::Follower(fetch.clone()));
+        }
+
+        let (done, fetch) = watch::channel(());
+        entries.in_flight.insert(key.clone(), fetch);
+
+        Some(Coalescing::Leader(InFlightFetch {
+            cache: self.clone(),
+            key,
+            _done: done,
+        }))
+    }
+
+    /// Updates a stored response after a successful revalidation.
+    pub(crate) fn refresh(
+        &self,
+        method: &http::Method,
+        uri: &Uri,
+        cached_response: &CachedResponse,
+        not_modified_headers: &http::HeaderMap,
+    ) -> CachedResponse {
+        let mut refreshed = cached_response.clone();
+        for (name, value) in not_modified_headers {
+            if name != http::header::CONTENT_LENGTH {
+                refreshed.headers.insert(name.clone(), value.clone());
+            }
+        }
+        refreshed.stored_at = SystemTime::now();
+        refreshed.freshness_lifetime = freshness_lifetime(&refreshed.headers);
+
+        let mut entries = self.0.lock().unwrap();
+        if let Some(variants) = entries.entries.get_mut(&Self::key(method, uri)) {
+            for variant in variants.iter_mut() {
+                if variant.vary == refreshed.vary {
+                    *variant = refreshed.clone();
+                }
+            }
+        }
+
+        refreshed
+    }
+
+    fn insert(&self, method: &http::Method, uri: &Uri, response: CachedResponse, max_size: usize) {
+        let key = Self::key(method, uri);
+        let mut entries = self.0.lock().unwrap();
+
+        let variants = entries.entries.entry(key.clone()).or_default();
+        let previous_size: usize = variants
+            .iter()
+            .filter(|variant| variant.vary == response.vary)
+            .map(|variant| variant.size())
+            .sum();
+        variants.retain(|variant| variant.vary != response.vary);
+        let size = response.size();
+        variants.push(response);
+
+        entries.size = entries.size - previous_size + size;
+        entries
+            .insertion_order
+            .retain(|stored_key| *stored_key != key);
+        entries.insertion_order.push_back(key);
+
+        while entries.size > max_size {
+            let oldest = match entries.insertion_order.pop_front() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+
+            if let Some(variants) = entries.entries.remove(&oldest) {
+                entries.size -= variants.iter().map(|variant| variant.size()).sum::<usize>();
+            }
+        }
+    }
+
+    /// Wraps the body of an upstream response so that it gets stored once fully received,
+    /// should it be cacheable. Requests coalesced with `in_flight` are released once it is,
+    /// or as soon as it is known it won't be.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn store_when_complete<S>(
+        &self,
+        cache_config: &CacheConfig,
+        method: &http::Method,
+        uri: &Uri,
+        request_headers: &http::HeaderMap,
+        status: StatusCode,
+        headers: &http::HeaderMap,
+        body: S,
+        in_flight: Option<InFlightFetch>,
+    ) -> Body
+    where
+        S: Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+    {
+        let max_object_size = cache_config.max_object_size_kb as usize * 1024;
+
+        let is_cacheable = method == http::Method::GET
+            && is_cacheable(request_headers, status, headers)
+            && match headers
+                .get(http::header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<usize>().ok())
+            {
+                Some(content_length) => content_length <= max_object_size,
+                None => true,
+            };
+
+        if !is_cacheable {
+            return Body::wrap_stream(body);
+        }
+
+        let vary = headers
+            .get_all(http::header::VARY)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|name| http::HeaderName::from_bytes(name.trim().as_bytes()).ok())
+            .map(|name| {
+                let value = request_headers.get(&name).cloned();
+                (name, value)
+            })
+            .collect();
+
+        let pending = CachedResponse {
+            status,
+            headers: headers.clone(),
+            body: Bytes::new(),
+            vary,
+            stored_at: SystemTime::now(),
+            freshness_lifetime: freshness_lifetime(headers),
+        };
+
+        Body::wrap_stream(CachingStream {
+            inner: Box::pin(body),
+            buffer: Some(Vec::new()),
+            pending: Some(pending),
+            in_flight,
+            cache: self.clone(),
+            method: method.clone(),
+            uri: uri.clone(),
+            max_object_size,
+            max_size: cache_config.max_size_mb as usize * 1024 * 1024,
+        })
+    }
+}
+
+/// Passes chunks through while keeping a copy, the response is stored once the body
+/// has been fully received.
+struct CachingStream {
+    inner: Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>,
+    buffer: Option<Vec<u8>>,
+    pending: Option<CachedResponse>,
+    in_flight: Option<InFlightFetch>,
+    cache: ResponseCache,
+    method: http::Method,
+    uri: Uri,
+    max_object_size: usize,
+    max_size: usize,
+}
+
+impl Stream for CachingStream {
+    type Item = reqwest::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        match this.inner.as_mut().poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                if let Some(buffer) = this.buffer.as_mut() {
+                    if buffer.len() + chunk.len() > this.max_object_size {
+                        this.buffer = None;
+                        this.in_flight = None;
+                    } else {
+                        buffer.extend_from_slice(&chunk);
+                    }
+                }
+
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(Some(Err(err))) => {
+                // Incomplete responses are never stored.
+                this.buffer = None;
+                this.in_flight = None;
+
+                Poll::Ready(Some(Err(err)))
+            }
+            Poll::Ready(None) => {
+                if let (Some(buffer), Some(mut pending)) = (this.buffer.take(), this.pending.take())
+                {
+                    pending.body = Bytes::from(buffer);
+                    this.cache
+                        .insert(&this.method, &this.uri, pending, this.max_size);
+                }
+                this.in_flight = None;
+
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    private: bool,
+    max_age: Option<u64>,
+    s_maxage: Option<u64>,
+}
+
+impl CacheControl {
+    fn from_headers(headers: &http::HeaderMap) -> Self {
+        let mut cache_control = Self::default();
+
+        let directives = headers
+            .get_all(http::header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+
+        for directive in directives {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+
+            match name.to_lowercase().as_str() {
+                "no-store" => cache_control.no_store = true,
+                "no-cache" => cache_control.no_cache = true,
+                "private" => cache_control.private = true,
+                "max-age" => cache_control.max_age = value.and_then(|v| v.parse().ok()),
+                "s-maxage" => cache_control.s_maxage = value.and_then(|v| v.parse().ok()),
+                _ => {}
+            }
+        }
+
+        // `Pragma: no-cache` is the HTTP/1.0 equivalent of `Cache-Control: no-cache`.
+        if headers
+            .get(http::header::PRAGMA)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.contains("no-cache"))
+            .unwrap_or(false)
+        {
+            cache_control.no_cache = true;
+        }
+
+        cache_control
+    }
+}
+
+fn is_cacheable(
+    request_headers: &http::HeaderMap,
+    status: StatusCode,
+    headers: &http::HeaderMap,
+) -> bool {
+    if !CACHEABLE_STATUS_CODES.contains(&status.as_u16()) {
+        return false;
+    }
+
+    let request_directives = CacheControl::from_headers(request_headers);
+    let directives = CacheControl::from_headers(headers);
+
+    // We are a shared cache, responses to authenticated requests and private
+    // responses must not be stored.
+    if request_directives.no_store
+        || request_headers.contains_key(http::header::AUTHORIZATION)
+        || directives.no_store
+        || directives.private
+        || headers.contains_key(http::header::SET_COOKIE)
+    {
+        return false;
+    }
+
+    let vary_all = headers
+        .get_all(http::header::VARY)
+        .iter()
+        .any(|value| value.to_str().map(|v| v.contains('*')).unwrap_or(true));
+    if vary_all {
+        return false;
+    }
+
+    !freshness_lifetime(headers).is_zero()
+        || headers.contains_key(http::header::ETAG)
+        || headers.contains_key(http::header::LAST_MODIFIED)
+}
+
+fn parse_http_date(headers: &http::HeaderMap, name: http::HeaderName) -> Option<SystemTime> {
+    let value = headers.get(name)?.to_str().ok()?;
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+
+    Some(SystemTime::from(date))
+}
+
+/// Computes how long a response stays fresh (RFC 7234, section 4.2.1).
+fn freshness_lifetime(headers: &http::HeaderMap) -> Duration {
+    let directives = CacheControl::from_headers(headers);
+
+    if directives.no_cache {
+        return Duration::ZERO;
+    }
+
+    if let Some(s_maxage) = directives.s_maxage {
+        return Duration::from_secs(s_maxage);
+    }
+
+    if let Some(max_age) = directives.max_age {
+        return Duration::from_secs(max_age);
+    }
+
+    let date = parse_http_date(headers, http::header::DATE).unwrap_or_else(SystemTime::now);
+
+    if let Some(expires) = parse_http_date(headers, http::header::EXPIRES) {
+        return expires.duration_since(date).unwrap_or_default();
+    }
+
+    // Heuristic freshness, 10% of the time since the last modification.
+    if let Some(last_modified) = parse_http_date(headers, http::header::LAST_MODIFIED) {
+        return date.duration_since(last_modified).unwrap_or_default() / 10;
+    }
+
+    Duration::ZERO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(headers: &[(http::HeaderName, &'static str)]) -> http::HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| (name.clone(), http::HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    fn cached_response(body: &'static [u8], headers: http::HeaderMap) -> CachedResponse {
+        CachedResponse {
+            status: StatusCode::OK,
+            freshness_lifetime: freshness_lifetime(&headers),
+            headers,
+            body: Bytes::from_static(body),
+            vary: Vec::new(),
+            stored_at: SystemTime::now(),
+        }
+    }
+
+    fn uri(path: &str) -> Uri {
+        format!("http://example.com{}", path).parse().unwrap()
+    }
+
+    #[test]
+    fn fresh_responses_are_served_as_is() {
+        let cache = ResponseCache::new();
+        let response = cached_response(
+            b"body",
+            headers(&[(http::header::CACHE_CONTROL, "max-age=60")]),
+        );
+        cache.insert(&http::Method::GET, &uri("/"), response, usize::MAX);
+
+        let lookup = cache.lookup(&http::Method::GET, &uri("/"), &http::HeaderMap::new());
+
+        assert!(matches!(lookup, Some(CacheLookup::Fresh(_))));
+    }
+
+    #[test]
+    fn stale_responses_with_validators_are_revalidated() {
+        let cache = ResponseCache::new();
+        let response = cached_response(
+            b"body",
+            headers(&[
+                (http::header::CACHE_CONTROL, "max-age=0"),
+                (http::header::ETAG, "\"v1\""),
+            ]),
+        );
+        cache.insert(&http::Method::GET, &uri("/"), response, usize::MAX);
+
+        let lookup = cache.lookup(&http::Method::GET, &uri("/"), &http::HeaderMap::new());
+
+        assert!(matches!(lookup, Some(CacheLookup::Stale(_))));
+    }
+
+    #[test]
+    fn stale_responses_without_validators_are_not_served() {
+        let cache = ResponseCache::new();
+        let response = cached_response(
+            b"body",
+            headers(&[(http::header::CACHE_CONTROL, "max-age=0")]),
+        );
+        cache.insert(&http::Method::GET, &uri("/"), response, usize::MAX);
+
+        assert!(cache
+            .lookup(&http::Method::GET, &uri("/"), &http::HeaderMap::new())
+            .is_none());
+    }
+
+    #[test]
+    fn no_cache_requests_revalidate_fresh_responses() {
+        let cache = ResponseCache::new();
+        let response = cached_response(
+            b"body",
+            headers(&[
+                (http::header::CACHE_CONTROL, "max-age=60"),
+                (http::header::ETAG, "\"v1\""),
+            ]),
+        );
+        cache.insert(&http::Method::GET, &uri("/"), response, usize::MAX);
+
+        let lookup = cache.lookup(
+            &http::Method::GET,
+            &uri("/"),
+            &headers(&[(http::header::CACHE_CONTROL, "no-cache")]),
+        );
+
+        assert!(matches!(lookup, Some(CacheLookup::Stale(_))));
+    }
+
+    #[test]
+    fn freshness_lifetime_prefers_s_maxage() {
+        let lifetime = freshness_lifetime(&headers(&[(
+            http::header::CACHE_CONTROL,
+            "max-age=60, s-maxage=120",
+        )]));
+
+        assert_eq!(lifetime, Duration::from_secs(120));
+    }
+
+    #[test]
+    fn variants_are_selected_by_the_headers_they_vary_on() {
+        let cache = ResponseCache::new();
+        for (language, body) in [("en", &b"hello"[..]), ("fr", &b"bonjour"[..])] {
+            let mut response = cached_response(
+                body,
+                headers(&[
+                    (http::header::CACHE_CONTROL, "max-age=60"),
+                    (http::header::VARY, "Accept-Language"),
+                ]),
+            );
+            response.vary = vec![(
+                http::header::ACCEPT_LANGUAGE,
+                Some(http::HeaderValue::from_static(language)),
+            )];
+            cache.insert(&http::Method::GET, &uri("/"), response, usize::MAX);
+        }
+
+        let lookup = cache.lookup(
+            &http::Method::GET,
+            &uri("/"),
+            &headers(&[(http::header::ACCEPT_LANGUAGE, "fr")]),
+        );
+        match lookup {
+            Some(CacheLookup::Fresh(response)) => assert_eq!(response.body, &b"bonjour"[..]),
+            _ => panic!("the French variant should be fresh"),
+        }
+
+        assert!(cache
+            .lookup(
+                &http::Method::GET,
+                &uri("/"),
+                &headers(&[(http::header::ACCEPT_LANGUAGE, "de")]),
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn least_recently_stored_responses_are_evicted_first() {
+        let cache = ResponseCache::new();
+        let fresh = || headers(&[(http::header::CACHE_CONTROL, "max-age=60")]);
+
+        cache.insert(
+            &http::Method::GET,
+            &uri("/a"),
+            cached_response(b"aaaa", fresh()),
+            8,
+        );
+        cache.insert(
+            &http::Method::GET,
+            &uri("/b"),
+            cached_response(b"bbbb", fresh()),
+            8,
+        );
+        cache.insert(
+            &http::Method::GET,
+            &uri("/c"),
+            cached_response(b"cccc", fresh()),
+            8,
+        );
+
+        let no_headers = http::HeaderMap::new();
+        assert!(cache
+            .lookup(&http::Method::GET, &uri("/a"), &no_headers)
+            .is_none());
+        assert!(cache
+            .lookup(&http::Method::GET, &uri("/b"), &no_headers)
+            .is_some());
+        assert!(cache
+            .lookup(&http::Method::GET, &uri("/c"), &no_headers)
+            .is_some());
+        assert_eq!(cache.0.lock().unwrap().size, 8);
+    }
+
+    #[test]
+    fn replaced_responses_are_stored_once() {
+        let cache = ResponseCache::new();
+        let fresh = || headers(&[(http::header::CACHE_CONTROL, "max-age=60")]);
+
+        cache.insert(
+            &http::Method::GET,
+            &uri("/a"),
+            cached_response(b"aaaa", fresh()),
+            8,
+        );
+        cache.insert(
+            &http::Method::GET,
+            &uri("/b"),
+            cached_response(b"bbbb", fresh()),
+            8,
+        );
+        // Stored again, `/a` is now the most recent and `/b` goes first.
+        cache.insert(
+            &http::Method::GET,
+            &uri("/a"),
+            cached_response(b"AAAA", fresh()),
+            8,
+        );
+        cache.insert(
+            &http::Method::GET,
+            &uri("/c"),
+            cached_response(b"cccc", fresh()),
+            8,
+        );
+
+        let no_headers = http::HeaderMap::new();
+        assert!(cache
+            .lookup(&http::Method::GET, &uri("/a"), &no_headers)
+            .is_some());
+        assert!(cache
+            .lookup(&http::Method::GET, &uri("/b"), &no_headers)
+            .is_none());
+        assert!(cache
+            .lookup(&http::Method::GET, &uri("/c"), &no_headers)
+            .is_some());
+
+        let entries = cache.0.lock().unwrap();
+        assert_eq!(
+            entries.insertion_order,
+            ["/a", "/c"].map(|path| ResponseCache::key(&http::Method::GET, &uri(path)))
+        );
+        assert_eq!(entries.size, 8);
+    }
+}
@@ -0,0 +1,543 @@
+//! Debug captures: requests of a client or a domain are recorded and exported as a HAR file,
+//! so that a site breaking through Privaxy can be looked into.
+use super::trailers;
+use crate::configuration::is_within_domain;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use http::uri::{Authority, Scheme};
+use hyper::{Body, HeaderMap, Request, Response, Version};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use utoipa::ToSchema;
+
+const DEFAULT_MAX_BODY_KB: u64 = 512;
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+/// Headers carrying credentials, whose values are left out of captures unless asked for.
+const CREDENTIAL_HEADERS: [http::HeaderName; 4] = [
+    http::header::AUTHORIZATION,
+    http::header::PROXY_AUTHORIZATION,
+    http::header::COOKIE,
+    http::header::SET_COOKIE,
+];
+const REDACTED: &str = "[redacted]";
+
+fn default_max_body_kb() -> u64 {
+    DEFAULT_MAX_BODY_KB
+}
+
+fn default_max_entries() -> usize {
+    DEFAULT_MAX_ENTRIES
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+/// What a capture records
+pub(crate) struct CaptureSettings {
+    /// IP address or username of the client whose requests are recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) client: Option<String>,
+    /// Domain whose requests are recorded, along with its subdomains.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) domain: Option<String>,
+    /// Record request and response bodies as well.
+    #[serde(default)]
+    pub(crate) include_bodies: bool,
+    /// Record the values of `Cookie`, `Set-Cookie` and authorization headers. They are redacted
+    /// otherwise, as anyone downloading the capture could take over the sessions they hold.
+    #[serde(default)]
+    pub(crate) include_credentials: bool,
+    /// Bodies are truncated to this size, in kilobytes.
+    #[serde(default = "default_max_body_kb")]
+    pub(crate) max_body_kb: u64,
+    /// Requests made once this many are recorded are left out.
+    #[serde(default = "default_max_entries")]
+    pub(crate) max_entries: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+/// The current or last capture
+pub(crate) struct CaptureStatus {
+    active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    settings: Option<CaptureSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    started_at: Option<DateTime<Utc>>,
+    entries: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct HarHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarPostData {
+    mime_type: String,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    method: String,
+    url: String,
+    http_version: String,
+    cookies: Vec<HarHeader>,
+    headers: Vec<HarHeader>,
+    query_string: Vec<HarHeader>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_data: Option<HarPostData>,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarContent {
+    size: i64,
+    mime_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarResponse {
+    status: u16,
+    status_text: String,
+    http_version: String,
+    cookies: Vec<HarHeader>,
+    headers: Vec<HarHeader>,
+    content: HarContent,
+    #[serde(rename = "redirectURL")]
+    redirect_url: String,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct HarTimings {
+    send: f64,
+    wait: f64,
+    receive: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarEntry {
+    started_date_time: DateTime<Utc>,
+    time: f64,
+    request: HarRequest,
+    response: HarResponse,
+    cache: serde_json::Value,
+    timings: HarTimings,
+    /// Client the request was made by, custom fields are prefixed by an underscore.
+    #[serde(rename = "_client")]
+    client: String,
+}
+
+struct Capture {
+    active: bool,
+    settings: CaptureSettings,
+    started_at: DateTime<Utc>,
+    entries: Vec<Arc<Mutex<HarEntry>>>,
+}
+
+impl Capture {
+    fn matches(
+        &self,
+        client_ip_address: IpAddr,
+        client_username: Option<&str>,
+        host: &str,
+    ) -> bool {
+        let is_client_matching = self.settings.client.as_deref().is_none_or(|client| {
+            client_ip_address.to_string() == client || client_username == Some(client)
+        });
+        let is_domain_matching = self
+            .settings
+            .domain
+            .as_deref()
+            .is_none_or(|domain| is_within_domain(host, domain));
+
+        is_client_matching && is_domain_matching
+    }
+}
+
+/// The capture requests are recorded into, if any. The last capture is kept once stopped so
+/// that it can still be downloaded.
+#[derive(Clone, Default)]
+pub(crate) struct CaptureStore(Arc<Mutex<Option<Capture>>>);
+
+impl CaptureStore {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a capture, discarding the previous one.
+    pub(crate) fn start(&self, settings: CaptureSettings) {
+        log::info!("Starting debug capture: {:?}", settings);
+
+        *self.0.lock().unwrap() = Some(Capture {
+            active: true,
+            settings,
+            started_at: Utc::now(),
+            entries: Vec::new(),
+        });
+    }
+
+    pub(crate) fn stop(&self) {
+        if let Some(capture) = self.0.lock().unwrap().as_mut() {
+            log::info!("Stopping debug capture");
+            capture.active = false;
+        }
+    }
+
+    pub(crate) fn status(&self) -> CaptureStatus {
+        match &*self.0.lock().unwrap() {
+            Some(capture) => CaptureStatus {
+                active: capture.active,
+                settings: Some(capture.settings.clone()),
+                started_at: Some(capture.started_at),
+                entries: capture.entries.len(),
+            },
+            None => CaptureStatus {
+                active: false,
+                settings: None,
+                started_at: None,
+                entries: 0,
+            },
+        }
+    }
+
+    /// The capture as a HAR 1.2 document, `None` when there never was one.
+    pub(crate) fn to_har(&self) -> Option<serde_json::Value> {
+        let capture = self.0.lock().unwrap();
+        let capture = capture.as_ref()?;
+
+        let entries = capture
+            .entries
+            .iter()
+            .map(|entry| entry.lock().unwrap().clone())
+            .collect::<Vec<_>>();
+
+        Some(serde_json::json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": "Privaxy",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": entries,
+            }
+        }))
+    }
+
+    /// Records `request` when a capture is active and matches it. The recording is completed
+    /// with [`CapturedRequest::record_response`].
+    pub(crate) fn record_request(
+        &self,
+        request: Request<Body>,
+        scheme: &Scheme,
+        authority: &Authority,
+        client_ip_address: IpAddr,
+        client_username: Option<&str>,
+    ) -> (Request<Body>, Option<CapturedRequest>) {
+        let mut capture = self.0.lock().unwrap();
+        let capture = match capture.as_mut() {
+            Some(capture)
+                if capture.active
+                    && capture.entries.len() < capture.settings.max_entries
+                    && capture.matches(client_ip_address, client_username, authority.host()) =>
+            {
+                capture
+            }
+            _ => return (request, None),
+        };
+
+        let url = format!(
+            "{}://{}{}",
+            scheme,
+            authority,
+            request
+                .uri()
+                .path_and_query()
+                .map_or("/", |path_and_query| path_and_query.as_str())
+        );
+        let query_string = url::Url::parse(&url)
+            .map(|url| {
+                url.query_pairs()
+                    .map(|(name, value)| HarHeader {
+                        name: name.into_owned(),
+                        value: value.into_owned(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let entry = Arc::new(Mutex::new(HarEntry {
+            started_date_time: Utc::now(),
+            time: 0.0,
+            request: HarRequest {
+                method: request.method().to_string(),
+                url,
+                http_version: http_version(request.version()),
+                cookies: Vec::new(),
+                headers: har_headers(request.headers(), capture.settings.include_credentials),
+                query_string,
+                post_data: None,
+                headers_size: -1,
+                body_size: -1,
+            },
+            response: HarResponse::default(),
+            cache: serde_json::json!({}),
+            timings: HarTimings::default(),
+            client: client_username
+                .map(str::to_string)
+                .unwrap_or_else(|| client_ip_address.to_string()),
+        }));
+        capture.entries.push(entry.clone());
+
+        let captured_request = CapturedRequest {
+            entry,
+            started_at: Instant::now(),
+            max_body_size: capture
+                .settings
+                .include_bodies
+                .then(|| capture.settings.max_body_kb as usize * 1024),
+            include_credentials: capture.settings.include_credentials,
+        };
+
+        // Requests without a body are left as they are, their body would otherwise be sent
+        // chunked.
+        let has_body = request.headers().get(http::header::CONTENT_LENGTH).map_or(
+            request
+                .headers()
+                .contains_key(http::header::TRANSFER_ENCODING),
+            |content_length| content_length != "0",
+        );
+
+        let request = match captured_request.max_body_size {
+            Some(max_body_size) if has_body => {
+                let mime_type = content_type(request.headers());
+                let entry = captured_request.entry.clone();
+                let (parts, body) = request.into_parts();
+
+                let body = record_body(body, max_body_size, move |body, size| {
+                    let mut entry = entry.lock().unwrap();
+                    entry.request.body_size = size as i64;
+                    if size > 0 {
+                        let (text, encoding) = body_text(body);
+                        entry.request.post_data = Some(HarPostData {
+                            mime_type,
+                            text,
+                            encoding,
+                        });
+                    }
+                });
+
+                Request::from_parts(parts, body)
+            }
+            _ => request,
+        };
+
+        (request, Some(captured_request))
+    }
+}
+
+/// A request being recorded.
+pub(crate) struct CapturedRequest {
+    entry: Arc<Mutex<HarEntry>>,
+    started_at: Instant,
+    /// `None` when bodies are not recorded.
+    max_body_size: Option<usize>,
+    include_credentials: bool,
+}
+
+impl CapturedRequest {
+    pub(crate) fn record_response(self, response: Response<Body>) -> Response<Body> {
+        let wait = self.started_at.elapsed();
+
+        {
+            let mut entry = self.entry.lock().unwrap();
+            let headers = response.headers();
+            entry.time = wait.as_secs_f64() * 1000.0;
+            entry.timings.wait = entry.time;
+            entry.response = HarResponse {
+                status: response.status().as_u16(),
+                status_text: response
+                    .status()
+                    .canonical_reason()
+                    .unwrap_or_default()
+                    .to_string(),
+                http_version: http_version(response.version()),
+                cookies: Vec::new(),
+                headers: har_headers(headers, self.include_credentials),
+                content: HarContent {
+                    size: -1,
+                    mime_type: content_type(headers),
+                    text: None,
+                    encoding: None,
+                },
+                redirect_url: headers
+                    .get(http::header::LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string(),
+                headers_size: -1,
+                body_size: -1,
+            };
+        }
+
+        let entry = self.entry;
+        let started_at = self.started_at;
+        let max_body_size = self.max_body_size.unwrap_or(0);
+        let include_body = self.max_body_size.is_some();
+        let (parts, body) = response.into_parts();
+
+        // The body size and the time it took to receive are known once it has been forwarded.
+        let body = record_body(body, max_body_size, move |body, size| {
+            let mut entry = entry.lock().unwrap();
+            let time = started_at.elapsed().as_secs_f64() * 1000.0;
+            entry.timings.receive = time - entry.timings.wait;
+            entry.time = time;
+            entry.response.body_size = size as i64;
+            entry.response.content.size = size as i64;
+            if include_body {
+                let (text, encoding) = body_text(body);
+                entry.response.content.text = Some(text);
+                entry.response.content.encoding = encoding;
+            }
+        });
+
+        Response::from_parts(parts, body)
+    }
+}
+
+fn http_version(version: Version) -> String {
+    format!("{:?}", version)
+}
+
+fn har_headers(headers: &HeaderMap, include_credentials: bool) -> Vec<HarHeader> {
+    headers
+        .iter()
+        .map(|(name, value)| HarHeader {
+            name: name.to_string(),
+            value: if include_credentials || !CREDENTIAL_HEADERS.contains(name) {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            } else {
+                REDACTED.to_string()
+            },
+        })
+        .collect()
+}
+
+fn content_type(headers: &HeaderMap) -> String {
+    headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Bodies are kept as text when they are valid UTF-8, and encoded in base64 otherwise.
+fn body_text(body: Vec<u8>) -> (String, Option<&'static str>) {
+    match String::from_utf8(body) {
+        Ok(text) => (text, None),
+        Err(err) => (
+            base64::engine::general_purpose::STANDARD.encode(err.into_bytes()),
+            Some("base64"),
+        ),
+    }
+}
+
+/// Keeps up to `max_size` bytes of `body` as it is forwarded, `on_end` is given them along
+/// with the size of the whole body once it is done or dropped.
+fn record_body<F>(body: Body, max_size: usize, on_end: F) -> Body
+where
+    F: FnOnce(Vec<u8>, usize) + Send + 'static,
+{
+    let mut recorder = BodyRecorder {
+        body: Vec::new(),
+        size: 0,
+        max_size,
+        on_end: Some(on_end),
+    };
+
+    trailers::map_chunks(body, move |chunk| {
+        recorder.record(&chunk);
+        futures::future::ready(chunk)
+    })
+}
+
+struct BodyRecorder<F: FnOnce(Vec<u8>, usize)> {
+    body: Vec<u8>,
+    size: usize,
+    max_size: usize,
+    on_end: Option<F>,
+}
+
+impl<F: FnOnce(Vec<u8>, usize)> BodyRecorder<F> {
+    fn record(&mut self, chunk: &[u8]) {
+        self.size += chunk.len();
+        let remaining = self.max_size.saturating_sub(self.body.len());
+        self.body
+            .extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+    }
+}
+
+impl<F: FnOnce(Vec<u8>, usize)> Drop for BodyRecorder<F> {
+    fn drop(&mut self) {
+        if let Some(on_end) = self.on_end.take() {
+            on_end(std::mem::take(&mut self.body), self.size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::COOKIE, "session=secret".parse().unwrap());
+        headers.insert(
+            http::header::AUTHORIZATION,
+            "Bearer secret".parse().unwrap(),
+        );
+        headers.insert(http::header::ACCEPT, "text/html".parse().unwrap());
+
+        headers
+    }
+
+    fn value<'a>(headers: &'a [HarHeader], name: &str) -> &'a str {
+        &headers
+            .iter()
+            .find(|header| header.name == name)
+            .unwrap()
+            .value
+    }
+
+    #[test]
+    fn credentials_are_redacted_by_default() {
+        let headers = har_headers(&headers(), false);
+
+        assert_eq!(value(&headers, "cookie"), REDACTED);
+        assert_eq!(value(&headers, "authorization"), REDACTED);
+        assert_eq!(value(&headers, "accept"), "text/html");
+    }
+
+    #[test]
+    fn credentials_are_recorded_when_asked_for() {
+        let headers = har_headers(&headers(), true);
+
+        assert_eq!(value(&headers, "cookie"), "session=secret");
+        assert_eq!(value(&headers, "authorization"), "Bearer secret");
+    }
+}
@@ -0,0 +1,95 @@
+use crate::blocker::BlockingDisabledStore;
+use crate::configuration::Category;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+
+/// Domains of every loaded category. A domain also matches its subdomains.
+#[derive(Debug, Clone)]
+pub(crate) struct CategoryStore {
+    domains: Arc<RwLock<HashMap<Category, HashSet<String>>>>,
+    blocking_disabled: BlockingDisabledStore,
+}
+
+impl CategoryStore {
+    pub(crate) fn new(blocking_disabled: BlockingDisabledStore) -> Self {
+        Self {
+            domains: Arc::new(RwLock::new(HashMap::new())),
+            blocking_disabled,
+        }
+    }
+
+    pub(crate) fn replace_lists(&self, lists: Vec<(Category, String)>) {
+        let mut domains: HashMap<Category, HashSet<String>> = HashMap::new();
+
+        for (category, contents) in lists {
+            domains
+                .entry(category)
+                .or_default()
+                .extend(contents.lines().filter_map(parse_line));
+        }
+
+        for (category, domains) in &domains {
+            log::debug!("Loaded {} domains for category {}", domains.len(), category);
+        }
+
+        *self.domains.write().unwrap() = domains;
+    }
+
+    /// Returns the first of `blocked` categories `host` belongs to, if any.
+    pub(crate) fn find_blocked(
+        &self,
+        host: &str,
+        blocked: &BTreeSet<Category>,
+    ) -> Option<Category> {
+        if blocked.is_empty() || !self.blocking_disabled.is_enabled() {
+            return None;
+        }
+
+        let host = host.trim_end_matches('.').to_lowercase();
+        // Addresses have no parent domains.
+        if host.parse::<IpAddr>().is_ok() {
+            return None;
+        }
+
+        let domains = self.domains.read().unwrap();
+
+        blocked.iter().copied().find(|category| {
+            let domains = match domains.get(category) {
+                Some(domains) => domains,
+                None => return false,
+            };
+
+            let mut domain = host.as_str();
+            loop {
+                if domains.contains(domain) {
+                    return true;
+                }
+                match domain.split_once('.') {
+                    Some((_, parent)) if parent.contains('.') => domain = parent,
+                    _ => return false,
+                }
+            }
+        })
+    }
+}
+
+/// Extracts the domain of a hosts file (`0.0.0.0 example.com`) or plain list line.
+fn parse_line(line: &str) -> Option<String> {
+    let line = line.split('#').next().unwrap_or_default();
+    let mut fields = line.split_whitespace();
+
+    let first = fields.next()?;
+    let domain = if first.parse::<IpAddr>().is_ok() {
+        fields.next()?
+    } else {
+        first
+    };
+
+    let domain = domain.trim_end_matches('.').to_lowercase();
+    if !domain.contains('.') || domain.parse::<IpAddr>().is_ok() {
+        return None;
+    }
+
+    Some(domain)
+}
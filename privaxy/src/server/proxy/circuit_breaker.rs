@@ -0,0 +1,89 @@
+use crate::configuration::RetriesConfig;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uluru::LRUCache;
+
+const MAX_HOSTS: usize = 1_000;
+
+#[derive(Debug)]
+struct HostState {
+    host: String,
+    /// Failures in a row.
+    failures: u32,
+    /// Set while the host is considered down.
+    opened_at: Option<Instant>,
+}
+
+/// Keeps track of hosts whose requests keep failing, so that requests to hosts which
+/// are down fail right away instead of waiting for each connection attempt.
+#[derive(Debug, Clone, Default)]
+pub struct CircuitBreaker(Arc<Mutex<LRUCache<HostState, MAX_HOSTS>>>);
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// For how long requests to `host` keep failing right away, `None` when they go through.
+    pub(crate) fn open_for(&self, host: &str, config: &RetriesConfig) -> Option<Duration> {
+        let host = host.to_lowercase();
+        let mut hosts = self.0.lock().unwrap();
+        let state = hosts.find(|state| state.host == host)?;
+
+        let remaining = config
+            .breaker_open_for()
+            .checked_sub(state.opened_at?.elapsed())
+            .filter(|remaining| !remaining.is_zero());
+        if remaining.is_none() {
+            // Half open: this request checks whether the host is back, others wait for it.
+            state.opened_at = Some(Instant::now());
+            return None;
+        }
+
+        remaining
+    }
+
+    pub(crate) fn record_success(&self, host: &str) {
+        let host = host.to_lowercase();
+        let mut hosts = self.0.lock().unwrap();
+
+        if let Some(state) = hosts.find(|state| state.host == host) {
+            if state.opened_at.is_some() {
+                log::info!("{} is reachable again", host);
+            }
+            state.failures = 0;
+            state.opened_at = None;
+        }
+    }
+
+    pub(crate) fn record_failure(&self, host: &str, config: &RetriesConfig) {
+        if config.breaker_failures == 0 {
+            return;
+        }
+
+        let host = host.to_lowercase();
+        let mut hosts = self.0.lock().unwrap();
+
+        // Found hosts are moved to the front, as are inserted ones.
+        if hosts.find(|state| state.host == host).is_none() {
+            hosts.insert(HostState {
+                host: host.clone(),
+                failures: 0,
+                opened_at: None,
+            });
+        }
+        let state = hosts.front_mut().unwrap();
+
+        state.failures = state.failures.saturating_add(1);
+        if state.failures >= config.breaker_failures {
+            if state.opened_at.is_none() {
+                log::warn!(
+                    "{} failed {} times in a row, considering it down",
+                    host,
+                    state.failures
+                );
+            }
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use rustls::client::{ResolvesClientCert, ServerCertVerified, ServerCertVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{Certificate, ClientConfig, ServerName, SignatureScheme};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use uluru::LRUCache;
+use utoipa::ToSchema;
+
+const MAX_HOSTS: usize = 1_000;
+
+/// Hosts requesting a client certificate are tunneled for this long
+/// before interception is attempted again.
+const TUNNEL_FOR: Duration = Duration::from_secs(60 * 60 * 24);
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A host which asked for a client certificate.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ClientCertificateHost {
+    pub host: String,
+    /// Seconds since a client certificate was last requested.
+    pub seconds_ago: u64,
+}
+
+/// Keeps track of hosts requesting client certificates, which can't be intercepted
+/// as we don't have the certificate of the client.
+#[derive(Debug, Clone, Default)]
+pub struct ClientCertificateStore(Arc<Mutex<LRUCache<(String, Instant), MAX_HOSTS>>>);
+
+impl ClientCertificateStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn requires_client_certificate(&self, host: &str) -> bool {
+        let host = host.to_lowercase();
+        let mut hosts = self.0.lock().unwrap();
+
+        match hosts.find(|(known_host, _since)| known_host == &host) {
+            Some((_host, since)) => since.elapsed() < TUNNEL_FOR,
+            None => false,
+        }
+    }
+
+    pub fn mark(&self, host: &str) {
+        let host = host.to_lowercase();
+        let mut hosts = self.0.lock().unwrap();
+
+        match hosts.find(|(known_host, _since)| known_host == &host) {
+            Some((_host, since)) => *since = Instant::now(),
+            None => {
+                hosts.insert((host, Instant::now()));
+            }
+        }
+    }
+
+    pub fn hosts(&self) -> Vec<ClientCertificateHost> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_host, since)| since.elapsed() < TUNNEL_FOR)
+            .map(|(host, since)| ClientCertificateHost {
+                host: host.clone(),
+                seconds_ago: since.elapsed().as_secs(),
+            })
+            .collect()
+    }
+}
+
+/// Records whether the server asked for a client certificate, without providing any.
+#[derive(Default)]
+struct CertificateRequestRecorder(AtomicBool);
+
+impl ResolvesClientCert for CertificateRequestRecorder {
+    fn resolve(
+        &self,
+        _acceptable_issuers: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        self.0.store(true, Ordering::Relaxed);
+        None
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+/// The probe sends no data, the certificate of the server doesn't matter. Servers with
+/// certificates from private authorities are common among those requesting client certificates.
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Performs a handshake with `host` and tells whether it requested a client certificate.
+pub(crate) async fn requests_client_certificate(host: &str, port: u16) -> bool {
+    let server_name = match ServerName::try_from(host) {
+        Ok(server_name) => server_name,
+        Err(_) => return false,
+    };
+
+    let recorder = Arc::new(CertificateRequestRecorder::default());
+    let client_config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+        .with_client_cert_resolver(recorder.clone());
+
+    let handshake = async {
+        let stream = TcpStream::connect((host, port)).await.ok()?;
+
+        TlsConnector::from(Arc::new(client_config))
+            .connect(server_name, stream)
+            .await
+            .ok()
+    };
+
+    // The handshake itself is expected to fail when a certificate was requested.
+    let _result = tokio::time::timeout(PROBE_TIMEOUT, handshake).await;
+
+    recorder.0.load(Ordering::Relaxed)
+}
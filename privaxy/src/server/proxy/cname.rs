@@ -0,0 +1,126 @@
+//! CNAME uncloaking: trackers served from a first party subdomain aliased to a tracker domain
+//! evade host based rules, the canonical name is checked as well.
+use super::dns::DnsResolver;
+use super::egress::EgressRouter;
+use hickory_resolver::proto::rr::RData;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Requests are held while resolving, don't let a slow resolver stall them for long.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone)]
+pub(crate) struct CnameResolver(DnsResolver, EgressRouter);
+
+impl CnameResolver {
+    pub(crate) fn new(dns_resolver: DnsResolver, egress_router: EgressRouter) -> Self {
+        Self(dns_resolver, egress_router)
+    }
+
+    pub(crate) fn dns_resolver(&self) -> &DnsResolver {
+        &self.0
+    }
+
+    pub(crate) fn egress_router(&self) -> &EgressRouter {
+        &self.1
+    }
+
+    /// Hosts reached through a proxy are resolved by it, looking them up would leak them.
+    fn is_resolved_by_proxy(&self, host: &str) -> bool {
+        self.1.proxy_for(host).is_some()
+    }
+
+    /// Returns the canonical name of `host` when it is an alias pointing to another site.
+    pub(crate) async fn uncloak(&self, host: &str) -> Option<String> {
+        // Overridden hosts aren't looked up.
+        if host.parse::<IpAddr>().is_ok()
+            || self.0.is_overridden(host)
+            || self.is_resolved_by_proxy(host)
+        {
+            return None;
+        }
+
+        let lookup = match tokio::time::timeout(
+            LOOKUP_TIMEOUT,
+            self.0.lookup_ip(format!("{}.", host)),
+        )
+        .await
+        {
+            Ok(Ok(lookup)) => lookup,
+            Ok(Err(err)) => {
+                log::debug!("Unable to resolve {}: {}", host, err);
+                return None;
+            }
+            Err(_elapsed) => {
+                log::debug!("Timed out resolving {}", host);
+                return None;
+            }
+        };
+
+        // The answer holds the whole chain, the last alias is the canonical name.
+        let canonical_name = lookup
+            .as_lookup()
+            .records()
+            .iter()
+            .filter_map(|record| match record.data() {
+                Some(RData::CNAME(cname)) => Some(cname.0.to_utf8()),
+                _ => None,
+            })
+            .next_back()?;
+        let canonical_name = canonical_name.trim_end_matches('.').to_lowercase();
+
+        if is_same_site(host, &canonical_name) {
+            return None;
+        }
+
+        Some(canonical_name)
+    }
+
+    /// Addresses `host` resolves to, `host` itself when it is an address.
+    /// Empty when `host` is resolved by a proxy.
+    pub(crate) async fn resolve(&self, host: &str) -> Vec<IpAddr> {
+        if self.is_resolved_by_proxy(host) {
+            return Vec::new();
+        }
+
+        match self.0.resolve(host).await {
+            Ok(ip_addresses) => ip_addresses,
+            Err(err) => {
+                log::debug!("Unable to resolve {}: {}", host, err);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Aliases within the same site, such as `www.example.com` to `example.com`, hide nothing.
+/// Sites are registrable domains according to the public suffix list, so that `example.co.uk`
+/// and `tracker.co.uk` are different sites.
+fn is_same_site(host: &str, canonical_name: &str) -> bool {
+    let host = host.trim_end_matches('.').to_lowercase();
+    let canonical_name = canonical_name.trim_end_matches('.').to_lowercase();
+
+    match (psl::domain_str(&host), psl::domain_str(&canonical_name)) {
+        (Some(site), Some(canonical_site)) => site == canonical_site,
+        _ => host == canonical_name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases_within_a_site_are_same_site() {
+        assert!(is_same_site("www.example.com", "example.com"));
+        assert!(is_same_site("WWW.Example.com", "cdn.example.com."));
+        assert!(is_same_site("www.example.co.uk", "static.example.co.uk"));
+    }
+
+    #[test]
+    fn sites_sharing_a_public_suffix_are_different() {
+        assert!(!is_same_site("www.example.co.uk", "tracker.co.uk"));
+        assert!(!is_same_site("shop.github.io", "tracker.github.io"));
+        assert!(!is_same_site("www.example.com", "example.tracker.net"));
+    }
+}
@@ -0,0 +1,54 @@
+use super::bandwidth::BandwidthLimiter;
+use super::cache::ResponseCache;
+use super::capture::CaptureStore;
+use super::categories::CategoryStore;
+use super::circuit_breaker::CircuitBreaker;
+use super::client_certificates::ClientCertificateStore;
+use super::cname::CnameResolver;
+use super::egress::{EgressConnector, UpstreamClients};
+use super::exclusions::TemporaryExclusionStore;
+use super::frame_context::FrameContextStore;
+use super::geoip::GeoIp;
+use super::https_upgrade::HttpsUpgradeStore;
+use super::scripting::RulesScript;
+use super::templates::TemplateStore;
+use super::threats::ThreatStore;
+use crate::blocker::AdblockRequester;
+use crate::configuration::{PrivacyConfig, ProxyConfig};
+use crate::statistics::Statistics;
+use crate::web_gui::events::Event;
+use hyper_rustls::HttpsConnector;
+use tokio::sync::broadcast;
+
+/// Clients, stores and configuration every request served by a run of the proxy shares.
+///
+/// Built once when the proxy starts, and on every reload, then handed to each connection
+/// behind an `Arc`.
+pub(crate) struct ProxyContext {
+    pub(crate) adblock_requester: AdblockRequester,
+    /// Performs upgrades.
+    pub(crate) hyper_client: hyper::Client<HttpsConnector<EgressConnector>>,
+    /// Negotiates HTTP/2, for requests expecting trailers.
+    pub(crate) trailers_client: hyper::Client<HttpsConnector<EgressConnector>>,
+    pub(crate) client: reqwest::Client,
+    /// Clients of the upstreams scripts may pick, by name.
+    pub(crate) upstream_clients: UpstreamClients,
+    pub(crate) broadcast_sender: broadcast::Sender<Event>,
+    pub(crate) statistics: Statistics,
+    pub(crate) proxy_config: ProxyConfig,
+    pub(crate) privacy_config: PrivacyConfig,
+    pub(crate) https_upgrade_store: HttpsUpgradeStore,
+    pub(crate) circuit_breaker: CircuitBreaker,
+    pub(crate) client_certificate_store: ClientCertificateStore,
+    pub(crate) template_store: TemplateStore,
+    pub(crate) temporary_exclusion_store: TemporaryExclusionStore,
+    pub(crate) response_cache: ResponseCache,
+    pub(crate) bandwidth_limiter: BandwidthLimiter,
+    pub(crate) category_store: CategoryStore,
+    pub(crate) threat_store: ThreatStore,
+    pub(crate) cname_resolver: CnameResolver,
+    pub(crate) geoip: GeoIp,
+    pub(crate) rules_script: RulesScript,
+    pub(crate) capture_store: CaptureStore,
+    pub(crate) frame_context_store: FrameContextStore,
+}
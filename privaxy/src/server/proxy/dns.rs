@@ -0,0 +1,285 @@
+//! Resolution of upstream servers. A single cache is shared by the HTTP clients and tunnels,
+//! so that answers are reused across them and host overrides apply everywhere.
+use crate::configuration::{is_within_domain, DnsConfig, DnsProtocol, DnsServers, IpPreference};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use hickory_resolver::config::{
+    LookupIpStrategy, NameServerConfigGroup, ResolverConfig, ResolverOpts,
+};
+use hickory_resolver::error::ResolveError;
+use hickory_resolver::lookup_ip::LookupIp;
+use hickory_resolver::TokioAsyncResolver;
+use hyper::client::connect::dns::Name;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+struct Resolver {
+    config: DnsConfig,
+    resolver: TokioAsyncResolver,
+    /// Resolvers of domains, by domain suffix, most specific first.
+    domain_resolvers: Vec<(String, TokioAsyncResolver)>,
+}
+
+impl Resolver {
+    fn new(config: &DnsConfig) -> Self {
+        let resolver = match &config.upstream {
+            Some(servers) => create_resolver(config, servers),
+            None => create_system_resolver(config),
+        };
+
+        let mut domain_resolvers = config
+            .domains
+            .iter()
+            .map(|domain| (domain.suffix(), create_resolver(config, &domain.servers)))
+            .collect::<Vec<_>>();
+        domain_resolvers.sort_by_key(|(suffix, _resolver)| std::cmp::Reverse(suffix.len()));
+
+        Self {
+            config: config.clone(),
+            resolver,
+            domain_resolvers,
+        }
+    }
+
+    fn resolver_for(&self, host: &str) -> &TokioAsyncResolver {
+        self.domain_resolvers
+            .iter()
+            .find(|(suffix, _resolver)| is_within_domain(host, suffix))
+            .map_or(&self.resolver, |(_suffix, resolver)| resolver)
+    }
+
+    fn clear_cache(&self) {
+        self.resolver.clear_cache();
+        for (_suffix, resolver) in &self.domain_resolvers {
+            resolver.clear_cache();
+        }
+    }
+}
+
+fn configure_opts(config: &DnsConfig, opts: &mut ResolverOpts) {
+    // Both families are needed to fall back on one when the other is broken.
+    opts.ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+    opts.cache_size = config.cache_size;
+    opts.positive_min_ttl = config.min_ttl.map(Duration::from_secs);
+    opts.positive_max_ttl = config.max_ttl.map(Duration::from_secs);
+}
+
+/// Uses the system resolver configuration, falling back on public resolvers.
+fn create_system_resolver(config: &DnsConfig) -> TokioAsyncResolver {
+    let (resolver_config, mut opts) = match hickory_resolver::system_conf::read_system_conf() {
+        Ok(system_conf) => system_conf,
+        Err(err) => {
+            log::warn!("Unable to read system resolver configuration: {}", err);
+            (ResolverConfig::default(), ResolverOpts::default())
+        }
+    };
+    configure_opts(config, &mut opts);
+
+    TokioAsyncResolver::tokio(resolver_config, opts)
+}
+
+fn create_resolver(config: &DnsConfig, servers: &DnsServers) -> TokioAsyncResolver {
+    let port = servers.port.unwrap_or(servers.protocol.default_port());
+    let tls_name = servers.tls_name.clone().unwrap_or_default();
+    let name_servers = match servers.protocol {
+        DnsProtocol::Udp => NameServerConfigGroup::from_ips_clear(&servers.addresses, port, true),
+        DnsProtocol::Tls => {
+            NameServerConfigGroup::from_ips_tls(&servers.addresses, port, tls_name, true)
+        }
+        DnsProtocol::Https => {
+            NameServerConfigGroup::from_ips_https(&servers.addresses, port, tls_name, true)
+        }
+    };
+
+    let mut opts = ResolverOpts::default();
+    configure_opts(config, &mut opts);
+
+    TokioAsyncResolver::tokio(
+        ResolverConfig::from_parts(None, Vec::new(), name_servers),
+        opts,
+    )
+}
+
+#[derive(Clone)]
+pub(crate) struct DnsResolver(Arc<RwLock<Arc<Resolver>>>);
+
+impl DnsResolver {
+    pub(crate) fn new(config: &DnsConfig) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(Resolver::new(config)))))
+    }
+
+    /// Applies `config`, discarding cached answers when it changed.
+    pub(crate) fn configure(&self, config: &DnsConfig) {
+        if self.current().config == *config {
+            return;
+        }
+
+        log::info!("DNS settings changed, discarding cached answers");
+        *self.0.write().unwrap() = Arc::new(Resolver::new(config));
+    }
+
+    pub(crate) fn flush(&self) {
+        log::info!("Flushing DNS cache");
+        self.current().clear_cache();
+    }
+
+    fn current(&self) -> Arc<Resolver> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub(crate) fn is_overridden(&self, host: &str) -> bool {
+        self.current().config.host_override(host).is_some()
+    }
+
+    /// Looks `name` up as is, without applying overrides.
+    pub(crate) async fn lookup_ip(&self, name: String) -> Result<LookupIp, ResolveError> {
+        let resolver = self.current();
+        let lookup = resolver.resolver_for(&name).lookup_ip(name.as_str()).await;
+
+        lookup
+    }
+
+    /// Addresses `host` resolves to, `host` itself when it is an address.
+    pub(crate) async fn resolve(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        // Hosts of URIs hold IPv6 addresses within brackets.
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip_address) = host.parse::<IpAddr>() {
+            return Ok(vec![ip_address]);
+        }
+
+        let resolver = self.current();
+        let ip_preference = resolver.config.ip_preference;
+        if let Some(addresses) = resolver.config.host_override(host) {
+            return Ok(sort_addresses(addresses.to_vec(), ip_preference));
+        }
+
+        let lookup = resolver
+            .resolver_for(host)
+            .lookup_ip(host)
+            .await
+            .map_err(io::Error::other)?;
+
+        Ok(sort_addresses(lookup.iter().collect(), ip_preference))
+    }
+
+    pub(crate) fn connection_attempt_delay(&self) -> Duration {
+        self.current().config.connection_attempt_delay()
+    }
+
+    /// Connects to the first of `addresses` accepting the connection. Attempts are started
+    /// one connection attempt delay apart, or as soon as the previous one failed (RFC 8305).
+    pub(crate) async fn connect(&self, addresses: &[SocketAddr]) -> io::Result<TcpStream> {
+        let attempt_delay = self.connection_attempt_delay();
+        let mut remaining_addresses = addresses.iter();
+        let mut attempts = FuturesUnordered::new();
+        let mut last_error = None;
+
+        loop {
+            if attempts.is_empty() {
+                match remaining_addresses.next() {
+                    Some(address) => attempts.push(TcpStream::connect(*address)),
+                    None => {
+                        return Err(last_error.unwrap_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::AddrNotAvailable,
+                                "No address to connect to",
+                            )
+                        }))
+                    }
+                }
+            }
+
+            tokio::select! {
+                Some(result) = attempts.next() => match result {
+                    Ok(stream) => return Ok(stream),
+                    Err(err) => {
+                        last_error = Some(err);
+                        if let Some(address) = remaining_addresses.next() {
+                            attempts.push(TcpStream::connect(*address));
+                        }
+                    }
+                },
+                _ = tokio::time::sleep(attempt_delay), if remaining_addresses.len() > 0 => {
+                    if let Some(address) = remaining_addresses.next() {
+                        attempts.push(TcpStream::connect(*address));
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn resolve_socket_addresses(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        // Connectors set the port themselves.
+        Ok(self
+            .resolve(host)
+            .await?
+            .into_iter()
+            .map(|ip_address| SocketAddr::new(ip_address, 0))
+            .collect())
+    }
+}
+
+/// Orders addresses by preferred family, alternating families so that a broken one doesn't
+/// delay connections for long.
+fn sort_addresses(addresses: Vec<IpAddr>, ip_preference: IpPreference) -> Vec<IpAddr> {
+    let prefers_ipv6 = match ip_preference {
+        IpPreference::System => addresses.first().is_some_and(IpAddr::is_ipv6),
+        IpPreference::Ipv4 => false,
+        IpPreference::Ipv6 => true,
+    };
+    let (preferred, fallback): (Vec<_>, Vec<_>) = addresses
+        .into_iter()
+        .partition(|address| address.is_ipv6() == prefers_ipv6);
+
+    let mut sorted_addresses = Vec::with_capacity(preferred.len() + fallback.len());
+    let mut preferred = preferred.into_iter();
+    let mut fallback = fallback.into_iter();
+    loop {
+        match (preferred.next(), fallback.next()) {
+            (None, None) => break,
+            (preferred, fallback) => sorted_addresses.extend(preferred.into_iter().chain(fallback)),
+        }
+    }
+
+    sorted_addresses
+}
+
+impl reqwest::dns::Resolve for DnsResolver {
+    fn resolve(&self, name: Name) -> reqwest::dns::Resolving {
+        let resolver = self.clone();
+
+        Box::pin(async move {
+            let addresses = resolver.resolve_socket_addresses(name.as_str()).await?;
+            let addresses: reqwest::dns::Addrs = Box::new(addresses.into_iter());
+
+            Ok(addresses)
+        })
+    }
+}
+
+/// Used by hyper's `HttpConnector`.
+impl tower_service::Service<Name> for DnsResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let resolver = self.clone();
+
+        Box::pin(async move {
+            Ok(resolver
+                .resolve_socket_addresses(name.as_str())
+                .await?
+                .into_iter())
+        })
+    }
+}
@@ -0,0 +1,315 @@
+//! Per-domain egress: selected domains are reached through a SOCKS5 proxy, such as Tor, or an
+//! HTTP proxy instead of directly. Host names are resolved by the proxy so that they don't leak
+//! to local DNS servers, which is also how `.onion` sites are reached.
+use super::parent_proxy;
+use super::pool::CountingResolver;
+use crate::configuration::{EgressConfig, IpNetwork, SocksProxy, Upstream, UpstreamKind};
+use hyper::client::HttpConnector;
+use hyper::Uri;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub(crate) const SOCKS_VERSION: u8 = 0x05;
+const NO_AUTHENTICATION: u8 = 0x00;
+pub(crate) const USERNAME_PASSWORD_AUTHENTICATION: u8 = 0x02;
+pub(crate) const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+pub(crate) const CONNECT_COMMAND: u8 = 0x01;
+pub(crate) const IPV4_ADDRESS: u8 = 0x01;
+pub(crate) const DOMAIN_NAME: u8 = 0x03;
+pub(crate) const IPV6_ADDRESS: u8 = 0x04;
+
+#[derive(Clone)]
+pub(crate) struct EgressRouter(Arc<RwLock<EgressConfig>>);
+
+impl EgressRouter {
+    pub(crate) fn new(config: &EgressConfig) -> Self {
+        Self(Arc::new(RwLock::new(config.clone())))
+    }
+
+    pub(crate) fn configure(&self, config: &EgressConfig) {
+        *self.0.write().unwrap() = config.clone();
+    }
+
+    /// Proxy connections to `host` go through, `None` when they are direct.
+    pub(crate) fn proxy_for(&self, host: &str) -> Option<SocksProxy> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        socks_proxy(&self.0.read().unwrap().upstream_for(host)?.kind)
+    }
+
+    pub(crate) fn reqwest_proxy(&self) -> reqwest::Proxy {
+        let router = self.clone();
+
+        reqwest::Proxy::custom(move |url| socks_url(&router.proxy_for(url.host_str()?)?))
+    }
+}
+
+/// SOCKS proxy connections through `upstream` go through, the relay of HTTP proxies. `None` when
+/// they are direct.
+fn socks_proxy(upstream: &UpstreamKind) -> Option<SocksProxy> {
+    match upstream.http_proxy() {
+        Some(http_proxy) => parent_proxy::relay(&http_proxy)
+            .map_err(|err| {
+                log::error!(
+                    "Unable to relay connections to {}: {}",
+                    http_proxy.address,
+                    err
+                )
+            })
+            .ok(),
+        None => upstream.socks_proxy(),
+    }
+}
+
+/// Proxy of reqwest for `proxy`. `socks5h` has the proxy resolve the host.
+fn socks_url(proxy: &SocksProxy) -> Option<reqwest::Url> {
+    let mut proxy_url = reqwest::Url::parse(&format!("socks5h://{}", proxy.address)).ok()?;
+    if let Some((username, password)) = &proxy.credentials {
+        proxy_url.set_username(username).ok()?;
+        proxy_url.set_password(Some(password)).ok()?;
+    }
+
+    Some(proxy_url)
+}
+
+/// Proxy of reqwest sending every request through `upstream`, `None` when it is direct.
+pub(crate) fn upstream_proxy(upstream: &Upstream) -> Option<reqwest::Proxy> {
+    let proxy_url = socks_url(&socks_proxy(&upstream.kind)?)?;
+
+    reqwest::Proxy::all(proxy_url).ok()
+}
+
+/// Clients sending requests through an upstream regardless of the egress routes, by upstream name.
+pub(crate) type UpstreamClients = Arc<HashMap<String, reqwest::Client>>;
+
+fn socks_error(message: impl Into<String>) -> io::Error {
+    io::Error::other(message.into())
+}
+
+/// Connects to `host:port` through `proxy` (RFC 1928), authenticating with a username and
+/// password when configured (RFC 1929).
+pub(crate) async fn connect_through(
+    proxy: &SocksProxy,
+    host: &str,
+    port: u16,
+) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(proxy.address.as_str()).await?;
+
+    let greeting: &[u8] = match proxy.credentials {
+        Some(_) => &[
+            SOCKS_VERSION,
+            2,
+            NO_AUTHENTICATION,
+            USERNAME_PASSWORD_AUTHENTICATION,
+        ],
+        None => &[SOCKS_VERSION, 1, NO_AUTHENTICATION],
+    };
+    stream.write_all(greeting).await?;
+
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await?;
+    if method[0] != SOCKS_VERSION {
+        return Err(socks_error(format!(
+            "{} is not a SOCKS5 proxy",
+            proxy.address
+        )));
+    }
+
+    match (method[1], &proxy.credentials) {
+        (NO_AUTHENTICATION, _) => {}
+        (USERNAME_PASSWORD_AUTHENTICATION, Some((username, password))) => {
+            if username.len() > 255 || password.len() > 255 {
+                return Err(socks_error("SOCKS credentials are too long"));
+            }
+            let mut authentication = vec![0x01, username.len() as u8];
+            authentication.extend_from_slice(username.as_bytes());
+            authentication.push(password.len() as u8);
+            authentication.extend_from_slice(password.as_bytes());
+            stream.write_all(&authentication).await?;
+
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[1] != 0x00 {
+                return Err(socks_error(format!(
+                    "{} rejected the credentials",
+                    proxy.address
+                )));
+            }
+        }
+        (NO_ACCEPTABLE_METHOD, _) => {
+            return Err(socks_error(format!(
+                "{} accepts none of the offered authentication methods",
+                proxy.address
+            )))
+        }
+        (_, _) => {
+            return Err(socks_error(format!(
+                "{} selected an unsupported authentication method",
+                proxy.address
+            )))
+        }
+    }
+
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let mut request = vec![SOCKS_VERSION, CONNECT_COMMAND, 0x00];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip_address)) => {
+            request.push(IPV4_ADDRESS);
+            request.extend_from_slice(&ip_address.octets());
+        }
+        Ok(IpAddr::V6(ip_address)) => {
+            request.push(IPV6_ADDRESS);
+            request.extend_from_slice(&ip_address.octets());
+        }
+        Err(_) => {
+            if host.len() > 255 {
+                return Err(socks_error(format!("{} is too long", host)));
+            }
+            request.push(DOMAIN_NAME);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0x00 {
+        return Err(socks_error(format!(
+            "{} failed to connect to {}:{}: {}",
+            proxy.address,
+            host,
+            port,
+            reply_message(reply[1])
+        )));
+    }
+
+    // The address the proxy bound to is of no use.
+    let bound_address_length = match reply[3] {
+        IPV4_ADDRESS => 4,
+        IPV6_ADDRESS => 16,
+        DOMAIN_NAME => stream.read_u8().await? as usize,
+        address_type => {
+            return Err(socks_error(format!(
+                "Unknown SOCKS address type {}",
+                address_type
+            )))
+        }
+    };
+    let mut bound_address = vec![0u8; bound_address_length + 2];
+    stream.read_exact(&mut bound_address).await?;
+
+    Ok(stream)
+}
+
+fn reply_message(reply: u8) -> &'static str {
+    match reply {
+        0x01 => "general failure",
+        0x02 => "connection not allowed",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/// Connector of the hyper client, going through the upstream of the host.
+#[derive(Clone)]
+pub(crate) struct EgressConnector {
+    http: HttpConnector<CountingResolver>,
+    router: EgressRouter,
+    /// Of connections through proxies, `http` has its own.
+    connect_timeout: Option<Duration>,
+    /// Direct connections which end up in these networks are dropped, hosts given as
+    /// addresses aren't resolved by `http` and aren't filtered by its resolver.
+    blocked_networks: Arc<Vec<IpNetwork>>,
+}
+
+impl EgressConnector {
+    pub(crate) fn new(
+        http: HttpConnector<CountingResolver>,
+        router: EgressRouter,
+        connect_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            http,
+            router,
+            connect_timeout,
+            blocked_networks: Arc::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn with_blocked_networks(mut self, blocked_networks: Vec<IpNetwork>) -> Self {
+        self.blocked_networks = Arc::new(blocked_networks);
+        self
+    }
+}
+
+impl tower_service::Service<Uri> for EgressConnector {
+    type Response = TcpStream;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let proxy = uri.host().and_then(|host| self.router.proxy_for(host));
+        let connect_timeout = self.connect_timeout;
+
+        match proxy {
+            Some(proxy) => Box::pin(async move {
+                let host = uri.host().unwrap_or_default();
+                let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+                    Some("https") | Some("wss") => 443,
+                    _ => 80,
+                });
+
+                let connecting = connect_through(&proxy, host, port);
+                match connect_timeout {
+                    Some(connect_timeout) => Ok(tokio::time::timeout(connect_timeout, connecting)
+                        .await
+                        .map_err(|_elapsed| {
+                            io::Error::new(io::ErrorKind::TimedOut, "Timed out connecting")
+                        })??),
+                    None => Ok(connecting.await?),
+                }
+            }),
+            None => {
+                let connecting = self.http.call(uri);
+                let blocked_networks = self.blocked_networks.clone();
+
+                Box::pin(async move {
+                    let stream = connecting.await?;
+
+                    let ip_address = stream.peer_addr()?.ip();
+                    if blocked_networks
+                        .iter()
+                        .any(|network| network.contains(&ip_address))
+                    {
+                        return Err(io::Error::new(
+                            io::ErrorKind::PermissionDenied,
+                            format!("{} is in a blocked network", ip_address),
+                        )
+                        .into());
+                    }
+
+                    Ok(stream)
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use async_compression::tokio::bufread::{
+    BrotliDecoder, GzipDecoder, GzipEncoder, ZlibDecoder, ZstdDecoder,
+};
+use futures_util::{Stream, TryStreamExt};
+use hyper::body::Bytes;
+use hyper::{http, Body};
+use once_cell::sync::Lazy;
+use std::io;
+use std::pin::Pin;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+pub(crate) type BodyStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+/// `Accept-Encoding` values sent upstream, for every combination of the encodings we support
+/// indexed by `ContentEncoding::bit`. Built once and shared by all requests, cloning them
+/// doesn't allocate.
+static ACCEPT_ENCODING_VALUES: Lazy<Vec<Option<http::HeaderValue>>> = Lazy::new(|| {
+    (0..1 << ContentEncoding::SUPPORTED.len())
+        .map(|mask: usize| {
+            let encodings = ContentEncoding::SUPPORTED
+                .iter()
+                .filter(|encoding| mask & encoding.bit() != 0)
+                .map(|encoding| encoding.as_str())
+                .collect::<Vec<_>>();
+
+            (!encodings.is_empty())
+                .then(|| http::HeaderValue::from_str(&encodings.join(", ")).unwrap())
+        })
+        .collect()
+});
+
+/// Content encodings we are able to decode in order to rewrite HTML documents.
+pub(crate) use crate::configuration::ContentEncoding;
+
+impl ContentEncoding {
+    const SUPPORTED: [Self; 4] = [Self::Gzip, Self::Deflate, Self::Brotli, Self::Zstd];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+            Self::Brotli => "br",
+            Self::Zstd => "zstd",
+        }
+    }
+
+    fn bit(&self) -> usize {
+        1 << *self as usize
+    }
+
+    fn mask(encodings: &[Self]) -> usize {
+        encodings
+            .iter()
+            .fold(0, |mask, encoding| mask | encoding.bit())
+    }
+
+    fn from_token(token: &str) -> Option<Self> {
+        Self::SUPPORTED
+            .into_iter()
+            .find(|encoding| token.eq_ignore_ascii_case(encoding.as_str()))
+    }
+
+    /// Returns the encoding of a response, `Ok(None)` meaning the response is not encoded.
+    ///
+    /// Errors when the response is encoded in a way we don't support, including when
+    /// multiple encodings were applied.
+    pub(crate) fn from_headers(headers: &http::HeaderMap) -> Result<Option<Self>, ()> {
+        let value = match headers.get(http::header::CONTENT_ENCODING) {
+            Some(value) => value.to_str().map_err(|_| ())?.trim(),
+            None => return Ok(None),
+        };
+
+        if value.is_empty() || value.eq_ignore_ascii_case("identity") {
+            return Ok(None);
+        }
+
+        Self::from_token(value).map(Some).ok_or(())
+    }
+
+    /// Whether a client accepts this encoding, according to its `Accept-Encoding` header.
+    pub(crate) fn is_accepted_by(&self, headers: &http::HeaderMap) -> bool {
+        accepted_encodings(headers).any(|encoding| encoding == *self)
+    }
+}
+
+fn accepted_encodings(headers: &http::HeaderMap) -> impl Iterator<Item = ContentEncoding> + '_ {
+    headers
+        .get_all(http::header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|token| {
+            let mut parts = token.split(';');
+            let encoding = ContentEncoding::from_token(parts.next()?.trim())?;
+
+            // Encodings explicitly refused with `q=0` are not accepted.
+            let refused = parts.any(|parameter| {
+                matches!(
+                    parameter.trim().split_once('='),
+                    Some((key, value)) if key.trim() == "q" && value.trim().parse::<f32>() == Ok(0.0)
+                )
+            });
+
+            if refused {
+                None
+            } else {
+                Some(encoding)
+            }
+        })
+}
+
+/// Restricts the `Accept-Encoding` header we send upstream to encodings the client, the
+/// configuration and ourselves all accept, so that any HTML document we receive can be
+/// decoded and rewritten.
+///
+/// Requests without the header are sent without one, clients which don't advertise encodings
+/// must not receive encoded responses.
+pub(crate) fn negotiate_accept_encoding(
+    headers: &mut http::HeaderMap,
+    upstream_encodings: &[ContentEncoding],
+) {
+    if !headers.contains_key(http::header::ACCEPT_ENCODING) {
+        return;
+    }
+
+    let allowed = ContentEncoding::mask(upstream_encodings);
+    let accepted = accepted_encodings(headers).fold(0, |mask, encoding| mask | encoding.bit());
+
+    let accept_encoding = ACCEPT_ENCODING_VALUES[accepted & allowed]
+        .clone()
+        // Servers may otherwise pick any encoding.
+        .unwrap_or_else(|| http::HeaderValue::from_static("identity"));
+    headers.insert(http::header::ACCEPT_ENCODING, accept_encoding);
+}
+
+/// Decodes a body as it is being streamed.
+pub(crate) fn decode<S>(stream: S, encoding: ContentEncoding) -> BodyStream
+where
+    S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+{
+    let reader = StreamReader::new(stream);
+
+    match encoding {
+        ContentEncoding::Gzip => Box::pin(ReaderStream::new(GzipDecoder::new(reader))),
+        ContentEncoding::Deflate => Box::pin(ReaderStream::new(ZlibDecoder::new(reader))),
+        ContentEncoding::Brotli => Box::pin(ReaderStream::new(BrotliDecoder::new(reader))),
+        ContentEncoding::Zstd => Box::pin(ReaderStream::new(ZstdDecoder::new(reader))),
+    }
+}
+
+/// Gzip compresses `body` as it is being streamed, without a task nor a channel in between.
+pub(crate) fn gzip_compressed_body(body: Body) -> Body {
+    let reader = StreamReader::new(body.map_err(io::Error::other));
+
+    Body::wrap_stream(ReaderStream::new(GzipEncoder::new(reader)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn negotiate(
+        accept_encoding: Option<&'static str>,
+        upstream_encodings: &[ContentEncoding],
+    ) -> Option<String> {
+        let mut headers = http::HeaderMap::new();
+        if let Some(accept_encoding) = accept_encoding {
+            headers.insert(
+                http::header::ACCEPT_ENCODING,
+                http::HeaderValue::from_static(accept_encoding),
+            );
+        }
+
+        negotiate_accept_encoding(&mut headers, upstream_encodings);
+
+        headers
+            .get(http::header::ACCEPT_ENCODING)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn requests_without_accept_encoding_ask_for_none() {
+        assert_eq!(negotiate(None, &ContentEncoding::SUPPORTED), None);
+        assert_eq!(negotiate(None, &[ContentEncoding::Gzip]), None);
+    }
+
+    #[test]
+    fn keeps_encodings_accepted_by_both() {
+        assert_eq!(
+            negotiate(
+                Some("br;q=1.0, gzip, zstd;q=0"),
+                &ContentEncoding::SUPPORTED
+            )
+            .as_deref(),
+            Some("gzip, br")
+        );
+        assert_eq!(
+            negotiate(Some("gzip, br"), &[ContentEncoding::Gzip]).as_deref(),
+            Some("gzip")
+        );
+    }
+
+    #[test]
+    fn asks_for_identity_without_common_encodings() {
+        assert_eq!(
+            negotiate(Some("compress"), &ContentEncoding::SUPPORTED).as_deref(),
+            Some("identity")
+        );
+        assert_eq!(negotiate(Some("br"), &[]).as_deref(), Some("identity"));
+    }
+}
@@ -1,5 +1,14 @@
+use base64::{engine::general_purpose, Engine};
+use hmac::{Hmac, Mac};
+use hyper::Uri;
 use lazy_static::lazy_static;
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
 use wildmatch::WildMatch;
 
 #[derive(Debug, Clone)]
@@ -32,46 +41,46 @@ impl WildMatchCollection {
 
 lazy_static! {
     static ref DEFAULT_EXCLUSIONS: WildMatchCollection = {
-        let mut exclusions = Vec::new();
-
         // Apple service exclusions, as defined in : https://support.apple.com/en-us/HT210060
         // > Apple services will fail any connection that uses
         // > HTTPS Interception (SSL Inspection). If the HTTPS traffic
         // > traverses a web proxy, disable HTTPS Interception for the hosts
         // > listed in this article.
-        exclusions.push(String::from("*.apple.com"));
-        exclusions.push(String::from("static.ips.apple.com"));
-        exclusions.push(String::from("*.push.apple.com"));
-        exclusions.push(String::from("setup.icloud.com"));
-        exclusions.push(String::from("*.business.apple.com"));
-        exclusions.push(String::from("*.school.apple.com"));
-        exclusions.push(String::from("upload.appleschoolcontent.com"));
-        exclusions.push(String::from("ws-ee-maidsvc.icloud.com"));
-        exclusions.push(String::from("itunes.com"));
-        exclusions.push(String::from("appldnld.apple.com.edgesuite.net"));
-        exclusions.push(String::from("*.itunes.apple.com"));
-        exclusions.push(String::from("updates-http.cdn-apple.com"));
-        exclusions.push(String::from("updates.cdn-apple.com"));
-        exclusions.push(String::from("*.apps.apple.com"));
-        exclusions.push(String::from("*.mzstatic.com"));
-        exclusions.push(String::from("*.appattest.apple.com"));
-        exclusions.push(String::from("doh.dns.apple.com"));
-        exclusions.push(String::from("appleid.cdn-apple.com"));
-        exclusions.push(String::from("*.apple-cloudkit.com"));
-        exclusions.push(String::from("*.apple-livephotoskit.com"));
-        exclusions.push(String::from("*.apzones.com"));
-        exclusions.push(String::from("*.cdn-apple.com"));
-        exclusions.push(String::from("*.gc.apple.com"));
-        exclusions.push(String::from("*.icloud.com"));
-        exclusions.push(String::from("*.icloud.com.cn"));
-        exclusions.push(String::from("*.icloud.apple.com"));
-        exclusions.push(String::from("*.icloud-content.com"));
-        exclusions.push(String::from("*.iwork.apple.com"));
-        exclusions.push(String::from("mask.icloud.com"));
-        exclusions.push(String::from("mask-h2.icloud.com"));
-        exclusions.push(String::from("mask-api.icloud.com"));
-        exclusions.push(String::from("devimages-cdn.apple.com"));
-        exclusions.push(String::from("download.developer.apple.com"));
+        let exclusions = vec![
+            String::from("*.apple.com"),
+            String::from("static.ips.apple.com"),
+            String::from("*.push.apple.com"),
+            String::from("setup.icloud.com"),
+            String::from("*.business.apple.com"),
+            String::from("*.school.apple.com"),
+            String::from("upload.appleschoolcontent.com"),
+            String::from("ws-ee-maidsvc.icloud.com"),
+            String::from("itunes.com"),
+            String::from("appldnld.apple.com.edgesuite.net"),
+            String::from("*.itunes.apple.com"),
+            String::from("updates-http.cdn-apple.com"),
+            String::from("updates.cdn-apple.com"),
+            String::from("*.apps.apple.com"),
+            String::from("*.mzstatic.com"),
+            String::from("*.appattest.apple.com"),
+            String::from("doh.dns.apple.com"),
+            String::from("appleid.cdn-apple.com"),
+            String::from("*.apple-cloudkit.com"),
+            String::from("*.apple-livephotoskit.com"),
+            String::from("*.apzones.com"),
+            String::from("*.cdn-apple.com"),
+            String::from("*.gc.apple.com"),
+            String::from("*.icloud.com"),
+            String::from("*.icloud.com.cn"),
+            String::from("*.icloud.apple.com"),
+            String::from("*.icloud-content.com"),
+            String::from("*.iwork.apple.com"),
+            String::from("mask.icloud.com"),
+            String::from("mask-h2.icloud.com"),
+            String::from("mask-api.icloud.com"),
+            String::from("devimages-cdn.apple.com"),
+            String::from("download.developer.apple.com"),
+        ];
 
         WildMatchCollection::new(exclusions)
     };
@@ -100,3 +109,269 @@ impl LocalExclusionStore {
         }
     }
 }
+
+/// Path, on any proxied host, used to redeem a temporary exclusion token.
+pub(crate) const TEMPORARY_EXCLUSION_PATH: &str = "/.privaxy/allow";
+
+/// Path, on any proxied host, used to redeem a token tunneling the host.
+pub(crate) const TUNNEL_PATH: &str = "/.privaxy/tunnel";
+
+/// How long the link embedded in a block page remains usable.
+const TEMPORARY_EXCLUSION_TOKEN_VALIDITY: Duration = Duration::from_secs(60 * 60);
+
+/// What a token grants, so that one can't be redeemed for another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenPurpose {
+    Allow,
+    Tunnel,
+}
+
+impl TokenPurpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Tunnel => "tunnel",
+        }
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+/// Host whose requests aren't blocked, for every client, until a given time
+pub struct SiteExclusion {
+    pub host: String,
+    /// Unix timestamp.
+    pub expires_at: u64,
+}
+
+/// Time limited exclusions of a host, whose requests are then filtered but never blocked.
+///
+/// Exclusions of a single client are granted from the block page, through signed tokens so
+/// that a page can't grant itself an exclusion. The signing key is generated on startup,
+/// tokens don't survive restarts. Exclusions of every client are granted from the web
+/// interface, and don't survive restarts either.
+///
+/// Error pages similarly offer to tunnel a host for a client, its connections then bypass
+/// the proxy entirely.
+#[derive(Debug, Clone)]
+pub struct TemporaryExclusionStore {
+    signing_key: Arc<Vec<u8>>,
+    exclusions: Arc<RwLock<HashMap<(String, IpAddr), Instant>>>,
+    site_exclusions: Arc<RwLock<HashMap<String, SystemTime>>>,
+    tunnels: Arc<RwLock<HashMap<(String, IpAddr), Instant>>>,
+}
+
+impl Default for TemporaryExclusionStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TemporaryExclusionStore {
+    pub fn new() -> Self {
+        let mut signing_key = vec![0; 32];
+        openssl::rand::rand_bytes(&mut signing_key).unwrap();
+
+        Self {
+            signing_key: Arc::new(signing_key),
+            exclusions: Arc::new(RwLock::new(HashMap::new())),
+            site_exclusions: Arc::new(RwLock::new(HashMap::new())),
+            tunnels: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    pub fn contains(&self, host: &str, client: IpAddr) -> bool {
+        let host = host.to_lowercase();
+
+        if let Some(expires_at) = self.site_exclusions.read().unwrap().get(&host) {
+            if *expires_at > SystemTime::now() {
+                return true;
+            }
+        }
+
+        match self.exclusions.read().unwrap().get(&(host, client)) {
+            Some(expires_at) => *expires_at > Instant::now(),
+            None => false,
+        }
+    }
+
+    /// Excludes `host` for every client, for `duration`.
+    pub fn exclude_site(&self, host: &str, duration: Duration) {
+        let host = host.to_lowercase();
+
+        log::info!(
+            "Temporarily excluding {} for all clients for {} minutes",
+            host,
+            duration.as_secs() / 60
+        );
+
+        let mut site_exclusions = self.site_exclusions.write().unwrap();
+        let now = SystemTime::now();
+        site_exclusions.retain(|_, expires_at| *expires_at > now);
+        site_exclusions.insert(host, now + duration);
+    }
+
+    /// Ends the exclusion of `host` for every client, returns whether there was one.
+    pub fn remove_site(&self, host: &str) -> bool {
+        let now = SystemTime::now();
+
+        match self
+            .site_exclusions
+            .write()
+            .unwrap()
+            .remove(&host.to_lowercase())
+        {
+            Some(expires_at) => expires_at > now,
+            None => false,
+        }
+    }
+
+    /// Hosts currently excluded for every client, those expiring first first.
+    pub fn sites(&self) -> Vec<SiteExclusion> {
+        let now = SystemTime::now();
+
+        let mut sites = self
+            .site_exclusions
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, expires_at)| **expires_at > now)
+            .map(|(host, expires_at)| SiteExclusion {
+                host: host.clone(),
+                expires_at: expires_at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            })
+            .collect::<Vec<_>>();
+        sites.sort_by_key(|site| site.expires_at);
+
+        sites
+    }
+
+    /// Creates a token allowing `client` to exclude the host of `url`.
+    pub fn create_token(&self, url: &Uri, client: IpAddr) -> String {
+        self.create_signed_token(TokenPurpose::Allow, url, client)
+    }
+
+    /// Creates a token allowing `client` to tunnel the host of `url`.
+    pub fn create_tunnel_token(&self, url: &Uri, client: IpAddr) -> String {
+        self.create_signed_token(TokenPurpose::Tunnel, url, client)
+    }
+
+    /// Validates `token` and excludes the host it was created for, for `duration`.
+    ///
+    /// Returns the URL that was blocked, so that the client can be sent back to it.
+    pub fn redeem_token(&self, token: &str, client: IpAddr, duration: Duration) -> Option<Uri> {
+        let url = self.verify_token(TokenPurpose::Allow, token, client)?;
+        let host = url.host()?.to_lowercase();
+
+        log::info!(
+            "Temporarily excluding {} for client {} for {} minutes",
+            host,
+            client,
+            duration.as_secs() / 60
+        );
+
+        let mut exclusions = self.exclusions.write().unwrap();
+        let now = Instant::now();
+        exclusions.retain(|_, expires_at| *expires_at > now);
+        exclusions.insert((host, client), now + duration);
+
+        Some(url)
+    }
+
+    /// Validates `token` and tunnels the host it was created for, for `duration`.
+    ///
+    /// Returns the URL that failed, so that the client can be sent back to it.
+    pub fn redeem_tunnel_token(
+        &self,
+        token: &str,
+        client: IpAddr,
+        duration: Duration,
+    ) -> Option<Uri> {
+        let url = self.verify_token(TokenPurpose::Tunnel, token, client)?;
+        let host = url.host()?.to_lowercase();
+
+        log::info!(
+            "Temporarily tunneling {} for client {} for {} minutes",
+            host,
+            client,
+            duration.as_secs() / 60
+        );
+
+        let mut tunnels = self.tunnels.write().unwrap();
+        let now = Instant::now();
+        tunnels.retain(|_, expires_at| *expires_at > now);
+        tunnels.insert((host, client), now + duration);
+
+        Some(url)
+    }
+
+    pub fn is_tunneled(&self, host: &str, client: IpAddr) -> bool {
+        match self
+            .tunnels
+            .read()
+            .unwrap()
+            .get(&(host.to_lowercase(), client))
+        {
+            Some(expires_at) => *expires_at > Instant::now(),
+            None => false,
+        }
+    }
+
+    fn create_signed_token(&self, purpose: TokenPurpose, url: &Uri, client: IpAddr) -> String {
+        let expires_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + TEMPORARY_EXCLUSION_TOKEN_VALIDITY.as_secs();
+
+        let payload = format!("{}\n{}\n{}\n{}", purpose.as_str(), expires_at, client, url);
+        let signature = self.sign(payload.as_bytes());
+
+        format!(
+            "{}.{}",
+            general_purpose::URL_SAFE_NO_PAD.encode(payload),
+            hex::encode(signature)
+        )
+    }
+
+    /// URL `token` was created for, if it is valid, was created for `purpose` and `client`,
+    /// and didn't expire.
+    fn verify_token(&self, purpose: TokenPurpose, token: &str, client: IpAddr) -> Option<Uri> {
+        let (payload, signature) = token.split_once('.')?;
+        let payload = general_purpose::URL_SAFE_NO_PAD.decode(payload).ok()?;
+        let signature = hex::decode(signature).ok()?;
+
+        let mut mac = HmacSha256::new_from_slice(&self.signing_key).unwrap();
+        mac.update(&payload);
+        mac.verify_slice(&signature).ok()?;
+
+        let payload = String::from_utf8(payload).ok()?;
+        let mut fields = payload.splitn(4, '\n');
+        let token_purpose = fields.next()?;
+        let expires_at: u64 = fields.next()?.parse().ok()?;
+        let token_client: IpAddr = fields.next()?.parse().ok()?;
+        let url: Uri = fields.next()?.parse().ok()?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        if token_purpose != purpose.as_str() || token_client != client || expires_at < now {
+            return None;
+        }
+
+        Some(url)
+    }
+
+    fn sign(&self, payload: &[u8]) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(&self.signing_key).unwrap();
+        mac.update(payload);
+
+        mac.finalize().into_bytes().to_vec()
+    }
+}
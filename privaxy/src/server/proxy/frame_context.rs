@@ -0,0 +1,116 @@
+use super::resource_type::ResourceType;
+use hyper::{http, Request, Uri};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use uluru::LRUCache;
+
+/// Frames remembered across clients, the oldest are forgotten first.
+const MAX_FRAMES: usize = 1_000;
+
+#[derive(Debug)]
+struct Frame {
+    client: IpAddr,
+    /// Origin the frame was loaded from, as `Referer` headers of its requests are often
+    /// trimmed down to it.
+    origin: String,
+    /// URL of the page at the top of the frame hierarchy.
+    top_level: String,
+}
+
+/// Keeps track of the pages embedding the frames clients load, so that requests made from
+/// a frame are matched against filters in the context of the page at the top of the
+/// hierarchy rather than of the frame itself, which is what `$third-party` and
+/// `$first-party` expect.
+#[derive(Debug, Clone, Default)]
+pub struct FrameContextStore(Arc<Mutex<LRUCache<Frame, MAX_FRAMES>>>);
+
+impl FrameContextStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// URL of the page `request` was made on behalf of, which filters consider as the first
+    /// party. Documents are their own first party. Frames are attributed to the page
+    /// embedding them, and other requests to the top-level page of the frame they came from,
+    /// which is known from the `Referer` header.
+    pub(crate) fn source_url<B>(
+        &self,
+        request: &Request<B>,
+        resource_type: ResourceType,
+        client: IpAddr,
+    ) -> String {
+        let uri = request.uri();
+        let referer = request
+            .headers()
+            .get(http::header::REFERER)
+            .and_then(|referer| referer.to_str().ok())
+            .and_then(|referer| referer.parse::<Uri>().ok())
+            .filter(|referer| referer.host().is_some());
+        let fetch_site = request
+            .headers()
+            .get("sec-fetch-site")
+            .and_then(|fetch_site| fetch_site.to_str().ok());
+
+        let source_url = match (resource_type, referer) {
+            (ResourceType::Document, _) => uri.to_string(),
+            (_, Some(referer)) => self.top_level(client, &referer),
+            // Without a referer, requests the browser tells were made by another site are
+            // attributed to the one the client last loaded a frame of.
+            (_, None) if fetch_site == Some("cross-site") => self
+                .last_top_level(client)
+                .unwrap_or_else(|| uri.to_string()),
+            // Otherwise, the request is taken as first party, as we'd rather miss a third
+            // party filter than block a site's own resources.
+            (_, None) => uri.to_string(),
+        };
+
+        if let (ResourceType::Document | ResourceType::Subdocument, Some(origin)) =
+            (resource_type, origin(uri))
+        {
+            self.remember(client, origin, source_url.clone());
+        }
+
+        source_url
+    }
+
+    fn top_level(&self, client: IpAddr, referer: &Uri) -> String {
+        let origin = match origin(referer) {
+            Some(origin) => origin,
+            None => return referer.to_string(),
+        };
+
+        self.0
+            .lock()
+            .unwrap()
+            .find(|frame| frame.client == client && frame.origin == origin)
+            .map(|frame| frame.top_level.clone())
+            .unwrap_or_else(|| referer.to_string())
+    }
+
+    fn last_top_level(&self, client: IpAddr) -> Option<String> {
+        self.0
+            .lock()
+            .unwrap()
+            .find(|frame| frame.client == client)
+            .map(|frame| frame.top_level.clone())
+    }
+
+    fn remember(&self, client: IpAddr, origin: String, top_level: String) {
+        let mut frames = self.0.lock().unwrap();
+
+        match frames.find(|frame| frame.client == client && frame.origin == origin) {
+            Some(frame) => frame.top_level = top_level,
+            None => {
+                frames.insert(Frame {
+                    client,
+                    origin,
+                    top_level,
+                });
+            }
+        }
+    }
+}
+
+fn origin(uri: &Uri) -> Option<String> {
+    Some(format!("{}://{}", uri.scheme_str()?, uri.authority()?))
+}
@@ -0,0 +1,117 @@
+use super::cname::CnameResolver;
+use crate::configuration::GeoIpConfig;
+use maxminddb::{geoip2, Reader};
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Location of an upstream server.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct GeoInfo {
+    pub(crate) ip_address: Option<IpAddr>,
+    /// ISO 3166-1 country code.
+    pub(crate) country: Option<String>,
+    pub(crate) asn: Option<u32>,
+    pub(crate) as_organization: Option<String>,
+}
+
+impl GeoInfo {
+    /// `AS15169 Google LLC`, as shown in statistics.
+    pub(crate) fn as_name(&self) -> Option<String> {
+        let asn = self.asn?;
+
+        Some(match &self.as_organization {
+            Some(organization) => format!("AS{} {}", asn, organization),
+            None => format!("AS{}", asn),
+        })
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct GeoIp {
+    country_reader: Option<Arc<Reader<Vec<u8>>>>,
+    asn_reader: Option<Arc<Reader<Vec<u8>>>>,
+}
+
+impl fmt::Debug for GeoIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeoIp")
+            .field("country_reader", &self.country_reader.is_some())
+            .field("asn_reader", &self.asn_reader.is_some())
+            .finish()
+    }
+}
+
+fn open_database(path: &Path) -> Option<Arc<Reader<Vec<u8>>>> {
+    match Reader::open_readfile(path) {
+        Ok(reader) => {
+            log::info!("Loaded GeoIP database: {:?}", path);
+            Some(Arc::new(reader))
+        }
+        Err(err) => {
+            log::error!("Unable to open GeoIP database {:?}: {}", path, err);
+            None
+        }
+    }
+}
+
+impl GeoIp {
+    pub(crate) fn open(configuration: &GeoIpConfig) -> Self {
+        Self {
+            country_reader: configuration
+                .country_database_path
+                .as_deref()
+                .and_then(open_database),
+            asn_reader: configuration
+                .asn_database_path
+                .as_deref()
+                .and_then(open_database),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.country_reader.is_some() || self.asn_reader.is_some()
+    }
+
+    pub(crate) fn lookup(&self, ip_address: IpAddr) -> GeoInfo {
+        let country = self.country_reader.as_ref().and_then(|reader| {
+            reader
+                .lookup::<geoip2::Country>(ip_address)
+                .ok()?
+                .country?
+                .iso_code
+                .map(str::to_string)
+        });
+
+        let asn = self
+            .asn_reader
+            .as_ref()
+            .and_then(|reader| reader.lookup::<geoip2::Asn>(ip_address).ok());
+
+        GeoInfo {
+            ip_address: Some(ip_address),
+            country,
+            asn: asn.as_ref().and_then(|asn| asn.autonomous_system_number),
+            as_organization: asn
+                .as_ref()
+                .and_then(|asn| asn.autonomous_system_organization)
+                .map(str::to_string),
+        }
+    }
+
+    /// Locates the first address `host` resolves to, `None` when no database is loaded.
+    pub(crate) async fn lookup_host(
+        &self,
+        cname_resolver: &CnameResolver,
+        host: &str,
+    ) -> Option<GeoInfo> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        let ip_address = cname_resolver.resolve(host).await.into_iter().next()?;
+
+        Some(self.lookup(ip_address))
+    }
+}
@@ -1,11 +1,14 @@
 use crate::{blocker::AdblockRequester, statistics::Statistics};
 use crossbeam_channel::Receiver;
 use hyper::body::Bytes;
+use lol_html::html_content::ContentType;
 use lol_html::{element, HtmlRewriter, Settings};
 use regex::Regex;
 use std::collections::HashSet;
 use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
 type InternalBodyChannel = (
@@ -17,6 +20,10 @@ struct AdblockProperties {
     url: String,
     ids: HashSet<String>,
     classes: HashSet<String>,
+    /// Time spent rewriting the body, the cosmetic filters are looked up afterwards.
+    rewrite_duration: Duration,
+    /// Rewriting stopped before the end of the body, which is followed by the rest of it.
+    is_cut_short: bool,
 }
 
 pub struct Rewriter {
@@ -26,8 +33,20 @@ pub struct Rewriter {
     body_sender: hyper::body::Sender,
     statistics: Statistics,
     internal_body_channel: InternalBodyChannel,
+    strip_scripts: bool,
+    /// Injected before the scripts of the page.
+    head_script: Option<&'static str>,
+    /// Set before the receiver is dropped when the rest of the body isn't rewritten.
+    is_cut_short: Arc<AtomicBool>,
 }
 
+/// Shown on pages whose scripts were stripped.
+const NO_SCRIPT_INDICATOR: &str = r#"
+<!-- privaxy proxy -->
+<div style="position: fixed; bottom: 8px; right: 8px; z-index: 2147483647; padding: 4px 8px; border-radius: 4px; background: #1f2937; color: #fff; font: 12px sans-serif; opacity: 0.8; pointer-events: none;">Scripts blocked by Privaxy</div>
+<!-- privaxy proxy -->
+"#;
+
 impl Rewriter {
     pub(crate) fn new(
         url: String,
@@ -35,6 +54,8 @@ impl Rewriter {
         receiver: Receiver<Bytes>,
         body_sender: hyper::body::Sender,
         statistics: Statistics,
+        strip_scripts: bool,
+        head_script: Option<&'static str>,
     ) -> Self {
         Self {
             url,
@@ -43,9 +64,19 @@ impl Rewriter {
             adblock_requester,
             receiver,
             internal_body_channel: mpsc::unbounded_channel(),
+            strip_scripts,
+            // Scripts don't run anyway.
+            head_script: head_script.filter(|_| !strip_scripts),
+            is_cut_short: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Flag to set before dropping the sender of the body when the rest of it is sent
+    /// without being rewritten.
+    pub(crate) fn cut_short_flag(&self) -> Arc<AtomicBool> {
+        self.is_cut_short.clone()
+    }
+
     pub(crate) fn rewrite(self) {
         let (internal_body_sender, internal_body_receiver) = self.internal_body_channel;
         let body_sender = self.body_sender;
@@ -62,6 +93,7 @@ impl Rewriter {
             body_sender,
             adblock_requester,
             statistics,
+            self.strip_scripts,
         ));
 
         let re = Regex::new(r"\s+").unwrap();
@@ -69,36 +101,65 @@ impl Rewriter {
         let ids_clone = Arc::clone(&ids);
         let internal_body_sender_clone = Arc::clone(&internal_body_sender);
 
+        let mut element_content_handlers = vec![
+            element!("*", move |element| {
+                if let Some(id) = element.get_attribute("id") {
+                    ids_clone.lock().unwrap().insert(id);
+                }
+                Ok(())
+            }),
+            element!("*", move |element| {
+                if let Some(class) = element.get_attribute("class") {
+                    let classes_without_duplicate_spaces = re.replace_all(&class, " ");
+                    let class_set: HashSet<_> = classes_without_duplicate_spaces
+                        .split_whitespace()
+                        .map(String::from)
+                        .collect();
+                    classes_clone.lock().unwrap().extend(class_set);
+                }
+                Ok(())
+            }),
+            element!("html, body", |element| {
+                if let Some(handlers) = element.end_tag_handlers() {
+                    handlers.push(Box::new(move |end| {
+                        end.remove();
+                        Ok(())
+                    }))
+                }
+                Ok(())
+            }),
+        ];
+        if let Some(head_script) = self.head_script {
+            let is_injected = AtomicBool::new(false);
+            // Documents without a `head` element start with their `body`.
+            element_content_handlers.push(element!("head, body", move |element| {
+                if !is_injected.swap(true, Ordering::Relaxed) {
+                    element.prepend(
+                        &format!(
+                            r#"<script type="application/javascript">{}</script>"#,
+                            head_script
+                        ),
+                        ContentType::Html,
+                    );
+                }
+                Ok(())
+            }));
+        }
+        if self.strip_scripts {
+            element_content_handlers.push(element!("script", |element| {
+                element.remove();
+                Ok(())
+            }));
+            // Fallback content is what the page looks like without scripts.
+            element_content_handlers.push(element!("noscript", |element| {
+                element.remove_and_keep_content();
+                Ok(())
+            }));
+        }
+
         let mut rewriter = HtmlRewriter::new(
             Settings {
-                element_content_handlers: vec![
-                    element!("*", move |element| {
-                        if let Some(id) = element.get_attribute("id") {
-                            ids_clone.lock().unwrap().insert(id);
-                        }
-                        Ok(())
-                    }),
-                    element!("*", move |element| {
-                        if let Some(class) = element.get_attribute("class") {
-                            let classes_without_duplicate_spaces = re.replace_all(&class, " ");
-                            let class_set: HashSet<_> = classes_without_duplicate_spaces
-                                .split_whitespace()
-                                .map(String::from)
-                                .collect();
-                            classes_clone.lock().unwrap().extend(class_set);
-                        }
-                        Ok(())
-                    }),
-                    element!("html, body", |element| {
-                        if let Some(handlers) = element.end_tag_handlers() {
-                            handlers.push(Box::new(move |end| {
-                                end.remove();
-                                Ok(())
-                            }))
-                        }
-                        Ok(())
-                    }),
-                ],
+                element_content_handlers,
                 ..Settings::default()
             },
             move |c: &[u8]| {
@@ -109,10 +170,16 @@ impl Rewriter {
             },
         );
 
+        // Time spent waiting for the upstream body isn't counted.
+        let mut rewrite_duration = Duration::ZERO;
         for message in self.receiver {
+            let started_at = Instant::now();
             rewriter.write(&message).unwrap();
+            rewrite_duration += started_at.elapsed();
         }
+        let started_at = Instant::now();
         rewriter.end().unwrap();
+        rewrite_duration += started_at.elapsed();
 
         let _ = internal_body_sender.lock().unwrap().send((
             Bytes::new(),
@@ -120,6 +187,8 @@ impl Rewriter {
                 ids: ids.lock().unwrap().clone(),
                 classes: classes.lock().unwrap().clone(),
                 url: self.url,
+                rewrite_duration,
+                is_cut_short: self.is_cut_short.load(Ordering::Acquire),
             }),
         ));
     }
@@ -129,13 +198,21 @@ impl Rewriter {
         mut body_sender: hyper::body::Sender,
         adblock_requester: AdblockRequester,
         statistics: Statistics,
+        strip_scripts: bool,
     ) {
         while let Some((bytes, adblock_properties)) = receiver.recv().await {
             if let Err(_err) = body_sender.send_data(bytes).await {
                 break;
             }
             if let Some(adblock_properties) = adblock_properties {
-                let mut response_has_been_modified = false;
+                // Anything appended would land in the middle of the document.
+                if adblock_properties.is_cut_short {
+                    statistics.record_rewrite(adblock_properties.rewrite_duration);
+                    break;
+                }
+
+                let mut response_has_been_modified = strip_scripts;
+                let cosmetic_started_at = Instant::now();
 
                 let blocker_result = adblock_requester
                     .get_cosmetic_response(
@@ -183,9 +260,16 @@ impl Rewriter {
                     .unwrap();
                 }
 
+                if strip_scripts {
+                    to_append_to_response.push_str(NO_SCRIPT_INDICATOR);
+                }
+
                 if response_has_been_modified {
                     statistics.increment_modified_responses();
                 }
+                statistics.record_rewrite(
+                    adblock_properties.rewrite_duration + cosmetic_started_at.elapsed(),
+                );
 
                 let bytes = Bytes::copy_from_slice(to_append_to_response.as_bytes());
 
@@ -0,0 +1,90 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uluru::LRUCache;
+
+const MAX_BROKEN_HOSTS: usize = 1_000;
+
+/// Hosts that failed an upgrade are served over plain HTTP for this long
+/// before another upgrade is attempted.
+const BROKEN_HOST_RETRY_AFTER: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// Keeps track of hosts that are known not to work over HTTPS.
+#[derive(Debug, Clone, Default)]
+pub struct HttpsUpgradeStore(Arc<Mutex<LRUCache<(String, Instant), MAX_BROKEN_HOSTS>>>);
+
+impl HttpsUpgradeStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_broken(&self, host: &str) -> bool {
+        let host = host.to_lowercase();
+        let mut broken_hosts = self.0.lock().unwrap();
+
+        match broken_hosts.find(|(broken_host, _since)| broken_host == &host) {
+            Some((_host, since)) => since.elapsed() < BROKEN_HOST_RETRY_AFTER,
+            None => false,
+        }
+    }
+
+    pub fn mark_broken(&self, host: &str) {
+        let host = host.to_lowercase();
+        let mut broken_hosts = self.0.lock().unwrap();
+
+        match broken_hosts.find(|(broken_host, _since)| broken_host == &host) {
+            Some((_host, since)) => *since = Instant::now(),
+            None => {
+                broken_hosts.insert((host, Instant::now()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hosts_are_not_broken_until_marked() {
+        let store = HttpsUpgradeStore::new();
+
+        assert!(!store.is_broken("example.com"));
+
+        store.mark_broken("example.com");
+
+        assert!(store.is_broken("example.com"));
+        assert!(!store.is_broken("example.org"));
+    }
+
+    #[test]
+    fn hosts_are_compared_case_insensitively() {
+        let store = HttpsUpgradeStore::new();
+
+        store.mark_broken("Example.COM");
+
+        assert!(store.is_broken("example.com"));
+        assert!(store.is_broken("EXAMPLE.com"));
+    }
+
+    #[test]
+    fn marking_a_host_again_keeps_a_single_entry() {
+        let store = HttpsUpgradeStore::new();
+
+        store.mark_broken("example.com");
+        store.mark_broken("example.com");
+
+        assert_eq!(store.0.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn least_recently_marked_hosts_are_forgotten() {
+        let store = HttpsUpgradeStore::new();
+
+        for index in 0..=MAX_BROKEN_HOSTS {
+            store.mark_broken(&format!("host-{}.example", index));
+        }
+
+        assert!(!store.is_broken("host-0.example"));
+        assert!(store.is_broken(&format!("host-{}.example", MAX_BROKEN_HOSTS)));
+    }
+}
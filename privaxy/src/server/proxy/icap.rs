@@ -0,0 +1,364 @@
+//! ICAP client (RFC 3507), handing requests and responses over to external scanners.
+//!
+//! Bodies are sent whole, without previews, and a new connection is used for each scan.
+use crate::configuration::IcapConfig;
+use futures_util::{Stream, StreamExt};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri};
+use hyper::body::Bytes;
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use url::Url;
+
+const DEFAULT_PORT: u16 = 1344;
+const MAX_HEADERS: usize = 128;
+/// Room for the headers of a scan result, on top of its body.
+const MAX_HEADERS_SIZE: u64 = 64 * 1024;
+
+/// What the scanner made of a request or a response.
+#[derive(Debug)]
+pub(crate) enum IcapOutcome {
+    /// Forwarded as is.
+    Unmodified,
+    /// The scanner modified the request.
+    Request { headers: HeaderMap, body: Bytes },
+    /// The scanner answered instead, blocking the request or replacing the response.
+    Response {
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Bytes,
+    },
+}
+
+/// A body read to be scanned.
+pub(crate) enum ScannedBody<S> {
+    /// The whole body, within the size limit.
+    Complete(Bytes),
+    /// The body is over the size limit, with what was read of it and the rest.
+    TooLarge(Bytes, S),
+}
+
+/// Reads a body, whose size may not be known beforehand, up to the size limit of scans.
+pub(crate) async fn read_body<S, E>(
+    configuration: &IcapConfig,
+    content_length: Option<u64>,
+    mut body: S,
+) -> Result<ScannedBody<S>, E>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    let max_size = configuration.max_size_kb.saturating_mul(1024);
+    if content_length.is_some_and(|content_length| content_length > max_size) {
+        return Ok(ScannedBody::TooLarge(Bytes::new(), body));
+    }
+
+    let mut buffer = Vec::new();
+    while let Some(chunk) = body.next().await {
+        buffer.extend_from_slice(&chunk?);
+
+        if buffer.len() as u64 > max_size {
+            return Ok(ScannedBody::TooLarge(buffer.into(), body));
+        }
+    }
+
+    Ok(ScannedBody::Complete(buffer.into()))
+}
+
+/// Puts a body too large to be scanned back together, to be forwarded as is.
+pub(crate) fn rejoin<S, E>(read: Bytes, rest: S) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    futures_util::stream::once(async move { Ok(read) }).chain(rest)
+}
+
+fn icap_error(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Scans a request, whose body is `None` when it has none.
+pub(crate) async fn reqmod(
+    configuration: &IcapConfig,
+    service: &Url,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: Option<&Bytes>,
+) -> io::Result<IcapOutcome> {
+    let request_header = request_header(method, uri, headers);
+
+    let encapsulated = match body {
+        Some(_) => format!("req-hdr=0, req-body={}", request_header.len()),
+        None => format!("req-hdr=0, null-body={}", request_header.len()),
+    };
+
+    let mut message = request_header;
+    if let Some(body) = body {
+        write_chunked(&mut message, body);
+    }
+
+    exchange(configuration, service, "REQMOD", &encapsulated, message).await
+}
+
+/// Scans the response to a request.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn respmod(
+    configuration: &IcapConfig,
+    service: &Url,
+    method: &Method,
+    uri: &Uri,
+    request_headers: &HeaderMap,
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &Bytes,
+) -> io::Result<IcapOutcome> {
+    let request_header = request_header(method, uri, request_headers);
+
+    let mut response_header = format!(
+        "HTTP/1.1 {} {}\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or_default()
+    )
+    .into_bytes();
+    write_headers(&mut response_header, headers);
+
+    let encapsulated = format!(
+        "req-hdr=0, res-hdr={}, res-body={}",
+        request_header.len(),
+        request_header.len() + response_header.len()
+    );
+
+    let mut message = request_header;
+    message.extend_from_slice(&response_header);
+    write_chunked(&mut message, body);
+
+    exchange(configuration, service, "RESPMOD", &encapsulated, message).await
+}
+
+fn request_header(method: &Method, uri: &Uri, headers: &HeaderMap) -> Vec<u8> {
+    let mut request_header = format!("{} {} HTTP/1.1\r\n", method, uri).into_bytes();
+    if !headers.contains_key(http::header::HOST) {
+        if let Some(authority) = uri.authority() {
+            request_header.extend_from_slice(format!("Host: {}\r\n", authority).as_bytes());
+        }
+    }
+    write_headers(&mut request_header, headers);
+
+    request_header
+}
+
+fn write_headers(buffer: &mut Vec<u8>, headers: &HeaderMap) {
+    for (name, value) in headers {
+        buffer.extend_from_slice(name.as_str().as_bytes());
+        buffer.extend_from_slice(b": ");
+        buffer.extend_from_slice(value.as_bytes());
+        buffer.extend_from_slice(b"\r\n");
+    }
+    buffer.extend_from_slice(b"\r\n");
+}
+
+fn write_chunked(buffer: &mut Vec<u8>, body: &[u8]) {
+    if !body.is_empty() {
+        buffer.extend_from_slice(format!("{:x}\r\n", body.len()).as_bytes());
+        buffer.extend_from_slice(body);
+        buffer.extend_from_slice(b"\r\n");
+    }
+    buffer.extend_from_slice(b"0\r\n\r\n");
+}
+
+async fn exchange(
+    configuration: &IcapConfig,
+    service: &Url,
+    icap_method: &str,
+    encapsulated: &str,
+    message: Vec<u8>,
+) -> io::Result<IcapOutcome> {
+    let exchange = async {
+        let host = service
+            .host_str()
+            .ok_or_else(|| icap_error(format!("{} has no host", service)))?;
+        let port = service.port().unwrap_or(DEFAULT_PORT);
+        let mut stream = TcpStream::connect((host, port)).await?;
+
+        let header = format!(
+            "{} {} ICAP/1.0\r\nHost: {}:{}\r\nAllow: 204\r\nConnection: close\r\nEncapsulated: {}\r\n\r\n",
+            icap_method, service, host, port, encapsulated
+        );
+        stream.write_all(header.as_bytes()).await?;
+        stream.write_all(&message).await?;
+        stream.flush().await?;
+
+        let max_size = configuration.max_size_kb * 1024 + MAX_HEADERS_SIZE;
+        let mut response = Vec::new();
+        (&mut stream)
+            .take(max_size + 1)
+            .read_to_end(&mut response)
+            .await?;
+        if response.len() as u64 > max_size {
+            return Err(icap_error("The ICAP response is too large"));
+        }
+
+        parse_response(&response)
+    };
+
+    match configuration.timeout() {
+        Some(timeout) => tokio::time::timeout(timeout, exchange)
+            .await
+            .unwrap_or_else(|_| {
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "The ICAP server took too long to respond",
+                ))
+            }),
+        None => exchange.await,
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn parse_response(response: &[u8]) -> io::Result<IcapOutcome> {
+    let header_end =
+        find(response, b"\r\n\r\n").ok_or_else(|| icap_error("Incomplete ICAP response"))? + 4;
+    let header = std::str::from_utf8(&response[..header_end])
+        .map_err(|_err| icap_error("Invalid ICAP response"))?;
+    let mut lines = header.split("\r\n");
+
+    let status = lines
+        .next()
+        .filter(|status_line| status_line.starts_with("ICAP/"))
+        .and_then(|status_line| status_line.split_whitespace().nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| icap_error("Invalid ICAP status line"))?;
+    match status {
+        204 => return Ok(IcapOutcome::Unmodified),
+        200 => {}
+        status => {
+            return Err(icap_error(format!(
+                "The ICAP server answered with status {}",
+                status
+            )))
+        }
+    }
+
+    let encapsulated = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _value)| name.trim().eq_ignore_ascii_case("encapsulated"))
+        .map(|(_name, value)| value.trim())
+        .ok_or_else(|| icap_error("Missing Encapsulated header"))?;
+    let sections = encapsulated
+        .split(',')
+        .map(|section| {
+            let (name, offset) = section
+                .trim()
+                .split_once('=')
+                .ok_or_else(|| icap_error("Invalid Encapsulated header"))?;
+            let offset = offset
+                .parse::<usize>()
+                .map_err(|_err| icap_error("Invalid Encapsulated header"))?;
+
+            Ok((name, offset))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let payload = &response[header_end..];
+    let section = |index: usize| -> io::Result<&[u8]> {
+        let start = sections[index].1;
+        let end = sections
+            .get(index + 1)
+            .map_or(payload.len(), |(_name, offset)| *offset);
+
+        payload
+            .get(start..end)
+            .ok_or_else(|| icap_error("Encapsulated offsets are out of bounds"))
+    };
+
+    let mut request_headers = None;
+    let mut response_head = None;
+    let mut body = Bytes::new();
+    for (index, (name, _offset)) in sections.iter().enumerate() {
+        match *name {
+            "req-hdr" => request_headers = Some(parse_request_headers(section(index)?)?),
+            "res-hdr" => response_head = Some(parse_response_head(section(index)?)?),
+            "req-body" | "res-body" => body = dechunk(section(index)?)?,
+            _ => {}
+        }
+    }
+
+    Ok(match (response_head, request_headers) {
+        (Some((status, headers)), _) => IcapOutcome::Response {
+            status,
+            headers,
+            body,
+        },
+        (None, Some(headers)) => IcapOutcome::Request { headers, body },
+        (None, None) => IcapOutcome::Unmodified,
+    })
+}
+
+fn to_header_map(headers: &[httparse::Header<'_>]) -> io::Result<HeaderMap> {
+    let mut header_map = HeaderMap::with_capacity(headers.len());
+    for header in headers {
+        let name = HeaderName::from_bytes(header.name.as_bytes())
+            .map_err(|_err| icap_error("Invalid encapsulated header name"))?;
+        let value = HeaderValue::from_bytes(header.value)
+            .map_err(|_err| icap_error("Invalid encapsulated header value"))?;
+        header_map.append(name, value);
+    }
+
+    Ok(header_map)
+}
+
+fn parse_request_headers(section: &[u8]) -> io::Result<HeaderMap> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut request = httparse::Request::new(&mut headers);
+    match request.parse(section) {
+        Ok(httparse::Status::Complete(_length)) => to_header_map(request.headers),
+        _ => Err(icap_error("Invalid encapsulated request")),
+    }
+}
+
+fn parse_response_head(section: &[u8]) -> io::Result<(StatusCode, HeaderMap)> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut response = httparse::Response::new(&mut headers);
+    match response.parse(section) {
+        Ok(httparse::Status::Complete(_length)) => {
+            let status = response
+                .code
+                .and_then(|code| StatusCode::from_u16(code).ok())
+                .ok_or_else(|| icap_error("Invalid encapsulated response status"))?;
+
+            Ok((status, to_header_map(response.headers)?))
+        }
+        _ => Err(icap_error("Invalid encapsulated response")),
+    }
+}
+
+fn dechunk(mut chunked: &[u8]) -> io::Result<Bytes> {
+    let mut body = Vec::with_capacity(chunked.len());
+
+    loop {
+        let line_end =
+            find(chunked, b"\r\n").ok_or_else(|| icap_error("Incomplete encapsulated body"))?;
+        // Extensions, such as `ieof`, are ignored.
+        let size = std::str::from_utf8(&chunked[..line_end])
+            .ok()
+            .and_then(|line| line.split(';').next())
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or_else(|| icap_error("Invalid chunk size"))?;
+        chunked = &chunked[line_end + 2..];
+
+        if size == 0 {
+            return Ok(Bytes::from(body));
+        }
+
+        let chunk = chunked
+            .get(..size)
+            .ok_or_else(|| icap_error("Incomplete encapsulated body"))?;
+        body.extend_from_slice(chunk);
+        chunked = chunked.get(size + 2..).unwrap_or_default();
+    }
+}
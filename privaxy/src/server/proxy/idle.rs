@@ -0,0 +1,134 @@
+//! Tunnels carry data Privaxy can't see into, they are closed once nothing went through
+//! them for a while so that abandoned connections don't pile up.
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Records when data last went through a stream, in either direction.
+struct IdleTracker<S> {
+    stream: S,
+    started: Instant,
+    /// Milliseconds between `started` and the last activity.
+    last_activity_ms: Arc<AtomicU64>,
+    /// Bytes read and written.
+    bytes: u64,
+}
+
+impl<S> IdleTracker<S> {
+    fn touch(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+        self.last_activity_ms
+            .store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for IdleTracker<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.stream).poll_read(cx, buf);
+        if buf.filled().len() > filled {
+            self.touch(buf.filled().len() - filled);
+        }
+
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for IdleTracker<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.stream).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            if written > 0 {
+                self.touch(written);
+            }
+        }
+
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+pub(crate) struct TunnelEnd {
+    /// Bytes sent and received, even when the tunnel failed.
+    pub(crate) bytes: u64,
+    /// The tunnel was closed as it was idle for too long.
+    pub(crate) idle: bool,
+    pub(crate) result: io::Result<()>,
+}
+
+/// Copies data between `client` and `server` until either closes the connection, or nothing
+/// went through for `idle_timeout`.
+pub(crate) async fn copy_bidirectional_until_idle<C, S>(
+    client: &mut C,
+    server: S,
+    idle_timeout: Option<Duration>,
+) -> TunnelEnd
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let last_activity_ms = Arc::new(AtomicU64::new(0));
+    let started = Instant::now();
+    // Data from the client is written to the server, all traffic goes through it.
+    let mut server = IdleTracker {
+        stream: server,
+        started,
+        last_activity_ms: last_activity_ms.clone(),
+        bytes: 0,
+    };
+
+    let idle_timeout = match idle_timeout {
+        Some(idle_timeout) => idle_timeout,
+        None => {
+            let result = tokio::io::copy_bidirectional(client, &mut server).await;
+
+            return TunnelEnd {
+                bytes: server.bytes,
+                idle: false,
+                result: result.map(|_bytes| ()),
+            };
+        }
+    };
+
+    let idle = async {
+        loop {
+            let last_activity = Duration::from_millis(last_activity_ms.load(Ordering::Relaxed));
+            let idle_for = started.elapsed().saturating_sub(last_activity);
+            if idle_for >= idle_timeout {
+                break;
+            }
+
+            tokio::time::sleep(idle_timeout - idle_for).await;
+        }
+    };
+
+    let (idle, result) = tokio::select! {
+        result = tokio::io::copy_bidirectional(client, &mut server) => (false, result.map(|_bytes| ())),
+        _ = idle => (true, Ok(())),
+    };
+
+    TunnelEnd {
+        bytes: server.bytes,
+        idle,
+        result,
+    }
+}
@@ -1,27 +1,49 @@
-use super::{exclusions::LocalExclusionStore, serve::serve};
-use crate::{blocker::AdblockRequester, cert::CertCache, statistics::Statistics, Event};
+use super::{
+    authentication,
+    bandwidth::{BandwidthLimiter, ThrottledStream},
+    cname::CnameResolver,
+    context::ProxyContext,
+    dns::DnsResolver,
+    egress::{self, EgressRouter},
+    exclusions::LocalExclusionStore,
+    geoip::GeoIp,
+    idle,
+    serve::serve,
+};
+use crate::{
+    cert::CertCache,
+    configuration::{ProxyConfig, ProxyUser, TimeoutsConfig},
+    statistics::{Statistics, TimeoutStage},
+    Event,
+};
 use http::uri::{Authority, Scheme};
 use hyper::{
-    client::HttpConnector, http, server::conn::Http, service::service_fn, upgrade::Upgraded, Body,
-    Method, Request, Response,
+    http, server::conn::Http, service::service_fn, upgrade::Upgraded, Body, Method, Request,
+    Response,
+};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
 };
-use hyper_rustls::HttpsConnector;
-use std::{net::IpAddr, sync::Arc};
-use tokio::{net::TcpStream, sync::broadcast};
 use tokio_rustls::TlsAcceptor;
 
-#[allow(clippy::too_many_arguments)]
 pub(crate) async fn serve_mitm_session(
-    adblock_requester: AdblockRequester,
-    hyper_client: hyper::Client<HttpsConnector<HttpConnector>>,
-    client: reqwest::Client,
-    req: Request<Body>,
+    context: Arc<ProxyContext>,
+    mut req: Request<Body>,
     cert_cache: CertCache,
-    broadcast_tx: broadcast::Sender<Event>,
-    statistics: Statistics,
     client_ip_address: IpAddr,
     local_exclusion_store: LocalExclusionStore,
 ) -> Result<Response<Body>, hyper::Error> {
+    let proxy_config = &context.proxy_config;
+    // Requests tunneled through a `CONNECT` are attributed to the user who opened the tunnel.
+    let client_username =
+        match authentication::authenticate(&proxy_config.authentication, req.headers()) {
+            Ok(client_username) => client_username,
+            Err(()) => return Ok(authentication::get_proxy_authentication_required_response()),
+        };
+    // Credentials are meant for us, not for upstream servers.
+    req.headers_mut().remove(http::header::PROXY_AUTHORIZATION);
+
     let authority = match req.uri().authority().cloned() {
         Some(authority) => authority,
         None => {
@@ -46,39 +68,115 @@ pub(crate) async fn serve_mitm_session(
         // then we can eventually upgrade the connection and talk a new protocol.
         let server_configuration =
             Arc::new(cert_cache.get(authority.clone()).await.server_configuration);
+        // Named profiles replace the exclusions of the default one.
+        let is_excluded_for_profile = proxy_config
+            .profiles
+            .profile_for(client_ip_address, client_username.as_deref())
+            .map(|profile| profile.is_excluded(authority.host()));
+        // Tunneled from an error page.
+        let is_tunneled_for_client = context
+            .temporary_exclusion_store
+            .is_tunneled(authority.host(), client_ip_address);
 
         tokio::task::spawn(async move {
             match hyper::upgrade::on(req).await {
                 Ok(mut upgraded) => {
-                    let is_host_blacklisted = local_exclusion_store.contains(authority.host());
+                    let ProxyContext {
+                        proxy_config,
+                        client_certificate_store,
+                        cname_resolver,
+                        geoip,
+                        broadcast_sender,
+                        statistics,
+                        bandwidth_limiter,
+                        ..
+                    } = &*context;
+                    let is_host_blacklisted = is_excluded_for_profile
+                        .unwrap_or_else(|| local_exclusion_store.contains(authority.host()));
+                    // We don't hold the certificate the origin asks for, interception can't work.
+                    let requires_client_certificate =
+                        client_certificate_store.requires_client_certificate(authority.host());
 
-                    if is_host_blacklisted {
-                        let _result = tunnel(&mut upgraded, &authority).await;
+                    if is_host_blacklisted || requires_client_certificate || is_tunneled_for_client
+                    {
+                        let addresses = match get_tunnel_addresses(
+                            &authority,
+                            cname_resolver,
+                            geoip,
+                            proxy_config,
+                        )
+                        .await
+                        {
+                            Some(addresses) => addresses,
+                            None => return,
+                        };
+
+                        let _result = broadcast_sender.send(Event {
+                            now: chrono::Utc::now(),
+                            method: Method::CONNECT.to_string(),
+                            url: authority.to_string(),
+                            is_request_blocked: false,
+                            blocked_by: None,
+                            is_tunneled: true,
+                            country: addresses
+                                .first()
+                                .and_then(|address| geoip.lookup(address.ip()).country),
+                        });
+
+                        let _result = tunnel(
+                            &mut upgraded,
+                            &authority,
+                            &addresses,
+                            cname_resolver.dns_resolver(),
+                            cname_resolver.egress_router(),
+                            &proxy_config.timeouts,
+                            statistics,
+                            client_username
+                                .as_deref()
+                                .and_then(|username| proxy_config.authentication.user(username)),
+                            bandwidth_limiter,
+                        )
+                        .await;
 
                         return;
                     }
 
-                    let http = Http::new();
+                    let mut http = Http::new();
+                    if let Some(request_timeout) = proxy_config.timeouts.request() {
+                        http.http1_header_read_timeout(request_timeout);
+                    }
 
-                    match TlsAcceptor::from(server_configuration)
-                        .accept(upgraded)
-                        .await
-                    {
+                    let accepting = TlsAcceptor::from(server_configuration).accept(upgraded);
+                    let accepted = match proxy_config.timeouts.tls_handshake() {
+                        Some(tls_handshake_timeout) => {
+                            match tokio::time::timeout(tls_handshake_timeout, accepting).await {
+                                Ok(accepted) => accepted,
+                                Err(_elapsed) => {
+                                    log::debug!(
+                                        "Timed out performing handshake for host: {}",
+                                        authority
+                                    );
+                                    statistics.increment_timeouts(TimeoutStage::TlsHandshake);
+                                    return;
+                                }
+                            }
+                        }
+                        None => accepting.await,
+                    };
+
+                    match accepted {
                         Ok(tls_stream) => {
                             let _result = http
                                 .serve_connection(
                                     tls_stream,
                                     service_fn(move |req| {
                                         serve(
-                                            adblock_requester.clone(),
+                                            context.clone(),
                                             req,
-                                            hyper_client.clone(),
-                                            client.clone(),
                                             authority.clone(),
                                             Scheme::HTTPS,
-                                            broadcast_tx.clone(),
-                                            statistics.clone(),
                                             client_ip_address,
+                                            client_username.clone(),
                                         )
                                     }),
                                 )
@@ -105,24 +203,124 @@ pub(crate) async fn serve_mitm_session(
         // The request is not of method `CONNECT`. Therefore,
         // this request is for an HTTP resource.
         serve(
-            adblock_requester,
+            context,
             req,
-            hyper_client.clone(),
-            client.clone(),
             authority,
             Scheme::HTTP,
-            broadcast_tx,
-            statistics,
             client_ip_address,
+            client_username,
         )
         .await
     }
 }
 
-async fn tunnel(mut upgraded: &mut Upgraded, authority: &Authority) -> std::io::Result<()> {
-    let mut server = TcpStream::connect(authority.to_string()).await?;
+/// Resolves the destination of a tunnel, `None` when it is blocked. Empty when the host is
+/// resolved by a proxy, or couldn't be resolved and won't be connected to.
+///
+/// Connecting to the checked addresses, rather than resolving the host again, ensures
+/// blocked addresses can't be reached through a different DNS answer.
+async fn get_tunnel_addresses(
+    authority: &Authority,
+    cname_resolver: &CnameResolver,
+    geoip: &GeoIp,
+    proxy_config: &ProxyConfig,
+) -> Option<Vec<SocketAddr>> {
+    let has_country_rules = proxy_config.geoip.has_country_rules();
+    let port = authority.port_u16().unwrap_or(443);
+    // Resolved here rather than when connecting, so that cached answers and overrides apply.
+    let ip_addresses = cname_resolver.resolve(authority.host()).await;
+
+    if let Some((ip_address, network)) = proxy_config.find_blocked_ip_address(&ip_addresses) {
+        log::info!(
+            "Refused tunnel to {}: IP address {} in {}",
+            authority,
+            ip_address,
+            network
+        );
+
+        return None;
+    }
 
-    tokio::io::copy_bidirectional(&mut upgraded, &mut server).await?;
+    if has_country_rules {
+        let blocked_country = ip_addresses.iter().find_map(|ip_address| {
+            geoip
+                .lookup(*ip_address)
+                .country
+                .filter(|country| proxy_config.geoip.is_country_blocked(country))
+        });
+
+        if let Some(country) = blocked_country {
+            log::info!("Refused tunnel to {}: located in {}", authority, country);
+
+            return None;
+        }
+    }
+
+    Some(
+        ip_addresses
+            .into_iter()
+            .map(|ip_address| SocketAddr::new(ip_address, port))
+            .collect(),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn tunnel(
+    upgraded: &mut Upgraded,
+    authority: &Authority,
+    addresses: &[SocketAddr],
+    dns_resolver: &DnsResolver,
+    egress_router: &EgressRouter,
+    timeouts: &TimeoutsConfig,
+    statistics: &Statistics,
+    user: Option<&ProxyUser>,
+    bandwidth_limiter: &BandwidthLimiter,
+) -> std::io::Result<()> {
+    let connecting = async {
+        if let Some(proxy) = egress_router.proxy_for(authority.host()) {
+            egress::connect_through(
+                &proxy,
+                authority.host(),
+                authority.port_u16().unwrap_or(443),
+            )
+            .await
+        } else {
+            // Hosts which couldn't be resolved fail to connect, rather than being resolved again
+            // by other means whose addresses wouldn't be checked.
+            dns_resolver.connect(addresses).await
+        }
+    };
+    let server = match timeouts.connect() {
+        Some(connect_timeout) => match tokio::time::timeout(connect_timeout, connecting).await {
+            Ok(server) => server?,
+            Err(_elapsed) => {
+                log::debug!("Timed out connecting to {}", authority);
+                statistics.increment_timeouts(TimeoutStage::Connect);
+                return Ok(());
+            }
+        },
+        None => connecting.await?,
+    };
+
+    statistics.increment_tunneled_connections(authority.to_string());
+
+    // Downloads through tunnels count towards the bandwidth of the user as well.
+    let server = match user {
+        Some(user) if user.bandwidth_limit_kbps > 0 => bandwidth_limiter.throttle_stream(
+            &user.username,
+            user.bandwidth_limit_kbps * 1024,
+            server,
+        ),
+        _ => ThrottledStream::unlimited(server),
+    };
+
+    let end = idle::copy_bidirectional_until_idle(upgraded, server, timeouts.tunnel_idle()).await;
+    statistics.add_tunneled_bytes(authority.to_string(), end.bytes);
+    if end.idle {
+        log::debug!("Closed idle tunnel to {}", authority);
+        statistics.increment_timeouts(TimeoutStage::TunnelIdle);
+    }
+    end.result?;
 
     log::debug!("Started tunneling host: {}", authority);
 
@@ -1,5 +1,32 @@
+pub(crate) mod authentication;
+pub(crate) mod bandwidth;
+pub(crate) mod cache;
+pub(crate) mod capture;
+pub(crate) mod categories;
+pub(crate) mod circuit_breaker;
+pub(crate) mod client_certificates;
+pub(crate) mod cname;
+pub(crate) mod context;
+pub(crate) mod dns;
+pub(crate) mod egress;
+pub(crate) mod encoding;
 pub(crate) mod mitm;
 pub(crate) mod serve;
 pub(crate) use mitm::serve_mitm_session;
 pub(crate) mod exclusions;
+pub(crate) mod frame_context;
+pub(crate) mod geoip;
 pub(crate) mod html_rewriter;
+pub(crate) mod https_upgrade;
+pub(crate) mod icap;
+pub(crate) mod idle;
+pub(crate) mod ntlm;
+pub(crate) mod parent_proxy;
+pub(crate) mod pool;
+pub(crate) mod proxy_protocol;
+pub(crate) mod resource_type;
+pub(crate) mod scripting;
+pub(crate) mod templates;
+pub(crate) mod threats;
+pub(crate) mod trailers;
+pub(crate) mod upstream_errors;
@@ -0,0 +1,212 @@
+//! NTLMv2 messages (MS-NLMP), to authenticate to proxies requiring NTLM. Only authentication is
+//! performed, the session isn't signed nor sealed.
+use hmac::{Hmac, Mac};
+use md4::{Digest, Md4};
+use md5::Md5;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SIGNATURE: &[u8; 8] = b"NTLMSSP\0";
+const NEGOTIATE_MESSAGE: u32 = 1;
+const CHALLENGE_MESSAGE: u32 = 2;
+const AUTHENTICATE_MESSAGE: u32 = 3;
+
+const NEGOTIATE_UNICODE: u32 = 0x0000_0001;
+const REQUEST_TARGET: u32 = 0x0000_0004;
+const NEGOTIATE_NTLM: u32 = 0x0000_0200;
+const NEGOTIATE_ALWAYS_SIGN: u32 = 0x0000_8000;
+const NEGOTIATE_EXTENDED_SESSIONSECURITY: u32 = 0x0008_0000;
+const NEGOTIATE_TARGET_INFO: u32 = 0x0080_0000;
+const NEGOTIATE_128: u32 = 0x2000_0000;
+const NEGOTIATE_56: u32 = 0x8000_0000;
+
+const FLAGS: u32 = NEGOTIATE_UNICODE
+    | REQUEST_TARGET
+    | NEGOTIATE_NTLM
+    | NEGOTIATE_ALWAYS_SIGN
+    | NEGOTIATE_EXTENDED_SESSIONSECURITY
+    | NEGOTIATE_TARGET_INFO
+    | NEGOTIATE_128
+    | NEGOTIATE_56;
+
+/// `MsvAvTimestamp` of the target information, when servers send it the LMv2 response is empty.
+const AV_TIMESTAMP: u16 = 7;
+const AV_EOL: u16 = 0;
+
+/// Seconds between 1601-01-01, the epoch of Windows timestamps, and 1970-01-01.
+const WINDOWS_EPOCH_OFFSET: u64 = 11_644_473_600;
+
+type HmacMd5 = Hmac<Md5>;
+
+pub(crate) struct Challenge {
+    server_challenge: [u8; 8],
+    flags: u32,
+    target_info: Vec<u8>,
+}
+
+pub(crate) fn negotiate_message() -> Vec<u8> {
+    let mut message = Vec::with_capacity(32);
+    message.extend_from_slice(SIGNATURE);
+    message.extend_from_slice(&NEGOTIATE_MESSAGE.to_le_bytes());
+    message.extend_from_slice(&FLAGS.to_le_bytes());
+    // Empty domain and workstation.
+    message.extend_from_slice(&[0; 16]);
+
+    message
+}
+
+fn read_u16(message: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        message.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(message: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        message.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Contents of the security buffer described at `offset`.
+fn read_buffer(message: &[u8], offset: usize) -> Option<&[u8]> {
+    let length = read_u16(message, offset)? as usize;
+    let start = read_u32(message, offset + 4)? as usize;
+
+    message.get(start..start.checked_add(length)?)
+}
+
+pub(crate) fn parse_challenge_message(message: &[u8]) -> Option<Challenge> {
+    if message.get(..8)? != SIGNATURE || read_u32(message, 8)? != CHALLENGE_MESSAGE {
+        return None;
+    }
+
+    let flags = read_u32(message, 20)?;
+    let server_challenge = message.get(24..32)?.try_into().ok()?;
+    let target_info = if flags & NEGOTIATE_TARGET_INFO != 0 {
+        read_buffer(message, 40)?.to_vec()
+    } else {
+        Vec::new()
+    };
+
+    Some(Challenge {
+        server_challenge,
+        flags,
+        target_info,
+    })
+}
+
+fn utf16_le(value: &str) -> Vec<u8> {
+    value.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn hmac_md5(key: &[u8], parts: &[&[u8]]) -> [u8; 16] {
+    let mut mac = HmacMd5::new_from_slice(key).unwrap();
+    for part in parts {
+        mac.update(part);
+    }
+
+    mac.finalize().into_bytes().into()
+}
+
+/// Value of the `MsvAvTimestamp` pair of `target_info`.
+fn target_timestamp(target_info: &[u8]) -> Option<[u8; 8]> {
+    let mut offset = 0;
+    loop {
+        let id = read_u16(target_info, offset)?;
+        let length = read_u16(target_info, offset + 2)? as usize;
+        match id {
+            AV_EOL => return None,
+            AV_TIMESTAMP => {
+                return target_info
+                    .get(offset + 4..offset + 4 + length)?
+                    .try_into()
+                    .ok()
+            }
+            _ => offset += 4 + length,
+        }
+    }
+}
+
+fn current_timestamp() -> [u8; 8] {
+    let since_unix_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let ticks = (since_unix_epoch.as_secs() + WINDOWS_EPOCH_OFFSET) * 10_000_000
+        + u64::from(since_unix_epoch.subsec_nanos() / 100);
+
+    ticks.to_le_bytes()
+}
+
+pub(crate) fn authenticate_message(
+    challenge: &Challenge,
+    username: &str,
+    password: &str,
+    domain: &str,
+) -> Vec<u8> {
+    let nt_hash = Md4::digest(utf16_le(password));
+    let ntlmv2_hash = hmac_md5(
+        &nt_hash,
+        &[&utf16_le(&username.to_uppercase()), &utf16_le(domain)],
+    );
+
+    let mut client_challenge = [0u8; 8];
+    openssl::rand::rand_bytes(&mut client_challenge).unwrap();
+    let target_timestamp = target_timestamp(&challenge.target_info);
+
+    let mut blob = vec![0x01, 0x01, 0, 0, 0, 0, 0, 0];
+    blob.extend_from_slice(&target_timestamp.unwrap_or_else(current_timestamp));
+    blob.extend_from_slice(&client_challenge);
+    blob.extend_from_slice(&[0; 4]);
+    blob.extend_from_slice(&challenge.target_info);
+    blob.extend_from_slice(&[0; 4]);
+
+    let nt_proof = hmac_md5(&ntlmv2_hash, &[&challenge.server_challenge, &blob]);
+    let mut nt_response = nt_proof.to_vec();
+    nt_response.extend_from_slice(&blob);
+
+    let lm_response = match target_timestamp {
+        Some(_) => vec![0; 24],
+        None => {
+            let mut lm_response = hmac_md5(
+                &ntlmv2_hash,
+                &[&challenge.server_challenge, &client_challenge],
+            )
+            .to_vec();
+            lm_response.extend_from_slice(&client_challenge);
+            lm_response
+        }
+    };
+
+    let flags = (challenge.flags & FLAGS) | NEGOTIATE_UNICODE | NEGOTIATE_NTLM;
+    let payloads = [
+        lm_response,
+        nt_response,
+        utf16_le(domain),
+        utf16_le(username),
+        // Workstation.
+        Vec::new(),
+        // Session key.
+        Vec::new(),
+    ];
+
+    let header_length = 64;
+    let mut message =
+        Vec::with_capacity(header_length + payloads.iter().map(Vec::len).sum::<usize>());
+    message.extend_from_slice(SIGNATURE);
+    message.extend_from_slice(&AUTHENTICATE_MESSAGE.to_le_bytes());
+
+    let mut offset = header_length as u32;
+    for payload in &payloads {
+        let length = payload.len() as u16;
+        message.extend_from_slice(&length.to_le_bytes());
+        message.extend_from_slice(&length.to_le_bytes());
+        message.extend_from_slice(&offset.to_le_bytes());
+        offset += payload.len() as u32;
+    }
+    message.extend_from_slice(&flags.to_le_bytes());
+
+    for payload in payloads {
+        message.extend_from_slice(&payload);
+    }
+
+    message
+}
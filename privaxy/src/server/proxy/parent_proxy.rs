@@ -0,0 +1,454 @@
+//! Tunnels through HTTP parent proxies, authenticating with Basic, NTLM or Negotiate.
+//!
+//! reqwest can't authenticate with NTLM nor Negotiate, which are connection based, so every
+//! connection through an HTTP proxy goes through a SOCKS relay on the loopback interface that
+//! opens the `CONNECT` tunnels. The relay requires a random password so that other users of the
+//! machine can't reach the proxy with Privaxy's credentials.
+use super::egress::{
+    CONNECT_COMMAND, DOMAIN_NAME, IPV4_ADDRESS, IPV6_ADDRESS, NO_ACCEPTABLE_METHOD, SOCKS_VERSION,
+    USERNAME_PASSWORD_AUTHENTICATION,
+};
+use super::ntlm;
+use crate::configuration::{HttpProxy, ProxyAuthentication, SocksProxy};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const MAX_RESPONSE_HEAD_BYTES: usize = 64 * 1024;
+const MAX_HEADERS: usize = 64;
+/// Negotiate may take a few rounds, NTLM takes two.
+const MAX_AUTHENTICATION_ROUNDS: usize = 4;
+const RELAY_USERNAME: &str = "privaxy";
+const SUCCEEDED: u8 = 0x00;
+const GENERAL_FAILURE: u8 = 0x01;
+const COMMAND_NOT_SUPPORTED: u8 = 0x07;
+
+/// Relays by parent proxy, kept for the lifetime of the process.
+static RELAYS: Lazy<Mutex<HashMap<HttpProxy, SocksProxy>>> = Lazy::new(Default::default);
+
+fn proxy_error(message: impl Into<String>) -> io::Error {
+    io::Error::other(message.into())
+}
+
+/// Handshake with the proxy, producing the successive `Proxy-Authorization` values.
+enum Authentication<'a> {
+    None,
+    Basic(String),
+    Ntlm {
+        username: &'a str,
+        password: &'a str,
+        domain: &'a str,
+        answered: bool,
+    },
+    #[cfg(feature = "kerberos")]
+    Negotiate {
+        service_principal: String,
+        context: Option<cross_krb5::PendingClientCtx>,
+    },
+}
+
+impl<'a> Authentication<'a> {
+    fn new(proxy: &'a HttpProxy) -> io::Result<Self> {
+        match &proxy.authentication {
+            None => Ok(Self::None),
+            Some(ProxyAuthentication::Basic { username, password }) => Ok(Self::Basic(format!(
+                "Basic {}",
+                BASE64.encode(format!("{}:{}", username, password))
+            ))),
+            Some(ProxyAuthentication::Ntlm {
+                username,
+                password,
+                domain,
+            }) => {
+                let (domain, username) = match (domain, username.split_once('\\')) {
+                    (Some(domain), _) => (domain.as_str(), username.as_str()),
+                    (None, Some((domain, username))) => (domain, username),
+                    (None, None) => ("", username.as_str()),
+                };
+
+                Ok(Self::Ntlm {
+                    username,
+                    password,
+                    domain,
+                    answered: false,
+                })
+            }
+            #[cfg(feature = "kerberos")]
+            Some(ProxyAuthentication::Negotiate { service_principal }) => {
+                let proxy_host = proxy
+                    .address
+                    .rsplit_once(':')
+                    .map_or(proxy.address.as_str(), |(host, _port)| host);
+
+                Ok(Self::Negotiate {
+                    service_principal: service_principal
+                        .clone()
+                        .unwrap_or_else(|| format!("HTTP/{}", proxy_host)),
+                    context: None,
+                })
+            }
+            #[cfg(not(feature = "kerberos"))]
+            Some(ProxyAuthentication::Negotiate { .. }) => Err(proxy_error(
+                "Negotiate authentication requires Privaxy to be built with the kerberos feature",
+            )),
+        }
+    }
+
+    fn scheme(&self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Basic(_) => "Basic",
+            Self::Ntlm { .. } => "NTLM",
+            #[cfg(feature = "kerberos")]
+            Self::Negotiate { .. } => "Negotiate",
+        }
+    }
+
+    /// Authorization of the first request.
+    fn first(&mut self) -> io::Result<Option<String>> {
+        match self {
+            Self::None => Ok(None),
+            Self::Basic(authorization) => Ok(Some(authorization.clone())),
+            Self::Ntlm { .. } => Ok(Some(format!(
+                "NTLM {}",
+                BASE64.encode(ntlm::negotiate_message())
+            ))),
+            #[cfg(feature = "kerberos")]
+            Self::Negotiate {
+                service_principal,
+                context,
+            } => {
+                let (pending, token) = cross_krb5::ClientCtx::new(
+                    cross_krb5::InitiateFlags::empty(),
+                    None,
+                    service_principal,
+                    None,
+                )
+                .map_err(|err| proxy_error(format!("Kerberos: {}", err)))?;
+                *context = Some(pending);
+
+                Ok(Some(format!("Negotiate {}", BASE64.encode(&*token))))
+            }
+        }
+    }
+
+    /// Authorization answering the `challenge` of a `407` response, `None` when the credentials
+    /// were rejected.
+    fn answer(&mut self, challenge: Option<&str>) -> io::Result<Option<String>> {
+        let challenge = challenge
+            .map(|challenge| BASE64.decode(challenge.trim()))
+            .transpose()
+            .map_err(|_err| proxy_error("Invalid authentication challenge"))?;
+
+        match (self, challenge) {
+            (
+                Self::Ntlm {
+                    username,
+                    password,
+                    domain,
+                    answered: answered @ false,
+                },
+                Some(challenge),
+            ) => {
+                let challenge = ntlm::parse_challenge_message(&challenge)
+                    .ok_or_else(|| proxy_error("Invalid NTLM challenge"))?;
+                *answered = true;
+
+                Ok(Some(format!(
+                    "NTLM {}",
+                    BASE64.encode(ntlm::authenticate_message(
+                        &challenge, username, password, domain
+                    ))
+                )))
+            }
+            #[cfg(feature = "kerberos")]
+            (Self::Negotiate { context, .. }, Some(challenge)) => {
+                let pending = match context.take() {
+                    Some(pending) => pending,
+                    None => return Ok(None),
+                };
+
+                match pending
+                    .step(&challenge)
+                    .map_err(|err| proxy_error(format!("Kerberos: {}", err)))?
+                {
+                    cross_krb5::Step::Finished((_context, Some(token))) => {
+                        Ok(Some(format!("Negotiate {}", BASE64.encode(&*token))))
+                    }
+                    cross_krb5::Step::Finished((_context, None)) => Ok(None),
+                    cross_krb5::Step::Continue((pending, token)) => {
+                        *context = Some(pending);
+                        Ok(Some(format!("Negotiate {}", BASE64.encode(&*token))))
+                    }
+                }
+            }
+            (_, _) => Ok(None),
+        }
+    }
+}
+
+struct ResponseHead {
+    status: u16,
+    /// `Proxy-Authenticate` values.
+    challenges: Vec<String>,
+    content_length: usize,
+    close: bool,
+}
+
+impl ResponseHead {
+    /// Challenge of `scheme`, without the scheme.
+    fn challenge(&self, scheme: &str) -> Option<&str> {
+        self.challenges.iter().find_map(|challenge| {
+            let (challenge_scheme, value) = challenge
+                .split_once(' ')
+                .unwrap_or((challenge.as_str(), ""));
+
+            (challenge_scheme.eq_ignore_ascii_case(scheme) && !value.trim().is_empty())
+                .then_some(value)
+        })
+    }
+}
+
+/// Reads the head of a response, and not a byte more as the tunnel follows it.
+async fn read_response_head(stream: &mut TcpStream) -> io::Result<ResponseHead> {
+    let mut head = Vec::with_capacity(512);
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_RESPONSE_HEAD_BYTES {
+            return Err(proxy_error("The proxy response is too large"));
+        }
+        head.push(stream.read_u8().await?);
+    }
+
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut response = httparse::Response::new(&mut headers);
+    match response.parse(&head) {
+        Ok(httparse::Status::Complete(_length)) => {}
+        _ => return Err(proxy_error("Invalid proxy response")),
+    }
+
+    let header_values = |name: &'static str| {
+        response
+            .headers
+            .iter()
+            .filter(move |header| header.name.eq_ignore_ascii_case(name))
+            .filter_map(|header| std::str::from_utf8(header.value).ok())
+    };
+
+    Ok(ResponseHead {
+        status: response.code.unwrap_or_default(),
+        challenges: header_values("proxy-authenticate")
+            .map(str::to_string)
+            .collect(),
+        content_length: header_values("content-length")
+            .find_map(|value| value.trim().parse().ok())
+            .unwrap_or(0),
+        close: header_values("connection")
+            .chain(header_values("proxy-connection"))
+            .any(|value| value.trim().eq_ignore_ascii_case("close")),
+    })
+}
+
+/// Connects to `host:port` with a `CONNECT` tunnel through `proxy`.
+pub(crate) async fn connect_through(
+    proxy: &HttpProxy,
+    host: &str,
+    port: u16,
+) -> io::Result<TcpStream> {
+    let authority = if host.contains(':') && !host.starts_with('[') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+
+    let mut authentication = Authentication::new(proxy)?;
+    let mut authorization = authentication.first()?;
+    let mut stream = TcpStream::connect(proxy.address.as_str()).await?;
+
+    for _round in 0..MAX_AUTHENTICATION_ROUNDS {
+        let mut request = format!(
+            "CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\nProxy-Connection: Keep-Alive\r\n"
+        );
+        if let Some(authorization) = &authorization {
+            request.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        let response = read_response_head(&mut stream).await?;
+        match response.status {
+            200..=299 => return Ok(stream),
+            407 => {}
+            status => {
+                return Err(proxy_error(format!(
+                    "{} refused to connect to {}: {}",
+                    proxy.address, authority, status
+                )))
+            }
+        }
+
+        authorization = match authentication.answer(response.challenge(authentication.scheme()))? {
+            Some(authorization) => Some(authorization),
+            None => {
+                return Err(proxy_error(format!(
+                    "{} rejected the credentials",
+                    proxy.address
+                )))
+            }
+        };
+
+        // The handshake continues on the same connection, which NTLM requires.
+        if response.close {
+            stream = TcpStream::connect(proxy.address.as_str()).await?;
+        } else {
+            let mut body = (&mut stream).take(response.content_length as u64);
+            tokio::io::copy(&mut body, &mut tokio::io::sink()).await?;
+        }
+    }
+
+    Err(proxy_error(format!(
+        "{} kept requesting authentication",
+        proxy.address
+    )))
+}
+
+/// SOCKS proxy relaying connections to `proxy`, started on first use.
+pub(crate) fn relay(proxy: &HttpProxy) -> io::Result<SocksProxy> {
+    let mut relays = RELAYS.lock().unwrap();
+    if let Some(relay) = relays.get(proxy) {
+        return Ok(relay.clone());
+    }
+
+    let runtime = tokio::runtime::Handle::try_current().map_err(io::Error::other)?;
+    let _guard = runtime.enter();
+
+    let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    listener.set_nonblocking(true)?;
+    let listener = TcpListener::from_std(listener)?;
+
+    let mut password = [0u8; 16];
+    openssl::rand::rand_bytes(&mut password).map_err(io::Error::from)?;
+    let password: Arc<str> = hex::encode(password).into();
+
+    let relay = SocksProxy {
+        address: listener.local_addr()?.to_string(),
+        credentials: Some((RELAY_USERNAME.to_string(), password.to_string())),
+    };
+    log::debug!("Relaying to {} from {}", proxy.address, relay.address);
+
+    let proxy_clone = proxy.clone();
+    runtime.spawn(async move {
+        loop {
+            let (client, _address) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(err) => {
+                    log::debug!("Relay to {} failed to accept: {}", proxy_clone.address, err);
+                    continue;
+                }
+            };
+
+            let proxy = proxy_clone.clone();
+            let password = password.clone();
+            tokio::spawn(async move {
+                if let Err(err) = relay_connection(client, &proxy, &password).await {
+                    log::debug!("Unable to relay to {}: {}", proxy.address, err);
+                }
+            });
+        }
+    });
+
+    relays.insert(proxy.clone(), relay.clone());
+
+    Ok(relay)
+}
+
+/// Serves a SOCKS5 client (RFC 1928), which must authenticate with `password` (RFC 1929).
+async fn relay_connection(
+    mut client: TcpStream,
+    proxy: &HttpProxy,
+    password: &str,
+) -> io::Result<()> {
+    let mut greeting = [0u8; 2];
+    client.read_exact(&mut greeting).await?;
+    let mut methods = vec![0u8; greeting[1] as usize];
+    client.read_exact(&mut methods).await?;
+    if greeting[0] != SOCKS_VERSION || !methods.contains(&USERNAME_PASSWORD_AUTHENTICATION) {
+        client
+            .write_all(&[SOCKS_VERSION, NO_ACCEPTABLE_METHOD])
+            .await?;
+        return Err(proxy_error("The client doesn't authenticate"));
+    }
+    client
+        .write_all(&[SOCKS_VERSION, USERNAME_PASSWORD_AUTHENTICATION])
+        .await?;
+
+    let mut username_length = [0u8; 2];
+    client.read_exact(&mut username_length).await?;
+    let mut username = vec![0u8; username_length[1] as usize];
+    client.read_exact(&mut username).await?;
+    let mut client_password = vec![0u8; client.read_u8().await? as usize];
+    client.read_exact(&mut client_password).await?;
+    if client_password.len() != password.len()
+        || !openssl::memcmp::eq(&client_password, password.as_bytes())
+    {
+        client.write_all(&[0x01, 0x01]).await?;
+        return Err(proxy_error("The client sent the wrong password"));
+    }
+    client.write_all(&[0x01, 0x00]).await?;
+
+    let mut request = [0u8; 4];
+    client.read_exact(&mut request).await?;
+    if request[1] != CONNECT_COMMAND {
+        reply(&mut client, COMMAND_NOT_SUPPORTED).await?;
+        return Err(proxy_error("Only CONNECT is supported"));
+    }
+    let host = match request[3] {
+        IPV4_ADDRESS => {
+            let mut address = [0u8; 4];
+            client.read_exact(&mut address).await?;
+            Ipv4Addr::from(address).to_string()
+        }
+        IPV6_ADDRESS => {
+            let mut address = [0u8; 16];
+            client.read_exact(&mut address).await?;
+            Ipv6Addr::from(address).to_string()
+        }
+        DOMAIN_NAME => {
+            let mut domain = vec![0u8; client.read_u8().await? as usize];
+            client.read_exact(&mut domain).await?;
+            String::from_utf8(domain).map_err(|_err| proxy_error("Invalid domain name"))?
+        }
+        address_type => {
+            return Err(proxy_error(format!(
+                "Unknown SOCKS address type {}",
+                address_type
+            )))
+        }
+    };
+    let port = client.read_u16().await?;
+
+    let mut server = match connect_through(proxy, &host, port).await {
+        Ok(server) => server,
+        Err(err) => {
+            reply(&mut client, GENERAL_FAILURE).await?;
+            return Err(err);
+        }
+    };
+    reply(&mut client, SUCCEEDED).await?;
+
+    tokio::io::copy_bidirectional(&mut client, &mut server).await?;
+
+    Ok(())
+}
+
+/// Replies to a SOCKS request. The bound address is left unspecified, clients don't use it.
+async fn reply(client: &mut TcpStream, status: u8) -> io::Result<()> {
+    client
+        .write_all(&[SOCKS_VERSION, status, 0x00, IPV4_ADDRESS, 0, 0, 0, 0, 0, 0])
+        .await
+}
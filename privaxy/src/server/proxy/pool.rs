@@ -0,0 +1,100 @@
+//! Connections opened by the upstream clients, to tell how well their pools are reused.
+//!
+//! Neither reqwest nor hyper expose their pools. They resolve the host of every connection
+//! they open though, and only then, so resolutions are counted instead.
+use super::dns::DnsResolver;
+use crate::configuration::IpNetwork;
+use crate::statistics::Statistics;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Resolve, Resolving};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{io, vec};
+use tower_service::Service;
+
+#[derive(Clone)]
+pub(crate) struct CountingResolver {
+    resolver: DnsResolver,
+    statistics: Statistics,
+    /// Addresses in these networks are left out of resolutions, so that a host resolving to
+    /// another address once its request was checked still can't be connected to them.
+    blocked_networks: Arc<Vec<IpNetwork>>,
+}
+
+impl CountingResolver {
+    pub(crate) fn new(resolver: DnsResolver, statistics: Statistics) -> Self {
+        Self {
+            resolver,
+            statistics,
+            blocked_networks: Arc::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn with_blocked_networks(mut self, blocked_networks: Vec<IpNetwork>) -> Self {
+        self.blocked_networks = Arc::new(blocked_networks);
+        self
+    }
+
+    async fn resolve_allowed(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        let addresses = self.resolver.resolve_socket_addresses(host).await?;
+        if self.blocked_networks.is_empty() {
+            return Ok(addresses);
+        }
+
+        let resolved = addresses.len();
+        let addresses = addresses
+            .into_iter()
+            .filter(|address| {
+                !self
+                    .blocked_networks
+                    .iter()
+                    .any(|network| network.contains(&address.ip()))
+            })
+            .collect::<Vec<_>>();
+
+        if addresses.is_empty() && resolved > 0 {
+            log::info!("Refused connection to {}: its addresses are blocked", host);
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} resolves to blocked networks only", host),
+            ));
+        }
+
+        Ok(addresses)
+    }
+}
+
+impl Resolve for CountingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        self.statistics.increment_upstream_connections();
+        let resolver = self.clone();
+
+        Box::pin(async move {
+            let addresses: reqwest::dns::Addrs =
+                Box::new(resolver.resolve_allowed(name.as_str()).await?.into_iter());
+
+            Ok(addresses)
+        })
+    }
+}
+
+/// Used by hyper's `HttpConnector`.
+impl Service<Name> for CountingResolver {
+    type Response = vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        self.statistics.increment_upstream_connections();
+        let resolver = self.clone();
+
+        Box::pin(async move { Ok(resolver.resolve_allowed(name.as_str()).await?.into_iter()) })
+    }
+}
@@ -0,0 +1,255 @@
+//! PROXY protocol (v1 and v2) support, so that clients connecting through a load balancer
+//! are seen with their own address rather than the load balancer's.
+//!
+//! See <https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt>.
+use crate::configuration::IpNetwork;
+use crate::connection_limits::ConnectionPermit;
+use futures_util::{Stream, StreamExt};
+use hyper::server::conn::AddrStream;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc;
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+const V1_PREFIX: &[u8] = b"PROXY ";
+/// Longest possible v1 header, including the trailing CRLF.
+const V1_MAX_LENGTH: usize = 107;
+
+/// Clients have this long to send the PROXY header before being disconnected.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An accepted connection along with the address of the client behind it.
+pub(crate) struct ClientStream {
+    stream: AddrStream,
+    client_address: SocketAddr,
+    /// Released when the connection is closed.
+    permit: Option<ConnectionPermit>,
+}
+
+impl ClientStream {
+    pub(crate) fn new(stream: AddrStream) -> Self {
+        let client_address = stream.remote_addr();
+
+        Self {
+            stream,
+            client_address,
+            permit: None,
+        }
+    }
+
+    pub(crate) fn with_permit(mut self, permit: ConnectionPermit) -> Self {
+        self.permit = Some(permit);
+        self
+    }
+
+    pub(crate) fn client_address(&self) -> SocketAddr {
+        self.client_address
+    }
+}
+
+impl AsyncRead for ClientStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ClientStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Accepts connections right away and reads their PROXY header in a task of their own, so
+/// that slow or silent clients do not hold up others. Only peers in `trusted_senders` may
+/// convey a client address, connections from other peers are served with their own address
+/// and whatever they send is read as a request. Connections without a valid header are dropped.
+pub(crate) fn accept<S>(
+    connections: S,
+    trusted_senders: Arc<Vec<IpNetwork>>,
+) -> impl Stream<Item = io::Result<ClientStream>> + Send
+where
+    S: Stream<Item = io::Result<AddrStream>> + Send,
+{
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+
+    let accepted = connections
+        .filter_map(|connection| async move { connection.ok() })
+        .filter_map(move |stream| {
+            let peer_address = stream.remote_addr();
+
+            let connection = if is_trusted(&trusted_senders, peer_address.ip()) {
+                tokio::spawn(read_client_address(stream, sender.clone()));
+                None
+            } else {
+                Some(ClientStream::new(stream))
+            };
+
+            async move { connection }
+        });
+    let headers_read = futures_util::stream::poll_fn(move |cx| receiver.poll_recv(cx));
+
+    // Ends once the listener is closed and every pending header has been read.
+    futures_util::stream::select(accepted, headers_read).map(Ok)
+}
+
+fn is_trusted(trusted_senders: &[IpNetwork], peer_address: IpAddr) -> bool {
+    // Peers connecting over IPv6 to a dual stack socket have mapped IPv4 addresses.
+    let peer_address = match peer_address {
+        IpAddr::V6(address) => address.to_ipv4_mapped().map_or(peer_address, IpAddr::V4),
+        IpAddr::V4(_) => peer_address,
+    };
+
+    trusted_senders
+        .iter()
+        .any(|network| network.contains(&peer_address))
+}
+
+async fn read_client_address(
+    mut stream: AddrStream,
+    connections: mpsc::UnboundedSender<ClientStream>,
+) {
+    let peer_address = stream.remote_addr();
+
+    match tokio::time::timeout(HEADER_TIMEOUT, read_header(&mut stream)).await {
+        Ok(Ok(client_address)) => {
+            // The listener may have been closed in the meantime.
+            let _ = connections.send(ClientStream {
+                stream,
+                // `LOCAL` connections, such as health checks, carry no address.
+                client_address: client_address.unwrap_or(peer_address),
+                permit: None,
+            });
+        }
+        Ok(Err(err)) => {
+            log::debug!("Invalid PROXY header from {}: {}", peer_address, err);
+        }
+        Err(_) => {
+            log::debug!("No PROXY header received from {}", peer_address);
+        }
+    }
+}
+
+fn invalid_header(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+/// Reads exactly the PROXY header, leaving the rest of the stream untouched.
+async fn read_header<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    // The shortest v1 header, `PROXY UNKNOWN\r\n`, is longer than the v2 signature.
+    let mut prefix = [0u8; 12];
+    stream.read_exact(&mut prefix).await?;
+
+    if &prefix == V2_SIGNATURE {
+        read_v2_header(stream).await
+    } else if prefix.starts_with(V1_PREFIX) {
+        read_v1_header(stream, &prefix).await
+    } else {
+        Err(invalid_header("missing PROXY header"))
+    }
+}
+
+async fn read_v1_header<R: AsyncRead + Unpin>(
+    stream: &mut R,
+    prefix: &[u8],
+) -> io::Result<Option<SocketAddr>> {
+    let mut header = prefix.to_vec();
+
+    while !header.ends_with(b"\r\n") {
+        if header.len() >= V1_MAX_LENGTH {
+            return Err(invalid_header("v1 header too long"));
+        }
+        header.push(stream.read_u8().await?);
+    }
+
+    let header = std::str::from_utf8(&header[V1_PREFIX.len()..header.len() - 2])
+        .map_err(|_| invalid_header("v1 header is not valid ASCII"))?;
+    let fields = header.split(' ').collect::<Vec<_>>();
+
+    match fields.as_slice() {
+        ["UNKNOWN", ..] => Ok(None),
+        ["TCP4" | "TCP6", source, _destination, source_port, _destination_port] => {
+            let source = IpAddr::from_str(source)
+                .map_err(|_| invalid_header("invalid v1 source address"))?;
+            let source_port =
+                u16::from_str(source_port).map_err(|_| invalid_header("invalid v1 source port"))?;
+
+            Ok(Some(SocketAddr::new(source, source_port)))
+        }
+        _ => Err(invalid_header("malformed v1 header")),
+    }
+}
+
+async fn read_v2_header<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    let version_command = stream.read_u8().await?;
+    let family = stream.read_u8().await?;
+    let length = stream.read_u16().await? as usize;
+
+    let mut addresses = vec![0u8; length];
+    stream.read_exact(&mut addresses).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid_header("unsupported version"));
+    }
+
+    // `LOCAL` command.
+    if version_command & 0x0f == 0 {
+        return Ok(None);
+    }
+
+    match family {
+        // TCP over IPv4.
+        0x11 if length >= 12 => {
+            let source = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            let source_port = u16::from_be_bytes([addresses[8], addresses[9]]);
+
+            Ok(Some(SocketAddr::new(source.into(), source_port)))
+        }
+        // TCP over IPv6.
+        0x21 if length >= 36 => {
+            let mut source = [0u8; 16];
+            source.copy_from_slice(&addresses[..16]);
+            let source_port = u16::from_be_bytes([addresses[32], addresses[33]]);
+
+            Ok(Some(SocketAddr::new(
+                Ipv6Addr::from(source).into(),
+                source_port,
+            )))
+        }
+        // Unspecified or unsupported families carry no usable address.
+        _ => Ok(None),
+    }
+}
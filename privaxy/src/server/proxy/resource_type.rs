@@ -0,0 +1,101 @@
+use hyper::{http, Request};
+
+/// The kind of resource a request is fetching, as far as we can tell from the outside.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ResourceType {
+    Document,
+    Subdocument,
+    Image,
+    Script,
+    Stylesheet,
+    Font,
+    Media,
+    Xhr,
+    Other,
+}
+
+impl ResourceType {
+    /// Guesses the resource type of a request.
+    ///
+    /// `Sec-Fetch-Dest` is used when the client sends it, which is the case for all modern browsers.
+    /// Otherwise, we fallback on the `Accept` header and then, on the extension of the requested path.
+    pub(crate) fn from_request<B>(request: &Request<B>) -> Self {
+        let headers = request.headers();
+
+        if let Some(destination) = headers
+            .get("sec-fetch-dest")
+            .and_then(|value| value.to_str().ok())
+        {
+            let resource_type = Self::from_fetch_destination(destination);
+
+            if resource_type != Self::Other {
+                return resource_type;
+            }
+        }
+
+        if let Some(accept) = headers
+            .get(http::header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+        {
+            if accept.starts_with("text/html") {
+                return Self::Document;
+            } else if accept.starts_with("image/") {
+                return Self::Image;
+            } else if accept.starts_with("text/css") {
+                return Self::Stylesheet;
+            } else if accept.starts_with("application/json") {
+                return Self::Xhr;
+            }
+        }
+
+        Self::from_path(request.uri().path())
+    }
+
+    fn from_fetch_destination(destination: &str) -> Self {
+        match destination {
+            "document" => Self::Document,
+            "iframe" | "frame" | "embed" | "object" => Self::Subdocument,
+            "image" => Self::Image,
+            "script" | "worker" | "sharedworker" | "serviceworker" => Self::Script,
+            "style" => Self::Stylesheet,
+            "font" => Self::Font,
+            "audio" | "video" | "track" => Self::Media,
+            // Requests performed by `fetch` or `XMLHttpRequest`.
+            "empty" => Self::Xhr,
+            _ => Self::Other,
+        }
+    }
+
+    fn from_path(path: &str) -> Self {
+        let extension = match path.rsplit_once('.') {
+            Some((_, extension)) => extension.to_lowercase(),
+            None => return Self::Other,
+        };
+
+        match extension.as_str() {
+            "html" | "htm" => Self::Document,
+            "png" | "jpg" | "jpeg" | "gif" | "webp" | "avif" | "svg" | "ico" | "bmp" => Self::Image,
+            "js" | "mjs" => Self::Script,
+            "css" => Self::Stylesheet,
+            "woff" | "woff2" | "ttf" | "otf" | "eot" => Self::Font,
+            "mp3" | "mp4" | "webm" | "ogg" | "m4a" | "wav" | "m3u8" => Self::Media,
+            "json" => Self::Xhr,
+            _ => Self::Other,
+        }
+    }
+
+    /// Request type, as understood by the adblock engine.
+    pub(crate) fn as_adblock_request_type(&self) -> &'static str {
+        match self {
+            Self::Document => "document",
+            Self::Subdocument => "subdocument",
+            Self::Image => "image",
+            Self::Script => "script",
+            Self::Stylesheet => "stylesheet",
+            Self::Font => "font",
+            Self::Media => "media",
+            Self::Xhr => "xmlhttprequest",
+            Self::Other => "other",
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! User scripts deciding what happens to intercepted requests, for rules which filters
+//! can't express.
+use crate::configuration::{compile_script, ScriptingConfig, DECIDE_FUNCTION};
+use http::{HeaderMap, Method, Uri};
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// What a script decided for a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ScriptDecision {
+    /// Never blocked, as if temporarily allowed.
+    Allow,
+    /// Blocked, for the given reason if any.
+    Block(Option<String>),
+    /// Sent to another URL instead.
+    Rewrite(Uri),
+    /// Sent through the named egress upstream.
+    Upstream(String),
+}
+
+impl ScriptDecision {
+    fn from_dynamic(value: Dynamic) -> Result<Option<Self>, String> {
+        if value.is_unit() {
+            return Ok(None);
+        }
+        if value.is_string() {
+            let action = value.into_string().unwrap_or_default();
+
+            return match action.as_str() {
+                "allow" => Ok(Some(Self::Allow)),
+                "block" => Ok(Some(Self::Block(None))),
+                _ => Err(format!("Unknown action {}", action)),
+            };
+        }
+
+        let map = value
+            .try_cast::<Map>()
+            .ok_or_else(|| "Expected (), a string or a map".to_string())?;
+        let field = |name: &str| {
+            map.get(name)
+                .and_then(|value| value.clone().into_string().ok())
+        };
+
+        match field("action").as_deref() {
+            Some("allow") => Ok(Some(Self::Allow)),
+            Some("block") => Ok(Some(Self::Block(field("reason")))),
+            Some("rewrite") => {
+                let url = field("url").ok_or("A rewrite requires an url")?;
+                let uri = url
+                    .parse::<Uri>()
+                    .ok()
+                    .filter(|uri| {
+                        matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some()
+                    })
+                    .ok_or_else(|| format!("{} is not an absolute http(s) URL", url))?;
+
+                Ok(Some(Self::Rewrite(uri)))
+            }
+            Some("upstream") => Ok(Some(Self::Upstream(
+                field("upstream").ok_or("An upstream requires its name")?,
+            ))),
+            Some(action) => Err(format!("Unknown action {}", action)),
+            None => Err("Missing action".to_string()),
+        }
+    }
+}
+
+struct CompiledScript {
+    engine: Engine,
+    ast: AST,
+}
+
+/// The script of the configuration, loaded each time the proxy starts so that it follows
+/// configuration changes. Requests are left to filters when there is none.
+#[derive(Clone, Default)]
+pub(crate) struct RulesScript(Option<Arc<CompiledScript>>);
+
+impl fmt::Debug for RulesScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RulesScript")
+            .field(&self.0.is_some())
+            .finish()
+    }
+}
+
+impl RulesScript {
+    pub(crate) async fn load(configuration: &ScriptingConfig) -> Self {
+        let path = match &configuration.path {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+
+        let source = match tokio::fs::read_to_string(path).await {
+            Ok(source) => source,
+            Err(err) => {
+                log::error!("Unable to read script {:?}: {}", path, err);
+                return Self::default();
+            }
+        };
+
+        let engine = configuration.engine();
+        match compile_script(&engine, &source) {
+            Ok(ast) => {
+                log::info!("Loaded script: {:?}", path);
+                Self(Some(Arc::new(CompiledScript { engine, ast })))
+            }
+            Err(err) => {
+                log::error!("Unable to compile script {:?}: {}", path, err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Runs the script for a request. Failing scripts leave the request to filters.
+    pub(crate) fn decide(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+        client_ip_address: IpAddr,
+        client_username: Option<&str>,
+    ) -> Option<ScriptDecision> {
+        let script = self.0.as_ref()?;
+
+        let mut header_map = Map::new();
+        for (name, value) in headers {
+            if let Ok(value) = value.to_str() {
+                header_map.insert(name.as_str().into(), value.to_string().into());
+            }
+        }
+
+        let mut request = Map::new();
+        request.insert("method".into(), method.to_string().into());
+        request.insert("scheme".into(), uri.scheme_str().unwrap_or_default().into());
+        request.insert("host".into(), uri.host().unwrap_or_default().into());
+        request.insert(
+            "port".into(),
+            Dynamic::from_int(uri.port_u16().map_or_else(
+                || match uri.scheme_str() {
+                    Some("https") => 443,
+                    _ => 80,
+                },
+                i64::from,
+            )),
+        );
+        request.insert("path".into(), uri.path().into());
+        request.insert("query".into(), uri.query().unwrap_or_default().into());
+        request.insert("client".into(), client_ip_address.to_string().into());
+        request.insert(
+            "user".into(),
+            client_username.map_or(Dynamic::UNIT, |username| username.into()),
+        );
+        request.insert("headers".into(), header_map.into());
+
+        let result = script
+            .engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &script.ast, DECIDE_FUNCTION, (request,))
+            .map_err(|err| err.to_string())
+            .and_then(ScriptDecision::from_dynamic);
+
+        match result {
+            Ok(decision) => {
+                if let Some(decision) = &decision {
+                    log::debug!("Script decided {:?} for {}", decision, uri);
+                }
+
+                decision
+            }
+            Err(err) => {
+                log::warn!("Script failed for {}: {}", uri, err);
+                None
+            }
+        }
+    }
+}
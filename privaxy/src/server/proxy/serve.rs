@@ -1,32 +1,245 @@
+use super::cache::{CacheLookup, Coalescing, COALESCING_TIMEOUT};
+use super::client_certificates;
+use super::context::ProxyContext;
+use super::egress::EgressConnector;
+use super::encoding::{self, ContentEncoding};
+use super::exclusions::{TemporaryExclusionStore, TEMPORARY_EXCLUSION_PATH, TUNNEL_PATH};
+use super::geoip::GeoInfo;
 use super::html_rewriter::Rewriter;
-use crate::blocker::AdblockRequester;
-use crate::statistics::Statistics;
+use super::icap::{self, IcapOutcome, ScannedBody};
+use super::resource_type::ResourceType;
+use super::scripting::ScriptDecision;
+use super::templates::{Template, TemplateStore};
+use super::threats::Threat;
+use super::trailers;
+use super::upstream_errors;
+use crate::blocker::{BlockCategory, BlockSource};
+use crate::configuration::{ProxyConfig, RetriesConfig};
+use crate::statistics::{Statistics, TimeoutStage, UpstreamErrorClass};
 use crate::web_gui::events::Event;
 use adblock::blocker::BlockerResult;
+use base64::{engine::general_purpose, Engine};
+use futures_util::{Stream, StreamExt, TryStreamExt};
 use http::uri::{Authority, Scheme};
 use http::{StatusCode, Uri};
 use hyper::body::Bytes;
-use hyper::client::HttpConnector;
 use hyper::{http, Body, Request, Response};
 use hyper_rustls::HttpsConnector;
 use std::net::IpAddr;
-use tokio::sync::broadcast;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A transparent 1x1 GIF, served in place of blocked images.
+const TRANSPARENT_GIF: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// Target of access logs, so that they can be enabled independently.
+const ACCESS_LOG_TARGET: &str = "privaxy::access";
+/// Request bodies up to this size are buffered so that they can be sent more than once,
+/// larger and chunked ones are streamed upstream as they are received.
+const MAX_REPLAYABLE_BODY_SIZE: u64 = 64 * 1024;
+
+static REQUEST_ID: AtomicU64 = AtomicU64::new(0);
+
+/// What was done with a request, reported in access logs.
+#[derive(Debug, Clone, Copy)]
+enum RequestAction {
+    Proxied,
+    Blocked,
+    Cached,
+    Upgraded,
+    Failed,
+}
+
+impl RequestAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Proxied => "proxied",
+            Self::Blocked => "blocked",
+            Self::Cached => "cached",
+            Self::Upgraded => "upgraded",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// Time spent waiting for the headers of the upstream response, attached to responses of
+/// requests sent upstream.
+#[derive(Debug, Clone, Copy)]
+struct UpstreamTime(Duration);
+
+fn with_action(mut response: Response<Body>, action: RequestAction) -> Response<Body> {
+    response.extensions_mut().insert(action);
+    response
+}
 
-#[allow(clippy::too_many_arguments)]
 pub(crate) async fn serve(
-    adblock_requester: AdblockRequester,
+    context: Arc<ProxyContext>,
     request: Request<Body>,
-    hyper_client: hyper::Client<HttpsConnector<HttpConnector>>,
-    client: reqwest::Client,
     authority: Authority,
     scheme: Scheme,
-    broadcast_sender: broadcast::Sender<Event>,
-    statistics: Statistics,
     client_ip_address: IpAddr,
+    client_username: Option<String>,
 ) -> Result<Response<Body>, hyper::Error> {
+    let ProxyContext {
+        statistics,
+        proxy_config,
+        privacy_config,
+        bandwidth_limiter,
+        capture_store,
+        ..
+    } = &*context;
+    let request_id = REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+    let started_at = Instant::now();
+    let method = request.method().clone();
+    let host = authority.host().to_string();
+    let path = request.uri().path().to_string();
+    let user = client_username
+        .as_deref()
+        .and_then(|username| proxy_config.authentication.user(username))
+        .cloned();
+    let (request, captured_request) = capture_store.record_request(
+        request,
+        &scheme,
+        &authority,
+        client_ip_address,
+        client_username.as_deref(),
+    );
+
+    // Located by `serve_request`, once it knows the request isn't blocked otherwise.
+    let mut upstream = None;
+    let response = serve_request(
+        &context,
+        request,
+        authority,
+        scheme,
+        client_ip_address,
+        client_username.clone(),
+        &mut upstream,
+    )
+    .await?;
+
+    let action = response
+        .extensions()
+        .get::<RequestAction>()
+        .copied()
+        .unwrap_or(RequestAction::Proxied);
+    let client = privacy_config.client_address(client_ip_address).to_string();
+
+    if privacy_config.track_clients {
+        if let Some(client_username) = &client_username {
+            statistics
+                .increment_user_requests(client_username, matches!(action, RequestAction::Blocked));
+        }
+    }
+    if matches!(action, RequestAction::Blocked) {
+        if let Some(client_key) =
+            privacy_config.client_key(client_ip_address, client_username.as_deref())
+        {
+            statistics.increment_blocked_clients(client_key);
+        }
+    }
+
+    let upstream = upstream.unwrap_or_default();
+    let upstream_as_name = upstream.as_name();
+    statistics.increment_top_upstreams(upstream.country.clone(), upstream_as_name.clone());
+
+    // The duration covers the time to the response headers, bodies are streamed afterwards.
+    let duration = started_at.elapsed();
+    let upstream_time = response
+        .extensions()
+        .get::<UpstreamTime>()
+        .map(|upstream_time| upstream_time.0);
+    if let Some(upstream_time) = upstream_time {
+        statistics.record_added_latency(duration.saturating_sub(upstream_time));
+    }
+    statistics.record_history(
+        matches!(action, RequestAction::Blocked),
+        matches!(action, RequestAction::Proxied | RequestAction::Upgraded).then_some(duration),
+    );
+
+    log::info!(
+        target: ACCESS_LOG_TARGET,
+        request_id = request_id,
+        client = client.as_str(),
+        user = client_username.as_deref().unwrap_or(""),
+        host = host.as_str(),
+        action = action.as_str(),
+        country = upstream.country.as_deref().unwrap_or(""),
+        asn = upstream_as_name.as_deref().unwrap_or(""),
+        status = response.status().as_u16(),
+        duration_ms = duration.as_millis() as u64,
+        upstream_ms = upstream_time.map_or(0, |upstream_time| upstream_time.as_millis() as u64);
+        "{} {}{}", method, host, path
+    );
+
+    let response = match captured_request {
+        Some(captured_request) => captured_request.record_response(response),
+        None => response,
+    };
+
+    match user {
+        Some(user) if user.bandwidth_limit_kbps > 0 => {
+            let (parts, body) = response.into_parts();
+            let body =
+                bandwidth_limiter.throttle(&user.username, user.bandwidth_limit_kbps * 1024, body);
+
+            Ok(Response::from_parts(parts, body))
+        }
+        _ => Ok(response),
+    }
+}
+
+async fn serve_request(
+    context: &ProxyContext,
+    request: Request<Body>,
+    authority: Authority,
+    scheme: Scheme,
+    client_ip_address: IpAddr,
+    client_username: Option<String>,
+    upstream: &mut Option<GeoInfo>,
+) -> Result<Response<Body>, hyper::Error> {
+    let ProxyContext {
+        adblock_requester,
+        hyper_client,
+        trailers_client,
+        client,
+        upstream_clients,
+        broadcast_sender,
+        statistics,
+        proxy_config,
+        privacy_config,
+        https_upgrade_store,
+        circuit_breaker,
+        client_certificate_store,
+        template_store,
+        temporary_exclusion_store,
+        response_cache,
+        category_store,
+        threat_store,
+        cname_resolver,
+        geoip,
+        rules_script,
+        frame_context_store,
+        ..
+    } = context;
+    let adblock_requester = adblock_requester.for_profile(
+        proxy_config
+            .profiles
+            .profile_name_for(client_ip_address, client_username.as_deref()),
+    );
     let scheme_string = scheme.to_string();
+    // Only requests which change nothing upstream are upgraded, as they may be sent twice.
+    let upgrade_to_https = scheme == Scheme::HTTP
+        && proxy_config.https_upgrade
+        && matches!(*request.method(), http::Method::GET | http::Method::HEAD)
+        && !https_upgrade_store.is_broken(authority.host());
 
-    let uri = match http::uri::Builder::new()
+    let mut uri = match http::uri::Builder::new()
         .scheme(scheme)
         .authority(authority)
         .path_and_query(match request.uri().path_and_query() {
@@ -42,133 +255,1271 @@ pub(crate) async fn serve(
     };
 
     if request.headers().contains_key(http::header::UPGRADE) {
-        return Ok(perform_two_ends_upgrade(request, uri, hyper_client).await);
+        return Ok(with_action(
+            perform_two_ends_upgrade(
+                request,
+                uri,
+                hyper_client.clone(),
+                proxy_config.timeouts.response_header(),
+                statistics,
+            )
+            .await,
+            RequestAction::Upgraded,
+        ));
+    }
+
+    if uri.path() == TEMPORARY_EXCLUSION_PATH {
+        return Ok(redeem_temporary_exclusion(
+            &uri,
+            client_ip_address,
+            temporary_exclusion_store,
+            proxy_config,
+        ));
+    }
+
+    if uri.path() == TUNNEL_PATH {
+        return Ok(redeem_tunnel(
+            &uri,
+            client_ip_address,
+            temporary_exclusion_store,
+            proxy_config,
+        ));
+    }
+
+    let script_decision = rules_script.decide(
+        request.method(),
+        &uri,
+        request.headers(),
+        client_ip_address,
+        client_username.as_deref(),
+    );
+    if let Some(ScriptDecision::Rewrite(rewritten_uri)) = &script_decision {
+        log::debug!("Rewrote {} to {}", uri, rewritten_uri);
+        uri = rewritten_uri.clone();
     }
 
     let (mut parts, body) = request.into_parts();
     parts.uri = uri.clone();
 
-    let (sender, new_body) = Body::channel();
-
     let req = Request::from_parts(parts, body);
 
     log::debug!("{} {}", req.method(), req.uri());
 
-    statistics.increment_top_clients(client_ip_address);
+    if let Some(client_key) =
+        privacy_config.client_key(client_ip_address, client_username.as_deref())
+    {
+        statistics.increment_top_clients(client_key);
+    }
+
+    let resource_type = ResourceType::from_request(&req);
 
-    let (is_request_blocked, blocker_result) = adblock_requester
+    // Page at the top of the frame hierarchy, which filters consider as the first party.
+    let source_url = frame_context_store.source_url(&req, resource_type, client_ip_address);
+
+    // Scripts are blocked according to the site of the page loading them.
+    let page_host = source_url
+        .parse::<Uri>()
+        .ok()
+        .and_then(|source_url| source_url.host().map(str::to_string));
+    let is_script_blocked = page_host
+        .as_deref()
+        .is_some_and(|page_host| proxy_config.is_script_blocked(page_host));
+
+    let blocker_started_at = Instant::now();
+    let (mut is_request_blocked, mut blocker_result, mut block_source) = adblock_requester
         .is_network_url_blocked(
             uri.to_string(),
-            match req.headers().get(http::header::REFERER) {
-                Some(referer) => referer.to_str().unwrap().to_string(),
-                // When no referer, we default to `uri` as we otherwise may get many false
-                // positives due to the blocker thinking it's third party requests.
-                None => uri.to_string(),
-            },
+            source_url.clone(),
+            resource_type.as_adblock_request_type(),
         )
         .await;
+    statistics.record_blocker_decision(blocker_started_at.elapsed());
+
+    // Exceptions apply to the cloaked host as well.
+    if !is_request_blocked && blocker_result.exception.is_none() && proxy_config.cname_uncloaking {
+        if let Some(canonical_name) = cname_resolver.uncloak(uri.host().unwrap()).await {
+            if let Some(uncloaked_url) = uncloak_url(&uri, &canonical_name) {
+                let (is_uncloaked_blocked, uncloaked_blocker_result, uncloaked_block_source) =
+                    adblock_requester
+                        .is_network_url_blocked(
+                            uncloaked_url,
+                            source_url,
+                            resource_type.as_adblock_request_type(),
+                        )
+                        .await;
+
+                if is_uncloaked_blocked {
+                    log::debug!("Uncloaked {} to {}", uri.host().unwrap(), canonical_name);
+
+                    is_request_blocked = true;
+                    blocker_result = uncloaked_blocker_result;
+                    block_source = uncloaked_block_source;
+                    blocker_result.filter = Some(format!(
+                        "{} (CNAME {})",
+                        blocker_result.filter.as_deref().unwrap_or("No information"),
+                        canonical_name
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some(ScriptDecision::Block(reason)) = &script_decision {
+        is_request_blocked = true;
+        blocker_result.filter = Some(format!(
+            "Script: {}",
+            reason.as_deref().unwrap_or("No information")
+        ));
+        block_source = Some(BlockSource::new("Rules script", BlockCategory::Custom));
+    }
+
+    if !is_request_blocked && is_script_blocked && resource_type == ResourceType::Script {
+        is_request_blocked = true;
+        blocker_result.filter = Some(format!(
+            "No scripts on {}",
+            page_host.as_deref().unwrap_or_default()
+        ));
+        block_source = Some(BlockSource::new("Blocked scripts", BlockCategory::Custom));
+    }
+
+    let blocked_resources = proxy_config.resources.blocked_for(page_host.as_deref());
+    let is_resource_blocked = match resource_type {
+        ResourceType::Font => blocked_resources.fonts,
+        ResourceType::Media => blocked_resources.media,
+        _ => false,
+    };
+    if !is_request_blocked && is_resource_blocked {
+        is_request_blocked = true;
+        blocker_result.filter = Some(format!(
+            "{} blocked on {}",
+            resource_type.as_adblock_request_type(),
+            page_host.as_deref().unwrap_or_default()
+        ));
+        block_source = Some(BlockSource::new("Blocked resources", BlockCategory::Custom));
+    }
+
+    let blocked_category = if is_request_blocked {
+        None
+    } else {
+        category_store.find_blocked(
+            uri.host().unwrap(),
+            proxy_config
+                .categories
+                .blocked_for(client_ip_address, client_username.as_deref()),
+        )
+    };
+    if let Some(category) = blocked_category {
+        blocker_result.filter = Some(format!("Category: {}", category));
+        block_source = Some(BlockSource::new(
+            format!("Category: {}", category),
+            category.block_category(),
+        ));
+    }
+
+    let is_request_allowed = script_decision == Some(ScriptDecision::Allow)
+        || temporary_exclusion_store.contains(uri.host().unwrap(), client_ip_address);
+
+    let is_document = matches!(
+        resource_type,
+        ResourceType::Document | ResourceType::Subdocument
+    );
+    let threat = if is_request_allowed {
+        None
+    } else {
+        threat_store
+            .find(&uri, is_document, client, &proxy_config.threat_protection)
+            .await
+    };
+
+    let blocked_ip_address = if is_request_blocked
+        || is_request_allowed
+        || blocked_category.is_some()
+        || proxy_config.blocked_networks.is_empty()
+    {
+        None
+    } else {
+        let ip_addresses = cname_resolver.resolve(uri.host().unwrap()).await;
+        proxy_config.find_blocked_ip_address(&ip_addresses)
+    };
+    if let Some((ip_address, network)) = blocked_ip_address {
+        blocker_result.filter = Some(format!("IP address {} in {}", ip_address, network));
+        block_source = Some(BlockSource::new("Blocked networks", BlockCategory::Custom));
+    }
+
+    // Servers of requests blocked otherwise aren't located, which would resolve their hosts.
+    let is_blocked_otherwise = !is_request_allowed
+        && (is_request_blocked
+            || blocked_category.is_some()
+            || threat.is_some()
+            || blocked_ip_address.is_some());
+    let applies_country_rules = !is_request_allowed && proxy_config.geoip.has_country_rules();
+    if !is_blocked_otherwise && (applies_country_rules || proxy_config.geoip.statistics) {
+        *upstream = geoip.lookup_host(cname_resolver, uri.host().unwrap()).await;
+    }
+
+    let upstream_country = upstream
+        .as_ref()
+        .and_then(|upstream| upstream.country.clone());
+    let blocked_country = if is_blocked_otherwise || !applies_country_rules {
+        None
+    } else {
+        upstream_country
+            .as_deref()
+            .filter(|country| proxy_config.geoip.is_country_blocked(country))
+    };
+    if let Some(country) = blocked_country {
+        blocker_result.filter = Some(format!("Country: {}", country));
+        block_source = Some(BlockSource::new("Blocked countries", BlockCategory::Custom));
+    }
+
+    let is_request_blocked = (is_request_blocked
+        || blocked_category.is_some()
+        || threat.is_some()
+        || blocked_ip_address.is_some()
+        || blocked_country.is_some())
+        && !is_request_allowed;
+
+    // Threats take precedence, as they are what the client is told about.
+    let block_source = match &threat {
+        Some(threat) => Some(BlockSource::new(
+            threat.source.clone(),
+            BlockCategory::Malware,
+        )),
+        None => block_source,
+    }
+    .filter(|_| is_request_blocked);
 
     let _result = broadcast_sender.send(Event {
         now: chrono::Utc::now(),
         method: req.method().to_string(),
         url: req.uri().to_string(),
         is_request_blocked,
+        blocked_by: block_source.clone(),
+        is_tunneled: false,
+        country: upstream_country.clone(),
     });
 
     if is_request_blocked {
-        statistics.increment_blocked_requests();
+        statistics
+            .increment_blocked_requests(&block_source.unwrap_or_else(BlockSource::unknown_list));
         statistics.increment_top_blocked_paths(format!(
             "{}://{}{}",
             scheme_string,
             uri.host().unwrap(),
             uri.path()
         ));
+        statistics.increment_top_blocked_domains(uri.host().unwrap().to_string());
 
         log::debug!("Blocked request: {}", uri);
 
-        return Ok(get_blocked_by_privaxy_response(blocker_result));
+        if let Some(threat) = &threat {
+            statistics.increment_threats_blocked();
+            log::warn!(
+                "Blocked {} reported by {}: {}",
+                threat.kind,
+                threat.source,
+                uri
+            );
+
+            // Dangerous pages are always explained, even when blocking silently.
+            if is_document {
+                let response = get_threat_response(
+                    threat,
+                    template_store,
+                    temporary_exclusion_store,
+                    &uri,
+                    client_ip_address,
+                )
+                .await;
+
+                return Ok(with_action(response, RequestAction::Blocked));
+            }
+        }
+
+        if proxy_config.is_silently_blocked(uri.host().unwrap())
+            && blocker_result.redirect.is_none()
+        {
+            return Ok(with_action(
+                get_silently_blocked_response(),
+                RequestAction::Blocked,
+            ));
+        }
+
+        let response = get_blocked_by_privaxy_response(
+            blocker_result,
+            resource_type,
+            template_store,
+            temporary_exclusion_store,
+            &uri,
+            client_ip_address,
+            proxy_config.blocked_frame_allow_link,
+        )
+        .await;
+
+        return Ok(with_action(response, RequestAction::Blocked));
     }
 
-    let mut new_response = Response::new(new_body);
+    let client_accepts_gzip = ContentEncoding::Gzip.is_accepted_by(req.headers());
 
-    let mut request_headers = req.headers().clone();
+    // We own the request, its headers can be forwarded without being copied.
+    let (parts, mut body) = req.into_parts();
+    let method = parts.method;
+    let mut request_headers = parts.headers;
     request_headers.remove(http::header::CONNECTION);
     request_headers.remove(http::header::HOST);
-    let mut response = match client
-        .request(req.method().clone(), req.uri().to_string())
-        .headers(request_headers)
-        .body(req.into_body())
-        .send()
-        .await
-    {
-        Ok(response) => response,
+    // hyper sends `100 Continue` to the client the first time its body is read, which only
+    // happens once the request is allowed, so blocked requests are answered before any upload.
+    // reqwest doesn't wait for an interim response before sending the body, there is no point
+    // in asking the server for one.
+    request_headers.remove(http::header::EXPECT);
+    // Ranges are offsets in the representation the client already started receiving, asking
+    // for other encodings could get bytes of another one. Partial responses aren't rewritten.
+    if !request_headers.contains_key(http::header::RANGE) {
+        encoding::negotiate_accept_encoding(&mut request_headers, &proxy_config.upstream_encodings);
+    }
+
+    let icap_config = &proxy_config.icap;
+    if let Some(reqmod_url) = &icap_config.reqmod_url {
+        let content_length = get_content_length(&request_headers);
+        let has_body = content_length.map_or(
+            request_headers.contains_key(http::header::TRANSFER_ENCODING),
+            |content_length| content_length > 0,
+        );
+        let content_type = request_headers
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok());
+
+        // Requests without a body are always scanned, for their URL and headers.
+        let scanned_body = if !has_body {
+            body = Body::empty();
+            Some(None)
+        } else if icap_config.scans(content_type) {
+            match icap::read_body(icap_config, content_length, &mut body).await? {
+                ScannedBody::Complete(request_body) => Some(Some(request_body)),
+                ScannedBody::TooLarge(read, _) if icap_config.fail_open => {
+                    log::debug!("Request too large to be scanned, forwarding it: {}", uri);
+                    body = Body::wrap_stream(icap::rejoin(read, std::mem::take(&mut body)));
+                    None
+                }
+                ScannedBody::TooLarge(..) => {
+                    log::error!("Request too large to be scanned: {}", uri);
+                    let response = get_informative_error_response(
+                        "The request is too large to be scanned",
+                        UpstreamErrorClass::Other,
+                        template_store,
+                        temporary_exclusion_store,
+                        proxy_config,
+                        &uri,
+                        client_ip_address,
+                    )
+                    .await;
+
+                    return Ok(with_action(response, RequestAction::Failed));
+                }
+            }
+        } else {
+            None
+        };
+
+        if let Some(request_body) = scanned_body {
+            let outcome = icap::reqmod(
+                icap_config,
+                reqmod_url,
+                &method,
+                &uri,
+                &request_headers,
+                request_body.as_ref(),
+            )
+            .await;
+
+            let request_body = match outcome {
+                Ok(IcapOutcome::Unmodified) => request_body,
+                Ok(IcapOutcome::Request {
+                    headers,
+                    body: modified_body,
+                }) => {
+                    log::debug!("Request modified by the ICAP server: {}", uri);
+                    request_headers = headers;
+                    request_headers.remove(http::header::CONNECTION);
+                    request_headers.remove(http::header::HOST);
+                    request_headers.remove(http::header::TRANSFER_ENCODING);
+                    request_headers.insert(
+                        http::header::CONTENT_LENGTH,
+                        http::HeaderValue::from(modified_body.len()),
+                    );
+
+                    Some(modified_body)
+                }
+                Ok(IcapOutcome::Response {
+                    status,
+                    headers,
+                    body,
+                }) => {
+                    log::debug!("Request blocked by the ICAP server: {}", uri);
+                    // ICAP servers are mostly used to scan for malware.
+                    statistics.increment_blocked_requests(&BlockSource::new(
+                        "ICAP server",
+                        BlockCategory::Malware,
+                    ));
+
+                    let mut response = Response::new(Body::from(body));
+                    *response.status_mut() = status;
+                    *response.headers_mut() = headers;
+
+                    return Ok(with_action(response, RequestAction::Blocked));
+                }
+                Err(err) if icap_config.fail_open => {
+                    log::warn!("Unable to scan request {}, forwarding it: {}", uri, err);
+                    request_body
+                }
+                Err(err) => {
+                    log::error!("Unable to scan request {}: {}", uri, err);
+                    let response = get_informative_error_response(
+                        "The request could not be scanned",
+                        UpstreamErrorClass::Other,
+                        template_store,
+                        temporary_exclusion_store,
+                        proxy_config,
+                        &uri,
+                        client_ip_address,
+                    )
+                    .await;
+
+                    return Ok(with_action(response, RequestAction::Failed));
+                }
+            };
+
+            if let Some(request_body) = request_body {
+                body = Body::from(request_body);
+            }
+        }
+    }
+
+    let cache_enabled = proxy_config.cache.enabled;
+    // Request headers as sent by the client, `Vary` refers to them and they are handed over
+    // to the scanner of responses. Only copied when needed, only `GET` responses are cached.
+    let client_request_headers =
+        if (cache_enabled && method == http::Method::GET) || icap_config.respmod_url.is_some() {
+            request_headers.clone()
+        } else {
+            http::HeaderMap::new()
+        };
+
+    let mut cache_lookup = cache_enabled
+        .then(|| response_cache.lookup(&method, &uri, &request_headers))
+        .flatten();
+
+    // Fetch of the resource this request makes on behalf of concurrent ones.
+    let mut in_flight = None;
+    let mut is_coalesced = false;
+    if cache_lookup.is_none() && cache_enabled && proxy_config.cache.coalesce_requests {
+        match response_cache.coalesce(&method, &uri, &request_headers) {
+            Some(Coalescing::Leader(fetch)) => in_flight = Some(fetch),
+            Some(Coalescing::Follower(mut fetch)) => {
+                log::debug!("Waiting for the fetch of {} in progress", uri);
+                // Returns once the fetch is over, its response is cached if it could be.
+                let _ = tokio::time::timeout(COALESCING_TIMEOUT, fetch.changed()).await;
+
+                cache_lookup = response_cache.lookup(&method, &uri, &request_headers);
+                is_coalesced = true;
+            }
+            None => {}
+        }
+    }
+
+    let stale_response = match cache_lookup {
+        Some(CacheLookup::Fresh(cached_response)) => {
+            log::debug!("Serving {} from cache", uri);
+            statistics.increment_proxied_requests();
+            if is_coalesced {
+                statistics.increment_coalesced_requests();
+            }
+
+            return Ok(with_action(
+                cached_response.to_response(),
+                RequestAction::Cached,
+            ));
+        }
+        Some(CacheLookup::Stale(cached_response)) => {
+            cached_response.add_validators(&mut request_headers);
+
+            Some(cached_response)
+        }
+        None => None,
+    };
+
+    let client = match &script_decision {
+        Some(ScriptDecision::Upstream(upstream)) => match upstream_clients.get(upstream) {
+            Some(upstream_client) => upstream_client.clone(),
+            None => {
+                log::warn!("Script chose unknown upstream {} for {}", upstream, uri);
+                client.clone()
+            }
+        },
+        _ => client.clone(),
+    };
+
+    let retries = proxy_config.retries;
+    if let Some(open_for) = circuit_breaker.open_for(uri.host().unwrap(), &retries) {
+        log::debug!(
+            "{} is considered down, not sending request: {}",
+            uri.host().unwrap(),
+            uri
+        );
+        let response = get_informative_error_response(
+            &format!(
+                "The server failed repeatedly and is considered down, it will be tried again in {} seconds",
+                open_for.as_secs().max(1)
+            ),
+            UpstreamErrorClass::Other,
+            template_store,
+            temporary_exclusion_store,
+            proxy_config,
+            &uri,
+            client_ip_address,
+        )
+        .await;
+
+        return Ok(with_action(response, RequestAction::Failed));
+    }
+
+    if trailers::expects_trailers(&request_headers) {
+        // Forwarded as is: responses ending with trailers are neither rewritten nor cached,
+        // and failures get empty responses as gRPC clients can't make sense of error pages.
+        let mut upstream_request = Request::new(body);
+        *upstream_request.method_mut() = method;
+        *upstream_request.uri_mut() = uri.clone();
+        *upstream_request.headers_mut() = request_headers;
+
+        let sent_at = Instant::now();
+        let sending = trailers_client.request(upstream_request);
+        let response = match proxy_config.timeouts.response_header() {
+            Some(response_header_timeout) => {
+                match tokio::time::timeout(response_header_timeout, sending).await {
+                    Ok(response) => response,
+                    Err(_elapsed) => {
+                        log::warn!("Timed out waiting for the response of {}", uri);
+                        statistics.increment_timeouts(TimeoutStage::ResponseHeader);
+
+                        return Ok(with_action(
+                            get_empty_response(StatusCode::GATEWAY_TIMEOUT),
+                            RequestAction::Failed,
+                        ));
+                    }
+                }
+            }
+            None => sending.await,
+        };
+
+        return match response {
+            Ok(mut response) => {
+                let upstream_time = sent_at.elapsed();
+                circuit_breaker.record_success(uri.host().unwrap());
+                statistics.increment_proxied_requests();
+                statistics.record_time_to_first_byte(uri.host().unwrap(), upstream_time);
+                statistics.record_upstream_response(
+                    response.status(),
+                    uri.scheme_str(),
+                    response.version(),
+                );
+                response
+                    .extensions_mut()
+                    .insert(UpstreamTime(upstream_time));
+
+                Ok(response)
+            }
+            Err(err) => {
+                if err.is_connect() {
+                    circuit_breaker.record_failure(uri.host().unwrap(), &retries);
+                }
+                log::error!("Failed to send request: {}", err);
+
+                Ok(with_action(
+                    get_empty_response(StatusCode::BAD_GATEWAY),
+                    RequestAction::Failed,
+                ))
+            }
+        };
+    }
+
+    let has_body = get_content_length(&request_headers).map_or(
+        request_headers.contains_key(http::header::TRANSFER_ENCODING),
+        |content_length| content_length > 0,
+    );
+    let is_replayable = get_content_length(&request_headers).map_or(
+        !request_headers.contains_key(http::header::TRANSFER_ENCODING),
+        |content_length| content_length <= MAX_REPLAYABLE_BODY_SIZE,
+    );
+    if upgrade_to_https && !is_replayable {
+        log::debug!(
+            "Not upgrading {} to HTTPS, its body is too large to be sent twice",
+            uri
+        );
+    }
+
+    let sent_at = Instant::now();
+    // Resolves once the headers of the response are received.
+    let send = async {
+        let response = if upgrade_to_https && is_replayable {
+            // The body has to be buffered as it may be sent twice, should the upgrade fail.
+            let body = hyper::body::to_bytes(body).await?;
+            // Built once, the HTTPS attempt sends a copy of it.
+            let request = client
+                .request(method.clone(), uri.to_string())
+                .headers(request_headers)
+                .body(body)
+                .build();
+
+            match request {
+                Ok(request) => match send_upgraded_request(&client, &request, &uri).await {
+                    Some(response) => response,
+                    None => {
+                        log::debug!("HTTPS upgrade failed for {}, falling back to HTTP", uri);
+                        https_upgrade_store.mark_broken(uri.host().unwrap());
+
+                        client.execute(request).await
+                    }
+                },
+                Err(err) => Err(err),
+            }
+        } else if retries.max_retries > 0
+            && method.is_idempotent()
+            && (!has_body || retries.retry_request_bodies)
+            && is_replayable
+        {
+            // Buffered as it may be sent more than once.
+            let body = hyper::body::to_bytes(body).await?;
+            let request = client
+                .request(method.clone(), uri.to_string())
+                .headers(request_headers)
+                .body(body)
+                .build();
+
+            match request {
+                Ok(request) => send_with_retries(&client, request, &retries).await,
+                Err(err) => Err(err),
+            }
+        } else {
+            client
+                .request(method.clone(), uri.to_string())
+                .headers(request_headers)
+                .body(body)
+                .send()
+                .await
+        };
+
+        Ok::<_, hyper::Error>(response)
+    };
+
+    let response = match proxy_config.timeouts.response_header() {
+        Some(response_header_timeout) => {
+            match tokio::time::timeout(response_header_timeout, send).await {
+                Ok(response) => response?,
+                Err(_elapsed) => {
+                    log::warn!("Timed out waiting for the response of {}", uri);
+                    statistics.increment_timeouts(TimeoutStage::ResponseHeader);
+                    statistics.increment_upstream_errors(UpstreamErrorClass::Timeout);
+
+                    let response = get_informative_error_response(
+                        "The server took too long to respond",
+                        UpstreamErrorClass::Timeout,
+                        template_store,
+                        temporary_exclusion_store,
+                        proxy_config,
+                        &uri,
+                        client_ip_address,
+                    )
+                    .await;
+
+                    return Ok(with_action(response, RequestAction::Failed));
+                }
+            }
+        }
+        None => send.await?,
+    };
+
+    let mut response = match response {
+        Ok(response) => {
+            circuit_breaker.record_success(uri.host().unwrap());
+
+            response
+        }
         Err(err) => {
+            if err.is_connect() && err.is_timeout() {
+                statistics.increment_timeouts(TimeoutStage::Connect);
+            }
+            if err.is_connect() || is_transient(&err) {
+                circuit_breaker.record_failure(uri.host().unwrap(), &retries);
+            }
+
+            if err.is_connect()
+                && uri.scheme() == Some(&Scheme::HTTPS)
+                && cname_resolver
+                    .egress_router()
+                    .proxy_for(uri.host().unwrap())
+                    .is_none()
+                && client_certificates::requests_client_certificate(
+                    uri.host().unwrap(),
+                    uri.port_u16().unwrap_or(443),
+                )
+                .await
+            {
+                log::warn!(
+                    "{} requested a client certificate, tunneling it from now on",
+                    uri.host().unwrap()
+                );
+                client_certificate_store.mark(uri.host().unwrap());
+
+                return Ok(with_action(
+                    get_reconnect_response(&uri),
+                    RequestAction::Failed,
+                ));
+            }
+
             log::error!("Failed to send request: {}", err.to_string());
-            return Ok(get_informative_error_response(&err.to_string()));
+            let class = upstream_errors::classify(&err);
+            statistics.increment_upstream_errors(class);
+
+            let response = get_informative_error_response(
+                &err.to_string(),
+                class,
+                template_store,
+                temporary_exclusion_store,
+                proxy_config,
+                &uri,
+                client_ip_address,
+            )
+            .await;
+
+            return Ok(with_action(response, RequestAction::Failed));
         }
     };
 
+    let upstream_time = sent_at.elapsed();
     statistics.increment_proxied_requests();
+    statistics.record_time_to_first_byte(uri.host().unwrap(), upstream_time);
+    // The URL of the response tells whether the request was upgraded to HTTPS.
+    statistics.record_upstream_response(
+        response.status(),
+        Some(response.url().scheme()),
+        response.version(),
+    );
+
+    if let Some(stale_response) = stale_response {
+        if response.status() == StatusCode::NOT_MODIFIED {
+            log::debug!("Revalidated cached response for {}", uri);
+
+            let cached_response =
+                response_cache.refresh(&method, &uri, &stale_response, response.headers());
+
+            return Ok(with_action(
+                cached_response.to_response(),
+                RequestAction::Cached,
+            ));
+        }
+    }
 
-    *new_response.headers_mut() = response.headers().clone();
+    let mut status = response.status();
+    // Response headers are moved out of the upstream response rather than copied.
+    let mut headers = std::mem::take(response.headers_mut());
+    // Parts of a resource, typically seeks in media, are streamed as they are received.
+    let is_partial =
+        status == StatusCode::PARTIAL_CONTENT || headers.contains_key(http::header::CONTENT_RANGE);
 
-    let (mut parts, new_new_body) = new_response.into_parts();
-    parts.status = response.status();
+    // Resources which couldn't be told apart from the request.
+    let blocked_content_type = headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .filter(|content_type| {
+            !is_document
+                && !is_request_allowed
+                && blocked_resources.blocks_content_type(content_type)
+        });
+    if let Some(content_type) = blocked_content_type {
+        log::debug!("Blocked {} response: {}", content_type, uri);
+        statistics.increment_blocked_requests(&BlockSource::new(
+            "Blocked resources",
+            BlockCategory::Custom,
+        ));
+
+        return Ok(with_action(
+            get_silently_blocked_response(),
+            RequestAction::Blocked,
+        ));
+    }
 
-    let new_response = Response::from_parts(parts, new_new_body);
+    if let Some(respmod_url) = &icap_config.respmod_url {
+        let content_type = headers
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok());
 
-    if let Some(content_type) = response.headers().get(http::header::CONTENT_TYPE) {
-        if let Ok(value) = content_type.to_str() {
-            if value.contains("text/html") {
-                let (sender_rewriter, receiver_rewriter) = crossbeam_channel::unbounded::<Bytes>();
+        let scanned_body = if !is_partial && icap_config.scans(content_type) {
+            let content_length = get_content_length(&headers);
+            let body = Box::pin(response.bytes_stream());
 
-                let rewriter = Rewriter::new(
-                    uri.to_string(),
-                    adblock_requester,
-                    receiver_rewriter,
-                    sender,
-                    statistics,
+            match icap::read_body(icap_config, content_length, body).await {
+                Ok(ScannedBody::Complete(response_body)) => {
+                    // The rest of the processing reads the scanned body as it would the
+                    // upstream one.
+                    response = reqwest::Response::from(http::Response::new(response_body.clone()));
+                    Some(response_body)
+                }
+                Ok(ScannedBody::TooLarge(read, rest)) if icap_config.fail_open => {
+                    log::debug!("Response of {} too large to be scanned, forwarding it", uri);
+                    let body = reqwest::Body::wrap_stream(icap::rejoin(read, rest));
+                    response = reqwest::Response::from(http::Response::new(body));
+                    None
+                }
+                Ok(ScannedBody::TooLarge(..)) => {
+                    log::error!("Response of {} too large to be scanned", uri);
+                    let response = get_informative_error_response(
+                        "The response is too large to be scanned",
+                        UpstreamErrorClass::Other,
+                        template_store,
+                        temporary_exclusion_store,
+                        proxy_config,
+                        &uri,
+                        client_ip_address,
+                    )
+                    .await;
+
+                    return Ok(with_action(response, RequestAction::Failed));
+                }
+                Err(err) => {
+                    log::error!("Failed to read response: {}", err);
+                    let class = upstream_errors::classify(&err);
+                    statistics.increment_upstream_errors(class);
+
+                    let response = get_informative_error_response(
+                        &err.to_string(),
+                        class,
+                        template_store,
+                        temporary_exclusion_store,
+                        proxy_config,
+                        &uri,
+                        client_ip_address,
+                    )
+                    .await;
+
+                    return Ok(with_action(response, RequestAction::Failed));
+                }
+            }
+        } else {
+            None
+        };
+
+        if let Some(response_body) = scanned_body {
+            let outcome = icap::respmod(
+                icap_config,
+                respmod_url,
+                &method,
+                &uri,
+                &client_request_headers,
+                status,
+                &headers,
+                &response_body,
+            )
+            .await;
+
+            match outcome {
+                Ok(IcapOutcome::Unmodified | IcapOutcome::Request { .. }) => {}
+                Ok(IcapOutcome::Response {
+                    status: modified_status,
+                    headers: modified_headers,
+                    body: modified_body,
+                }) => {
+                    log::debug!("Response modified by the ICAP server: {}", uri);
+                    status = modified_status;
+                    headers = modified_headers;
+                    headers.remove(http::header::TRANSFER_ENCODING);
+                    headers.insert(
+                        http::header::CONTENT_LENGTH,
+                        http::HeaderValue::from(modified_body.len()),
+                    );
+                    response = reqwest::Response::from(http::Response::new(modified_body));
+                }
+                Err(err) if icap_config.fail_open => {
+                    log::warn!("Unable to scan response of {}, forwarding it: {}", uri, err);
+                }
+                Err(err) => {
+                    log::error!("Unable to scan response of {}: {}", uri, err);
+                    let response = get_informative_error_response(
+                        "The response could not be scanned",
+                        UpstreamErrorClass::Other,
+                        template_store,
+                        temporary_exclusion_store,
+                        proxy_config,
+                        &uri,
+                        client_ip_address,
+                    )
+                    .await;
+
+                    return Ok(with_action(response, RequestAction::Failed));
+                }
+            }
+        }
+    }
+
+    let is_html = headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map(|content_type| content_type.contains("text/html"))
+        .unwrap_or(false);
+    // Very large documents are passed through as is, rewriting them would hold
+    // them in memory for too long. So are those of sites which break when rewritten.
+    let is_rewritable = is_html
+        && !is_partial
+        && !proxy_config.is_rewrite_disabled(uri.host().unwrap())
+        && !proxy_config.exceeds_rewrite_size(get_content_length(&headers));
+    // Documents encoded in a way we can't decode, or didn't ask for, are passed through as is.
+    let content_encoding =
+        ContentEncoding::from_headers(&headers)
+            .ok()
+            .filter(|content_encoding| match content_encoding {
+                Some(content_encoding) => {
+                    proxy_config.upstream_encodings.contains(content_encoding)
+                }
+                None => true,
+            });
+
+    let body = match (is_rewritable, content_encoding) {
+        (true, Some(content_encoding)) => {
+            // The body is about to change, these no longer hold.
+            headers.remove(http::header::CONTENT_LENGTH);
+            headers.remove(http::header::CONTENT_ENCODING);
+
+            // The rewriter runs on a blocking thread, its output is sent through a channel.
+            let (sender, rewritten_body) = Body::channel();
+            // Followed by what is received once rewriting took too long.
+            let (mut passthrough_sender, passthrough_body) = Body::channel();
+            let body = Body::wrap_stream(rewritten_body.chain(passthrough_body));
+
+            let body = if proxy_config.compress_rewritten_html && client_accepts_gzip {
+                headers.insert(
+                    http::header::CONTENT_ENCODING,
+                    http::HeaderValue::from_static("gzip"),
                 );
 
-                tokio::task::spawn_blocking(|| rewriter.rewrite());
+                encoding::gzip_compressed_body(body)
+            } else {
+                body
+            };
+
+            let (sender_rewriter, receiver_rewriter) = crossbeam_channel::unbounded::<Bytes>();
+            let strip_scripts = is_script_blocked && resource_type == ResourceType::Document;
+            let rewrite_deadline = proxy_config
+                .max_rewrite_duration()
+                .filter(|_| !strip_scripts)
+                .map(|max_rewrite_duration| Instant::now() + max_rewrite_duration);
+
+            let rewriter = Rewriter::new(
+                uri.to_string(),
+                adblock_requester,
+                receiver_rewriter,
+                sender,
+                statistics.clone(),
+                strip_scripts,
+                proxy_config.webrtc.script_for(uri.host().unwrap()),
+            );
+
+            let is_rewrite_cut_short = rewriter.cut_short_flag();
+
+            tokio::task::spawn_blocking(|| rewriter.rewrite());
+
+            let upstream_body = measure_upstream_body(
+                response.bytes_stream(),
+                statistics.clone(),
+                uri.host().unwrap().to_string(),
+                sent_at,
+            )
+            .map_err(std::io::Error::other);
 
-                while let Ok(Some(chunk)) = response.chunk().await {
-                    if let Err(_err) = sender_rewriter.send(chunk) {
+            let mut upstream_body: encoding::BodyStream = match content_encoding {
+                Some(content_encoding) => encoding::decode(upstream_body, content_encoding),
+                None => Box::pin(upstream_body),
+            };
+
+            // The body is fed to the rewriter in the background so that the response
+            // can start being streamed to the client as soon as the first chunk is rewritten.
+            tokio::spawn(async move {
+                let mut sender_rewriter = Some(sender_rewriter);
+
+                while let Some(Ok(chunk)) = upstream_body.next().await {
+                    if sender_rewriter.is_some()
+                        && rewrite_deadline.is_some_and(|deadline| Instant::now() >= deadline)
+                    {
+                        is_rewrite_cut_short.store(true, Ordering::Release);
+                        // The rewriter ends the document received so far once this is dropped.
+                        sender_rewriter = None;
+                    }
+
+                    let is_sent = match &sender_rewriter {
+                        Some(sender_rewriter) => sender_rewriter.send(chunk).is_ok(),
+                        None => passthrough_sender.send_data(chunk).await.is_ok(),
+                    };
+                    if !is_sent {
                         break;
                     }
                 }
+            });
 
-                return Ok(new_response);
+            body
+        }
+        _ if cache_enabled => response_cache.store_when_complete(
+            &proxy_config.cache,
+            &method,
+            &uri,
+            &client_request_headers,
+            status,
+            &headers,
+            measure_upstream_body(
+                response.bytes_stream(),
+                statistics.clone(),
+                uri.host().unwrap().to_string(),
+                sent_at,
+            ),
+            in_flight,
+        ),
+        // Chunks are reference counted `Bytes`, they are handed over to hyper without being copied.
+        _ => Body::wrap_stream(measure_upstream_body(
+            response.bytes_stream(),
+            statistics.clone(),
+            uri.host().unwrap().to_string(),
+            sent_at,
+        )),
+    };
+
+    let mut new_response = Response::new(body);
+    *new_response.status_mut() = status;
+    *new_response.headers_mut() = headers;
+    new_response
+        .extensions_mut()
+        .insert(UpstreamTime(upstream_time));
+
+    Ok(new_response)
+}
+
+/// Counts the bytes of `body` and records the time from sending the request to `host` to
+/// receiving its end, once it was entirely received.
+fn measure_upstream_body<S>(
+    body: S,
+    statistics: Statistics,
+    host: String,
+    sent_at: Instant,
+) -> impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static
+where
+    S: Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+{
+    // Added once the body is over, rather than for every chunk.
+    let bytes = Arc::new(AtomicU64::new(0));
+    let body_bytes = bytes.clone();
+
+    body.inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            body_bytes.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        }
+    })
+    .chain(
+        futures_util::stream::once(async move {
+            statistics.add_proxied_bytes(bytes.load(Ordering::Relaxed));
+            statistics.record_total_duration(&host, sent_at.elapsed());
+        })
+        .filter_map(|()| futures_util::future::ready(None)),
+    )
+}
+
+/// Whether sending the request again may succeed: the connection was refused or reset, or
+/// the host couldn't be resolved.
+fn is_transient(err: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<std::io::Error>() {
+            if matches!(
+                err.kind(),
+                std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+            ) {
+                return true;
+            }
+            if err
+                .get_ref()
+                .is_some_and(|err| err.is::<hickory_resolver::error::ResolveError>())
+            {
+                return true;
             }
         }
+        if err.is::<hickory_resolver::error::ResolveError>() {
+            return true;
+        }
+        source = err.source();
+    }
+
+    false
+}
 
-        tokio::spawn(write_proxied_body(response, sender));
+/// Sends a request which can safely be repeated, retrying transient failures with an
+/// exponential backoff.
+async fn send_with_retries(
+    client: &reqwest::Client,
+    request: reqwest::Request,
+    retries: &RetriesConfig,
+) -> Result<reqwest::Response, reqwest::Error> {
+    let mut retry = 0;
 
-        return Ok(new_response);
+    loop {
+        // Its body is buffered, so the request can always be copied.
+        let result = client.execute(request.try_clone().unwrap()).await;
+
+        match result {
+            Err(err) if retry < retries.max_retries && is_transient(&err) => {
+                let backoff = retries.backoff(retry);
+                log::debug!("Retrying {} in {:?}: {}", request.url(), backoff, err);
+                tokio::time::sleep(backoff).await;
+                retry += 1;
+            }
+            result => return result,
+        }
     }
+}
 
-    tokio::spawn(write_proxied_body(response, sender));
+/// Attempts to perform a plain HTTP request over HTTPS instead.
+///
+/// Returns `None` when the host does not appear to properly support HTTPS, that is when
+/// no TLS connection could be established or when the host redirects back to plain HTTP.
+/// Errors happening once the request was sent are returned, it isn't sent again.
+async fn send_upgraded_request(
+    client: &reqwest::Client,
+    request: &reqwest::Request,
+    uri: &Uri,
+) -> Option<reqwest::Result<reqwest::Response>> {
+    // An explicit port is very unlikely to serve TLS.
+    if uri.port().is_some() {
+        return None;
+    }
 
-    Ok(new_response)
+    // Its body is buffered, so the request can be copied.
+    let mut https_request = request.try_clone()?;
+    https_request.url_mut().set_scheme("https").ok()?;
+
+    let response = match client.execute(https_request).await {
+        Ok(response) => response,
+        // TLS handshake failures are reported as connection errors too.
+        Err(err) if err.is_connect() => {
+            log::debug!("Unable to upgrade {} to HTTPS: {}", uri, err);
+            return None;
+        }
+        Err(err) => return Some(Err(err)),
+    };
+
+    // Some hosts redirect HTTPS requests back to HTTP, following them would result in a loop.
+    if response.status().is_redirection() {
+        if let Some(location) = response.headers().get(http::header::LOCATION) {
+            if let Ok(location) = location.to_str() {
+                if location.starts_with("http://") {
+                    return None;
+                }
+            }
+        }
+    }
+
+    Some(Ok(response))
 }
 
-fn get_informative_error_response(reason: &str) -> Response<Body> {
-    let mut response_body = String::from(include_str!("../../resources/head.html"));
-    response_body +=
-        &include_str!("../../resources/error.html").replace("#{request_error_reson}#", reason);
+/// Sends the client to the same location over a new connection, which the client opens with
+/// a new `CONNECT` request, letting it be tunneled.
+fn get_reconnect_response(uri: &Uri) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::TEMPORARY_REDIRECT)
+        .header(http::header::LOCATION, uri.to_string())
+        .header(http::header::CONNECTION, "close")
+        .body(Body::empty())
+        .unwrap()
+}
+
+async fn get_informative_error_response(
+    reason: &str,
+    class: UpstreamErrorClass,
+    template_store: &TemplateStore,
+    temporary_exclusion_store: &TemporaryExclusionStore,
+    proxy_config: &ProxyConfig,
+    uri: &Uri,
+    client_ip_address: IpAddr,
+) -> Response<Body> {
+    let url = uri.to_string();
+    let client = client_ip_address.to_string();
+    let (error_title, error_hint) = upstream_errors::describe(class);
+    let status = match class {
+        UpstreamErrorClass::Timeout => http::StatusCode::GATEWAY_TIMEOUT,
+        _ => http::StatusCode::BAD_GATEWAY,
+    };
+    let status_code = status.as_u16().to_string();
+
+    // Only hosts reached through `CONNECT` can be tunneled.
+    let (tunnel_url, tunnel_display) = if uri.scheme() == Some(&Scheme::HTTPS) {
+        let token = temporary_exclusion_store.create_tunnel_token(uri, client_ip_address);
+
+        (format!("{}?token={}", TUNNEL_PATH, token), "block")
+    } else {
+        (String::new(), "none")
+    };
+    let tunnel_minutes = (proxy_config.temporary_allow_duration().as_secs() / 60).to_string();
+
+    let response_body = template_store
+        .render(
+            Template::Error,
+            &[
+                ("status", status_code.as_str()),
+                ("error_title", error_title),
+                ("error_hint", error_hint),
+                ("request_error_reson", reason),
+                ("request_error_reason", reason),
+                ("url", url.as_str()),
+                ("client", client.as_str()),
+                ("tunnel_url", tunnel_url.as_str()),
+                ("tunnel_display", tunnel_display),
+                ("tunnel_minutes", tunnel_minutes.as_str()),
+            ],
+        )
+        .await;
 
     let mut response = Response::new(Body::from(response_body));
-    *response.status_mut() = http::StatusCode::BAD_GATEWAY;
+    *response.status_mut() = status;
 
     response
 }
 
-fn get_blocked_by_privaxy_response(blocker_result: BlockerResult) -> Response<Body> {
+async fn get_blocked_by_privaxy_response(
+    blocker_result: BlockerResult,
+    resource_type: ResourceType,
+    template_store: &TemplateStore,
+    temporary_exclusion_store: &TemporaryExclusionStore,
+    uri: &Uri,
+    client_ip_address: IpAddr,
+    frame_allow_link: bool,
+) -> Response<Body> {
     // We don't redirect to network urls due to security concerns.
     if let Some(resource) = blocker_result.redirect {
-        let response = Response::new(Body::from(resource));
+        return get_surrogate_response(&resource);
+    }
 
-        return response;
+    // Only documents get to see the block page. Frames get a small placeholder which fits
+    // in widgets, and subresources a neutral response of the expected type so pages don't
+    // break and consoles don't fill up with errors.
+    match resource_type {
+        ResourceType::Document | ResourceType::Subdocument => {}
+        ResourceType::Image => {
+            return get_neutral_response("image/gif", Body::from(TRANSPARENT_GIF));
+        }
+        ResourceType::Script => {
+            return get_neutral_response("application/javascript", Body::empty());
+        }
+        ResourceType::Stylesheet => {
+            return get_neutral_response("text/css", Body::empty());
+        }
+        ResourceType::Font | ResourceType::Media | ResourceType::Xhr | ResourceType::Other => {
+            return get_empty_response(http::StatusCode::NO_CONTENT);
+        }
     }
 
     let filter_information = match blocker_result.filter {
@@ -176,9 +1527,43 @@ fn get_blocked_by_privaxy_response(blocker_result: BlockerResult) -> Response<Bo
         None => "No information".to_string(),
     };
 
-    let mut response_body = String::from(include_str!("../../resources/head.html"));
-    response_body += &include_str!("../../resources/blocked_by_privaxy.html")
-        .replace("#{matching_filter}#", &filter_information);
+    let url = uri.to_string();
+    let client = client_ip_address.to_string();
+    let allow_url = format!(
+        "{}?token={}",
+        TEMPORARY_EXCLUSION_PATH,
+        temporary_exclusion_store.create_token(uri, client_ip_address)
+    );
+
+    let response_body = if resource_type == ResourceType::Subdocument {
+        template_store
+            .render(
+                Template::BlockedFrame,
+                &[
+                    ("matching_filter", filter_information.as_str()),
+                    ("url", url.as_str()),
+                    ("host", uri.host().unwrap_or_default()),
+                    ("allow_url", allow_url.as_str()),
+                    (
+                        "allow_link_display",
+                        if frame_allow_link { "inline" } else { "none" },
+                    ),
+                ],
+            )
+            .await
+    } else {
+        template_store
+            .render(
+                Template::Blocked,
+                &[
+                    ("matching_filter", filter_information.as_str()),
+                    ("url", url.as_str()),
+                    ("client", client.as_str()),
+                    ("allow_url", allow_url.as_str()),
+                ],
+            )
+            .await
+    };
 
     let mut response = Response::new(Body::from(response_body));
     *response.status_mut() = http::StatusCode::FORBIDDEN;
@@ -186,6 +1571,189 @@ fn get_blocked_by_privaxy_response(blocker_result: BlockerResult) -> Response<Bo
     response
 }
 
+/// `uri` with its host replaced by `canonical_name`.
+fn uncloak_url(uri: &Uri, canonical_name: &str) -> Option<String> {
+    let mut url = url::Url::parse(&uri.to_string()).ok()?;
+    url.set_host(Some(canonical_name)).ok()?;
+
+    Some(url.to_string())
+}
+
+async fn get_threat_response(
+    threat: &Threat,
+    template_store: &TemplateStore,
+    temporary_exclusion_store: &TemporaryExclusionStore,
+    uri: &Uri,
+    client_ip_address: IpAddr,
+) -> Response<Body> {
+    let threat_kind = threat.kind.to_lowercase();
+    let allow_url = format!(
+        "{}?token={}",
+        TEMPORARY_EXCLUSION_PATH,
+        temporary_exclusion_store.create_token(uri, client_ip_address)
+    );
+
+    let response_body = template_store
+        .render(
+            Template::Threat,
+            &[
+                ("threat", threat_kind.as_str()),
+                ("source", threat.source.as_str()),
+                ("allow_url", allow_url.as_str()),
+            ],
+        )
+        .await;
+
+    let mut response = Response::new(Body::from(response_body));
+    *response.status_mut() = http::StatusCode::FORBIDDEN;
+
+    response
+}
+
+fn redeem_temporary_exclusion(
+    uri: &Uri,
+    client_ip_address: IpAddr,
+    temporary_exclusion_store: &TemporaryExclusionStore,
+    proxy_config: &ProxyConfig,
+) -> Response<Body> {
+    let token = uri.query().and_then(|query| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _value)| key == "token")
+            .map(|(_key, value)| value.into_owned())
+    });
+
+    let redirect_to = token.and_then(|token| {
+        temporary_exclusion_store.redeem_token(
+            &token,
+            client_ip_address,
+            proxy_config.temporary_allow_duration(),
+        )
+    });
+
+    match redirect_to {
+        Some(redirect_to) => match http::HeaderValue::from_str(&redirect_to.to_string()) {
+            Ok(location) => {
+                let mut response = get_empty_response(http::StatusCode::FOUND);
+                response
+                    .headers_mut()
+                    .insert(http::header::LOCATION, location);
+
+                response
+            }
+            Err(err) => {
+                // The host is allowed nonetheless, reloading the page is enough.
+                log::warn!("Unable to redirect to {}: {}", redirect_to, err);
+
+                get_empty_response(http::StatusCode::BAD_REQUEST)
+            }
+        },
+        None => {
+            log::warn!(
+                "Invalid temporary exclusion token from {}",
+                client_ip_address
+            );
+
+            get_empty_response(http::StatusCode::FORBIDDEN)
+        }
+    }
+}
+
+/// Tunnels the host of the error page the token was issued for, and has the client reconnect
+/// so that its next `CONNECT` request isn't intercepted.
+fn redeem_tunnel(
+    uri: &Uri,
+    client_ip_address: IpAddr,
+    temporary_exclusion_store: &TemporaryExclusionStore,
+    proxy_config: &ProxyConfig,
+) -> Response<Body> {
+    let token = uri.query().and_then(|query| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _value)| key == "token")
+            .map(|(_key, value)| value.into_owned())
+    });
+
+    let tunneled = token.and_then(|token| {
+        temporary_exclusion_store.redeem_tunnel_token(
+            &token,
+            client_ip_address,
+            proxy_config.temporary_allow_duration(),
+        )
+    });
+
+    match tunneled {
+        Some(tunneled) => get_reconnect_response(&tunneled),
+        None => {
+            log::warn!("Invalid tunnel token from {}", client_ip_address);
+
+            get_empty_response(http::StatusCode::FORBIDDEN)
+        }
+    }
+}
+
+fn get_silently_blocked_response() -> Response<Body> {
+    let mut response = get_empty_response(http::StatusCode::FORBIDDEN);
+    response.headers_mut().insert(
+        http::header::CONNECTION,
+        http::HeaderValue::from_static("close"),
+    );
+
+    response
+}
+
+/// Serves the surrogate of a `$redirect` filter, which the engine gives as a `data:` URL, with
+/// the type of the resource so that media and scripts stubs load instead of erroring.
+fn get_surrogate_response(resource: &str) -> Response<Body> {
+    let (media_type, data) = match resource
+        .strip_prefix("data:")
+        .and_then(|resource| resource.split_once(','))
+    {
+        Some(resource) => resource,
+        None => {
+            log::warn!("Unexpected redirect resource: {}", resource);
+            return get_empty_response(http::StatusCode::NO_CONTENT);
+        }
+    };
+
+    let (content_type, body) = match media_type.strip_suffix(";base64") {
+        Some(content_type) => match general_purpose::STANDARD.decode(data) {
+            Ok(body) => (content_type, body),
+            Err(err) => {
+                log::warn!("Unable to decode redirect resource: {}", err);
+                return get_empty_response(http::StatusCode::NO_CONTENT);
+            }
+        },
+        None => (media_type, data.as_bytes().to_vec()),
+    };
+
+    let mut response = Response::new(Body::from(body));
+    let headers = response.headers_mut();
+    if let Ok(content_type) = http::HeaderValue::from_str(content_type) {
+        headers.insert(http::header::CONTENT_TYPE, content_type);
+    }
+    // Not kept by the client, so that the real resource loads once the site is allowed.
+    headers.insert(
+        http::header::CACHE_CONTROL,
+        http::HeaderValue::from_static("no-store"),
+    );
+    // Surrogates stand in for resources which may be fetched in CORS mode.
+    headers.insert(
+        http::header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        http::HeaderValue::from_static("*"),
+    );
+
+    response
+}
+
+fn get_neutral_response(content_type: &'static str, body: Body) -> Response<Body> {
+    let mut response = Response::new(body);
+    response.headers_mut().insert(
+        http::header::CONTENT_TYPE,
+        http::HeaderValue::from_static(content_type),
+    );
+
+    response
+}
+
 fn get_empty_response(status_code: http::StatusCode) -> Response<Body> {
     let mut response = Response::new(Body::empty());
     *response.status_mut() = status_code;
@@ -193,26 +1761,30 @@ fn get_empty_response(status_code: http::StatusCode) -> Response<Body> {
     response
 }
 
-async fn write_proxied_body(mut response: reqwest::Response, mut sender: hyper::body::Sender) {
-    while let Ok(Some(chunk)) = response.chunk().await {
-        // The other end is broken, let's abort immediately.
-        if let Err(_err) = sender.send_data(chunk).await {
-            break;
-        }
-    }
+fn get_content_length(headers: &http::HeaderMap) -> Option<u64> {
+    headers
+        .get(http::header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
 }
 
 /// When we receive a request to perform an upgrade, we need to initiate a bidirectional tunnel.
 /// We upgrade the request towards the target server, towards the proxy end and we connect both through a duplex stream.
 async fn perform_two_ends_upgrade(
-    request: Request<Body>,
+    mut request: Request<Body>,
     uri: Uri,
-    hyper_client: hyper::Client<HttpsConnector<HttpConnector>>,
+    hyper_client: hyper::Client<HttpsConnector<EgressConnector>>,
+    response_header_timeout: Option<Duration>,
+    statistics: &Statistics,
 ) -> Response<Body> {
     let (mut duplex_client, mut duplex_server) = tokio::io::duplex(32);
 
+    // Headers are moved rather than copied, only the extensions of the request are needed to
+    // upgrade it.
     let mut new_request = Request::new(Body::empty());
-    *new_request.headers_mut() = request.headers().clone();
+    *new_request.headers_mut() = std::mem::take(request.headers_mut());
     *new_request.uri_mut() = uri;
 
     tokio::spawn(async move {
@@ -227,13 +1799,26 @@ async fn perform_two_ends_upgrade(
         }
     });
 
-    let response = match hyper_client.request(new_request).await {
+    let sending = hyper_client.request(new_request);
+    let response = match response_header_timeout {
+        Some(response_header_timeout) => {
+            match tokio::time::timeout(response_header_timeout, sending).await {
+                Ok(response) => response,
+                Err(_elapsed) => {
+                    statistics.increment_timeouts(TimeoutStage::ResponseHeader);
+                    return get_empty_response(http::StatusCode::GATEWAY_TIMEOUT);
+                }
+            }
+        }
+        None => sending.await,
+    };
+    let mut response = match response {
         Ok(response) => response,
         Err(_err) => return get_empty_response(http::StatusCode::BAD_REQUEST),
     };
 
     let mut new_response = get_empty_response(StatusCode::SWITCHING_PROTOCOLS);
-    *new_response.headers_mut() = response.headers().clone();
+    *new_response.headers_mut() = std::mem::take(response.headers_mut());
 
     match hyper::upgrade::on(response).await {
         Ok(mut upgraded_server) => {
@@ -249,3 +1834,248 @@ async fn perform_two_ends_upgrade(
 
     new_response
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocker::{AdblockRequester, Blocker, BlockingDisabledStore};
+    use crate::configuration::{DnsConfig, EgressConfig, PrivacyConfig};
+    use crate::proxy::bandwidth::BandwidthLimiter;
+    use crate::proxy::cache::ResponseCache;
+    use crate::proxy::capture::CaptureStore;
+    use crate::proxy::categories::CategoryStore;
+    use crate::proxy::circuit_breaker::CircuitBreaker;
+    use crate::proxy::client_certificates::ClientCertificateStore;
+    use crate::proxy::cname::CnameResolver;
+    use crate::proxy::dns::DnsResolver;
+    use crate::proxy::egress::EgressRouter;
+    use crate::proxy::exclusions::TemporaryExclusionStore;
+    use crate::proxy::frame_context::FrameContextStore;
+    use crate::proxy::geoip::GeoIp;
+    use crate::proxy::https_upgrade::HttpsUpgradeStore;
+    use crate::proxy::pool::CountingResolver;
+    use crate::proxy::scripting::RulesScript;
+    use crate::proxy::templates::TemplateStore;
+    use crate::proxy::threats::ThreatStore;
+    use hyper::client::HttpConnector;
+    use hyper::service::{make_service_fn, service_fn};
+    use std::collections::HashMap;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::{Mutex, RwLock};
+    use tokio::sync::broadcast;
+
+    /// Headers of the requests received by an upstream server.
+    type ReceivedRequests = Arc<Mutex<Vec<http::HeaderMap>>>;
+
+    /// Answers every request on a local port with `respond`.
+    fn spawn_upstream(respond: fn() -> Response<Body>) -> (SocketAddr, ReceivedRequests) {
+        let received_requests = ReceivedRequests::default();
+        let received_by_server = received_requests.clone();
+
+        let make_service = make_service_fn(move |_connection| {
+            let received_requests = received_by_server.clone();
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    received_requests
+                        .lock()
+                        .unwrap()
+                        .push(request.headers().clone());
+
+                    async move { Ok::<_, Infallible>(respond()) }
+                }))
+            }
+        });
+
+        let server =
+            hyper::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let address = server.local_addr();
+        tokio::spawn(server);
+
+        (address, received_requests)
+    }
+
+    /// What `serve_request` shares between requests.
+    struct Proxy {
+        adblock_requester: AdblockRequester,
+        hyper_client: hyper::Client<HttpsConnector<EgressConnector>>,
+        client: reqwest::Client,
+        cname_resolver: CnameResolver,
+        statistics: Statistics,
+        https_upgrade_store: HttpsUpgradeStore,
+        response_cache: ResponseCache,
+        blocking_disabled_store: BlockingDisabledStore,
+    }
+
+    impl Proxy {
+        fn new() -> Self {
+            let statistics = Statistics::new();
+            let blocking_disabled_store = BlockingDisabledStore(Arc::new(RwLock::new(false)));
+
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            let blocker_sender = sender.clone();
+            let blocker_disabled_store = blocking_disabled_store.clone();
+            let blocker_statistics = statistics.clone();
+            // Engines aren't `Send`, the blocker is created on its own thread.
+            std::thread::spawn(move || {
+                Blocker::new(
+                    blocker_sender,
+                    receiver,
+                    blocker_disabled_store,
+                    blocker_statistics,
+                )
+                .handle_requests()
+            });
+
+            let dns_resolver = DnsResolver::new(&DnsConfig::default());
+            let egress_router = EgressRouter::new(&EgressConfig::default());
+            let mut http_connector = HttpConnector::new_with_resolver(CountingResolver::new(
+                dns_resolver.clone(),
+                statistics.clone(),
+            ));
+            http_connector.enforce_http(false);
+
+            let https_connector = hyper_rustls::HttpsConnectorBuilder::new()
+                .with_native_roots()
+                .https_or_http()
+                .enable_http1()
+                .wrap_connector(EgressConnector::new(
+                    http_connector,
+                    egress_router.clone(),
+                    None,
+                ));
+
+            Self {
+                adblock_requester: AdblockRequester::new(sender),
+                hyper_client: hyper::Client::builder().build(https_connector),
+                client: reqwest::Client::builder().no_proxy().build().unwrap(),
+                cname_resolver: CnameResolver::new(dns_resolver, egress_router),
+                statistics,
+                https_upgrade_store: HttpsUpgradeStore::new(),
+                response_cache: ResponseCache::new(),
+                blocking_disabled_store,
+            }
+        }
+
+        async fn send(&self, request: Request<Body>, proxy_config: ProxyConfig) -> Response<Body> {
+            let authority = request.uri().authority().unwrap().clone();
+            let (broadcast_sender, _broadcast_receiver) = broadcast::channel(16);
+
+            let context = ProxyContext {
+                adblock_requester: self.adblock_requester.clone(),
+                hyper_client: self.hyper_client.clone(),
+                trailers_client: self.hyper_client.clone(),
+                client: self.client.clone(),
+                upstream_clients: Arc::new(HashMap::new()),
+                broadcast_sender,
+                statistics: self.statistics.clone(),
+                proxy_config,
+                privacy_config: PrivacyConfig::default(),
+                https_upgrade_store: self.https_upgrade_store.clone(),
+                circuit_breaker: CircuitBreaker::new(),
+                client_certificate_store: ClientCertificateStore::new(),
+                template_store: TemplateStore::new(),
+                temporary_exclusion_store: TemporaryExclusionStore::new(),
+                response_cache: self.response_cache.clone(),
+                bandwidth_limiter: BandwidthLimiter::new(),
+                category_store: CategoryStore::new(self.blocking_disabled_store.clone()),
+                threat_store: ThreatStore::new(self.blocking_disabled_store.clone()),
+                cname_resolver: self.cname_resolver.clone(),
+                geoip: GeoIp::default(),
+                rules_script: RulesScript::default(),
+                capture_store: CaptureStore::new(),
+                frame_context_store: FrameContextStore::new(),
+            };
+
+            serve_request(
+                &context,
+                request,
+                authority,
+                Scheme::HTTP,
+                IpAddr::from([127, 0, 0, 1]),
+                None,
+                &mut None,
+            )
+            .await
+            .unwrap()
+        }
+    }
+
+    const PARTIAL_BODY: &[u8] = b"<htm";
+
+    fn partial_content() -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(http::header::CONTENT_TYPE, "text/html")
+            .header(http::header::CONTENT_RANGE, "bytes 0-3/10")
+            .header(http::header::CONTENT_LENGTH, PARTIAL_BODY.len())
+            .header(http::header::CACHE_CONTROL, "max-age=3600")
+            .body(Body::from(PARTIAL_BODY))
+            .unwrap()
+    }
+
+    fn range_request(address: SocketAddr) -> Request<Body> {
+        Request::get(format!("http://{}/page.html", address))
+            .header(http::header::RANGE, "bytes=0-3")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn range_requests_are_forwarded_upstream() {
+        let (address, received_requests) = spawn_upstream(partial_content);
+        let proxy = Proxy::new();
+
+        proxy
+            .send(range_request(address), ProxyConfig::default())
+            .await;
+
+        let received_requests = received_requests.lock().unwrap();
+        assert_eq!(received_requests.len(), 1);
+        assert_eq!(received_requests[0][http::header::RANGE], "bytes=0-3");
+    }
+
+    #[tokio::test]
+    async fn partial_responses_are_returned_untouched() {
+        let (address, _received_requests) = spawn_upstream(partial_content);
+        let proxy = Proxy::new();
+
+        let response = proxy
+            .send(range_request(address), ProxyConfig::default())
+            .await;
+
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers()[http::header::CONTENT_RANGE],
+            "bytes 0-3/10"
+        );
+        // Removed by the rewriter, which changes the length of the bodies it goes through.
+        assert_eq!(
+            response.headers()[http::header::CONTENT_LENGTH],
+            PARTIAL_BODY.len().to_string().as_str()
+        );
+        assert_eq!(
+            hyper::body::to_bytes(response.into_body()).await.unwrap(),
+            PARTIAL_BODY
+        );
+    }
+
+    #[tokio::test]
+    async fn partial_responses_are_not_cached() {
+        let (address, received_requests) = spawn_upstream(partial_content);
+        let proxy = Proxy::new();
+        let mut proxy_config = ProxyConfig::default();
+        proxy_config.cache.enabled = true;
+
+        for _ in 0..2 {
+            let response = proxy
+                .send(range_request(address), proxy_config.clone())
+                .await;
+            assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+            hyper::body::to_bytes(response.into_body()).await.unwrap();
+        }
+
+        assert_eq!(received_requests.lock().unwrap().len(), 2);
+    }
+}
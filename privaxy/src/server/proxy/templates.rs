@@ -0,0 +1,134 @@
+use crate::configuration::get_base_directory;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tokio::fs;
+
+/// Name of the directory, inside of the configuration directory, holding user provided templates.
+pub(crate) const TEMPLATES_DIRECTORY_NAME: &str = "templates";
+
+/// Pages served by the proxy itself.
+///
+/// Each of them can be overridden by placing a file with the same name in the templates directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Template {
+    Head,
+    Blocked,
+    /// Served in place of blocked frames, standalone so that it stays small.
+    BlockedFrame,
+    Threat,
+    Error,
+}
+
+impl Template {
+    fn file_name(&self) -> &'static str {
+        match self {
+            Self::Head => "head.html",
+            Self::Blocked => "blocked_by_privaxy.html",
+            Self::BlockedFrame => "blocked_frame.html",
+            Self::Threat => "blocked_threat.html",
+            Self::Error => "error.html",
+        }
+    }
+
+    fn builtin(&self) -> &'static str {
+        match self {
+            Self::Head => include_str!("../../resources/head.html"),
+            Self::Blocked => include_str!("../../resources/blocked_by_privaxy.html"),
+            Self::BlockedFrame => include_str!("../../resources/blocked_frame.html"),
+            Self::Threat => include_str!("../../resources/blocked_threat.html"),
+            Self::Error => include_str!("../../resources/error.html"),
+        }
+    }
+}
+
+/// Loads templates from the templates directory, falling back on the built-in ones.
+///
+/// Templates are reloaded whenever their modification time changes, so there's no need to restart
+/// or reload Privaxy after editing one.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TemplateStore(Arc<RwLock<HashMap<Template, (SystemTime, String)>>>);
+
+impl TemplateStore {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    async fn get(&self, template: Template) -> String {
+        let path = match get_templates_directory() {
+            Some(directory) => directory.join(template.file_name()),
+            None => return template.builtin().to_string(),
+        };
+
+        let modified = match fs::metadata(&path).await.and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            // No user provided template.
+            Err(_err) => return template.builtin().to_string(),
+        };
+
+        if let Some((cached_modified, contents)) = self.0.read().unwrap().get(&template) {
+            if *cached_modified == modified {
+                return contents.clone();
+            }
+        }
+
+        match fs::read_to_string(&path).await {
+            Ok(contents) => {
+                log::debug!("Loaded template: {:?}", path);
+
+                self.0
+                    .write()
+                    .unwrap()
+                    .insert(template, (modified, contents.clone()));
+
+                contents
+            }
+            Err(err) => {
+                log::warn!("Unable to read template {:?}: {}", path, err);
+
+                template.builtin().to_string()
+            }
+        }
+    }
+
+    /// Renders a full page, made of the head template followed by `template`, unless the
+    /// template is standalone.
+    ///
+    /// Variables are referenced as `#{name}#` in templates and are HTML escaped.
+    pub(crate) async fn render(&self, template: Template, variables: &[(&str, &str)]) -> String {
+        let mut page = self.get(template).await;
+
+        for (name, value) in variables {
+            page = page.replace(&format!("#{{{}}}#", name), &escape_html(value));
+        }
+
+        match template {
+            Template::BlockedFrame => page,
+            _ => self.get(Template::Head).await + page.as_str(),
+        }
+    }
+}
+
+fn get_templates_directory() -> Option<PathBuf> {
+    get_base_directory()
+        .ok()
+        .map(|directory| directory.join(TEMPLATES_DIRECTORY_NAME))
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
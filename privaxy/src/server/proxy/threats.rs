@@ -0,0 +1,313 @@
+use crate::blocker::BlockingDisabledStore;
+use crate::configuration::{resolve_optional_secret, ThreatProtectionConfig};
+use hyper::Uri;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use url::Url;
+
+const SAFE_BROWSING_ENDPOINT: &str = "https://safebrowsing.googleapis.com/v4/threatMatches:find";
+/// Lookups taking longer than this let the request through.
+const SAFE_BROWSING_TIMEOUT: Duration = Duration::from_secs(2);
+/// Verdicts kept at most, the cache is emptied once reached.
+const SAFE_BROWSING_CACHE_ENTRIES: usize = 10_000;
+
+/// Why a URL is considered dangerous.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Threat {
+    /// Feed or service which reported the URL.
+    pub(crate) source: String,
+    pub(crate) kind: &'static str,
+}
+
+/// Safe Browsing verdicts, along with when they expire.
+type Verdicts = HashMap<String, (Instant, Option<Threat>)>;
+
+#[derive(Debug, Default)]
+struct Feeds {
+    /// Normalized URLs, see `normalize`, along with the feed listing them.
+    urls: HashMap<String, String>,
+    /// Hosts listed without a path, every URL of which is dangerous.
+    hosts: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ThreatStore {
+    feeds: Arc<RwLock<Feeds>>,
+    /// Verdicts by host.
+    safe_browsing_cache: Arc<Mutex<Verdicts>>,
+    blocking_disabled: BlockingDisabledStore,
+}
+
+#[derive(Debug, Deserialize)]
+struct SafeBrowsingResponse {
+    #[serde(default)]
+    matches: Vec<SafeBrowsingMatch>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SafeBrowsingMatch {
+    threat_type: String,
+    cache_duration: Option<String>,
+}
+
+impl ThreatStore {
+    pub(crate) fn new(blocking_disabled: BlockingDisabledStore) -> Self {
+        Self {
+            feeds: Arc::new(RwLock::new(Feeds::default())),
+            safe_browsing_cache: Arc::new(Mutex::new(HashMap::new())),
+            blocking_disabled,
+        }
+    }
+
+    pub(crate) fn replace_feeds(&self, feeds: Vec<(String, String)>) {
+        let mut new_feeds = Feeds::default();
+
+        for (name, contents) in feeds {
+            for line in contents.lines() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                let url = match Url::parse(line) {
+                    Ok(url) => url,
+                    Err(_err) => continue,
+                };
+                let host = match url.host_str() {
+                    Some(host) => host.to_lowercase(),
+                    None => continue,
+                };
+
+                if url.path() == "/" && url.query().is_none() {
+                    new_feeds.hosts.insert(host, name.clone());
+                } else {
+                    new_feeds.urls.insert(normalize(&url), name.clone());
+                }
+            }
+        }
+
+        log::debug!(
+            "Loaded {} URLs and {} hosts from threat feeds",
+            new_feeds.urls.len(),
+            new_feeds.hosts.len()
+        );
+
+        *self.feeds.write().unwrap() = new_feeds;
+    }
+
+    /// Checks `uri` against the threat feeds then, for documents, against Safe Browsing.
+    pub(crate) async fn find(
+        &self,
+        uri: &Uri,
+        is_document: bool,
+        http_client: &reqwest::Client,
+        configuration: &ThreatProtectionConfig,
+    ) -> Option<Threat> {
+        if !configuration.enabled || !self.blocking_disabled.is_enabled() {
+            return None;
+        }
+
+        let url = Url::parse(&uri.to_string()).ok()?;
+
+        if let Some(threat) = self.find_in_feeds(&url) {
+            return Some(threat);
+        }
+
+        if !is_document {
+            return None;
+        }
+
+        let api_key =
+            resolve_optional_secret(configuration.safe_browsing_api_key.as_deref())?.into_owned();
+        self.find_in_safe_browsing(&url, &api_key, http_client, configuration)
+            .await
+    }
+
+    fn find_in_feeds(&self, url: &Url) -> Option<Threat> {
+        let feeds = self.feeds.read().unwrap();
+
+        let host = url.host_str()?.to_lowercase();
+        let source = feeds
+            .hosts
+            .get(&host)
+            .or_else(|| feeds.urls.get(&normalize(url)))?;
+
+        Some(Threat {
+            source: source.clone(),
+            kind: "Malware or phishing",
+        })
+    }
+
+    /// Only the host of `url` is sent to Google, never its path or query, which may carry
+    /// personal data. Threats listed for a path of a host that is otherwise safe, such as pages
+    /// of shared hosting services, are thus missed; feeds are matched against full URLs.
+    async fn find_in_safe_browsing(
+        &self,
+        url: &Url,
+        api_key: &str,
+        http_client: &reqwest::Client,
+        configuration: &ThreatProtectionConfig,
+    ) -> Option<Threat> {
+        let key = url.host_str()?.to_lowercase();
+
+        if let Some((expires_at, threat)) = self.safe_browsing_cache.lock().unwrap().get(&key) {
+            if *expires_at > Instant::now() {
+                return threat.clone();
+            }
+        }
+
+        let body = json!({
+            "client": {
+                "clientId": "privaxy",
+                "clientVersion": env!("CARGO_PKG_VERSION"),
+            },
+            "threatInfo": {
+                "threatTypes": [
+                    "MALWARE",
+                    "SOCIAL_ENGINEERING",
+                    "UNWANTED_SOFTWARE",
+                    "POTENTIALLY_HARMFUL_APPLICATION",
+                ],
+                "platformTypes": ["ANY_PLATFORM"],
+                "threatEntryTypes": ["URL"],
+                "threatEntries": [{ "url": format!("http://{}/", key) }],
+            },
+        });
+
+        let response = http_client
+            .post(SAFE_BROWSING_ENDPOINT)
+            .query(&[("key", api_key)])
+            .json(&body)
+            .timeout(SAFE_BROWSING_TIMEOUT)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        let response = match response {
+            Ok(response) => response.json::<SafeBrowsingResponse>().await,
+            Err(err) => Err(err),
+        };
+
+        let response = match response {
+            Ok(response) => response,
+            Err(err) => {
+                // Not cached, the lookup is retried by the next request.
+                log::warn!("Safe Browsing lookup failed: {}", err);
+                return None;
+            }
+        };
+
+        let threat_match = response.matches.into_iter().next();
+        let cache_duration = threat_match
+            .as_ref()
+            .and_then(|threat_match| threat_match.cache_duration.as_deref())
+            .and_then(parse_duration)
+            .unwrap_or_else(|| configuration.safe_browsing_cache_duration());
+        let threat = threat_match.map(|threat_match| Threat {
+            source: "Google Safe Browsing".to_string(),
+            kind: match threat_match.threat_type.as_str() {
+                "MALWARE" => "Malware",
+                "SOCIAL_ENGINEERING" => "Phishing",
+                "UNWANTED_SOFTWARE" => "Unwanted software",
+                "POTENTIALLY_HARMFUL_APPLICATION" => "Potentially harmful application",
+                _ => "Malware or phishing",
+            },
+        });
+
+        let mut cache = self.safe_browsing_cache.lock().unwrap();
+        if cache.len() >= SAFE_BROWSING_CACHE_ENTRIES {
+            cache.clear();
+        }
+        cache.insert(key, (Instant::now() + cache_duration, threat.clone()));
+
+        threat
+    }
+}
+
+/// Strips the scheme, fragment and trailing slash so that feed entries match regardless of them.
+fn normalize(url: &Url) -> String {
+    let host = url.host_str().unwrap_or_default().to_lowercase();
+    let port = url
+        .port()
+        .map(|port| format!(":{}", port))
+        .unwrap_or_default();
+    let query = url
+        .query()
+        .map(|query| format!("?{}", query))
+        .unwrap_or_default();
+
+    format!("{}{}{}{}", host, port, url.path(), query)
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Parses durations as sent by Google APIs, such as `300s` or `1.5s`.
+fn parse_duration(duration: &str) -> Option<Duration> {
+    duration
+        .strip_suffix('s')?
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn threat_store() -> (ThreatStore, BlockingDisabledStore) {
+        let blocking_disabled = BlockingDisabledStore(Arc::new(RwLock::new(false)));
+        let threat_store = ThreatStore::new(blocking_disabled.clone());
+        threat_store.replace_feeds(vec![(
+            "Feed".to_string(),
+            "https://malware.example/\nhttps://phishing.example/login\n".to_string(),
+        )]);
+
+        (threat_store, blocking_disabled)
+    }
+
+    async fn find(threat_store: &ThreatStore, uri: &str) -> Option<Threat> {
+        let configuration = ThreatProtectionConfig {
+            enabled: true,
+            ..ThreatProtectionConfig::default()
+        };
+
+        threat_store
+            .find(
+                &uri.parse().unwrap(),
+                true,
+                &reqwest::Client::new(),
+                &configuration,
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn matches_hosts_and_urls_of_feeds() {
+        let (threat_store, _blocking_disabled) = threat_store();
+
+        assert!(find(&threat_store, "http://malware.example/any/path")
+            .await
+            .is_some());
+        assert!(find(&threat_store, "https://phishing.example/login/")
+            .await
+            .is_some());
+        assert!(find(&threat_store, "https://phishing.example/")
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn nothing_is_blocked_while_blocking_is_disabled() {
+        let (threat_store, blocking_disabled) = threat_store();
+        blocking_disabled.set(false);
+
+        assert!(find(&threat_store, "http://malware.example/")
+            .await
+            .is_none());
+    }
+}
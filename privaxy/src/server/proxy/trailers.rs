@@ -0,0 +1,151 @@
+//! Trailers, sent after the body, carry the outcome of gRPC calls in `grpc-status`.
+//!
+//! reqwest drops them, so requests expecting them are sent with a hyper client instead. Bodies
+//! rebuilt with `Body::wrap_stream` lose them too, bodies transformed chunk by chunk go through
+//! `map_chunks` which forwards them once the data is over.
+use futures_util::Future;
+use hyper::body::{Bytes, HttpBody};
+use hyper::{http, Body};
+
+/// Whether the response to a request is expected to end with trailers: the client said it
+/// accepts them, as gRPC clients do, or the request is a gRPC call. gRPC-web encodes trailers
+/// in the body and doesn't need them.
+pub(crate) fn expects_trailers(request_headers: &http::HeaderMap) -> bool {
+    let accepts_trailers = request_headers
+        .get_all(http::header::TE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("trailers"));
+
+    let is_grpc = request_headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| {
+            content_type.starts_with("application/grpc")
+                && !content_type.starts_with("application/grpc-web")
+        });
+
+    accepts_trailers || is_grpc
+}
+
+/// Forwards the chunks of `body` as `map` resolves them, followed by its trailers.
+pub(crate) fn map_chunks<F, Fut>(mut body: Body, mut map: F) -> Body
+where
+    F: FnMut(Bytes) -> Fut + Send + 'static,
+    Fut: Future<Output = Bytes> + Send,
+{
+    let (mut sender, mapped) = Body::channel();
+
+    tokio::spawn(async move {
+        while let Some(chunk) = body.data().await {
+            let chunk = match chunk {
+                Ok(chunk) => map(chunk).await,
+                Err(_err) => {
+                    sender.abort();
+                    return;
+                }
+            };
+
+            // The receiving end went away.
+            if sender.send_data(chunk).await.is_err() {
+                return;
+            }
+        }
+
+        match body.trailers().await {
+            Ok(Some(trailers)) => {
+                let _result = sender.send_trailers(trailers).await;
+            }
+            Ok(None) => {}
+            Err(_err) => sender.abort(),
+        }
+    });
+
+    mapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(headers: &[(http::HeaderName, &'static str)]) -> http::HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| (name.clone(), http::HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn requests_accepting_trailers_expect_them() {
+        assert!(expects_trailers(&headers(&[(
+            http::header::TE,
+            "trailers"
+        )])));
+        assert!(expects_trailers(&headers(&[(
+            http::header::TE,
+            "gzip, Trailers"
+        )])));
+        assert!(!expects_trailers(&headers(&[(http::header::TE, "gzip")])));
+        assert!(!expects_trailers(&http::HeaderMap::new()));
+    }
+
+    #[test]
+    fn grpc_calls_expect_trailers() {
+        assert!(expects_trailers(&headers(&[(
+            http::header::CONTENT_TYPE,
+            "application/grpc"
+        )])));
+        assert!(expects_trailers(&headers(&[(
+            http::header::CONTENT_TYPE,
+            "application/grpc+proto"
+        )])));
+    }
+
+    #[test]
+    fn grpc_web_calls_dont_expect_trailers() {
+        assert!(!expects_trailers(&headers(&[(
+            http::header::CONTENT_TYPE,
+            "application/grpc-web"
+        )])));
+        assert!(!expects_trailers(&headers(&[(
+            http::header::CONTENT_TYPE,
+            "application/grpc-web-text+proto"
+        )])));
+    }
+
+    #[tokio::test]
+    async fn mapped_chunks_are_followed_by_trailers() {
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            sender
+                .send_data(Bytes::from_static(b"hello "))
+                .await
+                .unwrap();
+            sender
+                .send_data(Bytes::from_static(b"world"))
+                .await
+                .unwrap();
+            sender
+                .send_trailers(headers(&[(
+                    http::HeaderName::from_static("grpc-status"),
+                    "0",
+                )]))
+                .await
+                .unwrap();
+        });
+
+        let mut mapped = map_chunks(body, |chunk| async move {
+            Bytes::from(chunk.to_ascii_uppercase())
+        });
+
+        let mut data = Vec::new();
+        while let Some(chunk) = mapped.data().await {
+            data.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(data, b"HELLO WORLD");
+
+        let trailers = mapped.trailers().await.unwrap().unwrap();
+        assert_eq!(trailers["grpc-status"], "0");
+    }
+}
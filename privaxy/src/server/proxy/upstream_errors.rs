@@ -0,0 +1,57 @@
+use crate::statistics::UpstreamErrorClass;
+use std::error::Error;
+use std::io;
+
+/// Tells why a request couldn't be sent upstream, from the errors that caused `err`.
+pub(crate) fn classify(err: &reqwest::Error) -> UpstreamErrorClass {
+    if err.is_timeout() {
+        return UpstreamErrorClass::Timeout;
+    }
+
+    let mut source = err.source();
+
+    while let Some(error) = source {
+        if let Some(io_error) = error.downcast_ref::<io::Error>() {
+            match io_error.kind() {
+                io::ErrorKind::ConnectionRefused => return UpstreamErrorClass::ConnectionRefused,
+                io::ErrorKind::TimedOut => return UpstreamErrorClass::Timeout,
+                // Failed handshakes and invalid certificates are reported as invalid data by
+                // the TLS streams.
+                io::ErrorKind::InvalidData if err.is_connect() => return UpstreamErrorClass::Tls,
+                _ => {}
+            }
+        }
+
+        // Resolution failures are reported by hyper's connector as such.
+        if error.to_string().starts_with("dns error") {
+            return UpstreamErrorClass::Dns;
+        }
+
+        source = error.source();
+    }
+
+    UpstreamErrorClass::Other
+}
+
+/// Title and explanation of the error page.
+pub(crate) fn describe(class: UpstreamErrorClass) -> (&'static str, &'static str) {
+    match class {
+        UpstreamErrorClass::Dns => (
+            "DNS failure",
+            "The name of the server could not be resolved. Check the address, or the DNS settings of Privaxy.",
+        ),
+        UpstreamErrorClass::Tls => (
+            "TLS error",
+            "A secure connection to the server could not be established. Its certificate may be invalid, or it may not support what Privaxy offers.",
+        ),
+        UpstreamErrorClass::Timeout => (
+            "Timed out",
+            "The server took too long to respond. It may be overloaded or unreachable.",
+        ),
+        UpstreamErrorClass::ConnectionRefused => (
+            "Connection refused",
+            "The server refused the connection. It may be down, or not accept connections on this port.",
+        ),
+        UpstreamErrorClass::Other => ("Bad Gateway", "Unable to process your request."),
+    }
+}
@@ -0,0 +1,228 @@
+//! Daily or weekly summaries of the statistics, sent by email or to webhooks for those who
+//! don't watch the dashboard.
+use crate::alerts::send_webhook;
+use crate::configuration::{
+    resolve_optional_secret, ReportFrequency, ReportsConfig, SmtpConfig, SmtpSecurity,
+};
+use crate::statistics::{Statistics, Window};
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, Local, NaiveTime, TimeZone};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::Notify;
+
+const REPORT_KIND: &str = "report";
+/// Entries of the tables of a report.
+const REPORT_TABLE_ENTRIES: usize = 10;
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+type SmtpTransport = AsyncSmtpTransport<Tokio1Executor>;
+
+#[derive(Debug, Error)]
+enum ReportError {
+    #[error("invalid address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("unable to build message: {0}")]
+    Message(#[from] lettre::error::Error),
+    #[error("mail server error: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+}
+
+/// Next time a report is due after `now`.
+fn next_run(configuration: &ReportsConfig, now: DateTime<Local>) -> DateTime<Local> {
+    let time = NaiveTime::from_hms_opt(configuration.hour.min(23), 0, 0).unwrap();
+    let mut date = now.date_naive();
+
+    loop {
+        let is_due_day = configuration.frequency != ReportFrequency::Weekly
+            || date.weekday() == configuration.weekday;
+        let naive = date.and_time(time);
+        // Hours skipped by daylight saving time changes are sent an hour later.
+        let run = Local.from_local_datetime(&naive).earliest().or_else(|| {
+            Local
+                .from_local_datetime(&(naive + ChronoDuration::hours(1)))
+                .earliest()
+        });
+
+        match run {
+            Some(run) if is_due_day && run > now => return run,
+            _ => date = date.succ_opt().unwrap(),
+        }
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Title and plain text body of the report of the last `window`.
+fn render(statistics: &Statistics, window: Window) -> (String, String) {
+    let title = match window {
+        Window::LastWeek => "Weekly report",
+        _ => "Daily report",
+    }
+    .to_string();
+
+    let window_statistics = statistics.get_window(window);
+    let counters = window_statistics.counters;
+    let serialized = statistics.get_serialized();
+
+    let mut body = String::new();
+    let covered_hours = window_statistics.covered_seconds / 3600;
+    if window_statistics.covered_seconds < window.duration().as_secs() {
+        let _ = writeln!(
+            body,
+            "Statistics only go back {} hours, since Privaxy started or they were reset.\n",
+            covered_hours
+        );
+    }
+
+    let total = counters.proxied_requests + counters.blocked_requests;
+    let blocked_share = if total == 0 {
+        0.0
+    } else {
+        counters.blocked_requests as f64 * 100.0 / total as f64
+    };
+    let _ = writeln!(body, "Proxied requests: {}", counters.proxied_requests);
+    let _ = writeln!(
+        body,
+        "Blocked requests: {} ({:.1}%)",
+        counters.blocked_requests, blocked_share
+    );
+    let _ = writeln!(body, "Threats blocked: {}", counters.threats_blocked);
+    let _ = writeln!(body, "Modified responses: {}", counters.modified_responses);
+    let _ = writeln!(
+        body,
+        "Downloaded: {}, tunneled without filtering: {}",
+        format_bytes(counters.proxied_bytes),
+        format_bytes(counters.tunneled_bytes)
+    );
+
+    // Tables aren't kept per window.
+    let tables = [
+        ("Top blocked domains", &serialized.top_blocked_domains),
+        ("Top clients", &serialized.top_clients),
+    ];
+    for (table_title, entries) in tables {
+        if entries.is_empty() {
+            continue;
+        }
+
+        let _ = writeln!(body, "\n{} since the statistics started:", table_title);
+        for (entry, count) in entries.iter().take(REPORT_TABLE_ENTRIES) {
+            let _ = writeln!(body, "  {:>8}  {}", count, entry);
+        }
+    }
+
+    (title, body)
+}
+
+fn smtp_transport(smtp: &SmtpConfig) -> Result<SmtpTransport, ReportError> {
+    let builder = match smtp.security {
+        SmtpSecurity::StartTls => SmtpTransport::starttls_relay(&smtp.host)?,
+        SmtpSecurity::Tls => SmtpTransport::relay(&smtp.host)?,
+        SmtpSecurity::None => SmtpTransport::builder_dangerous(&smtp.host),
+    }
+    .port(smtp.port)
+    .timeout(Some(SMTP_TIMEOUT));
+
+    let builder = match &smtp.username {
+        Some(username) => builder.credentials(Credentials::new(
+            username.clone(),
+            resolve_optional_secret(smtp.password.as_deref())
+                .unwrap_or_default()
+                .into_owned(),
+        )),
+        None => builder,
+    };
+
+    Ok(builder.build())
+}
+
+async fn send_email(smtp: &SmtpConfig, title: &str, body: &str) -> Result<(), ReportError> {
+    let mut message = Message::builder()
+        .from(smtp.from.parse::<Mailbox>()?)
+        .subject(format!("Privaxy: {}", title));
+    for to in &smtp.to {
+        message = message.to(to.parse::<Mailbox>()?);
+    }
+    let message = message.body(body.to_string())?;
+
+    smtp_transport(smtp)?.send(message).await?;
+
+    Ok(())
+}
+
+async fn send_report(
+    configuration: &ReportsConfig,
+    http_client: &reqwest::Client,
+    statistics: &Statistics,
+) {
+    let window = match configuration.frequency {
+        ReportFrequency::Weekly => Window::LastWeek,
+        _ => Window::LastDay,
+    };
+    let (title, body) = render(statistics, window);
+
+    log::info!("Sending {}", title.to_lowercase());
+
+    if let Some(smtp) = &configuration.smtp {
+        if let Err(err) = send_email(smtp, &title, &body).await {
+            log::warn!("Unable to email report: {}", err);
+        }
+    }
+    for webhook in &configuration.webhooks {
+        if let Err(err) = send_webhook(http_client, webhook, REPORT_KIND, &title, &body).await {
+            log::warn!("Unable to send report to {}: {}", webhook.url, err);
+        }
+    }
+}
+
+/// Sends reports on schedule until a configuration reload is notified.
+pub(crate) async fn run(
+    configuration: ReportsConfig,
+    http_client: reqwest::Client,
+    statistics: Statistics,
+    notify_reload: Arc<Notify>,
+) {
+    if configuration.frequency == ReportFrequency::Never
+        || (configuration.smtp.is_none() && configuration.webhooks.is_empty())
+    {
+        notify_reload.notified().await;
+        return;
+    }
+
+    let schedule = async {
+        loop {
+            let now = Local::now();
+            let next_run = next_run(&configuration, now);
+            log::debug!("Next report at {}", next_run);
+
+            let wait = (next_run - now).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+
+            send_report(&configuration, &http_client, &statistics).await;
+        }
+    };
+
+    tokio::select! {
+        _ = schedule => {},
+        _ = notify_reload.notified() => {},
+    }
+}
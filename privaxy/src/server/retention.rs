@@ -0,0 +1,50 @@
+//! Enforces the privacy settings on data collected before they applied: logs and statistics
+//! older than their retention are pruned, and client tables are emptied once clients are no
+//! longer tracked. The event log prunes itself, see `event_log`.
+use crate::configuration::PrivacyConfig;
+use crate::logging;
+use crate::statistics::Statistics;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+pub(crate) const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Prunes expired data every `PRUNE_INTERVAL` until a configuration reload is notified.
+pub(crate) async fn run(
+    configuration: PrivacyConfig,
+    statistics: Statistics,
+    notify_reload: Arc<Notify>,
+) {
+    if !configuration.track_clients {
+        statistics.clear_clients();
+    }
+
+    let log_retention = configuration.log_retention();
+    let statistics_retention = configuration.statistics_retention();
+
+    if log_retention.is_none() && statistics_retention.is_none() {
+        notify_reload.notified().await;
+        return;
+    }
+
+    let prune = async {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if let Some(log_retention) = log_retention {
+                logging::prune(log_retention);
+            }
+            if let Some(statistics_retention) = statistics_retention {
+                statistics.prune(statistics_retention);
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = prune => {},
+        _ = notify_reload.notified() => {},
+    }
+}
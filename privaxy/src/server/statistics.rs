@@ -1,31 +1,497 @@
-use serde::Serialize;
+use crate::blocker::{AdblockRequester, BlockCategory, BlockSource, CosmeticExceptions};
+use crate::connection_limits::Listener;
+use hyper::http::{StatusCode, Version};
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    net::IpAddr,
+    collections::{HashMap, VecDeque},
     sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use uluru::LRUCache;
+use utoipa::ToSchema;
 
 const ENTRIES_PER_STATISTICS_TABLE: u8 = 50;
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(60 * 5);
+/// Snapshots are kept for the longest window.
+const SNAPSHOT_RETENTION: Duration = Duration::from_secs(60 * 60 * 24 * 7);
+const HISTORY_RESOLUTION_SECONDS: u64 = 60;
+/// 24 hours of history at `HISTORY_RESOLUTION_SECONDS`.
+const HISTORY_POINTS: usize = 60 * 24;
+const RUNTIME_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Latest requests of an origin its percentiles are computed over.
+const LATENCY_SAMPLES: usize = 100;
+/// Anomalies kept to be shown on the dashboard.
+const ANOMALIES_KEPT: usize = 20;
+/// Anomalies older than this are no longer shown.
+const ANOMALY_DISPLAY_SECONDS: u64 = 60 * 60;
+/// Latest requests the percentiles of the added latency are computed over.
+const OVERHEAD_SAMPLES: usize = 1_000;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, ToSchema)]
+/// Period over which counters are queried
+pub enum Window {
+    #[serde(rename = "1h")]
+    LastHour,
+    #[serde(rename = "24h")]
+    LastDay,
+    #[serde(rename = "7d")]
+    LastWeek,
+}
+
+impl Window {
+    pub fn duration(&self) -> Duration {
+        match self {
+            Window::LastHour => Duration::from_secs(60 * 60),
+            Window::LastDay => Duration::from_secs(60 * 60 * 24),
+            Window::LastWeek => SNAPSHOT_RETENTION,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+pub struct Counters {
+    pub proxied_requests: u64,
+    pub blocked_requests: u64,
+    pub modified_responses: u64,
+    pub threats_blocked: u64,
+    /// Connections tunneled without being filtered.
+    pub tunneled_connections: u64,
+    /// Bodies of the responses received from upstream servers, as encoded by them.
+    pub proxied_bytes: u64,
+    pub tunneled_bytes: u64,
+}
+
+impl Counters {
+    fn since(&self, baseline: &Counters) -> Counters {
+        Counters {
+            proxied_requests: self
+                .proxied_requests
+                .saturating_sub(baseline.proxied_requests),
+            blocked_requests: self
+                .blocked_requests
+                .saturating_sub(baseline.blocked_requests),
+            modified_responses: self
+                .modified_responses
+                .saturating_sub(baseline.modified_responses),
+            threats_blocked: self
+                .threats_blocked
+                .saturating_sub(baseline.threats_blocked),
+            tunneled_connections: self
+                .tunneled_connections
+                .saturating_sub(baseline.tunneled_connections),
+            proxied_bytes: self.proxied_bytes.saturating_sub(baseline.proxied_bytes),
+            tunneled_bytes: self.tunneled_bytes.saturating_sub(baseline.tunneled_bytes),
+        }
+    }
+}
+
+/// Stage of a connection which timed out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutStage {
+    Connect,
+    TlsHandshake,
+    ResponseHeader,
+    TunnelIdle,
+}
+
+/// Why a request couldn't be sent to the upstream server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamErrorClass {
+    Dns,
+    Tls,
+    Timeout,
+    ConnectionRefused,
+    Other,
+}
+
+/// Why a connection was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionRejection {
+    /// Too many connections were open.
+    ConnectionLimit,
+    /// The client opened too many connections per minute.
+    RateLimit,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+/// Connections refused by the proxy and web servers, since the start of the history
+pub struct RejectedConnections {
+    pub proxy_connection_limit: u64,
+    pub proxy_rate_limit: u64,
+    pub web_connection_limit: u64,
+    pub web_rate_limit: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+/// Timeouts by stage, since the start of the history
+pub struct TimeoutCounts {
+    pub connect: u64,
+    pub tls_handshake: u64,
+    pub response_header: u64,
+    pub tunnel_idle: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+/// Requests which couldn't be sent upstream by cause, since the start of the history
+pub struct UpstreamErrorCounts {
+    pub dns: u64,
+    pub tls: u64,
+    pub timeout: u64,
+    pub connection_refused: u64,
+    pub other: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+/// Blocked requests by category of what blocked them, since the start of the history
+pub struct BlockCategoryCounts {
+    pub ads: u64,
+    pub trackers: u64,
+    pub malware: u64,
+    pub custom: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+/// Upstream responses by class of status code
+pub struct StatusClassCounts {
+    pub informational: u64,
+    pub success: u64,
+    pub redirection: u64,
+    pub client_error: u64,
+    pub server_error: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+/// Upstream responses by scheme of the request sent
+pub struct SchemeCounts {
+    pub http: u64,
+    pub https: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+/// Upstream responses by HTTP version
+pub struct HttpVersionCounts {
+    pub http_1_0: u64,
+    pub http_1_1: u64,
+    pub http_2: u64,
+    pub http_3: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+/// Responses received from upstream servers, since the start of the history. Blocked requests
+/// and responses served from cache aren't counted.
+pub struct ResponseBreakdown {
+    pub status_classes: StatusClassCounts,
+    pub schemes: SchemeCounts,
+    pub versions: HttpVersionCounts,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+/// Reuse of the connections to upstream servers, since the start of the history
+pub struct PoolStatistics {
+    /// Connections opened by the upstream clients, others were reused from their pools.
+    pub connections_opened: u64,
+    /// Upstream responses received per connection opened, `None` before any connection.
+    pub requests_per_connection: Option<f64>,
+}
+
+/// Requests handled during one `HISTORY_RESOLUTION_SECONDS` period.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HistoryBucket {
+    /// Unix timestamp of the start of the period.
+    pub timestamp: u64,
+    pub requests: u64,
+    pub blocked_requests: u64,
+    /// Time to response headers of requests sent upstream, in milliseconds.
+    pub latency_ms_total: u64,
+    pub latency_samples: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SerializableHistoryPoint {
+    pub timestamp: u64,
+    pub requests_per_second: f64,
+    pub blocked_per_second: f64,
+    /// `None` when no request was sent upstream during the period.
+    pub average_latency_ms: Option<f64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SerializableHistory {
+    pub resolution_seconds: u64,
+    /// Oldest first, periods without requests included.
+    pub points: Vec<SerializableHistoryPoint>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SerializableWindowStatistics {
+    /// Seconds actually covered, shorter than the window when not enough history is kept.
+    pub covered_seconds: u64,
+    #[serde(flatten)]
+    pub counters: Counters,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+/// Saturation of the async runtime and of the internal queues, sampled every second
+pub struct RuntimeStatistics {
+    pub workers: u64,
+    /// Share of the last sample worker threads spent busy, between 0 and 1.
+    pub worker_utilization: f64,
+    pub alive_tasks: u64,
+    /// Tasks waiting in the global queue of the runtime.
+    pub global_queue_depth: u64,
+    /// Requests waiting for the blocking engine.
+    pub blocker_backlog: u64,
+    /// Certificates being generated or waiting for a blocking thread.
+    pub certificate_queue: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+/// Rules loaded in the blocking engine of a profile
+pub struct EngineStatistics {
+    pub profile: String,
+    pub network_rules: u64,
+    pub cosmetic_rules: u64,
+    /// Estimated heap used by the engine, in bytes.
+    pub memory_bytes: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct SerializableStatistics {
     pub proxied_requests: u64,
     pub blocked_requests: u64,
+    pub blocked_by_category: BlockCategoryCounts,
+    /// Blocked requests by filter list, or by what else blocked them.
+    #[serde(with = "tuple_vec_map")]
+    #[schema(value_type = HashMap<String, u64>)]
+    pub blocked_by_list: Vec<(String, u64)>,
     pub modified_responses: u64,
+    pub threats_blocked: u64,
+    /// Requests served the response of a concurrent fetch of the same resource.
+    pub coalesced_requests: u64,
+    pub timeouts: TimeoutCounts,
+    pub upstream_errors: UpstreamErrorCounts,
+    pub rejected_connections: RejectedConnections,
+    pub responses: ResponseBreakdown,
+    pub pool: PoolStatistics,
+    /// Bodies of the responses received from upstream servers, as encoded by them.
+    pub proxied_bytes: u64,
+    pub tunneled_connections: u64,
+    pub tunneled_bytes: u64,
+    /// Authorities tunneled the most bytes to.
+    pub top_tunneled: Vec<SerializableTunnelStatistics>,
     #[serde(with = "tuple_vec_map")]
+    #[schema(value_type = HashMap<String, u64>)]
     pub top_blocked_paths: Vec<(String, u64)>,
     #[serde(with = "tuple_vec_map")]
+    #[schema(value_type = HashMap<String, u64>)]
+    pub top_blocked_domains: Vec<(String, u64)>,
+    #[serde(with = "tuple_vec_map")]
+    #[schema(value_type = HashMap<String, u64>)]
     pub top_clients: Vec<(String, u64)>,
+    #[serde(with = "tuple_vec_map")]
+    #[schema(value_type = HashMap<String, u64>)]
+    pub top_countries: Vec<(String, u64)>,
+    #[serde(with = "tuple_vec_map")]
+    #[schema(value_type = HashMap<String, u64>)]
+    pub top_asns: Vec<(String, u64)>,
+    pub users: Vec<SerializableUserStatistics>,
+    /// Blocking engines, by profile name.
+    pub engines: Vec<EngineStatistics>,
+    pub runtime: RuntimeStatistics,
+    /// Anomalies detected during the last hour, most recent last.
+    pub anomalies: Vec<Anomaly>,
+    pub overhead: SerializableProxyOverhead,
+    /// Origins with the highest average time to first byte.
+    pub slowest_origins: Vec<SerializableOriginLatency>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+/// Per-minute series an anomaly was detected in
+pub enum AnomalySeries {
+    Requests,
+    BlockedRequests,
+}
+
+impl AnomalySeries {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Requests => "requests",
+            Self::BlockedRequests => "blocked requests",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+/// Minute during which much more requests than usual were seen
+pub struct Anomaly {
+    /// Unix timestamp of the end of the minute.
+    pub timestamp: u64,
+    /// Username or IP address of the client, `None` for all of them.
+    pub client: Option<String>,
+    pub series: AnomalySeries,
+    pub count: u64,
+    /// Average per minute before the anomaly.
+    pub average: u64,
+}
+
+/// Time spent by Privaxy itself, in microseconds.
+#[derive(Debug, Clone, Default)]
+pub struct ProxyOverhead {
+    /// Requests sent upstream, whose added latency is known.
+    pub requests: u64,
+    pub added_latency_us_total: u64,
+    /// Latest `OVERHEAD_SAMPLES` added latencies.
+    pub added_latency_samples: VecDeque<u64>,
+    pub blocker_decisions: u64,
+    pub blocker_us_total: u64,
+    pub rewritten_documents: u64,
+    pub rewriter_us_total: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+/// Time spent by Privaxy itself rather than waiting for upstream servers, since the start of
+/// the history
+pub struct SerializableProxyOverhead {
+    pub requests: u64,
+    /// Time to the response headers which wasn't spent waiting for the upstream server:
+    /// filtering, header processing and connection handling, in microseconds.
+    pub average_added_latency_us: f64,
+    pub p95_added_latency_us: u64,
+    /// Time to match a request against network filters, in microseconds.
+    pub average_blocker_us: f64,
+    pub rewritten_documents: u64,
+    /// Time spent rewriting a document, cosmetic filters included, in microseconds. Bodies are
+    /// rewritten as they are streamed, after the response headers.
+    pub average_rewriter_us: f64,
+}
+
+/// Latencies of the requests sent to a host.
+#[derive(Debug, Clone, Default)]
+pub struct OriginLatency {
+    pub host: String,
+    pub requests: u64,
+    pub time_to_first_byte_ms_total: u64,
+    /// Requests whose body was entirely received, others have no total duration.
+    pub completed_requests: u64,
+    pub total_duration_ms_total: u64,
+    /// Latest `LATENCY_SAMPLES` times to first byte, in milliseconds.
+    pub time_to_first_byte_samples: VecDeque<u64>,
+    /// Latest `LATENCY_SAMPLES` total durations, in milliseconds.
+    pub total_duration_samples: VecDeque<u64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SerializableOriginLatency {
+    pub host: String,
+    pub requests: u64,
+    /// Time from sending the request to receiving the response headers.
+    pub average_time_to_first_byte_ms: f64,
+    pub p95_time_to_first_byte_ms: u64,
+    /// Time from sending the request to receiving the end of the body, `None` until a body
+    /// was entirely received.
+    pub average_total_duration_ms: Option<f64>,
+    pub p95_total_duration_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+/// Pages of a host on which exceptions disabled cosmetic filters
+pub struct CosmeticExceptionHits {
+    pub host: String,
+    pub generichide: u64,
+    pub specifichide: u64,
+    pub elemhide: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SerializableUserStatistics {
+    pub username: String,
+    pub proxied_requests: u64,
+    pub blocked_requests: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SerializableTunnelStatistics {
+    pub authority: String,
+    pub connections: u64,
+    /// Bytes sent and received.
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TunnelStatistics {
+    pub connections: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UserStatistics {
+    pub proxied_requests: u64,
+    pub blocked_requests: u64,
 }
 
 #[derive(Debug, Clone)]
 pub struct Statistics {
     pub proxied_requests: Arc<Mutex<u64>>,
     pub blocked_requests: Arc<Mutex<u64>>,
+    pub blocked_by_category: Arc<Mutex<BlockCategoryCounts>>,
+    /// Keyed by title of the filter list, or by what else blocked the requests.
+    pub blocked_by_list: Arc<Mutex<HashMap<String, u64>>>,
     pub modified_responses: Arc<Mutex<u64>>,
+    /// Requests blocked by threat protection, also counted in `blocked_requests`.
+    pub threats_blocked: Arc<Mutex<u64>>,
+    /// Requests served the response of a concurrent fetch of the same resource, also counted
+    /// in `proxied_requests`.
+    pub coalesced_requests: Arc<Mutex<u64>>,
+    pub timeouts: Arc<Mutex<TimeoutCounts>>,
+    pub upstream_errors: Arc<Mutex<UpstreamErrorCounts>>,
+    pub rejected_connections: Arc<Mutex<RejectedConnections>>,
+    pub responses: Arc<Mutex<ResponseBreakdown>>,
+    /// Connections opened to upstream servers.
+    pub upstream_connections: Arc<Mutex<u64>>,
+    pub proxied_bytes: Arc<Mutex<u64>>,
+    /// Connections to excluded hosts, which bypass filtering.
+    pub tunneled_connections: Arc<Mutex<u64>>,
+    pub tunneled_bytes: Arc<Mutex<u64>>,
+    /// Tunnels keyed by authority.
+    pub tunnels: Arc<Mutex<HashMap<String, TunnelStatistics>>>,
     pub top_blocked_paths: Arc<Mutex<LRUCache<(String, u64), 1_000>>>,
-    pub top_clients: Arc<Mutex<HashMap<IpAddr, u64>>>,
+    /// Blocked requests aggregated by host, paths of a host are split across `top_blocked_paths`.
+    pub top_blocked_domains: Arc<Mutex<LRUCache<(String, u64), 1_000>>>,
+    /// Keyed by username for authenticated clients, IP address otherwise.
+    pub top_clients: Arc<Mutex<HashMap<String, u64>>>,
+    /// Blocked requests, keyed as `top_clients`.
+    pub blocked_clients: Arc<Mutex<HashMap<String, u64>>>,
+    /// Countries of upstream servers, only known with a GeoIP database.
+    pub top_countries: Arc<Mutex<HashMap<String, u64>>>,
+    /// Autonomous systems of upstream servers, only known with a GeoIP database.
+    pub top_asns: Arc<Mutex<HashMap<String, u64>>>,
+    /// Requests of authenticated clients, keyed by username.
+    pub users: Arc<Mutex<HashMap<String, UserStatistics>>>,
+    pub overhead: Arc<Mutex<ProxyOverhead>>,
+    /// Latest `ANOMALIES_KEPT` anomalies, oldest first.
+    pub anomalies: Arc<Mutex<VecDeque<Anomaly>>>,
+    /// Latencies of the hosts requests were recently sent to.
+    pub origin_latencies: Arc<Mutex<LRUCache<OriginLatency, 256>>>,
+    /// Hosts cosmetic exceptions recently fired on.
+    pub cosmetic_exceptions: Arc<Mutex<LRUCache<CosmeticExceptionHits, 1_000>>>,
+    /// Authorities of intercepted connections, whose certificates are pre-generated. Restored
+    /// from the previous run and not cleared on reset.
+    pub intercepted_authorities: Arc<Mutex<LRUCache<(String, u64), 1_000>>>,
+    /// Blocking engines currently loaded, sorted by profile name. Not cleared on reset.
+    pub engines: Arc<Mutex<Vec<EngineStatistics>>>,
+    /// Last sample of the runtime, `certificate_queue` is kept up to date separately.
+    pub runtime: Arc<Mutex<RuntimeStatistics>>,
+    pub certificate_queue: Arc<Mutex<u64>>,
+    /// Counters taken every `SNAPSHOT_INTERVAL`, oldest first, to compute windows.
+    pub snapshots: Arc<Mutex<VecDeque<(Instant, Counters)>>>,
+    /// Start of the history, either the start of the process or the last reset.
+    pub started_at: Arc<Mutex<Instant>>,
+    /// Since when tables are collected, they are emptied by `prune` once older than the
+    /// retention.
+    pub tables_started_at: Arc<Mutex<Instant>>,
+    /// Ring buffer of the last `HISTORY_POINTS` periods with requests, oldest first.
+    pub history: Arc<Mutex<VecDeque<HistoryBucket>>>,
 }
 
 impl Default for Statistics {
@@ -39,29 +505,469 @@ impl Statistics {
         Self {
             proxied_requests: Arc::new(Mutex::new(0)),
             blocked_requests: Arc::new(Mutex::new(0)),
+            blocked_by_category: Arc::new(Mutex::new(BlockCategoryCounts::default())),
+            blocked_by_list: Arc::new(Mutex::new(HashMap::new())),
             modified_responses: Arc::new(Mutex::new(0)),
+            threats_blocked: Arc::new(Mutex::new(0)),
+            coalesced_requests: Arc::new(Mutex::new(0)),
+            timeouts: Arc::new(Mutex::new(TimeoutCounts::default())),
+            upstream_errors: Arc::new(Mutex::new(UpstreamErrorCounts::default())),
+            rejected_connections: Arc::new(Mutex::new(RejectedConnections::default())),
+            responses: Arc::new(Mutex::new(ResponseBreakdown::default())),
+            upstream_connections: Arc::new(Mutex::new(0)),
+            tunneled_connections: Arc::new(Mutex::new(0)),
+            proxied_bytes: Arc::new(Mutex::new(0)),
+            tunneled_bytes: Arc::new(Mutex::new(0)),
+            tunnels: Arc::new(Mutex::new(HashMap::new())),
             top_blocked_paths: Arc::new(Mutex::new(LRUCache::default())),
+            top_blocked_domains: Arc::new(Mutex::new(LRUCache::default())),
             top_clients: Arc::new(Mutex::new(HashMap::new())),
+            blocked_clients: Arc::new(Mutex::new(HashMap::new())),
+            top_countries: Arc::new(Mutex::new(HashMap::new())),
+            top_asns: Arc::new(Mutex::new(HashMap::new())),
+            overhead: Arc::new(Mutex::new(ProxyOverhead::default())),
+            anomalies: Arc::new(Mutex::new(VecDeque::new())),
+            origin_latencies: Arc::new(Mutex::new(LRUCache::default())),
+            cosmetic_exceptions: Arc::new(Mutex::new(LRUCache::default())),
+            users: Arc::new(Mutex::new(HashMap::new())),
+            intercepted_authorities: Arc::new(Mutex::new(LRUCache::default())),
+            engines: Arc::new(Mutex::new(Vec::new())),
+            runtime: Arc::new(Mutex::new(RuntimeStatistics::default())),
+            certificate_queue: Arc::new(Mutex::new(0)),
+            snapshots: Arc::new(Mutex::new(VecDeque::new())),
+            started_at: Arc::new(Mutex::new(Instant::now())),
+            tables_started_at: Arc::new(Mutex::new(Instant::now())),
+            history: Arc::new(Mutex::new(VecDeque::with_capacity(HISTORY_POINTS))),
         }
     }
 
-    pub fn increment_top_blocked_paths(&self, path_: String) {
-        let mut top_blocked_paths = self.top_blocked_paths.lock().unwrap();
+    /// Records a request in the history. `latency` is only known for requests sent upstream.
+    pub fn record_history(&self, is_blocked: bool, latency: Option<Duration>) {
+        let timestamp = unix_timestamp() / HISTORY_RESOLUTION_SECONDS * HISTORY_RESOLUTION_SECONDS;
+        let mut history = self.history.lock().unwrap();
 
-        match top_blocked_paths.find(|(path, _count)| path == &path_) {
-            Some((_path, count)) => {
-                *count += 1;
+        if history.back().map(|bucket| bucket.timestamp) != Some(timestamp) {
+            if history.len() >= HISTORY_POINTS {
+                history.pop_front();
             }
-            None => {
-                top_blocked_paths.insert((path_, 1));
+            history.push_back(HistoryBucket {
+                timestamp,
+                ..Default::default()
+            });
+        }
+
+        let bucket = history.back_mut().unwrap();
+        bucket.requests += 1;
+        if is_blocked {
+            bucket.blocked_requests += 1;
+        }
+        if let Some(latency) = latency {
+            bucket.latency_ms_total += latency.as_millis() as u64;
+            bucket.latency_samples += 1;
+        }
+    }
+
+    /// The last 24 hours of history, the current period excluded as it is incomplete.
+    pub fn get_history(&self) -> SerializableHistory {
+        let current = unix_timestamp() / HISTORY_RESOLUTION_SECONDS * HISTORY_RESOLUTION_SECONDS;
+        let first = current - HISTORY_POINTS as u64 * HISTORY_RESOLUTION_SECONDS;
+        let history = self.history.lock().unwrap();
+        let mut buckets = history
+            .iter()
+            .filter(|bucket| bucket.timestamp >= first)
+            .peekable();
+
+        let points = (0..HISTORY_POINTS as u64)
+            .map(|index| {
+                let timestamp = first + index * HISTORY_RESOLUTION_SECONDS;
+                let bucket = match buckets.peek() {
+                    Some(bucket) if bucket.timestamp == timestamp => buckets.next().copied(),
+                    _ => None,
+                }
+                .unwrap_or(HistoryBucket {
+                    timestamp,
+                    ..Default::default()
+                });
+
+                SerializableHistoryPoint {
+                    timestamp,
+                    requests_per_second: bucket.requests as f64 / HISTORY_RESOLUTION_SECONDS as f64,
+                    blocked_per_second: bucket.blocked_requests as f64
+                        / HISTORY_RESOLUTION_SECONDS as f64,
+                    average_latency_ms: if bucket.latency_samples > 0 {
+                        Some(bucket.latency_ms_total as f64 / bucket.latency_samples as f64)
+                    } else {
+                        None
+                    },
+                }
+            })
+            .collect();
+
+        SerializableHistory {
+            resolution_seconds: HISTORY_RESOLUTION_SECONDS,
+            points,
+        }
+    }
+
+    fn counters(&self) -> Counters {
+        Counters {
+            proxied_requests: *self.proxied_requests.lock().unwrap(),
+            blocked_requests: *self.blocked_requests.lock().unwrap(),
+            modified_responses: *self.modified_responses.lock().unwrap(),
+            threats_blocked: *self.threats_blocked.lock().unwrap(),
+            tunneled_connections: *self.tunneled_connections.lock().unwrap(),
+            proxied_bytes: *self.proxied_bytes.lock().unwrap(),
+            tunneled_bytes: *self.tunneled_bytes.lock().unwrap(),
+        }
+    }
+
+    /// Periodically snapshots counters, so that they can be queried over windows.
+    pub fn spawn_snapshots(&self) {
+        let statistics = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SNAPSHOT_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let now = Instant::now();
+                let counters = statistics.counters();
+                let mut snapshots = statistics.snapshots.lock().unwrap();
+
+                snapshots.push_back((now, counters));
+                while let Some((at, _counters)) = snapshots.front() {
+                    if now.duration_since(*at) <= SNAPSHOT_RETENTION + SNAPSHOT_INTERVAL {
+                        break;
+                    }
+                    snapshots.pop_front();
+                }
             }
+        });
+    }
+
+    /// Counters incremented during `window`, give or take `SNAPSHOT_INTERVAL`.
+    pub fn get_window(&self, window: Window) -> SerializableWindowStatistics {
+        let now = Instant::now();
+        let counters = self.counters();
+        let snapshots = self.snapshots.lock().unwrap();
+        let started_at = *self.started_at.lock().unwrap();
+
+        // The most recent snapshot taken before the window started, if history goes back
+        // that far. Otherwise counters are taken since the start of the history.
+        let baseline = snapshots
+            .iter()
+            .rev()
+            .find(|(at, _counters)| now.duration_since(*at) >= window.duration());
+
+        match baseline {
+            Some((at, baseline)) => SerializableWindowStatistics {
+                covered_seconds: now.duration_since(*at).as_secs(),
+                counters: counters.since(baseline),
+            },
+            None => SerializableWindowStatistics {
+                covered_seconds: now.duration_since(started_at).as_secs(),
+                counters,
+            },
+        }
+    }
+
+    /// Zeroes counters and empties tables and snapshots.
+    pub fn reset(&self) {
+        *self.proxied_requests.lock().unwrap() = 0;
+        *self.blocked_requests.lock().unwrap() = 0;
+        *self.blocked_by_category.lock().unwrap() = BlockCategoryCounts::default();
+        self.blocked_by_list.lock().unwrap().clear();
+        *self.modified_responses.lock().unwrap() = 0;
+        *self.threats_blocked.lock().unwrap() = 0;
+        *self.coalesced_requests.lock().unwrap() = 0;
+        *self.timeouts.lock().unwrap() = TimeoutCounts::default();
+        *self.upstream_errors.lock().unwrap() = UpstreamErrorCounts::default();
+        *self.rejected_connections.lock().unwrap() = RejectedConnections::default();
+        *self.responses.lock().unwrap() = ResponseBreakdown::default();
+        *self.upstream_connections.lock().unwrap() = 0;
+        *self.tunneled_connections.lock().unwrap() = 0;
+        *self.proxied_bytes.lock().unwrap() = 0;
+        *self.tunneled_bytes.lock().unwrap() = 0;
+        self.clear_tables();
+        *self.overhead.lock().unwrap() = ProxyOverhead::default();
+        self.anomalies.lock().unwrap().clear();
+        self.snapshots.lock().unwrap().clear();
+        self.history.lock().unwrap().clear();
+        *self.started_at.lock().unwrap() = Instant::now();
+
+        log::info!("Statistics reset");
+    }
+
+    /// Empties the tables keyed by client, host or path.
+    fn clear_tables(&self) {
+        self.tunnels.lock().unwrap().clear();
+        self.top_blocked_paths.lock().unwrap().clear();
+        self.top_blocked_domains.lock().unwrap().clear();
+        self.top_clients.lock().unwrap().clear();
+        self.blocked_clients.lock().unwrap().clear();
+        self.top_countries.lock().unwrap().clear();
+        self.top_asns.lock().unwrap().clear();
+        self.origin_latencies.lock().unwrap().clear();
+        self.cosmetic_exceptions.lock().unwrap().clear();
+        self.users.lock().unwrap().clear();
+        *self.tables_started_at.lock().unwrap() = Instant::now();
+    }
+
+    /// Drops history and anomalies older than `retention`, and empties tables once they were
+    /// collected for longer than that. Counters are kept.
+    pub fn prune(&self, retention: Duration) {
+        let oldest_timestamp = unix_timestamp().saturating_sub(retention.as_secs());
+
+        self.history
+            .lock()
+            .unwrap()
+            .retain(|bucket| bucket.timestamp >= oldest_timestamp);
+        self.anomalies
+            .lock()
+            .unwrap()
+            .retain(|anomaly| anomaly.timestamp >= oldest_timestamp);
+
+        let tables_started_at = *self.tables_started_at.lock().unwrap();
+        if tables_started_at.elapsed() >= retention {
+            self.clear_tables();
+            log::info!("Statistics tables older than the retention emptied");
         }
     }
 
-    pub fn increment_top_clients(&self, client: IpAddr) {
+    /// Forgets requests counted per client and per user, for when clients are no longer
+    /// tracked.
+    pub fn clear_clients(&self) {
+        self.top_clients.lock().unwrap().clear();
+        self.blocked_clients.lock().unwrap().clear();
+        self.users.lock().unwrap().clear();
+        self.anomalies
+            .lock()
+            .unwrap()
+            .retain(|anomaly| anomaly.client.is_none());
+    }
+
+    pub fn increment_top_blocked_paths(&self, path_: String) {
+        increment_lru(&mut self.top_blocked_paths.lock().unwrap(), path_);
+    }
+
+    /// Most blocked paths of `domain`, among all those kept rather than the overall top ones.
+    pub fn top_blocked_paths_of(&self, domain: &str) -> Vec<(String, u64)> {
+        top_lru_entries(
+            self.top_blocked_paths
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(path, _count)| blocked_path_host(path) == Some(domain)),
+        )
+    }
+
+    pub fn increment_top_blocked_domains(&self, domain: String) {
+        increment_lru(&mut self.top_blocked_domains.lock().unwrap(), domain);
+    }
+
+    pub fn increment_intercepted_authorities(&self, authority: String) {
+        increment_lru(&mut self.intercepted_authorities.lock().unwrap(), authority);
+    }
+
+    /// Authorities of intercepted connections, most frequent first.
+    pub fn intercepted_authorities(&self) -> Vec<String> {
+        let mut authorities = self
+            .intercepted_authorities
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(authority, count)| (authority.clone(), *count))
+            .collect::<Vec<_>>();
+
+        authorities.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+        authorities
+            .into_iter()
+            .map(|(authority, _count)| authority)
+            .collect()
+    }
+
+    /// Restores the authorities saved by a previous run, most frequent first, keeping their
+    /// order ahead of connections made since.
+    pub fn restore_intercepted_authorities(&self, authorities: Vec<String>) {
+        let mut intercepted_authorities = self.intercepted_authorities.lock().unwrap();
+        let count = authorities.len() as u64;
+
+        for (rank, authority) in authorities.into_iter().enumerate().rev() {
+            if intercepted_authorities
+                .find(|(entry, _count)| entry == &authority)
+                .is_none()
+            {
+                intercepted_authorities.insert((authority, count - rank as u64));
+            }
+        }
+    }
+
+    pub fn increment_top_clients(&self, client: String) {
         *self.top_clients.lock().unwrap().entry(client).or_insert(0) += 1;
     }
 
+    pub fn increment_blocked_clients(&self, client: String) {
+        *self
+            .blocked_clients
+            .lock()
+            .unwrap()
+            .entry(client)
+            .or_insert(0) += 1;
+    }
+
+    /// Keeps an anomaly to be shown on the dashboard.
+    pub fn record_anomaly(
+        &self,
+        client: Option<String>,
+        series: AnomalySeries,
+        count: u64,
+        average: u64,
+    ) {
+        let mut anomalies = self.anomalies.lock().unwrap();
+
+        if anomalies.len() >= ANOMALIES_KEPT {
+            anomalies.pop_front();
+        }
+        anomalies.push_back(Anomaly {
+            timestamp: unix_timestamp(),
+            client,
+            series,
+            count,
+            average,
+        });
+    }
+
+    pub fn increment_top_upstreams(&self, country: Option<String>, asn: Option<String>) {
+        if let Some(country) = country {
+            *self
+                .top_countries
+                .lock()
+                .unwrap()
+                .entry(country)
+                .or_insert(0) += 1;
+        }
+        if let Some(asn) = asn {
+            *self.top_asns.lock().unwrap().entry(asn).or_insert(0) += 1;
+        }
+    }
+
+    /// Records the time to the response headers of a request sent upstream which wasn't spent
+    /// waiting for the upstream server.
+    pub fn record_added_latency(&self, added_latency: Duration) {
+        let mut overhead = self.overhead.lock().unwrap();
+        let added_latency = added_latency.as_micros() as u64;
+
+        overhead.requests += 1;
+        overhead.added_latency_us_total += added_latency;
+        push_sample(
+            &mut overhead.added_latency_samples,
+            added_latency,
+            OVERHEAD_SAMPLES,
+        );
+    }
+
+    /// Records the time taken to match a request against network filters.
+    pub fn record_blocker_decision(&self, duration: Duration) {
+        let mut overhead = self.overhead.lock().unwrap();
+
+        overhead.blocker_decisions += 1;
+        overhead.blocker_us_total += duration.as_micros() as u64;
+    }
+
+    /// Records the time spent rewriting a document.
+    pub fn record_rewrite(&self, duration: Duration) {
+        let mut overhead = self.overhead.lock().unwrap();
+
+        overhead.rewritten_documents += 1;
+        overhead.rewriter_us_total += duration.as_micros() as u64;
+    }
+
+    /// Records the time to the response headers of a request sent to `host`.
+    pub fn record_time_to_first_byte(&self, host: &str, time_to_first_byte: Duration) {
+        let mut origin_latencies = self.origin_latencies.lock().unwrap();
+        let origin = match origin_latencies.find(|origin| origin.host == host) {
+            Some(origin) => origin,
+            None => {
+                origin_latencies.insert(OriginLatency {
+                    host: host.to_string(),
+                    ..Default::default()
+                });
+                origin_latencies.front_mut().unwrap()
+            }
+        };
+        let time_to_first_byte = time_to_first_byte.as_millis() as u64;
+
+        origin.requests += 1;
+        origin.time_to_first_byte_ms_total += time_to_first_byte;
+        push_sample(
+            &mut origin.time_to_first_byte_samples,
+            time_to_first_byte,
+            LATENCY_SAMPLES,
+        );
+    }
+
+    /// Records the time to the end of the body of a request sent to `host`, once it was
+    /// entirely received.
+    pub fn record_total_duration(&self, host: &str, total_duration: Duration) {
+        let mut origin_latencies = self.origin_latencies.lock().unwrap();
+        // Evicted since its headers were received.
+        let origin = match origin_latencies.find(|origin| origin.host == host) {
+            Some(origin) => origin,
+            None => return,
+        };
+        let total_duration = total_duration.as_millis() as u64;
+
+        origin.completed_requests += 1;
+        origin.total_duration_ms_total += total_duration;
+        push_sample(
+            &mut origin.total_duration_samples,
+            total_duration,
+            LATENCY_SAMPLES,
+        );
+    }
+
+    /// Records a page of `host` on which cosmetic filters were disabled by `exceptions`.
+    pub fn record_cosmetic_exceptions(&self, host: String, exceptions: CosmeticExceptions) {
+        let mut cosmetic_exceptions = self.cosmetic_exceptions.lock().unwrap();
+        let hits = match cosmetic_exceptions.find(|hits| hits.host == host) {
+            Some(hits) => hits,
+            None => {
+                cosmetic_exceptions.insert(CosmeticExceptionHits {
+                    host,
+                    ..Default::default()
+                });
+                cosmetic_exceptions.front_mut().unwrap()
+            }
+        };
+
+        hits.generichide += exceptions.generichide as u64;
+        hits.specifichide += exceptions.specifichide as u64;
+        hits.elemhide += exceptions.elemhide as u64;
+    }
+
+    /// Hosts cosmetic exceptions fired on, most recent first.
+    pub fn cosmetic_exceptions(&self) -> Vec<CosmeticExceptionHits> {
+        self.cosmetic_exceptions
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    pub fn increment_user_requests(&self, username: &str, is_blocked: bool) {
+        let mut users = self.users.lock().unwrap();
+        let user = users.entry(username.to_string()).or_default();
+
+        if is_blocked {
+            user.blocked_requests += 1;
+        } else {
+            user.proxied_requests += 1;
+        }
+    }
+
     pub fn increment_proxied_requests(&self) -> u64 {
         let mut proxied_requests = self.proxied_requests.lock().unwrap();
 
@@ -69,7 +975,24 @@ impl Statistics {
         *proxied_requests
     }
 
-    pub fn increment_blocked_requests(&self) -> u64 {
+    pub fn increment_blocked_requests(&self, source: &BlockSource) -> u64 {
+        {
+            let mut blocked_by_category = self.blocked_by_category.lock().unwrap();
+
+            match source.category {
+                BlockCategory::Ads => blocked_by_category.ads += 1,
+                BlockCategory::Trackers => blocked_by_category.trackers += 1,
+                BlockCategory::Malware => blocked_by_category.malware += 1,
+                BlockCategory::Custom => blocked_by_category.custom += 1,
+            }
+        }
+        *self
+            .blocked_by_list
+            .lock()
+            .unwrap()
+            .entry(source.list.clone())
+            .or_default() += 1;
+
         let mut blocked_requests = self.blocked_requests.lock().unwrap();
 
         *blocked_requests += 1;
@@ -83,45 +1006,402 @@ impl Statistics {
         *modified_responses
     }
 
+    pub fn increment_threats_blocked(&self) -> u64 {
+        let mut threats_blocked = self.threats_blocked.lock().unwrap();
+
+        *threats_blocked += 1;
+        *threats_blocked
+    }
+
+    pub fn increment_coalesced_requests(&self) {
+        *self.coalesced_requests.lock().unwrap() += 1;
+    }
+
+    pub fn increment_tunneled_connections(&self, authority: String) {
+        *self.tunneled_connections.lock().unwrap() += 1;
+        self.tunnels
+            .lock()
+            .unwrap()
+            .entry(authority)
+            .or_default()
+            .connections += 1;
+    }
+
+    pub fn add_proxied_bytes(&self, bytes: u64) {
+        *self.proxied_bytes.lock().unwrap() += bytes;
+    }
+
+    pub fn add_tunneled_bytes(&self, authority: String, bytes: u64) {
+        *self.tunneled_bytes.lock().unwrap() += bytes;
+        self.tunnels
+            .lock()
+            .unwrap()
+            .entry(authority)
+            .or_default()
+            .bytes += bytes;
+    }
+
+    pub(crate) fn increment_rejected_connections(
+        &self,
+        listener: Listener,
+        rejection: ConnectionRejection,
+    ) {
+        let mut rejected_connections = self.rejected_connections.lock().unwrap();
+
+        match (listener, rejection) {
+            (Listener::Proxy, ConnectionRejection::ConnectionLimit) => {
+                rejected_connections.proxy_connection_limit += 1
+            }
+            (Listener::Proxy, ConnectionRejection::RateLimit) => {
+                rejected_connections.proxy_rate_limit += 1
+            }
+            (Listener::Web, ConnectionRejection::ConnectionLimit) => {
+                rejected_connections.web_connection_limit += 1
+            }
+            (Listener::Web, ConnectionRejection::RateLimit) => {
+                rejected_connections.web_rate_limit += 1
+            }
+        }
+    }
+
+    /// Samples the runtime the task is spawned on and the backlog of the blocking engine.
+    pub(crate) fn spawn_runtime_sampler(&self, blocker_requester: AdblockRequester) {
+        let runtime = self.runtime.clone();
+        let metrics = tokio::runtime::Handle::current().metrics();
+
+        tokio::spawn(async move {
+            let busy_duration = |metrics: &tokio::runtime::RuntimeMetrics| {
+                (0..metrics.num_workers())
+                    .map(|worker| metrics.worker_total_busy_duration(worker))
+                    .sum::<Duration>()
+            };
+
+            let mut last_sample = Instant::now();
+            let mut last_busy_duration = busy_duration(&metrics);
+
+            loop {
+                tokio::time::sleep(RUNTIME_SAMPLE_INTERVAL).await;
+
+                let now = Instant::now();
+                let busy = busy_duration(&metrics);
+                let workers = metrics.num_workers();
+                let available = now.duration_since(last_sample).as_secs_f64() * workers as f64;
+                let worker_utilization = if available > 0.0 {
+                    (busy.saturating_sub(last_busy_duration).as_secs_f64() / available).min(1.0)
+                } else {
+                    0.0
+                };
+
+                last_sample = now;
+                last_busy_duration = busy;
+
+                let mut runtime = runtime.lock().unwrap();
+                runtime.workers = workers as u64;
+                runtime.worker_utilization = worker_utilization;
+                runtime.alive_tasks = metrics.num_alive_tasks() as u64;
+                runtime.global_queue_depth = metrics.global_queue_depth() as u64;
+                runtime.blocker_backlog = blocker_requester.backlog() as u64;
+            }
+        });
+    }
+
+    /// Counts a certificate generation in `certificate_queue` until the guard is dropped.
+    pub(crate) fn certificate_generation(&self) -> CertificateGeneration {
+        *self.certificate_queue.lock().unwrap() += 1;
+
+        CertificateGeneration {
+            certificate_queue: self.certificate_queue.clone(),
+        }
+    }
+
+    /// Replaces the statistics of the blocking engines once they were rebuilt.
+    pub fn set_engines(&self, mut engines: Vec<EngineStatistics>) {
+        engines.sort_by(|a, b| a.profile.cmp(&b.profile));
+
+        *self.engines.lock().unwrap() = engines;
+    }
+
+    pub fn increment_timeouts(&self, stage: TimeoutStage) {
+        let mut timeouts = self.timeouts.lock().unwrap();
+
+        match stage {
+            TimeoutStage::Connect => timeouts.connect += 1,
+            TimeoutStage::TlsHandshake => timeouts.tls_handshake += 1,
+            TimeoutStage::ResponseHeader => timeouts.response_header += 1,
+            TimeoutStage::TunnelIdle => timeouts.tunnel_idle += 1,
+        }
+    }
+
+    pub fn increment_upstream_errors(&self, class: UpstreamErrorClass) {
+        let mut upstream_errors = self.upstream_errors.lock().unwrap();
+
+        match class {
+            UpstreamErrorClass::Dns => upstream_errors.dns += 1,
+            UpstreamErrorClass::Tls => upstream_errors.tls += 1,
+            UpstreamErrorClass::Timeout => upstream_errors.timeout += 1,
+            UpstreamErrorClass::ConnectionRefused => upstream_errors.connection_refused += 1,
+            UpstreamErrorClass::Other => upstream_errors.other += 1,
+        }
+    }
+
+    /// Tallies a response received from an upstream server.
+    pub fn record_upstream_response(
+        &self,
+        status: StatusCode,
+        scheme: Option<&str>,
+        version: Version,
+    ) {
+        let mut responses = self.responses.lock().unwrap();
+
+        match status.as_u16() / 100 {
+            1 => responses.status_classes.informational += 1,
+            2 => responses.status_classes.success += 1,
+            3 => responses.status_classes.redirection += 1,
+            4 => responses.status_classes.client_error += 1,
+            5 => responses.status_classes.server_error += 1,
+            _ => {}
+        }
+
+        match scheme {
+            Some("http") => responses.schemes.http += 1,
+            Some("https") => responses.schemes.https += 1,
+            _ => {}
+        }
+
+        match version {
+            Version::HTTP_10 => responses.versions.http_1_0 += 1,
+            Version::HTTP_11 => responses.versions.http_1_1 += 1,
+            Version::HTTP_2 => responses.versions.http_2 += 1,
+            Version::HTTP_3 => responses.versions.http_3 += 1,
+            _ => {}
+        }
+    }
+
+    pub fn increment_upstream_connections(&self) {
+        *self.upstream_connections.lock().unwrap() += 1;
+    }
+
     pub fn get_serialized(&self) -> SerializableStatistics {
+        let responses = *self.responses.lock().unwrap();
+        let connections_opened = *self.upstream_connections.lock().unwrap();
+        let status_classes = responses.status_classes;
+        let upstream_responses = status_classes.informational
+            + status_classes.success
+            + status_classes.redirection
+            + status_classes.client_error
+            + status_classes.server_error;
+
         SerializableStatistics {
             proxied_requests: *self.proxied_requests.lock().unwrap(),
             blocked_requests: *self.blocked_requests.lock().unwrap(),
+            blocked_by_category: *self.blocked_by_category.lock().unwrap(),
+            blocked_by_list: top_entries(&self.blocked_by_list.lock().unwrap()),
             modified_responses: *self.modified_responses.lock().unwrap(),
-            top_blocked_paths: {
-                let top_blocked_paths = self.top_blocked_paths.lock().unwrap();
-                let mut top_blocked_paths_iterator = top_blocked_paths.iter();
-
-                let mut top_blocked_paths = (0..=ENTRIES_PER_STATISTICS_TABLE)
-                    .into_iter()
-                    .filter_map(|_| {
-                        let (path, count) = top_blocked_paths_iterator.next()?;
-
-                        Some((path.clone(), *count))
+            threats_blocked: *self.threats_blocked.lock().unwrap(),
+            coalesced_requests: *self.coalesced_requests.lock().unwrap(),
+            timeouts: *self.timeouts.lock().unwrap(),
+            upstream_errors: *self.upstream_errors.lock().unwrap(),
+            rejected_connections: *self.rejected_connections.lock().unwrap(),
+            responses,
+            pool: PoolStatistics {
+                connections_opened,
+                requests_per_connection: (connections_opened > 0)
+                    .then(|| upstream_responses as f64 / connections_opened as f64),
+            },
+            tunneled_connections: *self.tunneled_connections.lock().unwrap(),
+            proxied_bytes: *self.proxied_bytes.lock().unwrap(),
+            tunneled_bytes: *self.tunneled_bytes.lock().unwrap(),
+            top_tunneled: {
+                let mut tunnels = self
+                    .tunnels
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(authority, statistics)| SerializableTunnelStatistics {
+                        authority: authority.clone(),
+                        connections: statistics.connections,
+                        bytes: statistics.bytes,
                     })
                     .collect::<Vec<_>>();
 
-                top_blocked_paths.sort_by(|a, b| b.1.cmp(&a.1));
+                tunnels.sort_by_key(|tunnel| std::cmp::Reverse(tunnel.bytes));
+                tunnels.truncate(ENTRIES_PER_STATISTICS_TABLE as usize);
 
-                top_blocked_paths
+                tunnels
             },
+            top_blocked_paths: top_lru_entries(self.top_blocked_paths.lock().unwrap().iter()),
+            top_blocked_domains: top_lru_entries(self.top_blocked_domains.lock().unwrap().iter()),
             top_clients: {
                 let top_clients = self.top_clients.lock().unwrap();
                 let mut top_clients_iter = top_clients.iter();
 
                 let mut top_clients = (0..=ENTRIES_PER_STATISTICS_TABLE)
-                    .into_iter()
                     .filter_map(|_| {
-                        let (ipv4, count) = top_clients_iter.next()?;
+                        let (client, count) = top_clients_iter.next()?;
 
-                        Some((ipv4.to_string(), *count))
+                        Some((client.clone(), *count))
                     })
                     .collect::<Vec<_>>();
 
-                top_clients.sort_by(|a, b| b.1.cmp(&a.1));
+                top_clients.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
 
                 top_clients
             },
+            top_countries: top_entries(&self.top_countries.lock().unwrap()),
+            top_asns: top_entries(&self.top_asns.lock().unwrap()),
+            anomalies: {
+                let since = unix_timestamp().saturating_sub(ANOMALY_DISPLAY_SECONDS);
+
+                self.anomalies
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|anomaly| anomaly.timestamp >= since)
+                    .cloned()
+                    .collect()
+            },
+            overhead: {
+                let overhead = self.overhead.lock().unwrap();
+
+                SerializableProxyOverhead {
+                    requests: overhead.requests,
+                    average_added_latency_us: overhead.added_latency_us_total as f64
+                        / overhead.requests.max(1) as f64,
+                    p95_added_latency_us: p95(&overhead.added_latency_samples).unwrap_or_default(),
+                    average_blocker_us: overhead.blocker_us_total as f64
+                        / overhead.blocker_decisions.max(1) as f64,
+                    rewritten_documents: overhead.rewritten_documents,
+                    average_rewriter_us: overhead.rewriter_us_total as f64
+                        / overhead.rewritten_documents.max(1) as f64,
+                }
+            },
+            slowest_origins: {
+                let mut origins = self
+                    .origin_latencies
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|origin| SerializableOriginLatency {
+                        host: origin.host.clone(),
+                        requests: origin.requests,
+                        average_time_to_first_byte_ms: origin.time_to_first_byte_ms_total as f64
+                            / origin.requests.max(1) as f64,
+                        p95_time_to_first_byte_ms: p95(&origin.time_to_first_byte_samples)
+                            .unwrap_or_default(),
+                        average_total_duration_ms: (origin.completed_requests > 0).then(|| {
+                            origin.total_duration_ms_total as f64 / origin.completed_requests as f64
+                        }),
+                        p95_total_duration_ms: p95(&origin.total_duration_samples),
+                    })
+                    .collect::<Vec<_>>();
+
+                origins.sort_by(|a, b| {
+                    b.average_time_to_first_byte_ms
+                        .total_cmp(&a.average_time_to_first_byte_ms)
+                });
+                origins.truncate(ENTRIES_PER_STATISTICS_TABLE as usize);
+
+                origins
+            },
+            users: {
+                let mut users = self
+                    .users
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(username, statistics)| SerializableUserStatistics {
+                        username: username.clone(),
+                        proxied_requests: statistics.proxied_requests,
+                        blocked_requests: statistics.blocked_requests,
+                    })
+                    .collect::<Vec<_>>();
+
+                users.sort_by(|a, b| a.username.cmp(&b.username));
+
+                users
+            },
+            engines: self.engines.lock().unwrap().clone(),
+            runtime: RuntimeStatistics {
+                certificate_queue: *self.certificate_queue.lock().unwrap(),
+                ..*self.runtime.lock().unwrap()
+            },
+        }
+    }
+}
+
+/// Certificate generation in progress, see `Statistics::certificate_generation`.
+pub(crate) struct CertificateGeneration {
+    certificate_queue: Arc<Mutex<u64>>,
+}
+
+impl Drop for CertificateGeneration {
+    fn drop(&mut self) {
+        let mut certificate_queue = self.certificate_queue.lock().unwrap();
+        *certificate_queue = certificate_queue.saturating_sub(1);
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn increment_lru(entries: &mut LRUCache<(String, u64), 1_000>, key: String) {
+    match entries.find(|(entry, _count)| entry == &key) {
+        Some((_entry, count)) => {
+            *count += 1;
+        }
+        None => {
+            entries.insert((key, 1));
         }
     }
 }
+
+/// Entries with the highest counts among all those kept, by descending count.
+fn top_lru_entries<'a>(entries: impl Iterator<Item = &'a (String, u64)>) -> Vec<(String, u64)> {
+    let mut entries = entries
+        .map(|(key, count)| (key.clone(), *count))
+        .collect::<Vec<_>>();
+
+    entries.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    entries.truncate(ENTRIES_PER_STATISTICS_TABLE as usize + 1);
+
+    entries
+}
+
+/// Host of a blocked path, stored as `scheme://host/path`.
+fn blocked_path_host(path: &str) -> Option<&str> {
+    path.split_once("://")?.1.split('/').next()
+}
+
+/// Appends `sample`, dropping the oldest once `max_samples` are kept.
+fn push_sample(samples: &mut VecDeque<u64>, sample: u64, max_samples: usize) {
+    if samples.len() >= max_samples {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+/// 95th percentile of `samples`, by the nearest rank.
+fn p95(samples: &VecDeque<u64>) -> Option<u64> {
+    let mut samples = samples.iter().copied().collect::<Vec<_>>();
+    samples.sort_unstable();
+
+    let rank = (samples.len() * 95).div_ceil(100);
+    samples.get(rank.checked_sub(1)?).copied()
+}
+
+/// Most frequent entries of `counts`, in descending order.
+fn top_entries(counts: &HashMap<String, u64>) -> Vec<(String, u64)> {
+    let mut entries = counts
+        .iter()
+        .map(|(key, count)| (key.clone(), *count))
+        .collect::<Vec<_>>();
+
+    entries.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    entries.truncate(ENTRIES_PER_STATISTICS_TABLE as usize);
+
+    entries
+}
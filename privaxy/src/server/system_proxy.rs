@@ -0,0 +1,801 @@
+//! Points the system proxy to Privaxy on start and puts the previous settings back on stop.
+//!
+//! On Windows, both WinINET, used by browsers and most desktop applications, and WinHTTP, used
+//! by services, are configured. WinINET settings belong to the user Privaxy runs as, a service
+//! running as `LocalSystem` only changes the machine wide WinHTTP proxy.
+//!
+//! On macOS, the HTTP and HTTPS proxies of every enabled network service are set with
+//! `networksetup`, which requires running as root.
+//!
+//! On Linux, the GNOME proxy settings are set with `gsettings` and the KDE ones in
+//! `kioslaverc`. Both belong to the desktop user, Privaxy has to run as that user, within
+//! their session.
+use crate::configuration::{NetworkConfig, SystemProxyConfig};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Address clients of the system proxy reach the proxy server at.
+fn proxy_address(network: &NetworkConfig) -> SocketAddr {
+    let ip_address = match network.bind_addr.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip_address)) if ip_address.is_unspecified() => {
+            IpAddr::V4(Ipv4Addr::LOCALHOST)
+        }
+        Ok(IpAddr::V6(ip_address)) if ip_address.is_unspecified() => {
+            IpAddr::V6(Ipv6Addr::LOCALHOST)
+        }
+        Ok(ip_address) => ip_address,
+        Err(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+    };
+
+    SocketAddr::new(ip_address, network.proxy_port)
+}
+
+#[cfg(windows)]
+pub(crate) fn apply(config: &SystemProxyConfig, network: &NetworkConfig) {
+    if !config.enabled {
+        return;
+    }
+
+    let proxy_server = proxy_address(network).to_string();
+    let bypass = config.bypass.join(";");
+
+    windows::apply(&proxy_server, &bypass);
+    log::info!("System proxy set to {}", proxy_server);
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+pub(crate) fn apply(config: &SystemProxyConfig, network: &NetworkConfig) {
+    if !config.enabled {
+        return;
+    }
+
+    let proxy_address = proxy_address(network);
+    // Only WinINET knows about `<local>`, simple host names are always proxied elsewhere.
+    let bypass = config
+        .bypass
+        .iter()
+        .filter(|domain| domain.as_str() != "<local>")
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+
+    #[cfg(target_os = "macos")]
+    macos::apply(proxy_address, &bypass);
+    #[cfg(target_os = "linux")]
+    linux::apply(proxy_address, &bypass);
+}
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+pub(crate) fn apply(config: &SystemProxyConfig, network: &NetworkConfig) {
+    if config.enabled {
+        log::warn!(
+            "Not setting the system proxy to {}, not supported on this platform",
+            proxy_address(network)
+        );
+    }
+}
+
+/// Puts back the settings found when the system proxy was set, if it was.
+pub(crate) fn restore() {
+    #[cfg(windows)]
+    windows::restore();
+    #[cfg(target_os = "macos")]
+    macos::restore();
+    #[cfg(target_os = "linux")]
+    linux::restore();
+}
+
+/// Runs `program`, returns its standard output when it succeeds.
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn run(program: &str, args: &[&str]) -> std::io::Result<String> {
+    let output = std::process::Command::new(program).args(args).output()?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(std::io::Error::other(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::io;
+    use std::net::SocketAddr;
+    use std::sync::Mutex;
+
+    /// Keys changed, mode first so that restoring it turns the proxy off before anything else.
+    const GNOME_KEYS: [(&str, &str); 6] = [
+        ("org.gnome.system.proxy", "mode"),
+        ("org.gnome.system.proxy", "ignore-hosts"),
+        ("org.gnome.system.proxy.http", "host"),
+        ("org.gnome.system.proxy.http", "port"),
+        ("org.gnome.system.proxy.https", "host"),
+        ("org.gnome.system.proxy.https", "port"),
+    ];
+
+    const KIOSLAVERC: &str = "kioslaverc";
+    const KIOSLAVERC_GROUP: &str = "Proxy Settings";
+    const KDE_KEYS: [&str; 4] = ["ProxyType", "httpProxy", "httpsProxy", "NoProxyFor"];
+    /// `ProxyType` of manually configured proxies.
+    const KDE_MANUAL_PROXY: &str = "1";
+
+    /// `kreadconfig` and `kwriteconfig` of the installed Plasma version.
+    type KdeTools = (&'static str, &'static str);
+
+    const KDE_TOOLS: [KdeTools; 2] = [
+        ("kreadconfig6", "kwriteconfig6"),
+        ("kreadconfig5", "kwriteconfig5"),
+    ];
+
+    struct SavedSettings {
+        /// Values as printed by `gsettings get`, which `gsettings set` takes back.
+        gnome: Option<Vec<String>>,
+        /// `None` for keys which were not set.
+        kde: Option<(KdeTools, Vec<Option<String>>)>,
+    }
+
+    static SAVED_SETTINGS: Mutex<Option<SavedSettings>> = Mutex::new(None);
+
+    fn has_program(name: &str) -> bool {
+        std::env::var_os("PATH").is_some_and(|paths| {
+            std::env::split_paths(&paths).any(|directory| directory.join(name).is_file())
+        })
+    }
+
+    fn gsettings_get(schema: &str, key: &str) -> io::Result<String> {
+        Ok(super::run("gsettings", &["get", schema, key])?
+            .trim()
+            .to_string())
+    }
+
+    fn gsettings_set(schema: &str, key: &str, value: &str) -> io::Result<()> {
+        super::run("gsettings", &["set", schema, key, value]).map(|_| ())
+    }
+
+    fn set_gnome(proxy_address: SocketAddr, bypass: &[&str]) -> io::Result<Vec<String>> {
+        let previous = GNOME_KEYS
+            .iter()
+            .map(|(schema, key)| gsettings_get(schema, key))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let host = format!("'{}'", proxy_address.ip());
+        let port = proxy_address.port().to_string();
+        let ignore_hosts = format!(
+            "[{}]",
+            bypass
+                .iter()
+                .map(|host| format!("'{}'", host.replace('\'', "")))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        for (schema, key, value) in [
+            ("org.gnome.system.proxy.http", "host", host.as_str()),
+            ("org.gnome.system.proxy.http", "port", port.as_str()),
+            ("org.gnome.system.proxy.https", "host", host.as_str()),
+            ("org.gnome.system.proxy.https", "port", port.as_str()),
+            (
+                "org.gnome.system.proxy",
+                "ignore-hosts",
+                ignore_hosts.as_str(),
+            ),
+            // Last, so that the proxy is only used once fully configured.
+            ("org.gnome.system.proxy", "mode", "'manual'"),
+        ] {
+            gsettings_set(schema, key, value)?;
+        }
+
+        Ok(previous)
+    }
+
+    fn restore_gnome(previous: &[String]) -> io::Result<()> {
+        for ((schema, key), value) in GNOME_KEYS.iter().zip(previous) {
+            gsettings_set(schema, key, value)?;
+        }
+
+        Ok(())
+    }
+
+    fn kreadconfig(tools: KdeTools, key: &str) -> io::Result<Option<String>> {
+        let value = super::run(
+            tools.0,
+            &[
+                "--file",
+                KIOSLAVERC,
+                "--group",
+                KIOSLAVERC_GROUP,
+                "--key",
+                key,
+            ],
+        )?;
+        let value = value.trim_end_matches('\n');
+
+        Ok((!value.is_empty()).then(|| value.to_string()))
+    }
+
+    fn kwriteconfig(tools: KdeTools, key: &str, value: Option<&str>) -> io::Result<()> {
+        let mut args = vec![
+            "--file",
+            KIOSLAVERC,
+            "--group",
+            KIOSLAVERC_GROUP,
+            "--key",
+            key,
+        ];
+        match value {
+            Some(value) => args.push(value),
+            None => args.push("--delete"),
+        }
+
+        super::run(tools.1, &args).map(|_| ())
+    }
+
+    /// Running KDE applications only pick up `kioslaverc` changes once told about them.
+    fn notify_kde() {
+        let _ = super::run(
+            "dbus-send",
+            &[
+                "--type=signal",
+                "/KIO/Scheduler",
+                "org.kde.KIO.Scheduler.reparseSlaveConfiguration",
+                "string:",
+            ],
+        );
+    }
+
+    fn set_kde(
+        tools: KdeTools,
+        proxy_address: SocketAddr,
+        bypass: &[&str],
+    ) -> io::Result<Vec<Option<String>>> {
+        let previous = KDE_KEYS
+            .iter()
+            .map(|key| kreadconfig(tools, key))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        // KDE separates the port with a space.
+        let proxy = format!("http://{} {}", proxy_address.ip(), proxy_address.port());
+        let no_proxy_for = bypass.join(",");
+
+        for (key, value) in [
+            ("httpProxy", proxy.as_str()),
+            ("httpsProxy", proxy.as_str()),
+            ("NoProxyFor", no_proxy_for.as_str()),
+            ("ProxyType", KDE_MANUAL_PROXY),
+        ] {
+            kwriteconfig(tools, key, Some(value))?;
+        }
+        notify_kde();
+
+        Ok(previous)
+    }
+
+    fn restore_kde(tools: KdeTools, previous: &[Option<String>]) -> io::Result<()> {
+        for (key, value) in KDE_KEYS.iter().zip(previous) {
+            kwriteconfig(tools, key, value.as_deref())?;
+        }
+        notify_kde();
+
+        Ok(())
+    }
+
+    pub(super) fn apply(proxy_address: SocketAddr, bypass: &[&str]) {
+        let mut saved_settings = SAVED_SETTINGS.lock().unwrap();
+
+        let gnome = if has_program("gsettings") {
+            match set_gnome(proxy_address, bypass) {
+                Ok(previous) => {
+                    log::info!("GNOME proxy set to {}", proxy_address);
+                    Some(previous)
+                }
+                Err(err) => {
+                    log::warn!("Unable to set the GNOME proxy: {err}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let kde = KDE_TOOLS
+            .into_iter()
+            .find(|(kreadconfig, kwriteconfig)| {
+                has_program(kreadconfig) && has_program(kwriteconfig)
+            })
+            .and_then(|tools| match set_kde(tools, proxy_address, bypass) {
+                Ok(previous) => {
+                    log::info!("KDE proxy set to {}", proxy_address);
+                    Some((tools, previous))
+                }
+                Err(err) => {
+                    log::warn!("Unable to set the KDE proxy: {err}");
+                    None
+                }
+            });
+
+        if gnome.is_none() && kde.is_none() {
+            log::warn!("Neither GNOME nor KDE proxy settings could be set");
+        }
+
+        // Settings found the first time are the ones to put back.
+        if saved_settings.is_none() {
+            *saved_settings = Some(SavedSettings { gnome, kde });
+        }
+    }
+
+    pub(super) fn restore() {
+        let saved_settings = match SAVED_SETTINGS.lock().unwrap().take() {
+            Some(saved_settings) => saved_settings,
+            None => return,
+        };
+
+        if let Some(previous) = &saved_settings.gnome {
+            if let Err(err) = restore_gnome(previous) {
+                log::warn!("Unable to restore the GNOME proxy: {err}");
+            }
+        }
+        if let Some((tools, previous)) = &saved_settings.kde {
+            if let Err(err) = restore_kde(*tools, previous) {
+                log::warn!("Unable to restore the KDE proxy: {err}");
+            }
+        }
+
+        log::info!("System proxy restored");
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use std::io;
+    use std::net::SocketAddr;
+    use std::sync::Mutex;
+
+    /// Proxy settings of a network service, such as "Wi-Fi", found before setting it.
+    struct SavedSettings {
+        service: String,
+        web_proxy: ProxySettings,
+        secure_web_proxy: ProxySettings,
+        bypass_domains: Vec<String>,
+    }
+
+    struct ProxySettings {
+        enabled: bool,
+        server: String,
+        port: String,
+    }
+
+    static SAVED_SETTINGS: Mutex<Option<Vec<SavedSettings>>> = Mutex::new(None);
+
+    fn networksetup(args: &[&str]) -> io::Result<String> {
+        super::run("networksetup", args)
+    }
+
+    /// Enabled network services. The first line of the output is a notice, disabled services
+    /// are prefixed with an asterisk.
+    fn network_services() -> io::Result<Vec<String>> {
+        Ok(networksetup(&["-listallnetworkservices"])?
+            .lines()
+            .skip(1)
+            .filter(|service| !service.is_empty() && !service.starts_with('*'))
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Parses the output of `-getwebproxy` and `-getsecurewebproxy`:
+    ///
+    /// ```text
+    /// Enabled: Yes
+    /// Server: 127.0.0.1
+    /// Port: 8100
+    /// Authenticated Proxy Enabled: 0
+    /// ```
+    fn get_proxy(option: &str, service: &str) -> io::Result<ProxySettings> {
+        let output = networksetup(&[option, service])?;
+        let value = |name: &str| {
+            output
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        Ok(ProxySettings {
+            enabled: value("Enabled") == "Yes",
+            server: value("Server"),
+            port: value("Port"),
+        })
+    }
+
+    fn get_bypass_domains(service: &str) -> io::Result<Vec<String>> {
+        let output = networksetup(&["-getproxybypassdomains", service])?;
+
+        // Printed instead of domains when there are none.
+        if output.starts_with("There aren't any bypass domains") {
+            return Ok(Vec::new());
+        }
+
+        Ok(output
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn set_proxy(
+        set_option: &str,
+        state_option: &str,
+        service: &str,
+        settings: &ProxySettings,
+    ) -> io::Result<()> {
+        if !settings.server.is_empty() {
+            networksetup(&[set_option, service, &settings.server, &settings.port])?;
+        }
+        networksetup(&[
+            state_option,
+            service,
+            if settings.enabled { "on" } else { "off" },
+        ])?;
+
+        Ok(())
+    }
+
+    fn set_bypass_domains(service: &str, domains: &[&str]) -> io::Result<()> {
+        let mut args = vec!["-setproxybypassdomains", service];
+        if domains.is_empty() {
+            args.push("Empty");
+        } else {
+            args.extend_from_slice(domains);
+        }
+
+        networksetup(&args).map(|_| ())
+    }
+
+    fn set_service(
+        service: &str,
+        proxy: &ProxySettings,
+        bypass: &[&str],
+    ) -> io::Result<SavedSettings> {
+        let saved_settings = SavedSettings {
+            service: service.to_string(),
+            web_proxy: get_proxy("-getwebproxy", service)?,
+            secure_web_proxy: get_proxy("-getsecurewebproxy", service)?,
+            bypass_domains: get_bypass_domains(service)?,
+        };
+
+        set_proxy("-setwebproxy", "-setwebproxystate", service, proxy)?;
+        set_proxy(
+            "-setsecurewebproxy",
+            "-setsecurewebproxystate",
+            service,
+            proxy,
+        )?;
+        set_bypass_domains(service, bypass)?;
+
+        Ok(saved_settings)
+    }
+
+    fn restore_service(saved_settings: &SavedSettings) -> io::Result<()> {
+        let service = saved_settings.service.as_str();
+
+        set_proxy(
+            "-setwebproxy",
+            "-setwebproxystate",
+            service,
+            &saved_settings.web_proxy,
+        )?;
+        set_proxy(
+            "-setsecurewebproxy",
+            "-setsecurewebproxystate",
+            service,
+            &saved_settings.secure_web_proxy,
+        )?;
+        set_bypass_domains(
+            service,
+            &saved_settings
+                .bypass_domains
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    pub(super) fn apply(proxy_address: SocketAddr, bypass: &[&str]) {
+        let mut saved_settings = SAVED_SETTINGS.lock().unwrap();
+
+        let services = match network_services() {
+            Ok(services) => services,
+            Err(err) => {
+                log::warn!("Unable to list network services: {err}");
+                return;
+            }
+        };
+
+        let proxy = ProxySettings {
+            enabled: true,
+            server: proxy_address.ip().to_string(),
+            port: proxy_address.port().to_string(),
+        };
+
+        let mut saved = Vec::new();
+        for service in services {
+            match set_service(&service, &proxy, bypass) {
+                Ok(settings) => saved.push(settings),
+                Err(err) => log::warn!("Unable to set the proxy of {}: {err}", service),
+            }
+        }
+        log::info!("System proxy set to {}", proxy_address);
+
+        // Settings found the first time are the ones to put back.
+        if saved_settings.is_none() {
+            *saved_settings = Some(saved);
+        }
+    }
+
+    pub(super) fn restore() {
+        let saved_settings = match SAVED_SETTINGS.lock().unwrap().take() {
+            Some(saved_settings) => saved_settings,
+            None => return,
+        };
+
+        for settings in &saved_settings {
+            if let Err(err) = restore_service(settings) {
+                log::warn!("Unable to restore the proxy of {}: {err}", settings.service);
+            }
+        }
+
+        log::info!("System proxy restored");
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::OsStr;
+    use std::os::windows::ffi::OsStrExt;
+    use std::sync::Mutex;
+    use windows_sys::Win32::Foundation::GlobalFree;
+    use windows_sys::Win32::Networking::WinHttp::{
+        WinHttpGetDefaultProxyConfiguration, WinHttpSetDefaultProxyConfiguration,
+        WINHTTP_ACCESS_TYPE_NAMED_PROXY, WINHTTP_PROXY_INFO,
+    };
+    use windows_sys::Win32::Networking::WinInet::{
+        InternetSetOptionW, INTERNET_OPTION_REFRESH, INTERNET_OPTION_SETTINGS_CHANGED,
+    };
+    use winreg::enums::{HKEY_CURRENT_USER, KEY_READ, KEY_WRITE};
+    use winreg::RegKey;
+
+    const INTERNET_SETTINGS_KEY: &str =
+        r"Software\Microsoft\Windows\CurrentVersion\Internet Settings";
+
+    /// Settings found before setting the system proxy.
+    struct SavedSettings {
+        wininet: Option<WinInetSettings>,
+        winhttp: Option<WinHttpSettings>,
+    }
+
+    struct WinInetSettings {
+        proxy_enable: Option<u32>,
+        proxy_server: Option<String>,
+        proxy_override: Option<String>,
+    }
+
+    struct WinHttpSettings {
+        access_type: u32,
+        proxy: Option<String>,
+        proxy_bypass: Option<String>,
+    }
+
+    static SAVED_SETTINGS: Mutex<Option<SavedSettings>> = Mutex::new(None);
+
+    pub(super) fn apply(proxy_server: &str, bypass: &str) {
+        let mut saved_settings = SAVED_SETTINGS.lock().unwrap();
+
+        let wininet = match set_wininet(proxy_server, bypass) {
+            Ok(previous) => Some(previous),
+            Err(err) => {
+                log::warn!("Unable to set the WinINET proxy: {err}");
+                None
+            }
+        };
+        let winhttp = match set_winhttp(proxy_server, bypass) {
+            Ok(previous) => Some(previous),
+            Err(err) => {
+                log::warn!(
+                    "Unable to set the WinHTTP proxy, this requires administrator rights: {err}"
+                );
+                None
+            }
+        };
+
+        // Settings found the first time are the ones to put back.
+        if saved_settings.is_none() {
+            *saved_settings = Some(SavedSettings { wininet, winhttp });
+        }
+    }
+
+    pub(super) fn restore() {
+        let saved_settings = match SAVED_SETTINGS.lock().unwrap().take() {
+            Some(saved_settings) => saved_settings,
+            None => return,
+        };
+
+        if let Some(wininet) = saved_settings.wininet {
+            if let Err(err) = restore_wininet(wininet) {
+                log::warn!("Unable to restore the WinINET proxy: {err}");
+            }
+        }
+        if let Some(winhttp) = saved_settings.winhttp {
+            if let Err(err) = restore_winhttp(winhttp) {
+                log::warn!("Unable to restore the WinHTTP proxy: {err}");
+            }
+        }
+
+        log::info!("System proxy restored");
+    }
+
+    fn open_internet_settings() -> std::io::Result<RegKey> {
+        RegKey::predef(HKEY_CURRENT_USER)
+            .open_subkey_with_flags(INTERNET_SETTINGS_KEY, KEY_READ | KEY_WRITE)
+    }
+
+    fn set_wininet(proxy_server: &str, bypass: &str) -> std::io::Result<WinInetSettings> {
+        let key = open_internet_settings()?;
+
+        let previous = WinInetSettings {
+            proxy_enable: key.get_value("ProxyEnable").ok(),
+            proxy_server: key.get_value("ProxyServer").ok(),
+            proxy_override: key.get_value("ProxyOverride").ok(),
+        };
+
+        key.set_value("ProxyEnable", &1u32)?;
+        key.set_value("ProxyServer", &proxy_server)?;
+        key.set_value("ProxyOverride", &bypass)?;
+        notify_wininet();
+
+        Ok(previous)
+    }
+
+    fn restore_wininet(previous: WinInetSettings) -> std::io::Result<()> {
+        let key = open_internet_settings()?;
+
+        match previous.proxy_enable {
+            Some(proxy_enable) => key.set_value("ProxyEnable", &proxy_enable)?,
+            None => key.set_value("ProxyEnable", &0u32)?,
+        }
+        for (name, value) in [
+            ("ProxyServer", previous.proxy_server),
+            ("ProxyOverride", previous.proxy_override),
+        ] {
+            match value {
+                Some(value) => key.set_value(name, &value)?,
+                None => {
+                    let _ = key.delete_value(name);
+                }
+            }
+        }
+        notify_wininet();
+
+        Ok(())
+    }
+
+    /// Running applications only pick up registry changes once told about them.
+    fn notify_wininet() {
+        // Safety: no buffer is passed along with these options.
+        unsafe {
+            InternetSetOptionW(
+                std::ptr::null(),
+                INTERNET_OPTION_SETTINGS_CHANGED,
+                std::ptr::null(),
+                0,
+            );
+            InternetSetOptionW(
+                std::ptr::null(),
+                INTERNET_OPTION_REFRESH,
+                std::ptr::null(),
+                0,
+            );
+        }
+    }
+
+    fn set_winhttp(proxy_server: &str, bypass: &str) -> std::io::Result<WinHttpSettings> {
+        let previous = get_winhttp()?;
+
+        set_default_proxy_configuration(
+            WINHTTP_ACCESS_TYPE_NAMED_PROXY,
+            Some(proxy_server),
+            Some(bypass),
+        )?;
+
+        Ok(previous)
+    }
+
+    fn restore_winhttp(previous: WinHttpSettings) -> std::io::Result<()> {
+        set_default_proxy_configuration(
+            previous.access_type,
+            previous.proxy.as_deref(),
+            previous.proxy_bypass.as_deref(),
+        )
+    }
+
+    fn get_winhttp() -> std::io::Result<WinHttpSettings> {
+        let mut proxy_info: WINHTTP_PROXY_INFO = unsafe { std::mem::zeroed() };
+
+        // Safety: `proxy_info` is a valid, writable structure. Strings it receives are
+        // allocated by WinHTTP and freed below.
+        unsafe {
+            if WinHttpGetDefaultProxyConfiguration(&mut proxy_info) == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            let settings = WinHttpSettings {
+                access_type: proxy_info.dwAccessType,
+                proxy: from_wide(proxy_info.lpszProxy),
+                proxy_bypass: from_wide(proxy_info.lpszProxyBypass),
+            };
+
+            if !proxy_info.lpszProxy.is_null() {
+                GlobalFree(proxy_info.lpszProxy as _);
+            }
+            if !proxy_info.lpszProxyBypass.is_null() {
+                GlobalFree(proxy_info.lpszProxyBypass as _);
+            }
+
+            Ok(settings)
+        }
+    }
+
+    fn set_default_proxy_configuration(
+        access_type: u32,
+        proxy: Option<&str>,
+        proxy_bypass: Option<&str>,
+    ) -> std::io::Result<()> {
+        let mut proxy = proxy.map(to_wide);
+        let mut proxy_bypass = proxy_bypass.map(to_wide);
+
+        let mut proxy_info = WINHTTP_PROXY_INFO {
+            dwAccessType: access_type,
+            lpszProxy: proxy
+                .as_mut()
+                .map_or(std::ptr::null_mut(), |proxy| proxy.as_mut_ptr()),
+            lpszProxyBypass: proxy_bypass
+                .as_mut()
+                .map_or(std::ptr::null_mut(), |proxy_bypass| {
+                    proxy_bypass.as_mut_ptr()
+                }),
+        };
+
+        // Safety: strings outlive the call, WinHTTP copies them.
+        if unsafe { WinHttpSetDefaultProxyConfiguration(&mut proxy_info) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    fn to_wide(value: &str) -> Vec<u16> {
+        OsStr::new(value)
+            .encode_wide()
+            .chain(std::iter::once(0))
+            .collect()
+    }
+
+    /// # Safety
+    ///
+    /// `value` must be null or point to a nul terminated UTF-16 string.
+    unsafe fn from_wide(value: *const u16) -> Option<String> {
+        if value.is_null() {
+            return None;
+        }
+
+        let mut length = 0;
+        while *value.add(length) != 0 {
+            length += 1;
+        }
+
+        Some(String::from_utf16_lossy(std::slice::from_raw_parts(
+            value, length,
+        )))
+    }
+}
@@ -0,0 +1,199 @@
+//! Integration with systemd: readiness and watchdog notifications (`sd_notify(3)`) as well as
+//! socket activation (`sd_listen_fds(3)`).
+//!
+//! Everything in here is a no-op when privaxy is not started by systemd, and on platforms
+//! other than Unix.
+use std::env;
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::io::{FromRawFd, RawFd};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+/// First file descriptor passed by systemd, see `SD_LISTEN_FDS_START`.
+#[cfg(unix)]
+const LISTEN_FDS_START: RawFd = 3;
+
+/// `FileDescriptorName=` of the socket unit for the proxy listener.
+#[cfg(unix)]
+const PROXY_SOCKET_NAME: &str = "proxy";
+/// `FileDescriptorName=` of the socket unit for the web GUI listener.
+#[cfg(unix)]
+const WEB_SOCKET_NAME: &str = "web";
+
+#[cfg(not(unix))]
+pub(crate) fn notify(_state: &str) {}
+
+/// Sends a state update to the service manager, if any.
+#[cfg(unix)]
+pub(crate) fn notify(state: &str) {
+    let socket_path = match env::var_os("NOTIFY_SOCKET") {
+        Some(socket_path) => socket_path,
+        None => return,
+    };
+
+    let socket = match UnixDatagram::unbound() {
+        Ok(socket) => socket,
+        Err(err) => {
+            log::warn!("Unable to create systemd notification socket: {err}");
+            return;
+        }
+    };
+
+    let socket_path = socket_path.to_string_lossy();
+    let result = match socket_path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(abstract_name) => {
+            use std::os::linux::net::SocketAddrExt;
+
+            std::os::unix::net::SocketAddr::from_abstract_name(abstract_name.as_bytes())
+                .and_then(|address| socket.send_to_addr(state.as_bytes(), &address))
+        }
+        _ => socket.send_to(state.as_bytes(), socket_path.as_ref()),
+    };
+
+    if let Err(err) = result {
+        log::warn!("Unable to notify systemd: {err}");
+    }
+}
+
+/// Interval at which systemd expects `WATCHDOG=1` pings, when the watchdog is enabled for us.
+fn watchdog_interval() -> Option<Duration> {
+    if let Ok(watchdog_pid) = env::var("WATCHDOG_PID") {
+        if watchdog_pid.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+
+    let microseconds = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+
+    (microseconds > 0).then(|| Duration::from_micros(microseconds))
+}
+
+/// Pings the systemd watchdog at half the configured interval for as long as the runtime
+/// is able to schedule tasks.
+pub(crate) fn spawn_watchdog() {
+    let interval = match watchdog_interval() {
+        Some(interval) => interval / 2,
+        None => return,
+    };
+
+    log::debug!("Pinging systemd watchdog every {:?}", interval);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+            notify("WATCHDOG=1");
+        }
+    });
+}
+
+/// Listeners passed by systemd through socket activation.
+#[derive(Debug, Default)]
+pub(crate) struct ActivatedListeners {
+    proxy: Option<TcpListener>,
+    web: Option<TcpListener>,
+}
+
+impl ActivatedListeners {
+    /// Takes ownership of the file descriptors passed by systemd. Sockets are matched with
+    /// their `FileDescriptorName=`, unnamed sockets are used for the proxy then the web GUI,
+    /// in order.
+    ///
+    /// This must only be called once, environment variables are cleared so that child
+    /// processes do not inherit them.
+    #[cfg(unix)]
+    pub(crate) fn from_env() -> Self {
+        let listen_pid = env::var("LISTEN_PID").ok();
+        let listen_fds = env::var("LISTEN_FDS").ok();
+        let listen_fdnames = env::var("LISTEN_FDNAMES").ok();
+
+        env::remove_var("LISTEN_PID");
+        env::remove_var("LISTEN_FDS");
+        env::remove_var("LISTEN_FDNAMES");
+
+        let listen_fds = match (listen_pid, listen_fds) {
+            (Some(pid), Some(fds)) if pid.parse::<u32>().ok() == Some(std::process::id()) => {
+                fds.parse::<RawFd>().unwrap_or(0)
+            }
+            _ => return Self::default(),
+        };
+
+        let names = listen_fdnames
+            .map(|names| names.split(':').map(str::to_string).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let mut listeners = Self::default();
+        let mut unnamed = Vec::new();
+
+        for (index, fd) in (LISTEN_FDS_START..LISTEN_FDS_START + listen_fds).enumerate() {
+            // Safety: systemd hands over these file descriptors to us, nothing else owns them.
+            let listener = unsafe { TcpListener::from_raw_fd(fd) };
+
+            if let Err(err) = listener.set_nonblocking(true) {
+                log::error!("Unable to use socket passed by systemd: {err}");
+                continue;
+            }
+
+            match names.get(index).map(String::as_str) {
+                Some(PROXY_SOCKET_NAME) => listeners.proxy = Some(listener),
+                Some(WEB_SOCKET_NAME) => listeners.web = Some(listener),
+                _ => unnamed.push(listener),
+            }
+        }
+
+        let mut unnamed = unnamed.into_iter();
+        if listeners.proxy.is_none() {
+            listeners.proxy = unnamed.next();
+        }
+        if listeners.web.is_none() {
+            listeners.web = unnamed.next();
+        }
+
+        if let Some(listener) = &listeners.proxy {
+            log::info!(
+                "Using proxy socket passed by systemd: {:?}",
+                listener.local_addr()
+            );
+        }
+        if let Some(listener) = &listeners.web {
+            log::info!(
+                "Using web socket passed by systemd: {:?}",
+                listener.local_addr()
+            );
+        }
+
+        listeners
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn from_env() -> Self {
+        Self::default()
+    }
+
+    /// Returns a handle to the proxy listener. Listeners are cloned rather than moved so that
+    /// they survive configuration reloads.
+    pub(crate) fn proxy(&self) -> Option<TcpListener> {
+        Self::try_clone(&self.proxy)
+    }
+
+    /// Returns a handle to the web GUI listener.
+    pub(crate) fn web(&self) -> Option<TcpListener> {
+        Self::try_clone(&self.web)
+    }
+
+    fn try_clone(listener: &Option<TcpListener>) -> Option<TcpListener> {
+        listener
+            .as_ref()
+            .and_then(|listener| match listener.try_clone() {
+                Ok(listener) => Some(listener),
+                Err(err) => {
+                    log::error!("Unable to clone socket passed by systemd: {err}");
+                    None
+                }
+            })
+    }
+}
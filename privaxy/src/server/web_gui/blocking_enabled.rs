@@ -1,24 +1,39 @@
 use crate::blocker::BlockingDisabledStore;
 use serde::Deserialize;
 use std::convert::Infallible;
+use utoipa::ToSchema;
 use warp::filters::BoxedFilter;
 use warp::http::StatusCode;
 use warp::Filter as RouteFilter;
 
-#[derive(Deserialize)]
+/// Whether requests should be blocked.
+#[derive(Deserialize, ToSchema)]
 pub struct BlockingEnabled(bool);
 
+#[utoipa::path(
+    get,
+    path = "/api/blocking-enabled",
+    tag = "blocking",
+    responses((status = 200, description = "Whether requests are being blocked", body = bool))
+)]
 pub async fn get_blocking_enabled(
     blocking_disabled_store: BlockingDisabledStore,
 ) -> Result<impl warp::Reply, Infallible> {
     Ok(warp::reply::json(&blocking_disabled_store.is_enabled()))
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/blocking-enabled",
+    tag = "blocking",
+    request_body = BlockingEnabled,
+    responses((status = 204, description = "Blocking turned on for `true`, off for `false`"))
+)]
 pub async fn put_blocking_enabled(
     blocking_enabled: BlockingEnabled,
     blocking_disabled_store: BlockingDisabledStore,
 ) -> Result<impl warp::Reply, Infallible> {
-    blocking_disabled_store.set(!blocking_enabled.0);
+    blocking_disabled_store.set(blocking_enabled.0);
 
     Ok(StatusCode::NO_CONTENT)
 }
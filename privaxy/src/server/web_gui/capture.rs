@@ -0,0 +1,120 @@
+use super::error_response;
+use crate::proxy::capture::{CaptureSettings, CaptureStore};
+use std::convert::Infallible;
+use warp::filters::BoxedFilter;
+use warp::http::{self, Response, StatusCode};
+use warp::Filter as RouteFilter;
+
+#[utoipa::path(
+    get,
+    path = "/api/capture",
+    tag = "debugging",
+    responses((status = 200, description = "Current or last capture", body = CaptureStatus))
+)]
+pub async fn get_capture(capture_store: CaptureStore) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&capture_store.status()))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/capture",
+    tag = "debugging",
+    request_body = CaptureSettings,
+    responses(
+        (status = 204, description = "Capture started, replacing the previous one"),
+        (status = 400, description = "Neither a client nor a domain was given", body = ApiError)
+    )
+)]
+pub async fn put_capture(
+    settings: CaptureSettings,
+    capture_store: CaptureStore,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let is_blank =
+        |value: &Option<String>| value.as_deref().is_none_or(|value| value.trim().is_empty());
+    if is_blank(&settings.client) && is_blank(&settings.domain) {
+        return Ok(error_response(
+            StatusCode::BAD_REQUEST,
+            "A client or a domain is required",
+        ));
+    }
+
+    capture_store.start(CaptureSettings {
+        client: settings.client.filter(|client| !client.trim().is_empty()),
+        domain: settings.domain.filter(|domain| !domain.trim().is_empty()),
+        ..settings
+    });
+
+    Ok(Box::new(StatusCode::NO_CONTENT))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/capture",
+    tag = "debugging",
+    responses((status = 204, description = "Capture stopped, it can still be downloaded"))
+)]
+pub async fn delete_capture(capture_store: CaptureStore) -> Result<impl warp::Reply, Infallible> {
+    capture_store.stop();
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/capture/har",
+    tag = "debugging",
+    responses(
+        (status = 200, description = "Recorded requests as a HAR 1.2 file"),
+        (status = 404, description = "Nothing was captured", body = ApiError)
+    )
+)]
+pub async fn get_har(capture_store: CaptureStore) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let har = match capture_store.to_har() {
+        Some(har) => har,
+        None => {
+            return Ok(error_response(
+                StatusCode::NOT_FOUND,
+                "Nothing was captured",
+            ))
+        }
+    };
+
+    Ok(Box::new(
+        Response::builder()
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(
+                http::header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"privaxy-{}.har\"",
+                    chrono::Utc::now().format("%Y%m%d-%H%M%S")
+                ),
+            )
+            .body(serde_json::to_string(&har).unwrap())
+            .unwrap(),
+    ))
+}
+
+pub(super) fn create_routes(capture_store: CaptureStore) -> BoxedFilter<(impl warp::Reply,)> {
+    let with_capture_store = warp::any().map(move || capture_store.clone());
+
+    let har_route = warp::path("har")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_capture_store.clone())
+        .and_then(self::get_har);
+
+    let capture_route = warp::path::end().and(
+        warp::get()
+            .and(with_capture_store.clone())
+            .and_then(self::get_capture)
+            .or(warp::put()
+                .and(warp::body::json())
+                .and(with_capture_store.clone())
+                .and_then(self::put_capture))
+            .or(warp::delete()
+                .and(with_capture_store)
+                .and_then(self::delete_capture)),
+    );
+
+    har_route.or(capture_route).boxed()
+}
@@ -0,0 +1,88 @@
+use super::error_response;
+use crate::cert::CertCache;
+use http::uri::Authority;
+use std::convert::Infallible;
+use std::str::FromStr;
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::Filter as RouteFilter;
+
+#[utoipa::path(
+    get,
+    path = "/api/certificates/cache",
+    tag = "certificates",
+    responses(
+        (status = 200, description = "Hit rate, size and age of the cached certificates", body = CertificateCacheStatus)
+    )
+)]
+pub async fn get_cache(cert_cache: CertCache) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&cert_cache.status().await))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/certificates/cache",
+    tag = "certificates",
+    responses(
+        (status = 204, description = "Cached certificates discarded, they are signed again on the next connection")
+    )
+)]
+pub async fn flush_cache(cert_cache: CertCache) -> Result<impl warp::Reply, Infallible> {
+    let count = cert_cache.flush().await;
+    log::info!("Discarded {} cached certificates", count);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/certificates/cache/{authority}",
+    tag = "certificates",
+    params(("authority" = String, Path, description = "Host, and port when not 443, of the certificate")),
+    responses(
+        (status = 204, description = "Certificate discarded"),
+        (status = 400, description = "Invalid authority", body = ApiError),
+        (status = 404, description = "No certificate cached for this authority", body = ApiError)
+    )
+)]
+pub async fn evict_certificate(
+    authority: String,
+    cert_cache: CertCache,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let authority = match Authority::from_str(&authority) {
+        Ok(authority) => authority,
+        Err(_) => return Ok(error_response(StatusCode::BAD_REQUEST, "Invalid authority")),
+    };
+
+    if !cert_cache.evict(&authority).await {
+        return Ok(error_response(
+            StatusCode::NOT_FOUND,
+            "No certificate cached for this authority",
+        ));
+    }
+
+    log::info!("Discarded the cached certificate of {}", authority);
+
+    Ok(Box::new(StatusCode::NO_CONTENT))
+}
+
+pub(super) fn create_routes(cert_cache: CertCache) -> BoxedFilter<(impl warp::Reply,)> {
+    let with_cert_cache = warp::any().map(move || cert_cache.clone());
+
+    let evict_route = warp::path::param::<String>()
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(with_cert_cache.clone())
+        .and_then(self::evict_certificate);
+
+    let cache_route = warp::path::end().and(
+        warp::get()
+            .and(with_cert_cache.clone())
+            .and_then(self::get_cache)
+            .or(warp::delete()
+                .and(with_cert_cache)
+                .and_then(self::flush_cache)),
+    );
+
+    evict_route.or(cache_route).boxed()
+}
@@ -1,4 +1,5 @@
 use super::get_error_response;
+use super::revision::{check_revision, with_expected_revision, with_revision};
 use crate::configuration::Configuration;
 use std::{convert::Infallible, sync::Arc};
 use tokio::sync::mpsc::Sender;
@@ -6,6 +7,16 @@ use warp::filters::BoxedFilter;
 use warp::http::StatusCode;
 use warp::Filter as RouteFilter;
 
+#[utoipa::path(
+    get,
+    path = "/api/custom-filters",
+    tag = "filters",
+    responses(
+        (status = 200, description = "Custom filters, one per line", body = String,
+            headers(("ETag" = String, description = "Revision of the configuration"))),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
 async fn get_custom_filters() -> Result<Box<dyn warp::Reply>, Infallible> {
     let configuration = match Configuration::read_from_home().await {
         Ok(configuration) => configuration,
@@ -17,11 +28,29 @@ async fn get_custom_filters() -> Result<Box<dyn warp::Reply>, Infallible> {
 
     let custom_filters = configuration.custom_filters.join("\n");
 
-    Ok(Box::new(warp::reply::json(&custom_filters)))
+    Ok(with_revision(
+        warp::reply::json(&custom_filters),
+        &configuration,
+    ))
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/custom-filters",
+    tag = "filters",
+    request_body(content = String, description = "Custom filters, one per line"),
+    params(("If-Match" = String, Header, description = "Revision the changes were made to")),
+    responses(
+        (status = 202, description = "Saved, applied on the next filters reload",
+            headers(("ETag" = String, description = "Revision of the saved configuration"))),
+        (status = 409, description = "The configuration was changed in the meantime, nothing was saved", body = RevisionConflict),
+        (status = 428, description = "Missing revision", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
 async fn put_custom_filters(
     custom_filters: String,
+    expected_revision: Option<u64>,
     configuration_updater_sender: Sender<Configuration>,
     configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
 ) -> Result<Box<dyn warp::Reply>, Infallible> {
@@ -35,6 +64,10 @@ async fn put_custom_filters(
         }
     };
 
+    if let Some(response) = check_revision(&configuration, expected_revision) {
+        return Ok(response);
+    }
+
     if let Err(err) = configuration.set_custom_filters(&custom_filters).await {
         log::error!("Failed to set customs filters: {err}");
         return Ok(Box::new(get_error_response(err)));
@@ -45,7 +78,7 @@ async fn put_custom_filters(
         .await
         .unwrap();
 
-    Ok(Box::new(StatusCode::ACCEPTED))
+    Ok(with_revision(StatusCode::ACCEPTED, &configuration))
 }
 
 pub(super) fn create_routes(
@@ -56,6 +89,7 @@ pub(super) fn create_routes(
         .and_then(self::get_custom_filters)
         .or(warp::put()
             .and(warp::body::json())
+            .and(with_expected_revision())
             .and(super::with_configuration_updater_sender(
                 configuration_updater_sender.clone(),
             ))
@@ -0,0 +1,111 @@
+use super::revision::{check_revision, with_expected_revision, with_revision};
+use super::{get_error_response, ApiError};
+use crate::blocker::parse_custom_scriptlets;
+use crate::configuration::Configuration;
+use std::{convert::Infallible, sync::Arc};
+use tokio::sync::mpsc::Sender;
+use warp::filters::BoxedFilter;
+use warp::http::{Response, StatusCode};
+use warp::Filter as RouteFilter;
+
+#[utoipa::path(
+    get,
+    path = "/api/custom-scriptlets",
+    tag = "filters",
+    responses(
+        (status = 200, description = "Custom scriptlets, in the format of uBlock Origin's scriptlets.js", body = String,
+            headers(("ETag" = String, description = "Revision of the configuration"))),
+        (status = 500, description = "Unable to read the configuration", body = ApiError)
+    )
+)]
+async fn get_custom_scriptlets() -> Result<Box<dyn warp::Reply>, Infallible> {
+    let configuration = match Configuration::read_from_home().await {
+        Ok(configuration) => configuration,
+        Err(err) => {
+            log::error!("Failed to get custom scriptlets: {err}");
+            return Ok(Box::new(get_error_response(err)));
+        }
+    };
+
+    Ok(with_revision(
+        warp::reply::json(&configuration.custom_scriptlets),
+        &configuration,
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/custom-scriptlets",
+    tag = "filters",
+    request_body(content = String, description = "Custom scriptlets, in the format of uBlock Origin's scriptlets.js"),
+    params(("If-Match" = String, Header, description = "Revision the changes were made to")),
+    responses(
+        (status = 202, description = "Saved, available to `+js()` rules once applied",
+            headers(("ETag" = String, description = "Revision of the saved configuration"))),
+        (status = 400, description = "Malformed scriptlet, or name already taken", body = ApiError),
+        (status = 409, description = "The configuration was changed in the meantime, nothing was saved", body = RevisionConflict),
+        (status = 428, description = "Missing revision", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
+async fn put_custom_scriptlets(
+    custom_scriptlets: String,
+    expected_revision: Option<u64>,
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    if let Err(err) = parse_custom_scriptlets(&custom_scriptlets) {
+        return Ok(Box::new(
+            Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(serde_json::to_string(&ApiError { error: err }).unwrap())
+                .unwrap(),
+        ));
+    }
+
+    let _guard = configuration_save_lock.lock().await;
+
+    let mut configuration = match Configuration::read_from_home().await {
+        Ok(configuration) => configuration,
+        Err(err) => {
+            log::error!("Failed to put custom scriptlets: {err}");
+            return Ok(Box::new(get_error_response(err)));
+        }
+    };
+
+    if let Some(response) = check_revision(&configuration, expected_revision) {
+        return Ok(response);
+    }
+
+    if let Err(err) = configuration
+        .set_custom_scriptlets(&custom_scriptlets)
+        .await
+    {
+        log::error!("Failed to set custom scriptlets: {err}");
+        return Ok(Box::new(get_error_response(err)));
+    }
+
+    configuration_updater_sender
+        .send(configuration.clone())
+        .await
+        .unwrap();
+
+    Ok(with_revision(StatusCode::ACCEPTED, &configuration))
+}
+
+pub(super) fn create_routes(
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::get()
+        .and_then(self::get_custom_scriptlets)
+        .or(warp::put()
+            .and(warp::body::json())
+            .and(with_expected_revision())
+            .and(super::with_configuration_updater_sender(
+                configuration_updater_sender,
+            ))
+            .and(super::with_configuration_save_lock(configuration_save_lock))
+            .and_then(self::put_custom_scriptlets))
+        .boxed()
+}
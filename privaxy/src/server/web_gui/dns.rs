@@ -0,0 +1,28 @@
+use crate::proxy::dns::DnsResolver;
+use std::convert::Infallible;
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::Filter as RouteFilter;
+
+#[utoipa::path(
+    delete,
+    path = "/api/dns/cache",
+    tag = "settings",
+    responses(
+        (status = 204, description = "Cached DNS answers discarded")
+    )
+)]
+async fn flush_cache(dns_resolver: DnsResolver) -> Result<impl warp::Reply, Infallible> {
+    dns_resolver.flush();
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(super) fn create_routes(dns_resolver: DnsResolver) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path("cache")
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(warp::any().map(move || dns_resolver.clone()))
+        .and_then(self::flush_cache)
+        .boxed()
+}
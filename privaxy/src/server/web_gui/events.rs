@@ -1,18 +1,42 @@
+use crate::blocker::BlockSource;
+use crate::event_log::EventLog;
 use chrono::{DateTime, Utc};
 use futures::{SinkExt, StreamExt};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
+use utoipa::ToSchema;
 use warp::ws::{Message, WebSocket};
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Event {
     pub now: DateTime<Utc>,
     pub method: String,
     pub url: String,
     pub is_request_blocked: bool,
+    /// Filter list, or what else, which blocked the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocked_by: Option<BlockSource>,
+    /// The connection was tunneled to an excluded host, without being filtered. `url` is
+    /// then the authority the client connected to.
+    pub is_tunneled: bool,
+    /// Country of the upstream server, when GeoIP is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
 }
 
-pub(super) async fn events(websocket: WebSocket, events_sender: broadcast::Sender<Event>) {
+/// Upgrades to a WebSocket streaming an `Event` for each request. When the event log is
+/// enabled, the latest events are sent first.
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    tag = "statistics",
+    responses((status = 101, description = "Switching to the WebSocket protocol"))
+)]
+pub(super) async fn events(
+    websocket: WebSocket,
+    events_sender: broadcast::Sender<Event>,
+    event_log: EventLog,
+) {
     let mut events_receiver = events_sender.subscribe();
 
     let (mut tx, mut rx) = websocket.split();
@@ -20,6 +44,14 @@ pub(super) async fn events(websocket: WebSocket, events_sender: broadcast::Sende
     // To handle Ping / Pong messages
     tokio::spawn(async move { while let Some(_message) = rx.next().await {} });
 
+    for event in event_log.recent() {
+        let message = Message::text(serde_json::to_string(&event).unwrap());
+
+        if let Err(_err) = tx.send(message).await {
+            return;
+        }
+    }
+
     while let Ok(event) = events_receiver.recv().await {
         let message = Message::text(serde_json::to_string(&event).unwrap());
 
@@ -0,0 +1,208 @@
+use super::revision::{check_revision, with_expected_revision, with_revision};
+use super::{error_response, get_error_response};
+use crate::blocker::matching_exceptions;
+use crate::configuration::{exception_filter_error, Configuration};
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, sync::Arc};
+use tokio::sync::mpsc::Sender;
+use utoipa::ToSchema;
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::Filter as RouteFilter;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct InvalidExceptionFilter {
+    rule: String,
+    reason: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct InvalidExceptionFilters {
+    error: String,
+    rules: Vec<InvalidExceptionFilter>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub(crate) struct ExceptionFilterTest {
+    /// URL of the request.
+    url: String,
+    /// URL of the page making the request, `url` itself when left out.
+    source_url: Option<String>,
+    /// `document`, `script`, `image`, `xmlhttprequest`... `document` when left out.
+    request_type: Option<String>,
+    /// Rules to test, the saved ones when left out.
+    rules: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct ExceptionFilterTestResult {
+    /// Network exception rules applying to the request.
+    matching_rules: Vec<String>,
+}
+
+/// Trimmed rules, without blank lines.
+fn clean_rules(rules: Vec<String>) -> Vec<String> {
+    rules
+        .iter()
+        .map(|rule| rule.trim())
+        .filter(|rule| !rule.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/exception-filters",
+    tag = "filters",
+    responses(
+        (status = 200, description = "Exception rules, applied in every profile", body = [String],
+            headers(("ETag" = String, description = "Revision of the configuration"))),
+        (status = 500, description = "Unable to read the configuration", body = ApiError)
+    )
+)]
+async fn get_exception_filters() -> Result<Box<dyn warp::Reply>, Infallible> {
+    let configuration = match Configuration::read_from_home().await {
+        Ok(configuration) => configuration,
+        Err(err) => {
+            log::error!("Failed to get exception filters: {err}");
+            return Ok(Box::new(get_error_response(err)));
+        }
+    };
+
+    Ok(with_revision(
+        warp::reply::json(&configuration.exception_filters),
+        &configuration,
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/exception-filters",
+    tag = "filters",
+    request_body(content = [String], description = "Exception rules, `@@` for requests and `#@#` for elements"),
+    params(("If-Match" = String, Header, description = "Revision the changes were made to")),
+    responses(
+        (status = 202, description = "Saved, applied on the next filters reload",
+            headers(("ETag" = String, description = "Revision of the saved configuration"))),
+        (status = 400, description = "Some rules are not valid exceptions, nothing was saved", body = InvalidExceptionFilters),
+        (status = 409, description = "The configuration was changed in the meantime, nothing was saved", body = RevisionConflict),
+        (status = 428, description = "Missing revision", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
+async fn put_exception_filters(
+    exception_filters: Vec<String>,
+    expected_revision: Option<u64>,
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let exception_filters = clean_rules(exception_filters);
+
+    let invalid_rules = exception_filters
+        .iter()
+        .filter_map(|rule| {
+            exception_filter_error(rule).map(|reason| InvalidExceptionFilter {
+                rule: rule.clone(),
+                reason: reason.to_string(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if !invalid_rules.is_empty() {
+        return Ok(Box::new(warp::reply::with_status(
+            warp::reply::json(&InvalidExceptionFilters {
+                error: format!("{} invalid exception rules", invalid_rules.len()),
+                rules: invalid_rules,
+            }),
+            StatusCode::BAD_REQUEST,
+        )));
+    }
+
+    let _guard = configuration_save_lock.lock().await;
+
+    let mut configuration = match Configuration::read_from_home().await {
+        Ok(configuration) => configuration,
+        Err(err) => {
+            log::error!("Failed to put exception filters: {err}");
+            return Ok(Box::new(get_error_response(err)));
+        }
+    };
+
+    if let Some(response) = check_revision(&configuration, expected_revision) {
+        return Ok(response);
+    }
+
+    if let Err(err) = configuration.set_exception_filters(exception_filters).await {
+        log::error!("Failed to set exception filters: {err}");
+        return Ok(Box::new(get_error_response(err)));
+    }
+
+    configuration_updater_sender
+        .send(configuration.clone())
+        .await
+        .unwrap();
+
+    Ok(with_revision(StatusCode::ACCEPTED, &configuration))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/exception-filters/test",
+    tag = "filters",
+    request_body = ExceptionFilterTest,
+    responses(
+        (status = 200, description = "Exception rules applying to the request", body = ExceptionFilterTestResult),
+        (status = 400, description = "Invalid URL", body = ApiError),
+        (status = 500, description = "Unable to read the configuration", body = ApiError)
+    )
+)]
+async fn test_exception_filters(
+    test: ExceptionFilterTest,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let rules = match test.rules {
+        Some(rules) => clean_rules(rules),
+        None => match Configuration::read_from_home().await {
+            Ok(configuration) => configuration.exception_filters,
+            Err(err) => {
+                log::error!("Failed to get exception filters: {err}");
+                return Ok(Box::new(get_error_response(err)));
+            }
+        },
+    };
+
+    let source_url = test.source_url.as_deref().unwrap_or(&test.url);
+    let request_type = test.request_type.as_deref().unwrap_or("document");
+
+    match matching_exceptions(&rules, &test.url, source_url, request_type) {
+        Ok(matching_rules) => Ok(Box::new(warp::reply::json(&ExceptionFilterTestResult {
+            matching_rules,
+        }))),
+        Err(_err) => Ok(error_response(StatusCode::BAD_REQUEST, "Invalid URL")),
+    }
+}
+
+pub(super) fn create_routes(
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+) -> BoxedFilter<(impl warp::Reply,)> {
+    let test_route = warp::path("test")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then(self::test_exception_filters);
+
+    let exception_filters_route = warp::path::end().and(
+        warp::get()
+            .and_then(self::get_exception_filters)
+            .or(warp::put()
+                .and(warp::body::json())
+                .and(with_expected_revision())
+                .and(super::with_configuration_updater_sender(
+                    configuration_updater_sender,
+                ))
+                .and(super::with_configuration_save_lock(configuration_save_lock))
+                .and_then(self::put_exception_filters)),
+    );
+
+    test_route.or(exception_filters_route).boxed()
+}
@@ -1,11 +1,52 @@
-use super::get_error_response;
-use crate::{configuration::Configuration, proxy::exclusions::LocalExclusionStore};
+use super::revision::{check_revision, with_expected_revision, with_revision};
+use super::{error_response, get_error_response};
+use crate::configuration::Configuration;
+use crate::proxy::client_certificates::ClientCertificateStore;
+use crate::proxy::exclusions::{LocalExclusionStore, TemporaryExclusionStore};
+use http::uri::Authority;
+use serde::Deserialize;
+use std::time::Duration;
 use std::{convert::Infallible, sync::Arc};
 use tokio::sync::mpsc::Sender;
+use utoipa::ToSchema;
 use warp::filters::BoxedFilter;
 use warp::http::StatusCode;
 use warp::Filter as RouteFilter;
 
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, ToSchema)]
+/// For how long blocking is disabled on a site
+pub enum SiteExclusionDuration {
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "24h")]
+    OneDay,
+    /// The site is added to the exclusions, its connections are tunneled without being filtered.
+    #[serde(rename = "permanent")]
+    Permanent,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SiteExclusionRequest {
+    pub host: String,
+    pub duration: SiteExclusionDuration,
+}
+
+/// Host names only, without port.
+pub(crate) fn is_valid_host(host: &str) -> bool {
+    host.parse::<Authority>()
+        .is_ok_and(|authority| authority.port().is_none())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/exclusions",
+    tag = "filters",
+    responses(
+        (status = 200, description = "Excluded hosts, one per line", body = String,
+            headers(("ETag" = String, description = "Revision of the configuration"))),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
 async fn get_exclusions() -> Result<Box<dyn warp::Reply>, Infallible> {
     let configuration = match Configuration::read_from_home().await {
         Ok(configuration) => configuration,
@@ -15,13 +56,31 @@ async fn get_exclusions() -> Result<Box<dyn warp::Reply>, Infallible> {
         }
     };
 
-    let exclusions = Vec::from_iter(configuration.exclusions.into_iter()).join("\n");
+    let exclusions = Vec::from_iter(configuration.exclusions.iter().cloned()).join("\n");
 
-    Ok(Box::new(warp::reply::json(&exclusions)))
+    Ok(with_revision(
+        warp::reply::json(&exclusions),
+        &configuration,
+    ))
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/exclusions",
+    tag = "filters",
+    request_body(content = String, description = "Excluded hosts, one per line"),
+    params(("If-Match" = String, Header, description = "Revision the changes were made to")),
+    responses(
+        (status = 202, description = "Saved, applied on the next filters reload",
+            headers(("ETag" = String, description = "Revision of the saved configuration"))),
+        (status = 409, description = "The configuration was changed in the meantime, nothing was saved", body = RevisionConflict),
+        (status = 428, description = "Missing revision", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
 async fn put_exclusions(
     exclusions: String,
+    expected_revision: Option<u64>,
     configuration_updater_sender: Sender<Configuration>,
     configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
     local_exclusions_store: LocalExclusionStore,
@@ -36,6 +95,10 @@ async fn put_exclusions(
         }
     };
 
+    if let Some(response) = check_revision(&configuration, expected_revision) {
+        return Ok(response);
+    }
+
     if let Err(err) = configuration
         .set_exclusions(&exclusions, local_exclusions_store)
         .await
@@ -48,18 +111,167 @@ async fn put_exclusions(
         .await
         .unwrap();
 
-    Ok(Box::new(StatusCode::ACCEPTED))
+    Ok(with_revision(StatusCode::ACCEPTED, &configuration))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/exclusions/client-certificates",
+    tag = "filters",
+    responses(
+        (status = 200, description = "Hosts which requested a client certificate and are tunneled instead of being filtered", body = [ClientCertificateHost])
+    )
+)]
+async fn get_client_certificate_hosts(
+    client_certificate_store: ClientCertificateStore,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&client_certificate_store.hosts()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/exclusions/sites",
+    tag = "filters",
+    responses(
+        (status = 200, description = "Sites on which blocking is disabled for a while", body = [SiteExclusion])
+    )
+)]
+async fn get_site_exclusions(
+    temporary_exclusion_store: TemporaryExclusionStore,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&temporary_exclusion_store.sites()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/exclusions/sites",
+    tag = "filters",
+    request_body = SiteExclusionRequest,
+    responses(
+        (status = 204, description = "Blocking disabled on the site for the given duration"),
+        (status = 202, description = "Site added to the exclusions"),
+        (status = 400, description = "Invalid host", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
+async fn post_site_exclusion(
+    request: SiteExclusionRequest,
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    local_exclusions_store: LocalExclusionStore,
+    temporary_exclusion_store: TemporaryExclusionStore,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let host = request.host.trim();
+    if !is_valid_host(host) {
+        return Ok(error_response(StatusCode::BAD_REQUEST, "Invalid host"));
+    }
+
+    let duration = match request.duration {
+        SiteExclusionDuration::OneHour => Duration::from_secs(60 * 60),
+        SiteExclusionDuration::OneDay => Duration::from_secs(60 * 60 * 24),
+        SiteExclusionDuration::Permanent => {
+            let _guard = configuration_save_lock.lock().await;
+
+            let mut configuration = match Configuration::read_from_home().await {
+                Ok(configuration) => configuration,
+                Err(err) => {
+                    log::error!("Failed to add exclusion: {err}");
+                    return Ok(Box::new(get_error_response(err)));
+                }
+            };
+
+            if let Err(err) = configuration
+                .add_exclusion(host, local_exclusions_store)
+                .await
+            {
+                return Ok(Box::new(get_error_response(err)));
+            }
+
+            configuration_updater_sender
+                .send(configuration.clone())
+                .await
+                .unwrap();
+
+            return Ok(Box::new(StatusCode::ACCEPTED));
+        }
+    };
+
+    temporary_exclusion_store.exclude_site(host, duration);
+
+    Ok(Box::new(StatusCode::NO_CONTENT))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/exclusions/sites/{host}",
+    tag = "filters",
+    params(("host" = String, Path, description = "Host on which blocking was disabled")),
+    responses(
+        (status = 204, description = "Blocking enabled again on the site"),
+        (status = 404, description = "Blocking isn't disabled on this site", body = ApiError)
+    )
+)]
+async fn delete_site_exclusion(
+    host: String,
+    temporary_exclusion_store: TemporaryExclusionStore,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    if !temporary_exclusion_store.remove_site(&host) {
+        return Ok(error_response(
+            StatusCode::NOT_FOUND,
+            "Blocking isn't disabled on this site",
+        ));
+    }
+
+    Ok(Box::new(StatusCode::NO_CONTENT))
 }
 
 pub fn create_routes(
     configuration_updater_sender: Sender<Configuration>,
     configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
     local_exclusions_store: LocalExclusionStore,
+    temporary_exclusion_store: TemporaryExclusionStore,
+    client_certificate_store: ClientCertificateStore,
 ) -> BoxedFilter<(impl warp::Reply,)> {
-    warp::get()
-        .and_then(self::get_exclusions)
+    let with_temporary_exclusion_store = warp::any().map(move || temporary_exclusion_store.clone());
+
+    let sites_route = warp::path("sites").and(
+        warp::path::end()
+            .and(warp::get())
+            .and(with_temporary_exclusion_store.clone())
+            .and_then(self::get_site_exclusions)
+            .or(warp::path::end()
+                .and(warp::post())
+                .and(warp::body::json())
+                .and(super::with_configuration_updater_sender(
+                    configuration_updater_sender.clone(),
+                ))
+                .and(super::with_configuration_save_lock(
+                    configuration_save_lock.clone(),
+                ))
+                .and(super::with_local_exclusions_store(
+                    local_exclusions_store.clone(),
+                ))
+                .and(with_temporary_exclusion_store.clone())
+                .and_then(self::post_site_exclusion))
+            .or(warp::path::param::<String>()
+                .and(warp::path::end())
+                .and(warp::delete())
+                .and(with_temporary_exclusion_store)
+                .and_then(self::delete_site_exclusion)),
+    );
+
+    sites_route
+        .or(warp::get()
+            .and(warp::path("client-certificates"))
+            .and(warp::path::end())
+            .and(super::with_client_certificate_store(
+                client_certificate_store,
+            ))
+            .and_then(self::get_client_certificate_hosts))
+        .or(warp::get().and_then(self::get_exclusions))
         .or(warp::put()
             .and(warp::body::json())
+            .and(with_expected_revision())
             .and(super::with_configuration_updater_sender(
                 configuration_updater_sender.clone(),
             ))
@@ -0,0 +1,140 @@
+use super::{error_response, get_error_response};
+use crate::blocker::build_engine;
+use crate::configuration::{get_filters_content, save_bundle, Configuration, ConfigurationError};
+use std::{convert::Infallible, sync::Arc};
+use tokio::sync::mpsc::Sender;
+use warp::filters::BoxedFilter;
+use warp::http::{Response, StatusCode};
+use warp::hyper::body::Bytes;
+use warp::Filter as RouteFilter;
+
+/// Well above the few megabytes compiled lists take, even with every list enabled.
+const MAX_BUNDLE_BYTES: u64 = 128 * 1024 * 1024;
+
+#[utoipa::path(
+    get,
+    path = "/api/filters/bundle",
+    tag = "filters",
+    responses(
+        (status = 200, description = "Engine compiled from the enabled filter lists, custom rules excluded, to be imported by instances running the same version", content_type = "application/octet-stream"),
+        (status = 500, description = "Unable to read the configuration or to compile the lists", body = ApiError)
+    )
+)]
+async fn get_bundle(http_client: reqwest::Client) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let mut configuration = match Configuration::read_from_home().await {
+        Ok(configuration) => configuration,
+        Err(err) => {
+            log::error!("Failed to export filter bundle: {err}");
+            return Ok(Box::new(get_error_response(err)));
+        }
+    };
+
+    let filters = get_filters_content(&mut configuration, &http_client).await;
+    let bundle =
+        tokio::task::spawn_blocking(move || build_engine(&filters.lists).serialize_raw()).await;
+
+    match bundle {
+        Ok(Ok(bundle)) => Ok(Box::new(
+            Response::builder()
+                .header(http::header::CONTENT_TYPE, "application/octet-stream")
+                .header(
+                    http::header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"privaxy-filters.dat\"",
+                )
+                .body(bundle)
+                .unwrap(),
+        )),
+        Ok(Err(err)) => {
+            log::error!("Failed to serialize filter bundle: {:?}", err);
+            Ok(error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Unable to serialize the compiled lists",
+            ))
+        }
+        Err(err) => {
+            log::error!("Failed to compile filter bundle: {err}");
+            Ok(error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Unable to compile the lists",
+            ))
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/filters/bundle",
+    tag = "filters",
+    request_body(content = Vec<u8>, description = "Bundle exported by another instance", content_type = "application/octet-stream"),
+    responses(
+        (status = 204, description = "Bundle saved and loaded for the default profile in place of its filter lists"),
+        (status = 400, description = "Not a bundle, or exported by another version", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
+async fn put_bundle(
+    bundle: Bytes,
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    match save_bundle(bundle.to_vec()).await {
+        Ok(()) => {}
+        Err(ConfigurationError::FilterError(err)) => {
+            log::warn!("Rejected filter bundle: {err}");
+            return Ok(error_response(StatusCode::BAD_REQUEST, &err));
+        }
+        Err(err) => {
+            log::error!("Failed to save filter bundle: {err}");
+            return Ok(Box::new(get_error_response(err)));
+        }
+    }
+
+    let _guard = configuration_save_lock.lock().await;
+
+    let mut configuration = match Configuration::read_from_home().await {
+        Ok(configuration) => configuration,
+        Err(err) => {
+            log::error!("Failed to read configuration: {err}");
+            return Ok(Box::new(get_error_response(err)));
+        }
+    };
+
+    if !configuration.filter_bundle.enabled {
+        configuration.filter_bundle.enabled = true;
+        if let Err(err) = configuration.save().await {
+            log::error!("Failed to save configuration: {err}");
+            return Ok(Box::new(get_error_response(err)));
+        }
+    }
+
+    if let Err(err) = configuration_updater_sender.send(configuration).await {
+        log::error!("Failed to send updated configuration: {err}");
+        return Ok(Box::new(get_error_response(err)));
+    }
+
+    log::info!("Imported filter bundle of {} bytes", bundle.len());
+
+    Ok(Box::new(StatusCode::NO_CONTENT))
+}
+
+pub(super) fn create_routes(
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    http_client: reqwest::Client,
+) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path::end()
+        .and(
+            warp::get()
+                .and(super::with_http_client(http_client))
+                .and_then(self::get_bundle)
+                .or(warp::put()
+                    .and(warp::body::content_length_limit(MAX_BUNDLE_BYTES))
+                    .and(warp::body::bytes())
+                    .and(super::with_configuration_updater_sender(
+                        configuration_updater_sender,
+                    ))
+                    .and(super::with_configuration_save_lock(configuration_save_lock))
+                    .and_then(self::put_bundle)),
+        )
+        .boxed()
+}
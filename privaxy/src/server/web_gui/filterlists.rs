@@ -1,9 +1,17 @@
-use filterlists_api;
 use warp::Filter as RouteFilter;
 
 use std::convert::Infallible;
 use warp::filters::BoxedFilter;
 
+#[utoipa::path(
+    get,
+    path = "/api/filterlists/list",
+    tag = "filterlists",
+    responses(
+        (status = 200, description = "Filter lists known to FilterLists, as returned by the FilterLists API", body = [Object]),
+        (status = 500, description = "Unable to query FilterLists", body = ApiError)
+    )
+)]
 async fn get_filters() -> Result<Box<dyn warp::Reply>, Infallible> {
     log::debug!("Getting filters");
     match filterlists_api::get_filters().await {
@@ -15,6 +23,16 @@ async fn get_filters() -> Result<Box<dyn warp::Reply>, Infallible> {
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/filterlists/list/{id}",
+    tag = "filterlists",
+    params(("id" = u32, Path, description = "FilterLists identifier of the list")),
+    responses(
+        (status = 200, description = "Details of a filter list, as returned by the FilterLists API", body = Object),
+        (status = 500, description = "Unable to query FilterLists", body = ApiError)
+    )
+)]
 async fn get_filter(id: u32) -> Result<Box<dyn warp::Reply>, Infallible> {
     log::debug!("Getting filter {id}");
     match filterlists_api::get_filter_information(filterlists_api::FilterArgs::U32(id)).await {
@@ -26,6 +44,15 @@ async fn get_filter(id: u32) -> Result<Box<dyn warp::Reply>, Infallible> {
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/filterlists/syntaxes",
+    tag = "filterlists",
+    responses(
+        (status = 200, description = "Filter syntaxes, as returned by the FilterLists API", body = [Object]),
+        (status = 500, description = "Unable to query FilterLists", body = ApiError)
+    )
+)]
 async fn get_syntaxes() -> Result<Box<dyn warp::Reply>, Infallible> {
     log::debug!("Getting syntaxes");
     match filterlists_api::get_syntaxes().await {
@@ -34,6 +61,15 @@ async fn get_syntaxes() -> Result<Box<dyn warp::Reply>, Infallible> {
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/filterlists/languages",
+    tag = "filterlists",
+    responses(
+        (status = 200, description = "Languages, as returned by the FilterLists API", body = [Object]),
+        (status = 500, description = "Unable to query FilterLists", body = ApiError)
+    )
+)]
 async fn get_languages() -> Result<Box<dyn warp::Reply>, Infallible> {
     log::debug!("Getting languages");
     match filterlists_api::get_languages().await {
@@ -42,6 +78,15 @@ async fn get_languages() -> Result<Box<dyn warp::Reply>, Infallible> {
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/filterlists/tags",
+    tag = "filterlists",
+    responses(
+        (status = 200, description = "Tags, as returned by the FilterLists API", body = [Object]),
+        (status = 500, description = "Unable to query FilterLists", body = ApiError)
+    )
+)]
 async fn get_tags() -> Result<Box<dyn warp::Reply>, Infallible> {
     log::debug!("Getting tags");
     match filterlists_api::get_tags().await {
@@ -50,6 +95,15 @@ async fn get_tags() -> Result<Box<dyn warp::Reply>, Infallible> {
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/filterlists/licenses",
+    tag = "filterlists",
+    responses(
+        (status = 200, description = "Licenses, as returned by the FilterLists API", body = [Object]),
+        (status = 500, description = "Unable to query FilterLists", body = ApiError)
+    )
+)]
 async fn get_licenses() -> Result<Box<dyn warp::Reply>, Infallible> {
     log::debug!("Getting licenses");
     match filterlists_api::get_licenses().await {
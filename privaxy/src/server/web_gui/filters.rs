@@ -1,5 +1,7 @@
 use super::get_error_response;
+use super::revision::{check_revision, with_expected_revision, with_revision};
 use crate::configuration::{calc_filter_filename, Configuration, Filter, FilterGroup};
+use crate::filter_health::FilterHealthStore;
 use crate::web_gui::ApiError;
 use serde::Deserialize;
 use serde_with::{serde_as, DisplayFromStr};
@@ -7,78 +9,127 @@ use serde_with::{serde_as, DisplayFromStr};
 use std::{convert::Infallible, sync::Arc};
 use tokio::sync::mpsc::Sender;
 use url::Url;
+use utoipa::ToSchema;
 use warp::http::Response;
 use warp::Filter as RouteFilter;
 
 use warp::filters::BoxedFilter;
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct FilterStatusChangeRequest {
     enabled: bool,
     file_name: String,
 }
 
 #[serde_as]
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq, ToSchema)]
 pub struct FilterRequest {
     pub enabled: bool,
     pub title: String,
     pub group: FilterGroup,
     #[serde_as(as = "DisplayFromStr")]
+    #[schema(value_type = String)]
     pub url: Url,
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/filters",
+    tag = "filters",
+    request_body = [FilterStatusChangeRequest],
+    params(("If-Match" = String, Header, description = "Revision the changes were made to")),
+    responses(
+        (status = 202, description = "Filter lists enabled or disabled",
+            headers(("ETag" = String, description = "Revision of the saved configuration"))),
+        (status = 409, description = "The configuration was changed in the meantime, nothing was saved", body = RevisionConflict),
+        (status = 428, description = "Missing revision", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
 async fn change_filter_status(
     filter_status_change_request: Vec<FilterStatusChangeRequest>,
+    expected_revision: Option<u64>,
     configuration_updater_sender: Sender<Configuration>,
     configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
-) -> Result<impl warp::Reply, Infallible> {
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let guard = configuration_save_lock.lock().await;
+
     let mut configuration = match Configuration::read_from_home().await {
         Ok(configuration) => configuration,
         Err(err) => {
             log::error!("Failed to change filter status: {err}");
-            return Ok(get_error_response(err));
+            return Ok(Box::new(get_error_response(err)));
         }
     };
 
+    if let Some(response) = check_revision(&configuration, expected_revision) {
+        return Ok(response);
+    }
+
     for filter in filter_status_change_request {
         if let Err(err) = configuration
             .set_filter_enabled_status(&filter.file_name, filter.enabled)
             .await
         {
             log::error!("Failed to change filter status: {err}");
-            return Ok(get_error_response(err));
+            return Ok(Box::new(get_error_response(err)));
         }
     }
-    let guard = configuration_save_lock.lock().await;
 
     configuration_updater_sender
         .send(configuration.clone())
         .await
         .unwrap();
     drop(guard);
-    Ok(Response::builder()
-        .status(http::StatusCode::ACCEPTED)
-        .body("".to_string())
-        .unwrap())
+    Ok(with_revision(
+        Response::builder()
+            .status(http::StatusCode::ACCEPTED)
+            .body("".to_string())
+            .unwrap(),
+        &configuration,
+    ))
 }
 
-async fn get_filters_configuration() -> Result<impl warp::Reply, Infallible> {
+#[utoipa::path(
+    get,
+    path = "/api/filters",
+    tag = "filters",
+    responses(
+        (status = 200, description = "Configured filter lists", body = [Filter],
+            headers(("ETag" = String, description = "Revision of the configuration"))),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
+async fn get_filters_configuration() -> Result<Box<dyn warp::Reply>, Infallible> {
     let configuration = match Configuration::read_from_home().await {
         Ok(configuration) => configuration,
         Err(err) => {
             log::error!("Failed to get filters configuration: {err}");
-            return Ok(get_error_response(err));
+            return Ok(Box::new(get_error_response(err)));
         }
     };
 
-    let filters = configuration.filters;
+    let filters = &configuration.filters;
     log::debug!("Filters: {:?}", filters);
-    Ok(Response::builder()
-        .header(http::header::CONTENT_TYPE, "application/json")
-        .body(serde_json::to_string(&filters).unwrap())
-        .unwrap())
+    Ok(with_revision(
+        Response::builder()
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(filters).unwrap())
+            .unwrap(),
+        &configuration,
+    ))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/filters",
+    tag = "filters",
+    request_body = FilterRequest,
+    responses(
+        (status = 201, description = "Filter list added"),
+        (status = 409, description = "Filter list already added", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
 async fn add_filter(
     filter_request: FilterRequest,
     http_client: reqwest::Client,
@@ -119,7 +170,7 @@ async fn add_filter(
         url: filter_url,
         title: filter_request.title.clone(),
         group: filter_request.group,
-        file_name: calc_filter_filename(&filter_request.url.to_string()),
+        file_name: calc_filter_filename(filter_request.url.as_ref()),
     };
 
     match configuration
@@ -159,6 +210,16 @@ async fn add_filter(
         .unwrap())
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/filters",
+    tag = "filters",
+    request_body = FilterRequest,
+    responses(
+        (status = 204, description = "Filter list removed"),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
 async fn delete_filter(
     filter_request: FilterRequest,
     configuration_updater_sender: Sender<Configuration>,
@@ -197,15 +258,91 @@ async fn delete_filter(
         .unwrap())
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/filters/update",
+    tag = "filters",
+    responses(
+        (status = 204, description = "Enabled filter lists downloaded again and applied"),
+        (status = 500, description = "Unable to download filter lists", body = ApiError)
+    )
+)]
+async fn update_filters(
+    http_client: reqwest::Client,
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+) -> Result<impl warp::Reply, Infallible> {
+    // Held until the configuration is sent, so that changes saved during the download aren't
+    // overwritten by the configuration read before it.
+    let _guard = configuration_save_lock.lock().await;
+
+    let mut configuration = match Configuration::read_from_home().await {
+        Ok(configuration) => configuration,
+        Err(err) => {
+            log::error!("Failed to read configuration: {err}");
+            return Ok(get_error_response(err));
+        }
+    };
+
+    if let Err(err) = configuration.update_filters(http_client).await {
+        log::error!("Failed to update filters: {err}");
+        return Ok(get_error_response(err));
+    }
+
+    // The updater reads the downloaded filters back when applying the configuration.
+    if let Err(err) = configuration_updater_sender.send(configuration).await {
+        log::error!("Failed to send updated configuration: {err}");
+        return Ok(get_error_response(err));
+    }
+
+    log::info!("Updated filters on request");
+
+    Ok(Response::builder()
+        .status(http::StatusCode::NO_CONTENT)
+        .body("".to_string())
+        .unwrap())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/filters/health",
+    tag = "filters",
+    responses(
+        (status = 200, description = "Outcome of the last checks of the filter lists in use, empty until the first check", body = [FilterHealth])
+    )
+)]
+async fn get_filters_health(
+    filter_health_store: FilterHealthStore,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&filter_health_store.list()))
+}
+
 pub(super) fn create_routes(
     configuration_updater_sender: Sender<Configuration>,
     configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
     http_client: reqwest::Client,
+    filter_health_store: FilterHealthStore,
 ) -> BoxedFilter<(impl warp::Reply,)> {
-    warp::get()
-        .and_then(self::get_filters_configuration)
+    warp::path("health")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::any().map(move || filter_health_store.clone()))
+        .and_then(self::get_filters_health)
+        .or(warp::path("update")
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(super::with_http_client(http_client.clone()))
+            .and(super::with_configuration_updater_sender(
+                configuration_updater_sender.clone(),
+            ))
+            .and(super::with_configuration_save_lock(
+                configuration_save_lock.clone(),
+            ))
+            .and_then(self::update_filters))
+        .or(warp::get().and_then(self::get_filters_configuration))
         .or(warp::put()
             .and(warp::body::json())
+            .and(with_expected_revision())
             .and(super::with_configuration_updater_sender(
                 configuration_updater_sender.clone(),
             ))
@@ -0,0 +1,71 @@
+use super::ApiError;
+use crate::logging::{self, LogDirectives};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use utoipa::ToSchema;
+use warp::filters::BoxedFilter;
+use warp::http::{Response, StatusCode};
+use warp::Filter as RouteFilter;
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct LogLevels {
+    /// Levels in the `RUST_LOG` syntax, e.g. `privaxy=debug,hyper=info`.
+    directives: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/logging",
+    tag = "settings",
+    responses((status = 200, description = "Current log levels", body = LogLevels))
+)]
+pub async fn get_log_levels() -> Result<impl warp::Reply, Infallible> {
+    let directives = logging::directives()
+        .map(|directives| directives.to_string())
+        .unwrap_or_default();
+
+    Ok(warp::reply::json(&LogLevels { directives }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/logging",
+    tag = "settings",
+    request_body = LogLevels,
+    responses(
+        (status = 204, description = "Log levels changed"),
+        (status = 400, description = "Invalid directives", body = ApiError)
+    )
+)]
+pub async fn put_log_levels(log_levels: LogLevels) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let directives = match log_levels.directives.parse::<LogDirectives>() {
+        Ok(directives) => directives,
+        Err(err) => {
+            return Ok(Box::new(
+                Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(
+                        serde_json::to_string(&ApiError {
+                            error: err.to_string(),
+                        })
+                        .unwrap(),
+                    )
+                    .unwrap(),
+            ))
+        }
+    };
+
+    log::info!("Setting log levels to {}", directives);
+    logging::set_directives(directives);
+
+    Ok(Box::new(StatusCode::NO_CONTENT))
+}
+
+pub(super) fn create_routes() -> BoxedFilter<(impl warp::Reply,)> {
+    warp::get()
+        .and_then(self::get_log_levels)
+        .or(warp::put()
+            .and(warp::body::json())
+            .and_then(self::put_log_levels))
+        .boxed()
+}
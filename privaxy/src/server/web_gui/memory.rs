@@ -0,0 +1,73 @@
+use crate::allocator;
+use std::convert::Infallible;
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::Filter;
+
+fn allocator_error_response(error: String) -> Box<dyn warp::Reply> {
+    let status = if cfg!(feature = "jemalloc") {
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else {
+        StatusCode::NOT_IMPLEMENTED
+    };
+
+    super::error_response(status, &error)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/debug/memory",
+    tag = "debugging",
+    responses(
+        (status = 200, description = "Statistics of the allocator", body = AllocatorStatistics),
+        (status = 501, description = "Privaxy was built without the jemalloc feature", body = ApiError)
+    )
+)]
+pub async fn get_memory() -> Result<Box<dyn warp::Reply>, Infallible> {
+    Ok(match allocator::statistics() {
+        Ok(statistics) => Box::new(warp::reply::json(&statistics)),
+        Err(err) => allocator_error_response(err),
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/debug/memory/purge",
+    tag = "debugging",
+    responses(
+        (status = 200, description = "Unused memory returned to the operating system, statistics after the purge", body = AllocatorStatistics),
+        (status = 501, description = "Privaxy was built without the jemalloc feature", body = ApiError)
+    )
+)]
+pub async fn purge_memory() -> Result<Box<dyn warp::Reply>, Infallible> {
+    let result = tokio::task::spawn_blocking(|| {
+        allocator::purge()?;
+        allocator::statistics()
+    })
+    .await
+    .unwrap();
+
+    Ok(match result {
+        Ok(statistics) => {
+            log::info!(
+                "Purged allocator arenas, {} bytes resident",
+                statistics.resident
+            );
+            Box::new(warp::reply::json(&statistics))
+        }
+        Err(err) => allocator_error_response(err),
+    })
+}
+
+pub(super) fn create_routes() -> BoxedFilter<(impl warp::Reply,)> {
+    let purge_route = warp::path("purge")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and_then(self::purge_memory);
+
+    let memory_route = warp::path::end()
+        .and(warp::get())
+        .and_then(self::get_memory);
+
+    purge_route.or(memory_route).boxed()
+}
@@ -0,0 +1,23 @@
+use crate::statistics::Statistics;
+use std::convert::Infallible;
+use warp::filters::BoxedFilter;
+use warp::Filter;
+
+#[utoipa::path(
+    get,
+    path = "/api/metrics/history",
+    tag = "statistics",
+    responses((status = 200, description = "Requests, blocked requests and latency over the last 24 hours", body = SerializableHistory))
+)]
+async fn get_history(statistics: Statistics) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&statistics.get_history()))
+}
+
+pub(super) fn create_routes(statistics: Statistics) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path("history")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::any().map(move || statistics.clone()))
+        .and_then(self::get_history)
+        .boxed()
+}
@@ -1,65 +1,138 @@
-use crate::proxy::exclusions::LocalExclusionStore;
+use crate::cert::CertCache;
+use crate::event_log::EventLog;
+use crate::filter_health::FilterHealthStore;
+use crate::proxy::capture::CaptureStore;
+use crate::proxy::client_certificates::ClientCertificateStore;
+use crate::proxy::dns::DnsResolver;
+use crate::proxy::exclusions::{LocalExclusionStore, TemporaryExclusionStore};
 use crate::statistics::Statistics;
+#[cfg(feature = "gui")]
 use crate::WEBAPP_FRONTEND_DIR;
 use crate::{blocker::BlockingDisabledStore, configuration::Configuration};
 use serde::Serialize;
 use std::sync::Arc;
 use tokio::sync::Notify;
 use tokio::sync::{broadcast, mpsc::Sender};
+use utoipa::ToSchema;
 use warp::filters::BoxedFilter;
 use warp::http::Response;
 use warp::path::Tail;
 use warp::{http, Filter, Reply};
 
 pub(crate) mod blocking_enabled;
+mod capture;
+mod certificates;
 pub(crate) mod custom_filters;
+mod custom_scriptlets;
+mod dns;
 pub(crate) mod events;
+mod exception_filters;
 pub(crate) mod exclusions;
+mod filter_bundle;
 mod filterlists;
 pub(crate) mod filters;
+mod logging;
+mod memory;
+mod metrics;
+mod openapi;
+mod origin;
+pub(crate) mod pac;
+mod profiles;
+mod readiness;
+mod reload;
+mod revision;
 pub(crate) mod settings;
 pub(crate) mod statistics;
 
-#[derive(Debug, Serialize)]
+/// Sent with every response of the web server, unless a route sets its own. Inline scripts are
+/// allowed for the loader of the web frontend, and inline styles for its components.
+const CONTENT_SECURITY_POLICY: &str = "default-src 'self'; \
+    script-src 'self' 'unsafe-inline' 'wasm-unsafe-eval'; style-src 'self' 'unsafe-inline'; \
+    img-src 'self' data:; frame-ancestors 'none'; base-uri 'self'; form-action 'self'";
+
+#[derive(Debug, Serialize, ToSchema)]
 pub(crate) struct ApiError {
     error: String,
 }
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn get_frontend(
     events_sender: broadcast::Sender<events::Event>,
+    event_log: EventLog,
     statistics: Statistics,
     blocking_disabled_store: &BlockingDisabledStore,
     configuration_updater_sender: &Sender<Configuration>,
     configuration_save_lock: &Arc<tokio::sync::Mutex<()>>,
     local_exclusions_store: &LocalExclusionStore,
+    temporary_exclusion_store: &TemporaryExclusionStore,
+    client_certificate_store: &ClientCertificateStore,
+    dns_resolver: &DnsResolver,
+    capture_store: &CaptureStore,
+    filter_health_store: &FilterHealthStore,
+    cert_cache: &CertCache,
     notify_reload: Arc<Notify>,
+    host_names: Vec<String>,
 ) -> BoxedFilter<(impl warp::Reply,)> {
-    let static_files_routes = create_static_routes();
-
-    let cors = warp::cors()
-        .allow_any_origin()
-        .allow_methods(vec!["GET", "PUT", "POST", "DELETE"])
-        .allow_headers(vec![
-            http::header::CONTENT_TYPE,
-            http::header::CONTENT_LENGTH,
-            http::header::DATE,
-        ]);
-
     let http_client = reqwest::Client::new();
 
     let api_routes = create_api_routes(
         events_sender,
+        event_log,
         statistics,
         blocking_disabled_store,
         configuration_updater_sender,
         configuration_save_lock,
         local_exclusions_store,
+        temporary_exclusion_store,
+        client_certificate_store,
+        dns_resolver,
+        capture_store,
+        filter_health_store,
+        cert_cache,
         http_client,
         notify_reload,
     );
 
-    api_routes.or(static_files_routes).with(cors).boxed()
+    let pac_routes = pac::create_routes();
+
+    let readiness_routes = readiness::create_routes(filter_health_store.clone());
+
+    // Proxy auto-config files are also fetched under names such as `wpad`, and hold nothing
+    // worth reading. Neither does the readiness of the server.
+    let host_routes = origin::reject_unknown_hosts(host_names);
+
+    #[cfg(feature = "gui")]
+    let routes = pac_routes
+        .or(readiness_routes)
+        .or(host_routes)
+        .or(api_routes)
+        .or(create_static_routes())
+        .boxed();
+    #[cfg(not(feature = "gui"))]
+    let routes = pac_routes
+        .or(readiness_routes)
+        .or(host_routes)
+        .or(api_routes)
+        .boxed();
+
+    // No CORS headers are sent: the web frontend is served from the same origin as the API,
+    // and other sites have no business reading its responses.
+    routes
+        .with(warp::reply::with::default_header(
+            http::header::CONTENT_SECURITY_POLICY,
+            CONTENT_SECURITY_POLICY,
+        ))
+        .with(warp::reply::with::default_header(
+            http::header::X_FRAME_OPTIONS,
+            "DENY",
+        ))
+        .with(warp::reply::with::default_header(
+            http::header::REFERRER_POLICY,
+            "no-referrer",
+        ))
+        .boxed()
 }
 
+#[cfg(feature = "gui")]
 fn create_static_routes() -> BoxedFilter<(impl warp::Reply,)> {
     warp::get()
         .and(warp::path::tail())
@@ -83,13 +156,21 @@ fn create_static_routes() -> BoxedFilter<(impl warp::Reply,)> {
         .boxed()
 }
 
+#[allow(clippy::too_many_arguments)]
 fn create_api_routes(
     events_sender: broadcast::Sender<events::Event>,
+    event_log: EventLog,
     statistics: Statistics,
     blocking_disabled_store: &BlockingDisabledStore,
     configuration_updater_sender: &Sender<Configuration>,
     configuration_save_lock: &Arc<tokio::sync::Mutex<()>>,
     local_exclusions_store: &LocalExclusionStore,
+    temporary_exclusion_store: &TemporaryExclusionStore,
+    client_certificate_store: &ClientCertificateStore,
+    dns_resolver: &DnsResolver,
+    capture_store: &CaptureStore,
+    filter_health_store: &FilterHealthStore,
+    cert_cache: &CertCache,
     http_client: reqwest::Client,
     notify_reload: Arc<Notify>,
 ) -> BoxedFilter<(impl Reply,)> {
@@ -99,22 +180,36 @@ fn create_api_routes(
 
     let events_route = warp::path("events")
         .and(warp::ws())
-        .map(move |ws: warp::ws::Ws| {
+        .and(origin::is_same_origin())
+        .map(move |ws: warp::ws::Ws, same_origin: bool| {
+            if !same_origin {
+                return origin::cross_origin_response().into_response();
+            }
+
             let events_sender = events_sender.clone();
-            ws.on_upgrade(move |websocket| events::events(websocket, events_sender))
+            let event_log = event_log.clone();
+            ws.on_upgrade(move |websocket| events::events(websocket, events_sender, event_log))
+                .into_response()
         });
 
-    let statistics_route = warp::path("statistics")
-        .and(warp::ws())
-        .map(move |ws: warp::ws::Ws| {
-            let statistics = statistics.clone();
-            ws.on_upgrade(move |websocket| statistics::statistics(websocket, statistics))
-        });
+    let metrics_route = warp::path("metrics").and(metrics::create_routes(statistics.clone()));
+
+    let statistics_route = warp::path("statistics").and(statistics::create_routes(statistics));
+
+    let filter_bundle_route =
+        warp::path("filters")
+            .and(warp::path("bundle"))
+            .and(filter_bundle::create_routes(
+                configuration_updater_sender.clone(),
+                configuration_save_lock.clone(),
+                http_client.clone(),
+            ));
 
     let filters_route = warp::path("filters").and(filters::create_routes(
         configuration_updater_sender.clone(),
         configuration_save_lock.clone(),
         http_client.clone(),
+        filter_health_store.clone(),
     ));
 
     let custom_filters_route = warp::path("custom-filters").and(custom_filters::create_routes(
@@ -122,10 +217,24 @@ fn create_api_routes(
         configuration_save_lock.clone(),
     ));
 
+    let custom_scriptlets_route =
+        warp::path("custom-scriptlets").and(custom_scriptlets::create_routes(
+            configuration_updater_sender.clone(),
+            configuration_save_lock.clone(),
+        ));
+
+    let exception_filters_route =
+        warp::path("exception-filters").and(exception_filters::create_routes(
+            configuration_updater_sender.clone(),
+            configuration_save_lock.clone(),
+        ));
+
     let exclusions_route = warp::path("exclusions").and(exclusions::create_routes(
         configuration_updater_sender.clone(),
         configuration_save_lock.clone(),
         local_exclusions_store.clone(),
+        temporary_exclusion_store.clone(),
+        client_certificate_store.clone(),
     ));
 
     let settings_route = warp::path("settings").and(settings::create_routes(
@@ -134,6 +243,33 @@ fn create_api_routes(
         notify_reload.clone(),
     ));
 
+    let profiles_route = warp::path("profiles").and(profiles::create_routes(
+        configuration_updater_sender.clone(),
+        configuration_save_lock.clone(),
+        notify_reload.clone(),
+    ));
+
+    let dns_route = warp::path("dns").and(dns::create_routes(dns_resolver.clone()));
+
+    let logging_route = warp::path("logging").and(logging::create_routes());
+
+    let capture_route = warp::path("capture").and(capture::create_routes(capture_store.clone()));
+
+    let certificates_route = warp::path("certificates")
+        .and(warp::path("cache"))
+        .and(certificates::create_routes(cert_cache.clone()));
+
+    let memory_route = warp::path("debug")
+        .and(warp::path("memory"))
+        .and(memory::create_routes());
+
+    let reload_route = warp::path("reload").and(reload::create_routes(
+        configuration_updater_sender.clone(),
+        configuration_save_lock.clone(),
+        local_exclusions_store.clone(),
+        notify_reload.clone(),
+    ));
+
     let blocking_enabled_route = warp::path("blocking-enabled").and(
         blocking_enabled::create_routes(blocking_disabled_store.clone()),
     );
@@ -142,6 +278,8 @@ fn create_api_routes(
 
     let filterlists_route = warp::path("filterlists").and(filterlists::create_routes());
 
+    let openapi_route = openapi::create_routes();
+
     let not_found = warp::path::tail()
         .map(move |tail: Tail| {
             let tail_str = tail.as_str();
@@ -160,15 +298,28 @@ fn create_api_routes(
 
     api_path
         .and(
-            events_route
+            origin::reject_cross_origin_writes()
+                .or(events_route)
                 .or(statistics_route)
+                .or(metrics_route)
+                .or(filter_bundle_route)
                 .or(filters_route)
                 .or(custom_filters_route)
+                .or(custom_scriptlets_route)
+                .or(exception_filters_route)
                 .or(exclusions_route)
                 .or(blocking_enabled_route)
                 .or(settings_route)
+                .or(profiles_route)
+                .or(reload_route)
+                .or(dns_route)
+                .or(logging_route)
+                .or(capture_route)
+                .or(certificates_route)
+                .or(memory_route)
                 .or(options_route)
                 .or(filterlists_route)
+                .or(openapi_route)
                 .or(not_found),
         )
         .with(def_headers)
@@ -181,6 +332,12 @@ pub(crate) fn with_local_exclusions_store(
     warp::any().map(move || local_exclusions_store.clone())
 }
 
+pub(crate) fn with_client_certificate_store(
+    client_certificate_store: ClientCertificateStore,
+) -> impl Filter<Extract = (ClientCertificateStore,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || client_certificate_store.clone())
+}
+
 pub(crate) fn with_configuration_save_lock(
     configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
 ) -> impl Filter<Extract = (Arc<tokio::sync::Mutex<()>>,), Error = std::convert::Infallible> + Clone
@@ -194,24 +351,40 @@ fn with_blocking_disabled_store(
     warp::any().map(move || blocking_disabled.clone())
 }
 
-pub(self) fn with_configuration_updater_sender(
+fn with_configuration_updater_sender(
     sender: Sender<Configuration>,
 ) -> impl Filter<Extract = (Sender<Configuration>,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || sender.clone())
 }
 
-pub(self) fn with_http_client(
+fn with_http_client(
     http_client: reqwest::Client,
 ) -> impl Filter<Extract = (reqwest::Client,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || http_client.clone())
 }
 
-pub(self) fn with_notify_reload(
+fn with_notify_reload(
     notify_reload: Arc<Notify>,
 ) -> impl Filter<Extract = (Arc<Notify>,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || notify_reload.clone())
 }
 
+/// JSON error with `status`, for errors worth showing as is.
+pub(crate) fn error_response(status: http::StatusCode, error: &str) -> Box<dyn warp::Reply> {
+    Box::new(
+        Response::builder()
+            .status(status)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(
+                serde_json::to_string(&ApiError {
+                    error: error.to_string(),
+                })
+                .unwrap(),
+            )
+            .unwrap(),
+    )
+}
+
 pub(crate) fn get_error_response(err: impl std::error::Error) -> Response<String> {
     log::debug!("Building error response: {:?}", err);
     Response::builder()
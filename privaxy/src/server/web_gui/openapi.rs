@@ -0,0 +1,246 @@
+use super::settings::{
+    ca_certificate, categories, dns as dns_settings, network, resources, validation,
+};
+use super::{
+    blocking_enabled, capture, certificates, custom_filters, custom_scriptlets, dns, events,
+    exception_filters, exclusions, filter_bundle, filterlists, filters, logging, memory, metrics,
+    pac, profiles, readiness, reload, revision, statistics, ApiError,
+};
+use crate::allocator::AllocatorStatistics;
+use crate::blocker::{BlockCategory, BlockSource};
+use crate::cert::{CertificateAges, CertificateCacheStatus};
+use crate::configuration::{
+    BlockedResources, Ca, Category, ClientCategories, ClientProfile, DnsConfig, DnsProtocol,
+    DnsServers, DomainDnsServers, Filter, FilterGroup, IpPreference, ListenerConfig, NetworkConfig,
+    Profile, ProfilesConfig, ResourceBlockingConfig, Severity, SiteBlockedResources,
+    ValidationIssue, ValidationReport,
+};
+use crate::filter_health::FilterHealth;
+use crate::proxy::capture::{CaptureSettings, CaptureStatus};
+use crate::proxy::client_certificates::ClientCertificateHost;
+use crate::proxy::exclusions::SiteExclusion;
+use crate::statistics::{
+    Anomaly, AnomalySeries, BlockCategoryCounts, CosmeticExceptionHits, Counters, EngineStatistics,
+    HttpVersionCounts, PoolStatistics, RejectedConnections, ResponseBreakdown, RuntimeStatistics,
+    SchemeCounts, SerializableHistory, SerializableHistoryPoint, SerializableOriginLatency,
+    SerializableProxyOverhead, SerializableStatistics, SerializableTunnelStatistics,
+    SerializableUserStatistics, SerializableWindowStatistics, StatusClassCounts, TimeoutCounts,
+    UpstreamErrorCounts, Window,
+};
+use utoipa::OpenApi;
+use warp::filters::BoxedFilter;
+use warp::http::Response;
+use warp::{http, Filter as RouteFilter};
+
+/// Allows the scripts and styles of the pinned release of Swagger UI, and nothing else, to be
+/// loaded from its CDN.
+const DOCUMENTATION_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; \
+    script-src 'self' 'unsafe-inline' https://unpkg.com/swagger-ui-dist@5.17.14/; \
+    style-src 'self' https://unpkg.com/swagger-ui-dist@5.17.14/; \
+    img-src 'self' data:; frame-ancestors 'none'";
+
+/// Swagger UI is loaded from a CDN so that it isn't bundled in the binary. The release is pinned,
+/// and its files are checked against their hashes, so that what runs on the page only changes
+/// along with Privaxy.
+const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="utf-8" />
+    <title>Privaxy API</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui.css"
+        integrity="sha384-wxLW6kwyHktdDGr6Pv1zgm/VGJh99lfUbzSn6HNHBENZlCN7W602k9VkGdxuFvPn" crossorigin="anonymous" />
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui-bundle.js"
+        integrity="sha384-wmyclcVGX/WhUkdkATwhaK1X1JtiNrr2EoYJ+diV3vj4v6OC5yCeSu+yW13SYJep" crossorigin="anonymous"></script>
+    <script>
+        window.onload = () => {
+            window.ui = SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });
+        };
+    </script>
+</body>
+</html>
+"##;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Privaxy", description = "API of the Privaxy web interface"),
+    paths(
+        blocking_enabled::get_blocking_enabled,
+        blocking_enabled::put_blocking_enabled,
+        capture::get_capture,
+        capture::put_capture,
+        capture::delete_capture,
+        capture::get_har,
+        certificates::get_cache,
+        certificates::flush_cache,
+        certificates::evict_certificate,
+        memory::get_memory,
+        memory::purge_memory,
+        custom_filters::get_custom_filters,
+        custom_filters::put_custom_filters,
+        custom_scriptlets::get_custom_scriptlets,
+        custom_scriptlets::put_custom_scriptlets,
+        dns::flush_cache,
+        events::events,
+        exception_filters::get_exception_filters,
+        exception_filters::put_exception_filters,
+        exception_filters::test_exception_filters,
+        exclusions::get_exclusions,
+        exclusions::put_exclusions,
+        exclusions::get_client_certificate_hosts,
+        exclusions::get_site_exclusions,
+        exclusions::post_site_exclusion,
+        exclusions::delete_site_exclusion,
+        filterlists::get_filters,
+        filterlists::get_filter,
+        filterlists::get_syntaxes,
+        filterlists::get_languages,
+        filterlists::get_tags,
+        filterlists::get_licenses,
+        filters::get_filters_configuration,
+        filters::change_filter_status,
+        filters::add_filter,
+        filters::delete_filter,
+        filters::update_filters,
+        filters::get_filters_health,
+        filter_bundle::get_bundle,
+        filter_bundle::put_bundle,
+        logging::get_log_levels,
+        logging::put_log_levels,
+        metrics::get_history,
+        profiles::get_profiles,
+        profiles::put_profiles,
+        profiles::put_active_profile,
+        reload::reload,
+        statistics::get_window,
+        statistics::get_blocked_paths,
+        statistics::reset,
+        statistics::get_cosmetic_exceptions,
+        ca_certificate::get_ca_certificates,
+        ca_certificate::put_ca_certificates,
+        ca_certificate::validate_ca_certificates,
+        categories::get_categories_settings,
+        categories::put_categories_settings,
+        dns_settings::get_dns_settings,
+        dns_settings::put_dns_settings,
+        resources::get_resources_settings,
+        resources::put_resources_settings,
+        network::get_network_settings,
+        network::put_network_settings,
+        validation::get_validation,
+        validation::post_validation,
+        pac::get_proxy_auto_config,
+        readiness::get_readiness,
+    ),
+    components(schemas(
+        ApiError,
+        BlockCategory,
+        BlockSource,
+        BlockedResources,
+        blocking_enabled::BlockingEnabled,
+        CaptureSettings,
+        CaptureStatus,
+        CertificateAges,
+        CertificateCacheStatus,
+        events::Event,
+        exception_filters::ExceptionFilterTest,
+        exception_filters::ExceptionFilterTestResult,
+        exception_filters::InvalidExceptionFilter,
+        exception_filters::InvalidExceptionFilters,
+        exclusions::SiteExclusionDuration,
+        exclusions::SiteExclusionRequest,
+        revision::RevisionConflict,
+        statistics::BlockedPaths,
+        SiteExclusion,
+        filters::FilterStatusChangeRequest,
+        filters::FilterRequest,
+        FilterHealth,
+        logging::LogLevels,
+        categories::CategoriesRequest,
+        network::NetworkConfigRequest,
+        Ca,
+        Category,
+        ClientCategories,
+        ClientCertificateHost,
+        ClientProfile,
+        DnsConfig,
+        DnsProtocol,
+        DnsServers,
+        DomainDnsServers,
+        IpPreference,
+        Filter,
+        FilterGroup,
+        ListenerConfig,
+        NetworkConfig,
+        Profile,
+        ProfilesConfig,
+        ResourceBlockingConfig,
+        SiteBlockedResources,
+        Severity,
+        ValidationIssue,
+        ValidationReport,
+        AllocatorStatistics,
+        Counters,
+        BlockCategoryCounts,
+        CosmeticExceptionHits,
+        Anomaly,
+        AnomalySeries,
+        TimeoutCounts,
+        UpstreamErrorCounts,
+        RejectedConnections,
+        ResponseBreakdown,
+        StatusClassCounts,
+        SchemeCounts,
+        HttpVersionCounts,
+        PoolStatistics,
+        EngineStatistics,
+        RuntimeStatistics,
+        SerializableTunnelStatistics,
+        SerializableHistory,
+        SerializableHistoryPoint,
+        SerializableOriginLatency,
+        SerializableProxyOverhead,
+        SerializableStatistics,
+        SerializableUserStatistics,
+        SerializableWindowStatistics,
+        Window,
+        readiness::Readiness,
+        readiness::ReadinessStatus,
+    )),
+    tags(
+        (name = "blocking", description = "Turning blocking on and off"),
+        (name = "filters", description = "Filter lists, custom filters and exclusions"),
+        (name = "profiles", description = "Named blocking profiles and the active one"),
+        (name = "filterlists", description = "Filter lists directory, from filterlists.com"),
+        (name = "statistics", description = "Counters, history and live events"),
+        (name = "settings", description = "Network, CA certificate, categories and logging"),
+        (name = "certificates", description = "Certificates signed for intercepted hosts"),
+        (name = "debugging", description = "Captures of requests, exported as HAR files, and allocator statistics"),
+        (name = "clients", description = "Proxy auto-config for clients and readiness of the instance, outside of `/api`"),
+    )
+)]
+struct ApiDoc;
+
+pub(super) fn create_routes() -> BoxedFilter<(impl warp::Reply,)> {
+    let specification_route = warp::path("openapi.json")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|| warp::reply::json(&ApiDoc::openapi()));
+
+    let documentation_route = warp::path("docs")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|| {
+            Response::builder()
+                .header(http::header::CONTENT_TYPE, "text/html; charset=utf-8")
+                .header(
+                    http::header::CONTENT_SECURITY_POLICY,
+                    DOCUMENTATION_CONTENT_SECURITY_POLICY,
+                )
+                .body(SWAGGER_UI_HTML)
+        });
+
+    specification_route.or(documentation_route).boxed()
+}
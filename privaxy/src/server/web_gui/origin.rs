@@ -0,0 +1,122 @@
+use super::ApiError;
+use std::net::IpAddr;
+use std::sync::Arc;
+use warp::filters::BoxedFilter;
+use warp::http::uri::Authority;
+use warp::http::{Method, Response, StatusCode};
+use warp::Filter;
+
+/// Whether the request comes from a page served by the web GUI itself.
+///
+/// Browsers send an `Origin` header along with WebSocket handshakes and cross-site requests,
+/// which must then point to the host the request was sent to. Requests without one come from
+/// clients which aren't browsers, and are let through.
+pub(super) fn is_same_origin() -> BoxedFilter<(bool,)> {
+    warp::header::optional::<String>("origin")
+        .and(warp::header::optional::<String>("host"))
+        .map(
+            |origin: Option<String>, host: Option<String>| match (origin, host) {
+                (None, _) => true,
+                (Some(origin), Some(host)) => matches_host(&origin, &host),
+                (Some(_), None) => false,
+            },
+        )
+        .boxed()
+}
+
+/// Answers requests which would change something when they are sent by other sites, as forms
+/// they submit or scripts they run, so that they can't reconfigure Privaxy behind the user's
+/// back. Rejects every other request, for the actual routes to handle.
+pub(super) fn reject_cross_origin_writes() -> BoxedFilter<(Response<String>,)> {
+    warp::method()
+        .and(is_same_origin())
+        .and_then(|method: Method, same_origin: bool| async move {
+            if same_origin || method.is_safe() {
+                Err(warp::reject())
+            } else {
+                log::warn!("Refused cross-origin {} request", method);
+                Ok(cross_origin_response())
+            }
+        })
+        .boxed()
+}
+
+/// Answers requests sent to a host name the web GUI isn't known under. Pages of other sites
+/// could otherwise read from it once their name resolves to its address, with DNS rebinding.
+/// Addresses, `localhost` and `host_names` are let through, as are requests without a host,
+/// which don't come from browsers. Rejects them, for the actual routes to handle.
+pub(super) fn reject_unknown_hosts(host_names: Vec<String>) -> BoxedFilter<(Response<String>,)> {
+    let host_names = Arc::new(host_names);
+
+    warp::host::optional()
+        .and_then(move |host: Option<Authority>| {
+            let host_names = host_names.clone();
+
+            async move {
+                match host {
+                    Some(host) if !is_known_host(host.host(), &host_names) => {
+                        log::warn!("Refused request sent to unknown host {}", host);
+                        Ok(unknown_host_response())
+                    }
+                    _ => Err(warp::reject()),
+                }
+            }
+        })
+        .boxed()
+}
+
+fn is_known_host(host: &str, host_names: &[String]) -> bool {
+    let host = host.trim_end_matches('.');
+    // IPv6 addresses are enclosed in brackets.
+    let address = host.trim_start_matches('[').trim_end_matches(']');
+
+    address.parse::<IpAddr>().is_ok()
+        || host.eq_ignore_ascii_case("localhost")
+        || host.to_ascii_lowercase().ends_with(".localhost")
+        || host_names
+            .iter()
+            .any(|host_name| host_name.trim_end_matches('.').eq_ignore_ascii_case(host))
+}
+
+fn matches_host(origin: &str, host: &str) -> bool {
+    let (origin, host) = match (url::Url::parse(origin), host.parse::<Authority>()) {
+        (Ok(origin), Ok(host)) => (origin, host),
+        _ => return false,
+    };
+
+    let port = host.port_u16().or_else(|| match origin.scheme() {
+        "https" => Some(443),
+        "http" => Some(80),
+        _ => None,
+    });
+
+    origin
+        .host_str()
+        .is_some_and(|origin_host| origin_host.eq_ignore_ascii_case(host.host()))
+        && origin.port_or_known_default() == port
+}
+
+fn unknown_host_response() -> Response<String> {
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header(warp::http::header::CONTENT_TYPE, "application/json")
+        .body(
+            serde_json::to_string(&ApiError {
+                error: "Unknown host, add it to the host names of the web server".to_string(),
+            })
+            .unwrap(),
+        )
+        .unwrap()
+}
+
+pub(super) fn cross_origin_response() -> Response<String> {
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .body(
+            serde_json::to_string(&ApiError {
+                error: "Cross-origin requests are not allowed".to_string(),
+            })
+            .unwrap(),
+        )
+        .unwrap()
+}
@@ -0,0 +1,109 @@
+use super::get_error_response;
+use crate::configuration::Configuration;
+use std::convert::Infallible;
+use std::net::IpAddr;
+use warp::filters::BoxedFilter;
+use warp::http::{self, Response};
+use warp::Filter as RouteFilter;
+
+const CONTENT_TYPE: &str = "application/x-ns-proxy-autoconfig";
+
+/// `/proxy.pac` and `/wpad.dat`, served by the web server and the WPAD listener.
+pub(crate) fn create_routes() -> BoxedFilter<(impl warp::Reply,)> {
+    warp::get()
+        .and(warp::path("proxy.pac").or(warp::path("wpad.dat")).unify())
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>(
+            http::header::HOST.as_str(),
+        ))
+        .and_then(get_proxy_auto_config)
+        .boxed()
+}
+
+/// Host name of a `Host` header, without its port.
+fn host_name(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        // Bracketed IPv6 addresses contain colons as well.
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    }
+}
+
+/// Condition of `FindProxyForURL` matching hosts of `bypass`, in the syntax of the system proxy
+/// settings.
+fn bypass_condition(bypass: &[String]) -> Option<String> {
+    let conditions = bypass
+        .iter()
+        .map(|pattern| pattern.trim())
+        .filter(|pattern| !pattern.is_empty())
+        .map(|pattern| match pattern {
+            "<local>" => "isPlainHostName(host)".to_string(),
+            pattern => format!(
+                "shExpMatch(host, {})",
+                serde_json::to_string(&pattern.to_ascii_lowercase()).unwrap()
+            ),
+        })
+        .collect::<Vec<_>>();
+
+    if conditions.is_empty() {
+        None
+    } else {
+        Some(conditions.join(" ||\n        "))
+    }
+}
+
+/// Proxy auto-config sending requests to Privaxy at `proxy_host`, except for the hosts bypassing
+/// the system proxy. Requests are sent directly when Privaxy can't be reached.
+pub(crate) fn proxy_auto_config(configuration: &Configuration, proxy_host: &str) -> String {
+    let proxy = serde_json::to_string(&format!(
+        "PROXY {}:{}; DIRECT",
+        proxy_host, configuration.network.proxy_port
+    ))
+    .unwrap();
+
+    let mut script = String::from("function FindProxyForURL(url, host) {\n");
+    if let Some(condition) = bypass_condition(&configuration.system_proxy.bypass) {
+        script.push_str(&format!(
+            "    host = host.toLowerCase();\n    if ({}) {{\n        return \"DIRECT\";\n    }}\n",
+            condition
+        ));
+    }
+    script.push_str(&format!("    return {};\n}}\n", proxy));
+
+    script
+}
+
+/// Also served as `/wpad.dat`, and by the WPAD listener when it is enabled. Proxies requests
+/// through the host the script was requested from, unless `wpad.proxy_host` is set.
+#[utoipa::path(
+    get,
+    path = "/proxy.pac",
+    tag = "clients",
+    responses(
+        (status = 200, description = "Proxy auto-config script", body = String, content_type = "application/x-ns-proxy-autoconfig"),
+        (status = 500, description = "Unable to read the configuration", body = ApiError),
+    )
+)]
+async fn get_proxy_auto_config(host: Option<String>) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let configuration = match Configuration::read_from_home().await {
+        Ok(configuration) => configuration,
+        Err(err) => return Ok(Box::new(get_error_response(err))),
+    };
+
+    let proxy_host = match (&configuration.wpad.proxy_host, &host) {
+        (Some(proxy_host), _) => proxy_host.clone(),
+        (None, Some(host)) => host_name(host).to_string(),
+        (None, None) => match configuration.network.parsed_ip_address() {
+            IpAddr::V6(ip) => format!("[{}]", ip),
+            ip => ip.to_string(),
+        },
+    };
+
+    Ok(Box::new(
+        Response::builder()
+            .header(http::header::CONTENT_TYPE, CONTENT_TYPE)
+            .header(http::header::CACHE_CONTROL, "no-cache")
+            .body(proxy_auto_config(&configuration, &proxy_host))
+            .unwrap(),
+    ))
+}
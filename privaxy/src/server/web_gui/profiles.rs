@@ -0,0 +1,161 @@
+use super::get_error_response;
+use super::settings::validation::reject_invalid_section;
+use crate::configuration::{Configuration, ProfilesConfig, DEFAULT_PROFILE};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::Notify;
+use warp::filters::BoxedFilter;
+use warp::http::Response;
+use warp::Filter as RouteFilter;
+
+#[utoipa::path(
+    get,
+    path = "/api/profiles",
+    tag = "profiles",
+    responses(
+        (status = 200, description = "Blocking profiles", body = ProfilesConfig),
+        (status = 500, description = "Unable to read the configuration", body = ApiError)
+    )
+)]
+async fn get_profiles() -> Result<Box<dyn warp::Reply>, Infallible> {
+    match Configuration::read_from_home().await {
+        Ok(configuration) => Ok(Box::new(warp::reply::json(&configuration.proxy.profiles))),
+        Err(err) => {
+            log::error!("Failed to get profiles: {err}");
+            Ok(Box::new(get_error_response(err)))
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/profiles",
+    tag = "profiles",
+    request_body = ProfilesConfig,
+    responses(
+        (status = 204, description = "Profiles saved"),
+        (status = 400, description = "Invalid profiles", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
+async fn put_profiles(
+    profiles: ProfilesConfig,
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    notify_reload: Arc<Notify>,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    save_profiles(
+        move |current| *current = profiles,
+        configuration_updater_sender,
+        configuration_save_lock,
+        notify_reload,
+    )
+    .await
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/profiles/active",
+    tag = "profiles",
+    request_body(content = String, description = "Name of the profile, `default` for the top-level filters"),
+    responses(
+        (status = 204, description = "Active profile switched"),
+        (status = 400, description = "Unknown profile", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
+async fn put_active_profile(
+    name: String,
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    notify_reload: Arc<Notify>,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    log::info!("Switching to the {} profile", name);
+
+    save_profiles(
+        move |current| current.active = name,
+        configuration_updater_sender,
+        configuration_save_lock,
+        notify_reload,
+    )
+    .await
+}
+
+async fn save_profiles(
+    update: impl FnOnce(&mut ProfilesConfig),
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    notify_reload: Arc<Notify>,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let guard = configuration_save_lock.lock().await;
+    let mut configuration = match Configuration::read_from_home().await {
+        Ok(configuration) => configuration,
+        Err(err) => {
+            log::error!("Failed to get profiles: {}", err);
+            return Ok(Box::new(get_error_response(err)));
+        }
+    };
+
+    update(&mut configuration.proxy.profiles);
+    if configuration.proxy.profiles.active.is_empty() {
+        configuration.proxy.profiles.active = DEFAULT_PROFILE.to_string();
+    }
+
+    if let Some(response) = reject_invalid_section(&configuration, "proxy.profiles").await {
+        return Ok(Box::new(response));
+    }
+
+    if let Err(err) = configuration.save().await {
+        return Ok(Box::new(get_error_response(err)));
+    }
+    configuration_updater_sender
+        .send(configuration.clone())
+        .await
+        .unwrap();
+    drop(guard);
+
+    // The proxy reads its configuration when started.
+    notify_reload.notify_waiters();
+
+    Ok(Box::new(
+        Response::builder()
+            .status(http::StatusCode::NO_CONTENT)
+            .body("".to_string()),
+    ))
+}
+
+pub(super) fn create_routes(
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    notify_reload: Arc<Notify>,
+) -> BoxedFilter<(impl warp::Reply,)> {
+    let get_route = warp::get()
+        .and(warp::path::end())
+        .and_then(self::get_profiles);
+
+    let put_route = warp::put()
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and(super::with_configuration_updater_sender(
+            configuration_updater_sender.clone(),
+        ))
+        .and(super::with_configuration_save_lock(
+            configuration_save_lock.clone(),
+        ))
+        .and(super::with_notify_reload(notify_reload.clone()))
+        .and_then(self::put_profiles);
+
+    let put_active_route = warp::put()
+        .and(warp::path("active"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and(super::with_configuration_updater_sender(
+            configuration_updater_sender,
+        ))
+        .and(super::with_configuration_save_lock(configuration_save_lock))
+        .and(super::with_notify_reload(notify_reload))
+        .and_then(self::put_active_profile);
+
+    get_route.or(put_route).or(put_active_route).boxed()
+}
@@ -0,0 +1,55 @@
+use crate::filter_health::{FilterHealth, FilterHealthStore};
+use serde::Serialize;
+use std::convert::Infallible;
+use utoipa::ToSchema;
+use warp::filters::BoxedFilter;
+use warp::Filter as RouteFilter;
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum ReadinessStatus {
+    Ready,
+    /// Requests are still served, but filter lists need attention.
+    Degraded,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct Readiness {
+    status: ReadinessStatus,
+    /// Filter lists the last check found a problem with.
+    filters: Vec<FilterHealth>,
+}
+
+/// Answered with `200 OK` as long as the web server is up. A failing list doesn't keep the proxy
+/// from serving requests, so it is reported as `degraded` rather than taking the instance out of
+/// rotation.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "clients",
+    responses((status = 200, description = "The web server is up, along with the filter lists found unhealthy", body = Readiness))
+)]
+async fn get_readiness(
+    filter_health_store: FilterHealthStore,
+) -> Result<impl warp::Reply, Infallible> {
+    let filters = filter_health_store.unhealthy();
+    let status = if filters.is_empty() {
+        ReadinessStatus::Ready
+    } else {
+        ReadinessStatus::Degraded
+    };
+
+    Ok(warp::reply::json(&Readiness { status, filters }))
+}
+
+/// `/readyz`, for load balancers and orchestrators.
+pub(crate) fn create_routes(
+    filter_health_store: FilterHealthStore,
+) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::get()
+        .and(warp::path("readyz"))
+        .and(warp::path::end())
+        .and(warp::any().map(move || filter_health_store.clone()))
+        .and_then(get_readiness)
+        .boxed()
+}
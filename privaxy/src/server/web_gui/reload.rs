@@ -0,0 +1,57 @@
+use super::get_error_response;
+use crate::configuration::{self, Configuration};
+use crate::proxy::exclusions::LocalExclusionStore;
+use std::{convert::Infallible, sync::Arc};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::Notify;
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::Filter as RouteFilter;
+
+#[utoipa::path(
+    post,
+    path = "/api/reload",
+    tag = "settings",
+    responses(
+        (status = 204, description = "Configuration reloaded from disk"),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
+async fn reload(
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    local_exclusions_store: LocalExclusionStore,
+    notify_reload: Arc<Notify>,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    if let Err(err) = configuration::reload_configuration(
+        &configuration_updater_sender,
+        &configuration_save_lock,
+        &local_exclusions_store,
+        &notify_reload,
+    )
+    .await
+    {
+        log::error!("Failed to reload configuration: {err}");
+        return Ok(Box::new(get_error_response(err)));
+    }
+
+    Ok(Box::new(StatusCode::NO_CONTENT))
+}
+
+pub(super) fn create_routes(
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    local_exclusions_store: LocalExclusionStore,
+    notify_reload: Arc<Notify>,
+) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path::end()
+        .and(warp::post())
+        .and(super::with_configuration_updater_sender(
+            configuration_updater_sender,
+        ))
+        .and(super::with_configuration_save_lock(configuration_save_lock))
+        .and(super::with_local_exclusions_store(local_exclusions_store))
+        .and(super::with_notify_reload(notify_reload))
+        .and_then(self::reload)
+        .boxed()
+}
@@ -0,0 +1,90 @@
+use super::ApiError;
+use crate::configuration::Configuration;
+use serde::Serialize;
+use std::convert::Infallible;
+use utoipa::ToSchema;
+use warp::http::{self, Response, StatusCode};
+use warp::Filter as RouteFilter;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct RevisionConflict {
+    error: String,
+    /// Revision of the saved configuration, to be sent back to overwrite it anyway.
+    revision: u64,
+}
+
+/// Revisions are sent as `ETag: "<revision>"` and expected back in `If-Match`.
+pub(crate) fn etag(revision: u64) -> String {
+    format!("\"{}\"", revision)
+}
+
+fn parse_etag(value: &str) -> Option<u64> {
+    value
+        .trim()
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .parse()
+        .ok()
+}
+
+/// Extracts the revision a PUT request was based on, `None` when `If-Match` is missing or invalid.
+pub(crate) fn with_expected_revision(
+) -> impl RouteFilter<Extract = (Option<u64>,), Error = Infallible> + Clone {
+    warp::header::optional::<String>(http::header::IF_MATCH.as_str())
+        .map(|if_match: Option<String>| if_match.as_deref().and_then(parse_etag))
+        .or(warp::any().map(|| None))
+        .unify()
+}
+
+/// Returns the response to send instead of saving when `expected_revision` is not the revision of
+/// `configuration`.
+pub(crate) fn check_revision(
+    configuration: &Configuration,
+    expected_revision: Option<u64>,
+) -> Option<Box<dyn warp::Reply>> {
+    match expected_revision {
+        None => Some(Box::new(
+            Response::builder()
+                .status(StatusCode::PRECONDITION_REQUIRED)
+                .body(
+                    serde_json::to_string(&ApiError {
+                        error: "The revision being edited must be sent in If-Match".to_string(),
+                    })
+                    .unwrap(),
+                )
+                .unwrap(),
+        )),
+        Some(revision) if revision != configuration.revision => {
+            log::debug!(
+                "Rejecting changes to revision {}, current revision is {}",
+                revision,
+                configuration.revision
+            );
+
+            Some(Box::new(warp::reply::with_header(
+                warp::reply::with_status(
+                    warp::reply::json(&RevisionConflict {
+                        error: "The configuration was changed in the meantime".to_string(),
+                        revision: configuration.revision,
+                    }),
+                    StatusCode::CONFLICT,
+                ),
+                http::header::ETAG,
+                etag(configuration.revision),
+            )))
+        }
+        Some(_revision) => None,
+    }
+}
+
+/// Adds the revision of `configuration` to `reply`.
+pub(crate) fn with_revision(
+    reply: impl warp::Reply + 'static,
+    configuration: &Configuration,
+) -> Box<dyn warp::Reply> {
+    Box::new(warp::reply::with_header(
+        reply,
+        http::header::ETAG,
+        etag(configuration.revision),
+    ))
+}
@@ -13,6 +13,15 @@ use warp::filters::BoxedFilter;
 use warp::http::Response;
 use warp::Filter as RouteFilter;
 
+#[utoipa::path(
+    get,
+    path = "/api/settings/ca-certificate",
+    tag = "settings",
+    responses(
+        (status = 200, description = "CA certificate to install in browsers", content_type = "application/x-pem-file", body = String),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
 async fn get_ca_certificates() -> Result<Box<dyn warp::Reply>, Infallible> {
     log::debug!("Getting CA certificates");
     let configuration = match Configuration::read_from_home().await {
@@ -41,6 +50,16 @@ async fn get_ca_certificates() -> Result<Box<dyn warp::Reply>, Infallible> {
     ))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/settings/ca-certificate/validate",
+    tag = "settings",
+    request_body = Ca,
+    responses(
+        (status = 204, description = "Valid certificate and private key"),
+        (status = 400, description = "Invalid certificate or private key", body = ApiError)
+    )
+)]
 async fn validate_ca_certificates(body: Ca) -> Result<Box<dyn warp::Reply>, Infallible> {
     match body.validate().await {
         Ok(_) => Ok(Box::new(
@@ -50,7 +69,7 @@ async fn validate_ca_certificates(body: Ca) -> Result<Box<dyn warp::Reply>, Infa
         )),
         Err(err) => {
             log::error!("Invalid CA certificates: {err}");
-            return Ok(Box::new(
+            Ok(Box::new(
                 Response::builder()
                     .status(http::StatusCode::BAD_REQUEST)
                     .body(
@@ -59,16 +78,27 @@ async fn validate_ca_certificates(body: Ca) -> Result<Box<dyn warp::Reply>, Infa
                         })
                         .unwrap(),
                     ),
-            ));
+            ))
         }
     }
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/settings/ca-certificate",
+    tag = "settings",
+    request_body = Ca,
+    responses(
+        (status = 204, description = "CA replaced"),
+        (status = 400, description = "Invalid certificate or private key", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
 async fn put_ca_certificates(
     ca_cert_struct: Ca,
     configuration_updater_sender: Sender<Configuration>,
     configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
-    notify_reload: Arc<Notify>,
+    _notify_reload: Arc<Notify>,
 ) -> Result<Box<dyn warp::Reply>, Infallible> {
     let _guard = configuration_save_lock.lock().await;
 
@@ -80,6 +110,12 @@ async fn put_ca_certificates(
         }
     };
 
+    let mut candidate = configuration.clone();
+    candidate.ca = ca_cert_struct.clone();
+    if let Some(response) = super::validation::reject_invalid_section(&candidate, "ca").await {
+        return Ok(Box::new(response));
+    }
+
     if let Err(err) = configuration.set_ca_settings(&ca_cert_struct).await {
         log::error!("Failed to set CA certificate: {err}");
         return Ok(Box::new(get_error_response(err)));
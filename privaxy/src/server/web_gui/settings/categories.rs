@@ -0,0 +1,133 @@
+use super::get_error_response;
+use crate::configuration::{Category, ClientCategories, Configuration};
+use crate::web_gui::with_configuration_save_lock;
+use crate::web_gui::with_configuration_updater_sender;
+use crate::web_gui::with_notify_reload;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::Notify;
+use utoipa::ToSchema;
+use warp::filters::BoxedFilter;
+use warp::http::Response;
+use warp::Filter as RouteFilter;
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+/// Category toggles, lists are only editable through the configuration file
+pub struct CategoriesRequest {
+    /// Categories blocked for clients without their own entry.
+    pub blocked: BTreeSet<Category>,
+    pub clients: Vec<ClientCategories>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/settings/categories",
+    tag = "settings",
+    responses(
+        (status = 200, description = "Blocked categories", body = CategoriesRequest),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
+async fn get_categories_settings() -> Result<Box<dyn warp::Reply>, Infallible> {
+    log::debug!("Getting categories settings");
+    let configuration = match Configuration::read_from_home().await {
+        Ok(configuration) => configuration,
+        Err(err) => {
+            log::error!("Failed to get categories settings: {err}");
+            return Ok(Box::new(get_error_response(err)));
+        }
+    };
+    let categories = configuration.proxy.categories;
+
+    Ok(Box::new(warp::reply::json(&CategoriesRequest {
+        blocked: categories.blocked,
+        clients: categories.clients,
+    })))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/settings/categories",
+    tag = "settings",
+    request_body = CategoriesRequest,
+    responses(
+        (status = 204, description = "Blocked categories saved"),
+        (status = 400, description = "Invalid categories", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
+async fn put_categories_settings(
+    categories_settings: CategoriesRequest,
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    notify_reload: Arc<Notify>,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let guard = configuration_save_lock.lock().await;
+    let mut configuration = match Configuration::read_from_home().await {
+        Ok(configuration) => configuration,
+        Err(err) => {
+            log::error!("Failed to get categories settings: {}", err);
+            return Ok(Box::new(get_error_response(err)));
+        }
+    };
+
+    configuration.proxy.categories.blocked = categories_settings.blocked;
+    configuration.proxy.categories.clients = categories_settings
+        .clients
+        .into_iter()
+        .map(|mut client| {
+            client.client = client.client.trim().to_string();
+            client
+        })
+        .filter(|client| !client.client.is_empty())
+        .collect();
+
+    if let Some(response) =
+        super::validation::reject_invalid_section(&configuration, "proxy.categories").await
+    {
+        return Ok(Box::new(response));
+    }
+
+    if let Err(err) = configuration.save().await {
+        return Ok(Box::new(get_error_response(err)));
+    }
+    configuration_updater_sender
+        .send(configuration.clone())
+        .await
+        .unwrap();
+    drop(guard);
+
+    // The proxy reads its configuration when started.
+    notify_reload.notify_waiters();
+
+    Ok(Box::new(
+        Response::builder()
+            .status(http::StatusCode::NO_CONTENT)
+            .body("".to_string()),
+    ))
+}
+
+pub(super) fn create_routes(
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    notify_reload: Arc<Notify>,
+) -> BoxedFilter<(impl warp::Reply,)> {
+    let get_route = warp::get()
+        .and(warp::path::end())
+        .and_then(get_categories_settings);
+
+    let put_route = warp::put()
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and(with_configuration_updater_sender(
+            configuration_updater_sender,
+        ))
+        .and(with_configuration_save_lock(configuration_save_lock))
+        .and(with_notify_reload(notify_reload))
+        .and_then(put_categories_settings);
+
+    get_route.or(put_route).boxed()
+}
@@ -0,0 +1,105 @@
+use super::get_error_response;
+use crate::configuration::{Configuration, DnsConfig};
+use crate::web_gui::with_configuration_save_lock;
+use crate::web_gui::with_configuration_updater_sender;
+use crate::web_gui::with_notify_reload;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::Notify;
+use warp::filters::BoxedFilter;
+use warp::http::Response;
+use warp::Filter as RouteFilter;
+
+#[utoipa::path(
+    get,
+    path = "/api/settings/dns",
+    tag = "settings",
+    responses(
+        (status = 200, description = "DNS settings", body = DnsConfig),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
+async fn get_dns_settings() -> Result<Box<dyn warp::Reply>, Infallible> {
+    log::debug!("Getting DNS settings");
+    match Configuration::read_from_home().await {
+        Ok(configuration) => Ok(Box::new(warp::reply::json(&configuration.dns))),
+        Err(err) => {
+            log::error!("Failed to get DNS settings: {err}");
+            Ok(Box::new(get_error_response(err)))
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/settings/dns",
+    tag = "settings",
+    request_body = DnsConfig,
+    responses(
+        (status = 204, description = "DNS settings saved"),
+        (status = 400, description = "Invalid DNS settings", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
+async fn put_dns_settings(
+    dns_settings: DnsConfig,
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    notify_reload: Arc<Notify>,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let guard = configuration_save_lock.lock().await;
+    let mut configuration = match Configuration::read_from_home().await {
+        Ok(configuration) => configuration,
+        Err(err) => {
+            log::error!("Failed to get DNS settings: {}", err);
+            return Ok(Box::new(get_error_response(err)));
+        }
+    };
+
+    configuration.dns = dns_settings;
+
+    if let Some(response) = super::validation::reject_invalid_section(&configuration, "dns").await {
+        return Ok(Box::new(response));
+    }
+
+    if let Err(err) = configuration.save().await {
+        return Ok(Box::new(get_error_response(err)));
+    }
+    configuration_updater_sender
+        .send(configuration.clone())
+        .await
+        .unwrap();
+    drop(guard);
+
+    // The resolver is reconfigured when the proxy restarts.
+    notify_reload.notify_waiters();
+
+    Ok(Box::new(
+        Response::builder()
+            .status(http::StatusCode::NO_CONTENT)
+            .body("".to_string()),
+    ))
+}
+
+pub(super) fn create_routes(
+    configuration_updater_sender: Sender<Configuration>,
+    configuration_save_lock: Arc<tokio::sync::Mutex<()>>,
+    notify_reload: Arc<Notify>,
+) -> BoxedFilter<(impl warp::Reply,)> {
+    let get_route = warp::get()
+        .and(warp::path::end())
+        .and_then(get_dns_settings);
+
+    let put_route = warp::put()
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and(with_configuration_updater_sender(
+            configuration_updater_sender,
+        ))
+        .and(with_configuration_save_lock(configuration_save_lock))
+        .and(with_notify_reload(notify_reload))
+        .and_then(put_dns_settings);
+
+    get_route.or(put_route).boxed()
+}
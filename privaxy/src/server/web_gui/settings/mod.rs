@@ -6,8 +6,12 @@ use tokio::sync::Notify;
 use warp::filters::BoxedFilter;
 use warp::Filter as RouteFilter;
 
-mod ca_certificate;
-mod network;
+pub(super) mod ca_certificate;
+pub(super) mod categories;
+pub(super) mod dns;
+pub(super) mod network;
+pub(super) mod resources;
+pub(super) mod validation;
 
 pub(crate) fn create_routes(
     configuration_updater_sender: Sender<Configuration>,
@@ -26,5 +30,31 @@ pub(crate) fn create_routes(
         notify_reload.clone(),
     ));
 
-    network_settings_route.or(ca_cert_route).boxed()
+    let categories_route = warp::path("categories").and(categories::create_routes(
+        configuration_updater_sender.clone(),
+        configuration_save_lock.clone(),
+        notify_reload.clone(),
+    ));
+
+    let dns_route = warp::path("dns").and(dns::create_routes(
+        configuration_updater_sender.clone(),
+        configuration_save_lock.clone(),
+        notify_reload.clone(),
+    ));
+
+    let resources_route = warp::path("resources").and(resources::create_routes(
+        configuration_updater_sender.clone(),
+        configuration_save_lock.clone(),
+        notify_reload.clone(),
+    ));
+
+    let validation_route = warp::path("validation").and(validation::create_routes());
+
+    network_settings_route
+        .or(ca_cert_route)
+        .or(categories_route)
+        .or(dns_route)
+        .or(resources_route)
+        .or(validation_route)
+        .boxed()
 }
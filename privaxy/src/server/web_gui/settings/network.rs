@@ -1,11 +1,11 @@
 use super::get_error_response;
-use crate::configuration;
 use crate::configuration::NetworkConfig;
 use crate::web_gui::with_configuration_save_lock;
 use crate::web_gui::with_configuration_updater_sender;
 use crate::web_gui::with_notify_reload;
 use serde::{Deserialize, Serialize};
 use tokio::sync::Notify;
+use utoipa::ToSchema;
 
 use std::convert::Infallible;
 use std::sync::Arc;
@@ -16,7 +16,7 @@ use warp::Filter as RouteFilter;
 
 use crate::configuration::Configuration;
 
-#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
 /// Network configuration for Privaxy
 pub struct NetworkConfigRequest {
     /// Bind address for the proxy server.
@@ -29,20 +29,36 @@ pub struct NetworkConfigRequest {
     pub tls: bool,
 }
 
-impl Into<NetworkConfig> for NetworkConfigRequest {
-    fn into(self) -> NetworkConfig {
+impl From<NetworkConfigRequest> for NetworkConfig {
+    fn from(request: NetworkConfigRequest) -> Self {
         NetworkConfig {
-            bind_addr: self.bind_addr,
-            proxy_port: self.proxy_port,
-            web_port: self.web_port,
-            tls: self.tls,
+            bind_addr: request.bind_addr,
+            proxy_port: request.proxy_port,
+            web_port: request.web_port,
+            tls: request.tls,
             tls_cert_path: None,
             tls_key_path: None,
             listen_url: None,
+            proxy_protocol: false,
+            proxy_protocol_trusted_networks: Vec::new(),
+            secure_proxy_port: None,
+            proxy_listeners: Vec::new(),
+            web_listeners: Vec::new(),
+            web_allowed_networks: Vec::new(),
+            web_host_names: Vec::new(),
         }
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/settings/network",
+    tag = "settings",
+    responses(
+        (status = 200, description = "Network configuration", body = NetworkConfig),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
 async fn get_network_settings() -> Result<Box<dyn warp::Reply>, Infallible> {
     log::debug!("Getting network settings");
     let configuration = match Configuration::read_from_home().await {
@@ -55,6 +71,17 @@ async fn get_network_settings() -> Result<Box<dyn warp::Reply>, Infallible> {
     Ok(Box::new(warp::reply::json(&configuration.network)))
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/settings/network",
+    tag = "settings",
+    request_body = NetworkConfigRequest,
+    responses(
+        (status = 204, description = "Network configuration saved, listeners restarted"),
+        (status = 400, description = "Invalid network configuration", body = ApiError),
+        (status = 500, description = "Unable to read or save the configuration", body = ApiError)
+    )
+)]
 async fn put_network_settings(
     network_settings: NetworkConfigRequest,
     configuration_updater_sender: Sender<Configuration>,
@@ -76,11 +103,19 @@ async fn put_network_settings(
     net_cfg.tls_cert_path = current_cfg.tls_cert_path;
     net_cfg.tls_key_path = current_cfg.tls_key_path;
     net_cfg.listen_url = current_cfg.listen_url;
-    if let Err(err) = &net_cfg.validate().await {
-        log::error!("Invalid network settings: {}", err);
-        return Ok(Box::new(get_error_response(err)));
-    };
-    configuration.network = net_cfg.clone();
+    net_cfg.proxy_protocol = current_cfg.proxy_protocol;
+    net_cfg.proxy_protocol_trusted_networks = current_cfg.proxy_protocol_trusted_networks;
+    net_cfg.secure_proxy_port = current_cfg.secure_proxy_port;
+    net_cfg.proxy_listeners = current_cfg.proxy_listeners;
+    net_cfg.web_listeners = current_cfg.web_listeners;
+    net_cfg.web_allowed_networks = current_cfg.web_allowed_networks;
+    net_cfg.web_host_names = current_cfg.web_host_names;
+    configuration.network = net_cfg;
+    if let Some(response) =
+        super::validation::reject_invalid_section(&configuration, "network").await
+    {
+        return Ok(Box::new(response));
+    }
 
     let guard = configuration_save_lock.lock().await;
     configuration.save().await.unwrap();
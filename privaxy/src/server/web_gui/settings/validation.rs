@@ -0,0 +1,93 @@
+use crate::configuration::{self, Configuration};
+use crate::web_gui::ApiError;
+use std::convert::Infallible;
+use warp::filters::BoxedFilter;
+use warp::http::Response;
+use warp::hyper::body::Bytes;
+use warp::Filter as RouteFilter;
+
+#[utoipa::path(
+    get,
+    path = "/api/settings/validation",
+    tag = "settings",
+    responses((status = 200, description = "Problems found in the saved configuration", body = ValidationReport))
+)]
+async fn get_validation() -> Result<impl warp::Reply, Infallible> {
+    let report = configuration::validate_stored().await;
+
+    Ok(warp::reply::json(&report))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/settings/validation",
+    tag = "settings",
+    request_body(content = String, description = "Configuration file to check, in TOML", content_type = "application/toml"),
+    responses(
+        (status = 200, description = "Problems found in the configuration file", body = ValidationReport),
+        (status = 400, description = "Not UTF-8 text", body = ApiError)
+    )
+)]
+async fn post_validation(body: Bytes) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let contents = match std::str::from_utf8(&body) {
+        Ok(contents) => contents,
+        Err(err) => {
+            return Ok(Box::new(
+                Response::builder()
+                    .status(http::StatusCode::BAD_REQUEST)
+                    .body(
+                        serde_json::to_string(&ApiError {
+                            error: format!("Configuration is not UTF-8 text: {err}"),
+                        })
+                        .unwrap(),
+                    ),
+            ))
+        }
+    };
+
+    let report = configuration::validate_str(contents).await;
+
+    Ok(Box::new(warp::reply::json(&report)))
+}
+
+/// Validates `configuration` as it would be saved, the response to send back when `section`
+/// has errors. Forms get the same messages as `--check-config`.
+pub(crate) async fn reject_invalid_section(
+    configuration: &Configuration,
+    section: &str,
+) -> Option<Response<String>> {
+    let report = configuration.validate().await;
+    let errors = report
+        .section_errors(section)
+        .map(|issue| format!("{}: {}", issue.key, issue.message))
+        .collect::<Vec<_>>();
+
+    if errors.is_empty() {
+        return None;
+    }
+
+    log::error!("Invalid {} settings: {}", section, errors.join("; "));
+
+    Some(
+        Response::builder()
+            .status(http::StatusCode::BAD_REQUEST)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(
+                serde_json::to_string(&ApiError {
+                    error: errors.join("\n"),
+                })
+                .unwrap(),
+            )
+            .unwrap(),
+    )
+}
+
+pub(super) fn create_routes() -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path::end()
+        .and(
+            warp::get().and_then(self::get_validation).or(warp::post()
+                .and(warp::body::bytes())
+                .and_then(self::post_validation)),
+        )
+        .boxed()
+}
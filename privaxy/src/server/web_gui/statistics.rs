@@ -1,12 +1,147 @@
 use futures::{SinkExt, StreamExt};
-use log;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
 use std::time::Duration;
 use tokio::time::sleep;
+use utoipa::{IntoParams, ToSchema};
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
 use warp::ws::{Message, WebSocket};
+use warp::{Filter, Reply};
 
-use crate::statistics::Statistics;
+use crate::statistics::{Statistics, Window};
 
-pub(super) async fn statistics(websocket: WebSocket, statistics: Statistics) {
+#[derive(Debug, Deserialize)]
+pub(super) struct WindowQuery {
+    window: Window,
+}
+
+// Both operations share the path, the WebSocket is documented along with the window.
+/// Without the `window` parameter, upgrades to a WebSocket streaming `SerializableStatistics`
+/// every second.
+#[utoipa::path(
+    get,
+    path = "/api/statistics",
+    tag = "statistics",
+    params(("window" = Option<Window>, Query, description = "Window the counters are computed over. Omitted for the WebSocket.")),
+    responses(
+        (status = 200, description = "Counters over the window", body = SerializableWindowStatistics),
+        (status = 101, description = "Switching to the WebSocket protocol, whose text messages are `SerializableStatistics`"),
+    )
+)]
+async fn get_window(
+    query: WindowQuery,
+    statistics: Statistics,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&statistics.get_window(query.window)))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct BlockedPathsQuery {
+    /// Host the paths were blocked on.
+    domain: String,
+}
+
+/// Counts keyed by path, by descending count as `top_blocked_paths` of the statistics.
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct BlockedPaths(
+    #[serde(with = "tuple_vec_map")]
+    #[schema(value_type = std::collections::HashMap<String, u64>)]
+    Vec<(String, u64)>,
+);
+
+#[utoipa::path(
+    get,
+    path = "/api/statistics/blocked-paths",
+    tag = "statistics",
+    params(BlockedPathsQuery),
+    responses((status = 200, description = "Most blocked paths of the domain, among all those kept", body = BlockedPaths))
+)]
+async fn get_blocked_paths(
+    query: BlockedPathsQuery,
+    statistics: Statistics,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&BlockedPaths(
+        statistics.top_blocked_paths_of(&query.domain),
+    )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/statistics/reset",
+    tag = "statistics",
+    responses((status = 204, description = "Statistics and history cleared"))
+)]
+async fn reset(statistics: Statistics) -> Result<impl warp::Reply, Infallible> {
+    statistics.reset();
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/statistics/cosmetic-exceptions",
+    tag = "statistics",
+    responses((status = 200, description = "Hosts on which `$generichide`, `$specifichide` or `$elemhide` exceptions disabled cosmetic filters, most recent first", body = [CosmeticExceptionHits]))
+)]
+async fn get_cosmetic_exceptions(statistics: Statistics) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&statistics.cosmetic_exceptions()))
+}
+
+pub(super) fn create_routes(statistics: Statistics) -> BoxedFilter<(impl warp::Reply,)> {
+    let with_statistics = {
+        let statistics = statistics.clone();
+        warp::any().map(move || statistics.clone())
+    };
+
+    let websocket_route = warp::path::end()
+        .and(warp::ws())
+        .and(super::origin::is_same_origin())
+        .map(move |ws: warp::ws::Ws, same_origin: bool| {
+            if !same_origin {
+                return super::origin::cross_origin_response().into_response();
+            }
+
+            let statistics = statistics.clone();
+            ws.on_upgrade(move |websocket| self::statistics(websocket, statistics))
+                .into_response()
+        });
+
+    let window_route = warp::path::end()
+        .and(warp::get())
+        .and(warp::query::<WindowQuery>())
+        .and(with_statistics.clone())
+        .and_then(self::get_window);
+
+    let blocked_paths_route = warp::path("blocked-paths")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<BlockedPathsQuery>())
+        .and(with_statistics.clone())
+        .and_then(self::get_blocked_paths);
+
+    let reset_route = warp::path("reset")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(with_statistics.clone())
+        .and_then(self::reset);
+
+    let cosmetic_exceptions_route = warp::path("cosmetic-exceptions")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_statistics)
+        .and_then(self::get_cosmetic_exceptions);
+
+    websocket_route
+        .or(window_route)
+        .or(blocked_paths_route)
+        .or(reset_route)
+        .or(cosmetic_exceptions_route)
+        .boxed()
+}
+
+async fn statistics(websocket: WebSocket, statistics: Statistics) {
     let (mut tx, mut rx) = websocket.split();
 
     // To handle Ping / Pong messages
@@ -0,0 +1,115 @@
+//! Connections to the web server, which is served over TLS when enabled, and to the proxy, which
+//! clients reach over TLS on the secure proxy port.
+use crate::proxy::proxy_protocol::ClientStream;
+use futures::{Stream, StreamExt};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+pub(crate) enum WebStream {
+    Plain(ClientStream),
+    Tls(Box<TlsStream<ClientStream>>),
+}
+
+impl WebStream {
+    pub(crate) fn client_address(&self) -> SocketAddr {
+        match self {
+            WebStream::Plain(stream) => stream.client_address(),
+            WebStream::Tls(stream) => stream.get_ref().0.client_address(),
+        }
+    }
+}
+
+/// Performs the TLS handshakes of `connections` with `tls_acceptor`, or passes them through
+/// without one. Handshakes are performed concurrently, so that slow clients don't hold up the
+/// others, and failed ones are skipped.
+pub(crate) fn accept_tls(
+    connections: impl Stream<Item = io::Result<ClientStream>> + Send + 'static,
+    tls_acceptor: Option<TlsAcceptor>,
+    handshake_timeout: Option<Duration>,
+    concurrent_handshakes: usize,
+) -> impl Stream<Item = io::Result<WebStream>> + Send {
+    connections
+        .map(move |connection| {
+            let tls_acceptor = tls_acceptor.clone();
+
+            async move {
+                let (connection, tls_acceptor) = match (connection, tls_acceptor) {
+                    (Ok(connection), Some(tls_acceptor)) => (connection, tls_acceptor),
+                    (Ok(connection), None) => return Some(Ok(WebStream::Plain(connection))),
+                    (Err(err), _) => return Some(Err(err)),
+                };
+                let client_address = connection.client_address();
+
+                let handshake = tls_acceptor.accept(connection);
+                let handshake = match handshake_timeout {
+                    Some(handshake_timeout) => {
+                        match tokio::time::timeout(handshake_timeout, handshake).await {
+                            Ok(handshake) => handshake,
+                            Err(_) => Err(io::Error::new(
+                                io::ErrorKind::TimedOut,
+                                "TLS handshake timed out",
+                            )),
+                        }
+                    }
+                    None => handshake.await,
+                };
+
+                // A failed handshake only concerns its client, the server keeps accepting.
+                match handshake {
+                    Ok(stream) => Some(Ok(WebStream::Tls(Box::new(stream)))),
+                    Err(err) => {
+                        log::debug!("TLS handshake with {client_address} failed: {err}");
+                        None
+                    }
+                }
+            }
+        })
+        .buffer_unordered(concurrent_handshakes)
+        .filter_map(futures::future::ready)
+}
+
+impl AsyncRead for WebStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            WebStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            WebStream::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for WebStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            WebStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            WebStream::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            WebStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            WebStream::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            WebStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            WebStream::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Running Privaxy as a Windows service, and registering it with the service control manager.
+//!
+//! The service runs as `LocalSystem` and starts with Windows. Its working directory, where
+//! the configuration directory is looked up, is the one `--install-service` is run from.
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{
+    self, ServiceControlHandlerResult, ServiceStatusHandle,
+};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+pub const SERVICE_NAME: &str = "privaxy";
+const SERVICE_DISPLAY_NAME: &str = "Privaxy";
+const SERVICE_DESCRIPTION: &str = "Tracker and advertisement blocking proxy";
+/// Argument the service control manager starts the executable with.
+pub const SERVICE_ARGUMENT: &str = "--service";
+
+/// Registers the current executable as a service started with Windows.
+pub fn install(working_directory: &Path) -> windows_service::Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )?;
+
+    let executable_path = std::env::current_exe().map_err(windows_service::Error::Winapi)?;
+
+    let service_info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from(SERVICE_DISPLAY_NAME),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path,
+        launch_arguments: vec![
+            OsString::from(SERVICE_ARGUMENT),
+            working_directory.as_os_str().to_os_string(),
+        ],
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+
+    let service = manager.create_service(&service_info, ServiceAccess::CHANGE_CONFIG)?;
+    service.set_description(SERVICE_DESCRIPTION)?;
+
+    Ok(())
+}
+
+/// Stops the service if it is running and removes it.
+pub fn uninstall() -> windows_service::Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service = manager.open_service(
+        SERVICE_NAME,
+        ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+    )?;
+
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+
+    service.delete()
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Hands the current thread over to the service control manager until the service stops.
+/// Only works when started by the service control manager.
+pub fn run(working_directory: &Path) -> windows_service::Result<()> {
+    // Services are started from the system directory.
+    std::env::set_current_dir(working_directory).map_err(windows_service::Error::Winapi)?;
+
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(err) = run_service() {
+        log::error!("Windows service failed: {err}");
+    }
+}
+
+fn run_service() -> windows_service::Result<()> {
+    let notify_stop = Arc::new(Notify::new());
+    let notify_stop_handler = notify_stop.clone();
+
+    let status_handle =
+        service_control_handler::register(SERVICE_NAME, move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                notify_stop_handler.notify_one();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+
+    set_status(
+        &status_handle,
+        ServiceState::StartPending,
+        ServiceControlAccept::empty(),
+    )?;
+
+    let runtime = tokio::runtime::Runtime::new().map_err(windows_service::Error::Winapi)?;
+
+    runtime.block_on(async {
+        crate::start_privaxy().await;
+
+        set_status(
+            &status_handle,
+            ServiceState::Running,
+            ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        )?;
+        log::info!("Windows service started");
+
+        notify_stop.notified().await;
+
+        Ok::<_, windows_service::Error>(())
+    })?;
+
+    log::info!("Windows service stopping");
+    set_status(
+        &status_handle,
+        ServiceState::StopPending,
+        ServiceControlAccept::empty(),
+    )?;
+    crate::system_proxy::restore();
+    runtime.shutdown_timeout(Duration::from_secs(5));
+
+    set_status(
+        &status_handle,
+        ServiceState::Stopped,
+        ServiceControlAccept::empty(),
+    )
+}
+
+fn set_status(
+    status_handle: &ServiceStatusHandle,
+    current_state: ServiceState,
+    controls_accepted: ServiceControlAccept,
+) -> windows_service::Result<()> {
+    status_handle.set_service_status(ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state,
+        controls_accepted,
+        exit_code: ServiceExitCode::Win32(0),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    })
+}
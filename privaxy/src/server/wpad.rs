@@ -0,0 +1,33 @@
+//! Serves `wpad.dat` on the port devices detecting proxies automatically request it from, once
+//! `wpad.<domain>` resolves to Privaxy.
+use crate::configuration::WpadConfig;
+use crate::web_gui::pac;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Serves the proxy auto-config until a configuration reload is notified.
+pub(crate) async fn run(configuration: WpadConfig, ip: IpAddr, notify_reload: Arc<Notify>) {
+    if !configuration.enabled {
+        notify_reload.notified().await;
+        return;
+    }
+
+    let address = SocketAddr::from((ip, configuration.port));
+    let notify_shutdown = notify_reload.clone();
+    let server =
+        warp::serve(pac::create_routes()).try_bind_with_graceful_shutdown(address, async move {
+            notify_shutdown.notified().await;
+        });
+
+    match server {
+        Ok((address, server)) => {
+            log::info!("WPAD available at http://{}/wpad.dat", address);
+            server.await;
+        }
+        Err(err) => {
+            log::error!("Unable to serve WPAD on {}: {}", address, err);
+            notify_reload.notified().await;
+        }
+    }
+}
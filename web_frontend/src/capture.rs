@@ -0,0 +1,226 @@
+use crate::button::{get_css, ButtonColor};
+use crate::{failure_banner, ApiError};
+use reqwasm::http::Request;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew::{html, Component, Context, Html};
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CaptureSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    client: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    domain: Option<String>,
+    #[serde(default)]
+    include_bodies: bool,
+    #[serde(default)]
+    include_credentials: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+pub struct CaptureStatus {
+    active: bool,
+    #[serde(default)]
+    settings: Option<CaptureSettings>,
+    entries: usize,
+}
+
+pub enum Message {
+    Load,
+    Display(CaptureStatus),
+    UpdateClient(String),
+    UpdateDomain(String),
+    ToggleBodies,
+    ToggleCredentials,
+    Start,
+    Stop,
+    Failed(ApiError),
+    AcknowledgeError,
+}
+
+pub struct Capture {
+    status: CaptureStatus,
+    client: String,
+    domain: String,
+    include_bodies: bool,
+    include_credentials: bool,
+    error: Option<String>,
+}
+
+fn optional(value: &str) -> Option<String> {
+    let value = value.trim();
+
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+impl Component for Capture {
+    type Message = Message;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(Message::Load);
+
+        Self {
+            status: CaptureStatus::default(),
+            client: String::new(),
+            domain: String::new(),
+            include_bodies: false,
+            include_credentials: false,
+            error: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Message::Load => {
+                let callback = ctx.link().callback(|message: Message| message);
+
+                spawn_local(async move {
+                    match Request::get("/api/capture").send().await {
+                        Ok(response) if response.ok() => {
+                            if let Ok(status) = response.json::<CaptureStatus>().await {
+                                callback.emit(Message::Display(status));
+                            }
+                        }
+                        Ok(response) => {
+                            log::error!("Failed to load capture: {:?}", response.status())
+                        }
+                        Err(err) => log::error!("Failed to load capture: {:?}", err),
+                    }
+                });
+            }
+            Message::Display(status) => {
+                if let Some(settings) = &status.settings {
+                    self.client = settings.client.clone().unwrap_or_default();
+                    self.domain = settings.domain.clone().unwrap_or_default();
+                    self.include_bodies = settings.include_bodies;
+                    self.include_credentials = settings.include_credentials;
+                }
+                self.status = status;
+            }
+            Message::UpdateClient(client) => self.client = client,
+            Message::UpdateDomain(domain) => self.domain = domain,
+            Message::ToggleBodies => self.include_bodies = !self.include_bodies,
+            Message::ToggleCredentials => self.include_credentials = !self.include_credentials,
+            Message::Start | Message::Stop => {
+                let request = match msg {
+                    Message::Start => Request::put("/api/capture")
+                        .header("Content-Type", "application/json")
+                        .body(
+                            serde_json::to_string(&CaptureSettings {
+                                client: optional(&self.client),
+                                domain: optional(&self.domain),
+                                include_bodies: self.include_bodies,
+                                include_credentials: self.include_credentials,
+                            })
+                            .unwrap(),
+                        ),
+                    _ => Request::delete("/api/capture"),
+                };
+                let callback = ctx.link().callback(|message: Message| message);
+
+                spawn_local(async move {
+                    match request.send().await {
+                        Ok(response) if response.ok() => callback.emit(Message::Load),
+                        Ok(response) => match response.json::<ApiError>().await {
+                            Ok(err) => callback.emit(Message::Failed(err)),
+                            Err(err) => callback.emit(Message::Failed(ApiError {
+                                error: format!("{:?}", err),
+                            })),
+                        },
+                        Err(err) => callback.emit(Message::Failed(ApiError {
+                            error: format!("{:?}", err),
+                        })),
+                    }
+                });
+            }
+            Message::Failed(err) => self.error = Some(err.error),
+            Message::AcknowledgeError => self.error = None,
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let input_css = "shadow-sm focus:ring-blue-500 focus:border-blue-500 block w-full sm:text-sm border-gray-300 rounded-md";
+        let oninput_client = ctx.link().callback(|e: InputEvent| {
+            let input = e
+                .target_dyn_into::<HtmlInputElement>()
+                .expect("event target should be an input element");
+            Message::UpdateClient(input.value())
+        });
+        let oninput_domain = ctx.link().callback(|e: InputEvent| {
+            let input = e
+                .target_dyn_into::<HtmlInputElement>()
+                .expect("event target should be an input element");
+            Message::UpdateDomain(input.value())
+        });
+
+        let status = if self.status.active {
+            format!("Capturing, {} requests recorded.", self.status.entries)
+        } else if self.status.settings.is_some() {
+            format!("Stopped, {} requests recorded.", self.status.entries)
+        } else {
+            "Nothing was captured yet.".to_string()
+        };
+
+        let action_button = if self.status.active {
+            html! {
+                <button onclick={ctx.link().callback(|_| Message::Stop)} type="button" class={get_css(ButtonColor::Red)}>{"Stop"}</button>
+            }
+        } else {
+            html! {
+                <button onclick={ctx.link().callback(|_| Message::Start)} type="button" class={get_css(ButtonColor::Blue)}>{"Start capture"}</button>
+            }
+        };
+
+        let download_link = if self.status.entries > 0 {
+            html! {
+                <a href="/api/capture/har" download="" class="inline-flex items-center px-4 py-2 border border-gray-300 shadow-sm text-sm font-medium rounded-md text-gray-700 bg-white hover:bg-gray-50">{"Download HAR"}</a>
+            }
+        } else {
+            html! {}
+        };
+
+        html! {
+            <>
+                <div class="pt-1.5 mb-4">
+                    <h1 class="text-2xl font-bold text-gray-900">{ "Debug capture" }</h1>
+                </div>
+                { failure_banner!(self.error.is_some(), ctx.link().callback(|_| Message::AcknowledgeError), self.error.clone().unwrap_or_default()) }
+                <p class="text-gray-600">
+                    {"Records the requests of a client or a domain, to be downloaded as a HAR file and attached to a report about a site breaking through Privaxy. Bodies may hold personal data, share them with care."}
+                </p>
+                <div class="mt-4 grid grid-cols-1 gap-4 sm:grid-cols-2 max-w-2xl">
+                    <div>
+                        <label for="capture-client" class="block text-sm font-medium text-gray-700">{"Client IP address or username"}</label>
+                        <input id="capture-client" type="text" value={self.client.clone()} oninput={oninput_client} disabled={self.status.active} placeholder="192.168.1.20" class={input_css} />
+                    </div>
+                    <div>
+                        <label for="capture-domain" class="block text-sm font-medium text-gray-700">{"Domain"}</label>
+                        <input id="capture-domain" type="text" value={self.domain.clone()} oninput={oninput_domain} disabled={self.status.active} placeholder="example.com" class={input_css} />
+                    </div>
+                </div>
+                <div class="mt-4 flex items-center">
+                    <input id="capture-bodies" type="checkbox" checked={self.include_bodies} disabled={self.status.active}
+                        onchange={ctx.link().callback(|_| Message::ToggleBodies)}
+                        class="focus:ring-blue-500 h-4 w-4 text-blue-600 border-gray-300 rounded" />
+                    <label for="capture-bodies" class="ml-2 text-sm text-gray-700">{"Record request and response bodies"}</label>
+                </div>
+                <div class="mt-4 flex items-center">
+                    <input id="capture-credentials" type="checkbox" checked={self.include_credentials} disabled={self.status.active}
+                        onchange={ctx.link().callback(|_| Message::ToggleCredentials)}
+                        class="focus:ring-blue-500 h-4 w-4 text-blue-600 border-gray-300 rounded" />
+                    <label for="capture-credentials" class="ml-2 text-sm text-gray-700">{"Record cookies and authorization headers"}</label>
+                </div>
+                <p class="mt-4 text-sm text-gray-500">{ status }</p>
+                <div class="mt-4 flex space-x-4">
+                    { action_button }
+                    { download_link }
+                </div>
+            </>
+        }
+    }
+}
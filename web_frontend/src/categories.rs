@@ -0,0 +1,303 @@
+use crate::button::ButtonState;
+use crate::{failure_banner, save_button, success_banner, ApiError};
+use reqwasm::http::Request;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew::{html, Callback, Component, Context, Html};
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Adult,
+    Gambling,
+    Social,
+    Ads,
+}
+
+impl Category {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Category::Adult => "Adult",
+            Category::Gambling => "Gambling",
+            Category::Social => "Social",
+            Category::Ads => "Ads",
+        }
+    }
+
+    pub fn values() -> Vec<Self> {
+        vec![
+            Category::Adult,
+            Category::Gambling,
+            Category::Social,
+            Category::Ads,
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ClientCategories {
+    /// IP address or username of the client.
+    client: String,
+    blocked: BTreeSet<Category>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CategoriesConfig {
+    /// Categories blocked for clients without their own entry.
+    blocked: BTreeSet<Category>,
+    clients: Vec<ClientCategories>,
+}
+
+pub enum Message {
+    Load,
+    Display(CategoriesConfig),
+    /// `None` toggles the categories of clients without their own entry.
+    Toggle(Option<usize>, Category),
+    UpdateClient(usize, String),
+    AddClient,
+    RemoveClient(usize),
+    Save,
+    SaveSuccess,
+    SaveFailed(ApiError),
+    AcknowledgeSuccess,
+    AcknowledgeError,
+}
+
+pub struct Categories {
+    configuration: Option<CategoriesConfig>,
+    configuration_before_changes: Option<CategoriesConfig>,
+    show_success: bool,
+    error: Option<String>,
+}
+
+impl Categories {
+    fn configuration_has_changed(&self) -> bool {
+        self.configuration != self.configuration_before_changes
+    }
+}
+
+fn toggle(blocked: &mut BTreeSet<Category>, category: Category) {
+    if !blocked.remove(&category) {
+        blocked.insert(category);
+    }
+}
+
+impl Component for Categories {
+    type Message = Message;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(Message::Load);
+
+        Self {
+            configuration: None,
+            configuration_before_changes: None,
+            show_success: false,
+            error: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Message::Load => {
+                let callback = ctx.link().callback(|message: Message| message);
+
+                spawn_local(async move {
+                    match Request::get("/api/settings/categories").send().await {
+                        Ok(response) if response.ok() => {
+                            if let Ok(configuration) = response.json::<CategoriesConfig>().await {
+                                callback.emit(Message::Display(configuration));
+                            }
+                        }
+                        Ok(response) => {
+                            log::error!("Failed to load categories: {:?}", response.status())
+                        }
+                        Err(err) => log::error!("Failed to load categories: {:?}", err),
+                    }
+                });
+            }
+            Message::Display(configuration) => {
+                self.configuration = Some(configuration.clone());
+                self.configuration_before_changes = Some(configuration);
+            }
+            Message::Toggle(index, category) => {
+                if let Some(configuration) = self.configuration.as_mut() {
+                    match index {
+                        None => toggle(&mut configuration.blocked, category),
+                        Some(index) => {
+                            if let Some(client) = configuration.clients.get_mut(index) {
+                                toggle(&mut client.blocked, category);
+                            }
+                        }
+                    }
+                }
+            }
+            Message::UpdateClient(index, value) => {
+                if let Some(client) = self
+                    .configuration
+                    .as_mut()
+                    .and_then(|configuration| configuration.clients.get_mut(index))
+                {
+                    client.client = value;
+                }
+            }
+            Message::AddClient => {
+                if let Some(configuration) = self.configuration.as_mut() {
+                    configuration.clients.push(ClientCategories {
+                        client: String::new(),
+                        blocked: configuration.blocked.clone(),
+                    });
+                }
+            }
+            Message::RemoveClient(index) => {
+                if let Some(configuration) = self.configuration.as_mut() {
+                    if index < configuration.clients.len() {
+                        configuration.clients.remove(index);
+                    }
+                }
+            }
+            Message::Save => {
+                if !self.configuration_has_changed() {
+                    return false;
+                }
+                let configuration = self.configuration.clone().unwrap();
+                let request = Request::put("/api/settings/categories")
+                    .header("Content-Type", "application/json")
+                    .body(serde_json::to_string(&configuration).unwrap());
+                let callback = ctx.link().callback(|message: Message| message);
+
+                spawn_local(async move {
+                    match request.send().await {
+                        Ok(response) if response.ok() => callback.emit(Message::SaveSuccess),
+                        Ok(response) => match response.json::<ApiError>().await {
+                            Ok(err) => callback.emit(Message::SaveFailed(err)),
+                            Err(err) => callback.emit(Message::SaveFailed(ApiError {
+                                error: format!("{:?}", err),
+                            })),
+                        },
+                        Err(err) => callback.emit(Message::SaveFailed(ApiError {
+                            error: format!("{:?}", err),
+                        })),
+                    }
+                });
+            }
+            Message::SaveSuccess => {
+                // Empty clients are dropped when saving.
+                ctx.link().send_message(Message::Load);
+                self.show_success = true;
+            }
+            Message::SaveFailed(err) => self.error = Some(err.error),
+            Message::AcknowledgeSuccess => self.show_success = false,
+            Message::AcknowledgeError => self.error = None,
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let title = html! {
+            <div class="pt-1.5 mb-4">
+                <h1 class="text-2xl font-bold text-gray-900">{ "Categories" }</h1>
+            </div>
+        };
+
+        let configuration = match &self.configuration {
+            Some(configuration) => configuration,
+            None => return title,
+        };
+
+        let render_checkboxes = |index: Option<usize>, blocked: &BTreeSet<Category>| -> Html {
+            Category::values()
+                .into_iter()
+                .map(|category| {
+                    let onchange = ctx
+                        .link()
+                        .callback(move |_| Message::Toggle(index, category));
+                    html! {
+                        <td class="px-3 py-4 text-center">
+                            <input checked={blocked.contains(&category)} {onchange} type="checkbox"
+                                class="focus:ring-blue-500 h-4 w-4 text-blue-600 border-gray-300 rounded" />
+                        </td>
+                    }
+                })
+                .collect()
+        };
+
+        let client_rows = configuration
+            .clients
+            .iter()
+            .enumerate()
+            .map(|(index, client)| {
+                let oninput = ctx.link().callback(move |e: InputEvent| {
+                    let input = e
+                        .target_dyn_into::<HtmlInputElement>()
+                        .expect("event target should be an input element");
+                    Message::UpdateClient(index, input.value())
+                });
+                let onclick = ctx.link().callback(move |_| Message::RemoveClient(index));
+
+                html! {
+                    <tr>
+                        <td class="py-4 pr-3">
+                            <input type="text" value={client.client.clone()} {oninput} placeholder="IP address or username"
+                                class="shadow-sm focus:ring-blue-500 focus:border-blue-500 block w-full sm:text-sm border-gray-300 rounded-md" />
+                        </td>
+                        { render_checkboxes(Some(index), &client.blocked) }
+                        <td class="px-3 py-4 text-right">
+                            <button {onclick} type="button" class="text-sm text-red-600 hover:text-red-900">{"Remove"}</button>
+                        </td>
+                    </tr>
+                }
+            })
+            .collect::<Html>();
+
+        let save_button_state = if self.configuration_has_changed() {
+            ButtonState::Enabled
+        } else {
+            ButtonState::Disabled
+        };
+        let save_callback = ctx.link().callback(|_| Message::Save);
+
+        html! {
+            <>
+                { title }
+                { success_banner!(self.show_success, ctx.link().callback(|_| Message::AcknowledgeSuccess)) }
+                { failure_banner!(self.error.is_some(), ctx.link().callback(|_| Message::AcknowledgeError), self.error.clone().unwrap_or_default()) }
+                <p class="text-gray-600">
+                    {"Requests to domains of the checked categories are blocked. Clients are matched by username when authenticated, by IP address otherwise."}
+                </p>
+                <table class="mt-4 min-w-full divide-y divide-gray-200">
+                    <thead>
+                        <tr>
+                            <th class="py-3 pr-3 text-left text-sm font-medium text-gray-900">{"Client"}</th>
+                            { for Category::values().into_iter().map(|category| html! {
+                                <th class="px-3 py-3 text-center text-sm font-medium text-gray-900">{category.as_str()}</th>
+                            }) }
+                            <th></th>
+                        </tr>
+                    </thead>
+                    <tbody class="divide-y divide-gray-200">
+                        <tr>
+                            <td class="py-4 pr-3 text-sm text-gray-900">{"Everyone else"}</td>
+                            { render_checkboxes(None, &configuration.blocked) }
+                            <td></td>
+                        </tr>
+                        { client_rows }
+                    </tbody>
+                </table>
+                <div class="flex space-x-4">
+                    <div class="mt-5">
+                        <button onclick={ctx.link().callback(|_| Message::AddClient)} type="button"
+                            class="inline-flex items-center px-4 py-2 border border-gray-300 shadow-sm text-sm font-medium rounded-md text-gray-700 bg-white hover:bg-gray-50">
+                            {"Add client"}
+                        </button>
+                    </div>
+                    { save_button!(save_callback, save_button_state) }
+                </div>
+            </>
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::i18n::{t, t_with};
+use yew::{html, Html};
+
+const HEIGHT: f64 = 100.0;
+
+/// Line chart of evenly spaced values, scaled to the largest one. Missing values break the line.
+pub fn line_chart(title: &str, unit: &str, values: &[Option<f64>]) -> Html {
+    let maximum = values.iter().flatten().copied().fold(0.0_f64, f64::max);
+    let scale = if maximum > 0.0 { HEIGHT / maximum } else { 0.0 };
+    let width = values.len().max(1) as f64;
+
+    let lines = values
+        .split(|value| value.is_none())
+        .scan(0, |start, segment| {
+            let segment_start = *start;
+            *start += segment.len() + 1;
+
+            Some((segment_start, segment))
+        })
+        .filter(|(_start, segment)| !segment.is_empty())
+        .map(|(start, segment)| {
+            segment
+                .iter()
+                .enumerate()
+                .map(|(index, value)| {
+                    format!(
+                        "{},{:.2}",
+                        start + index,
+                        HEIGHT - value.unwrap_or_default() * scale
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>();
+
+    let latest = values.iter().rev().flatten().next().copied();
+
+    html! {
+        <div class="mt-4 bg-white overflow-hidden shadow rounded-lg divide-y divide-gray-200">
+            <div class="px-4 py-5 sm:px-6 flex justify-between items-baseline">
+                <h3 class="text-lg font-medium">{title}</h3>
+                <span class="text-sm text-gray-500">
+                    { match latest {
+                        Some(latest) => format!(
+                            "{:.2} {} ({})",
+                            latest,
+                            unit,
+                            t_with("chart-maximum", &[("maximum", format!("{:.2}", maximum).into())])
+                        ),
+                        None => t("chart-no-data"),
+                    } }
+                </span>
+            </div>
+            <div class="px-4 py-5 sm:p-6">
+                <svg class="w-full h-32" viewBox={format!("0 0 {} {}", width, HEIGHT)} preserveAspectRatio="none">
+                    { for lines.into_iter().map(|points| html! {
+                        <polyline points={points} fill="none" stroke="#2563eb" stroke-width="2"
+                            vector-effect="non-scaling-stroke" />
+                    }) }
+                </svg>
+                <div class="flex justify-between text-xs text-gray-400">
+                    <span>{t("chart-start")}</span>
+                    <span>{t("chart-now")}</span>
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,50 @@
+use crate::button::{ButtonColor, ButtonState, PrivaxyButton};
+use serde::Deserialize;
+use yew::{function_component, html, Callback, Properties};
+
+/// Body of `409 Conflict` responses, when the configuration was saved from elsewhere since it was
+/// loaded.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RevisionConflict {
+    pub error: String,
+    pub revision: u64,
+}
+
+impl RevisionConflict {
+    /// Value of `If-Match` overwriting the configuration anyway.
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.revision)
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    /// Discards the changes and loads the saved configuration.
+    pub on_reload: Callback<()>,
+    /// Saves the changes over the ones made elsewhere.
+    pub on_overwrite: Callback<()>,
+}
+
+#[function_component(ConflictDialog)]
+pub fn conflict_dialog(props: &Props) -> Html {
+    let on_reload = props.on_reload.reform(|_| ());
+    let on_overwrite = props.on_overwrite.reform(|_| ());
+
+    html! {
+        <div class="fixed inset-0 bg-gray-600 bg-opacity-75 flex items-center justify-center z-50">
+            <div class="bg-white p-6 rounded-lg shadow-lg max-w-lg">
+                <h3 class="text-lg font-medium text-gray-900">{"The configuration was changed elsewhere"}</h3>
+                <p class="mt-2 text-sm text-gray-600">
+                    {"It was saved from another tab or client since this page was loaded. "}
+                    {"Reload it to see those changes, which discards yours, or overwrite them with yours."}
+                </p>
+                <div class="mt-5 flex justify-end space-x-4">
+                    <PrivaxyButton state={ButtonState::Enabled} onclick={on_reload}
+                        color={ButtonColor::Gray} button_text={"Reload"} />
+                    <PrivaxyButton state={ButtonState::Enabled} onclick={on_overwrite}
+                        color={ButtonColor::Red} button_text={"Overwrite"} />
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -1,28 +1,277 @@
 use crate::blocking_enabled::BlockingEnabled;
+use crate::charts::line_chart;
+use crate::i18n::{t, t_with};
+use crate::profile_select::ProfileSelect;
 use futures::future::{AbortHandle, Abortable};
 use futures::StreamExt;
 use gloo_timers::future::TimeoutFuture;
 use num_format::{Locale, ToFormattedString};
+use reqwasm::http::Request;
 use reqwasm::websocket::futures::WebSocket;
 use serde::Deserialize;
 use std::io::Cursor;
 use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlSelectElement;
+use yew::prelude::*;
 use yew::{html, Component, Context, Html};
 
-#[derive(Debug, Deserialize, PartialEq, Eq)]
-pub struct Message {
+/// How often the history is reloaded, it has a one minute resolution.
+const HISTORY_RELOAD_INTERVAL_MS: u32 = 60_000;
+
+/// Windows counters can be queried over, along with their label.
+const WINDOWS: [(&str, &str); 3] = [
+    ("1h", "dashboard-last-hour"),
+    ("24h", "dashboard-last-day"),
+    ("7d", "dashboard-last-week"),
+];
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Statistics {
     proxied_requests: Option<u64>,
     blocked_requests: Option<u64>,
     modified_responses: Option<u64>,
+    #[serde(default)]
+    threats_blocked: Option<u64>,
+    #[serde(default)]
+    blocked_by_category: Option<BlockCategoryCounts>,
+    #[serde(default, with = "tuple_vec_map")]
+    blocked_by_list: Vec<(String, u64)>,
     #[serde(with = "tuple_vec_map")]
     top_blocked_paths: Vec<(String, u64)>,
+    #[serde(default, with = "tuple_vec_map")]
+    top_blocked_domains: Vec<(String, u64)>,
     #[serde(with = "tuple_vec_map")]
     top_clients: Vec<(String, u64)>,
+    #[serde(default, with = "tuple_vec_map")]
+    top_countries: Vec<(String, u64)>,
+    #[serde(default, with = "tuple_vec_map")]
+    top_asns: Vec<(String, u64)>,
+    #[serde(default)]
+    users: Vec<UserStatistics>,
+    #[serde(default)]
+    tunneled_connections: Option<u64>,
+    #[serde(default)]
+    top_tunneled: Vec<TunnelStatistics>,
+    #[serde(default)]
+    engines: Vec<EngineStatistics>,
+    #[serde(default)]
+    runtime: Option<RuntimeStatistics>,
+    #[serde(default)]
+    responses: Option<ResponseBreakdown>,
+    #[serde(default)]
+    slowest_origins: Vec<OriginLatency>,
+    #[serde(default)]
+    overhead: Option<ProxyOverhead>,
+    #[serde(default)]
+    anomalies: Vec<Anomaly>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct BlockCategoryCounts {
+    ads: u64,
+    trackers: u64,
+    malware: u64,
+    custom: u64,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct Anomaly {
+    client: Option<String>,
+    series: AnomalySeries,
+    count: u64,
+    average: u64,
 }
 
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalySeries {
+    Requests,
+    BlockedRequests,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct ProxyOverhead {
+    requests: u64,
+    average_added_latency_us: f64,
+    p95_added_latency_us: u64,
+    average_blocker_us: f64,
+    rewritten_documents: u64,
+    average_rewriter_us: f64,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct OriginLatency {
+    host: String,
+    requests: u64,
+    average_time_to_first_byte_ms: f64,
+    p95_time_to_first_byte_ms: u64,
+    average_total_duration_ms: Option<f64>,
+    p95_total_duration_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct StatusClassCounts {
+    informational: u64,
+    success: u64,
+    redirection: u64,
+    client_error: u64,
+    server_error: u64,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct SchemeCounts {
+    http: u64,
+    https: u64,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct HttpVersionCounts {
+    http_1_0: u64,
+    http_1_1: u64,
+    http_2: u64,
+    http_3: u64,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct ResponseBreakdown {
+    status_classes: StatusClassCounts,
+    schemes: SchemeCounts,
+    versions: HttpVersionCounts,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct RuntimeStatistics {
+    workers: u64,
+    worker_utilization: f64,
+    alive_tasks: u64,
+    global_queue_depth: u64,
+    blocker_backlog: u64,
+    certificate_queue: u64,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct EngineStatistics {
+    profile: String,
+    network_rules: u64,
+    cosmetic_rules: u64,
+    memory_bytes: u64,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct TunnelStatistics {
+    authority: String,
+    connections: u64,
+    bytes: u64,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct UserStatistics {
+    username: String,
+    proxied_requests: u64,
+    blocked_requests: u64,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct WindowStatistics {
+    proxied_requests: u64,
+    blocked_requests: u64,
+    modified_responses: u64,
+    threats_blocked: u64,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct HistoryPoint {
+    requests_per_second: f64,
+    blocked_per_second: f64,
+    average_latency_ms: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct History {
+    points: Vec<HistoryPoint>,
+}
+
+pub enum Message {
+    Statistics(Statistics),
+    History(History),
+    /// Counters since start when `None`.
+    SelectWindow(Option<String>),
+    WindowStatistics(WindowStatistics),
+    Reset,
+    /// Shows the blocked paths of a domain only, all of them when `None`.
+    SelectDomain(Option<String>),
+    /// Blocked paths of a domain, loaded once it is selected.
+    DomainPaths(String, BlockedPaths),
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct BlockedPaths(#[serde(with = "tuple_vec_map")] Vec<(String, u64)>);
+
 pub struct Dashboard {
-    message: Message,
+    statistics: Statistics,
+    selected_domain: Option<String>,
+    /// Of `selected_domain`, among all blocked paths rather than the overall top ones.
+    selected_domain_paths: Vec<(String, u64)>,
+    selected_window: Option<String>,
+    window_statistics: Option<WindowStatistics>,
+    history: Option<History>,
     ws_abort_handle: AbortHandle,
+    history_abort_handle: AbortHandle,
+}
+
+impl Dashboard {
+    fn load_domain_paths(&self, ctx: &Context<Self>) {
+        let domain = match &self.selected_domain {
+            Some(domain) => domain.clone(),
+            None => return,
+        };
+        let url = format!(
+            "/api/statistics/blocked-paths?{}",
+            url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("domain", &domain)
+                .finish()
+        );
+        let callback = ctx
+            .link()
+            .callback(move |paths| Message::DomainPaths(domain.clone(), paths));
+
+        spawn_local(async move {
+            match Request::get(&url).send().await {
+                Ok(response) if response.ok() => {
+                    if let Ok(paths) = response.json::<BlockedPaths>().await {
+                        callback.emit(paths);
+                    }
+                }
+                Ok(response) => {
+                    log::error!("Failed to load blocked paths: {:?}", response.status())
+                }
+                Err(err) => log::error!("Failed to load blocked paths: {:?}", err),
+            }
+        });
+    }
+
+    fn load_window_statistics(&self, ctx: &Context<Self>) {
+        let window = match &self.selected_window {
+            Some(window) => window.clone(),
+            None => return,
+        };
+        let callback = ctx.link().callback(Message::WindowStatistics);
+
+        spawn_local(async move {
+            let url = format!("/api/statistics?window={}", window);
+            match Request::get(&url).send().await {
+                Ok(response) if response.ok() => {
+                    if let Ok(statistics) = response.json::<WindowStatistics>().await {
+                        callback.emit(statistics);
+                    }
+                }
+                Ok(response) => {
+                    log::error!("Failed to load statistics: {:?}", response.status())
+                }
+                Err(err) => log::error!("Failed to load statistics: {:?}", err),
+            }
+        });
+    }
 }
 
 impl Component for Dashboard {
@@ -30,7 +279,7 @@ impl Component for Dashboard {
     type Properties = ();
 
     fn create(ctx: &Context<Self>) -> Self {
-        let message_callback = ctx.link().callback(|message: Message| message);
+        let message_callback = ctx.link().callback(Message::Statistics);
 
         let (abort_handle, abort_registration) = AbortHandle::new_pair();
         let future = Abortable::new(
@@ -57,7 +306,7 @@ impl Component for Dashboard {
                                         let cursor = Cursor::new(s.as_bytes());
                                         let mut deserializer =
                                             serde_json::Deserializer::from_reader(cursor)
-                                                .into_iter::<Message>();
+                                                .into_iter::<Statistics>();
 
                                         match deserializer.next() {
                                             Some(Ok(message)) => message,
@@ -96,30 +345,130 @@ impl Component for Dashboard {
             let _result = future.await;
         });
 
+        let history_callback = ctx.link().callback(Message::History);
+        let (history_abort_handle, history_abort_registration) = AbortHandle::new_pair();
+        let history_future = Abortable::new(
+            async move {
+                loop {
+                    match Request::get("/api/metrics/history").send().await {
+                        Ok(response) if response.ok() => match response.json::<History>().await {
+                            Ok(history) => history_callback.emit(history),
+                            Err(err) => log::error!("Failed to deserialize history: {:?}", err),
+                        },
+                        Ok(response) => {
+                            log::error!("Failed to load history: {:?}", response.status())
+                        }
+                        Err(err) => log::error!("Failed to load history: {:?}", err),
+                    }
+
+                    TimeoutFuture::new(HISTORY_RELOAD_INTERVAL_MS).await;
+                }
+            },
+            history_abort_registration,
+        );
+
+        spawn_local(async {
+            let _result = history_future.await;
+        });
+
         Self {
             ws_abort_handle: abort_handle,
-            message: Message {
+            history_abort_handle,
+            history: None,
+            selected_domain: None,
+            selected_domain_paths: Vec::new(),
+            selected_window: None,
+            window_statistics: None,
+            statistics: Statistics {
                 proxied_requests: None,
                 blocked_requests: None,
                 modified_responses: None,
+                threats_blocked: None,
+                blocked_by_category: None,
+                blocked_by_list: Vec::new(),
                 top_blocked_paths: Vec::new(),
+                top_blocked_domains: Vec::new(),
                 top_clients: Vec::new(),
+                top_countries: Vec::new(),
+                top_asns: Vec::new(),
+                users: Vec::new(),
+                tunneled_connections: None,
+                top_tunneled: Vec::new(),
+                engines: Vec::new(),
+                runtime: None,
             },
         }
     }
 
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
-        let update = self.message != msg;
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Message::Statistics(statistics) => {
+                let update = self.statistics != statistics;
+                if update {
+                    self.load_window_statistics(ctx);
+                }
+
+                self.statistics = statistics;
+                update
+            }
+            Message::History(history) => {
+                let update = self.history.as_ref() != Some(&history);
+
+                self.history = Some(history);
+                update
+            }
+            Message::SelectWindow(window) => {
+                self.selected_window = window;
+                self.window_statistics = None;
+                self.load_window_statistics(ctx);
+                true
+            }
+            Message::WindowStatistics(statistics) => {
+                let update = self.window_statistics.as_ref() != Some(&statistics);
+
+                self.window_statistics = Some(statistics);
+                update
+            }
+            Message::Reset => {
+                let confirmed = web_sys::window()
+                    .and_then(|window| {
+                        window
+                            .confirm_with_message(&t("dashboard-reset-confirm"))
+                            .ok()
+                    })
+                    .unwrap_or(false);
 
-        self.message = msg;
-        update
+                if confirmed {
+                    spawn_local(async {
+                        if let Err(err) = Request::post("/api/statistics/reset").send().await {
+                            log::error!("Failed to reset statistics: {:?}", err);
+                        }
+                    });
+                }
+                false
+            }
+            Message::SelectDomain(domain) => {
+                self.selected_domain = domain;
+                self.selected_domain_paths.clear();
+                self.load_domain_paths(ctx);
+                true
+            }
+            Message::DomainPaths(domain, BlockedPaths(paths)) => {
+                // Ignored when another domain was selected in the meantime.
+                if self.selected_domain.as_deref() != Some(domain.as_str()) {
+                    return false;
+                }
+                self.selected_domain_paths = paths;
+                true
+            }
+        }
     }
 
-    fn view(&self, _ctx: &Context<Self>) -> Html {
+    fn view(&self, ctx: &Context<Self>) -> Html {
         fn some_or_loading(s: Option<u64>) -> String {
             match s {
                 Some(s) => s.to_formatted_string(&Locale::en),
-                None => "Loading".to_string(),
+                None => t("dashboard-loading"),
             }
         }
 
@@ -137,11 +486,516 @@ impl Component for Dashboard {
                  }
         }
 
+        fn render_user_element(user: &UserStatistics) -> Html {
+            html! {
+            <li class="relative bg-white py-5 px-4">
+                <div class="flex justify-between space-x-3">
+                    <div class="min-w-0 flex-1">
+                        <p class="text-sm font-medium text-gray-900 truncate">{ &user.username }</p>
+                    </div>
+                    <div class="flex-shrink-0 whitespace-nowrap text-sm text-gray-500">
+                        { format!("{} proxied, {} blocked",
+                        user.proxied_requests.to_formatted_string(&Locale::en),
+                        user.blocked_requests.to_formatted_string(&Locale::en)) }
+                    </div>
+                </div>
+            </li>
+                 }
+        }
+
+        fn format_bytes(bytes: u64) -> String {
+            const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+            let mut value = bytes as f64;
+            let mut unit = 0;
+            while value >= 1024.0 && unit < UNITS.len() - 1 {
+                value /= 1024.0;
+                unit += 1;
+            }
+
+            if unit == 0 {
+                format!("{} {}", bytes, UNITS[0])
+            } else {
+                format!("{:.1} {}", value, UNITS[unit])
+            }
+        }
+
+        fn render_tunnel_element(tunnel: &TunnelStatistics) -> Html {
+            html! {
+            <li class="relative bg-white py-5 px-4">
+                <div class="flex justify-between space-x-3">
+                    <div class="min-w-0 flex-1">
+                        <p class="text-sm font-medium text-gray-900 truncate">{ &tunnel.authority }</p>
+                    </div>
+                    <div class="flex-shrink-0 whitespace-nowrap text-sm text-gray-500">
+                        { t_with("dashboard-tunneled-summary", &[
+                            ("connections", tunnel.connections.to_formatted_string(&Locale::en).into()),
+                            ("size", format_bytes(tunnel.bytes).into()),
+                        ]) }
+                    </div>
+                </div>
+            </li>
+                 }
+        }
+
+        fn render_engine_element(engine: &EngineStatistics) -> Html {
+            html! {
+            <li class="relative bg-white py-5 px-4">
+                <div class="flex justify-between space-x-3">
+                    <div class="min-w-0 flex-1">
+                        <p class="text-sm font-medium text-gray-900 truncate">{ &engine.profile }</p>
+                    </div>
+                    <div class="flex-shrink-0 whitespace-nowrap text-sm text-gray-500">
+                        { t_with("dashboard-engine-summary", &[
+                            ("network", engine.network_rules.to_formatted_string(&Locale::en).into()),
+                            ("cosmetic", engine.cosmetic_rules.to_formatted_string(&Locale::en).into()),
+                            ("size", format_bytes(engine.memory_bytes).into()),
+                        ]) }
+                    </div>
+                </div>
+            </li>
+                 }
+        }
+
+        let engines = if self.statistics.engines.is_empty() {
+            html! {}
+        } else {
+            html! {
+                <div class="mt-4 bg-white overflow-hidden shadow rounded-lg divide-y divide-gray-200">
+                    <div class="px-4 py-5 sm:px-6">
+                        <h3 class="text-lg font-medium">{t("dashboard-engines")}</h3>
+                    </div>
+                    <div class="px-4 py-5 sm:p-6">
+                        <ol role="list" class="divide-y divide-gray-200">
+                            { for self.statistics.engines.iter().map(render_engine_element) }
+                        </ol>
+                    </div>
+                </div>
+            }
+        };
+
+        let runtime = match &self.statistics.runtime {
+            Some(runtime) => {
+                let render_value = |label: &str, value: String| {
+                    html! {
+                    <li class="relative bg-white py-5 px-4">
+                        <div class="flex justify-between space-x-3">
+                            <div class="min-w-0 flex-1">
+                                <p class="text-sm font-medium text-gray-900 truncate">{ label }</p>
+                            </div>
+                            <div class="flex-shrink-0 whitespace-nowrap text-sm text-gray-500">{ value }</div>
+                        </div>
+                    </li>
+                         }
+                };
+
+                html! {
+                    <div class="mt-4 bg-white overflow-hidden shadow rounded-lg divide-y divide-gray-200">
+                        <div class="px-4 py-5 sm:px-6">
+                            <h3 class="text-lg font-medium">{t("dashboard-runtime")}</h3>
+                        </div>
+                        <div class="px-4 py-5 sm:p-6">
+                            <ol role="list" class="divide-y divide-gray-200">
+                                { render_value(&t("dashboard-runtime-utilization"), t_with("dashboard-runtime-utilization-value", &[
+                                    ("percent", format!("{:.0}", runtime.worker_utilization * 100.0).into()),
+                                    ("workers", runtime.workers.into()),
+                                ])) }
+                                { render_value(&t("dashboard-runtime-tasks"), runtime.alive_tasks.to_formatted_string(&Locale::en)) }
+                                { render_value(&t("dashboard-runtime-queue"), runtime.global_queue_depth.to_formatted_string(&Locale::en)) }
+                                { render_value(&t("dashboard-runtime-blocker-backlog"), runtime.blocker_backlog.to_formatted_string(&Locale::en)) }
+                                { render_value(&t("dashboard-runtime-certificate-queue"), runtime.certificate_queue.to_formatted_string(&Locale::en)) }
+                            </ol>
+                        </div>
+                    </div>
+                }
+            }
+            None => html! {},
+        };
+
+        let responses = match &self.statistics.responses {
+            Some(responses) => {
+                let total = responses.schemes.http + responses.schemes.https;
+                let render_value = |label: &str, count: u64| {
+                    let share = if total == 0 {
+                        0.0
+                    } else {
+                        count as f64 * 100.0 / total as f64
+                    };
+
+                    html! {
+                    <li class="relative bg-white py-3 px-4">
+                        <div class="flex justify-between space-x-3">
+                            <div class="min-w-0 flex-1">
+                                <p class="text-sm font-medium text-gray-900 truncate">{ label }</p>
+                            </div>
+                            <div class="flex-shrink-0 whitespace-nowrap text-sm text-gray-500">
+                                { format!("{} ({:.1}%)", count.to_formatted_string(&Locale::en), share) }
+                            </div>
+                        </div>
+                    </li>
+                         }
+                };
+                let status_classes = &responses.status_classes;
+                let schemes = &responses.schemes;
+                let versions = &responses.versions;
+
+                html! {
+                    <div class="mt-4 bg-white overflow-hidden shadow rounded-lg divide-y divide-gray-200">
+                        <div class="px-4 py-5 sm:px-6 flex justify-between items-baseline">
+                            <h3 class="text-lg font-medium">{t("dashboard-responses")}</h3>
+                            <span class="text-sm text-gray-500">{ total.to_formatted_string(&Locale::en) }</span>
+                        </div>
+                        <div class="px-4 py-5 sm:p-6">
+                            <h4 class="text-sm font-medium text-gray-500">{t("dashboard-responses-status")}</h4>
+                            <ol role="list" class="divide-y divide-gray-200">
+                                { render_value("1xx", status_classes.informational) }
+                                { render_value("2xx", status_classes.success) }
+                                { render_value("3xx", status_classes.redirection) }
+                                { render_value("4xx", status_classes.client_error) }
+                                { render_value("5xx", status_classes.server_error) }
+                            </ol>
+                            <h4 class="mt-4 text-sm font-medium text-gray-500">{t("dashboard-responses-scheme")}</h4>
+                            <ol role="list" class="divide-y divide-gray-200">
+                                { render_value("HTTP", schemes.http) }
+                                { render_value("HTTPS", schemes.https) }
+                            </ol>
+                            <h4 class="mt-4 text-sm font-medium text-gray-500">{t("dashboard-responses-version")}</h4>
+                            <ol role="list" class="divide-y divide-gray-200">
+                                { render_value("HTTP/1.0", versions.http_1_0) }
+                                { render_value("HTTP/1.1", versions.http_1_1) }
+                                { render_value("HTTP/2", versions.http_2) }
+                                { render_value("HTTP/3", versions.http_3) }
+                            </ol>
+                        </div>
+                    </div>
+                }
+            }
+            None => html! {},
+        };
+
+        // Tells slow sites apart from time spent in Privaxy.
+        let slowest_origins = if self.statistics.slowest_origins.is_empty() {
+            html! {}
+        } else {
+            let render_origin = |origin: &OriginLatency| {
+                let total_duration = match (
+                    origin.average_total_duration_ms,
+                    origin.p95_total_duration_ms,
+                ) {
+                    (Some(average), Some(p95)) => format!("{:.0} / {} ms", average, p95),
+                    _ => "-".to_string(),
+                };
+
+                html! {
+                    <tr>
+                        <td class="py-3 pr-3 text-sm font-medium text-gray-900 truncate">{ &origin.host }</td>
+                        <td class="px-3 py-3 text-right text-sm text-gray-500">{ origin.requests.to_formatted_string(&Locale::en) }</td>
+                        <td class="px-3 py-3 text-right text-sm text-gray-500 whitespace-nowrap">
+                            { format!("{:.0} / {} ms", origin.average_time_to_first_byte_ms, origin.p95_time_to_first_byte_ms) }
+                        </td>
+                        <td class="pl-3 py-3 text-right text-sm text-gray-500 whitespace-nowrap">{ total_duration }</td>
+                    </tr>
+                }
+            };
+
+            html! {
+                <div class="mt-4 bg-white overflow-hidden shadow rounded-lg divide-y divide-gray-200">
+                    <div class="px-4 py-5 sm:px-6">
+                        <h3 class="text-lg font-medium">{t("dashboard-slowest-origins")}</h3>
+                    </div>
+                    <div class="px-4 py-5 sm:p-6 overflow-x-auto">
+                        <table class="min-w-full divide-y divide-gray-200">
+                            <thead>
+                                <tr>
+                                    <th class="py-3 pr-3 text-left text-sm font-medium text-gray-900">{t("dashboard-slowest-origins-host")}</th>
+                                    <th class="px-3 py-3 text-right text-sm font-medium text-gray-900">{t("dashboard-slowest-origins-requests")}</th>
+                                    <th class="px-3 py-3 text-right text-sm font-medium text-gray-900">{t("dashboard-slowest-origins-ttfb")}</th>
+                                    <th class="pl-3 py-3 text-right text-sm font-medium text-gray-900">{t("dashboard-slowest-origins-total")}</th>
+                                </tr>
+                            </thead>
+                            <tbody class="divide-y divide-gray-200">
+                                { for self.statistics.slowest_origins.iter().map(render_origin) }
+                            </tbody>
+                        </table>
+                    </div>
+                </div>
+            }
+        };
+
+        fn format_microseconds(microseconds: f64) -> String {
+            if microseconds >= 1_000.0 {
+                format!("{:.1} ms", microseconds / 1_000.0)
+            } else {
+                format!("{:.0} µs", microseconds)
+            }
+        }
+
+        let overhead = match &self.statistics.overhead {
+            Some(overhead) if overhead.requests > 0 => {
+                let render_value = |label: &str, value: String| {
+                    html! {
+                    <li class="relative bg-white py-5 px-4">
+                        <div class="flex justify-between space-x-3">
+                            <div class="min-w-0 flex-1">
+                                <p class="text-sm font-medium text-gray-900 truncate">{ label }</p>
+                            </div>
+                            <div class="flex-shrink-0 whitespace-nowrap text-sm text-gray-500">{ value }</div>
+                        </div>
+                    </li>
+                         }
+                };
+
+                html! {
+                    <div class="mt-4 bg-white overflow-hidden shadow rounded-lg divide-y divide-gray-200">
+                        <div class="px-4 py-5 sm:px-6">
+                            <h3 class="text-lg font-medium">{t("dashboard-overhead")}</h3>
+                        </div>
+                        <div class="px-4 py-5 sm:p-6">
+                            <ol role="list" class="divide-y divide-gray-200">
+                                { render_value(&t("dashboard-overhead-added-latency"), t_with("dashboard-overhead-average-p95", &[
+                                    ("average", format_microseconds(overhead.average_added_latency_us).into()),
+                                    ("p95", format_microseconds(overhead.p95_added_latency_us as f64).into()),
+                                ])) }
+                                { render_value(&t("dashboard-overhead-blocker"), format_microseconds(overhead.average_blocker_us)) }
+                                { render_value(&t_with("dashboard-overhead-rewriter", &[
+                                    ("documents", overhead.rewritten_documents.to_formatted_string(&Locale::en).into()),
+                                ]), format_microseconds(overhead.average_rewriter_us)) }
+                            </ol>
+                        </div>
+                    </div>
+                }
+            }
+            _ => html! {},
+        };
+
+        // Spikes detected during the last hour, most recent first.
+        let anomalies = if self.statistics.anomalies.is_empty() {
+            html! {}
+        } else {
+            let render_anomaly = |anomaly: &Anomaly| {
+                let series = match anomaly.series {
+                    AnomalySeries::Requests => t("dashboard-anomaly-requests"),
+                    AnomalySeries::BlockedRequests => t("dashboard-anomaly-blocked-requests"),
+                };
+                let args = [
+                    ("series", series.into()),
+                    (
+                        "count",
+                        anomaly.count.to_formatted_string(&Locale::en).into(),
+                    ),
+                    (
+                        "average",
+                        anomaly.average.to_formatted_string(&Locale::en).into(),
+                    ),
+                ];
+
+                html! {
+                    <li class="text-sm text-yellow-800">
+                        { match &anomaly.client {
+                            Some(client) => {
+                                let mut args = args.to_vec();
+                                args.push(("client", client.as_str().into()));
+                                t_with("dashboard-anomaly-client", &args)
+                            }
+                            None => t_with("dashboard-anomaly-overall", &args),
+                        } }
+                    </li>
+                }
+            };
+
+            html! {
+                <div class="mt-5 rounded-md bg-yellow-50 p-4">
+                    <h3 class="text-sm font-medium text-yellow-800">{t("dashboard-anomalies")}</h3>
+                    <ul role="list" class="mt-2 list-disc pl-5 space-y-1">
+                        { for self.statistics.anomalies.iter().rev().map(render_anomaly) }
+                    </ul>
+                </div>
+            }
+        };
+
+        // Excluded hosts bypass filtering, their connections are only counted.
+        let tunneled = if self.statistics.top_tunneled.is_empty() {
+            html! {}
+        } else {
+            html! {
+                <div class="mt-4 bg-white overflow-hidden shadow rounded-lg divide-y divide-gray-200">
+                    <div class="px-4 py-5 sm:px-6 flex justify-between items-baseline">
+                        <h3 class="text-lg font-medium">{t("dashboard-tunneled")}</h3>
+                        <span class="text-sm text-gray-500">
+                            { self.statistics.tunneled_connections.unwrap_or_default().to_formatted_string(&Locale::en) }
+                        </span>
+                    </div>
+                    <div class="px-4 py-5 sm:p-6">
+                        <ol role="list" class="divide-y divide-gray-200">
+                            { for self.statistics.top_tunneled.iter().map(render_tunnel_element) }
+                        </ol>
+                    </div>
+                </div>
+            }
+        };
+
+        let users = if self.statistics.users.is_empty() {
+            html! {}
+        } else {
+            html! {
+                <div class="mt-4 bg-white overflow-hidden shadow rounded-lg divide-y divide-gray-200">
+                    <div class="px-4 py-5 sm:px-6">
+                        <h3 class="text-lg font-medium">{t("dashboard-users")}</h3>
+                    </div>
+                    <div class="px-4 py-5 sm:p-6">
+                        <ol role="list" class="divide-y divide-gray-200">
+                            { for self.statistics.users.iter().map(render_user_element) }
+                        </ol>
+                    </div>
+                </div>
+            }
+        };
+
+        let render_domain_element = |domain: &str, count: u64| {
+            let is_selected = self.selected_domain.as_deref() == Some(domain);
+            let selected_domain = if is_selected {
+                None
+            } else {
+                Some(domain.to_string())
+            };
+            let onclick = ctx
+                .link()
+                .callback(move |_| Message::SelectDomain(selected_domain.clone()));
+            let background = if is_selected {
+                "bg-blue-50"
+            } else {
+                "bg-white"
+            };
+
+            html! {
+            <li class={format!("relative py-5 px-4 cursor-pointer hover:bg-gray-50 {}", background)} {onclick}>
+                <div class="flex justify-between space-x-3">
+                    <div class="min-w-0 flex-1">
+                        <p class="text-sm font-medium text-gray-900 truncate">{ domain }</p>
+                    </div>
+                    <div class="flex-shrink-0 whitespace-nowrap text-sm text-gray-500">{ count.to_formatted_string(&Locale::en) }</div>
+                </div>
+            </li>
+                 }
+        };
+
+        let top_blocked_paths = match &self.selected_domain {
+            Some(_domain) => self.selected_domain_paths.iter(),
+            None => self.statistics.top_blocked_paths.iter(),
+        };
+        let top_blocked_paths_title = match &self.selected_domain {
+            Some(domain) => t_with(
+                "dashboard-top-blocked-paths-of",
+                &[("domain", domain.as_str().into())],
+            ),
+            None => t("dashboard-top-blocked-paths"),
+        };
+
+        // Only filled in when a GeoIP database is configured.
+        let render_upstream_card = |title: &str, entries: &[(String, u64)]| {
+            if entries.is_empty() {
+                return html! {};
+            }
+
+            html! {
+                <div class="mt-4 bg-white overflow-hidden shadow rounded-lg divide-y divide-gray-200">
+                    <div class="px-4 py-5 sm:px-6">
+                        <h3 class="text-lg font-medium">{title}</h3>
+                    </div>
+                    <div class="px-4 py-5 sm:p-6">
+                        <ol role="list" class="divide-y divide-gray-200">
+                            { for entries.iter().map(|(entry, count)| render_list_element(entry, *count)) }
+                        </ol>
+                    </div>
+                </div>
+            }
+        };
+        let top_countries = render_upstream_card(
+            &t("dashboard-top-countries"),
+            &self.statistics.top_countries,
+        );
+        let top_asns =
+            render_upstream_card(&t("dashboard-top-networks"), &self.statistics.top_asns);
+
+        let blocked_by_category = match &self.statistics.blocked_by_category {
+            Some(counts) => {
+                let mut categories = [
+                    (t("dashboard-category-ads"), counts.ads),
+                    (t("dashboard-category-trackers"), counts.trackers),
+                    (t("dashboard-category-malware"), counts.malware),
+                    (t("dashboard-category-custom"), counts.custom),
+                ]
+                .into_iter()
+                .filter(|(_category, count)| *count > 0)
+                .collect::<Vec<_>>();
+                categories.sort_by(|a, b| b.1.cmp(&a.1));
+
+                render_upstream_card(&t("dashboard-blocked-by-category"), &categories)
+            }
+            None => html! {},
+        };
+        let blocked_by_list = render_upstream_card(
+            &t("dashboard-blocked-by-list"),
+            &self.statistics.blocked_by_list,
+        );
+
+        let charts = match &self.history {
+            Some(history) => {
+                let series = |value: fn(&HistoryPoint) -> Option<f64>| {
+                    history.points.iter().map(value).collect::<Vec<_>>()
+                };
+
+                html! {
+                    <div class="mt-4 lg:grid lg:gap-y-4 lg:gap-x-8 lg:grid-cols-3">
+                        { line_chart(&t("dashboard-chart-requests"), "req/s", &series(|point| Some(point.requests_per_second))) }
+                        { line_chart(&t("dashboard-chart-blocked"), "req/s", &series(|point| Some(point.blocked_per_second))) }
+                        { line_chart(&t("dashboard-chart-latency"), "ms", &series(|point| point.average_latency_ms)) }
+                    </div>
+                }
+            }
+            None => html! {},
+        };
+
+        // Counters over the selected window replace those since start once loaded.
+        let (proxied_requests, blocked_requests, modified_responses, threats_blocked) =
+            match (&self.selected_window, &self.window_statistics) {
+                (None, _) => (
+                    self.statistics.proxied_requests,
+                    self.statistics.blocked_requests,
+                    self.statistics.modified_responses,
+                    self.statistics.threats_blocked,
+                ),
+                (Some(_), Some(window_statistics)) => (
+                    Some(window_statistics.proxied_requests),
+                    Some(window_statistics.blocked_requests),
+                    Some(window_statistics.modified_responses),
+                    Some(window_statistics.threats_blocked),
+                ),
+                (Some(_), None) => (None, None, None, None),
+            };
+
+        let onchange_window = ctx.link().callback(|e: Event| {
+            let select = e
+                .target_dyn_into::<HtmlSelectElement>()
+                .expect("event target should be a select element");
+            let value = select.value();
+
+            Message::SelectWindow(if value.is_empty() { None } else { Some(value) })
+        });
+        let window_select = html! {
+            <select onchange={onchange_window}
+                class="block pl-3 pr-10 py-2 text-sm border-gray-300 focus:outline-none focus:ring-blue-500 focus:border-blue-500 rounded-md">
+                <option value="" selected={self.selected_window.is_none()}>{t("dashboard-since-start")}</option>
+                { for WINDOWS.iter().map(|(window, label)| html! {
+                    <option value={*window} selected={self.selected_window.as_deref() == Some(*window)}>{t(label)}</option>
+                }) }
+            </select>
+        };
+
         html! {
             <>
                 <div class="md:flex md:justify-between md:space-x-5">
                     <div class="pt-1.5">
-                        <h1 class="text-2xl font-bold text-gray-900">{ "Dashboard" }<div
+                        <h1 class="text-2xl font-bold text-gray-900">{ t("dashboard-title") }<div
                                 class=" mt-3 ml-3 inline pulsating-circle"></div>
                         </h1>
                     </div>
@@ -154,55 +1008,89 @@ impl Component for Dashboard {
                             <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2"
                                 d="M12 10v6m0 0l-3-3m3 3l3-3m2 8H7a2 2 0 01-2-2V5a2 2 0 012-2h5.586a1 1 0 01.707.293l5.414 5.414a1 1 0 01.293.707V19a2 2 0 01-2 2z" />
                         </svg>
-                        {"Download CA certificate"}
+                        {t("dashboard-download-ca")}
                     </a>
+                        <ProfileSelect />
                         <BlockingEnabled />
                     </div>
                 </div>
 
+                <div class="mt-5 flex items-center justify-end space-x-3">
+                    { window_select }
+                    <button onclick={ctx.link().callback(|_| Message::Reset)} type="button"
+                        class="inline-flex items-center px-4 py-2 border border-gray-300 shadow-sm text-sm font-medium rounded-md text-gray-700 bg-white hover:bg-gray-50">
+                        {t("dashboard-reset")}
+                    </button>
+                </div>
+
+                { anomalies }
+
                 <dl
-                    class="mt-5 grid grid-cols-1 rounded-lg bg-white overflow-hidden shadow divide-y divide-gray-200 md:grid-cols-3 md:divide-y-0 md:divide-x">
+                    class="mt-5 grid grid-cols-1 rounded-lg bg-white overflow-hidden shadow divide-y divide-gray-200 md:grid-cols-4 md:divide-y-0 md:divide-x">
                     <div class="px-4 py-5 sm:p-6">
                         <dt class="text-base font-normal text-gray-900">
-                            {"Proxied requests"}
+                            {t("dashboard-proxied-requests")}
                         </dt>
                         <dd class="mt-1 flex justify-between items-baseline md:block lg:flex">
                             <div class="flex items-baseline text-2xl font-semibold text-blue-600">
-                                { some_or_loading(self.message.proxied_requests) }
+                                { some_or_loading(proxied_requests) }
                             </div>
                         </dd>
                     </div>
 
                     <div class="px-4 py-5 sm:p-6">
                         <dt class="text-base font-normal text-gray-900">
-                            {"Blocked requests"}
+                            {t("dashboard-blocked-requests")}
                         </dt>
                         <dd class="mt-1 flex justify-between items-baseline md:block lg:flex">
                             <div class="flex items-baseline text-2xl font-semibold text-blue-600">
-                                { some_or_loading(self.message.blocked_requests) }
+                                { some_or_loading(blocked_requests) }
                             </div>
                         </dd>
                     </div>
 
                     <div class="px-4 py-5 sm:p-6">
                         <dt class="text-base font-normal text-gray-900">
-                            {"Modified responses"}
+                            {t("dashboard-modified-responses")}
                         </dt>
                         <dd class="mt-1 flex justify-between items-baseline md:block lg:flex">
                             <div class="flex items-baseline text-2xl font-semibold text-blue-600">
-                                { some_or_loading(self.message.modified_responses) }
+                                { some_or_loading(modified_responses) }
+                            </div>
+                        </dd>
+                    </div>
+
+                    <div class="px-4 py-5 sm:p-6">
+                        <dt class="text-base font-normal text-gray-900">
+                            {t("dashboard-threats-blocked")}
+                        </dt>
+                        <dd class="mt-1 flex justify-between items-baseline md:block lg:flex">
+                            <div class="flex items-baseline text-2xl font-semibold text-red-600">
+                                { some_or_loading(threats_blocked) }
                             </div>
                         </dd>
                     </div>
                 </dl>
+                { charts }
                 <div class="mt-4 lg:grid lg:gap-y-4 lg:gap-x-8 lg:grid-cols-2">
                     <div class="mt-4 bg-white overflow-hidden shadow rounded-lg divide-y divide-gray-200">
                         <div class="px-4 py-5 sm:px-6">
-                            <h3 class="text-lg font-medium">{"Top blocked paths"}</h3>
+                            <h3 class="text-lg font-medium">{t("dashboard-top-blocked-domains")}</h3>
+                        </div>
+                        <div class="px-4 py-5 sm:p-6">
+                            <ol role="list" class="divide-y divide-gray-200">
+                                { for self.statistics.top_blocked_domains.iter().map(|(domain,
+                                count)|render_domain_element(domain, *count)) }
+                            </ol>
+                        </div>
+                    </div>
+                    <div class="mt-4 bg-white overflow-hidden shadow rounded-lg divide-y divide-gray-200">
+                        <div class="px-4 py-5 sm:px-6">
+                            <h3 class="text-lg font-medium">{top_blocked_paths_title}</h3>
                         </div>
                         <div class="px-4 py-5 sm:p-6">
                             <ol role="list" class="divide-y divide-gray-200">
-                                { for self.message.top_blocked_paths.iter().map(|(path,
+                                { for top_blocked_paths.map(|(path,
                                 count)|render_list_element(path, *count)) }
                             </ol>
 
@@ -210,21 +1098,33 @@ impl Component for Dashboard {
                     </div>
                     <div class="mt-4 bg-white overflow-hidden shadow rounded-lg divide-y divide-gray-200">
                         <div class="px-4 py-5 sm:px-6">
-                            <h3 class="text-lg font-medium">{"Top clients"}</h3>
+                            <h3 class="text-lg font-medium">{t("dashboard-top-clients")}</h3>
                         </div>
                         <div class="px-4 py-5 sm:p-6">
                             <ol role="list" class="divide-y divide-gray-200">
-                                { for self.message.top_clients.iter().map(|(client,
+                                { for self.statistics.top_clients.iter().map(|(client,
                                 count)|render_list_element(client, *count)) }
                             </ol>
                         </div>
                     </div>
+                    { blocked_by_category }
+                    { blocked_by_list }
+                    { responses }
+                    { slowest_origins }
+                    { overhead }
+                    { users }
+                    { engines }
+                    { runtime }
+                    { tunneled }
+                    { top_countries }
+                    { top_asns }
                 </div>
             </>
         }
     }
 
     fn destroy(&mut self, _ctx: &Context<Self>) {
-        self.ws_abort_handle.abort()
+        self.ws_abort_handle.abort();
+        self.history_abort_handle.abort();
     }
 }
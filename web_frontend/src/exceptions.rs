@@ -0,0 +1,367 @@
+use crate::button::ButtonState;
+use crate::conflict_dialog::{ConflictDialog, RevisionConflict};
+use crate::{failure_banner, save_button, success_banner, ApiError};
+use reqwasm::http::Request;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+use yew::{html, Component, Context, Html};
+
+const REQUEST_TYPES: [&str; 8] = [
+    "document",
+    "subdocument",
+    "script",
+    "stylesheet",
+    "image",
+    "xmlhttprequest",
+    "media",
+    "other",
+];
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct InvalidExceptionFilter {
+    rule: String,
+    reason: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InvalidExceptionFilters {
+    error: String,
+    rules: Vec<InvalidExceptionFilter>,
+}
+
+#[derive(Serialize)]
+struct ExceptionFilterTest {
+    url: String,
+    request_type: String,
+    rules: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ExceptionFilterTestResult {
+    matching_rules: Vec<String>,
+}
+
+pub enum Message {
+    Load,
+    Display(Vec<String>, Option<String>),
+    UpdateRules(String),
+    UpdateDomain(String),
+    /// Adds an exception unblocking every request to the domain being typed.
+    AddDomain,
+    UpdateTestUrl(String),
+    UpdateTestRequestType(String),
+    Test,
+    Tested(Vec<String>),
+    Save,
+    SaveSuccess(Option<String>),
+    SaveFailed(ApiError),
+    Conflict(RevisionConflict),
+    Overwrite,
+    Invalid(InvalidExceptionFilters),
+    AcknowledgeSuccess,
+    AcknowledgeError,
+}
+
+pub struct Exceptions {
+    rules: String,
+    rules_before_changes: String,
+    domain: String,
+    test_url: String,
+    test_request_type: String,
+    /// Rules matching the tested request, `None` until a request is tested.
+    matching_rules: Option<Vec<String>>,
+    invalid_rules: Vec<InvalidExceptionFilter>,
+    /// `ETag` of the loaded rules, sent back when saving.
+    revision: Option<String>,
+    conflict: Option<RevisionConflict>,
+    show_success: bool,
+    error: Option<String>,
+}
+
+impl Exceptions {
+    fn rules(&self) -> Vec<String> {
+        self.rules
+            .lines()
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl Component for Exceptions {
+    type Message = Message;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(Message::Load);
+
+        Self {
+            rules: String::new(),
+            rules_before_changes: String::new(),
+            domain: String::new(),
+            test_url: String::new(),
+            test_request_type: REQUEST_TYPES[0].to_string(),
+            matching_rules: None,
+            invalid_rules: Vec::new(),
+            revision: None,
+            conflict: None,
+            show_success: false,
+            error: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Message::Load => {
+                let callback = ctx.link().callback(|message: Message| message);
+
+                spawn_local(async move {
+                    match Request::get("/api/exception-filters").send().await {
+                        Ok(response) if response.ok() => {
+                            let revision = response.headers().get("etag");
+                            if let Ok(rules) = response.json::<Vec<String>>().await {
+                                callback.emit(Message::Display(rules, revision));
+                            }
+                        }
+                        Ok(response) => {
+                            log::error!("Failed to load exceptions: {:?}", response.status())
+                        }
+                        Err(err) => log::error!("Failed to load exceptions: {:?}", err),
+                    }
+                });
+            }
+            Message::Display(rules, revision) => {
+                self.rules = rules.join("\n");
+                self.rules_before_changes = self.rules.clone();
+                self.revision = revision;
+                self.conflict = None;
+            }
+            Message::UpdateRules(rules) => self.rules = rules,
+            Message::UpdateDomain(domain) => self.domain = domain,
+            Message::AddDomain => {
+                let domain = self.domain.trim().trim_start_matches("*.").to_lowercase();
+                if domain.is_empty() {
+                    return false;
+                }
+
+                if !self.rules.is_empty() && !self.rules.ends_with('\n') {
+                    self.rules.push('\n');
+                }
+                self.rules.push_str(&format!("@@||{}^", domain));
+                self.domain.clear();
+            }
+            Message::UpdateTestUrl(url) => self.test_url = url,
+            Message::UpdateTestRequestType(request_type) => self.test_request_type = request_type,
+            Message::Test => {
+                let test = ExceptionFilterTest {
+                    url: self.test_url.trim().to_string(),
+                    request_type: self.test_request_type.clone(),
+                    rules: self.rules(),
+                };
+                let request = Request::post("/api/exception-filters/test")
+                    .header("Content-Type", "application/json")
+                    .body(serde_json::to_string(&test).unwrap());
+                let callback = ctx.link().callback(|message: Message| message);
+
+                spawn_local(async move {
+                    match request.send().await {
+                        Ok(response) if response.ok() => {
+                            match response.json::<ExceptionFilterTestResult>().await {
+                                Ok(result) => callback.emit(Message::Tested(result.matching_rules)),
+                                Err(err) => callback.emit(Message::SaveFailed(ApiError {
+                                    error: format!("{:?}", err),
+                                })),
+                            }
+                        }
+                        Ok(response) => match response.json::<ApiError>().await {
+                            Ok(err) => callback.emit(Message::SaveFailed(err)),
+                            Err(err) => callback.emit(Message::SaveFailed(ApiError {
+                                error: format!("{:?}", err),
+                            })),
+                        },
+                        Err(err) => callback.emit(Message::SaveFailed(ApiError {
+                            error: format!("{:?}", err),
+                        })),
+                    }
+                });
+
+                return false;
+            }
+            Message::Tested(matching_rules) => self.matching_rules = Some(matching_rules),
+            Message::Save => {
+                if self.rules == self.rules_before_changes {
+                    return false;
+                }
+                let mut request = Request::put("/api/exception-filters")
+                    .header("Content-Type", "application/json")
+                    .body(serde_json::to_string(&self.rules()).unwrap());
+                if let Some(revision) = &self.revision {
+                    request = request.header("If-Match", revision);
+                }
+                let callback = ctx.link().callback(|message: Message| message);
+
+                spawn_local(async move {
+                    match request.send().await {
+                        Ok(response) if response.ok() => {
+                            callback.emit(Message::SaveSuccess(response.headers().get("etag")))
+                        }
+                        Ok(response) if response.status() == 409 => {
+                            match response.json::<RevisionConflict>().await {
+                                Ok(conflict) => callback.emit(Message::Conflict(conflict)),
+                                Err(err) => callback.emit(Message::SaveFailed(ApiError {
+                                    error: format!("{:?}", err),
+                                })),
+                            }
+                        }
+                        Ok(response) if response.status() == 400 => {
+                            match response.json::<InvalidExceptionFilters>().await {
+                                Ok(invalid) => callback.emit(Message::Invalid(invalid)),
+                                Err(err) => callback.emit(Message::SaveFailed(ApiError {
+                                    error: format!("{:?}", err),
+                                })),
+                            }
+                        }
+                        Ok(response) => match response.json::<ApiError>().await {
+                            Ok(err) => callback.emit(Message::SaveFailed(err)),
+                            Err(err) => callback.emit(Message::SaveFailed(ApiError {
+                                error: format!("{:?}", err),
+                            })),
+                        },
+                        Err(err) => callback.emit(Message::SaveFailed(ApiError {
+                            error: format!("{:?}", err),
+                        })),
+                    }
+                });
+            }
+            Message::SaveSuccess(revision) => {
+                self.invalid_rules.clear();
+                self.revision = revision;
+                ctx.link().send_message(Message::Load);
+                self.show_success = true;
+            }
+            Message::Conflict(conflict) => self.conflict = Some(conflict),
+            Message::Overwrite => {
+                if let Some(conflict) = self.conflict.take() {
+                    self.revision = Some(conflict.etag());
+                    ctx.link().send_message(Message::Save);
+                }
+            }
+            Message::SaveFailed(err) => self.error = Some(err.error),
+            Message::Invalid(invalid) => {
+                self.error = Some(invalid.error);
+                self.invalid_rules = invalid.rules;
+            }
+            Message::AcknowledgeSuccess => self.show_success = false,
+            Message::AcknowledgeError => self.error = None,
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let input_css = "shadow-sm focus:ring-blue-500 focus:border-blue-500 block w-full sm:text-sm border-gray-300 rounded-md";
+        let secondary_button_css = "inline-flex items-center px-4 py-2 border border-gray-300 shadow-sm text-sm font-medium rounded-md text-gray-700 bg-white hover:bg-gray-50";
+
+        let save_button_state = if self.rules != self.rules_before_changes {
+            ButtonState::Enabled
+        } else {
+            ButtonState::Disabled
+        };
+        let save_callback = ctx.link().callback(|_| Message::Save);
+
+        let invalid_rules = if self.invalid_rules.is_empty() {
+            html! {}
+        } else {
+            html! {
+                <ul class="mt-2 text-sm text-red-600 list-disc list-inside">
+                    { for self.invalid_rules.iter().map(|invalid| html! {
+                        <li><span class="font-mono">{&invalid.rule}</span>{": "}{&invalid.reason}</li>
+                    }) }
+                </ul>
+            }
+        };
+
+        let conflict_dialog = if self.conflict.is_some() {
+            html! {
+                <ConflictDialog on_reload={ctx.link().callback(|_| Message::Load)}
+                    on_overwrite={ctx.link().callback(|_| Message::Overwrite)} />
+            }
+        } else {
+            html! {}
+        };
+
+        let test_result = match &self.matching_rules {
+            None => html! {},
+            Some(matching_rules) if matching_rules.is_empty() => html! {
+                <p class="mt-2 text-sm text-gray-600">{"No exception applies to this request."}</p>
+            },
+            Some(matching_rules) => html! {
+                <ul class="mt-2 text-sm text-green-700">
+                    { for matching_rules.iter().map(|rule| html! {
+                        <li class="font-mono">{rule}</li>
+                    }) }
+                </ul>
+            },
+        };
+
+        html! {
+            <>
+                <div class="pt-1.5 mb-4">
+                    <h1 class="text-2xl font-bold text-gray-900">{ "Exceptions" }</h1>
+                </div>
+                { success_banner!(self.show_success, ctx.link().callback(|_| Message::AcknowledgeSuccess)) }
+                { failure_banner!(self.error.is_some(), ctx.link().callback(|_| Message::AcknowledgeError), self.error.clone().unwrap_or_default()) }
+                { conflict_dialog }
+                <p class="text-gray-600">
+                    {"Exceptions unblock what filter lists block by mistake, in every profile. Prefix rules with "}
+                    <span class="font-mono bg-gray-100">{"@@"}</span>{" to allow requests, or use "}
+                    <span class="font-mono bg-gray-100">{"#@#"}</span>{" to show hidden elements again."}
+                </p>
+                <div class="mt-4 flex space-x-4 max-w-xl">
+                    <input type="text" value={self.domain.clone()} placeholder="example.com" class={input_css}
+                        oninput={ctx.link().callback(|e: InputEvent| {
+                            let input = e.target_dyn_into::<HtmlInputElement>().expect("event target should be an input element");
+                            Message::UpdateDomain(input.value())
+                        })} />
+                    <button onclick={ctx.link().callback(|_| Message::AddDomain)} type="button" class={secondary_button_css}>
+                        {"Allow domain"}
+                    </button>
+                </div>
+                <textarea rows="12" value={self.rules.clone()}
+                    class="mt-4 font-mono shadow-sm focus:ring-blue-500 focus:border-blue-500 block w-full sm:text-sm border-gray-300 rounded-md"
+                    oninput={ctx.link().callback(|e: InputEvent| {
+                        let input = e.target_unchecked_into::<HtmlInputElement>();
+                        Message::UpdateRules(input.value())
+                    })}></textarea>
+                <p class="mt-2 text-sm text-gray-500">{"Insert one rule per line"}</p>
+                { invalid_rules }
+                { save_button!(save_callback, save_button_state) }
+                <h2 class="mt-8 text-lg font-medium text-gray-900">{"Test a request"}</h2>
+                <p class="text-gray-600">{"Lists the exceptions above which apply to a request, before they are saved."}</p>
+                <div class="mt-4 flex space-x-4">
+                    <input type="text" value={self.test_url.clone()} placeholder="https://example.com/script.js" class={input_css}
+                        oninput={ctx.link().callback(|e: InputEvent| {
+                            let input = e.target_dyn_into::<HtmlInputElement>().expect("event target should be an input element");
+                            Message::UpdateTestUrl(input.value())
+                        })} />
+                    <select class="shadow-sm focus:ring-blue-500 focus:border-blue-500 sm:text-sm border-gray-300 rounded-md"
+                        onchange={ctx.link().callback(|e: Event| {
+                            let select = e.target_dyn_into::<HtmlSelectElement>().expect("event target should be a select element");
+                            Message::UpdateTestRequestType(select.value())
+                        })}>
+                        { for REQUEST_TYPES.iter().map(|request_type| html! {
+                            <option value={*request_type} selected={self.test_request_type == *request_type}>{request_type}</option>
+                        }) }
+                    </select>
+                    <button onclick={ctx.link().callback(|_| Message::Test)} type="button" class={secondary_button_css}>
+                        {"Test"}
+                    </button>
+                </div>
+                { test_result }
+            </>
+        }
+    }
+}
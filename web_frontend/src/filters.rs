@@ -1,4 +1,5 @@
 use crate::button::ButtonState;
+use crate::conflict_dialog::{ConflictDialog, RevisionConflict};
 use crate::filterlists::SearchFilterList;
 use crate::{save_button, submit_banner};
 use reqwasm::http::Request;
@@ -6,6 +7,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::de::IoRead;
 use serde_json::StreamDeserializer;
 use serde_with::{serde_as, DisplayFromStr};
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::io::Cursor;
 use url::Url;
@@ -322,12 +324,24 @@ pub struct FilterStatusChangeRequest {
 
 pub type FilterConfiguration = Vec<Filter>;
 
+/// Outcome of the last check of a filter list, only its problems are shown.
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+pub struct FilterHealth {
+    file_name: String,
+    warnings: Vec<String>,
+}
+
 pub enum Message {
     Load,
     Display(FilterConfiguration),
+    DisplayHealth(Vec<FilterHealth>),
+    UpdateRevision(Option<String>),
     UpdateFilterSelection((String, bool)),
     Save,
     ChangesSaved,
+    Conflict(RevisionConflict),
+    Overwrite,
+    Reload,
     AckChanges,
 }
 
@@ -335,6 +349,11 @@ pub struct Filters {
     filter_configuration: Option<FilterConfiguration>,
     filter_configuration_before_changes: Option<FilterConfiguration>,
     changes_saved: bool,
+    /// `ETag` of the loaded filters, sent back when saving.
+    revision: Option<String>,
+    conflict: Option<RevisionConflict>,
+    /// Warnings of the filter lists in use, by file name.
+    warnings: HashMap<String, Vec<String>>,
 }
 
 impl Filters {
@@ -365,6 +384,9 @@ impl Component for Filters {
             filter_configuration: None,
             filter_configuration_before_changes: None,
             changes_saved: false,
+            revision: None,
+            conflict: None,
+            warnings: HashMap::new(),
         }
     }
 
@@ -375,6 +397,13 @@ impl Component for Filters {
                 self.filter_configuration = Some(filter_configuration.clone());
                 self.filter_configuration_before_changes = Some(filter_configuration);
             }
+            Message::DisplayHealth(filters_health) => {
+                self.warnings = filters_health
+                    .into_iter()
+                    .filter(|health| !health.warnings.is_empty())
+                    .map(|health| (health.file_name, health.warnings))
+                    .collect();
+            }
             Message::Load => {
                 log::debug!("Retrieving filters..");
                 let request = Request::get("/api/filters");
@@ -387,6 +416,8 @@ impl Component for Filters {
                         log::debug!("Response: {:?}", response);
                         if response.ok() {
                             log::debug!("Response OK");
+                            message_callback
+                                .emit(Message::UpdateRevision(response.headers().get("etag")));
                             if let Ok(body) = response.text().await {
                                 let cursor = Cursor::new(body);
                                 let stream = StreamDeserializer::new(IoRead::new(cursor));
@@ -401,6 +432,15 @@ impl Component for Filters {
                         }
                     }
                 });
+
+                let health_callback = ctx.link().callback(|message: Message| message);
+                spawn_local(async move {
+                    if let Ok(response) = Request::get("/api/filters/health").send().await {
+                        if let Ok(filters_health) = response.json::<Vec<FilterHealth>>().await {
+                            health_callback.emit(Message::DisplayHealth(filters_health));
+                        }
+                    }
+                });
             }
             Message::Save => {
                 if !self.configuration_has_changed() {
@@ -417,9 +457,12 @@ impl Component for Filters {
                     })
                     .collect::<Vec<_>>();
 
-                let request = Request::put("/api/filters")
+                let mut request = Request::put("/api/filters")
                     .header("Content-Type", "application/json")
                     .body(serde_json::to_string(&request_body).unwrap());
+                if let Some(revision) = &self.revision {
+                    request = request.header("If-Match", revision);
+                }
 
                 let callback = ctx.link().callback(|message: Message| message);
 
@@ -427,10 +470,17 @@ impl Component for Filters {
                     match request.send().await {
                         Ok(response) => {
                             if response.ok() {
+                                callback
+                                    .emit(Message::UpdateRevision(response.headers().get("etag")));
                                 callback.emit(Message::ChangesSaved);
 
                                 return;
                             }
+                            if response.status() == 409 {
+                                if let Ok(conflict) = response.json::<RevisionConflict>().await {
+                                    callback.emit(Message::Conflict(conflict));
+                                }
+                            }
                         }
                         Err(_) => {}
                     }
@@ -456,6 +506,18 @@ impl Component for Filters {
                 self.changes_saved = true;
                 self.filter_configuration_before_changes = self.filter_configuration.clone();
             }
+            Message::UpdateRevision(revision) => self.revision = revision,
+            Message::Conflict(conflict) => self.conflict = Some(conflict),
+            Message::Overwrite => {
+                if let Some(conflict) = self.conflict.take() {
+                    self.revision = Some(conflict.etag());
+                    ctx.link().send_message(Message::Save);
+                }
+            }
+            Message::Reload => {
+                self.conflict = None;
+                ctx.link().send_message(Message::Load);
+            }
             Message::AckChanges => self.changes_saved = false,
         };
 
@@ -486,11 +548,19 @@ impl Component for Filters {
             let checkbox_callback = Callback::from(move |_| {
                 callback_clone.emit((filter_file_name.to_string(), !filter_enabled))
             });
+            let warnings = self
+                .warnings
+                .get(&filter.file_name)
+                .cloned()
+                .unwrap_or_default();
             log::debug!("Returning category filter.");
             html! {
             <div class="relative flex items-start py-4">
                 <div class="min-w-0 flex-1 text-sm">
                     <label for={filter.file_name.clone()} class="select-none">{&filter.title}</label>
+                    { for warnings.into_iter().map(|warning| html! {
+                        <p class="mt-1 text-xs text-yellow-800">{warning}</p>
+                    }) }
                 </div>
                 <div class="ml-3 flex items-center h-5">
                     <input checked={filter.enabled} onchange={checkbox_callback} name={filter.file_name.clone()} type="checkbox"
@@ -545,6 +615,15 @@ impl Component for Filters {
             html! {}
         };
 
+        let conflict_dialog = if self.conflict.is_some() {
+            html! {
+                <ConflictDialog on_reload={ctx.link().callback(|_| Message::Reload)}
+                    on_overwrite={ctx.link().callback(|_| Message::Overwrite)} />
+            }
+        } else {
+            html! {}
+        };
+
         let title = html! {
             <div class="pt-1.5 mb-4">
                 <h1 class="text-2xl font-bold text-gray-900">{ "Filters" }</h1>
@@ -557,6 +636,7 @@ impl Component for Filters {
                         <>
                             { title }
                             {success_banner}
+                            {conflict_dialog}
                             <div class="mb-5 flex space-x-4">
                                 <AddFilterComponent state={save_button::SaveButtonState::Enabled}/>
                                 <SearchFilterList filter_configuration={filter_configuration.clone()}/>
@@ -4,9 +4,9 @@ use crate::failure_banner;
 use crate::success_banner;
 use crate::{save_button, ApiError};
 use gloo_utils::format::JsValueSerdeExt;
-use regex::Regex;
 use reqwasm::http::Request;
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::spawn_local;
@@ -281,8 +281,7 @@ impl Component for GeneralSettings {
             Message::UpdateBindAddr(value) => {
                 if let Some(ref mut network_settings) = self.network_settings {
                     network_settings.raw_bind_addr = value.clone();
-                    let re = Regex::new(r"^((25[0-5]|(2[0-4]|1\d|[1-9]|)\d)\.?\b){4}$").unwrap();
-                    network_settings.bind_addr_error = if re.is_match(&value) {
+                    network_settings.bind_addr_error = if value.parse::<IpAddr>().is_ok() {
                         network_settings.current_config.bind_addr = value.clone();
                         None
                     } else {
@@ -0,0 +1,185 @@
+//! Translations, in Fluent format, from `src/locales/<language>.ftl`.
+//!
+//! Adding a language takes a new `.ftl` file and a `Language` variant. Messages missing from a
+//! translation fall back on English.
+use fluent_bundle::{FluentArgs, FluentBundle, FluentResource, FluentValue};
+use web_sys::HtmlSelectElement;
+use yew::prelude::*;
+
+/// Local storage key the selected language is saved under.
+const STORAGE_KEY: &str = "language";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    English,
+    French,
+    German,
+}
+
+impl Language {
+    pub const ALL: [Language; 3] = [Language::English, Language::French, Language::German];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::French => "fr",
+            Language::German => "de",
+        }
+    }
+
+    /// Name of the language, in that language.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::French => "Français",
+            Language::German => "Deutsch",
+        }
+    }
+
+    fn resource(&self) -> &'static str {
+        match self {
+            Language::English => include_str!("locales/en.ftl"),
+            Language::French => include_str!("locales/fr.ftl"),
+            Language::German => include_str!("locales/de.ftl"),
+        }
+    }
+
+    /// Matches `en`, `en-US` and the like.
+    fn from_code(code: &str) -> Option<Self> {
+        let primary = code.split('-').next()?.to_lowercase();
+
+        Self::ALL
+            .into_iter()
+            .find(|language| language.code() == primary)
+    }
+}
+
+fn bundle(language: Language) -> FluentBundle<FluentResource> {
+    let resource = FluentResource::try_new(language.resource().to_string())
+        .expect("translations should be valid Fluent");
+    let language_identifier = language
+        .code()
+        .parse()
+        .expect("language codes should be valid identifiers");
+
+    let mut bundle = FluentBundle::new(vec![language_identifier]);
+    // Isolation marks around arguments show up as boxes in some fonts.
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .expect("translations should not define messages twice");
+
+    bundle
+}
+
+thread_local! {
+    static CURRENT: Language = detect();
+    static BUNDLE: FluentBundle<FluentResource> = bundle(current());
+    static FALLBACK: FluentBundle<FluentResource> = bundle(Language::English);
+}
+
+/// The saved language, otherwise the browser's one when translated, otherwise English.
+fn detect() -> Language {
+    let window = match web_sys::window() {
+        Some(window) => window,
+        None => return Language::English,
+    };
+
+    let saved = window
+        .local_storage()
+        .ok()
+        .flatten()
+        .and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten());
+
+    saved
+        .or_else(|| window.navigator().language())
+        .and_then(|code| Language::from_code(&code))
+        .unwrap_or(Language::English)
+}
+
+pub fn current() -> Language {
+    CURRENT.with(|current| *current)
+}
+
+/// Saves `language` and reloads the page so that everything is rendered again in it.
+pub fn set(language: Language) {
+    let window = match web_sys::window() {
+        Some(window) => window,
+        None => return,
+    };
+
+    if let Ok(Some(storage)) = window.local_storage() {
+        if let Err(err) = storage.set_item(STORAGE_KEY, language.code()) {
+            log::error!("Unable to save language: {:?}", err);
+        }
+    }
+
+    if let Err(err) = window.location().reload() {
+        log::error!("Unable to reload page: {:?}", err);
+    }
+}
+
+fn format(
+    bundle: &FluentBundle<FluentResource>,
+    id: &str,
+    args: Option<&FluentArgs>,
+) -> Option<String> {
+    let pattern = bundle.get_message(id)?.value()?;
+    let mut errors = Vec::new();
+    let message = bundle.format_pattern(pattern, args, &mut errors);
+
+    if !errors.is_empty() {
+        log::warn!("Unable to format message {}: {:?}", id, errors);
+    }
+
+    Some(message.into_owned())
+}
+
+fn translate(id: &str, args: Option<&FluentArgs>) -> String {
+    BUNDLE
+        .with(|bundle| format(bundle, id, args))
+        .or_else(|| FALLBACK.with(|bundle| format(bundle, id, args)))
+        .unwrap_or_else(|| {
+            log::warn!("Missing translation: {}", id);
+            id.to_string()
+        })
+}
+
+/// Translates the message `id`.
+pub fn t(id: &str) -> String {
+    translate(id, None)
+}
+
+/// Translates the message `id`, with `{ $name }` placeables replaced by their value.
+pub fn t_with<'a>(id: &str, args: &[(&'a str, FluentValue<'a>)]) -> String {
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, value.clone());
+    }
+
+    translate(id, Some(&fluent_args))
+}
+
+#[function_component(LanguageSelect)]
+pub fn language_select() -> Html {
+    let onchange = Callback::from(|e: Event| {
+        let select = e
+            .target_dyn_into::<HtmlSelectElement>()
+            .expect("event target should be a select element");
+
+        if let Some(language) = Language::from_code(&select.value()) {
+            if language != current() {
+                set(language);
+            }
+        }
+    });
+
+    html! {
+        <select {onchange} aria-label={t("language")}
+            class="block pl-3 pr-8 py-1 text-sm bg-gray-700 text-gray-200 border-gray-600 focus:outline-none focus:ring-blue-500 focus:border-blue-500 rounded-md">
+            { for Language::ALL.into_iter().map(|language| html! {
+                <option value={language.code()} selected={language == current()}>{language.name()}</option>
+            }) }
+        </select>
+    }
+}
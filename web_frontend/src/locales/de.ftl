@@ -0,0 +1,88 @@
+# Navigation
+nav-dashboard = Übersicht
+nav-requests = Anfragen
+nav-settings = Einstellungen
+language = Sprache
+profile = Profil
+profile-default = Standard
+
+# Dashboard
+dashboard-title = Übersicht
+dashboard-download-ca = CA-Zertifikat herunterladen
+dashboard-proxied-requests = Weitergeleitete Anfragen
+dashboard-blocked-requests = Blockierte Anfragen
+dashboard-modified-responses = Geänderte Antworten
+dashboard-threats-blocked = Blockierte Bedrohungen
+dashboard-loading = Wird geladen
+dashboard-since-start = Seit dem Start
+dashboard-last-hour = Letzte Stunde
+dashboard-last-day = Letzte 24 Stunden
+dashboard-last-week = Letzte 7 Tage
+dashboard-reset = Statistiken zurücksetzen
+dashboard-reset-confirm = Alle Statistiken zurücksetzen?
+dashboard-top-blocked-domains = Meistblockierte Domains
+dashboard-top-blocked-paths = Meistblockierte Pfade
+dashboard-top-blocked-paths-of = Meistblockierte Pfade von { $domain }
+dashboard-top-clients = Häufigste Clients
+dashboard-blocked-by-category = Blockiert nach Kategorie
+dashboard-blocked-by-list = Blockiert nach Filterliste
+dashboard-category-ads = Werbung
+dashboard-category-trackers = Tracker
+dashboard-category-malware = Schadsoftware
+dashboard-category-custom = Eigene Regeln und Einstellungen
+dashboard-users = Benutzer
+dashboard-top-countries = Häufigste Zielländer
+dashboard-top-networks = Häufigste Zielnetzwerke
+dashboard-tunneled = Getunnelt (nicht gefiltert)
+dashboard-tunneled-summary = { $connections } Verbindungen, { $size }
+dashboard-engines = Filter-Engines
+dashboard-engine-summary = { $network } Netzwerkregeln, { $cosmetic } kosmetische Regeln, { $size }
+dashboard-runtime = Laufzeit
+dashboard-runtime-utilization = Auslastung der Worker
+dashboard-runtime-utilization-value = { $percent } % von { $workers } Workern
+dashboard-runtime-tasks = Aktive Tasks
+dashboard-runtime-queue = Wartende Tasks
+dashboard-runtime-blocker-backlog = Rückstau der Blocking-Engine
+dashboard-runtime-certificate-queue = Zertifikate in Erstellung
+dashboard-responses = Antworten der Server
+dashboard-responses-status = Nach Status
+dashboard-responses-scheme = Nach Schema
+dashboard-responses-version = Nach HTTP-Version
+dashboard-slowest-origins = Langsamste Server
+dashboard-slowest-origins-host = Host
+dashboard-slowest-origins-requests = Anfragen
+dashboard-slowest-origins-ttfb = Erstes Byte (Ø / p95)
+dashboard-slowest-origins-total = Gesamt (Ø / p95)
+dashboard-overhead = Zusätzliche Latenz
+dashboard-overhead-added-latency = Zeit bis zu den Antwort-Headern
+dashboard-overhead-average-p95 = { $average } im Mittel, { $p95 } bei p95
+dashboard-overhead-blocker = Filterentscheidung
+dashboard-overhead-rewriter = Umschreiben von Dokumenten ({ $documents } Dokumente)
+dashboard-anomalies = Ungewöhnliche Aktivität in der letzten Stunde
+dashboard-anomaly-requests = Anfragen
+dashboard-anomaly-blocked-requests = blockierte Anfragen
+dashboard-anomaly-client = { $client } hat { $count } { $series } in einer Minute gestellt, gegenüber { $average } im Mittel
+dashboard-anomaly-overall = { $count } { $series } in einer Minute, gegenüber { $average } im Mittel
+dashboard-chart-requests = Anfragen
+dashboard-chart-blocked = Blockierte Anfragen
+dashboard-chart-latency = Server-Latenz
+
+# Charts
+chart-no-data = Keine Daten
+chart-maximum = max. { $maximum }
+chart-start = Vor 24 Stunden
+chart-now = Jetzt
+
+# Requests
+requests-title = Anfragen-Feed
+requests-tunneled = Getunnelt
+requests-cosmetic-exceptions = Kosmetische Ausnahmen aktiv
+requests-disable-blocking = Blockierung deaktivieren:
+requests-disable-hour = 1 Stunde
+requests-disable-day = 24 Stunden
+requests-disable-permanently = Immer
+requests-blocking-disabled = Blockierung auf { $host } deaktiviert
+
+# Not found
+not-found-title = Seite nicht gefunden
+not-found-description = Bitte überprüfen Sie die URL in der Adressleiste und versuchen Sie es erneut.
@@ -0,0 +1,88 @@
+# Navigation
+nav-dashboard = Dashboard
+nav-requests = Requests
+nav-settings = Settings
+language = Language
+profile = Profile
+profile-default = Default
+
+# Dashboard
+dashboard-title = Dashboard
+dashboard-download-ca = Download CA certificate
+dashboard-proxied-requests = Proxied requests
+dashboard-blocked-requests = Blocked requests
+dashboard-modified-responses = Modified responses
+dashboard-threats-blocked = Threats blocked
+dashboard-loading = Loading
+dashboard-since-start = Since start
+dashboard-last-hour = Last hour
+dashboard-last-day = Last 24 hours
+dashboard-last-week = Last 7 days
+dashboard-reset = Reset statistics
+dashboard-reset-confirm = Reset all statistics?
+dashboard-top-blocked-domains = Top blocked domains
+dashboard-top-blocked-paths = Top blocked paths
+dashboard-top-blocked-paths-of = Top blocked paths of { $domain }
+dashboard-top-clients = Top clients
+dashboard-blocked-by-category = Blocked by category
+dashboard-blocked-by-list = Blocked by filter list
+dashboard-category-ads = Ads
+dashboard-category-trackers = Trackers
+dashboard-category-malware = Malware
+dashboard-category-custom = Custom rules and settings
+dashboard-users = Users
+dashboard-top-countries = Top destination countries
+dashboard-top-networks = Top destination networks
+dashboard-tunneled = Tunneled (not filtered)
+dashboard-tunneled-summary = { $connections } connections, { $size }
+dashboard-engines = Filter engines
+dashboard-engine-summary = { $network } network rules, { $cosmetic } cosmetic rules, { $size }
+dashboard-runtime = Runtime
+dashboard-runtime-utilization = Worker utilization
+dashboard-runtime-utilization-value = { $percent }% of { $workers } workers
+dashboard-runtime-tasks = Active tasks
+dashboard-runtime-queue = Queued tasks
+dashboard-runtime-blocker-backlog = Blocking engine backlog
+dashboard-runtime-certificate-queue = Certificates being generated
+dashboard-responses = Upstream responses
+dashboard-responses-status = By status
+dashboard-responses-scheme = By scheme
+dashboard-responses-version = By HTTP version
+dashboard-slowest-origins = Slowest origins
+dashboard-slowest-origins-host = Host
+dashboard-slowest-origins-requests = Requests
+dashboard-slowest-origins-ttfb = First byte (avg / p95)
+dashboard-slowest-origins-total = Total (avg / p95)
+dashboard-overhead = Added latency
+dashboard-overhead-added-latency = Time to response headers
+dashboard-overhead-average-p95 = { $average } on average, { $p95 } at p95
+dashboard-overhead-blocker = Filter decision
+dashboard-overhead-rewriter = Document rewriting ({ $documents } documents)
+dashboard-anomalies = Unusual activity during the last hour
+dashboard-anomaly-requests = requests
+dashboard-anomaly-blocked-requests = blocked requests
+dashboard-anomaly-client = { $client } made { $count } { $series } in a minute, against { $average } on average
+dashboard-anomaly-overall = { $count } { $series } in a minute, against { $average } on average
+dashboard-chart-requests = Requests
+dashboard-chart-blocked = Blocked requests
+dashboard-chart-latency = Upstream latency
+
+# Charts
+chart-no-data = No data
+chart-maximum = max { $maximum }
+chart-start = 24 hours ago
+chart-now = Now
+
+# Requests
+requests-title = Requests feed
+requests-tunneled = Tunneled
+requests-cosmetic-exceptions = Cosmetic exceptions active
+requests-disable-blocking = Disable blocking:
+requests-disable-hour = 1 hour
+requests-disable-day = 24 hours
+requests-disable-permanently = Always
+requests-blocking-disabled = Blocking disabled on { $host }
+
+# Not found
+not-found-title = Page not found
+not-found-description = Please check the URL in the address bar and try again.
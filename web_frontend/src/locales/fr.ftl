@@ -0,0 +1,88 @@
+# Navigation
+nav-dashboard = Tableau de bord
+nav-requests = Requêtes
+nav-settings = Paramètres
+language = Langue
+profile = Profil
+profile-default = Par défaut
+
+# Dashboard
+dashboard-title = Tableau de bord
+dashboard-download-ca = Télécharger le certificat CA
+dashboard-proxied-requests = Requêtes relayées
+dashboard-blocked-requests = Requêtes bloquées
+dashboard-modified-responses = Réponses modifiées
+dashboard-threats-blocked = Menaces bloquées
+dashboard-loading = Chargement
+dashboard-since-start = Depuis le démarrage
+dashboard-last-hour = Dernière heure
+dashboard-last-day = Dernières 24 heures
+dashboard-last-week = 7 derniers jours
+dashboard-reset = Réinitialiser les statistiques
+dashboard-reset-confirm = Réinitialiser toutes les statistiques ?
+dashboard-top-blocked-domains = Domaines les plus bloqués
+dashboard-top-blocked-paths = Chemins les plus bloqués
+dashboard-top-blocked-paths-of = Chemins les plus bloqués de { $domain }
+dashboard-top-clients = Principaux clients
+dashboard-blocked-by-category = Blocages par catégorie
+dashboard-blocked-by-list = Blocages par liste de filtres
+dashboard-category-ads = Publicités
+dashboard-category-trackers = Traqueurs
+dashboard-category-malware = Logiciels malveillants
+dashboard-category-custom = Règles et réglages personnalisés
+dashboard-users = Utilisateurs
+dashboard-top-countries = Principaux pays de destination
+dashboard-top-networks = Principaux réseaux de destination
+dashboard-tunneled = Tunnelisé (non filtré)
+dashboard-tunneled-summary = { $connections } connexions, { $size }
+dashboard-engines = Moteurs de filtrage
+dashboard-engine-summary = { $network } règles réseau, { $cosmetic } règles cosmétiques, { $size }
+dashboard-runtime = Exécution
+dashboard-runtime-utilization = Utilisation des workers
+dashboard-runtime-utilization-value = { $percent } % de { $workers } workers
+dashboard-runtime-tasks = Tâches actives
+dashboard-runtime-queue = Tâches en attente
+dashboard-runtime-blocker-backlog = File du moteur de blocage
+dashboard-runtime-certificate-queue = Certificats en cours de génération
+dashboard-responses = Réponses des serveurs
+dashboard-responses-status = Par statut
+dashboard-responses-scheme = Par schéma
+dashboard-responses-version = Par version HTTP
+dashboard-slowest-origins = Serveurs les plus lents
+dashboard-slowest-origins-host = Hôte
+dashboard-slowest-origins-requests = Requêtes
+dashboard-slowest-origins-ttfb = Premier octet (moy. / p95)
+dashboard-slowest-origins-total = Total (moy. / p95)
+dashboard-overhead = Latence ajoutée
+dashboard-overhead-added-latency = Délai des en-têtes de réponse
+dashboard-overhead-average-p95 = { $average } en moyenne, { $p95 } au p95
+dashboard-overhead-blocker = Décision de filtrage
+dashboard-overhead-rewriter = Réécriture des documents ({ $documents } documents)
+dashboard-anomalies = Activité inhabituelle durant la dernière heure
+dashboard-anomaly-requests = requêtes
+dashboard-anomaly-blocked-requests = requêtes bloquées
+dashboard-anomaly-client = { $client } a fait { $count } { $series } en une minute, contre { $average } en moyenne
+dashboard-anomaly-overall = { $count } { $series } en une minute, contre { $average } en moyenne
+dashboard-chart-requests = Requêtes
+dashboard-chart-blocked = Requêtes bloquées
+dashboard-chart-latency = Latence des serveurs
+
+# Charts
+chart-no-data = Aucune donnée
+chart-maximum = max { $maximum }
+chart-start = Il y a 24 heures
+chart-now = Maintenant
+
+# Requests
+requests-title = Flux des requêtes
+requests-tunneled = Tunnelisé
+requests-cosmetic-exceptions = Exceptions cosmétiques actives
+requests-disable-blocking = Désactiver le blocage :
+requests-disable-hour = 1 heure
+requests-disable-day = 24 heures
+requests-disable-permanently = Toujours
+requests-blocking-disabled = Blocage désactivé sur { $host }
+
+# Not found
+not-found-title = Page introuvable
+not-found-description = Vérifiez l'URL dans la barre d'adresse et réessayez.
@@ -1,3 +1,4 @@
+use i18n::t;
 use serde::Deserialize;
 use yew::functional::*;
 use yew::prelude::*;
@@ -5,11 +6,19 @@ use yew_router::prelude::*;
 
 mod blocking_enabled;
 mod button;
+mod capture;
+mod categories;
+mod charts;
+mod conflict_dialog;
 mod dashboard;
+mod exceptions;
 mod filterlists;
 mod filters;
 mod general;
+mod i18n;
+mod profile_select;
 mod requests;
+mod resources;
 mod save_button;
 mod settings;
 mod settings_textarea;
@@ -42,10 +51,8 @@ fn not_found() -> Html {
                 <p class="text-4xl font-extrabold text-blue-600 sm:text-5xl">{"404"}</p>
                 <div class="sm:ml-6">
                     <div class="sm:border-l sm:border-gray-200 sm:pl-6">
-                        <h1 class="text-4xl font-extrabold text-gray-900 tracking-tight sm:text-5xl">{"Page not
-                            found"}</h1>
-                        <p class="mt-1 text-base text-gray-500">{"Please check the URL in the address bar and try
-                            again."}</p>
+                        <h1 class="text-4xl font-extrabold text-gray-900 tracking-tight sm:text-5xl">{t("not-found-title")}</h1>
+                        <p class="mt-1 text-base text-gray-500">{t("not-found-description")}</p>
                     </div>
                 </div>
             </main>
@@ -89,30 +96,31 @@ fn switch(route: &Route) -> Html {
               <img class="h-8 w-auto text-white" src="/logo.svg" alt="Logo" />
             </div>
               <div class="flex ml-6 space-x-4">
-              <Link<Route> classes={ get_classes(*route, Route::Dashboard) } to={Route::Dashboard}>{ "Dashboard" }</Link<Route>>
-               <Link<Route> classes={ get_classes(*route, Route::Requests) } to={Route::Requests}>{ "Requests" }</Link<Route>>
-               <Link<settings::SettingsRoute> classes={ get_classes(*route, Route::Settings) } to={settings::SettingsRoute::Filters}>{ "Settings" }</Link<settings::SettingsRoute>>
+              <Link<Route> classes={ get_classes(*route, Route::Dashboard) } to={Route::Dashboard}>{ t("nav-dashboard") }</Link<Route>>
+               <Link<Route> classes={ get_classes(*route, Route::Requests) } to={Route::Requests}>{ t("nav-requests") }</Link<Route>>
+               <Link<settings::SettingsRoute> classes={ get_classes(*route, Route::Settings) } to={settings::SettingsRoute::Filters}>{ t("nav-settings") }</Link<settings::SettingsRoute>>
                </div>
           </div>
+          <i18n::LanguageSelect />
         </div>
       </div>
     </nav> };
 
     match route {
         Route::Dashboard => {
-            set_title("Dashboard");
+            set_title(&t("nav-dashboard"));
 
             html! { <>{navigation}<div class={"container mt-4 mb-10 mx-auto px-4 sm:px-6 lg:px-8"}> <dashboard::Dashboard /> </div></> }
         }
         Route::Requests => {
-            set_title("Requests");
+            set_title(&t("nav-requests"));
             html! { <>{navigation} <div class={"container mt-4 mb-10 mx-auto px-4 sm:px-6 lg:px-8"}> <requests::Requests /> </div></> }
         }
         Route::Settings => {
             html! {<>{navigation} <div class={"container mt-4 mb-10 mx-auto px-4 sm:px-6 lg:px-8"}> <Switch<settings::SettingsRoute> render={Switch::render(settings::switch_settings)} /> </div> </>}
         }
         Route::NotFound => {
-            set_title("Not Found");
+            set_title(&t("not-found-title"));
             html! { <>{navigation} <NotFound /></> }
         }
     }
@@ -0,0 +1,126 @@
+use crate::i18n::t;
+use reqwasm::http::Request;
+use serde::Deserialize;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlSelectElement;
+use yew::{html, Component, Context, Event, Html};
+
+const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Deserialize)]
+struct Profile {
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ProfilesResponse {
+    profiles: Vec<Profile>,
+    active: String,
+}
+
+pub enum Message {
+    Load,
+    Loaded(ProfilesResponse),
+    Switch(String),
+    Switched(String),
+}
+
+pub struct ProfileSelect {
+    profiles: Vec<String>,
+    active: String,
+    loading: bool,
+}
+
+impl Component for ProfileSelect {
+    type Message = Message;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(Message::Load);
+
+        Self {
+            profiles: Vec::new(),
+            active: DEFAULT_PROFILE.to_string(),
+            loading: true,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        let message_callback = ctx.link().callback(|message: Message| message);
+
+        match msg {
+            Message::Load => {
+                spawn_local(async move {
+                    if let Ok(response) = Request::get("/api/profiles").send().await {
+                        if response.ok() {
+                            if let Ok(profiles) = response.json::<ProfilesResponse>().await {
+                                message_callback.emit(Message::Loaded(profiles))
+                            }
+                        }
+                    }
+                });
+            }
+            Message::Loaded(response) => {
+                self.profiles = response
+                    .profiles
+                    .into_iter()
+                    .map(|profile| profile.name)
+                    .collect();
+                self.active = response.active;
+                self.loading = false;
+            }
+            Message::Switch(name) => {
+                self.loading = true;
+
+                let request = Request::put("/api/profiles/active")
+                    .header("Content-Type", "application/json")
+                    .body(serde_json::to_string(&name).unwrap());
+
+                spawn_local(async move {
+                    match request.send().await {
+                        Ok(response) if response.ok() => {
+                            message_callback.emit(Message::Switched(name))
+                        }
+                        // Show the profile which is actually active.
+                        _ => message_callback.emit(Message::Load),
+                    }
+                });
+            }
+            Message::Switched(name) => {
+                self.active = name;
+                self.loading = false;
+            }
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        // Nothing to switch between until profiles are configured.
+        if self.profiles.is_empty() {
+            return html! {};
+        }
+
+        let onchange = ctx.link().callback(|e: Event| {
+            let select = e
+                .target_dyn_into::<HtmlSelectElement>()
+                .expect("event target should be a select element");
+
+            Message::Switch(select.value())
+        });
+
+        let names = std::iter::once(DEFAULT_PROFILE.to_string()).chain(self.profiles.clone());
+
+        html! {
+            <select {onchange} disabled={self.loading} aria-label={t("profile")} title={t("profile")}
+                class="block pl-3 pr-8 py-2 text-sm font-medium border-gray-300 rounded-md shadow-sm focus:outline-none focus:ring-blue-500 focus:border-blue-500">
+                { for names.map(|name| {
+                    let label = if name == DEFAULT_PROFILE { t("profile-default") } else { name.clone() };
+                    html! {
+                        <option value={name.clone()} selected={name == self.active}>{label}</option>
+                    }
+                }) }
+            </select>
+        }
+    }
+}
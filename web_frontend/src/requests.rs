@@ -1,23 +1,86 @@
+use crate::i18n::{t, t_with};
+use crate::submit_banner::{Color, SubmitBanner};
+use crate::{failure_banner, info_icon, ApiError};
 use futures::future::{AbortHandle, Abortable};
 use futures::StreamExt;
+use gloo_timers::future::TimeoutFuture;
+use reqwasm::http::Request;
 use reqwasm::websocket::futures::WebSocket;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use wasm_bindgen_futures::spawn_local;
+use yew::html::Scope;
 use yew::{html, Component, Context, Html};
 
 const MAX_REQUESTS_SHOWN: usize = 500;
+const COSMETIC_EXCEPTIONS_RELOAD_INTERVAL_MS: u32 = 5_000;
 
 #[derive(Deserialize)]
-pub struct Message {
+pub struct RequestEvent {
     now: String,
     method: String,
     url: String,
     is_request_blocked: bool,
+    /// Filter list, or what else, which blocked the request.
+    #[serde(default)]
+    blocked_by: Option<BlockSource>,
+    /// Connections to excluded hosts, which aren't filtered.
+    #[serde(default)]
+    is_tunneled: bool,
+    /// Country of the upstream server, sent when a GeoIP database is configured.
+    #[serde(default)]
+    country: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BlockSource {
+    list: String,
+    category: String,
+}
+
+impl RequestEvent {
+    fn host(&self) -> Option<String> {
+        url::Url::parse(&self.url)
+            .ok()?
+            .host_str()
+            .map(str::to_string)
+    }
+}
+
+/// Pages of a host on which exceptions disabled cosmetic filters.
+#[derive(Deserialize)]
+pub struct CosmeticExceptionHits {
+    host: String,
+    generichide: u64,
+    specifichide: u64,
+    elemhide: u64,
+}
+
+#[derive(Serialize)]
+struct SiteExclusionRequest {
+    host: String,
+    duration: &'static str,
+}
+
+pub enum Message {
+    Event(RequestEvent),
+    CosmeticExceptions(Vec<CosmeticExceptionHits>),
+    /// Disables blocking on a host, for `1h`, `24h` or `permanent`ly.
+    DisableBlocking(String, &'static str),
+    BlockingDisabled(String),
+    Failed(ApiError),
+    AcknowledgeSuccess,
+    AcknowledgeError,
 }
 
 pub struct Requests {
-    messages: Vec<Message>,
+    messages: Vec<RequestEvent>,
     ws_abort_handle: AbortHandle,
+    cosmetic_exceptions_abort_handle: AbortHandle,
+    /// Keyed by host.
+    cosmetic_exceptions: HashMap<String, CosmeticExceptionHits>,
+    disabled_host: Option<String>,
+    error: Option<String>,
 }
 
 impl Component for Requests {
@@ -25,7 +88,7 @@ impl Component for Requests {
     type Properties = ();
 
     fn create(ctx: &Context<Self>) -> Self {
-        let message_callback = ctx.link().callback(|message: Message| message);
+        let message_callback = ctx.link().callback(Message::Event);
 
         let ws = WebSocket::open("/api/events").unwrap();
         let (_write, mut read) = ws.split();
@@ -36,7 +99,7 @@ impl Component for Requests {
                 while let Some(Ok(msg)) = read.next().await {
                     let message = match msg {
                         reqwasm::websocket::Message::Text(s) => {
-                            serde_json::from_str::<Message>(&s).unwrap()
+                            serde_json::from_str::<RequestEvent>(&s).unwrap()
                         }
                         reqwasm::websocket::Message::Bytes(_) => unreachable!(),
                     };
@@ -51,27 +114,171 @@ impl Component for Requests {
             let _result = future.await;
         });
 
+        let cosmetic_exceptions_callback = ctx.link().callback(Message::CosmeticExceptions);
+        let (cosmetic_exceptions_abort_handle, cosmetic_exceptions_abort_registration) =
+            AbortHandle::new_pair();
+        let cosmetic_exceptions_future = Abortable::new(
+            async move {
+                loop {
+                    match Request::get("/api/statistics/cosmetic-exceptions")
+                        .send()
+                        .await
+                    {
+                        Ok(response) if response.ok() => {
+                            match response.json::<Vec<CosmeticExceptionHits>>().await {
+                                Ok(hits) => cosmetic_exceptions_callback.emit(hits),
+                                Err(err) => log::error!(
+                                    "Failed to deserialize cosmetic exceptions: {:?}",
+                                    err
+                                ),
+                            }
+                        }
+                        Ok(response) => log::error!(
+                            "Failed to load cosmetic exceptions: {:?}",
+                            response.status()
+                        ),
+                        Err(err) => log::error!("Failed to load cosmetic exceptions: {:?}", err),
+                    }
+
+                    TimeoutFuture::new(COSMETIC_EXCEPTIONS_RELOAD_INTERVAL_MS).await;
+                }
+            },
+            cosmetic_exceptions_abort_registration,
+        );
+
+        spawn_local(async {
+            let _result = cosmetic_exceptions_future.await;
+        });
+
         Self {
             ws_abort_handle: abort_handle,
+            cosmetic_exceptions_abort_handle,
+            cosmetic_exceptions: HashMap::new(),
             messages: Vec::new(),
+            disabled_host: None,
+            error: None,
         }
     }
 
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
-        self.messages.insert(0, msg);
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Message::Event(event) => {
+                self.messages.insert(0, event);
+
+                self.messages.truncate(MAX_REQUESTS_SHOWN);
+            }
+            Message::CosmeticExceptions(hits) => {
+                self.cosmetic_exceptions = hits
+                    .into_iter()
+                    .map(|hits| (hits.host.clone(), hits))
+                    .collect();
+            }
+            Message::DisableBlocking(host, duration) => {
+                let callback = ctx.link().callback(|message: Message| message);
+                let request = Request::post("/api/exclusions/sites")
+                    .header("Content-Type", "application/json")
+                    .body(
+                        serde_json::to_string(&SiteExclusionRequest {
+                            host: host.clone(),
+                            duration,
+                        })
+                        .unwrap(),
+                    );
+
+                spawn_local(async move {
+                    match request.send().await {
+                        Ok(response) if response.ok() => {
+                            callback.emit(Message::BlockingDisabled(host))
+                        }
+                        Ok(response) => match response.json::<ApiError>().await {
+                            Ok(err) => callback.emit(Message::Failed(err)),
+                            Err(err) => callback.emit(Message::Failed(ApiError {
+                                error: format!("{:?}", err),
+                            })),
+                        },
+                        Err(err) => callback.emit(Message::Failed(ApiError {
+                            error: format!("{:?}", err),
+                        })),
+                    }
+                });
 
-        self.messages.truncate(MAX_REQUESTS_SHOWN);
+                return false;
+            }
+            Message::BlockingDisabled(host) => self.disabled_host = Some(host),
+            Message::Failed(err) => self.error = Some(err.error),
+            Message::AcknowledgeSuccess => self.disabled_host = None,
+            Message::AcknowledgeError => self.error = None,
+        }
 
         // The server only sends new messages when there is actually
         // new data.
         true
     }
 
-    fn view(&self, _ctx: &Context<Self>) -> Html {
-        fn render_element(element: &Message) -> Html {
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        fn render_actions(link: &Scope<Requests>, element: &RequestEvent) -> Html {
+            let host = match element.host() {
+                Some(host) if !element.is_tunneled => host,
+                _ => return html! {},
+            };
+            let action_css = "ml-2 text-xs font-medium text-blue-600 hover:text-blue-800";
+
+            let actions = [
+                ("1h", "requests-disable-hour"),
+                ("24h", "requests-disable-day"),
+                ("permanent", "requests-disable-permanently"),
+            ];
+
+            html! {
+                <>
+                    <span class="text-xs text-gray-400">{t("requests-disable-blocking")}</span>
+                    { for actions.into_iter().map(|(duration, label)| {
+                        let host = host.clone();
+                        html! {
+                            <button type="button" class={action_css}
+                                onclick={link.callback(move |_| Message::DisableBlocking(host.clone(), duration))}>
+                                {t(label)}
+                            </button>
+                        }
+                    }) }
+                </>
+            }
+        }
+
+        let render_cosmetic_exceptions = |element: &RequestEvent| -> Html {
+            let hits = match element
+                .host()
+                .and_then(|host| self.cosmetic_exceptions.get(&host))
+            {
+                Some(hits) if !element.is_tunneled => hits,
+                _ => return html! {},
+            };
+
+            let title = [
+                ("$generichide", hits.generichide),
+                ("$specifichide", hits.specifichide),
+                ("$elemhide", hits.elemhide),
+            ]
+            .into_iter()
+            .filter(|(_option, count)| *count > 0)
+            .map(|(option, count)| format!("{}: {}", option, count))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+            html! {
+                <span title={title}
+                    class="mr-2 inline-flex items-center px-2 py-0.5 rounded text-xs font-medium bg-purple-100 text-purple-800">
+                    {t("requests-cosmetic-exceptions")}
+                </span>
+            }
+        };
+
+        let render_element = |element: &RequestEvent| -> Html {
             let background = {
                 if element.is_request_blocked {
                     "bg-red-50"
+                } else if element.is_tunneled {
+                    "bg-yellow-50"
                 } else {
                     ""
                 }
@@ -90,16 +297,51 @@ impl Component for Requests {
                     </span>
                 </td>
                 <td class="px-6 py-4 whitespace-nowrap text-sm text-gray-500">
+                    { if element.is_tunneled {
+                        html! {
+                            <span
+                                class="mr-2 inline-flex items-center px-2 py-0.5 rounded text-xs font-medium bg-yellow-100 text-yellow-800">
+                                {t("requests-tunneled")}
+                            </span>
+                        }
+                    } else {
+                        html! {}
+                    } }
+                    { for element.blocked_by.iter().map(|blocked_by| html! {
+                        <span title={blocked_by.category.clone()}
+                            class="mr-2 inline-flex items-center px-2 py-0.5 rounded text-xs font-medium bg-red-100 text-red-800">
+                            {&blocked_by.list}
+                        </span>
+                    }) }
+                    { render_cosmetic_exceptions(element) }
+                    { for element.country.iter().map(|country| html! {
+                        <span
+                            class="mr-2 inline-flex items-center px-2 py-0.5 rounded text-xs font-medium bg-gray-100 text-gray-800">
+                            {country}
+                        </span>
+                    }) }
                     {&element.url}
                 </td>
+                <td class="w-1/12 px-6 py-4 whitespace-nowrap text-right">
+                    { render_actions(ctx.link(), element) }
+                </td>
             </tr>
                 }
-        }
+        };
+
+        let success_banner = html! {
+            <SubmitBanner
+                message={t_with("requests-blocking-disabled", &[("host", self.disabled_host.clone().unwrap_or_default().into())])}
+                icon={info_icon!()} color={Color::Green} visible={self.disabled_host.is_some()}
+                on_hide={ctx.link().callback(|_| Message::AcknowledgeSuccess)} />
+        };
 
         html! {
                <>
+          { success_banner }
+          { failure_banner!(self.error.is_some(), ctx.link().callback(|_| Message::AcknowledgeError), self.error.clone().unwrap_or_default()) }
           <h3 class="text-2xl font-bold text-gray-900 pt-1.5">
-            {"Requests feed"}
+            {t("requests-title")}
             <div class="mt-2 ml-3 inline pulsating-circle"></div>
           </h3>
           <div class="mt-4 flex flex-col">
@@ -121,10 +363,11 @@ impl Component for Requests {
                           class="px-6 py-3 text-left text-xs font-medium text-gray-500 uppercase tracking-wider">
                           {"Path"}
                         </th>
+                        <th scope="col" class="relative px-6 py-3"></th>
                       </tr>
                     </thead>
                     <tbody class="w-full bg-white divide-y divide-gray-200">
-                      { for self.messages.iter().map(render_element) }
+                      { for self.messages.iter().map(|element| render_element(element)) }
                     </tbody>
                   </table>
                 </div>
@@ -137,6 +380,7 @@ impl Component for Requests {
     }
 
     fn destroy(&mut self, _ctx: &Context<Self>) {
-        self.ws_abort_handle.abort()
+        self.ws_abort_handle.abort();
+        self.cosmetic_exceptions_abort_handle.abort();
     }
 }
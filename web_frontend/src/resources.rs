@@ -0,0 +1,314 @@
+use crate::button::ButtonState;
+use crate::{failure_banner, save_button, success_banner, ApiError};
+use reqwasm::http::Request;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew::{html, Callback, Component, Context, Html};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Fonts,
+    Media,
+}
+
+impl Resource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resource::Fonts => "Fonts",
+            Resource::Media => "Media",
+        }
+    }
+
+    pub fn values() -> Vec<Self> {
+        vec![Resource::Fonts, Resource::Media]
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct BlockedResources {
+    #[serde(default)]
+    fonts: bool,
+    #[serde(default)]
+    media: bool,
+}
+
+impl BlockedResources {
+    fn get(&self, resource: Resource) -> bool {
+        match resource {
+            Resource::Fonts => self.fonts,
+            Resource::Media => self.media,
+        }
+    }
+
+    fn toggle(&mut self, resource: Resource) {
+        match resource {
+            Resource::Fonts => self.fonts = !self.fonts,
+            Resource::Media => self.media = !self.media,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SiteBlockedResources {
+    /// Host of the pages, wildcards are allowed.
+    site: String,
+    #[serde(flatten)]
+    blocked: BlockedResources,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ResourceBlockingConfig {
+    /// Resources blocked on sites without their own entry.
+    #[serde(flatten)]
+    blocked: BlockedResources,
+    #[serde(default)]
+    sites: Vec<SiteBlockedResources>,
+}
+
+pub enum Message {
+    Load,
+    Display(ResourceBlockingConfig),
+    /// `None` toggles the resources of sites without their own entry.
+    Toggle(Option<usize>, Resource),
+    UpdateSite(usize, String),
+    AddSite,
+    RemoveSite(usize),
+    Save,
+    SaveSuccess,
+    SaveFailed(ApiError),
+    AcknowledgeSuccess,
+    AcknowledgeError,
+}
+
+pub struct Resources {
+    configuration: Option<ResourceBlockingConfig>,
+    configuration_before_changes: Option<ResourceBlockingConfig>,
+    show_success: bool,
+    error: Option<String>,
+}
+
+impl Resources {
+    fn configuration_has_changed(&self) -> bool {
+        self.configuration != self.configuration_before_changes
+    }
+}
+
+impl Component for Resources {
+    type Message = Message;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(Message::Load);
+
+        Self {
+            configuration: None,
+            configuration_before_changes: None,
+            show_success: false,
+            error: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Message::Load => {
+                let callback = ctx.link().callback(|message: Message| message);
+
+                spawn_local(async move {
+                    match Request::get("/api/settings/resources").send().await {
+                        Ok(response) if response.ok() => {
+                            if let Ok(configuration) =
+                                response.json::<ResourceBlockingConfig>().await
+                            {
+                                callback.emit(Message::Display(configuration));
+                            }
+                        }
+                        Ok(response) => {
+                            log::error!("Failed to load blocked resources: {:?}", response.status())
+                        }
+                        Err(err) => log::error!("Failed to load blocked resources: {:?}", err),
+                    }
+                });
+            }
+            Message::Display(configuration) => {
+                self.configuration = Some(configuration.clone());
+                self.configuration_before_changes = Some(configuration);
+            }
+            Message::Toggle(index, resource) => {
+                if let Some(configuration) = self.configuration.as_mut() {
+                    match index {
+                        None => configuration.blocked.toggle(resource),
+                        Some(index) => {
+                            if let Some(site) = configuration.sites.get_mut(index) {
+                                site.blocked.toggle(resource);
+                            }
+                        }
+                    }
+                }
+            }
+            Message::UpdateSite(index, value) => {
+                if let Some(site) = self
+                    .configuration
+                    .as_mut()
+                    .and_then(|configuration| configuration.sites.get_mut(index))
+                {
+                    site.site = value;
+                }
+            }
+            Message::AddSite => {
+                if let Some(configuration) = self.configuration.as_mut() {
+                    configuration.sites.push(SiteBlockedResources {
+                        site: String::new(),
+                        blocked: configuration.blocked.clone(),
+                    });
+                }
+            }
+            Message::RemoveSite(index) => {
+                if let Some(configuration) = self.configuration.as_mut() {
+                    if index < configuration.sites.len() {
+                        configuration.sites.remove(index);
+                    }
+                }
+            }
+            Message::Save => {
+                if !self.configuration_has_changed() {
+                    return false;
+                }
+                let configuration = self.configuration.clone().unwrap();
+                let request = Request::put("/api/settings/resources")
+                    .header("Content-Type", "application/json")
+                    .body(serde_json::to_string(&configuration).unwrap());
+                let callback = ctx.link().callback(|message: Message| message);
+
+                spawn_local(async move {
+                    match request.send().await {
+                        Ok(response) if response.ok() => callback.emit(Message::SaveSuccess),
+                        Ok(response) => match response.json::<ApiError>().await {
+                            Ok(err) => callback.emit(Message::SaveFailed(err)),
+                            Err(err) => callback.emit(Message::SaveFailed(ApiError {
+                                error: format!("{:?}", err),
+                            })),
+                        },
+                        Err(err) => callback.emit(Message::SaveFailed(ApiError {
+                            error: format!("{:?}", err),
+                        })),
+                    }
+                });
+            }
+            Message::SaveSuccess => {
+                ctx.link().send_message(Message::Load);
+                self.show_success = true;
+            }
+            Message::SaveFailed(err) => self.error = Some(err.error),
+            Message::AcknowledgeSuccess => self.show_success = false,
+            Message::AcknowledgeError => self.error = None,
+        }
+
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let title = html! {
+            <div class="pt-1.5 mb-4">
+                <h1 class="text-2xl font-bold text-gray-900">{ "Fonts and media" }</h1>
+            </div>
+        };
+
+        let configuration = match &self.configuration {
+            Some(configuration) => configuration,
+            None => return title,
+        };
+
+        let render_checkboxes = |index: Option<usize>, blocked: &BlockedResources| -> Html {
+            Resource::values()
+                .into_iter()
+                .map(|resource| {
+                    let onchange = ctx
+                        .link()
+                        .callback(move |_| Message::Toggle(index, resource));
+                    html! {
+                        <td class="px-3 py-4 text-center">
+                            <input checked={blocked.get(resource)} {onchange} type="checkbox"
+                                class="focus:ring-blue-500 h-4 w-4 text-blue-600 border-gray-300 rounded" />
+                        </td>
+                    }
+                })
+                .collect()
+        };
+
+        let site_rows = configuration
+            .sites
+            .iter()
+            .enumerate()
+            .map(|(index, site)| {
+                let oninput = ctx.link().callback(move |e: InputEvent| {
+                    let input = e
+                        .target_dyn_into::<HtmlInputElement>()
+                        .expect("event target should be an input element");
+                    Message::UpdateSite(index, input.value())
+                });
+                let onclick = ctx.link().callback(move |_| Message::RemoveSite(index));
+
+                html! {
+                    <tr>
+                        <td class="py-4 pr-3">
+                            <input type="text" value={site.site.clone()} {oninput} placeholder="news.example.com or *.example.com"
+                                class="shadow-sm focus:ring-blue-500 focus:border-blue-500 block w-full sm:text-sm border-gray-300 rounded-md" />
+                        </td>
+                        { render_checkboxes(Some(index), &site.blocked) }
+                        <td class="px-3 py-4 text-right">
+                            <button {onclick} type="button" class="text-sm text-red-600 hover:text-red-900">{"Remove"}</button>
+                        </td>
+                    </tr>
+                }
+            })
+            .collect::<Html>();
+
+        let save_button_state = if self.configuration_has_changed() {
+            ButtonState::Enabled
+        } else {
+            ButtonState::Disabled
+        };
+        let save_callback = ctx.link().callback(|_| Message::Save);
+
+        html! {
+            <>
+                { title }
+                { success_banner!(self.show_success, ctx.link().callback(|_| Message::AcknowledgeSuccess)) }
+                { failure_banner!(self.error.is_some(), ctx.link().callback(|_| Message::AcknowledgeError), self.error.clone().unwrap_or_default()) }
+                <p class="text-gray-600">
+                    {"Web fonts and audio or video loaded by pages are blocked when checked. Sites are matched by the host of the page, the first matching entry applies."}
+                </p>
+                <table class="mt-4 min-w-full divide-y divide-gray-200">
+                    <thead>
+                        <tr>
+                            <th class="py-3 pr-3 text-left text-sm font-medium text-gray-900">{"Site"}</th>
+                            { for Resource::values().into_iter().map(|resource| html! {
+                                <th class="px-3 py-3 text-center text-sm font-medium text-gray-900">{resource.as_str()}</th>
+                            }) }
+                            <th></th>
+                        </tr>
+                    </thead>
+                    <tbody class="divide-y divide-gray-200">
+                        <tr>
+                            <td class="py-4 pr-3 text-sm text-gray-900">{"Every other site"}</td>
+                            { render_checkboxes(None, &configuration.blocked) }
+                            <td></td>
+                        </tr>
+                        { site_rows }
+                    </tbody>
+                </table>
+                <div class="flex space-x-4">
+                    <div class="mt-5">
+                        <button onclick={ctx.link().callback(|_| Message::AddSite)} type="button"
+                            class="inline-flex items-center px-4 py-2 border border-gray-300 shadow-sm text-sm font-medium rounded-md text-gray-700 bg-white hover:bg-gray-50">
+                            {"Add site"}
+                        </button>
+                    </div>
+                    { save_button!(save_callback, save_button_state) }
+                </div>
+            </>
+        }
+    }
+}
@@ -1,5 +1,9 @@
+use crate::capture::Capture;
+use crate::categories::Categories;
+use crate::exceptions::Exceptions;
 use crate::filters::Filters;
 use crate::general::GeneralSettings;
+use crate::resources::Resources;
 use crate::set_title;
 use crate::settings_textarea::SettingsTextarea;
 use yew::prelude::*;
@@ -16,6 +20,16 @@ pub enum SettingsRoute {
     Exclusions,
     #[at("/settings/custom-filters")]
     CustomFilters,
+    #[at("/settings/scriptlets")]
+    Scriptlets,
+    #[at("/settings/exceptions")]
+    Exceptions,
+    #[at("/settings/categories")]
+    Categories,
+    #[at("/settings/resources")]
+    Resources,
+    #[at("/settings/capture")]
+    Capture,
 }
 
 pub fn switch_settings(route: &SettingsRoute) -> Html {
@@ -90,6 +104,46 @@ pub fn switch_settings(route: &SettingsRoute) -> Html {
 
             html! {<SettingsTextarea h1="Custom Filters" {description} input_name="custom_filters" {textarea_description} {resource_url} />}
         }
+        SettingsRoute::Scriptlets => {
+            set_title("Settings - Scriptlets");
+
+            let resource_url = "/api/custom-scriptlets";
+
+            let description = html! {
+                <p class="text-gray-600">
+                    {"Scriptlets injected by "}<span class="font-mono bg-gray-100">{"##+js(name.js, ...)"}</span>
+                    {" filters, in the format of uBlock Origin's scriptlets.js. Each one starts with "}
+                    <span class="font-mono bg-gray-100">{"/// name.js"}</span>
+                    {", optionally followed by "}<span class="font-mono bg-gray-100">{"/// alias other-name.js"}</span>
+                    {" lines, and ends with an empty line. "}<span class="font-mono bg-gray-100">{"{{1}}"}</span>
+                    {", "}<span class="font-mono bg-gray-100">{"{{2}}"}</span>{"... are replaced with the arguments of the filter."}
+                </p>
+            };
+
+            let textarea_description = "Insert scriptlets separated by empty lines";
+
+            html! {<SettingsTextarea h1="Scriptlets" {description} input_name="custom_scriptlets" {textarea_description} {resource_url} />}
+        }
+        SettingsRoute::Exceptions => {
+            set_title("Settings - Exceptions");
+
+            html! { <Exceptions /> }
+        }
+        SettingsRoute::Categories => {
+            set_title("Settings - Categories");
+
+            html! { <Categories /> }
+        }
+        SettingsRoute::Resources => {
+            set_title("Settings - Fonts and media");
+
+            html! { <Resources /> }
+        }
+        SettingsRoute::Capture => {
+            set_title("Settings - Debug capture");
+
+            html! { <Capture /> }
+        }
     };
 
     html! {<div class="md:grid md:grid-cols-8">
@@ -98,6 +152,11 @@ pub fn switch_settings(route: &SettingsRoute) -> Html {
         <Link<SettingsRoute> classes={get_classes(*route, SettingsRoute::Filters)} to={SettingsRoute::Filters}> <span class="truncate">{ "Filters" }</span></Link<SettingsRoute>>
         <Link<SettingsRoute> classes={get_classes(*route, SettingsRoute::Exclusions)} to={SettingsRoute::Exclusions}> <span class="truncate">{ "Exclusions" }</span></Link<SettingsRoute>>
         <Link<SettingsRoute> classes={get_classes(*route, SettingsRoute::CustomFilters)} to={SettingsRoute::CustomFilters}> <span class="truncate">{ "Custom filters" }</span></Link<SettingsRoute>>
+        <Link<SettingsRoute> classes={get_classes(*route, SettingsRoute::Scriptlets)} to={SettingsRoute::Scriptlets}> <span class="truncate">{ "Scriptlets" }</span></Link<SettingsRoute>>
+        <Link<SettingsRoute> classes={get_classes(*route, SettingsRoute::Exceptions)} to={SettingsRoute::Exceptions}> <span class="truncate">{ "Exceptions" }</span></Link<SettingsRoute>>
+        <Link<SettingsRoute> classes={get_classes(*route, SettingsRoute::Categories)} to={SettingsRoute::Categories}> <span class="truncate">{ "Categories" }</span></Link<SettingsRoute>>
+        <Link<SettingsRoute> classes={get_classes(*route, SettingsRoute::Resources)} to={SettingsRoute::Resources}> <span class="truncate">{ "Fonts and media" }</span></Link<SettingsRoute>>
+        <Link<SettingsRoute> classes={get_classes(*route, SettingsRoute::Capture)} to={SettingsRoute::Capture}> <span class="truncate">{ "Debug capture" }</span></Link<SettingsRoute>>
     </nav>
         <div class="container mx-auto px-4 sm:px-6 lg:px-8 mt-4 sm:col-span-6">{ content }</div>
     </div>
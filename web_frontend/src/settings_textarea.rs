@@ -1,3 +1,4 @@
+use crate::conflict_dialog::{ConflictDialog, RevisionConflict};
 use crate::save_button;
 use crate::submit_banner;
 use crate::success_banner;
@@ -21,14 +22,21 @@ pub struct SettingsTextarea {
     changes_saved: bool,
     input_data: String,
     previous_input_data: String,
+    /// `ETag` of the loaded configuration, sent back when saving.
+    revision: Option<String>,
+    conflict: Option<RevisionConflict>,
 }
 
 pub enum Message {
     LoadCurrentState,
     UpdateInput(String),
     UpdatePreviousInputData,
+    UpdateRevision(Option<String>),
     Save,
     Saved,
+    Conflict(RevisionConflict),
+    Overwrite,
+    Reload,
     AckChanges,
 }
 
@@ -44,6 +52,8 @@ impl Component for SettingsTextarea {
             input_data: String::new(),
             previous_input_data: String::new(),
             changes_saved: false,
+            revision: None,
+            conflict: None,
         }
     }
 
@@ -60,18 +70,32 @@ impl Component for SettingsTextarea {
                     return false;
                 }
 
-                let request = Request::put(&ctx.props().resource_url)
+                let mut request = Request::put(&ctx.props().resource_url)
                     .header("Content-Type", "application/json")
                     .body(&serde_json::to_string(&self.input_data).unwrap());
+                if let Some(revision) = &self.revision {
+                    request = request.header("If-Match", revision);
+                }
+
+                let message_callback = ctx.link().callback(|message: Message| message);
 
                 spawn_local(async move {
-                    if let Ok(response) = request.send().await {
+                    match request.send().await {
+                        Ok(response) if response.ok() => {
+                            message_callback
+                                .emit(Message::UpdateRevision(response.headers().get("etag")));
+                            message_callback.emit(Message::Saved);
+                        }
+                        Ok(response) if response.status() == 409 => {
+                            if let Ok(conflict) = response.json::<RevisionConflict>().await {
+                                message_callback.emit(Message::Conflict(conflict));
+                            }
+                        }
                         // Todo: Handle errors
-                        if response.ok() {}
+                        Ok(response) => log::error!("Failed to save: {:?}", response.status()),
+                        Err(err) => log::error!("Failed to save: {:?}", err),
                     }
                 });
-
-                ctx.link().send_message(Message::Saved);
             }
             Message::Saved => {
                 ctx.link().send_message(Message::UpdatePreviousInputData);
@@ -79,6 +103,18 @@ impl Component for SettingsTextarea {
                 self.changes_saved = true;
                 self.is_save_button_enabled = false;
             }
+            Message::Conflict(conflict) => self.conflict = Some(conflict),
+            Message::Overwrite => {
+                if let Some(conflict) = self.conflict.take() {
+                    self.revision = Some(conflict.etag());
+                    ctx.link().send_message(Message::Save);
+                }
+            }
+            Message::Reload => {
+                self.conflict = None;
+                self.is_save_button_enabled = false;
+                ctx.link().send_message(Message::LoadCurrentState);
+            }
             Message::AckChanges => {
                 self.changes_saved = false;
             }
@@ -91,6 +127,8 @@ impl Component for SettingsTextarea {
                     if let Ok(response) = request.send().await {
                         // Todo: Handle errors
                         if response.ok() {
+                            message_callback
+                                .emit(Message::UpdateRevision(response.headers().get("etag")));
                             if let Ok(response_content) = response.json::<String>().await {
                                 message_callback.emit(Message::UpdateInput(response_content));
                                 message_callback.emit(Message::UpdatePreviousInputData)
@@ -102,6 +140,7 @@ impl Component for SettingsTextarea {
             Message::UpdatePreviousInputData => {
                 self.previous_input_data = self.input_data.clone();
             }
+            Message::UpdateRevision(revision) => self.revision = revision,
         }
         true
     }
@@ -151,6 +190,15 @@ impl Component for SettingsTextarea {
 
         let onclick = ctx.link().callback(|_| Message::Save);
 
+        let conflict_dialog = if self.conflict.is_some() {
+            html! {
+                <ConflictDialog on_reload={ctx.link().callback(|_| Message::Reload)}
+                    on_overwrite={ctx.link().callback(|_| Message::Overwrite)} />
+            }
+        } else {
+            html! {}
+        };
+
         let props = ctx.props();
 
         html! {
@@ -161,6 +209,7 @@ impl Component for SettingsTextarea {
             {props.description.clone()}
 
             {success_banner}
+            {conflict_dialog}
 
             <div class="mt-4">
                 <label for={props.input_name.clone()} class="block text-sm font-medium text-gray-700">{&props.textarea_description}</label>